@@ -161,7 +161,7 @@ mod tests {
     use ic_test_utilities::types::ids::{canister_test_id, subnet_test_id, user_test_id};
     use ic_types::{
         ingress::WasmResult,
-        messages::CallContextId,
+        messages::{CallContextId, NO_DEADLINE},
         methods::{FuncRef, WasmMethod},
         time::Time,
         CanisterTimer, ComputeAllocation, Cycles, MemoryAllocation, NumBytes, NumInstructions,
@@ -181,6 +181,7 @@ mod tests {
                 NumInstructions::new(INSTRUCTION_LIMIT),
             ),
             canister_memory_limit: NumBytes::new(4 << 30),
+            wasm_memory_limit: None,
             compute_allocation: ComputeAllocation::default(),
             subnet_type: SubnetType::Application,
             execution_mode: ExecutionMode::Replicated,
@@ -193,6 +194,7 @@ mod tests {
         SandboxSafeSystemState::new_internal(
             canister_test_id(0),
             user_test_id(0).get(),
+            BTreeSet::from([user_test_id(0).get()]),
             CanisterStatusView::Running,
             NumSeconds::from(3600),
             MemoryAllocation::BestEffort,
@@ -208,6 +210,7 @@ mod tests {
             BTreeMap::new(),
             0,
             ic00_aliases,
+            BTreeMap::new(),
             SMALL_APP_SUBNET_MAX_SIZE,
             SchedulerConfig::application_subnet().dirty_page_overhead,
             CanisterTimer::Inactive,
@@ -244,6 +247,7 @@ mod tests {
                 Cycles::zero(),
                 PrincipalId::try_from([0].as_ref()).unwrap(),
                 CallContextId::from(0),
+                NO_DEADLINE,
             ),
             globals,
             canister_current_memory_usage: NumBytes::from(0),
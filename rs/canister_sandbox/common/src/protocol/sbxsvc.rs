@@ -127,6 +127,9 @@ impl EnumerateInnerFileDescriptors for CheckpointSerialization {
         if let Some(mapping) = self.mapping.as_mut() {
             mapping.enumerate_fds(fds)
         }
+        for overlay in self.overlays.iter_mut() {
+            overlay.enumerate_fds(fds)
+        }
     }
 }
 
@@ -141,14 +141,14 @@ fn try_from_reject_context_code_zero() {
 #[test]
 fn try_from_reject_context_code_out_of_range() {
     let context = types::RejectContext {
-        code: RejectCode::CanisterError as u8 + 1,
+        code: RejectCode::SysUnknown as u8 + 1,
         message: "Oops".into(),
     };
 
     match RejectContext::try_from(context) {
         Ok(ctx) => panic!("Expected Err(_), got Ok({:?})", ctx),
         Err(ProxyDecodeError::ValueOutOfRange { typ, err }) => {
-            assert_eq!(("RejectContext", "6"), (typ, err.as_str()))
+            assert_eq!(("RejectContext", "7"), (typ, err.as_str()))
         }
         Err(err) => panic!(
             "Expected Err(ProxyDecodeError::ValueOutOfRange), got Err({:?})",
@@ -65,6 +65,8 @@ pub struct Request {
     pub method_payload: Bytes,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cycles_payment: Option<Cycles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u32>,
 }
 
 /// Canonical representation of `ic_types::messages::Response`.
@@ -80,6 +82,8 @@ pub struct Response {
     pub response_payload: Payload,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cycles_refund: Option<Cycles>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<u32>,
 }
 
 /// Canonical representation of `ic_types::funds::Cycles`.
@@ -247,6 +251,7 @@ impl From<(&ic_types::messages::Request, CertificationVersion)> for Request {
             method_name: request.method_name.clone(),
             method_payload: request.method_payload.clone(),
             cycles_payment: None,
+            deadline: encode_deadline(request.deadline, certification_version),
         }
     }
 }
@@ -268,6 +273,7 @@ impl TryFrom<Request> for ic_types::messages::Request {
             payment,
             method_name: request.method_name,
             method_payload: request.method_payload,
+            deadline: decode_deadline(request.deadline),
         })
     }
 }
@@ -287,6 +293,7 @@ impl From<(&ic_types::messages::Response, CertificationVersion)> for Response {
             refund: funds,
             response_payload: (&response.response_payload, certification_version).into(),
             cycles_refund: None,
+            deadline: encode_deadline(response.deadline, certification_version),
         }
     }
 }
@@ -307,10 +314,33 @@ impl TryFrom<Response> for ic_types::messages::Response {
             originator_reply_callback: response.originator_reply_callback.into(),
             refund,
             response_payload: response.response_payload.try_into()?,
+            deadline: decode_deadline(response.deadline),
         })
     }
 }
 
+/// Encodes a `Request` or `Response` deadline. Guaranteed response messages
+/// (i.e. `NO_DEADLINE`) and certification versions before `V12` produce `None`.
+fn encode_deadline(
+    deadline: ic_types::time::CoarseTime,
+    certification_version: CertificationVersion,
+) -> Option<u32> {
+    if certification_version < CertificationVersion::V12
+        || deadline == ic_types::messages::NO_DEADLINE
+    {
+        return None;
+    }
+    Some(deadline.as_secs_since_unix_epoch())
+}
+
+/// Decodes an optional `Request` or `Response` deadline, defaulting to
+/// `NO_DEADLINE`.
+fn decode_deadline(deadline: Option<u32>) -> ic_types::time::CoarseTime {
+    deadline
+        .map(ic_types::time::CoarseTime::from_secs_since_unix_epoch)
+        .unwrap_or(ic_types::messages::NO_DEADLINE)
+}
+
 impl From<(&ic_types::funds::Cycles, CertificationVersion)> for Cycles {
     fn from(
         (cycles, _certification_version): (&ic_types::funds::Cycles, CertificationVersion),
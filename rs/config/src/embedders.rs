@@ -1,4 +1,4 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 use ic_base_types::NumBytes;
 use ic_registry_subnet_type::SubnetType;
@@ -59,6 +59,8 @@ pub struct FeatureFlags {
     /// Track dirty pages with a write barrier instead of the signal handler.
     pub write_barrier: FlagStatus,
     pub wasm_native_stable_memory: FlagStatus,
+    /// Allow canisters with a 64-bit (memory64) Wasm heap.
+    pub wasm64: FlagStatus,
 }
 
 impl FeatureFlags {
@@ -67,6 +69,7 @@ impl FeatureFlags {
             rate_limiting_of_debug_prints: FlagStatus::Enabled,
             write_barrier: FlagStatus::Disabled,
             wasm_native_stable_memory: FlagStatus::Disabled,
+            wasm64: FlagStatus::Disabled,
         }
     }
 }
@@ -129,6 +132,13 @@ pub struct Config {
     /// replaced with the correct value at runtime when the hypervisor is
     /// created.
     pub dirty_page_overhead: NumInstructions,
+
+    /// If set, every execution is profiled and the number of instructions
+    /// executed by each Wasm function is written to this file in the folded
+    /// stack format. Only the in-process executor (i.e. with canister
+    /// sandboxing disabled) supports profiling. Must never be set in
+    /// production.
+    pub instruction_profile_output: Option<PathBuf>,
 }
 
 impl Config {
@@ -149,6 +159,7 @@ impl Config {
             max_sandbox_idle_time: DEFAULT_MAX_SANDBOX_IDLE_TIME,
             subnet_type: SubnetType::Application,
             dirty_page_overhead: NumInstructions::new(0),
+            instruction_profile_output: None,
         }
     }
 }
@@ -9,7 +9,7 @@ use ic_types::{
     MAX_WASM_MEMORY_IN_BYTES,
 };
 use serde::{Deserialize, Serialize};
-use std::{str::FromStr, time::Duration};
+use std::{path::PathBuf, str::FromStr, time::Duration};
 
 const GB: u64 = 1024 * 1024 * 1024;
 
@@ -25,6 +25,17 @@ const GB: u64 = 1024 * 1024 * 1024;
 /// canister's data and the deltas.
 const SUBNET_MEMORY_CAPACITY: NumBytes = NumBytes::new(450 * GB);
 
+/// This is the threshold after which the subnet memory is considered to be
+/// saturated. Once the subnet memory usage exceeds this threshold, canisters
+/// that allocate new memory have to reserve cycles to pay for the future
+/// storage of that memory.
+const SUBNET_MEMORY_THRESHOLD: NumBytes = NumBytes::new(300 * GB);
+
+/// The default upper bound on the reserved cycles balance of a canister. It
+/// applies to canisters that do not specify `reserved_cycles_limit` in their
+/// settings.
+pub const DEFAULT_RESERVED_BALANCE_LIMIT: Cycles = Cycles::new(5_000_000_000_000);
+
 /// This is the upper limit on how much memory can be used by all canister
 /// messages on a given subnet.
 ///
@@ -79,6 +90,11 @@ const QUERY_EXECUTION_THREADS_TOTAL: usize = 2;
 /// value increases the user-visible latency of the queries.
 const QUERY_SCHEDULING_TIME_SLICE_PER_CANISTER: Duration = Duration::from_millis(20);
 
+/// The number of consecutive heights over which query statistics are
+/// collected locally before they are gossiped through consensus and
+/// aggregated in the replicated state.
+pub const QUERY_STATS_EPOCH_LENGTH: u64 = 2000;
+
 // The ID of the Bitcoin testnet canister.
 const BITCOIN_TESTNET_CANISTER_ID: &str = "g4xu7-jiaaa-aaaan-aaaaq-cai";
 
@@ -107,6 +123,10 @@ pub struct Config {
     /// the subnet.
     pub subnet_memory_capacity: NumBytes,
 
+    /// The memory usage of the subnet after which canisters start to reserve
+    /// cycles for newly allocated memory.
+    pub subnet_memory_threshold: NumBytes,
+
     /// The maximum amount of logical storage available to canister messages
     /// across the whole subnet.
     pub subnet_message_memory_capacity: NumBytes,
@@ -125,6 +145,9 @@ pub struct Config {
     /// The default number of seconds after which a canister will freeze.
     pub default_freeze_threshold: NumSeconds,
 
+    /// The default upper bound on the reserved cycles balance of a canister.
+    pub default_reserved_balance_limit: Cycles,
+
     /// Maximum number of controllers a canister can have.
     pub max_controllers: usize,
 
@@ -198,6 +221,18 @@ pub struct Config {
     /// The limit on the number of dirty pages in stable memory that a canister
     /// can create in a single message.
     pub stable_memory_dirty_page_limit: NumPages,
+
+    /// The number of consecutive heights over which query statistics are
+    /// collected before they are aggregated.
+    pub query_stats_epoch_length: u64,
+
+    /// If set, canister executions are profiled and the number of
+    /// instructions executed by each Wasm function is written to this file in
+    /// the folded stack format. This is meant for `drun` and `StateMachine`
+    /// tests: it requires canister sandboxing to be disabled and cannot be set
+    /// in a configuration file, so it is never enabled in production.
+    #[serde(skip)]
+    pub instruction_profile_output: Option<PathBuf>,
 }
 
 impl Default for Config {
@@ -216,14 +251,18 @@ impl Default for Config {
             create_funds_whitelist: String::default(),
             max_instructions_for_message_acceptance_calls: MAX_INSTRUCTIONS_PER_MESSAGE_WITHOUT_DTS,
             subnet_memory_capacity: SUBNET_MEMORY_CAPACITY,
+            subnet_memory_threshold: SUBNET_MEMORY_THRESHOLD,
             subnet_message_memory_capacity: SUBNET_MESSAGE_MEMORY_CAPACITY,
             ingress_history_memory_capacity: INGRESS_HISTORY_MEMORY_CAPACITY,
+            // Sized for 32-bit heaps, as 64-bit heaps are behind the `wasm64`
+            // feature flag, which is disabled by default.
             max_canister_memory_size: NumBytes::new(
                 MAX_STABLE_MEMORY_IN_BYTES + MAX_WASM_MEMORY_IN_BYTES,
             ),
             default_provisional_cycles_balance: Cycles::new(100_000_000_000_000),
             // The default freeze threshold is 30 days.
             default_freeze_threshold: NumSeconds::from(30 * 24 * 60 * 60),
+            default_reserved_balance_limit: DEFAULT_RESERVED_BALANCE_LIMIT,
             // Maximum number of controllers allowed in a request (specified in the public
             // Spec).
             max_controllers: 10,
@@ -262,6 +301,8 @@ impl Default for Config {
             stable_memory_dirty_page_limit: NumPages::new(
                 embedders::STABLE_MEMORY_DIRTY_PAGE_LIMIT,
             ),
+            query_stats_epoch_length: QUERY_STATS_EPOCH_LENGTH,
+            instruction_profile_output: None,
         }
     }
 }
@@ -1,17 +1,57 @@
+use crate::flag_status::FlagStatus;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Config {
     state_root: PathBuf,
+    /// If enabled, checkpoints store the pages of canister memories that
+    /// changed since the previous checkpoint in overlay files instead of
+    /// rewriting the memory files.
+    #[serde(default = "incremental_checkpoints_default")]
+    incremental_checkpoints: FlagStatus,
+    /// If enabled, a background thread periodically re-hashes all checkpoints
+    /// and compares them against their manifests to detect disk corruption.
+    #[serde(default = "checkpoint_scrubbing_default")]
+    checkpoint_scrubbing: FlagStatus,
+}
+
+fn incremental_checkpoints_default() -> FlagStatus {
+    FlagStatus::Disabled
+}
+
+fn checkpoint_scrubbing_default() -> FlagStatus {
+    FlagStatus::Disabled
 }
 
 impl Config {
     pub fn new(state_root: PathBuf) -> Self {
-        Self { state_root }
+        Self {
+            state_root,
+            incremental_checkpoints: incremental_checkpoints_default(),
+            checkpoint_scrubbing: checkpoint_scrubbing_default(),
+        }
+    }
+
+    pub fn with_incremental_checkpoints(mut self, status: FlagStatus) -> Self {
+        self.incremental_checkpoints = status;
+        self
+    }
+
+    pub fn with_checkpoint_scrubbing(mut self, status: FlagStatus) -> Self {
+        self.checkpoint_scrubbing = status;
+        self
     }
 
     pub fn state_root(&self) -> PathBuf {
         self.state_root.clone()
     }
+
+    pub fn incremental_checkpoints(&self) -> FlagStatus {
+        self.incremental_checkpoints
+    }
+
+    pub fn checkpoint_scrubbing(&self) -> FlagStatus {
+        self.checkpoint_scrubbing
+    }
 }
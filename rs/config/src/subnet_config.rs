@@ -127,6 +127,11 @@ pub const ECDSA_SIGNATURE_FEE: Cycles = Cycles::new(10 * B as u128);
 /// IMPORTANT: never set this value to zero.
 const DEFAULT_REFERENCE_SUBNET_SIZE: usize = 13;
 
+/// The maximum duration of storage for which cycles are reserved when a
+/// canister allocates memory on a saturated subnet. Corresponds to roughly
+/// 10 years.
+const MAX_STORAGE_RESERVATION_PERIOD: Duration = Duration::from_secs(300_000_000);
+
 /// Costs for each newly created dirty page in stable memory.
 const DEFAULT_DIRTY_PAGE_OVERHEAD: NumInstructions = NumInstructions::new(1_000);
 const SYSTEM_SUBNET_DIRTY_PAGE_OVERHEAD: NumInstructions = NumInstructions::new(0);
@@ -379,6 +384,11 @@ pub struct CyclesAccountManagerConfig {
 
     /// Fee per byte for networking and consensus work done for a http request or response.
     pub http_request_per_byte_fee: Cycles,
+
+    /// The upper bound on the storage reservation period: allocating memory
+    /// when the subnet memory is saturated reserves cycles to pay for storing
+    /// that memory for at most this long.
+    pub max_storage_reservation_period: Duration,
 }
 
 impl CyclesAccountManagerConfig {
@@ -407,6 +417,7 @@ impl CyclesAccountManagerConfig {
             ecdsa_signature_fee: ECDSA_SIGNATURE_FEE,
             http_request_baseline_fee: Cycles::new(400_000_000),
             http_request_per_byte_fee: Cycles::new(100_000),
+            max_storage_reservation_period: MAX_STORAGE_RESERVATION_PERIOD,
         }
     }
 
@@ -435,6 +446,7 @@ impl CyclesAccountManagerConfig {
             ecdsa_signature_fee: ECDSA_SIGNATURE_FEE,
             http_request_baseline_fee: Cycles::new(0),
             http_request_per_byte_fee: Cycles::new(0),
+            max_storage_reservation_period: MAX_STORAGE_RESERVATION_PERIOD,
         }
     }
 }
@@ -62,6 +62,7 @@ use ic_interfaces::{
     ecdsa::EcdsaPool,
     ingress_manager::IngressSelector,
     messaging::{MessageRouting, XNetPayloadBuilder},
+    query_stats::QueryStatsPayloadBuilder,
     self_validating_payload::SelfValidatingPayloadBuilder,
     time_source::TimeSource,
 };
@@ -143,6 +144,7 @@ impl ConsensusImpl {
         xnet_payload_builder: Arc<dyn XNetPayloadBuilder>,
         self_validating_payload_builder: Arc<dyn SelfValidatingPayloadBuilder>,
         canister_http_payload_builder: Arc<dyn CanisterHttpPayloadBuilder>,
+        query_stats_payload_builder: Arc<dyn QueryStatsPayloadBuilder>,
         dkg_pool: Arc<RwLock<dyn DkgPool>>,
         ecdsa_pool: Arc<RwLock<dyn EcdsaPool>>,
         dkg_key_manager: Arc<Mutex<DkgKeyManager>>,
@@ -162,6 +164,7 @@ impl ConsensusImpl {
             xnet_payload_builder,
             self_validating_payload_builder,
             canister_http_payload_builder,
+            query_stats_payload_builder,
             metrics_registry.clone(),
             logger.clone(),
         ));
@@ -628,6 +631,7 @@ pub fn setup(
     xnet_payload_builder: Arc<dyn XNetPayloadBuilder>,
     self_validating_payload_builder: Arc<dyn SelfValidatingPayloadBuilder>,
     canister_http_payload_builder: Arc<dyn CanisterHttpPayloadBuilder>,
+    query_stats_payload_builder: Arc<dyn QueryStatsPayloadBuilder>,
     dkg_pool: Arc<RwLock<dyn DkgPool>>,
     ecdsa_pool: Arc<RwLock<dyn EcdsaPool>>,
     dkg_key_manager: Arc<Mutex<DkgKeyManager>>,
@@ -662,6 +666,7 @@ pub fn setup(
             xnet_payload_builder,
             self_validating_payload_builder,
             canister_http_payload_builder,
+            query_stats_payload_builder,
             dkg_pool,
             ecdsa_pool,
             dkg_key_manager,
@@ -691,6 +696,7 @@ mod tests {
         canister_http::FakeCanisterHttpPayloadBuilder,
         ingress_selector::FakeIngressSelector,
         message_routing::FakeMessageRouting,
+        query_stats::FakeQueryStatsPayloadBuilder,
         self_validating_payload_builder::FakeSelfValidatingPayloadBuilder,
         types::ids::{node_test_id, subnet_test_id},
         xnet_payload_builder::FakeXNetPayloadBuilder,
@@ -754,6 +760,7 @@ mod tests {
             Arc::new(FakeXNetPayloadBuilder::new()),
             Arc::new(FakeSelfValidatingPayloadBuilder::new()),
             Arc::new(FakeCanisterHttpPayloadBuilder::new()),
+            Arc::new(FakeQueryStatsPayloadBuilder::new()),
             dkg_pool,
             ecdsa_pool,
             Arc::new(Mutex::new(DkgKeyManager::new(
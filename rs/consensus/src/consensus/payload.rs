@@ -4,7 +4,7 @@ use crate::consensus::metrics::{
 use ic_interfaces::{
     canister_http::CanisterHttpPayloadBuilder, consensus::PayloadValidationError,
     ingress_manager::IngressSelector, messaging::XNetPayloadBuilder,
-    self_validating_payload::SelfValidatingPayloadBuilder,
+    query_stats::QueryStatsPayloadBuilder, self_validating_payload::SelfValidatingPayloadBuilder,
 };
 use ic_logger::{error, warn, ReplicaLogger};
 use ic_types::{
@@ -41,6 +41,7 @@ pub(crate) enum BatchPayloadSectionBuilder {
     XNet(Arc<dyn XNetPayloadBuilder>),
     SelfValidating(Arc<dyn SelfValidatingPayloadBuilder>),
     CanisterHttp(Arc<dyn CanisterHttpPayloadBuilder>),
+    QueryStats(Arc<dyn QueryStatsPayloadBuilder>),
 }
 
 impl BatchPayloadSectionBuilder {
@@ -207,6 +208,49 @@ impl BatchPayloadSectionBuilder {
                     }
                 }
             }
+            Self::QueryStats(builder) => {
+                let past_payloads = builder.filter_past_payloads(past_payloads);
+                let query_stats =
+                    builder.get_query_stats_payload(validation_context, &past_payloads, max_size);
+                let size = NumBytes::new(
+                    query_stats
+                        .as_ref()
+                        .map_or(0, |query_stats| query_stats.count_bytes() as u64),
+                );
+
+                // Validate the query stats payload as a safety measure
+                if let Err(err) = builder.validate_query_stats_payload(
+                    query_stats.as_ref(),
+                    validation_context,
+                    &past_payloads,
+                ) {
+                    error!(
+                        logger,
+                        "QueryStats payload did not pass validation, this is a bug, {:?} @{}",
+                        err,
+                        CRITICAL_ERROR_VALIDATION_NOT_PASSED
+                    );
+
+                    metrics.critical_error_validation_not_passed.inc();
+                    payload.query_stats = None;
+                    return NumBytes::new(0);
+                }
+
+                if size > max_size {
+                    error!(
+                        logger,
+                        "QueryStatsPayload is larger than byte_limit. This is a bug, @{}",
+                        CRITICAL_ERROR_PAYLOAD_TOO_LARGE
+                    );
+
+                    metrics.critical_error_payload_too_large.inc();
+                    payload.query_stats = None;
+                    return NumBytes::new(0);
+                }
+
+                payload.query_stats = query_stats;
+                size
+            }
         }
     }
 
@@ -262,6 +306,14 @@ impl BatchPayloadSectionBuilder {
                     &past_payloads,
                 )?)
             }
+            BatchPayloadSectionBuilder::QueryStats(builder) => {
+                let past_payloads = builder.filter_past_payloads(past_payloads);
+                Ok(builder.validate_query_stats_payload(
+                    payload.query_stats.as_ref(),
+                    validation_context,
+                    &past_payloads,
+                )?)
+            }
         }
     }
 }
@@ -17,6 +17,7 @@ use crate::{
         ConsensusMessageId,
     },
     dkg, ecdsa,
+    query_stats::validate_query_stats_proposer,
 };
 use ic_interfaces::time_source::TimeSource;
 use ic_interfaces::{
@@ -911,6 +912,8 @@ impl Validator {
     /// - Any of the values in the `ValidationContext` on the `Block` are less
     ///   than the corresponding value on the parent `Block`'s
     ///   `ValidationContext`.
+    /// - The payload includes query statistics reported by a node other than
+    ///   the signer of the `BlockProposal`.
     fn check_block_validity(
         &self,
         pool_reader: &PoolReader<'_>,
@@ -947,6 +950,7 @@ impl Validator {
 
         let parent = get_notarized_parent(pool_reader, proposal)?;
         self.verify_signature(pool_reader, proposal)?;
+        let block_maker = proposal.signature.signer;
 
         // Ensure registry_version, certified_height and time are non-decreasing.
         let proposal = proposal.as_ref();
@@ -993,6 +997,24 @@ impl Validator {
                 )
             })?;
 
+        if !proposal.payload.is_summary() {
+            validate_query_stats_proposer(
+                proposal
+                    .payload
+                    .as_ref()
+                    .as_data()
+                    .batch
+                    .query_stats
+                    .as_ref(),
+                block_maker,
+            )
+            .map_err(|err| {
+                PermanentError::PayloadValidationError(
+                    PayloadPermanentError::QueryStatsPayloadValidationError(err),
+                )
+            })?;
+        }
+
         ecdsa::validate_payload(
             self.replica_config.subnet_id,
             self.registry_client.as_ref(),
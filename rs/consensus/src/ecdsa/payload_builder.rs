@@ -4,7 +4,7 @@
 
 use super::pre_signer::{EcdsaTranscriptBuilder, EcdsaTranscriptBuilderImpl};
 use super::signer::{EcdsaSignatureBuilder, EcdsaSignatureBuilderImpl};
-use super::utils::EcdsaBlockReaderImpl;
+use super::utils::{algorithm_for_key_id, EcdsaBlockReaderImpl};
 use crate::consensus::{
     crypto::ConsensusCrypto, metrics::EcdsaPayloadMetrics, pool_reader::PoolReader,
 };
@@ -733,6 +733,7 @@ pub(crate) fn create_data_payload_helper_2(
         current_key_transcript.as_ref(),
         &mut ecdsa_payload.key_transcript.next_in_creation,
         &mut ecdsa_payload.uid_generator,
+        algorithm_for_key_id(&ecdsa_payload.key_transcript.key_id),
         transcript_builder,
         height,
         log.clone(),
@@ -772,6 +773,7 @@ fn new_random_config(
     subnet_nodes: &[NodeId],
     summary_registry_version: RegistryVersion,
     uid_generator: &mut ecdsa::EcdsaUIDGenerator,
+    algorithm_id: AlgorithmId,
 ) -> Result<ecdsa::RandomTranscriptParams, EcdsaPayloadError> {
     let transcript_id = uid_generator.next_transcript_id();
     let dealers = subnet_nodes.iter().copied().collect::<BTreeSet<_>>();
@@ -781,7 +783,7 @@ fn new_random_config(
         dealers,
         receivers,
         summary_registry_version,
-        AlgorithmId::ThresholdEcdsaSecp256k1,
+        algorithm_id,
     ))
 }
 
@@ -815,11 +817,14 @@ fn make_new_quadruples_if_needed_helper(
     let unassigned_quadruples = ecdsa_payload.unassigned_quadruple_ids().count();
     let quadruples_to_create = ecdsa_config.quadruples_to_create_in_advance as usize;
     if quadruples_to_create > unassigned_quadruples {
+        let algorithm_id = algorithm_for_key_id(&ecdsa_payload.key_transcript.key_id);
         let quadruples_in_creation = &mut ecdsa_payload.quadruples_in_creation;
         let uid_generator = &mut ecdsa_payload.uid_generator;
         for _ in 0..(quadruples_to_create - unassigned_quadruples) {
-            let kappa_config = new_random_config(subnet_nodes, registry_version, uid_generator)?;
-            let lambda_config = new_random_config(subnet_nodes, registry_version, uid_generator)?;
+            let kappa_config =
+                new_random_config(subnet_nodes, registry_version, uid_generator, algorithm_id)?;
+            let lambda_config =
+                new_random_config(subnet_nodes, registry_version, uid_generator, algorithm_id)?;
             quadruples_in_creation.insert(
                 uid_generator.next_quadruple_id(),
                 ecdsa::QuadrupleInCreation::new(kappa_config, lambda_config),
@@ -955,6 +960,7 @@ pub(crate) fn get_signing_requests<'a>(
                     code: RejectCode::CanisterReject,
                     message: format!("Invalid key_id in signature request: {:?}", context.key_id),
                 }),
+                deadline: ic_types::messages::NO_DEADLINE,
             };
             ecdsa_payload.signature_agreements.insert(
                 context.pseudo_random_id,
@@ -1011,6 +1017,7 @@ pub(crate) fn get_signing_requests<'a>(
                         code: RejectCode::CanisterError,
                         message: "Signature request expired".to_string(),
                     }),
+                    deadline: ic_types::messages::NO_DEADLINE,
                 };
                 ecdsa_payload.signature_agreements.insert(
                     context.pseudo_random_id,
@@ -1099,6 +1106,7 @@ pub(crate) fn update_signature_agreements(
                 }
                 .encode(),
             ),
+            deadline: ic_types::messages::NO_DEADLINE,
         };
         completed.insert(*request_id, ecdsa::CompletedSignature::Unreported(response));
     }
@@ -1153,6 +1161,7 @@ fn update_next_key_transcript(
     current_key_transcript: Option<&ecdsa::UnmaskedTranscriptWithAttributes>,
     next_key_transcript_creation: &mut ecdsa::KeyTranscriptCreation,
     uid_generator: &mut ecdsa::EcdsaUIDGenerator,
+    algorithm_id: AlgorithmId,
     transcript_cache: &dyn EcdsaTranscriptBuilder,
     height: Height,
     log: ReplicaLogger,
@@ -1211,7 +1220,7 @@ fn update_next_key_transcript(
                     dealers_set,
                     receivers_set,
                     registry_version,
-                    AlgorithmId::ThresholdEcdsaSecp256k1,
+                    algorithm_id,
                 ),
             );
         }
@@ -1566,6 +1575,7 @@ fn make_reshare_dealings_response(
                             }
                             .encode(),
                         ),
+                        deadline: ic_types::messages::NO_DEADLINE,
                     });
                 }
             }
@@ -1833,10 +1843,20 @@ mod tests {
         uid_generator: &mut ecdsa::EcdsaUIDGenerator,
         quadruples_in_creation: &mut BTreeMap<ecdsa::QuadrupleId, ecdsa::QuadrupleInCreation>,
     ) -> (ecdsa::RandomTranscriptParams, ecdsa::RandomTranscriptParams) {
-        let kappa_config_ref =
-            new_random_config(subnet_nodes, registry_version, uid_generator).unwrap();
-        let lambda_config_ref =
-            new_random_config(subnet_nodes, registry_version, uid_generator).unwrap();
+        let kappa_config_ref = new_random_config(
+            subnet_nodes,
+            registry_version,
+            uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
+        )
+        .unwrap();
+        let lambda_config_ref = new_random_config(
+            subnet_nodes,
+            registry_version,
+            uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
+        )
+        .unwrap();
         quadruples_in_creation.insert(
             uid_generator.next_quadruple_id(),
             ecdsa::QuadrupleInCreation::new(kappa_config_ref.clone(), lambda_config_ref.clone()),
@@ -1900,6 +1920,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ecdsa_make_new_quadruples_for_secp256r1_key() {
+        let subnet_id = subnet_test_id(1);
+        let subnet_nodes = (0..4).map(node_test_id).collect::<Vec<_>>();
+        let mut ecdsa_payload = empty_ecdsa_payload(subnet_id);
+        ecdsa_payload.key_transcript.key_id = EcdsaKeyId::from_str("Secp256r1:some_key").unwrap();
+        let ecdsa_config = EcdsaConfig {
+            quadruples_to_create_in_advance: 2,
+            ..EcdsaConfig::default()
+        };
+        let result = make_new_quadruples_if_needed_helper(
+            &subnet_nodes,
+            RegistryVersion::new(10),
+            &ecdsa_config,
+            &mut ecdsa_payload,
+        );
+        assert!(result.is_ok());
+        assert_eq!(ecdsa_payload.quadruples_in_creation.len(), 2);
+        for quadruple in ecdsa_payload.quadruples_in_creation.values() {
+            for config in [&quadruple.kappa_config, &quadruple.lambda_config] {
+                assert_eq!(
+                    config.as_ref().algorithm_id,
+                    AlgorithmId::ThresholdEcdsaSecp256r1
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_ecdsa_signing_request_order() {
         let subnet_id = subnet_test_id(1);
@@ -2312,6 +2360,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2345,6 +2394,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2384,6 +2434,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2421,6 +2472,7 @@ mod tests {
             Some(&current_key_transcript),
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2455,6 +2507,7 @@ mod tests {
             Some(&current_key_transcript),
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2505,6 +2558,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2538,6 +2592,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2577,6 +2632,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2626,6 +2682,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
@@ -2664,6 +2721,7 @@ mod tests {
             None,
             &mut payload.key_transcript.next_in_creation,
             &mut payload.uid_generator,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
             &transcript_builder,
             cur_height,
             no_op_logger(),
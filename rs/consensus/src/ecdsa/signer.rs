@@ -762,6 +762,75 @@ mod tests {
         })
     }
 
+    // Tests that signature shares are sent for requests on a secp256r1 key, and
+    // that requests whose quadruple does not match the key algorithm are skipped.
+    #[test]
+    fn test_ecdsa_send_signature_shares_for_secp256r1_key() {
+        ic_test_utilities::artifact_pool_config::with_test_pool_config(|pool_config| {
+            with_test_replica_logger(|logger| {
+                let (ecdsa_pool, signer) = create_signer_dependencies(pool_config, logger);
+                let mut uid_generator = EcdsaUIDGenerator::new(subnet_test_id(1), Height::new(0));
+                let height = Height::from(100);
+                let (id_1, id_2, id_3) = (
+                    create_request_id(&mut uid_generator, height),
+                    create_request_id(&mut uid_generator, height),
+                    create_request_id(&mut uid_generator, height),
+                );
+                let algorithm_id = AlgorithmId::ThresholdEcdsaSecp256r1;
+
+                // Request 3 uses a secp256k1 quadruple with a secp256r1 key
+                let mut mismatched_sig_inputs = create_sig_inputs(3);
+                let key_transcript_ref = *mismatched_sig_inputs
+                    .sig_inputs_ref
+                    .key_transcript_ref
+                    .as_ref();
+                mismatched_sig_inputs
+                    .idkg_transcripts
+                    .get_mut(&key_transcript_ref)
+                    .unwrap()
+                    .algorithm_id = algorithm_id;
+
+                let block_reader = TestEcdsaBlockReader::for_signer_test(
+                    height,
+                    vec![
+                        (
+                            id_1,
+                            create_sig_inputs_with_height_and_algorithm(
+                                1,
+                                Height::new(0),
+                                algorithm_id,
+                            ),
+                        ),
+                        (
+                            id_2,
+                            create_sig_inputs_with_height_and_algorithm(
+                                2,
+                                Height::new(0),
+                                algorithm_id,
+                            ),
+                        ),
+                        (id_3, mismatched_sig_inputs),
+                    ],
+                );
+                let transcript_loader: TestEcdsaTranscriptLoader = Default::default();
+
+                let change_set =
+                    signer.send_signature_shares(&ecdsa_pool, &transcript_loader, &block_reader);
+                assert_eq!(change_set.len(), 2);
+                assert!(is_signature_share_added_to_validated(
+                    &change_set,
+                    &id_1,
+                    block_reader.tip_height()
+                ));
+                assert!(is_signature_share_added_to_validated(
+                    &change_set,
+                    &id_2,
+                    block_reader.tip_height()
+                ));
+            })
+        })
+    }
+
     // Tests that complaints are generated and added to the pool if loading transcript
     // results in complaints.
     #[test]
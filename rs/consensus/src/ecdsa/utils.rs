@@ -1,7 +1,7 @@
 //! Common utils for the ECDSA implementation.
 
 use crate::ecdsa::complaints::{EcdsaTranscriptLoader, TranscriptLoadStatus};
-use ic_ic00_types::EcdsaKeyId;
+use ic_ic00_types::{EcdsaCurve, EcdsaKeyId};
 use ic_interfaces::consensus_pool::ConsensusBlockChain;
 use ic_interfaces::ecdsa::{EcdsaChangeAction, EcdsaChangeSet, EcdsaPool};
 use ic_protobuf::registry::subnet::v1 as pb;
@@ -13,6 +13,7 @@ use ic_types::consensus::ecdsa::{
 use ic_types::crypto::canister_threshold_sig::idkg::{
     IDkgTranscript, IDkgTranscriptOperation, InitialIDkgDealings,
 };
+use ic_types::crypto::AlgorithmId;
 use ic_types::Height;
 use std::collections::BTreeSet;
 use std::convert::TryInto;
@@ -158,6 +159,14 @@ pub(crate) fn transcript_op_summary(op: &IDkgTranscriptOperation) -> String {
     }
 }
 
+/// Return the threshold signature algorithm used by transcripts of the given key.
+pub(crate) fn algorithm_for_key_id(key_id: &EcdsaKeyId) -> AlgorithmId {
+    match key_id.curve {
+        EcdsaCurve::Secp256k1 => AlgorithmId::ThresholdEcdsaSecp256k1,
+        EcdsaCurve::Secp256r1 => AlgorithmId::ThresholdEcdsaSecp256r1,
+    }
+}
+
 /// Inspect ecdsa_initializations field in the CUPContent.
 /// Return key_id and dealings.
 pub(crate) fn inspect_ecdsa_initializations(
@@ -256,6 +265,7 @@ pub(crate) mod test_utils {
             // be refunded to the canister.
             refund: ic_types::Cycles::new(0),
             response_payload: ic_types::messages::Payload::Data(vec![]),
+            deadline: ic_types::messages::NO_DEADLINE,
         }
     }
 
@@ -791,6 +801,19 @@ pub(crate) mod test_utils {
 
     // Creates a test signature input
     pub(crate) fn create_sig_inputs_with_height(caller: u8, height: Height) -> TestSigInputs {
+        create_sig_inputs_with_height_and_algorithm(
+            caller,
+            height,
+            AlgorithmId::ThresholdEcdsaSecp256k1,
+        )
+    }
+
+    // Creates a test signature input for a key of the given algorithm
+    pub(crate) fn create_sig_inputs_with_height_and_algorithm(
+        caller: u8,
+        height: Height,
+        algorithm_id: AlgorithmId,
+    ) -> TestSigInputs {
         let transcript_id = |offset| {
             let val = caller as u64;
             create_transcript_id(val * 214365 + offset)
@@ -806,7 +829,7 @@ pub(crate) mod test_utils {
             transcript_type: IDkgTranscriptType::Unmasked(
                 IDkgUnmaskedTranscriptOrigin::ReshareMasked(key_masked_id),
             ),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         create_sig_inputs_with_args(caller, &receivers, key_unmasked, height)
@@ -819,6 +842,7 @@ pub(crate) mod test_utils {
         key_unmasked: IDkgTranscript,
         height: Height,
     ) -> TestSigInputs {
+        let algorithm_id = key_unmasked.algorithm_id;
         let transcript_id = |offset| {
             let val = caller as u64;
             create_transcript_id(val * 214365 + offset)
@@ -838,7 +862,7 @@ pub(crate) mod test_utils {
             registry_version: RegistryVersion::from(1),
             verified_dealings: BTreeMap::new(),
             transcript_type: IDkgTranscriptType::Masked(IDkgMaskedTranscriptOrigin::Random),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         let kappa_masked_ref = MaskedTranscript::try_from((height, &kappa_masked)).unwrap();
@@ -852,7 +876,7 @@ pub(crate) mod test_utils {
             transcript_type: IDkgTranscriptType::Unmasked(
                 IDkgUnmaskedTranscriptOrigin::ReshareMasked(kappa_masked_id),
             ),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         let kappa_unmasked_ref = UnmaskedTranscript::try_from((height, &kappa_unmasked)).unwrap();
@@ -864,7 +888,7 @@ pub(crate) mod test_utils {
             registry_version: RegistryVersion::from(1),
             verified_dealings: BTreeMap::new(),
             transcript_type: IDkgTranscriptType::Masked(IDkgMaskedTranscriptOrigin::Random),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         let lambda_masked_ref = MaskedTranscript::try_from((height, &lambda_masked)).unwrap();
@@ -884,7 +908,7 @@ pub(crate) mod test_utils {
                     lambda_masked_id,
                 ),
             ),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         let kappa_unmasked_times_lambda_masked_ref =
@@ -902,7 +926,7 @@ pub(crate) mod test_utils {
             transcript_type: IDkgTranscriptType::Masked(
                 IDkgMaskedTranscriptOrigin::UnmaskedTimesMasked(key_unmasked_id, lambda_masked_id),
             ),
-            algorithm_id: AlgorithmId::ThresholdEcdsaSecp256k1,
+            algorithm_id,
             internal_transcript_raw: vec![],
         };
         let key_unmasked_times_lambda_masked_ref =
@@ -10,3 +10,4 @@ pub mod certification;
 pub mod consensus;
 pub mod dkg;
 pub mod ecdsa;
+pub mod query_stats;
@@ -0,0 +1,421 @@
+//! This module implements the payload builder that gossips the query
+//! statistics collected locally by each replica through consensus.
+
+use ic_interfaces::query_stats::{
+    InvalidQueryStatsPayload, QueryStatsPayloadBuilder, QueryStatsPayloadValidationError,
+    QueryStatsTransientValidationError,
+};
+use ic_interfaces_registry::RegistryClient;
+use ic_interfaces_state_manager::StateReader;
+use ic_logger::{warn, ReplicaLogger};
+use ic_registry_client_helpers::subnet::SubnetRegistry;
+use ic_replicated_state::ReplicatedState;
+use ic_types::{
+    batch::{
+        epoch_from_height, LocalQueryStats, QueryStatsEpoch, QueryStatsPayload, ValidationContext,
+    },
+    CanisterId, CountBytes, NodeId, NumBytes, SubnetId,
+};
+use std::{
+    collections::BTreeSet,
+    sync::{mpsc::Receiver, Arc, Mutex, RwLock},
+};
+
+/// Implementation of the [`QueryStatsPayloadBuilder`].
+///
+/// Receives the statistics of each finished epoch from the query handler and
+/// includes them in the blocks that this replica proposes, splitting them
+/// across several blocks if they do not fit into a single one.
+pub struct QueryStatsPayloadBuilderImpl {
+    state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
+    registry: Arc<dyn RegistryClient>,
+    node_id: NodeId,
+    subnet_id: SubnetId,
+    epoch_length: u64,
+    receiver: Mutex<Receiver<LocalQueryStats>>,
+    current_stats: RwLock<Option<LocalQueryStats>>,
+    log: ReplicaLogger,
+}
+
+impl QueryStatsPayloadBuilderImpl {
+    /// Create and initialize an instance of [`QueryStatsPayloadBuilderImpl`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
+        registry: Arc<dyn RegistryClient>,
+        node_id: NodeId,
+        subnet_id: SubnetId,
+        epoch_length: u64,
+        receiver: Receiver<LocalQueryStats>,
+        log: ReplicaLogger,
+    ) -> Self {
+        Self {
+            state_reader,
+            registry,
+            node_id,
+            subnet_id,
+            epoch_length,
+            receiver: Mutex::new(receiver),
+            current_stats: RwLock::new(None),
+            log,
+        }
+    }
+
+    /// Replaces the current statistics with the most recent ones sent by the
+    /// query handler, if any.
+    fn receive_stats(&self) {
+        let receiver = self.receiver.lock().unwrap();
+        let mut current_stats = self.current_stats.write().unwrap();
+        while let Ok(stats) = receiver.try_recv() {
+            *current_stats = Some(stats);
+        }
+    }
+
+    /// Returns the canisters for which this replica's statistics of `epoch`
+    /// were already included in `past_payloads` or in the state at the
+    /// certified height.
+    ///
+    /// Returns `None` if the state is not available or if `epoch` was already
+    /// aggregated.
+    fn already_included(
+        &self,
+        epoch: QueryStatsEpoch,
+        validation_context: &ValidationContext,
+        past_payloads: &[&QueryStatsPayload],
+    ) -> Option<BTreeSet<CanisterId>> {
+        let state = match self
+            .state_reader
+            .get_state_at(validation_context.certified_height)
+        {
+            Ok(state) => state.take(),
+            Err(err) => {
+                warn!(
+                    every_n_seconds => 30,
+                    self.log,
+                    "Failed to get state at height {} for the query stats payload: {:?}",
+                    validation_context.certified_height,
+                    err
+                );
+                return None;
+            }
+        };
+        let query_stats = &state.metadata.query_stats;
+        if query_stats
+            .highest_aggregated_epoch
+            .map_or(false, |aggregated| epoch <= aggregated)
+        {
+            // The epoch was already aggregated, there is nothing left to do.
+            return None;
+        }
+
+        let mut included: BTreeSet<CanisterId> = query_stats
+            .stats
+            .get(&epoch)
+            .into_iter()
+            .flat_map(|canisters| canisters.iter())
+            .filter(|(_, nodes)| nodes.contains_key(&self.node_id))
+            .map(|(canister_id, _)| *canister_id)
+            .collect();
+        included.extend(
+            past_payloads
+                .iter()
+                .filter(|payload| payload.proposer == self.node_id && payload.epoch == epoch)
+                .flat_map(|payload| payload.stats.iter().map(|stats| stats.canister_id)),
+        );
+        Some(included)
+    }
+}
+
+impl QueryStatsPayloadBuilder for QueryStatsPayloadBuilderImpl {
+    fn get_query_stats_payload(
+        &self,
+        validation_context: &ValidationContext,
+        past_payloads: &[&QueryStatsPayload],
+        byte_limit: NumBytes,
+    ) -> Option<QueryStatsPayload> {
+        self.receive_stats();
+        let current_stats = self.current_stats.read().unwrap();
+        let current_stats = current_stats.as_ref()?;
+
+        let certified_epoch =
+            epoch_from_height(validation_context.certified_height, self.epoch_length);
+        if current_stats.epoch >= certified_epoch {
+            return None;
+        }
+
+        let included =
+            self.already_included(current_stats.epoch, validation_context, past_payloads)?;
+        let mut payload = QueryStatsPayload {
+            epoch: current_stats.epoch,
+            proposer: self.node_id,
+            stats: vec![],
+        };
+        let mut size = payload.count_bytes() as u64;
+        for stats in current_stats
+            .stats
+            .iter()
+            .filter(|stats| !included.contains(&stats.canister_id))
+        {
+            size += stats.count_bytes() as u64;
+            if size > byte_limit.get() {
+                break;
+            }
+            payload.stats.push(stats.clone());
+        }
+
+        if payload.stats.is_empty() {
+            return None;
+        }
+        Some(payload)
+    }
+
+    fn validate_query_stats_payload(
+        &self,
+        payload: Option<&QueryStatsPayload>,
+        validation_context: &ValidationContext,
+        past_payloads: &[&QueryStatsPayload],
+    ) -> Result<NumBytes, QueryStatsPayloadValidationError> {
+        let payload = match payload {
+            Some(payload) => payload,
+            None => return Ok(NumBytes::new(0)),
+        };
+
+        let certified_epoch =
+            epoch_from_height(validation_context.certified_height, self.epoch_length);
+        if payload.epoch >= certified_epoch {
+            return Err(QueryStatsPayloadValidationError::Permanent(
+                InvalidQueryStatsPayload::EpochNotFinished {
+                    epoch: payload.epoch,
+                    certified_epoch,
+                },
+            ));
+        }
+
+        let nodes = self
+            .registry
+            .get_node_ids_on_subnet(self.subnet_id, validation_context.registry_version)
+            .map_err(|err| {
+                QueryStatsPayloadValidationError::Transient(
+                    QueryStatsTransientValidationError::RegistryUnavailable(err),
+                )
+            })?
+            .unwrap_or_default();
+        if !nodes.contains(&payload.proposer) {
+            return Err(QueryStatsPayloadValidationError::Permanent(
+                InvalidQueryStatsPayload::ProposerNotMember(payload.proposer),
+            ));
+        }
+
+        let mut seen: BTreeSet<CanisterId> = past_payloads
+            .iter()
+            .filter(|past| past.proposer == payload.proposer && past.epoch == payload.epoch)
+            .flat_map(|past| past.stats.iter().map(|stats| stats.canister_id))
+            .collect();
+        for stats in &payload.stats {
+            if !seen.insert(stats.canister_id) {
+                return Err(QueryStatsPayloadValidationError::Permanent(
+                    InvalidQueryStatsPayload::DuplicateCanisterId(stats.canister_id),
+                ));
+            }
+        }
+
+        Ok(NumBytes::new(payload.count_bytes() as u64))
+    }
+}
+
+/// Checks that the query statistics included in a block, if any, were
+/// reported by the block maker.
+///
+/// Delivered statistics are attributed to their proposer, so a block maker
+/// must not be able to report statistics on behalf of other nodes.
+pub(crate) fn validate_query_stats_proposer(
+    payload: Option<&QueryStatsPayload>,
+    block_maker: NodeId,
+) -> Result<(), InvalidQueryStatsPayload> {
+    match payload {
+        Some(payload) if payload.proposer != block_maker => {
+            Err(InvalidQueryStatsPayload::ProposerNotBlockMaker {
+                proposer: payload.proposer,
+                block_maker,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_interfaces_state_manager::Labeled;
+    use ic_interfaces_state_manager_mocks::MockStateManager;
+    use ic_logger::replica_logger::no_op_logger;
+    use ic_test_utilities::{
+        mock_time,
+        state::get_initial_state,
+        types::ids::{canister_test_id, node_test_id, subnet_test_id},
+    };
+    use ic_test_utilities_registry::{setup_registry, SubnetRecordBuilder};
+    use ic_types::{
+        batch::{CanisterQueryStats, QueryStats},
+        Height, RegistryVersion,
+    };
+    use std::sync::mpsc::{channel, Sender};
+
+    const EPOCH_LENGTH: u64 = 10;
+
+    fn setup(state: ReplicatedState) -> (QueryStatsPayloadBuilderImpl, Sender<LocalQueryStats>) {
+        let subnet_id = subnet_test_id(0);
+        let mut state_manager = MockStateManager::new();
+        state_manager
+            .expect_get_state_at()
+            .return_const(Ok(Labeled::new(Height::new(0), Arc::new(state))));
+        let registry = setup_registry(
+            subnet_id,
+            vec![(
+                1,
+                SubnetRecordBuilder::from(&[node_test_id(0), node_test_id(1)]).build(),
+            )],
+        );
+        let (sender, receiver) = channel();
+        let builder = QueryStatsPayloadBuilderImpl::new(
+            Arc::new(state_manager),
+            registry,
+            node_test_id(0),
+            subnet_id,
+            EPOCH_LENGTH,
+            receiver,
+            no_op_logger(),
+        );
+        (builder, sender)
+    }
+
+    fn context(certified_height: u64) -> ValidationContext {
+        ValidationContext {
+            registry_version: RegistryVersion::from(1),
+            certified_height: Height::from(certified_height),
+            time: mock_time(),
+        }
+    }
+
+    fn local_stats(epoch: u64, num_canisters: u64) -> LocalQueryStats {
+        LocalQueryStats {
+            epoch: QueryStatsEpoch::from(epoch),
+            stats: (0..num_canisters)
+                .map(|i| CanisterQueryStats {
+                    canister_id: canister_test_id(i),
+                    stats: QueryStats {
+                        num_calls: 1,
+                        num_instructions: 1_000,
+                        ingress_payload_size: 10,
+                        egress_payload_size: 100,
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn payload_includes_stats_of_finished_epoch_only() {
+        let (builder, sender) = setup(get_initial_state(0, 0));
+        sender.send(local_stats(1, 3)).unwrap();
+
+        // The certified height is still in epoch 1.
+        assert_eq!(
+            builder.get_query_stats_payload(&context(15), &[], NumBytes::new(1 << 20)),
+            None
+        );
+
+        let payload = builder
+            .get_query_stats_payload(&context(20), &[], NumBytes::new(1 << 20))
+            .unwrap();
+        assert_eq!(payload.epoch, QueryStatsEpoch::from(1));
+        assert_eq!(payload.proposer, node_test_id(0));
+        assert_eq!(payload.stats.len(), 3);
+        assert!(builder
+            .validate_query_stats_payload(Some(&payload), &context(20), &[])
+            .is_ok());
+    }
+
+    #[test]
+    fn payload_is_split_across_blocks() {
+        let (builder, sender) = setup(get_initial_state(0, 0));
+        sender.send(local_stats(1, 3)).unwrap();
+
+        let empty = QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(1),
+            proposer: node_test_id(0),
+            stats: vec![],
+        };
+        let byte_limit = NumBytes::new(
+            (empty.count_bytes() + 2 * local_stats(1, 1).stats[0].count_bytes()) as u64,
+        );
+        let first = builder
+            .get_query_stats_payload(&context(20), &[], byte_limit)
+            .unwrap();
+        assert_eq!(first.stats.len(), 2);
+
+        let second = builder
+            .get_query_stats_payload(&context(20), &[&first], byte_limit)
+            .unwrap();
+        assert_eq!(second.stats.len(), 1);
+        assert!(builder
+            .validate_query_stats_payload(Some(&second), &context(20), &[&first])
+            .is_ok());
+
+        assert_eq!(
+            builder.get_query_stats_payload(&context(20), &[&second, &first], byte_limit),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_payloads_are_rejected() {
+        let (builder, _sender) = setup(get_initial_state(0, 0));
+        let payload = QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(1),
+            proposer: node_test_id(1),
+            stats: local_stats(1, 2).stats,
+        };
+
+        assert!(matches!(
+            builder.validate_query_stats_payload(Some(&payload), &context(15), &[]),
+            Err(QueryStatsPayloadValidationError::Permanent(
+                InvalidQueryStatsPayload::EpochNotFinished { .. }
+            ))
+        ));
+
+        let not_member = QueryStatsPayload {
+            proposer: node_test_id(7),
+            ..payload.clone()
+        };
+        assert!(matches!(
+            builder.validate_query_stats_payload(Some(&not_member), &context(20), &[]),
+            Err(QueryStatsPayloadValidationError::Permanent(
+                InvalidQueryStatsPayload::ProposerNotMember(_)
+            ))
+        ));
+
+        assert!(matches!(
+            builder.validate_query_stats_payload(Some(&payload), &context(20), &[&payload]),
+            Err(QueryStatsPayloadValidationError::Permanent(
+                InvalidQueryStatsPayload::DuplicateCanisterId(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn payloads_of_other_nodes_are_rejected() {
+        let payload = QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(1),
+            proposer: node_test_id(1),
+            stats: local_stats(1, 2).stats,
+        };
+
+        assert!(validate_query_stats_proposer(None, node_test_id(0)).is_ok());
+        assert!(validate_query_stats_proposer(Some(&payload), node_test_id(1)).is_ok());
+        assert!(matches!(
+            validate_query_stats_proposer(Some(&payload), node_test_id(0)),
+            Err(InvalidQueryStatsPayload::ProposerNotBlockMaker { .. })
+        ));
+    }
+}
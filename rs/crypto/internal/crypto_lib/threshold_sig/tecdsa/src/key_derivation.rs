@@ -94,6 +94,66 @@ impl DerivationPath {
         }
     }
 
+    /// SLIP-0010 Public parent key -> public child key for NIST P-256
+    ///
+    /// See <https://github.com/satoshilabs/slips/blob/master/slip-0010.md>
+    ///
+    /// This matches BIP32 CKDpub except when iL >= order or the derived key
+    /// is the point at infinity. In that case SLIP-0010 does not move on to
+    /// the next index but hashes `0x01 || iR || index` with the same chain
+    /// key. Like [`Self::bip32_ckdpub`] it is extended to support larger
+    /// indices.
+    fn slip10_ckdpub(
+        public_key: &EccPoint,
+        chain_key: &[u8],
+        index: &DerivationIndex,
+    ) -> ThresholdEcdsaResult<(EccPoint, Vec<u8>, EccScalar)> {
+        if public_key.curve_type() != EccCurveType::P256 {
+            return Err(ThresholdEcdsaError::CurveMismatch);
+        }
+
+        let mut hmac = Hmac::<Sha512>::new(chain_key);
+
+        hmac.write(&public_key.serialize());
+        hmac.write(&index.0);
+
+        let mut hmac_output = hmac.finish();
+
+        loop {
+            let key_offset =
+                EccScalar::from_bytes_wide(public_key.curve_type(), &hmac_output[..32])?;
+
+            let new_key = public_key.add_points(&EccPoint::mul_by_g(&key_offset)?)?;
+
+            if key_offset.serialize() == hmac_output[..32] && !new_key.is_infinity()? {
+                return Ok((new_key, hmac_output[32..].to_vec(), key_offset));
+            }
+
+            let mut hmac = Hmac::<Sha512>::new(chain_key);
+
+            hmac.write(&[0x01]);
+            hmac.write(&hmac_output[32..]);
+            hmac.write(&index.0);
+
+            hmac_output = hmac.finish();
+        }
+    }
+
+    /// Public parent key -> public child key, using the derivation
+    /// scheme that is standard for the curve of `public_key`
+    ///
+    /// This is BIP32 for secp256k1 and SLIP-0010 for P-256.
+    fn ckdpub(
+        public_key: &EccPoint,
+        chain_key: &[u8],
+        index: &DerivationIndex,
+    ) -> ThresholdEcdsaResult<(EccPoint, Vec<u8>, EccScalar)> {
+        match public_key.curve_type() {
+            EccCurveType::K256 => Self::bip32_ckdpub(public_key, chain_key, index),
+            EccCurveType::P256 => Self::slip10_ckdpub(public_key, chain_key, index),
+        }
+    }
+
     pub fn derive_tweak(
         &self,
         master_public_key: &EccPoint,
@@ -116,27 +176,19 @@ impl DerivationPath {
 
         let curve_type = master_public_key.curve_type();
 
-        if curve_type == EccCurveType::K256 {
-            let mut derived_key = master_public_key.clone();
-            let mut derived_chain_key = chain_code.to_vec();
-            let mut derived_offset = EccScalar::zero(curve_type);
+        let mut derived_key = master_public_key.clone();
+        let mut derived_chain_key = chain_code.to_vec();
+        let mut derived_offset = EccScalar::zero(curve_type);
 
-            for idx in &self.path {
-                let (next_derived_key, next_chain_key, next_offset) =
-                    Self::bip32_ckdpub(&derived_key, &derived_chain_key, idx)?;
+        for idx in &self.path {
+            let (next_derived_key, next_chain_key, next_offset) =
+                Self::ckdpub(&derived_key, &derived_chain_key, idx)?;
 
-                derived_key = next_derived_key;
-                derived_chain_key = next_chain_key;
-                derived_offset = derived_offset.add(&next_offset)?;
-            }
-
-            Ok((derived_offset, derived_chain_key))
-        } else {
-            // Key derivation is not currently defined for curves other than secp256k1
-            Err(ThresholdEcdsaError::InvalidArguments(format!(
-                "Currently key derivation not defined for {}",
-                curve_type
-            )))
+            derived_key = next_derived_key;
+            derived_chain_key = next_chain_key;
+            derived_offset = derived_offset.add(&next_offset)?;
         }
+
+        Ok((derived_offset, derived_chain_key))
     }
 }
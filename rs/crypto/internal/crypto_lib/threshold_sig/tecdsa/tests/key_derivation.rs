@@ -24,21 +24,65 @@ fn test_index_next_behavior() {
 }
 
 #[test]
-fn test_that_key_derivation_on_secp256r1_currently_fails() -> Result<(), ThresholdEcdsaError> {
+fn test_that_key_derivation_on_secp256r1_is_consistent() -> Result<(), ThresholdEcdsaError> {
     let mut rng = reproducible_rng();
-    let path = DerivationPath::new_bip32(&[1, 2, 3]);
     let master_key = EccPoint::hash_to_point(
         EccCurveType::P256,
         &rng.gen::<[u8; 32]>(),
         "public_key".as_bytes(),
     )?;
 
-    assert_eq!(
-        path.derive_tweak(&master_key),
-        Err(ThresholdEcdsaError::InvalidArguments(
-            "Currently key derivation not defined for secp256r1".to_string()
-        ))
-    );
+    let path = DerivationPath::new_bip32(&[1, 2, 3]);
+    let (tweak, chain_key) = path.derive_tweak(&master_key)?;
+    assert_eq!(tweak.curve_type(), EccCurveType::P256);
+    assert_eq!(chain_key.len(), 32);
+
+    // Deriving step by step yields the same key as deriving the full path
+    let (tweak_1, chain_key_1) = DerivationPath::new_bip32(&[1]).derive_tweak(&master_key)?;
+    let key_1 = master_key.add_points(&EccPoint::mul_by_g(&tweak_1)?)?;
+    let (tweak_23, chain_key_23) =
+        DerivationPath::new_bip32(&[2, 3]).derive_tweak_with_chain_code(&key_1, &chain_key_1)?;
+
+    assert_eq!(tweak_1.add(&tweak_23)?, tweak);
+    assert_eq!(chain_key_23, chain_key);
+
+    Ok(())
+}
+
+#[test]
+fn should_secp256r1_key_derivation_match_slip10_test_vectors() -> Result<(), ThresholdEcdsaError> {
+    // Public derivation steps of the nist256p1 test vectors of SLIP-0010.
+    // The second one covers the case where the derived offset is not a
+    // valid scalar and derivation has to be retried.
+    let test_vectors = [
+        (
+            "02c9e16154474b3ed5b38218bb0463e008f89ee03e62d22fdcc8014beab25b48fa",
+            "96cd4465a9644e31528eda3592aa35eb39a9527769ce1855beafc1b81055e75d",
+            0,
+            "039b6df4bece7b6c81e2adfeea4bcf5c8c8a6e40ea7ffa3cf6e8494c61a1fc82cc",
+            "84e9c258bb8557a40e0d041115b376dd55eda99c0042ce29e81ebe4efed9b86a",
+        ),
+        (
+            "02519b5554a4872e8c9c1c847115363051ec43e93400e030ba3c36b52a3e70a5b7",
+            "e94c8ebe30c2250a14713212f6449b20f3329105ea15b652ca5bdfc68f6c65c2",
+            33941,
+            "0235bfee614c0d5b2cae260000bb1d0d84b270099ad790022c1ae0b2e782efe120",
+            "9e87fe95031f14736774cd82f25fd885065cb7c358c1edf813c72af535e83071",
+        ),
+    ];
+
+    for (parent_key, parent_chain_code, index, child_key, child_chain_code) in test_vectors {
+        let parent_key =
+            EccPoint::deserialize(EccCurveType::P256, &hex::decode(parent_key).unwrap())?;
+        let parent_chain_code = hex::decode(parent_chain_code).unwrap();
+
+        let (tweak, chain_code) = DerivationPath::new_bip32(&[index])
+            .derive_tweak_with_chain_code(&parent_key, &parent_chain_code)?;
+        let derived_key = parent_key.add_points(&EccPoint::mul_by_g(&tweak)?)?;
+
+        assert_eq!(hex::encode(derived_key.serialize()), child_key);
+        assert_eq!(hex::encode(chain_code), child_chain_code);
+    }
 
     Ok(())
 }
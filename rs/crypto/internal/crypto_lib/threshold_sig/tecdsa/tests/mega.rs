@@ -115,6 +115,60 @@ fn mega_single_smoke_test() -> Result<(), ThresholdEcdsaError> {
     Ok(())
 }
 
+#[test]
+fn mega_should_require_recipient_keys_on_the_plaintext_curve() -> Result<(), ThresholdEcdsaError> {
+    let curve = EccCurveType::P256;
+
+    let mut rng = reproducible_rng();
+
+    let a_sk = MEGaPrivateKey::generate(curve, &mut rng);
+    let a_pk = a_sk.public_key()?;
+    let k256_pk = MEGaPrivateKey::generate(EccCurveType::K256, &mut rng).public_key()?;
+
+    let associated_data = b"assoc_data_test";
+    let ptext_for_a = (
+        EccScalar::random(curve, &mut rng),
+        EccScalar::random(curve, &mut rng),
+    );
+
+    let dealer_index = 0;
+
+    let ctext = MEGaCiphertextPair::encrypt(
+        Seed::from_rng(&mut rng),
+        &[ptext_for_a.clone()],
+        &[a_pk.clone()],
+        dealer_index,
+        associated_data,
+    )?;
+
+    assert!(MEGaCiphertext::from(ctext.clone())
+        .verify_is(MEGaCiphertextType::Pairs, curve)
+        .is_ok());
+    assert_eq!(
+        MEGaCiphertext::from(ctext.clone())
+            .verify_is(MEGaCiphertextType::Pairs, EccCurveType::K256),
+        Err(ThresholdEcdsaError::CurveMismatch)
+    );
+    assert_eq!(
+        ctext.decrypt(associated_data, dealer_index, 0, &a_sk, &a_pk)?,
+        ptext_for_a
+    );
+
+    // P-256 plaintexts cannot be encrypted to secp256k1 MEGa keys
+    assert_eq!(
+        MEGaCiphertextSingle::encrypt(
+            Seed::from_rng(&mut rng),
+            &[ptext_for_a.0],
+            &[k256_pk],
+            dealer_index,
+            associated_data,
+        ),
+        Err(ThresholdEcdsaError::CurveMismatch)
+    );
+
+    Ok(())
+}
+
 #[test]
 fn mega_pair_smoke_test() -> Result<(), ThresholdEcdsaError> {
     let curve = EccCurveType::K256;
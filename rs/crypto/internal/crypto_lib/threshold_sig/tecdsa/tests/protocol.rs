@@ -207,18 +207,17 @@ fn random_subset(
     result
 }
 
-#[test]
-fn should_basic_signing_protocol_work() -> Result<(), ThresholdEcdsaError> {
-    fn test_sig_serialization(
-        alg: ic_types::crypto::AlgorithmId,
-        sig: &ThresholdEcdsaCombinedSigInternal,
-    ) -> Result<(), ThresholdEcdsaError> {
-        let bytes = sig.serialize();
-        let sig2 = ThresholdEcdsaCombinedSigInternal::deserialize(alg, &bytes)?;
-        assert_eq!(*sig, sig2);
-        Ok(())
-    }
+fn test_sig_serialization(
+    alg: ic_types::crypto::AlgorithmId,
+    sig: &ThresholdEcdsaCombinedSigInternal,
+) -> Result<(), ThresholdEcdsaError> {
+    let bytes = sig.serialize();
+    let sig2 = ThresholdEcdsaCombinedSigInternal::deserialize(alg, &bytes)?;
+    assert_eq!(*sig, sig2);
+    Ok(())
+}
 
+fn test_signing_protocol(curve: EccCurveType) -> Result<(), ThresholdEcdsaError> {
     let nodes = 10;
     let threshold = nodes / 3;
     let number_of_dealings_corrupted = threshold;
@@ -227,7 +226,7 @@ fn should_basic_signing_protocol_work() -> Result<(), ThresholdEcdsaError> {
     let random_seed = Seed::from_rng(&mut rng);
 
     let setup = SignatureProtocolSetup::new(
-        EccCurveType::K256,
+        curve,
         nodes,
         threshold,
         number_of_dealings_corrupted,
@@ -276,3 +275,13 @@ fn should_basic_signing_protocol_work() -> Result<(), ThresholdEcdsaError> {
 
     Ok(())
 }
+
+#[test]
+fn should_basic_signing_protocol_work() -> Result<(), ThresholdEcdsaError> {
+    test_signing_protocol(EccCurveType::K256)
+}
+
+#[test]
+fn should_basic_signing_protocol_work_with_secp256r1() -> Result<(), ThresholdEcdsaError> {
+    test_signing_protocol(EccCurveType::P256)
+}
@@ -28,11 +28,7 @@ impl ProtocolSetup {
     ) -> Result<Self, ThresholdEcdsaError> {
         let alg = match curve {
             EccCurveType::K256 => AlgorithmId::ThresholdEcdsaSecp256k1,
-            _ => {
-                return Err(ThresholdEcdsaError::InvalidArguments(
-                    "Unsupported curve".to_string(),
-                ))
-            }
+            EccCurveType::P256 => AlgorithmId::ThresholdEcdsaSecp256r1,
         };
 
         let mut rng = seed.into_rng();
@@ -737,8 +733,12 @@ impl SignatureProtocolSetup {
     }
 
     pub fn public_key(&self, path: &DerivationPath) -> Result<EcdsaPublicKey, ThresholdEcdsaError> {
+        let algorithm_id = match self.setup.alg {
+            AlgorithmId::ThresholdEcdsaSecp256r1 => AlgorithmId::EcdsaP256,
+            _ => AlgorithmId::EcdsaSecp256k1,
+        };
         let master_public_key = MasterEcdsaPublicKey {
-            algorithm_id: AlgorithmId::EcdsaSecp256k1,
+            algorithm_id,
             public_key: self.key.transcript.constant_term().serialize(),
         };
         ic_crypto_internal_threshold_sig_ecdsa::sign::derive_public_key(&master_public_key, path)
@@ -852,13 +852,27 @@ impl SignatureProtocolExecution {
 
         use k256::ecdsa::signature::{Signature, Verifier};
 
-        let vk = k256::ecdsa::VerifyingKey::from_sec1_bytes(&pk.public_key)
-            .expect("Failed to parse public key");
+        match self.setup.alg() {
+            AlgorithmId::ThresholdEcdsaSecp256k1 => {
+                let vk = k256::ecdsa::VerifyingKey::from_sec1_bytes(&pk.public_key)
+                    .expect("Failed to parse public key");
 
-        let sig = k256::ecdsa::Signature::from_bytes(&sig.serialize())
-            .expect("Failed to parse signature");
+                let sig = k256::ecdsa::Signature::from_bytes(&sig.serialize())
+                    .expect("Failed to parse signature");
+
+                assert!(vk.verify(&self.signed_message, &sig).is_ok());
+            }
+            AlgorithmId::ThresholdEcdsaSecp256r1 => {
+                let vk = p256::ecdsa::VerifyingKey::from_sec1_bytes(&pk.public_key)
+                    .expect("Failed to parse public key");
 
-        assert!(vk.verify(&self.signed_message, &sig).is_ok());
+                let sig = p256::ecdsa::Signature::from_bytes(&sig.serialize())
+                    .expect("Failed to parse signature");
+
+                assert!(vk.verify(&self.signed_message, &sig).is_ok());
+            }
+            alg => panic!("Unexpected algorithm {:?}", alg),
+        }
 
         Ok(())
     }
@@ -59,6 +59,77 @@ impl std::fmt::Display for CyclesAccountManagerError {
     }
 }
 
+/// Describes the saturation of a resource, such as the subnet memory.
+///
+/// Usage below `threshold` is free of reservations. Above the threshold, the
+/// number of cycles to reserve for an allocation grows linearly with usage
+/// and reaches its maximum when the usage reaches `capacity`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResourceSaturation {
+    usage: u64,
+    threshold: u64,
+    capacity: u64,
+}
+
+impl ResourceSaturation {
+    /// Creates a new resource saturation. Both `usage` and `threshold` are
+    /// capped at `capacity`.
+    pub fn new(usage: u64, threshold: u64, capacity: u64) -> Self {
+        let usage = usage.min(capacity);
+        let threshold = threshold.min(capacity);
+        Self {
+            usage,
+            threshold,
+            capacity,
+        }
+    }
+
+    /// Returns the current usage of the resource.
+    pub fn usage(&self) -> u64 {
+        self.usage
+    }
+
+    /// Returns the usage after which reservations start.
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    /// Returns the total capacity of the resource.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Computes the amount of the resource that is reserved for allocating
+    /// `allocated` units on top of the current usage.
+    ///
+    /// The reservation is the integral of the saturation ratio
+    /// `(usage - threshold) / (capacity - threshold)` over the allocated range,
+    /// so each allocated unit above the threshold reserves between zero and
+    /// one units depending on how saturated the resource is.
+    pub fn reservation_factor(&self, allocated: u64) -> u64 {
+        if self.capacity <= self.threshold {
+            // Usage cannot exceed the capacity, so it never goes above the
+            // threshold and nothing needs to be reserved.
+            return 0;
+        }
+        let c = (self.capacity - self.threshold) as u128;
+        // Integral of `min(y, c)` from 0 to `y`.
+        let integral = |y: u128| -> u128 {
+            if y <= c {
+                y * y / 2
+            } else {
+                c * c / 2 + (y - c) * c
+            }
+        };
+        let before = self.usage.saturating_sub(self.threshold) as u128;
+        let after = self
+            .usage
+            .saturating_add(allocated)
+            .saturating_sub(self.threshold) as u128;
+        ((integral(after) - integral(before)) / c) as u64
+    }
+}
+
 /// Handles any operation related to cycles accounting, such as charging (due to
 /// using system resources) or refunding unused cycles.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -491,6 +562,9 @@ impl CyclesAccountManager {
     /// stable memory (among other things). This will be revised in the future
     /// to take into account charging for dirty/read pages by the canister.
     ///
+    /// The cost is paid from the reserved balance first and the remainder is
+    /// paid from the main balance.
+    ///
     /// # Errors
     ///
     /// Returns a `CanisterOutOfCyclesError` if there's
@@ -503,9 +577,34 @@ impl CyclesAccountManager {
         subnet_size: usize,
     ) -> Result<(), CanisterOutOfCyclesError> {
         let cycles_amount = self.memory_cost(bytes, duration, subnet_size);
+        let from_reserved = std::cmp::min(cycles_amount, system_state.reserved_balance());
 
         // Can charge all the way to the empty account (zero cycles)
-        self.consume_with_threshold(system_state, cycles_amount, Cycles::zero())
+        self.consume_with_threshold(system_state, cycles_amount - from_reserved, Cycles::zero())?;
+
+        // Only touch the reserved balance once the main balance has been
+        // charged successfully, so that a failure leaves the state unchanged.
+        let removed = system_state.remove_reserved_cycles(from_reserved);
+        system_state.observe_consumed_cycles(removed);
+        Ok(())
+    }
+
+    /// Returns the number of cycles that need to be reserved when allocating
+    /// `allocated_bytes` of memory given the current saturation of the subnet
+    /// memory. The reserved cycles pay for storing the reserved portion of
+    /// the allocation for `max_storage_reservation_period`.
+    pub fn storage_reservation_cycles(
+        &self,
+        allocated_bytes: NumBytes,
+        subnet_memory_saturation: &ResourceSaturation,
+        subnet_size: usize,
+    ) -> Cycles {
+        let reserved_bytes = subnet_memory_saturation.reservation_factor(allocated_bytes.get());
+        self.memory_cost(
+            NumBytes::from(reserved_bytes),
+            self.config.max_storage_reservation_period,
+            subnet_size,
+        )
     }
 
     /// The cost of using `bytes` worth of memory.
@@ -562,11 +661,8 @@ impl CyclesAccountManager {
         //   - the fee to send the request (by size)
         //   - the fee for the largest possible response
         //   - the fee for executing the largest allowed response when it eventually arrives.
-        let fee = self.scale_cost(
-            self.config.xnet_call_fee
-                + self.config.xnet_byte_transmission_fee * request.payload_size_bytes().get(),
-            subnet_size,
-        ) + prepayment_for_response_transmission
+        let fee = self.xnet_call_request_fee(request.payload_size_bytes(), subnet_size)
+            + prepayment_for_response_transmission
             + prepayment_for_response_execution;
         self.withdraw_with_threshold(
             canister_id,
@@ -582,6 +678,24 @@ impl CyclesAccountManager {
         )
     }
 
+    /// Returns the fee for sending a request with the given payload size
+    /// (method name and argument), excluding the prepayments for its response.
+    fn xnet_call_request_fee(&self, payload_size: NumBytes, subnet_size: usize) -> Cycles {
+        self.scale_cost(
+            self.config.xnet_call_fee + self.config.xnet_byte_transmission_fee * payload_size.get(),
+            subnet_size,
+        )
+    }
+
+    /// Returns the total amount of cycles charged by `withdraw_request_cycles`
+    /// for a request with the given payload size (method name and argument),
+    /// including the prepayments for the largest possible response.
+    pub fn xnet_call_total_fee(&self, payload_size: NumBytes, subnet_size: usize) -> Cycles {
+        self.xnet_call_request_fee(payload_size, subnet_size)
+            + self.prepayment_for_response_transmission(subnet_size)
+            + self.prepayment_for_response_execution(subnet_size)
+    }
+
     /// Returns the amount of cycles required for executing the longest-running
     /// response callback.
     pub fn prepayment_for_response_execution(&self, subnet_size: usize) -> Cycles {
@@ -765,10 +879,14 @@ impl CyclesAccountManager {
     /// Charges a canister for its resource allocation and usage for the
     /// duration specified. If fees were successfully charged, then returns
     /// Ok(CanisterState) else returns Err(CanisterState).
+    ///
+    /// `snapshots_memory_usage` is the memory taken by the snapshots of the
+    /// canister. It is always charged on top of the memory allocation/usage.
     pub fn charge_canister_for_resource_allocation_and_usage(
         &self,
         log: &ReplicaLogger,
         canister: &mut CanisterState,
+        snapshots_memory_usage: NumBytes,
         duration_between_blocks: Duration,
         subnet_size: usize,
     ) -> Result<(), CanisterOutOfCyclesError> {
@@ -778,7 +896,7 @@ impl CyclesAccountManager {
             MemoryAllocation::Reserved(bytes) => bytes,
             // The canister uses best-effort memory allocation, so charge based on current usage.
             MemoryAllocation::BestEffort => canister.memory_usage(self.own_subnet_type),
-        };
+        } + snapshots_memory_usage;
         if let Err(err) = self.charge_for_memory(
             &mut canister.system_state,
             bytes_to_charge,
@@ -900,6 +1018,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reservation_factor() {
+        let gib = 1 << 30;
+        let saturation = ResourceSaturation::new(100 * gib, 200 * gib, 300 * gib);
+        // Allocations below the threshold reserve nothing.
+        assert_eq!(saturation.reservation_factor(100 * gib), 0);
+        // Allocating up to halfway to the capacity reserves a quarter of the
+        // 50 GiB above the threshold.
+        assert_eq!(
+            saturation.reservation_factor(150 * gib),
+            12_800 * gib / 1024
+        );
+
+        // Halfway to the capacity about half of the allocated units are reserved.
+        let saturation = ResourceSaturation::new(250 * gib, 200 * gib, 300 * gib);
+        assert_eq!(saturation.reservation_factor(2 * gib), gib + gib / 50);
+
+        // Above the capacity every allocated unit is reserved.
+        let saturation = ResourceSaturation::new(300 * gib, 200 * gib, 300 * gib);
+        assert_eq!(saturation.reservation_factor(gib), gib);
+
+        // Nothing is reserved if the threshold is not below the capacity.
+        let saturation = ResourceSaturation::new(300 * gib, 300 * gib, 300 * gib);
+        assert_eq!(saturation.reservation_factor(gib), 0);
+    }
+
     #[test]
     fn test_reference_subnet_size_is_not_zero() {
         // `reference_subnet_size` is used to scale cost according to a subnet size.
@@ -1,7 +1,7 @@
 use ic_base_types::NumSeconds;
 use ic_config::subnet_config::SubnetConfigs;
 use ic_constants::SMALL_APP_SUBNET_MAX_SIZE;
-use ic_cycles_account_manager::IngressInductionCost;
+use ic_cycles_account_manager::{IngressInductionCost, ResourceSaturation};
 use ic_ic00_types::{CanisterIdRecord, Payload, IC_00};
 use ic_interfaces::execution_environment::CanisterOutOfCyclesError;
 use ic_logger::replica_logger::no_op_logger;
@@ -12,7 +12,7 @@ use ic_test_utilities::{
     state::{new_canister_state, SystemStateBuilder},
     types::{
         ids::{canister_test_id, subnet_test_id, user_test_id},
-        messages::SignedIngressBuilder,
+        messages::{RequestBuilder, SignedIngressBuilder},
     },
 };
 use ic_test_utilities_logger::with_test_replica_logger;
@@ -70,6 +70,7 @@ fn test_can_charge_application_subnets() {
                         .charge_canister_for_resource_allocation_and_usage(
                             &log,
                             &mut canister,
+                            NumBytes::from(0),
                             duration,
                             subnet_size,
                         )
@@ -319,6 +320,91 @@ fn canister_charge_for_memory_until_zero_works() {
         .is_err());
 }
 
+#[test]
+fn charge_for_memory_uses_reserved_balance_first() {
+    let subnet_size = SMALL_APP_SUBNET_MAX_SIZE;
+    let mut system_state = SystemStateBuilder::new().build();
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let memory = NumBytes::from(1 << 30);
+    let duration = Duration::from_secs(1);
+    let fee = cycles_account_manager.memory_cost(memory, duration, subnet_size);
+
+    // Reserve enough cycles for one and a half charges.
+    system_state
+        .reserve_cycles(fee + Cycles::new(fee.get() / 2))
+        .unwrap();
+    let balance = system_state.balance();
+
+    // The first charge is paid entirely from the reserved balance.
+    cycles_account_manager
+        .charge_for_memory(&mut system_state, memory, duration, subnet_size)
+        .unwrap();
+    assert_eq!(system_state.balance(), balance);
+    assert_eq!(system_state.reserved_balance(), Cycles::new(fee.get() / 2));
+
+    // The second charge drains the reserved balance and pays the rest from
+    // the main balance.
+    cycles_account_manager
+        .charge_for_memory(&mut system_state, memory, duration, subnet_size)
+        .unwrap();
+    assert_eq!(system_state.reserved_balance(), Cycles::zero());
+    assert_eq!(
+        system_state.balance(),
+        balance - (fee - Cycles::new(fee.get() / 2))
+    );
+    assert_eq!(
+        system_state
+            .canister_metrics
+            .consumed_cycles_since_replica_started,
+        NominalCycles::from(fee + fee)
+    );
+}
+
+#[test]
+fn storage_reservation_cycles_grow_with_subnet_memory_usage() {
+    let subnet_size = SMALL_APP_SUBNET_MAX_SIZE;
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let allocated_bytes = NumBytes::from(1 << 30);
+    let threshold = 100 << 30;
+    let capacity = 200 << 30;
+
+    let below_threshold = ResourceSaturation::new(threshold / 2, threshold, capacity);
+    assert_eq!(
+        cycles_account_manager.storage_reservation_cycles(
+            allocated_bytes,
+            &below_threshold,
+            subnet_size
+        ),
+        Cycles::zero()
+    );
+
+    let mut previous = Cycles::zero();
+    for usage in [threshold, threshold + (10 << 30), threshold + (50 << 30)] {
+        let saturation = ResourceSaturation::new(usage, threshold, capacity);
+        let reservation = cycles_account_manager.storage_reservation_cycles(
+            allocated_bytes,
+            &saturation,
+            subnet_size,
+        );
+        assert!(reservation > previous);
+        previous = reservation;
+    }
+
+    // At full capacity every allocated byte is reserved for the whole period.
+    let full = ResourceSaturation::new(capacity, threshold, capacity);
+    let config = SubnetConfigs::default()
+        .own_subnet_config(SubnetType::Application)
+        .cycles_account_manager_config;
+    assert_eq!(
+        cycles_account_manager.storage_reservation_cycles(allocated_bytes, &full, subnet_size),
+        cycles_account_manager.memory_cost(
+            allocated_bytes,
+            config.max_storage_reservation_period,
+            subnet_size
+        )
+    );
+}
+
 #[test]
 fn ingress_induction_cost_valid_subnet_message() {
     let subnet_id = subnet_test_id(0);
@@ -351,6 +437,38 @@ fn ingress_induction_cost_valid_subnet_message() {
     }
 }
 
+#[test]
+fn charging_for_resources_includes_snapshots_memory_usage() {
+    with_test_replica_logger(|log| {
+        let subnet_size = SMALL_APP_SUBNET_MAX_SIZE;
+        let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+        let snapshots_memory_usage = NumBytes::from(1 << 30);
+        let duration = Duration::from_secs(1);
+
+        let mut canister = new_canister_state(
+            canister_test_id(1),
+            canister_test_id(11).get(),
+            INITIAL_CYCLES,
+            NumSeconds::from(0),
+        );
+        let memory = canister.memory_usage(SubnetType::Application) + snapshots_memory_usage;
+        let expected_fee = cycles_account_manager.memory_cost(memory, duration, subnet_size);
+        cycles_account_manager
+            .charge_canister_for_resource_allocation_and_usage(
+                &log,
+                &mut canister,
+                snapshots_memory_usage,
+                duration,
+                subnet_size,
+            )
+            .unwrap();
+        assert_eq!(
+            canister.system_state.balance(),
+            INITIAL_CYCLES - expected_fee
+        );
+    })
+}
+
 #[test]
 fn charging_removes_canisters_with_insufficient_balance() {
     with_test_replica_logger(|log| {
@@ -370,6 +488,7 @@ fn charging_removes_canisters_with_insufficient_balance() {
             .charge_canister_for_resource_allocation_and_usage(
                 &log,
                 &mut canister,
+                NumBytes::from(0),
                 Duration::from_secs(1),
                 subnet_size,
             )
@@ -388,6 +507,7 @@ fn charging_removes_canisters_with_insufficient_balance() {
             .charge_canister_for_resource_allocation_and_usage(
                 &log,
                 &mut canister,
+                NumBytes::from(0),
                 Duration::from_secs(1),
                 subnet_size,
             )
@@ -406,6 +526,7 @@ fn charging_removes_canisters_with_insufficient_balance() {
             .charge_canister_for_resource_allocation_and_usage(
                 &log,
                 &mut canister,
+                NumBytes::from(0),
                 Duration::from_secs(1),
                 subnet_size,
             )
@@ -694,3 +815,36 @@ fn consume_cycles_updates_consumed_cycles() {
         NominalCycles::from(1_000_000)
     );
 }
+
+#[test]
+fn xnet_call_total_fee_matches_withdraw_request_cycles() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new()
+        .with_subnet_type(SubnetType::Application)
+        .build();
+    let request = RequestBuilder::new()
+        .method_name("method")
+        .method_payload(vec![0; 1000])
+        .build();
+
+    let mut cycles_balance = INITIAL_CYCLES;
+    cycles_account_manager
+        .withdraw_request_cycles(
+            canister_test_id(1),
+            &mut cycles_balance,
+            NumSeconds::from(0),
+            MemoryAllocation::BestEffort,
+            NumBytes::from(0),
+            ComputeAllocation::default(),
+            &request,
+            cycles_account_manager.prepayment_for_response_execution(SMALL_APP_SUBNET_MAX_SIZE),
+            cycles_account_manager.prepayment_for_response_transmission(SMALL_APP_SUBNET_MAX_SIZE),
+            SMALL_APP_SUBNET_MAX_SIZE,
+        )
+        .unwrap();
+
+    assert_eq!(
+        INITIAL_CYCLES - cycles_balance,
+        cycles_account_manager
+            .xnet_call_total_fee(request.payload_size_bytes(), SMALL_APP_SUBNET_MAX_SIZE)
+    );
+}
@@ -47,6 +47,9 @@ use std::sync::{Arc, Mutex};
 use std::{thread::sleep, time::Duration};
 
 mod message;
+mod replay;
+
+pub use replay::ReplayOptions;
 
 // drun will panic if it takes more than this many batches
 // until a response for a message is received
@@ -59,6 +62,9 @@ pub struct DrunOptions {
     pub cfg: Config,
     pub extra_batches: u64,
     pub log_file: Option<PathBuf>,
+    /// If set, the messages file is a recording that is replayed against a
+    /// canister taken from a snapshot.
+    pub replay: Option<ReplayOptions>,
 }
 
 /// Deliver a single message to the Message Routing layer
@@ -101,6 +107,7 @@ fn get_registry(
     root_subnet_id: SubnetId,
     subnet_type: SubnetType,
     node_ids: &[NodeId],
+    routing_table: RoutingTable,
 ) -> Arc<RegistryClientImpl> {
     let registry_version = RegistryVersion::from(1);
     let data_provider = Arc::new(ProtoRegistryDataProvider::new());
@@ -116,8 +123,6 @@ fn get_registry(
             Some(root_subnet_id_proto),
         )
         .unwrap();
-    let mut routing_table = RoutingTable::new();
-    routing_table_insert_subnet(&mut routing_table, subnet_id).unwrap();
     let pb_routing_table = PbRoutingTable::from(routing_table);
     data_provider
         .add(
@@ -156,12 +161,16 @@ pub fn run_drun(uo: DrunOptions) -> Result<(), String> {
         cfg,
         extra_batches,
         log_file,
+        replay,
     } = uo;
     // Hardcoded magic values to create a ReplicaConfig that parses.
     let subnet_type = SubnetType::System;
     let subnet_config = SubnetConfigs::default().own_subnet_config(subnet_type);
     let subnet_id = SubnetId::from(PrincipalId::new_subnet_test_id(0));
     let root_subnet_id = SubnetId::from(PrincipalId::new_subnet_test_id(1));
+    // When replaying, all canisters but the replayed one are hosted on this
+    // (non-existent) subnet.
+    let remote_subnet_id = SubnetId::from(PrincipalId::new_subnet_test_id(2));
     let replica_config = ReplicaConfig {
         node_id: NodeId::from(PrincipalId::new_node_test_id(27)),
         subnet_id,
@@ -173,6 +182,17 @@ pub fn run_drun(uo: DrunOptions) -> Result<(), String> {
         None => slog::Logger::root(slog::Discard, slog::o!()),
     };
 
+    let routing_table = match &replay {
+        Some(replay) => {
+            replay::replay_routing_table(replay.canister_id, subnet_id, remote_subnet_id)?
+        }
+        None => {
+            let mut routing_table = RoutingTable::new();
+            routing_table_insert_subnet(&mut routing_table, subnet_id).unwrap();
+            routing_table
+        }
+    };
+
     let metrics_registry = MetricsRegistry::global();
     let registry = get_registry(
         &metrics_registry,
@@ -180,6 +200,7 @@ pub fn run_drun(uo: DrunOptions) -> Result<(), String> {
         root_subnet_id,
         subnet_type,
         &[replica_config.node_id],
+        routing_table,
     );
 
     let cycles_account_manager = Arc::new(CyclesAccountManager::new(
@@ -234,6 +255,17 @@ pub fn run_drun(uo: DrunOptions) -> Result<(), String> {
         MaliciousFlags::default(),
     );
 
+    if let Some(replay) = replay {
+        return replay::run_replay(
+            replay,
+            replay::recording_from_file(&msg_filename)?,
+            remote_subnet_id,
+            &state_manager,
+            &message_routing,
+            ingress_hist_reader.as_ref(),
+        );
+    }
+
     msg_stream.try_for_each(|parse_result| {
         parse_result.map(|msg| match msg {
             Message::Install(msg) => {
@@ -4,8 +4,9 @@ use ic_canister_sandbox_backend_lib::{
 };
 use ic_canister_sandbox_launcher::sandbox_launcher_main;
 use ic_config::{flag_status::FlagStatus, Config, ConfigSource};
-use ic_drun::{run_drun, DrunOptions};
-use std::path::PathBuf;
+use ic_drun::{run_drun, DrunOptions, ReplayOptions};
+use ic_types::CanisterId;
+use std::{path::PathBuf, str::FromStr};
 
 const DEFAULT_CONFIG_FILE: &str = "ic.json5";
 const DEFAULT_EXTRA_BATCHES: u64 = 0;
@@ -13,6 +14,9 @@ const ARG_CONF: &str = "config";
 const ARG_LOG_FILE: &str = "log-file";
 const ARG_MESSAGES: &str = "messages";
 const ARG_EXTRA_BATCHES: &str = "extra-batches";
+const ARG_PROFILE: &str = "profile";
+const ARG_SNAPSHOT: &str = "snapshot";
+const ARG_CANISTER: &str = "canister";
 
 fn main() -> Result<(), String> {
     // Check if `drun` is running in the canister sandbox mode where it waits
@@ -44,11 +48,19 @@ async fn drun_main() -> Result<(), String> {
         default_config.hypervisor.rate_limiting_of_debug_prints = FlagStatus::Disabled;
         default_config.hypervisor.rate_limiting_of_heap_delta = FlagStatus::Disabled;
         default_config.hypervisor.rate_limiting_of_instructions = FlagStatus::Disabled;
-        let cfg = Config::load_with_default(&source, default_config).unwrap_or_else(|err| {
+        let mut cfg = Config::load_with_default(&source, default_config).unwrap_or_else(|err| {
             eprintln!("Failed to load config:\n  {}", err);
             std::process::exit(1);
         });
 
+        if let Some(profile) = matches.value_of(ARG_PROFILE) {
+            // Instruction profiling is only supported by the in-process
+            // executor, which requires sandboxing and DTS to be disabled.
+            cfg.hypervisor.canister_sandboxing_flag = FlagStatus::Disabled;
+            cfg.hypervisor.deterministic_time_slicing = FlagStatus::Disabled;
+            cfg.hypervisor.instruction_profile_output = Some(PathBuf::from(profile));
+        }
+
         let log_file = matches.value_of(ARG_LOG_FILE).map(PathBuf::from);
 
         let extra_batches = matches
@@ -61,11 +73,23 @@ async fn drun_main() -> Result<(), String> {
             })
             .unwrap_or(DEFAULT_EXTRA_BATCHES);
 
+        let replay = matches.value_of(ARG_SNAPSHOT).map(|snapshot| {
+            let canister = matches.value_of(ARG_CANISTER).unwrap();
+            ReplayOptions {
+                snapshot: PathBuf::from(snapshot),
+                canister_id: CanisterId::from_str(canister).unwrap_or_else(|err| {
+                    eprintln!("Failed to parse ARG_CANISTER\n  {}", err);
+                    std::process::exit(1);
+                }),
+            }
+        });
+
         let uo = DrunOptions {
             msg_filename: matches.value_of(ARG_MESSAGES).unwrap().to_string(),
             cfg,
             extra_batches,
             log_file,
+            replay,
         };
         run_drun(uo)
     })
@@ -114,5 +138,35 @@ fn get_arg_matches() -> ArgMatches {
                 .help("Log file for the run (default: None).")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new(ARG_PROFILE)
+                .long(ARG_PROFILE)
+                .value_name("profile_file")
+                .help(
+                    "Write the number of instructions executed by each Wasm function to this \
+                     file in the folded stack format, which can be rendered as a flamegraph \
+                     (default: None).",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new(ARG_SNAPSHOT)
+                .long(ARG_SNAPSHOT)
+                .value_name("checkpoint_dir_or_archive")
+                .help(
+                    "Replay the recording given as messages file against the canister taken \
+                     from this checkpoint or canister archive (default: None).",
+                )
+                .takes_value(true)
+                .requires(ARG_CANISTER),
+        )
+        .arg(
+            Arg::new(ARG_CANISTER)
+                .long(ARG_CANISTER)
+                .value_name("canister_id")
+                .help("The canister to replay the recording against.")
+                .takes_value(true)
+                .requires(ARG_SNAPSHOT),
+        )
         .get_matches()
 }
@@ -46,13 +46,13 @@ impl fmt::Display for LineIteratorError {
 
 const LINE_ITERATOR_BUFFER_SIZE: usize = 16_777_216;
 
-struct LineIterator<R: Read> {
+pub(crate) struct LineIterator<R: Read> {
     inner: R,
     buffer: Vec<u8>,
 }
 
 impl<R: Read> LineIterator<R> {
-    fn new(inner: R) -> Self {
+    pub(crate) fn new(inner: R) -> Self {
         Self {
             inner,
             buffer: vec![],
@@ -192,7 +192,7 @@ fn parse_message(s: &str, nonce: u64) -> Result<Message, String> {
     }
 }
 
-fn parse_canister_id(canister_id: &str) -> Result<CanisterId, String> {
+pub(crate) fn parse_canister_id(canister_id: &str) -> Result<CanisterId, String> {
     use std::str::FromStr;
     match PrincipalId::from_str(canister_id) {
         Ok(id) => match CanisterId::new(id) {
@@ -263,7 +263,7 @@ fn parse_install(
     Ok(Message::Install(signed_ingress))
 }
 
-fn validate_method_name(method_name: &str) -> Result<String, String> {
+pub(crate) fn validate_method_name(method_name: &str) -> Result<String, String> {
     fn is_ident_start(c: char) -> bool {
         c.is_ascii() && (c.is_alphabetic() || c == '_')
     }
@@ -283,7 +283,7 @@ fn validate_method_name(method_name: &str) -> Result<String, String> {
     }
 }
 
-fn parse_octet_string(input_str: &str) -> Result<Vec<u8>, String> {
+pub(crate) fn parse_octet_string(input_str: &str) -> Result<Vec<u8>, String> {
     if input_str.starts_with('"') {
         parse_quoted(input_str)
     } else {
@@ -354,7 +354,7 @@ fn parse_escape(chars: &mut Chars<'_>, radix: Radix) -> Result<u8, String> {
     }
 }
 
-fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+pub(crate) fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
     if let Some(s) = s.strip_prefix("0x") {
         decode(s).map_err(|e| e.to_string())
     } else {
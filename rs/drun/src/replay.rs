@@ -0,0 +1,805 @@
+//! Deterministic replay of the recorded traffic of a single canister.
+//!
+//! The canister is loaded from a checkpoint or from a canister archive created
+//! by `state_tool export-canister`, and the recorded ingress messages and inter-canister requests
+//! are executed in order, each in batches carrying its recorded time. Calls
+//! that the canister makes to other canisters are answered with the recorded
+//! downstream responses. The responses and, where recorded, the hash of the
+//! canister's subtree of the certified state tree are compared against the
+//! recording and any divergence is reported.
+
+use super::{build_batch, CanisterId, WAIT_PER_BATCH};
+use crate::message::{
+    parse_canister_id, parse_hex, parse_octet_string, validate_method_name, LineIterator,
+};
+use hex::encode;
+use ic_constants::{MAX_INGRESS_TTL, PERMITTED_DRIFT};
+use ic_crypto_tree_hash::{Digest, HashTree};
+use ic_error_types::RejectCode;
+use ic_interfaces::{execution_environment::IngressHistoryReader, messaging::MessageRouting};
+use ic_interfaces_state_manager::{CertificationScope, StateManager, StateReader};
+use ic_registry_routing_table::{canister_id_into_u64, CanisterIdRange, RoutingTable};
+use ic_replicated_state::ReplicatedState;
+use ic_state_layout::{CanisterLayout, CompleteCheckpointLayout, ReadOnly};
+use ic_state_manager::{
+    canister_archive::import_canister_archive, checkpoint::load_canister_state,
+    tree_hash::hash_state, StateManagerImpl,
+};
+use ic_test_utilities::types::messages::{RequestBuilder, SignedIngressBuilder};
+use ic_types::{
+    batch::Batch,
+    ingress::{IngressState, IngressStatus, WasmResult},
+    messages::{
+        CallbackId, MessageId, Payload, RejectContext, RequestOrResponse, Response, SignedIngress,
+    },
+    xnet::StreamIndex,
+    Cycles, Height, NumBytes, PrincipalId, Randomness, SubnetId, Time, UserId,
+};
+use std::{
+    collections::VecDeque, fmt, fs::File, io::Read, path::PathBuf, str::FromStr, sync::Arc,
+    thread::sleep,
+};
+
+// Replayed messages whose execution depends on calls to other canisters for
+// which no downstream response was recorded never complete. Give up on a
+// message after this many batches.
+const MAX_BATCHES_UNTIL_REPLAYED_RESPONSE: u64 = 1000;
+
+/// Where to take the replayed canister from.
+pub struct ReplayOptions {
+    /// Path to a checkpoint containing the canister or to a canister archive.
+    pub snapshot: PathBuf,
+    pub canister_id: CanisterId,
+}
+
+/// A call to the replayed canister.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RecordedCall {
+    Ingress {
+        sender: UserId,
+        method_name: String,
+        method_payload: Vec<u8>,
+    },
+    Request {
+        sender: CanisterId,
+        payment: Cycles,
+        method_name: String,
+        method_payload: Vec<u8>,
+    },
+}
+
+/// The response to a call, as recorded or as observed during the replay.
+#[derive(Debug, PartialEq)]
+pub(crate) enum RecordedResponse {
+    Reply(Vec<u8>),
+    Reject(RejectCode, String),
+}
+
+impl fmt::Display for RecordedResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordedResponse::Reply(data) => write!(f, "Reply: 0x{}", encode(data)),
+            RecordedResponse::Reject(code, message) => {
+                write!(f, "Reject: {} {}", *code as u64, message)
+            }
+        }
+    }
+}
+
+/// A call from the recording together with what was observed on the
+/// original subnet.
+#[derive(Debug, PartialEq)]
+pub(crate) struct RecordedMessage {
+    pub time: Time,
+    pub call: RecordedCall,
+    /// The responses to the calls that the canister made to other canisters
+    /// while executing `call`, in the order in which the calls were made.
+    pub downstream_responses: Vec<RecordedResponse>,
+    pub expected_response: Option<RecordedResponse>,
+    pub expected_hash: Option<Vec<u8>>,
+}
+
+/// Returns a routing table that assigns `canister_id` to `own_subnet_id` and
+/// all other canister IDs to `remote_subnet_id`, so that the responses to the
+/// replayed inter-canister requests end up in the stream to `remote_subnet_id`.
+pub(crate) fn replay_routing_table(
+    canister_id: CanisterId,
+    own_subnet_id: SubnetId,
+    remote_subnet_id: SubnetId,
+) -> Result<RoutingTable, String> {
+    let id = canister_id_into_u64(canister_id);
+    let range = |start: u64, end: u64| CanisterIdRange {
+        start: CanisterId::from_u64(start),
+        end: CanisterId::from_u64(end),
+    };
+
+    let mut routing_table = RoutingTable::new();
+    routing_table
+        .insert(range(id, id), own_subnet_id)
+        .map_err(|e| format!("{:?}", e))?;
+    if id > 0 {
+        routing_table
+            .insert(range(0, id - 1), remote_subnet_id)
+            .map_err(|e| format!("{:?}", e))?;
+    }
+    if id < u64::MAX {
+        routing_table
+            .insert(range(id + 1, u64::MAX), remote_subnet_id)
+            .map_err(|e| format!("{:?}", e))?;
+    }
+    Ok(routing_table)
+}
+
+pub(crate) fn recording_from_file(filename: &str) -> Result<Vec<RecordedMessage>, String> {
+    let f = File::open(filename).map_err(|e| e.to_string())?;
+    parse_recording(f)
+}
+
+/// Parses a recording. Every call is given on a line of its own, optionally
+/// followed by lines with the responses to the calls the canister made while
+/// executing it, its expected response and the expected hash of the canister
+/// after it was executed:
+///
+/// ```text
+/// ingress <time> <sender> <method_name> <payload>
+/// request <time> <sender> <cycles> <method_name> <payload>
+/// downstream-reply <payload>
+/// downstream-reject <reject_code> <message>
+/// reply <payload>
+/// reject <reject_code> <message>
+/// hash <canister_hash>
+/// ```
+fn parse_recording<R: Read>(reader: R) -> Result<Vec<RecordedMessage>, String> {
+    let mut messages: Vec<RecordedMessage> = Vec::new();
+    for (i, line) in LineIterator::new(reader).enumerate() {
+        let line = line.map_err(|e| format!("Error while reading line {}: {}", i, e))?;
+        // let's skip commented ('#') and empty lines
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(message) = parse_recording_line(&line, messages.last_mut())
+            .map_err(|e| format!("Line {}: {}", i + 1, e))?
+        {
+            messages.push(message);
+        }
+    }
+    Ok(messages)
+}
+
+/// Parses a single line of a recording. Returns the new call if the line
+/// starts one; otherwise the line is added to `last`.
+fn parse_recording_line(
+    s: &str,
+    last: Option<&mut RecordedMessage>,
+) -> Result<Option<RecordedMessage>, String> {
+    let s = s.trim_end();
+    let tokens: Vec<&str> = s.splitn(2, char::is_whitespace).collect();
+
+    let last = match tokens[..] {
+        ["ingress", rest] => {
+            let tokens: Vec<&str> = rest.splitn(4, char::is_whitespace).collect();
+            return match tokens[..] {
+                [time, sender, method_name, payload] => Ok(Some(RecordedMessage {
+                    time: parse_time(time)?,
+                    call: RecordedCall::Ingress {
+                        sender: UserId::from(parse_principal_id(sender)?),
+                        method_name: validate_method_name(method_name)?,
+                        method_payload: parse_octet_string(payload)?,
+                    },
+                    downstream_responses: vec![],
+                    expected_response: None,
+                    expected_hash: None,
+                })),
+                _ => Err(format!("Malformed ingress message: {}", s)),
+            };
+        }
+        ["request", rest] => {
+            let tokens: Vec<&str> = rest.splitn(5, char::is_whitespace).collect();
+            return match tokens[..] {
+                [time, sender, cycles, method_name, payload] => Ok(Some(RecordedMessage {
+                    time: parse_time(time)?,
+                    call: RecordedCall::Request {
+                        sender: parse_canister_id(sender)?,
+                        payment: Cycles::new(
+                            cycles
+                                .parse()
+                                .map_err(|e| format!("Invalid cycles {}: {}", cycles, e))?,
+                        ),
+                        method_name: validate_method_name(method_name)?,
+                        method_payload: parse_octet_string(payload)?,
+                    },
+                    downstream_responses: vec![],
+                    expected_response: None,
+                    expected_hash: None,
+                })),
+                _ => Err(format!("Malformed inter-canister request: {}", s)),
+            };
+        }
+        _ => last.ok_or_else(|| format!("{} does not follow a call", s))?,
+    };
+
+    match tokens[..] {
+        ["downstream-reply", payload] => last
+            .downstream_responses
+            .push(RecordedResponse::Reply(parse_octet_string(payload)?)),
+        ["downstream-reply"] => last
+            .downstream_responses
+            .push(RecordedResponse::Reply(vec![])),
+        ["downstream-reject", rest] => last.downstream_responses.push(parse_reject(rest)?),
+        ["reply", payload] => {
+            set_once(
+                &mut last.expected_response,
+                RecordedResponse::Reply(parse_octet_string(payload)?),
+                "response",
+            )?;
+        }
+        ["reply"] => set_once(
+            &mut last.expected_response,
+            RecordedResponse::Reply(vec![]),
+            "response",
+        )?,
+        ["reject", rest] => set_once(&mut last.expected_response, parse_reject(rest)?, "response")?,
+        ["hash", hash] => set_once(&mut last.expected_hash, parse_hex(hash)?, "hash")?,
+        _ => {
+            return Err(format!(
+                "Failed to parse line {}, don't have a pattern to match this with",
+                s
+            ))
+        }
+    }
+    Ok(None)
+}
+
+/// Parses the `<reject_code> <message>` part of a reject line.
+fn parse_reject(s: &str) -> Result<RecordedResponse, String> {
+    let tokens: Vec<&str> = s.splitn(2, char::is_whitespace).collect();
+    let (code, message) = match tokens[..] {
+        [code] => (code, ""),
+        [code, message] => (code, message),
+        _ => unreachable!("splitn() returns at least one token"),
+    };
+    let code = code
+        .parse::<u64>()
+        .map_err(|e| e.to_string())
+        .and_then(|code| RejectCode::try_from(code).map_err(|e| format!("{:?}", e)))
+        .map_err(|e| format!("Invalid reject code {}: {}", code, e))?;
+    Ok(RecordedResponse::Reject(code, message.to_string()))
+}
+
+fn set_once<T>(field: &mut Option<T>, value: T, what: &str) -> Result<(), String> {
+    if field.is_some() {
+        return Err(format!("Call already has an expected {}.", what));
+    }
+    *field = Some(value);
+    Ok(())
+}
+
+fn parse_time(time: &str) -> Result<Time, String> {
+    time.parse()
+        .map(Time::from_nanos_since_unix_epoch)
+        .map_err(|e| format!("Invalid time {}: {}", time, e))
+}
+
+fn parse_principal_id(principal_id: &str) -> Result<PrincipalId, String> {
+    PrincipalId::from_str(principal_id).map_err(|err| {
+        format!(
+            "Failed to convert {} to principal id with {}",
+            principal_id, err
+        )
+    })
+}
+
+/// Loads the canister from the snapshot into the state of `state_manager` and
+/// replays `recording` against it. Returns an error if the replay diverged
+/// from the recording.
+pub(crate) fn run_replay(
+    options: ReplayOptions,
+    recording: Vec<RecordedMessage>,
+    remote_subnet_id: SubnetId,
+    state_manager: &StateManagerImpl,
+    message_routing: &dyn MessageRouting,
+    ingress_history: &dyn IngressHistoryReader,
+) -> Result<(), String> {
+    let ReplayOptions {
+        snapshot,
+        canister_id,
+    } = options;
+
+    let canister_layout = if snapshot.is_file() {
+        let canister_dir = state_manager
+            .state_layout()
+            .tmp()
+            .join(format!("replay_{}", canister_id));
+        let info = import_canister_archive(&snapshot, &canister_dir)
+            .map_err(|e| format!("Failed to unpack canister archive: {}", e))?;
+        if info.canister_id != canister_id {
+            return Err(format!(
+                "Canister archive {} contains canister {}, expected {}",
+                snapshot.display(),
+                info.canister_id,
+                canister_id
+            ));
+        }
+        CanisterLayout::<ReadOnly>::new(canister_dir)
+            .map_err(|e| format!("Failed to open unpacked canister archive: {}", e))?
+    } else {
+        let checkpoint_layout =
+            CompleteCheckpointLayout::new_untracked(snapshot.clone(), Height::new(0))
+                .map_err(|e| format!("Failed to open snapshot {}: {}", snapshot.display(), e))?;
+        checkpoint_layout
+            .canister(&canister_id)
+            .map_err(|e| format!("Canister {} not found in snapshot: {}", canister_id, e))?
+    };
+    let (canister_state, _) = load_canister_state(
+        &canister_layout,
+        &canister_id,
+        Height::new(0),
+        state_manager.get_fd_factory(),
+    )
+    .map_err(|e| format!("Failed to load canister {}: {}", canister_id, e))?;
+    update_state(state_manager, message_routing, |state| {
+        state.put_canister_state(canister_state)
+    });
+
+    let mut divergences = 0;
+    let mut downstream = DownstreamResponder {
+        state_manager,
+        message_routing,
+        canister_id,
+        remote_subnet_id,
+        next_stream_index: StreamIndex::new(0),
+        responses: VecDeque::new(),
+        unrecorded_calls: vec![],
+    };
+    for (i, message) in recording.into_iter().enumerate() {
+        let callback_id = CallbackId::from(i as u64);
+        downstream.responses = message.downstream_responses.into();
+        downstream.unrecorded_calls.clear();
+        let (kind, response) = match message.call {
+            RecordedCall::Ingress {
+                sender,
+                method_name,
+                method_payload,
+            } => {
+                let msg = SignedIngressBuilder::new()
+                    .sender(sender)
+                    .canister_id(canister_id)
+                    .method_name(method_name)
+                    .method_payload(method_payload)
+                    .nonce(i as u64)
+                    .expiry_time(message.time + MAX_INGRESS_TTL - PERMITTED_DRIFT)
+                    .build();
+                let message_id = msg.id();
+                let response = execute_until(message_routing, vec![msg], message.time, || {
+                    downstream.answer_calls();
+                    ingress_response(&message_id, ingress_history)
+                });
+                ("ingress", response)
+            }
+            RecordedCall::Request {
+                sender,
+                payment,
+                method_name,
+                method_payload,
+            } => {
+                let request = RequestBuilder::new()
+                    .sender(sender)
+                    .receiver(canister_id)
+                    .sender_reply_callback(callback_id)
+                    .payment(payment)
+                    .method_name(method_name)
+                    .method_payload(method_payload)
+                    .build();
+                update_state(state_manager, message_routing, |state| {
+                    let mut subnet_available_memory = i64::MAX;
+                    state
+                        .push_input(
+                            RequestOrResponse::Request(Arc::new(request)),
+                            NumBytes::new(u64::MAX),
+                            &mut subnet_available_memory,
+                        )
+                        .unwrap_or_else(|(err, _)| {
+                            panic!("Failed to enqueue request {}: {}", i, err)
+                        });
+                });
+                let response = execute_until(message_routing, vec![], message.time, || {
+                    downstream.answer_calls();
+                    request_response(
+                        &state_manager.get_latest_state().take(),
+                        remote_subnet_id,
+                        sender,
+                        callback_id,
+                    )
+                });
+                ("request", response)
+            }
+        };
+
+        match &response {
+            Some(response) => println!("replay({}) {}: {}", i, kind, response),
+            None => println!("replay({}) {}: No response", i, kind),
+        }
+        for (receiver, method_name) in downstream.unrecorded_calls.drain(..) {
+            divergences += 1;
+            println!(
+                "divergence({}): no response recorded for the call to {}.{}",
+                i, receiver, method_name
+            );
+        }
+        if !downstream.responses.is_empty() {
+            divergences += 1;
+            println!(
+                "divergence({}): {} recorded downstream response(s) were not used",
+                i,
+                downstream.responses.len()
+            );
+        }
+        if let Some(expected) = message.expected_response {
+            if response.as_ref() != Some(&expected) {
+                divergences += 1;
+                match response {
+                    Some(response) => {
+                        println!("divergence({}): expected {}, got {}", i, expected, response)
+                    }
+                    None => println!("divergence({}): expected {}, got no response", i, expected),
+                }
+            }
+        }
+        if let Some(expected) = message.expected_hash {
+            let hash = canister_hash(&state_manager.get_latest_state().take(), canister_id)
+                .ok_or_else(|| format!("Canister {} is missing from the state", canister_id))?;
+            if hash.0[..] != expected[..] {
+                divergences += 1;
+                println!(
+                    "divergence({}): expected canister hash 0x{}, got 0x{}",
+                    i,
+                    encode(expected),
+                    encode(hash.0)
+                );
+            }
+        }
+    }
+
+    if divergences > 0 {
+        return Err(format!(
+            "The replay diverged from the recording {} time(s).",
+            divergences
+        ));
+    }
+    Ok(())
+}
+
+/// Answers the calls that the replayed canister makes to other canisters with
+/// the recorded downstream responses.
+struct DownstreamResponder<'a> {
+    state_manager: &'a StateManagerImpl,
+    message_routing: &'a dyn MessageRouting,
+    canister_id: CanisterId,
+    remote_subnet_id: SubnetId,
+    /// Index of the first message in the stream to the remote subnet that was
+    /// not looked at yet.
+    next_stream_index: StreamIndex,
+    /// The recorded responses not used yet for the currently replayed call.
+    responses: VecDeque<RecordedResponse>,
+    /// Receivers and method names of the calls for which no response was
+    /// recorded.
+    unrecorded_calls: Vec<(CanisterId, String)>,
+}
+
+impl DownstreamResponder<'_> {
+    /// Answers the calls that the replayed canister made since the last
+    /// invocation with the next recorded responses, in order. The calls are
+    /// picked up from the stream to the remote subnet.
+    ///
+    /// The cycles attached to a call are refunded in full, as the recording
+    /// does not contain the refunds.
+    fn answer_calls(&mut self) {
+        let state = self.state_manager.get_latest_state().take();
+        let stream = match state.metadata.streams().get(&self.remote_subnet_id) {
+            Some(stream) => stream,
+            None => return,
+        };
+        let mut responses = vec![];
+        for (index, msg) in stream.messages().iter() {
+            let request = match msg {
+                RequestOrResponse::Request(request)
+                    if index >= self.next_stream_index && request.sender == self.canister_id =>
+                {
+                    request
+                }
+                _ => continue,
+            };
+            match self.responses.pop_front() {
+                Some(recorded) => responses.push(Response {
+                    originator: request.sender,
+                    respondent: request.receiver,
+                    originator_reply_callback: request.sender_reply_callback,
+                    refund: request.payment,
+                    response_payload: match recorded {
+                        RecordedResponse::Reply(data) => Payload::Data(data),
+                        RecordedResponse::Reject(code, message) => {
+                            Payload::Reject(RejectContext::new(code, message))
+                        }
+                    },
+                    deadline: request.deadline,
+                }),
+                None => self
+                    .unrecorded_calls
+                    .push((request.receiver, request.method_name.clone())),
+            }
+        }
+        self.next_stream_index = stream.messages_end();
+        if responses.is_empty() {
+            return;
+        }
+
+        update_state(self.state_manager, self.message_routing, |state| {
+            for response in responses {
+                let mut subnet_available_memory = i64::MAX;
+                state
+                    .push_input(
+                        RequestOrResponse::Response(Arc::new(response)),
+                        NumBytes::new(u64::MAX),
+                        &mut subnet_available_memory,
+                    )
+                    .unwrap_or_else(|(err, _)| {
+                        panic!("Failed to enqueue downstream response: {}", err)
+                    });
+            }
+        });
+    }
+}
+
+/// Waits until message routing has finished processing all delivered batches,
+/// applies `f` to the tip and commits the result as a new state.
+///
+/// The state is committed without a checkpoint: the replayed canister's memory
+/// is backed by the files of the snapshot, which the state manager does not
+/// know how to include in its own checkpoints.
+fn update_state(
+    state_manager: &StateManagerImpl,
+    message_routing: &dyn MessageRouting,
+    f: impl FnOnce(&mut ReplicatedState),
+) {
+    while message_routing.expected_batch_height() != state_manager.latest_state_height().increment()
+    {
+        sleep(WAIT_PER_BATCH);
+    }
+    let (height, mut state) = state_manager.take_tip();
+    f(&mut state);
+    state_manager.commit_and_certify(state, height.increment(), CertificationScope::Metadata);
+}
+
+/// Keeps delivering batches with the given `time` (the first one containing
+/// `msgs`) until `poll` returns a result or
+/// `MAX_BATCHES_UNTIL_REPLAYED_RESPONSE` batches were executed.
+fn execute_until<T>(
+    message_routing: &dyn MessageRouting,
+    mut msgs: Vec<SignedIngress>,
+    time: Time,
+    mut poll: impl FnMut() -> Option<T>,
+) -> Option<T> {
+    for _ in 0..MAX_BATCHES_UNTIL_REPLAYED_RESPONSE {
+        // The batch is rebuilt every time, as `poll` may commit states of its
+        // own and thus advance the expected batch height.
+        let batch = build_replay_batch(message_routing, msgs.clone(), time);
+        if message_routing.deliver_batch(batch).is_ok() {
+            msgs.clear();
+        }
+        sleep(WAIT_PER_BATCH);
+
+        if let Some(result) = poll() {
+            return Some(result);
+        }
+    }
+    None
+}
+
+/// Builds a batch with the given `time` and a randomness that only depends on
+/// the batch number. No batch requires a checkpoint, see `update_state()`.
+fn build_replay_batch(
+    message_routing: &dyn MessageRouting,
+    msgs: Vec<SignedIngress>,
+    time: Time,
+) -> Batch {
+    let batch = build_batch(message_routing, msgs);
+    let mut seed = [0; 32];
+    seed[..8].copy_from_slice(&batch.batch_number.get().to_le_bytes());
+    Batch {
+        requires_full_state_hash: false,
+        randomness: Randomness::from(seed),
+        time,
+        ..batch
+    }
+}
+
+fn ingress_response(
+    message_id: &MessageId,
+    ingress_history: &dyn IngressHistoryReader,
+) -> Option<RecordedResponse> {
+    match (ingress_history.get_latest_status())(message_id) {
+        IngressStatus::Known { state, .. } => match state {
+            IngressState::Completed(WasmResult::Reply(data)) => Some(RecordedResponse::Reply(data)),
+            IngressState::Completed(WasmResult::Reject(message)) => Some(RecordedResponse::Reject(
+                RejectCode::CanisterReject,
+                message,
+            )),
+            IngressState::Failed(error) => Some(RecordedResponse::Reject(
+                error.reject_code(),
+                error.description().to_string(),
+            )),
+            IngressState::Done | IngressState::Received | IngressState::Processing => None,
+        },
+        IngressStatus::Unknown => None,
+    }
+}
+
+/// Looks for the response to the replayed request with the given callback ID
+/// in the stream to the remote subnet.
+fn request_response(
+    state: &ReplicatedState,
+    remote_subnet_id: SubnetId,
+    sender: CanisterId,
+    callback_id: CallbackId,
+) -> Option<RecordedResponse> {
+    let stream = state.metadata.streams().get(&remote_subnet_id)?;
+    stream.messages().iter().find_map(|(_, msg)| match msg {
+        RequestOrResponse::Response(response)
+            if response.originator == sender
+                && response.originator_reply_callback == callback_id =>
+        {
+            Some(match &response.response_payload {
+                Payload::Data(data) => RecordedResponse::Reply(data.clone()),
+                Payload::Reject(context) => {
+                    RecordedResponse::Reject(context.code(), context.message())
+                }
+            })
+        }
+        _ => None,
+    })
+}
+
+/// Returns the digest of the `/canister/<canister_id>` subtree of the certified
+/// state tree.
+fn canister_hash(state: &ReplicatedState, canister_id: CanisterId) -> Option<Digest> {
+    let tree = hash_state(state);
+    let canisters = find_label(&tree, b"canister")?;
+    find_label(canisters, canister_id.get_ref().as_slice()).map(|tree| tree.digest().clone())
+}
+
+/// Returns the child of the given (sub)tree that has the given label.
+fn find_label<'a>(tree: &'a HashTree, label: &[u8]) -> Option<&'a HashTree> {
+    match tree {
+        HashTree::Leaf { .. } => None,
+        HashTree::Node {
+            label: node_label,
+            hash_tree,
+            ..
+        } => {
+            if node_label.as_bytes() == label {
+                Some(hash_tree)
+            } else {
+                None
+            }
+        }
+        HashTree::Fork {
+            left_tree,
+            right_tree,
+            ..
+        } => find_label(left_tree, label).or_else(|| find_label(right_tree, label)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::types::ids::{canister_test_id, subnet_test_id};
+    use std::io::Cursor;
+
+    const APP_CANISTER_URL: &str = "ryjl3-tyaaa-aaaaa-aaaba-cai";
+    const APP_CANISTER_ID: u64 = 2;
+
+    #[test]
+    fn test_parse_recording() {
+        let recording = format!(
+            r#"# a comment
+ingress 1000 {0} write "payload"
+reply 0x0102
+hash 0x0a0b
+request 2000 {0} 500 read 0x03
+downstream-reply 0x04
+downstream-reject 3 unreachable
+reject 4 no thanks
+"#,
+            APP_CANISTER_URL
+        );
+        let messages = parse_recording(Cursor::new(recording)).unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                RecordedMessage {
+                    time: Time::from_nanos_since_unix_epoch(1000),
+                    call: RecordedCall::Ingress {
+                        sender: UserId::from(canister_test_id(APP_CANISTER_ID).get()),
+                        method_name: "write".to_string(),
+                        method_payload: b"payload".to_vec(),
+                    },
+                    downstream_responses: vec![],
+                    expected_response: Some(RecordedResponse::Reply(vec![1, 2])),
+                    expected_hash: Some(vec![10, 11]),
+                },
+                RecordedMessage {
+                    time: Time::from_nanos_since_unix_epoch(2000),
+                    call: RecordedCall::Request {
+                        sender: canister_test_id(APP_CANISTER_ID),
+                        payment: Cycles::new(500),
+                        method_name: "read".to_string(),
+                        method_payload: vec![3],
+                    },
+                    downstream_responses: vec![
+                        RecordedResponse::Reply(vec![4]),
+                        RecordedResponse::Reject(
+                            RejectCode::DestinationInvalid,
+                            "unreachable".to_string()
+                        ),
+                    ],
+                    expected_response: Some(RecordedResponse::Reject(
+                        RejectCode::CanisterReject,
+                        "no thanks".to_string()
+                    )),
+                    expected_hash: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_recording_invalid_lines_fail() {
+        // An expectation without a call.
+        assert!(parse_recording(Cursor::new("reply 0x01")).is_err());
+
+        // Two responses for the same call.
+        let recording = format!(
+            "ingress 1000 {} write 0x01\nreply 0x01\nreply 0x02",
+            APP_CANISTER_URL
+        );
+        assert!(parse_recording(Cursor::new(recording)).is_err());
+
+        // An unknown reject code.
+        let recording = format!(
+            "ingress 1000 {} write 0x01\nreject 42 oops",
+            APP_CANISTER_URL
+        );
+        assert!(parse_recording(Cursor::new(recording)).is_err());
+
+        // A downstream response without a call.
+        assert!(parse_recording(Cursor::new("downstream-reply 0x01")).is_err());
+
+        // A request without cycles.
+        let recording = format!("request 1000 {} write 0x01", APP_CANISTER_URL);
+        assert!(parse_recording(Cursor::new(recording)).is_err());
+    }
+
+    #[test]
+    fn test_replay_routing_table() {
+        let own_subnet_id = subnet_test_id(0);
+        let remote_subnet_id = subnet_test_id(1);
+        let routing_table = replay_routing_table(
+            canister_test_id(APP_CANISTER_ID),
+            own_subnet_id,
+            remote_subnet_id,
+        )
+        .unwrap();
+
+        assert_eq!(
+            routing_table.route(canister_test_id(APP_CANISTER_ID).get()),
+            Some(own_subnet_id)
+        );
+        for id in [0, 1, 3, u64::MAX] {
+            assert_eq!(
+                routing_table.route(canister_test_id(id).get()),
+                Some(remote_subnet_id)
+            );
+        }
+    }
+}
@@ -1,4 +1,5 @@
 mod compilation_cache;
+pub mod profiling;
 mod serialized_module;
 mod signal_handler;
 pub mod wasm_executor;
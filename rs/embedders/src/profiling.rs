@@ -0,0 +1,207 @@
+//! Per-function instruction profiling of canister executions.
+//!
+//! When profiling is enabled (see
+//! [`ic_config::embedders::Config::instruction_profile_output`]), the
+//! instrumentation wraps the body of every function of the canister module in
+//! calls to the injected `profile_enter` and `profile_exit` functions. On every
+//! such call the embedder reads the instruction counter and attributes the
+//! instructions executed since the previous call to the current call stack.
+//!
+//! The collected profile is written in the folded stack format (one
+//! `canister_id;caller;callee <instructions>` line per call stack), which can
+//! be rendered directly by `flamegraph.pl` or `inferno-flamegraph`. Function
+//! names are taken from the `name` custom section of the canister module when
+//! it is present.
+//!
+//! Profiling is only supported by the in-process Wasm executor, which is never
+//! used in production replicated execution.
+
+use crate::wasm_utils::decoding::decode_wasm;
+use ic_types::CanisterId;
+use ic_wasm_types::{CanisterModule, WasmHash};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use wasmparser::{BinaryReader, Parser, Payload};
+
+/// The name of the custom section that holds the debug names of a module.
+const NAME_SECTION: &str = "name";
+/// The id of the subsection of the `name` section that names functions.
+const FUNCTION_NAMES_SUBSECTION_ID: u8 = 1;
+
+/// The call stacks observed during a single execution and the number of
+/// instructions executed by the innermost function of each of them.
+#[derive(Debug, Default)]
+pub struct ExecutionProfile {
+    stack: Vec<u32>,
+    last_instruction_counter: Option<i64>,
+    self_instructions: HashMap<Vec<u32>, u64>,
+}
+
+impl ExecutionProfile {
+    /// Records that the function with the given index was entered.
+    pub(crate) fn enter(&mut self, func_index: u32, instruction_counter: i64) {
+        self.charge(instruction_counter);
+        self.stack.push(func_index);
+    }
+
+    /// Records that the function with the given index returned.
+    pub(crate) fn exit(&mut self, func_index: u32, instruction_counter: i64) {
+        self.charge(instruction_counter);
+        debug_assert_eq!(self.stack.last(), Some(&func_index));
+        self.stack.pop();
+    }
+
+    /// Attributes the instructions executed since the last recorded call to
+    /// the functions that were still running when the execution ended, e.g.
+    /// because it trapped.
+    pub(crate) fn finish(&mut self, instruction_counter: i64) {
+        self.charge(instruction_counter);
+        self.stack.clear();
+        self.last_instruction_counter = None;
+    }
+
+    /// Returns the number of instructions executed by the innermost function
+    /// of each call stack, where the call stack is given as function indices
+    /// from the outermost to the innermost function.
+    pub fn self_instructions(&self) -> &HashMap<Vec<u32>, u64> {
+        &self.self_instructions
+    }
+
+    /// Returns the profile as folded stacks with `root` as the outermost frame
+    /// and function indices resolved to their names.
+    pub fn folded_stacks(&self, root: &str, names: &FunctionNames) -> BTreeMap<String, u64> {
+        let mut result = BTreeMap::new();
+        for (stack, instructions) in &self.self_instructions {
+            let mut folded = root.to_string();
+            for func_index in stack {
+                folded.push(';');
+                folded.push_str(&names.get(*func_index));
+            }
+            *result.entry(folded).or_default() += instructions;
+        }
+        result
+    }
+
+    fn charge(&mut self, instruction_counter: i64) {
+        // The instruction counter counts down.
+        if let Some(last) = self.last_instruction_counter {
+            let executed = last.saturating_sub(instruction_counter).max(0) as u64;
+            if executed > 0 && !self.stack.is_empty() {
+                *self
+                    .self_instructions
+                    .entry(self.stack.clone())
+                    .or_default() += executed;
+            }
+        }
+        self.last_instruction_counter = Some(instruction_counter);
+    }
+}
+
+/// The names of the functions of a Wasm module as given by its `name` custom
+/// section.
+#[derive(Debug, Default)]
+pub struct FunctionNames(BTreeMap<u32, String>);
+
+impl FunctionNames {
+    /// Reads the function names of the given (uncompressed) Wasm module.
+    /// Functions are left unnamed if the module does not have a valid `name`
+    /// section.
+    pub fn from_wasm(wasm: &[u8]) -> Self {
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload {
+                Ok(Payload::CustomSection(reader)) if reader.name() == NAME_SECTION => {
+                    return Self(read_function_names(reader.data()).unwrap_or_default());
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        Self::default()
+    }
+
+    /// Returns the name of the given function with the characters that have a
+    /// special meaning in folded stacks replaced.
+    pub fn get(&self, func_index: u32) -> String {
+        match self.0.get(&func_index) {
+            Some(name) => name.replace(|c: char| c == ';' || c.is_whitespace(), "_"),
+            None => format!("func[{}]", func_index),
+        }
+    }
+}
+
+fn read_function_names(data: &[u8]) -> wasmparser::Result<BTreeMap<u32, String>> {
+    let mut names = BTreeMap::new();
+    let mut reader = BinaryReader::new(data);
+    while !reader.eof() {
+        let subsection_id = reader.read_u8()?;
+        let subsection_size = reader.read_var_u32()?;
+        let subsection = reader.read_bytes(subsection_size as usize)?;
+        if subsection_id == FUNCTION_NAMES_SUBSECTION_ID {
+            let mut reader = BinaryReader::new(subsection);
+            let count = reader.read_var_u32()?;
+            for _ in 0..count {
+                let func_index = reader.read_var_u32()?;
+                let name = reader.read_string()?;
+                names.insert(func_index, name.to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    function_names: HashMap<WasmHash, Arc<FunctionNames>>,
+    folded_stacks: BTreeMap<String, u64>,
+}
+
+/// Accumulates the profiles of all executions and keeps the output file up to
+/// date.
+pub struct InstructionProfiler {
+    output: PathBuf,
+    state: Mutex<ProfilerState>,
+}
+
+impl InstructionProfiler {
+    pub fn new(output: PathBuf) -> Self {
+        Self {
+            output,
+            state: Mutex::new(ProfilerState::default()),
+        }
+    }
+
+    /// Adds the profile of an execution of `canister_module` on `canister_id`
+    /// and rewrites the output file with the accumulated profile.
+    pub(crate) fn record(
+        &self,
+        canister_id: CanisterId,
+        canister_module: &CanisterModule,
+        profile: &ExecutionProfile,
+    ) -> std::io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let names = Arc::clone(
+            state
+                .function_names
+                .entry(WasmHash::from(canister_module))
+                .or_insert_with(|| {
+                    let names = decode_wasm(canister_module.to_shared_vec())
+                        .map(|wasm| FunctionNames::from_wasm(wasm.as_slice()))
+                        .unwrap_or_default();
+                    Arc::new(names)
+                }),
+        );
+        for (stack, instructions) in profile.folded_stacks(&canister_id.to_string(), &names) {
+            *state.folded_stacks.entry(stack).or_default() += instructions;
+        }
+
+        let mut contents = String::new();
+        for (stack, instructions) in &state.folded_stacks {
+            writeln!(contents, "{} {}", stack, instructions).unwrap();
+        }
+        std::fs::write(&self.output, contents)
+    }
+}
@@ -13,6 +13,7 @@ use wasmtime::Module;
 
 use crate::wasmtime_embedder::CanisterMemoryType;
 use crate::{
+    profiling::InstructionProfiler,
     wasm_utils::{compile, decoding::decode_wasm, Segments, WasmImportsDetails},
     wasmtime_embedder::WasmtimeInstance,
     CompilationCache, CompilationResult, SerializedModule, WasmExecutionInput, WasmtimeEmbedder,
@@ -29,7 +30,7 @@ use ic_sys::{page_bytes_from_ptr, PageBytes, PageIndex, PAGE_SIZE};
 use ic_system_api::{
     system_api_empty::SystemApiEmpty, ExecutionParameters, ModificationTracking, SystemApiImpl,
 };
-use ic_types::{CanisterId, NumBytes, NumInstructions};
+use ic_types::{CanisterId, NumBytes, NumInstructions, MAX_WASM64_MEMORY_IN_BYTES};
 use ic_wasm_types::{BinaryEncodedWasm, CanisterModule};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
@@ -175,6 +176,7 @@ pub struct WasmExecutorImpl {
     metrics: WasmExecutorMetrics,
     log: ReplicaLogger,
     fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
+    instruction_profiler: Option<InstructionProfiler>,
 }
 
 impl WasmExecutor for WasmExecutorImpl {
@@ -221,12 +223,13 @@ impl WasmExecutor for WasmExecutorImpl {
         let wasm_reserved_pages = get_wasm_reserved_pages(execution_state);
         let mut wasm_memory = execution_state.wasm_memory.clone();
         let mut stable_memory = execution_state.stable_memory.clone();
+        let canister_id = sandbox_safe_system_state.canister_id();
 
         let (
             slice_execution_output,
             wasm_execution_output,
             wasm_state_changes,
-            instance_or_system_api,
+            mut instance_or_system_api,
         ) = process(
             func_ref,
             api_type,
@@ -249,6 +252,25 @@ impl WasmExecutor for WasmExecutorImpl {
             self.emit_state_hashes_for_debugging(&wasm_state_changes, &wasm_execution_output);
         }
 
+        if let (Some(profiler), Ok(instance)) =
+            (&self.instruction_profiler, &mut instance_or_system_api)
+        {
+            let instruction_counter = instance.instruction_counter();
+            if let Some(profile) = instance.store_data_mut().instruction_profile.as_mut() {
+                profile.finish(instruction_counter);
+                if let Err(err) =
+                    profiler.record(canister_id, &execution_state.wasm_binary.binary, profile)
+                {
+                    warn!(
+                        self.log,
+                        "Failed to write the instruction profile of canister {}: {}",
+                        canister_id,
+                        err
+                    );
+                }
+            }
+        }
+
         let canister_state_changes = match wasm_state_changes {
             Some(wasm_state_changes) => {
                 let system_api = match instance_or_system_api {
@@ -347,11 +369,17 @@ impl WasmExecutorImpl {
         log: ReplicaLogger,
         fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
     ) -> Self {
+        let instruction_profiler = wasm_embedder
+            .config()
+            .instruction_profile_output
+            .clone()
+            .map(InstructionProfiler::new);
         Self {
             wasm_embedder,
             metrics: WasmExecutorMetrics::new(metrics_registry),
             log,
             fd_factory: Arc::clone(&fd_factory),
+            instruction_profiler,
         }
     }
 
@@ -474,6 +502,7 @@ pub fn wasm_execution_error(
             allocated_bytes: NumBytes::from(0),
             allocated_message_bytes: NumBytes::from(0),
             instance_stats: InstanceStats::default(),
+            canister_log: Default::default(),
         },
         None,
     )
@@ -638,6 +667,7 @@ pub fn process(
                     allocated_bytes: NumBytes::from(0),
                     allocated_message_bytes: NumBytes::from(0),
                     instance_stats: InstanceStats::default(),
+                    canister_log: Default::default(),
                 },
                 None,
                 Err(system_api),
@@ -679,10 +709,15 @@ pub fn process(
         .store_data_mut()
         .system_api
         .take_execution_result(run_result.as_ref().err());
+    let canister_log = instance.store_data_mut().system_api.take_canister_log();
 
     let wasm_heap_size_after = instance.heap_size(CanisterMemoryType::Heap);
-    let wasm_heap_limit =
-        NumWasmPages::from(wasmtime_environ::WASM32_MAX_PAGES as usize) - wasm_reserved_pages;
+    let max_wasm_pages = if instance.is_wasm64() {
+        (MAX_WASM64_MEMORY_IN_BYTES / wasmtime_environ::WASM_PAGE_SIZE as u64) as usize
+    } else {
+        wasmtime_environ::WASM32_MAX_PAGES as usize
+    };
+    let wasm_heap_limit = NumWasmPages::from(max_wasm_pages) - wasm_reserved_pages;
 
     if wasm_heap_size_after > wasm_heap_limit {
         wasm_result = Err(HypervisorError::WasmReservedPages);
@@ -760,6 +795,7 @@ pub fn process(
             allocated_bytes,
             allocated_message_bytes,
             instance_stats,
+            canister_log,
         },
         wasm_state_changes,
         Ok(instance),
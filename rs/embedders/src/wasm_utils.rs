@@ -3,7 +3,7 @@ use std::{
     time::Instant,
 };
 
-use ic_config::embedders::Config as EmbeddersConfig;
+use ic_config::{embedders::Config as EmbeddersConfig, flag_status::FlagStatus};
 use ic_interfaces::execution_environment::HypervisorResult;
 use ic_replicated_state::{
     canister_state::{execution_state::WasmMetadata, WASM_PAGE_SIZE_IN_BYTES},
@@ -208,6 +208,10 @@ fn validate_and_instrument(
         config.cost_to_compile_wasm_instruction,
         config.feature_flags.write_barrier,
         config.feature_flags.wasm_native_stable_memory,
+        match config.instruction_profile_output {
+            Some(_) => FlagStatus::Enabled,
+            None => FlagStatus::Disabled,
+        },
         config.subnet_type,
         config.dirty_page_overhead,
     )?;
@@ -31,6 +31,8 @@
 //! (import "__" "internal_trap" (func (;1;) ((param i32))))
 //! ```
 //! Where the last three will only be inserted if Wasm-native stable memory is enabled.
+//! For modules with a 64-bit (memory64) heap, the parameters and the result of
+//! `update_available_memory` are `i64` to match `memory.grow`.
 //!
 //! It then inserts (and exports) a global mutable counter:
 //! ```wasm
@@ -109,15 +111,27 @@
 //! (memory (export "stable_memory_bytemap") i32 (i64.const STABLE_BYTEMAP_SIZE) (i64.const STABLE_BYTEMAP_SIZE))
 //! ```
 //!
+//! # Instruction profiling
+//!
+//! If instruction profiling is enabled, two more functions are imported and
+//! every function body is wrapped in calls to them, so that the embedder can
+//! attribute the executed instructions to the functions of the module:
+//! ```wasm
+//! (import "__" "profile_enter" (func (param i32)))
+//! (import "__" "profile_exit" (func (param i32)))
+//! ```
+//! The calls are injected after metering and therefore do not change the
+//! number of instructions charged for the execution.
+//!
 
 use super::system_api_replacements::replacement_functions;
-use super::validation::API_VERSION_IC0;
+use super::validation::{is_wasm64, API_VERSION_IC0};
 use super::{InstrumentationOutput, Segments, SystemApiFunc};
 use ic_config::flag_status::FlagStatus;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::NumWasmPages;
 use ic_sys::PAGE_SIZE;
-use ic_types::{methods::WasmMethod, MAX_WASM_MEMORY_IN_BYTES};
+use ic_types::{methods::WasmMethod, MAX_WASM64_MEMORY_IN_BYTES, MAX_WASM_MEMORY_IN_BYTES};
 use ic_types::{NumInstructions, MAX_STABLE_MEMORY_IN_BYTES};
 use ic_wasm_types::{BinaryEncodedWasm, WasmError, WasmInstrumentationError};
 use wasmtime_environ::WASM_PAGE_SIZE;
@@ -154,6 +168,16 @@ impl InjectedImports {
     }
 }
 
+// The number of imports injected for instruction profiling. If profiling is
+// enabled, `profile_enter` and `profile_exit` are injected right after the
+// imports listed in `InjectedImports`.
+fn profiling_imports_count(instruction_profiling: FlagStatus) -> usize {
+    match instruction_profiling {
+        FlagStatus::Enabled => 2,
+        FlagStatus::Disabled => 0,
+    }
+}
+
 // Gets the cost of an instruction.
 fn instruction_to_cost(i: &Operator) -> u64 {
     match i {
@@ -164,7 +188,53 @@ fn instruction_to_cost(i: &Operator) -> u64 {
         Operator::End => 0,
         Operator::Loop { .. } => 0,
 
-        // Default cost of an instruction is 1.
+        // Fixed-width SIMD instructions that take noticeably longer than a
+        // scalar instruction, either because they are slow on all lanes or
+        // because they are lowered to a sequence of machine instructions, e.g.
+        // to canonicalize NaNs.
+        Operator::F32x4Div | Operator::F64x2Div | Operator::F32x4Sqrt | Operator::F64x2Sqrt => 4,
+        Operator::I16x8Mul
+        | Operator::I32x4Mul
+        | Operator::I64x2Mul
+        | Operator::F32x4Mul
+        | Operator::F64x2Mul
+        | Operator::I32x4DotI16x8S
+        | Operator::I16x8Q15MulrSatS
+        | Operator::I16x8ExtMulLowI8x16S
+        | Operator::I16x8ExtMulHighI8x16S
+        | Operator::I16x8ExtMulLowI8x16U
+        | Operator::I16x8ExtMulHighI8x16U
+        | Operator::I32x4ExtMulLowI16x8S
+        | Operator::I32x4ExtMulHighI16x8S
+        | Operator::I32x4ExtMulLowI16x8U
+        | Operator::I32x4ExtMulHighI16x8U
+        | Operator::I64x2ExtMulLowI32x4S
+        | Operator::I64x2ExtMulHighI32x4S
+        | Operator::I64x2ExtMulLowI32x4U
+        | Operator::I64x2ExtMulHighI32x4U
+        | Operator::I8x16Swizzle
+        | Operator::I8x16Shuffle { .. }
+        | Operator::I8x16Popcnt
+        | Operator::F32x4Ceil
+        | Operator::F32x4Floor
+        | Operator::F32x4Trunc
+        | Operator::F32x4Nearest
+        | Operator::F64x2Ceil
+        | Operator::F64x2Floor
+        | Operator::F64x2Trunc
+        | Operator::F64x2Nearest
+        | Operator::F32x4Min
+        | Operator::F32x4Max
+        | Operator::F32x4PMin
+        | Operator::F32x4PMax
+        | Operator::F64x2Min
+        | Operator::F64x2Max
+        | Operator::F64x2PMin
+        | Operator::F64x2PMax => 2,
+
+        // Default cost of an instruction is 1. This includes the remaining
+        // SIMD instructions, e.g. loads, stores, lane accesses, bitwise
+        // operations, additions and comparisons.
         _ => 1,
     }
 }
@@ -188,6 +258,8 @@ const UPDATE_MEMORY_FUN_NAME: &str = "update_available_memory";
 const TRY_GROW_STABLE_MEMORY_FUN_NAME: &str = "try_grow_stable_memory";
 const DEALLOCATE_PAGES_NAME: &str = "deallocate_pages";
 const INTERNAL_TRAP_FUN_NAME: &str = "internal_trap";
+const PROFILE_ENTER_FUN_NAME: &str = "profile_enter";
+const PROFILE_EXIT_FUN_NAME: &str = "profile_exit";
 const TABLE_STR: &str = "table";
 pub(crate) const INSTRUCTIONS_COUNTER_GLOBAL_NAME: &str = "canister counter_instructions";
 pub(crate) const DIRTY_PAGES_COUNTER_GLOBAL_NAME: &str = "canister counter_dirty_pages";
@@ -197,10 +269,23 @@ const CANISTER_START_STR: &str = "canister_start";
 const BYTEMAP_SIZE_IN_WASM_PAGES: u64 =
     MAX_WASM_MEMORY_IN_BYTES / (PAGE_SIZE as u64) / (WASM_PAGE_SIZE as u64);
 
+const MAX_WASM64_MEMORY_IN_WASM_PAGES: u64 = MAX_WASM64_MEMORY_IN_BYTES / (WASM_PAGE_SIZE as u64);
+/// There is one byte for each OS page in a 64-bit wasm heap.
+const WASM64_BYTEMAP_SIZE_IN_WASM_PAGES: u64 = MAX_WASM64_MEMORY_IN_WASM_PAGES / (PAGE_SIZE as u64);
+
 const MAX_STABLE_MEMORY_IN_WASM_PAGES: u64 = MAX_STABLE_MEMORY_IN_BYTES / (WASM_PAGE_SIZE as u64);
 /// There is one byte for each OS page in the stable memory.
 const STABLE_BYTEMAP_SIZE_IN_WASM_PAGES: u64 = MAX_STABLE_MEMORY_IN_WASM_PAGES / (PAGE_SIZE as u64);
 
+/// The type of addresses into and sizes of the wasm heap.
+fn heap_addr_type(is_wasm64: bool) -> ValType {
+    if is_wasm64 {
+        ValType::I64
+    } else {
+        ValType::I32
+    }
+}
+
 fn add_type(module: &mut Module, ty: Type) -> u32 {
     let Type::Func(sig) = &ty;
     for (idx, Type::Func(msig)) in module.types.iter().enumerate() {
@@ -242,10 +327,19 @@ fn mutate_function_indices(module: &mut Module, f: impl Fn(u32) -> u32) {
     }
 }
 
-fn inject_helper_functions(mut module: Module, wasm_native_stable_memory: FlagStatus) -> Module {
+fn inject_helper_functions(
+    mut module: Module,
+    wasm_native_stable_memory: FlagStatus,
+    instruction_profiling: FlagStatus,
+    is_wasm64: bool,
+) -> Module {
     // insert types
     let ooi_type = Type::Func(FuncType::new([], []));
-    let uam_type = Type::Func(FuncType::new([ValType::I32, ValType::I32], [ValType::I32]));
+    let heap_addr_type = heap_addr_type(is_wasm64);
+    let uam_type = Type::Func(FuncType::new(
+        [heap_addr_type, heap_addr_type],
+        [heap_addr_type],
+    ));
 
     let ooi_type_idx = add_type(&mut module, ooi_type);
     let uam_type_idx = add_type(&mut module, uam_type);
@@ -264,8 +358,11 @@ fn inject_helper_functions(mut module: Module, wasm_native_stable_memory: FlagSt
     };
 
     let mut old_imports = module.imports;
-    module.imports =
-        Vec::with_capacity(old_imports.len() + InjectedImports::count(wasm_native_stable_memory));
+    module.imports = Vec::with_capacity(
+        old_imports.len()
+            + InjectedImports::count(wasm_native_stable_memory)
+            + profiling_imports_count(instruction_profiling),
+    );
     module.imports.push(ooi_imp);
     module.imports.push(uam_imp);
 
@@ -300,10 +397,26 @@ fn inject_helper_functions(mut module: Module, wasm_native_stable_memory: FlagSt
         module.imports.push(it_imp);
     }
 
+    if instruction_profiling == FlagStatus::Enabled {
+        let profile_type = Type::Func(FuncType::new([ValType::I32], []));
+        let profile_type_idx = add_type(&mut module, profile_type);
+        module.imports.push(Import {
+            module: INSTRUMENTED_FUN_MODULE,
+            name: PROFILE_ENTER_FUN_NAME,
+            ty: TypeRef::Func(profile_type_idx),
+        });
+        module.imports.push(Import {
+            module: INSTRUMENTED_FUN_MODULE,
+            name: PROFILE_EXIT_FUN_NAME,
+            ty: TypeRef::Func(profile_type_idx),
+        });
+    }
+
     module.imports.append(&mut old_imports);
 
-    // now increment all function references by InjectedImports::Count
-    let cnt = InjectedImports::count(wasm_native_stable_memory) as u32;
+    // now increment all function references by the number of injected imports
+    let cnt = (InjectedImports::count(wasm_native_stable_memory)
+        + profiling_imports_count(instruction_profiling)) as u32;
     mutate_function_indices(&mut module, |i| i + cnt);
 
     debug_assert!(
@@ -346,19 +459,27 @@ pub(super) struct SpecialIndices {
 ///
 /// Returns an [`InstrumentationOutput`] or an error if the input binary could
 /// not be instrumented.
+#[allow(clippy::too_many_arguments)]
 pub(super) fn instrument(
     module: Module<'_>,
     cost_to_compile_wasm_instruction: NumInstructions,
     write_barrier: FlagStatus,
     wasm_native_stable_memory: FlagStatus,
+    instruction_profiling: FlagStatus,
     subnet_type: SubnetType,
     dirty_page_overhead: NumInstructions,
 ) -> Result<InstrumentationOutput, WasmInstrumentationError> {
     let stable_memory_index;
-    let mut module = inject_helper_functions(module, wasm_native_stable_memory);
+    let is_wasm64 = is_wasm64(&module);
+    let mut module = inject_helper_functions(
+        module,
+        wasm_native_stable_memory,
+        instruction_profiling,
+        is_wasm64,
+    );
     module = export_table(module);
     (module, stable_memory_index) =
-        update_memories(module, write_barrier, wasm_native_stable_memory);
+        update_memories(module, write_barrier, wasm_native_stable_memory, is_wasm64);
 
     let mut extra_strs: Vec<String> = Vec::new();
     module = export_mutable_globals(module, &mut extra_strs);
@@ -408,7 +529,33 @@ pub(super) fn instrument(
 
     // inject instructions counter decrementation
     for func_body in &mut module.code_sections {
-        inject_metering(&mut func_body.instructions, &special_indices);
+        inject_metering(&mut func_body.instructions, &special_indices, is_wasm64);
+    }
+
+    if instruction_profiling == FlagStatus::Enabled {
+        let profile_enter_fn = InjectedImports::count(wasm_native_stable_memory) as u32;
+        // The profile refers to functions by their index in the original
+        // module, i.e. before the helper functions were injected.
+        let first_local_fn = num_imported_functions as u32
+            - profile_enter_fn
+            - profiling_imports_count(instruction_profiling) as u32;
+        for func_ix in 0..module.code_sections.len() {
+            let Type::Func(func_type) = module.types[module.functions[func_ix] as usize].clone();
+            let blockty = match func_type.results() {
+                [] => BlockType::Empty,
+                [result] => BlockType::Type(*result),
+                results => BlockType::FuncType(add_type(
+                    &mut module,
+                    Type::Func(FuncType::new([], results.iter().copied())),
+                )),
+            };
+            inject_profiling(
+                &mut module.code_sections[func_ix],
+                first_local_fn + func_ix as u32,
+                blockty,
+                profile_enter_fn,
+            );
+        }
     }
 
     // Collect all the function types of the locally defined functions inside the
@@ -428,9 +575,9 @@ pub(super) fn instrument(
     if !func_types.is_empty() {
         let func_bodies = &mut module.code_sections;
         for (func_ix, func_type) in func_types.into_iter().enumerate() {
-            inject_update_available_memory(&mut func_bodies[func_ix], &func_type);
+            inject_update_available_memory(&mut func_bodies[func_ix], &func_type, is_wasm64);
             if write_barrier == FlagStatus::Enabled {
-                inject_mem_barrier(&mut func_bodies[func_ix], &func_type);
+                inject_mem_barrier(&mut func_bodies[func_ix], &func_type, is_wasm64);
             }
         }
     }
@@ -748,11 +895,12 @@ enum Scope {
 // Describes how to calculate the instruction cost at this injection point.
 // `StaticCost` injection points contain information about the cost of the
 // following basic block. `DynamicCost` injection points assume there is an i32
-// on the stack which should be decremented from the instruction counter.
+// (or an i64 if `size_is_i64`, e.g. for bulk memory instructions on a 64-bit
+// heap) on the stack which should be decremented from the instruction counter.
 #[derive(Copy, Clone, Debug, PartialEq)]
 enum InjectionPointCostDetail {
     StaticCost { scope: Scope, cost: u64 },
-    DynamicCost,
+    DynamicCost { size_is_i64: bool },
 }
 
 impl InjectionPointCostDetail {
@@ -761,7 +909,7 @@ impl InjectionPointCostDetail {
     fn increment_cost(&mut self, additonal_cost: u64) {
         match self {
             Self::StaticCost { scope: _, cost } => *cost += additonal_cost,
-            Self::DynamicCost => {}
+            Self::DynamicCost { .. } => {}
         }
     }
 }
@@ -781,9 +929,9 @@ impl InjectionPoint {
         }
     }
 
-    fn new_dynamic_cost(position: usize) -> Self {
+    fn new_dynamic_cost(position: usize, size_is_i64: bool) -> Self {
         InjectionPoint {
-            cost_detail: InjectionPointCostDetail::DynamicCost,
+            cost_detail: InjectionPointCostDetail::DynamicCost { size_is_i64 },
             position,
         }
     }
@@ -798,15 +946,15 @@ impl InjectionPoint {
 // - we insert a function call before each dynamic cost instruction which
 //   performs an overflow check and then decrements the counter by the value at
 //   the top of the stack.
-fn inject_metering(code: &mut Vec<Operator>, export_data_module: &SpecialIndices) {
-    let points = injections(code);
+fn inject_metering(code: &mut Vec<Operator>, export_data_module: &SpecialIndices, is_wasm64: bool) {
+    let points = injections(code, is_wasm64);
     let points = points.iter().filter(|point| match point.cost_detail {
         InjectionPointCostDetail::StaticCost {
             scope: Scope::ReentrantBlockStart,
             cost: _,
         } => true,
         InjectionPointCostDetail::StaticCost { scope: _, cost } => cost > 0,
-        InjectionPointCostDetail::DynamicCost => true,
+        InjectionPointCostDetail::DynamicCost { .. } => true,
     });
     let orig_elems = code;
     let mut elems: Vec<Operator> = Vec::new();
@@ -845,7 +993,12 @@ fn inject_metering(code: &mut Vec<Operator>, export_data_module: &SpecialIndices
                     ]);
                 }
             }
-            InjectionPointCostDetail::DynamicCost => {
+            InjectionPointCostDetail::DynamicCost { size_is_i64: true } => {
+                elems.push(Call {
+                    function_index: export_data_module.decr_instruction_counter_fn,
+                });
+            }
+            InjectionPointCostDetail::DynamicCost { size_is_i64: false } => {
                 elems.extend_from_slice(&[
                     I64ExtendI32U,
                     Call {
@@ -870,81 +1023,86 @@ fn write_barrier_instructions<'a>(
     offset: u64,
     val_arg_idx: u32,
     addr_arg_idx: u32,
+    is_wasm64: bool,
 ) -> Vec<Operator<'a>> {
     use Operator::*;
     let page_size_shift = PAGE_SIZE.trailing_zeros() as i32;
     let tracking_mem_idx = 1;
-    if offset % PAGE_SIZE as u64 == 0 {
-        vec![
-            LocalSet {
-                local_index: val_arg_idx,
-            }, // value
-            LocalTee {
-                local_index: addr_arg_idx,
-            }, // address
-            I32Const {
-                value: page_size_shift,
-            },
-            I32ShrU,
-            I32Const { value: 1 },
-            I32Store8 {
-                memarg: wasmparser::MemArg {
-                    align: 0,
-                    max_align: 0,
-                    offset: offset >> page_size_shift,
-                    memory: tracking_mem_idx,
-                },
-            },
-            // Put original params on the stack
-            LocalGet {
-                local_index: addr_arg_idx,
-            },
-            LocalGet {
-                local_index: val_arg_idx,
+    let mut instructions = vec![
+        LocalSet {
+            local_index: val_arg_idx,
+        }, // value
+        LocalTee {
+            local_index: addr_arg_idx,
+        }, // address
+    ];
+    // The bytemap is a 32-bit memory, so the static offset of the bytemap
+    // store must fit into 32 bits.
+    let bytemap_offset =
+        if offset % PAGE_SIZE as u64 == 0 && offset >> page_size_shift <= u32::MAX as u64 {
+            offset >> page_size_shift
+        } else {
+            if is_wasm64 {
+                instructions.extend([
+                    I64Const {
+                        value: offset as i64,
+                    },
+                    I64Add,
+                ]);
+            } else {
+                instructions.extend([
+                    I32Const {
+                        value: offset as i32,
+                    },
+                    I32Add,
+                ]);
+            }
+            0
+        };
+    if is_wasm64 {
+        instructions.extend([
+            I64Const {
+                value: page_size_shift as i64,
             },
-        ]
+            I64ShrU,
+            I32WrapI64,
+        ]);
     } else {
-        vec![
-            LocalSet {
-                local_index: val_arg_idx,
-            }, // value
-            LocalTee {
-                local_index: addr_arg_idx,
-            }, // address
-            I32Const {
-                value: offset as i32,
-            },
-            I32Add,
+        instructions.extend([
             I32Const {
                 value: page_size_shift,
             },
             I32ShrU,
-            I32Const { value: 1 },
-            I32Store8 {
-                memarg: wasmparser::MemArg {
-                    align: 0,
-                    max_align: 0,
-                    offset: 0,
-                    memory: tracking_mem_idx,
-                },
-            },
-            // Put original params on the stack
-            LocalGet {
-                local_index: addr_arg_idx,
-            },
-            LocalGet {
-                local_index: val_arg_idx,
-            },
-        ]
+        ]);
     }
+    instructions.extend([
+        I32Const { value: 1 },
+        I32Store8 {
+            memarg: wasmparser::MemArg {
+                align: 0,
+                max_align: 0,
+                offset: bytemap_offset,
+                memory: tracking_mem_idx,
+            },
+        },
+        // Put original params on the stack
+        LocalGet {
+            local_index: addr_arg_idx,
+        },
+        LocalGet {
+            local_index: val_arg_idx,
+        },
+    ]);
+    instructions
 }
 
-fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType) {
+fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType, is_wasm64: bool) {
     use Operator::*;
     let mut val_i32_needed = false;
     let mut val_i64_needed = false;
     let mut val_f32_needed = false;
     let mut val_f64_needed = false;
+    let mut val_v128_needed = false;
 
     let mut injection_points: Vec<usize> = Vec::new();
     {
@@ -966,6 +1124,14 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
                     val_f64_needed = true;
                     injection_points.push(idx)
                 }
+                V128Store { .. }
+                | V128Store8Lane { .. }
+                | V128Store16Lane { .. }
+                | V128Store32Lane { .. }
+                | V128Store64Lane { .. } => {
+                    val_v128_needed = true;
+                    injection_points.push(idx)
+                }
                 _ => (),
             }
         }
@@ -978,7 +1144,7 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
         // the total number of locals.
         let n_locals: u32 = func_body.locals.iter().map(|x| x.0).sum();
         let mut next_local = func_type.params().len() as u32 + n_locals;
-        let arg_i32_addr_idx = next_local;
+        let arg_addr_idx = next_local;
         next_local += 1;
 
         // conditionally add following locals
@@ -986,8 +1152,18 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
         let arg_i64_val_idx;
         let arg_f32_val_idx;
         let arg_f64_val_idx;
-
-        if val_i32_needed {
+        let arg_v128_val_idx;
+
+        if is_wasm64 {
+            func_body.locals.push((1, ValType::I64)); // addr local
+            if val_i32_needed {
+                arg_i32_val_idx = next_local;
+                next_local += 1;
+                func_body.locals.push((1, ValType::I32));
+            } else {
+                arg_i32_val_idx = u32::MAX; // not used
+            }
+        } else if val_i32_needed {
             arg_i32_val_idx = next_local;
             next_local += 1;
             func_body.locals.push((2, ValType::I32)); // addr and val locals
@@ -1014,12 +1190,20 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
 
         if val_f64_needed {
             arg_f64_val_idx = next_local;
-            // next_local += 1;
+            next_local += 1;
             func_body.locals.push((1, ValType::F64));
         } else {
             arg_f64_val_idx = u32::MAX;
         }
 
+        if val_v128_needed {
+            arg_v128_val_idx = next_local;
+            // next_local += 1;
+            func_body.locals.push((1, ValType::V128));
+        } else {
+            arg_v128_val_idx = u32::MAX;
+        }
+
         let orig_elems = &func_body.instructions;
         let mut elems: Vec<Operator> = Vec::new();
         let mut last_injection_position = 0;
@@ -1032,7 +1216,8 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
                     elems.extend_from_slice(&write_barrier_instructions(
                         memarg.offset,
                         arg_i32_val_idx,
-                        arg_i32_addr_idx,
+                        arg_addr_idx,
+                        is_wasm64,
                     ));
                 }
                 I64Store { memarg }
@@ -1042,21 +1227,36 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
                     elems.extend_from_slice(&write_barrier_instructions(
                         memarg.offset,
                         arg_i64_val_idx,
-                        arg_i32_addr_idx,
+                        arg_addr_idx,
+                        is_wasm64,
                     ));
                 }
                 F32Store { memarg } => {
                     elems.extend_from_slice(&write_barrier_instructions(
                         memarg.offset,
                         arg_f32_val_idx,
-                        arg_i32_addr_idx,
+                        arg_addr_idx,
+                        is_wasm64,
                     ));
                 }
                 F64Store { memarg } => {
                     elems.extend_from_slice(&write_barrier_instructions(
                         memarg.offset,
                         arg_f64_val_idx,
-                        arg_i32_addr_idx,
+                        arg_addr_idx,
+                        is_wasm64,
+                    ));
+                }
+                V128Store { memarg }
+                | V128Store8Lane { memarg, .. }
+                | V128Store16Lane { memarg, .. }
+                | V128Store32Lane { memarg, .. }
+                | V128Store64Lane { memarg, .. } => {
+                    elems.extend_from_slice(&write_barrier_instructions(
+                        memarg.offset,
+                        arg_v128_val_idx,
+                        arg_addr_idx,
+                        is_wasm64,
                     ));
                 }
                 _ => {}
@@ -1071,11 +1271,64 @@ fn inject_mem_barrier(func_body: &mut wasm_transform::Body, func_type: &FuncType
     }
 }
 
+// Wraps the function body in a block, so that every branch to the outermost
+// label falls through to the end of the function, and inserts calls to
+// `profile_enter` at the beginning and to `profile_exit` before every return:
+//
+// ```wasm
+// i32.const <func_index>
+// call <profile_enter>
+// block (result ...)
+//   ;; the original body with `i32.const <func_index>; call <profile_exit>`
+//   ;; before every `return`
+// end
+// i32.const <func_index>
+// call <profile_exit>
+// end
+// ```
+fn inject_profiling(
+    func_body: &mut wasm_transform::Body,
+    func_index: u32,
+    blockty: BlockType,
+    profile_enter_fn: u32,
+) {
+    use Operator::*;
+    let func_index = func_index as i32;
+    let profile_exit = [
+        I32Const { value: func_index },
+        Call {
+            function_index: profile_enter_fn + 1,
+        },
+    ];
+    let mut elems: Vec<Operator> = Vec::with_capacity(func_body.instructions.len() + 7);
+    elems.extend_from_slice(&[
+        I32Const { value: func_index },
+        Call {
+            function_index: profile_enter_fn,
+        },
+        Block { blockty },
+    ]);
+    for instr in func_body.instructions.drain(..) {
+        if let Return | ReturnCall { .. } | ReturnCallIndirect { .. } = instr {
+            elems.extend_from_slice(&profile_exit);
+        }
+        elems.push(instr);
+    }
+    // The `end` of the original body closes the injected block.
+    elems.extend_from_slice(&profile_exit);
+    elems.push(End);
+    func_body.instructions = elems;
+}
+
 // Scans through a function and adds instrumentation after each `memory.grow`
 // instruction to make sure that there's enough available memory left to support
 // the requested extra memory. If no `memory.grow` instructions are present then
 // the function's code remains unchanged.
-fn inject_update_available_memory(func_body: &mut wasm_transform::Body, func_type: &FuncType) {
+fn inject_update_available_memory(
+    func_body: &mut wasm_transform::Body,
+    func_type: &FuncType,
+    is_wasm64: bool,
+) {
     use Operator::*;
     let mut injection_points: Vec<usize> = Vec::new();
     {
@@ -1095,7 +1348,7 @@ fn inject_update_available_memory(func_body: &mut wasm_transform::Body, func_typ
         // the total number of locals.
         let n_locals: u32 = func_body.locals.iter().map(|x| x.0).sum();
         let memory_local_ix = func_type.params().len() as u32 + n_locals;
-        func_body.locals.push((1, ValType::I32));
+        func_body.locals.push((1, heap_addr_type(is_wasm64)));
 
         let orig_elems = &func_body.instructions;
         let mut elems: Vec<Operator> = Vec::new();
@@ -1130,7 +1383,7 @@ fn inject_update_available_memory(func_body: &mut wasm_transform::Body, func_typ
 // with no branches) and before each bulk memory instruction. An injection point
 // contains a "hint" about the context of every basic block, specifically if
 // it's re-entrant or not.
-fn injections(code: &[Operator]) -> Vec<InjectionPoint> {
+fn injections(code: &[Operator], is_wasm64: bool) -> Vec<InjectionPoint> {
     let mut res = Vec::new();
     let mut stack = Vec::new();
     use Operator::*;
@@ -1165,12 +1418,13 @@ fn injections(code: &[Operator]) -> Vec<InjectionPoint> {
             }
             // Bulk memory instructions require injected metering __before__ the instruction
             // executes so that size arguments can be read from the stack at runtime.
-            MemoryFill { .. }
-            | MemoryCopy { .. }
-            | MemoryInit { .. }
-            | TableCopy { .. }
-            | TableInit { .. } => {
-                res.push(InjectionPoint::new_dynamic_cost(position));
+            // The size argument of `memory.fill` and `memory.copy` has the
+            // address type of the memory.
+            MemoryFill { .. } | MemoryCopy { .. } => {
+                res.push(InjectionPoint::new_dynamic_cost(position, is_wasm64));
+            }
+            MemoryInit { .. } | TableCopy { .. } | TableInit { .. } => {
+                res.push(InjectionPoint::new_dynamic_cost(position, false));
             }
             // Nothing special to be done for other instructions.
             _ => (),
@@ -1195,6 +1449,7 @@ fn get_data(
                     offset_expr,
                 } => match offset_expr {
                     Operator::I32Const { value } => *value as usize,
+                    Operator::I64Const { value } => *value as usize,
                     _ => return Err(WasmInstrumentationError::WasmDeserializeError(WasmError::new(
                         "complex initialization expressions for data segments are not supported!".into()
                     ))),
@@ -1241,9 +1496,23 @@ fn update_memories(
     mut module: Module,
     write_barrier: FlagStatus,
     wasm_native_stable_memory: FlagStatus,
+    is_wasm64: bool,
 ) -> (Module, u32) {
     let mut stable_index = 0;
 
+    // A 64-bit heap may declare a larger maximum than the replica supports.
+    if is_wasm64 {
+        if let Some(memory) = module.memories.first_mut() {
+            memory.maximum = Some(
+                memory
+                    .maximum
+                    .map_or(MAX_WASM64_MEMORY_IN_WASM_PAGES, |max| {
+                        max.min(MAX_WASM64_MEMORY_IN_WASM_PAGES)
+                    }),
+            );
+        }
+    }
+
     let mut memory_already_exported = false;
     for export in &mut module.exports {
         if let ExternalKind::Memory = export.kind {
@@ -1262,11 +1531,16 @@ fn update_memories(
     }
 
     if write_barrier == FlagStatus::Enabled && !module.memories.is_empty() {
+        let bytemap_size = if is_wasm64 {
+            WASM64_BYTEMAP_SIZE_IN_WASM_PAGES
+        } else {
+            BYTEMAP_SIZE_IN_WASM_PAGES
+        };
         module.memories.push(MemoryType {
             memory64: false,
             shared: false,
-            initial: BYTEMAP_SIZE_IN_WASM_PAGES,
-            maximum: Some(BYTEMAP_SIZE_IN_WASM_PAGES),
+            initial: bytemap_size,
+            maximum: Some(bytemap_size),
         });
 
         module.exports.push(Export {
@@ -10,6 +10,11 @@
 //! - track accesses and dirty pages
 //! - charge for instructions
 //!
+//! Growing the injected stable memory goes through `try_grow_stable_memory`.
+//! The `wasm_memory_limit` canister setting only bounds the Wasm heap, whose
+//! growth is checked by the `update_available_memory` call injected after
+//! every `memory.grow` of the heap, so it needs no check here.
+//!
 
 use crate::{
     wasm_utils::instrumentation::InjectedImports,
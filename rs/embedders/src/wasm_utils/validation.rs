@@ -3,7 +3,10 @@
 
 use super::{WasmImportsDetails, WasmValidationDetails};
 
-use ic_config::embedders::Config as EmbeddersConfig;
+use ic_config::{
+    embedders::{Config as EmbeddersConfig, FeatureFlags},
+    flag_status::FlagStatus,
+};
 use ic_replicated_state::canister_state::execution_state::{
     CustomSection, CustomSectionType, WasmMetadata,
 };
@@ -52,7 +55,15 @@ pub(super) const API_VERSION_IC0: &str = "ic0";
 // user tries to import a function that doesn't exist in any of the expected
 // modules vs the case where the function exists but is imported from the wrong
 // module.
-fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>> {
+//
+// For modules with a 64-bit heap, the heap addresses and sizes passed to and
+// returned from the System API are `i64` instead of `i32`.
+fn get_valid_system_apis(is_wasm64: bool) -> HashMap<String, HashMap<String, FunctionSignature>> {
+    let ptr = if is_wasm64 {
+        ValType::I64
+    } else {
+        ValType::I32
+    };
     let valid_system_apis = vec![
         (
             // Public methods
@@ -61,7 +72,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -70,7 +81,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -81,7 +92,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -90,7 +101,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -101,7 +112,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -110,7 +121,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -135,13 +146,23 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 },
             )],
         ),
+        (
+            "msg_deadline",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![],
+                    return_type: vec![ValType::I64],
+                },
+            )],
+        ),
         (
             "msg_reject_msg_size",
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -150,7 +171,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -160,7 +181,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -180,7 +201,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -191,7 +212,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -200,7 +221,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -211,7 +232,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -220,7 +241,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -241,16 +262,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                        ValType::I32,
-                    ],
+                    param_types: vec![ptr, ptr, ptr, ptr, ptr, ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -260,7 +272,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -270,7 +282,17 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
+                    return_type: vec![],
+                },
+            )],
+        ),
+        (
+            "call_with_best_effort_response",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValType::I32],
                     return_type: vec![],
                 },
             )],
@@ -301,7 +323,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -431,7 +453,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -481,7 +503,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -502,7 +524,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 API_VERSION_IC0,
                 FunctionSignature {
                     param_types: vec![],
-                    return_type: vec![ValType::I32],
+                    return_type: vec![ptr],
                 },
             )],
         ),
@@ -511,7 +533,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32, ValType::I32, ValType::I32],
+                    param_types: vec![ptr, ptr, ptr],
                     return_type: vec![],
                 },
             )],
@@ -536,6 +558,66 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
                 },
             )],
         ),
+        (
+            "is_controller",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ptr, ptr],
+                    return_type: vec![ValType::I32],
+                },
+            )],
+        ),
+        (
+            "in_replicated_execution",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![],
+                    return_type: vec![ValType::I32],
+                },
+            )],
+        ),
+        (
+            "cost_call",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValType::I64, ValType::I64, ptr],
+                    return_type: vec![],
+                },
+            )],
+        ),
+        (
+            "cost_create_canister",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ptr],
+                    return_type: vec![],
+                },
+            )],
+        ),
+        (
+            "cost_http_request",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ValType::I64, ValType::I64, ptr],
+                    return_type: vec![],
+                },
+            )],
+        ),
+        (
+            "cost_sign_with_ecdsa",
+            vec![(
+                API_VERSION_IC0,
+                FunctionSignature {
+                    param_types: vec![ptr, ptr, ValType::I32, ptr],
+                    return_type: vec![ValType::I32],
+                },
+            )],
+        ),
         (
             "call_cycles_add128",
             vec![(
@@ -551,7 +633,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32],
+                    param_types: vec![ptr],
                     return_type: vec![],
                 },
             )],
@@ -561,7 +643,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32],
+                    param_types: vec![ptr],
                     return_type: vec![],
                 },
             )],
@@ -571,7 +653,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I32],
+                    param_types: vec![ptr],
                     return_type: vec![],
                 },
             )],
@@ -581,7 +663,7 @@ fn get_valid_system_apis() -> HashMap<String, HashMap<String, FunctionSignature>
             vec![(
                 API_VERSION_IC0,
                 FunctionSignature {
-                    param_types: vec![ValType::I64, ValType::I64, ValType::I32],
+                    param_types: vec![ValType::I64, ValType::I64, ptr],
                     return_type: vec![],
                 },
             )],
@@ -669,6 +751,13 @@ fn get_valid_exported_functions() -> HashMap<String, FunctionSignature> {
                 return_type: vec![],
             },
         ),
+        (
+            "canister_on_low_wasm_memory",
+            FunctionSignature {
+                param_types: vec![],
+                return_type: vec![],
+            },
+        ),
     ];
 
     valid_exported_functions
@@ -729,11 +818,14 @@ fn set_imports_details(import_details: &mut WasmImportsDetails, import_module: &
 //
 // Returns information about what IC0 methods are imported via
 // `WasmImportsDetails`.
-fn validate_import_section(module: &Module) -> Result<WasmImportsDetails, WasmValidationError> {
+fn validate_import_section(
+    module: &Module,
+    is_wasm64: bool,
+) -> Result<WasmImportsDetails, WasmValidationError> {
     let mut imports_details = WasmImportsDetails::default();
 
     if !module.imports.is_empty() {
-        let valid_system_apis = get_valid_system_apis();
+        let valid_system_apis = get_valid_system_apis(is_wasm64);
         for entry in &module.imports {
             let import_module = entry.module;
             let field = entry.name;
@@ -892,7 +984,7 @@ fn validate_data_section(module: &Module) -> Result<(), WasmValidationError> {
                 memory_index: _,
                 offset_expr,
             } => match offset_expr {
-                Operator::I32Const { .. } => Ok(()),
+                Operator::I32Const { .. } | Operator::I64Const { .. } => Ok(()),
                 _ => Err(WasmValidationError::InvalidDataSection(format!(
                     "Invalid offset expression in data segment: {:?}",
                     offset_expr
@@ -915,9 +1007,68 @@ fn validate_global_section(module: &Module, max_globals: usize) -> Result<(), Wa
             allowed: max_globals,
         });
     }
+    // Mutable and exported globals are persisted in the canister state, which
+    // cannot represent `v128` values.
+    // Note that importing globals is not allowed, so the indices of the
+    // exported globals refer to the globals defined in the module.
+    let exported_globals: HashSet<u32> = module
+        .exports
+        .iter()
+        .filter(|export| export.kind == ExternalKind::Global)
+        .map(|export| export.index)
+        .collect();
+    for (index, global) in module.globals.iter().enumerate() {
+        if global.ty.content_type == ValType::V128
+            && (global.ty.mutable || exported_globals.contains(&(index as u32)))
+        {
+            return Err(WasmValidationError::InvalidGlobalSection(format!(
+                "Global {} of type v128 must be immutable and not exported.",
+                index
+            )));
+        }
+    }
     Ok(())
 }
 
+// Checks that the Wasm heap is a 64-bit memory only if 64-bit heaps are
+// enabled. The replacements of the stable memory System API used with
+// Wasm-native stable memory assume a 32-bit heap, so the two features cannot
+// be combined yet.
+//
+// Returns whether the module has a 64-bit heap.
+fn validate_memory_section(
+    module: &Module,
+    feature_flags: &FeatureFlags,
+) -> Result<bool, WasmValidationError> {
+    let is_wasm64 = is_wasm64(module);
+    if is_wasm64 && feature_flags.wasm64 == FlagStatus::Disabled {
+        return Err(WasmValidationError::InvalidMemorySection(
+            "64-bit Wasm memories are not supported.".to_string(),
+        ));
+    }
+    if is_wasm64 && feature_flags.wasm_native_stable_memory == FlagStatus::Enabled {
+        return Err(WasmValidationError::InvalidMemorySection(
+            "64-bit Wasm memories are not supported with Wasm-native stable memory.".to_string(),
+        ));
+    }
+    Ok(is_wasm64)
+}
+
+/// Returns whether the heap of the module, i.e. its first memory, is a 64-bit
+/// (memory64) memory.
+pub(crate) fn is_wasm64(module: &Module) -> bool {
+    module
+        .imports
+        .iter()
+        .filter_map(|import| match import.ty {
+            TypeRef::Memory(memory) => Some(memory),
+            _ => None,
+        })
+        .chain(module.memories.iter().copied())
+        .next()
+        .map_or(false, |memory| memory.memory64)
+}
+
 // Checks that no more than `max_functions` are defined in the
 // module.
 fn validate_function_section(
@@ -1119,16 +1270,25 @@ fn validate_code_section(module: &Module) -> Result<NumInstructions, WasmValidat
 }
 
 /// Sets Wasmtime flags to ensure deterministic execution.
+///
+/// Fixed-width SIMD is deterministic as long as NaNs are canonicalized, which
+/// Cranelift also does for the lanes of floating-point vector operations. The
+/// relaxed SIMD proposal has implementation-defined results and must stay
+/// disabled.
 pub fn ensure_determinism(config: &mut Config) {
     config
         .wasm_threads(false)
-        .wasm_simd(false)
+        .wasm_simd(true)
+        .wasm_relaxed_simd(false)
         .cranelift_nan_canonicalization(true);
 }
 
 fn can_compile(wasm: &BinaryEncodedWasm) -> Result<(), WasmValidationError> {
     let mut config = wasmtime::Config::default();
     ensure_determinism(&mut config);
+    // 64-bit memories are checked against the feature flags in
+    // `validate_memory_section`, which gives a more precise error.
+    config.wasm_memory64(true);
     let engine = wasmtime::Engine::new(&config).map_err(|_| {
         WasmValidationError::WasmtimeValidation(String::from("Failed to initialize Wasm engine"))
     })?;
@@ -1151,6 +1311,7 @@ fn can_compile(wasm: &BinaryEncodedWasm) -> Result<(), WasmValidationError> {
 /// * Code
 /// * Data
 /// * Global
+/// * Memory
 /// * Function
 /// * CustomSections
 ///
@@ -1162,7 +1323,8 @@ pub(super) fn validate_wasm_binary<'a>(
     can_compile(wasm)?;
     let module = Module::parse(wasm.as_slice(), false)
         .map_err(|err| WasmValidationError::DecodingError(format!("{}", err)))?;
-    let imports_details = validate_import_section(&module)?;
+    let is_wasm64 = validate_memory_section(&module, &config.feature_flags)?;
+    let imports_details = validate_import_section(&module, is_wasm64)?;
     let reserved_exports = validate_export_section(&module)?;
     validate_data_section(&module)?;
     validate_global_section(&module, config.max_globals)?;
@@ -38,7 +38,10 @@ use signal_stack::WasmtimeSignalStack;
 use crate::wasm_utils::instrumentation::{
     DIRTY_PAGES_COUNTER_GLOBAL_NAME, INSTRUCTIONS_COUNTER_GLOBAL_NAME,
 };
-use crate::{serialized_module::SerializedModuleBytes, wasm_utils::validation::ensure_determinism};
+use crate::{
+    profiling::ExecutionProfile, serialized_module::SerializedModuleBytes,
+    wasm_utils::validation::ensure_determinism,
+};
 
 use super::InstanceRunResult;
 
@@ -172,7 +175,9 @@ impl WasmtimeEmbedder {
         {
             config.wasm_multi_memory(true);
         }
-        if embedder_config.feature_flags.wasm_native_stable_memory == FlagStatus::Enabled {
+        if embedder_config.feature_flags.wasm_native_stable_memory == FlagStatus::Enabled
+            || embedder_config.feature_flags.wasm64 == FlagStatus::Enabled
+        {
             config.wasm_memory64(true);
         }
         config
@@ -297,16 +302,37 @@ impl WasmtimeEmbedder {
             StoreData {
                 system_api,
                 num_instructions_global: None,
+                instruction_profile: self
+                    .config
+                    .instruction_profile_output
+                    .as_ref()
+                    .map(|_| ExecutionProfile::default()),
             },
         );
 
-        let linker = system_api::syscalls(
-            self.log.clone(),
-            canister_id,
-            &store,
-            self.config.feature_flags,
-            self.config.stable_memory_dirty_page_limit,
+        // Heap addresses and sizes passed to the System API are 64-bit if the
+        // heap is a 64-bit memory.
+        let is_wasm64 = matches!(
+            module.get_export(WASM_HEAP_MEMORY_NAME),
+            Some(wasmtime::ExternType::Memory(memory)) if memory.is_64()
         );
+        let linker = if is_wasm64 {
+            system_api::syscalls::<S, u64>(
+                self.log.clone(),
+                canister_id,
+                &store,
+                self.config.feature_flags,
+                self.config.stable_memory_dirty_page_limit,
+            )
+        } else {
+            system_api::syscalls::<S, u32>(
+                self.log.clone(),
+                canister_id,
+                &store,
+                self.config.feature_flags,
+                self.config.stable_memory_dirty_page_limit,
+            )
+        };
 
         let instance = match linker.instantiate(&mut store, module) {
             Ok(instance) => instance,
@@ -615,6 +641,9 @@ fn sigsegv_memory_tracker<S>(
 pub struct StoreData<S> {
     pub system_api: S,
     pub num_instructions_global: Option<wasmtime::Global>,
+    /// The profile of the current execution if instruction profiling is
+    /// enabled.
+    pub instruction_profile: Option<ExecutionProfile>,
 }
 
 pub struct PageAccessResults {
@@ -969,8 +998,15 @@ impl<S: SystemApi> WasmtimeInstance<S> {
         }
     }
 
-    /// Returns the heap size.
-    /// Result is guaranteed to fit in a `u32`.
+    /// Returns whether the Wasm heap is a 64-bit (memory64) memory.
+    pub fn is_wasm64(&mut self) -> bool {
+        self.get_memory(WASM_HEAP_MEMORY_NAME)
+            .map_or(false, |mem| mem.ty(&self.store).is_64())
+    }
+
+    /// Returns the heap size in Wasm pages.
+    /// For 32-bit heaps the size in bytes fits in a `u32`. 64-bit heaps can
+    /// be up to `MAX_WASM64_MEMORY_IN_BYTES` large.
     pub fn heap_size(&mut self, canister_memory_type: CanisterMemoryType) -> NumWasmPages {
         let name = match canister_memory_type {
             CanisterMemoryType::Heap => WASM_HEAP_MEMORY_NAME,
@@ -29,10 +29,6 @@ fn round_up_to_os_page_size(size: usize) -> usize {
     round_up_to_page_size(size, PAGE_SIZE)
 }
 
-fn wasm_max_mem_size_in_bytes() -> usize {
-    WASM32_MAX_PAGES as usize * WASM_PAGE_SIZE as usize
-}
-
 #[derive(Hash, PartialEq, Eq)]
 pub(crate) struct MemoryStart(pub(crate) usize);
 
@@ -73,7 +69,9 @@ unsafe impl wasmtime::MemoryCreator for WasmtimeMemoryCreator {
         let min = std::cmp::min(ty.minimum(), max_pages) as usize;
         let max = std::cmp::min(ty.maximum().unwrap_or(max_pages), max_pages) as usize;
 
-        let mem_size = reserved_size_in_bytes.unwrap_or_else(wasm_max_mem_size_in_bytes);
+        // Dynamic memories, such as 64-bit memories, have no reservation from
+        // wasmtime, so reserve enough address space for their maximum size.
+        let mem_size = reserved_size_in_bytes.unwrap_or_else(|| convert_pages_to_bytes(max));
 
         let mem = MmapMemory::new(mem_size, guard_size);
 
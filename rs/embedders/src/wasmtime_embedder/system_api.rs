@@ -12,10 +12,61 @@ use ic_registry_subnet_type::SubnetType;
 use ic_sys::PAGE_SIZE;
 use ic_types::{CanisterId, Cycles, NumBytes, NumInstructions, NumPages, Time};
 
-use wasmtime::{AsContextMut, Caller, Global, Linker, Store, Val};
+use wasmtime::{AsContextMut, Caller, Global, Linker, Store, Val, WasmTy};
 
 use crate::InternalErrorCode;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, fmt::Display, num::TryFromIntError};
+
+/// The type of addresses into and sizes of regions of the Wasm heap that are
+/// passed to and returned from the System API: `u32` for 32-bit heaps and
+/// `u64` for 64-bit (memory64) heaps.
+pub(crate) trait WasmAddr: WasmTy + Copy + Display + Send + Sync + 'static {
+    fn to_usize(self) -> usize;
+
+    fn try_from_usize(value: usize) -> Result<Self, TryFromIntError>;
+
+    fn try_to_u32(self) -> Result<u32, TryFromIntError>;
+
+    /// Reinterprets the value as signed, e.g. to recognize the `-1` returned
+    /// by a failed `memory.grow`.
+    fn as_i64(self) -> i64;
+}
+
+impl WasmAddr for u32 {
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn try_from_usize(value: usize) -> Result<Self, TryFromIntError> {
+        u32::try_from(value)
+    }
+
+    fn try_to_u32(self) -> Result<u32, TryFromIntError> {
+        Ok(self)
+    }
+
+    fn as_i64(self) -> i64 {
+        self as i32 as i64
+    }
+}
+
+impl WasmAddr for u64 {
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn try_from_usize(value: usize) -> Result<Self, TryFromIntError> {
+        u64::try_from(value)
+    }
+
+    fn try_to_u32(self) -> Result<u32, TryFromIntError> {
+        u32::try_from(self)
+    }
+
+    fn as_i64(self) -> i64 {
+        self as i64
+    }
+}
 
 fn process_err<S: SystemApi>(
     store: &mut impl AsContextMut<Data = StoreData<S>>,
@@ -153,7 +204,7 @@ fn charge_for_system_api_call<S: SystemApi>(
     canister_id: CanisterId,
     caller: &mut Caller<'_, StoreData<S>>,
     system_api_overhead: NumInstructions,
-    num_bytes: u32,
+    num_bytes: usize,
     complexity: ExecutionComplexity,
     dirty_page_cost: NumInstructions,
     stable_memory_dirty_page_limit: NumPages,
@@ -360,7 +411,24 @@ fn ic0_performance_counter_helper<S: SystemApi>(
     }
 }
 
-pub(crate) fn syscalls<S: SystemApi>(
+/// Converts a function table index or closure environment passed by the
+/// canister. The System API keeps them as 32-bit values also for 64-bit heaps.
+fn closure_arg<S: SystemApi, I: WasmAddr>(
+    caller: &mut Caller<'_, StoreData<S>>,
+    value: I,
+) -> Result<u32, anyhow::Error> {
+    value.try_to_u32().map_err(|_| {
+        process_err(
+            caller,
+            HypervisorError::ContractViolation(format!(
+                "Closure argument {} does not fit into 32 bits",
+                value
+            )),
+        )
+    })
+}
+
+pub(crate) fn syscalls<S: SystemApi, I: WasmAddr>(
     log: ReplicaLogger,
     canister_id: CanisterId,
     store: &Store<StoreData<S>>,
@@ -405,7 +473,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_caller_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -417,10 +485,15 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_msg_caller_copy(dst as u32, offset as u32, size as u32, memory)
+                    system_api.ic0_msg_caller_copy(
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
+                        memory,
+                    )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -434,7 +507,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_msg_caller_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s as usize).map_err(|e| {
                             anyhow::Error::msg(format!("ic0::msg_caller_size failed: {}", e))
                         })
                     })
@@ -448,7 +521,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_msg_arg_data_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s as usize).map_err(|e| {
                             anyhow::Error::msg(format!("ic0::msg_arg_data_size failed: {}", e))
                         })
                     })
@@ -459,13 +532,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_arg_data_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::MSG_ARG_DATA_COPY,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::MSG_ARG_DATA_COPY,
                         ..Default::default()
@@ -474,10 +547,15 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, mem| {
-                    system_api.ic0_msg_arg_data_copy(dst as u32, offset as u32, size as u32, mem)
+                    system_api.ic0_msg_arg_data_copy(
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
+                        mem,
+                    )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -491,7 +569,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_msg_method_name_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s as usize).map_err(|e| {
                             anyhow::Error::msg(format!("ic0::msg_metohd_name_size failed: {}", e))
                         })
                     })
@@ -502,13 +580,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_method_name_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::MSG_METHOD_NAME_COPY,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::MSG_METHOD_NAME_COPY,
                         ..Default::default()
@@ -518,14 +596,14 @@ pub(crate) fn syscalls<S: SystemApi>(
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
                     system_api.ic0_msg_method_name_copy(
-                        dst as u32,
-                        offset as u32,
-                        size as u32,
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
                         memory,
                     )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -545,13 +623,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_reply_data_append", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, src: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::MSG_REPLY_DATA_APPEND,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::MSG_REPLY_DATA_APPEND,
                         ..Default::default()
@@ -560,7 +638,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_msg_reply_data_append(src as u32, size as u32, memory)
+                    system_api.ic0_msg_reply_data_append(src.to_usize(), size.to_usize(), memory)
                 })
             }
         })
@@ -584,16 +662,25 @@ pub(crate) fn syscalls<S: SystemApi>(
         })
         .unwrap();
 
+    linker
+        .func_wrap("ic0", "msg_deadline", {
+            move |mut caller: Caller<'_, StoreData<S>>| {
+                with_system_api(&mut caller, |s| s.ic0_msg_deadline())
+                    .map_err(|e| process_err(&mut caller, e))
+            }
+        })
+        .unwrap();
+
     linker
         .func_wrap("ic0", "msg_reject", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, src: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::MSG_REJECT,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::MSG_REJECT,
                         ..Default::default()
@@ -602,7 +689,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_msg_reject(src as u32, size as u32, memory)
+                    system_api.ic0_msg_reject(src.to_usize(), size.to_usize(), memory)
                 })
             }
         })
@@ -614,7 +701,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_msg_reject_msg_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s as usize).map_err(|e| {
                             anyhow::Error::msg(format!("ic0_msg_reject_msg_size failed: {}", e))
                         })
                     })
@@ -625,13 +712,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_reject_msg_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::MSG_REJECT_MSG_COPY,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::MSG_REJECT_MSG_COPY,
                         ..Default::default()
@@ -641,14 +728,14 @@ pub(crate) fn syscalls<S: SystemApi>(
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
                     system_api.ic0_msg_reject_msg_copy(
-                        dst as u32,
-                        offset as u32,
-                        size as u32,
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
                         memory,
                     )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -662,7 +749,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_canister_self_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s).map_err(|e| {
                             anyhow::Error::msg(format!("ic0_canister_self_size failed: {}", e))
                         })
                     })
@@ -673,7 +760,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "canister_self_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -686,14 +773,14 @@ pub(crate) fn syscalls<S: SystemApi>(
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
                     system_api.ic0_canister_self_copy(
-                        dst as u32,
-                        offset as u32,
-                        size as u32,
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
                         memory,
                     )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -707,7 +794,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_system_api(&mut caller, |s| s.ic0_controller_size())
                     .map_err(|e| process_err(&mut caller, e))
                     .and_then(|s| {
-                        i32::try_from(s).map_err(|e| {
+                        I::try_from_usize(s).map_err(|e| {
                             anyhow::Error::msg(format!("ic0_controller_size failed: {}", e))
                         })
                     })
@@ -718,7 +805,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "controller_copy", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: i32, offset: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -730,10 +817,15 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_controller_copy(dst as u32, offset as u32, size as u32, memory)
+                    system_api.ic0_controller_copy(
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
+                        memory,
+                    )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as u32 as usize, size as u32 as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -744,13 +836,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "debug_print", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, offset: i32, length: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, offset: I, length: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::DEBUG_PRINT,
-                    length as u32,
+                    length.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::DEBUG_PRINT,
                         ..Default::default()
@@ -758,6 +850,12 @@ pub(crate) fn syscalls<S: SystemApi>(
                     NumInstructions::from(0),
                     stable_memory_dirty_page_limit,
                 )?;
+                // The message is always recorded in the canister log, even if
+                // printing it is rate limited below.
+                with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.save_log_message(offset.to_usize(), length.to_usize(), memory);
+                    Ok(())
+                })?;
                 match (
                     caller.data().system_api.subnet_type(),
                     feature_flags.rate_limiting_of_debug_prints,
@@ -769,7 +867,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     // debug print produces output.
                     (_, FlagStatus::Disabled) | (SubnetType::System, FlagStatus::Enabled) => {
                         with_memory_and_system_api(&mut caller, |system_api, memory| {
-                            system_api.ic0_debug_print(offset as u32, length as u32, memory)
+                            system_api.ic0_debug_print(offset.to_usize(), length.to_usize(), memory)
                         })
                     }
                 }
@@ -780,13 +878,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "trap", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, offset: i32, length: i32| -> Result<(), _> {
+            move |mut caller: Caller<'_, StoreData<S>>, offset: I, length: I| -> Result<(), _> {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::TRAP,
-                    length as u32,
+                    length.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::TRAP,
                         ..Default::default()
@@ -795,7 +893,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_trap(offset as u32, length as u32, memory)
+                    system_api.ic0_trap(offset.to_usize(), length.to_usize(), memory)
                 })
             }
         })
@@ -805,14 +903,14 @@ pub(crate) fn syscalls<S: SystemApi>(
         .func_wrap("ic0", "call_new", {
             let log = log.clone();
             move |mut caller: Caller<'_, StoreData<S>>,
-                  callee_src: i32,
-                  callee_size: i32,
-                  name_src: i32,
-                  name_len: i32,
-                  reply_fun: i32,
-                  reply_env: i32,
-                  reject_fun: i32,
-                  reject_env: i32| {
+                  callee_src: I,
+                  callee_size: I,
+                  name_src: I,
+                  name_len: I,
+                  reply_fun: I,
+                  reply_env: I,
+                  reject_fun: I,
+                  reject_env: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -823,16 +921,20 @@ pub(crate) fn syscalls<S: SystemApi>(
                     },
                     stable_memory_dirty_page_limit,
                 )?;
+                let reply_fun = closure_arg(&mut caller, reply_fun)?;
+                let reply_env = closure_arg(&mut caller, reply_env)?;
+                let reject_fun = closure_arg(&mut caller, reject_fun)?;
+                let reject_env = closure_arg(&mut caller, reject_env)?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
                     system_api.ic0_call_new(
-                        callee_src as u32,
-                        callee_size as u32,
-                        name_src as u32,
-                        name_len as u32,
-                        reply_fun as u32,
-                        reply_env as u32,
-                        reject_fun as u32,
-                        reject_env as u32,
+                        callee_src.to_usize(),
+                        callee_size.to_usize(),
+                        name_src.to_usize(),
+                        name_len.to_usize(),
+                        reply_fun,
+                        reply_env,
+                        reject_fun,
+                        reject_env,
                         memory,
                     )
                 })
@@ -843,13 +945,13 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "call_data_append", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, src: i32, size: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I| {
                 charge_for_system_api_call(
                     &log,
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::CALL_DATA_APPEND,
-                    size as u32,
+                    size.to_usize(),
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::CALL_DATA_APPEND,
                         ..Default::default()
@@ -858,7 +960,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_call_data_append(src as u32, size as u32, memory)
+                    system_api.ic0_call_data_append(src.to_usize(), size.to_usize(), memory)
                 })
             }
         })
@@ -866,9 +968,20 @@ pub(crate) fn syscalls<S: SystemApi>(
 
     linker
         .func_wrap("ic0", "call_on_cleanup", {
-            move |mut caller: Caller<'_, StoreData<S>>, fun: i32, env: i32| {
+            move |mut caller: Caller<'_, StoreData<S>>, fun: I, env: I| {
+                let fun = closure_arg(&mut caller, fun)?;
+                let env = closure_arg(&mut caller, env)?;
+                with_system_api(&mut caller, |s| s.ic0_call_on_cleanup(fun, env))
+                    .map_err(|e| process_err(&mut caller, e))
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "call_with_best_effort_response", {
+            move |mut caller: Caller<'_, StoreData<S>>, timeout_seconds: u32| {
                 with_system_api(&mut caller, |s| {
-                    s.ic0_call_on_cleanup(fun as u32, env as u32)
+                    s.ic0_call_with_best_effort_response(timeout_seconds)
                 })
                 .map_err(|e| process_err(&mut caller, e))
             }
@@ -961,7 +1074,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::STABLE_READ,
-                    size as u32,
+                    size as u32 as usize,
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::STABLE_READ,
                         ..Default::default()
@@ -995,7 +1108,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::STABLE_WRITE,
-                    size,
+                    size as usize,
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::STABLE_WRITE,
                         stable_dirty_pages,
@@ -1055,7 +1168,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::STABLE64_READ,
-                    size as u32,
+                    size as u32 as usize,
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::STABLE64_READ,
                         ..Default::default()
@@ -1089,7 +1202,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     canister_id,
                     &mut caller,
                     system_api_complexity::overhead::STABLE64_WRITE,
-                    size as u32,
+                    size as u32 as usize,
                     ExecutionComplexity {
                         cpu: system_api_complexity::cpu::STABLE64_WRITE,
                         stable_dirty_pages,
@@ -1174,7 +1287,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "canister_cycle_balance128", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: u32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1186,10 +1299,10 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_canister_cycle_balance128(dst, memory)
+                    system_api.ic0_canister_cycle_balance128(dst.to_usize(), memory)
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as usize, 16)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
                 } else {
                     Ok(())
                 }
@@ -1214,7 +1327,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_cycles_available128", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: u32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1226,10 +1339,10 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_msg_cycles_available128(dst, memory)
+                    system_api.ic0_msg_cycles_available128(dst.to_usize(), memory)
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as usize, 16)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
                 } else {
                     Ok(())
                 }
@@ -1254,7 +1367,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "msg_cycles_refunded128", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, dst: u32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1266,10 +1379,10 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_msg_cycles_refunded128(dst, memory)
+                    system_api.ic0_msg_cycles_refunded128(dst.to_usize(), memory)
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as usize, 16)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
                 } else {
                     Ok(())
                 }
@@ -1292,7 +1405,7 @@ pub(crate) fn syscalls<S: SystemApi>(
             move |mut caller: Caller<'_, StoreData<S>>,
                   amount_high: i64,
                   amount_low: i64,
-                  dst: u32| {
+                  dst: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1306,12 +1419,12 @@ pub(crate) fn syscalls<S: SystemApi>(
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
                     system_api.ic0_msg_cycles_accept128(
                         Cycles::from_parts(amount_high as u64, amount_low as u64),
-                        dst,
+                        dst.to_usize(),
                         memory,
                     )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as usize, 16)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
                 } else {
                     Ok(())
                 }
@@ -1333,15 +1446,43 @@ pub(crate) fn syscalls<S: SystemApi>(
         })
         .unwrap();
 
+    linker
+        .func_wrap("__", "profile_enter", {
+            let log = log.clone();
+            move |mut caller: Caller<'_, StoreData<S>>, func_index: i32| -> Result<(), _> {
+                let global = get_num_instructions_global(&mut caller, &log, canister_id)?;
+                let instruction_counter = load_value(&global, &mut caller, &log, canister_id)?;
+                if let Some(profile) = caller.data_mut().instruction_profile.as_mut() {
+                    profile.enter(func_index as u32, instruction_counter);
+                }
+                Ok(())
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("__", "profile_exit", {
+            let log = log.clone();
+            move |mut caller: Caller<'_, StoreData<S>>, func_index: i32| -> Result<(), _> {
+                let global = get_num_instructions_global(&mut caller, &log, canister_id)?;
+                let instruction_counter = load_value(&global, &mut caller, &log, canister_id)?;
+                if let Some(profile) = caller.data_mut().instruction_profile.as_mut() {
+                    profile.exit(func_index as u32, instruction_counter);
+                }
+                Ok(())
+            }
+        })
+        .unwrap();
+
     linker
         .func_wrap("__", "update_available_memory", {
             move |mut caller: Caller<'_, StoreData<S>>,
-                  native_memory_grow_res: i32,
-                  additional_pages: i32| {
+                  native_memory_grow_res: I,
+                  additional_pages: I| {
                 with_system_api(&mut caller, |s| {
                     s.update_available_memory(
-                        native_memory_grow_res as i64,
-                        additional_pages as u32 as u64,
+                        native_memory_grow_res.as_i64(),
+                        additional_pages.to_usize() as u64,
                     )
                 })
                 .map(|()| native_memory_grow_res)
@@ -1393,7 +1534,7 @@ pub(crate) fn syscalls<S: SystemApi>(
     linker
         .func_wrap("ic0", "certified_data_set", {
             let log = log.clone();
-            move |mut caller: Caller<'_, StoreData<S>>, src: u32, size: u32| {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1405,7 +1546,7 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_certified_data_set(src, size, memory)
+                    system_api.ic0_certified_data_set(src.to_usize(), size.to_usize(), memory)
                 })
             }
         })
@@ -1425,13 +1566,18 @@ pub(crate) fn syscalls<S: SystemApi>(
             move |mut caller: Caller<'_, StoreData<S>>| {
                 with_system_api(&mut caller, |s| s.ic0_data_certificate_size())
                     .map_err(|e| process_err(&mut caller, e))
+                    .and_then(|s| {
+                        I::try_from_usize(s as usize).map_err(|e| {
+                            anyhow::Error::msg(format!("ic0_data_certificate_size failed: {}", e))
+                        })
+                    })
             }
         })
         .unwrap();
 
     linker
         .func_wrap("ic0", "data_certificate_copy", {
-            move |mut caller: Caller<'_, StoreData<S>>, dst: u32, offset: u32, size: u32| {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I, offset: I, size: I| {
                 observe_execution_complexity(
                     &log,
                     canister_id,
@@ -1443,10 +1589,15 @@ pub(crate) fn syscalls<S: SystemApi>(
                     stable_memory_dirty_page_limit,
                 )?;
                 with_memory_and_system_api(&mut caller, |system_api, memory| {
-                    system_api.ic0_data_certificate_copy(dst, offset, size, memory)
+                    system_api.ic0_data_certificate_copy(
+                        dst.to_usize(),
+                        offset.to_usize(),
+                        size.to_usize(),
+                        memory,
+                    )
                 })?;
                 if feature_flags.write_barrier == FlagStatus::Enabled {
-                    mark_writes_on_bytemap(&mut caller, dst as usize, size as usize)
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), size.to_usize())
                 } else {
                     Ok(())
                 }
@@ -1468,6 +1619,106 @@ pub(crate) fn syscalls<S: SystemApi>(
         })
         .unwrap();
 
+    linker
+        .func_wrap("ic0", "is_controller", {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I| {
+                with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.ic0_is_controller(src.to_usize(), size.to_usize(), memory)
+                })
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "in_replicated_execution", {
+            move |mut caller: Caller<'_, StoreData<S>>| {
+                with_system_api(&mut caller, |s| s.ic0_in_replicated_execution())
+                    .map_err(|e| process_err(&mut caller, e))
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "cost_call", {
+            move |mut caller: Caller<'_, StoreData<S>>,
+                  method_name_size: i64,
+                  payload_size: i64,
+                  dst: I| {
+                with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.ic0_cost_call(
+                        method_name_size as u64,
+                        payload_size as u64,
+                        dst.to_usize(),
+                        memory,
+                    )
+                })?;
+                if feature_flags.write_barrier == FlagStatus::Enabled {
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "cost_create_canister", {
+            move |mut caller: Caller<'_, StoreData<S>>, dst: I| {
+                with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.ic0_cost_create_canister(dst.to_usize(), memory)
+                })?;
+                if feature_flags.write_barrier == FlagStatus::Enabled {
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "cost_http_request", {
+            move |mut caller: Caller<'_, StoreData<S>>,
+                  request_size: i64,
+                  max_res_bytes: i64,
+                  dst: I| {
+                with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.ic0_cost_http_request(
+                        request_size as u64,
+                        max_res_bytes as u64,
+                        dst.to_usize(),
+                        memory,
+                    )
+                })?;
+                if feature_flags.write_barrier == FlagStatus::Enabled {
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .unwrap();
+
+    linker
+        .func_wrap("ic0", "cost_sign_with_ecdsa", {
+            move |mut caller: Caller<'_, StoreData<S>>, src: I, size: I, curve: u32, dst: I| {
+                let result = with_memory_and_system_api(&mut caller, |system_api, memory| {
+                    system_api.ic0_cost_sign_with_ecdsa(
+                        src.to_usize(),
+                        size.to_usize(),
+                        curve,
+                        dst.to_usize(),
+                        memory,
+                    )
+                })?;
+                if result == 0 && feature_flags.write_barrier == FlagStatus::Enabled {
+                    mark_writes_on_bytemap(&mut caller, dst.to_usize(), 16)?;
+                }
+                Ok(result)
+            }
+        })
+        .unwrap();
+
     linker
         .func_wrap("__", "internal_trap", {
             move |mut caller: Caller<'_, StoreData<S>>, err_code: i32| -> Result<(), _> {
@@ -55,6 +55,7 @@ fn test_wasmtime_system_api() {
                 MAX_NUM_INSTRUCTIONS,
             ),
             canister_memory_limit,
+            wasm_memory_limit: None,
             compute_allocation: ComputeAllocation::default(),
             subnet_type: SubnetType::Application,
             execution_mode: ExecutionMode::Replicated,
@@ -69,6 +70,7 @@ fn test_wasmtime_system_api() {
         StoreData {
             system_api,
             num_instructions_global: None,
+            instruction_profile: None,
         },
     );
 
@@ -96,7 +98,7 @@ fn test_wasmtime_system_api() {
     let module = Module::new(&engine, instrumentation_output.binary.as_slice())
         .expect("failed to instantiate module");
 
-    let linker = system_api::syscalls(
+    let linker = system_api::syscalls::<_, u32>(
         no_op_logger(),
         canister_id,
         &store,
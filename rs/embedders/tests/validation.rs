@@ -1,5 +1,5 @@
 use assert_matches::assert_matches;
-use ic_config::embedders::Config as EmbeddersConfig;
+use ic_config::{embedders::Config as EmbeddersConfig, flag_status::FlagStatus};
 use ic_embedders::{
     wasm_utils::{
         validate_and_instrument_for_testing,
@@ -872,3 +872,110 @@ fn complex_function_rejected() {
         })
     )
 }
+
+#[test]
+fn can_validate_module_with_simd_instructions() {
+    let wasm = wat2wasm(
+        r#"
+          (module
+            (memory 1)
+            (global $g v128 (v128.const i32x4 1 2 3 4))
+            (func $f (export "canister_update f")
+              (v128.store (i32.const 0)
+                (f32x4.div (v128.load (i32.const 16)) (global.get $g))
+              )
+            )
+          )"#,
+    )
+    .unwrap();
+    assert_matches!(
+        validate_wasm_binary(&wasm, &EmbeddersConfig::default()),
+        Ok(_)
+    );
+}
+
+#[test]
+fn mutable_v128_global_is_rejected() {
+    let wasm = wat2wasm(r#"(module (global (mut v128) (v128.const i64x2 0 0)))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(&wasm, &EmbeddersConfig::default()),
+        Err(WasmValidationError::InvalidGlobalSection(
+            "Global 0 of type v128 must be immutable and not exported.".to_string()
+        ))
+    );
+}
+
+#[test]
+fn exported_v128_global_is_rejected() {
+    let wasm = wat2wasm(r#"(module (global (export "g") v128 (v128.const i64x2 0 0)))"#).unwrap();
+    assert_matches!(
+        validate_wasm_binary(&wasm, &EmbeddersConfig::default()),
+        Err(WasmValidationError::InvalidGlobalSection(_))
+    );
+}
+
+fn wasm64_config() -> EmbeddersConfig {
+    let mut config = EmbeddersConfig::default();
+    config.feature_flags.wasm64 = FlagStatus::Enabled;
+    config
+}
+
+#[test]
+fn wasm64_module_is_rejected_when_disabled() {
+    let wasm = wat2wasm(r#"(module (memory i64 1))"#).unwrap();
+    assert_eq!(
+        validate_wasm_binary(&wasm, &EmbeddersConfig::default()),
+        Err(WasmValidationError::InvalidMemorySection(
+            "64-bit Wasm memories are not supported.".to_string()
+        ))
+    );
+}
+
+#[test]
+fn can_validate_wasm64_module_with_64_bit_system_api() {
+    let wasm = wat2wasm(
+        r#"
+          (module
+            (import "ic0" "msg_arg_data_size" (func $msg_arg_data_size (result i64)))
+            (import "ic0" "msg_arg_data_copy" (func $msg_arg_data_copy (param i64 i64 i64)))
+            (import "ic0" "msg_reply_data_append" (func $msg_reply_data_append (param i64 i64)))
+            (import "ic0" "msg_reply" (func $msg_reply))
+            (memory i64 1)
+            (data (i64.const 100) "data")
+            (func $echo (export "canister_update echo")
+              (call $msg_arg_data_copy (i64.const 0) (i64.const 0) (call $msg_arg_data_size))
+              (call $msg_reply_data_append (i64.const 0) (call $msg_arg_data_size))
+              (call $msg_reply)
+            )
+          )"#,
+    )
+    .unwrap();
+    assert_matches!(validate_wasm_binary(&wasm, &wasm64_config()), Ok(_));
+}
+
+#[test]
+fn wasm64_module_with_32_bit_system_api_is_rejected() {
+    let wasm = wat2wasm(
+        r#"
+          (module
+            (import "ic0" "msg_reply_data_append" (func $msg_reply_data_append (param i32 i32)))
+            (memory i64 1)
+          )"#,
+    )
+    .unwrap();
+    assert_matches!(
+        validate_wasm_binary(&wasm, &wasm64_config()),
+        Err(WasmValidationError::InvalidImportSection(_))
+    );
+}
+
+#[test]
+fn wasm64_module_is_rejected_with_wasm_native_stable_memory() {
+    let wasm = wat2wasm(r#"(module (memory i64 1))"#).unwrap();
+    let mut config = wasm64_config();
+    config.feature_flags.wasm_native_stable_memory = FlagStatus::Enabled;
+    assert_matches!(
+        validate_wasm_binary(&wasm, &config),
+        Err(WasmValidationError::InvalidMemorySection(_))
+    );
+}
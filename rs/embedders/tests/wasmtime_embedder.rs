@@ -1,4 +1,4 @@
-use ic_embedders::wasmtime_embedder::system_api_complexity;
+use ic_embedders::{profiling::FunctionNames, wasmtime_embedder::system_api_complexity};
 use ic_interfaces::execution_environment::SystemApi;
 use ic_replicated_state::Global;
 use ic_test_utilities::{
@@ -284,6 +284,7 @@ mod test {
                 Cycles::zero(),
                 PrincipalId::new_user_test_id(0),
                 0.into(),
+                ic_types::messages::NO_DEADLINE,
             ))
             .build();
 
@@ -328,6 +329,7 @@ mod test {
                 Cycles::zero(),
                 PrincipalId::new_user_test_id(0),
                 0.into(),
+                ic_types::messages::NO_DEADLINE,
             ))
             .with_num_instructions((expected_cpu_complexity as u64 - 1).into())
             .with_subnet_type(subnet_type)
@@ -358,6 +360,7 @@ mod test {
                 Cycles::zero(),
                 PrincipalId::new_user_test_id(0),
                 0.into(),
+                ic_types::messages::NO_DEADLINE,
             ))
             .with_num_instructions((expected_cpu_complexity as u64 - 1).into())
             .with_subnet_type(subnet_type)
@@ -689,6 +692,7 @@ mod test {
                 Cycles::zero(),
                 PrincipalId::new_user_test_id(0),
                 0.into(),
+                ic_types::messages::NO_DEADLINE,
             ))
             .build();
         instance
@@ -717,6 +721,7 @@ mod test {
                 Cycles::zero(),
                 PrincipalId::new_user_test_id(0),
                 0.into(),
+                ic_types::messages::NO_DEADLINE,
             ))
             .build();
         instance
@@ -810,6 +815,36 @@ mod test {
         assert_eq!(err, HypervisorError::CalledTrap("Hello".to_string()));
     }
 
+    #[test]
+    fn wasm64_heap_grow_and_trap() {
+        let wat = r#"
+            (module
+                (import "ic0" "trap" (func $ic_trap (param i64 i64)))
+                (func $test (export "canister_update test")
+                    (drop (memory.grow (i64.const 1)))
+
+                    (i32.store8 (i64.const 65546) (i32.const 72))
+                    (i32.store8 (i64.const 65547) (i32.const 101))
+                    (i32.store8 (i64.const 65548) (i32.const 108))
+                    (i32.store8 (i64.const 65549) (i32.const 108))
+                    (i32.store8 (i64.const 65550) (i32.const 111))
+
+                    (call $ic_trap (i64.const 65546) (i64.const 5))
+                )
+                (memory (export "memory") i64 1)
+            )"#;
+        let mut config = ic_config::embedders::Config::default();
+        config.feature_flags.wasm64 = ic_config::flag_status::FlagStatus::Enabled;
+        let mut instance = WasmtimeInstanceBuilder::new()
+            .with_config(config)
+            .with_wat(wat)
+            .build();
+        let err = instance
+            .run(FuncRef::Method(WasmMethod::Update("test".to_string())))
+            .unwrap_err();
+        assert_eq!(err, HypervisorError::CalledTrap("Hello".to_string()));
+    }
+
     #[test]
     fn multiple_stable_write() {
         let wat = r#"
@@ -1378,4 +1413,71 @@ mod test {
         let err = instance.run(func_ref("test_len_both")).unwrap_err();
         assert_eq!(err, Trapped(StableMemoryOutOfBounds));
     }
+
+    #[test]
+    fn instruction_profile_attributes_instructions_to_call_stacks() {
+        let wat = r#"
+            (module
+                (func $leaf (drop (i32.add (i32.const 1) (i32.const 2))))
+                (func $middle (call $leaf) (call $leaf))
+                (func (export "canister_update run")
+                    (call $middle)
+                    (call $leaf)
+                    (return)
+                )
+                (memory 1)
+            )"#;
+        let mut config = ic_config::embedders::Config::default();
+        config.instruction_profile_output = Some("unused".into());
+        let mut instance = WasmtimeInstanceBuilder::new()
+            .with_config(config)
+            .with_wat(wat)
+            .build();
+        instance
+            .run(FuncRef::Method(WasmMethod::Update("run".to_string())))
+            .unwrap();
+
+        let instructions_executed =
+            DEFAULT_NUM_INSTRUCTIONS.get() as i64 - instance.instruction_counter();
+        let profile = instance.store_data().instruction_profile.as_ref().unwrap();
+        let self_instructions = profile.self_instructions();
+        let (leaf, middle, run) = (0, 1, 2);
+        let mut stacks: Vec<_> = self_instructions.keys().cloned().collect();
+        stacks.sort();
+        assert_eq!(
+            stacks,
+            vec![
+                vec![run],
+                vec![run, leaf],
+                vec![run, middle],
+                vec![run, middle, leaf]
+            ]
+        );
+        // Calling `leaf` twice from `middle` costs twice as much as calling it
+        // once directly.
+        assert_eq!(
+            self_instructions[&vec![run, middle, leaf]],
+            2 * self_instructions[&vec![run, leaf]]
+        );
+        assert_eq!(
+            self_instructions.values().sum::<u64>() as i64,
+            instructions_executed
+        );
+
+        // Only named functions are resolved to their names.
+        let names = FunctionNames::from_wasm(&wat::parse_str(wat).unwrap());
+        let folded: Vec<_> = profile
+            .folded_stacks("canister", &names)
+            .into_keys()
+            .collect();
+        assert_eq!(
+            folded,
+            vec![
+                "canister;func[2]",
+                "canister;func[2];leaf",
+                "canister;func[2];middle",
+                "canister;func[2];middle;leaf",
+            ]
+        );
+    }
 }
@@ -72,6 +72,7 @@ fn test_api_for_update(
             Cycles::zero(),
             caller,
             call_context_test_id(13),
+            ic_types::messages::NO_DEADLINE,
         ),
         static_system_state,
         canister_current_memory_usage,
@@ -82,6 +83,7 @@ fn test_api_for_update(
                 instruction_limit,
             ),
             canister_memory_limit,
+            wasm_memory_limit: None,
             compute_allocation: ComputeAllocation::default(),
             subnet_type: SubnetType::Application,
             execution_mode: ExecutionMode::Replicated,
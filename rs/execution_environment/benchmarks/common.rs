@@ -32,7 +32,7 @@ use ic_test_utilities::{
 };
 use ic_test_utilities_execution_environment::generate_network_topology;
 use ic_types::{
-    messages::{CallbackId, Payload, RejectContext},
+    messages::{CallbackId, Payload, RejectContext, NO_DEADLINE},
     methods::{Callback, WasmClosure},
     Cycles, MemoryAllocation, NumBytes, NumInstructions, Time,
 };
@@ -102,8 +102,11 @@ where
         MemoryAllocation::try_from(NumBytes::from(0)).unwrap();
 
     // Create call context and callback
-    let call_origin =
-        CallOrigin::CanisterUpdate(canister_test_id(REMOTE_CANISTER_ID), CallbackId::new(0));
+    let call_origin = CallOrigin::CanisterUpdate(
+        canister_test_id(REMOTE_CANISTER_ID),
+        CallbackId::new(0),
+        NO_DEADLINE,
+    );
     let call_context_id = canister_state
         .system_state
         .call_context_manager_mut()
@@ -119,6 +122,7 @@ where
         WasmClosure::new(0, 1),
         WasmClosure::new(0, 1),
         None,
+        NO_DEADLINE,
     );
 
     // Create an Ingress message
@@ -144,6 +148,7 @@ where
             MAX_NUM_INSTRUCTIONS,
         ),
         canister_memory_limit: canister_state.memory_limit(NumBytes::new(std::u64::MAX)),
+        wasm_memory_limit: None,
         compute_allocation: canister_state.scheduler_state.compute_allocation,
         subnet_type: hypervisor.subnet_type(),
         execution_mode: ExecutionMode::Replicated,
@@ -1,5 +1,6 @@
 use crate::execution::install_code::{
-    validate_compute_allocation, validate_controller, validate_memory_allocation, OriginalContext,
+    canister_layout, validate_compute_allocation, validate_controller, validate_memory_allocation,
+    validate_reserved_cycles, OriginalContext,
 };
 use crate::execution::{install::execute_install, upgrade::execute_upgrade};
 use crate::execution_environment::{CompilationCostHandling, RoundContext, RoundLimits};
@@ -11,11 +12,13 @@ use crate::{
 };
 use ic_base_types::NumSeconds;
 use ic_config::flag_status::FlagStatus;
-use ic_cycles_account_manager::CyclesAccountManager;
+use ic_cycles_account_manager::{CyclesAccountManager, ResourceSaturation};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
-    CanisterInstallMode, CanisterStatusResultV2, CanisterStatusType, InstallCodeArgs,
-    Method as Ic00Method,
+    CanisterChangeDetails, CanisterChangeOrigin, CanisterInfoResponse, CanisterInstallMode,
+    CanisterSnapshotResponse, CanisterStatusResultV2, CanisterStatusType, ChunkHash,
+    InstallChunkedCodeArgs, InstallCodeArgs, LogVisibility, Method as Ic00Method,
+    StoredChunksReply, UploadChunkReply,
 };
 use ic_interfaces::execution_environment::{
     CanisterOutOfCyclesError, HypervisorError, IngressHistoryWriter, SubnetAvailableMemory,
@@ -25,8 +28,13 @@ use ic_logger::{error, fatal, info, ReplicaLogger};
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
-    CallOrigin, CanisterState, CanisterStatus, NetworkTopology, ReplicatedState, SchedulerState,
-    SystemState,
+    canister_snapshots::{snapshot_id_from_bytes, snapshot_id_to_bytes},
+    canister_state::system_state::{
+        wasm_chunk_store::{self, WasmChunkHash},
+        MAX_CANISTER_HISTORY_CHANGES,
+    },
+    CallOrigin, CanisterSnapshot, CanisterState, CanisterStatus, ExecutionState, Memory,
+    NetworkTopology, ReplicatedState, SchedulerState, SnapshotId, SystemState,
 };
 use ic_system_api::ExecutionParameters;
 use ic_types::messages::{MessageId, SignedIngressContent};
@@ -43,9 +51,12 @@ use ic_wasm_types::CanisterModule;
 use num_traits::cast::ToPrimitive;
 use prometheus::IntCounter;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{collections::BTreeSet, convert::TryFrom, str::FromStr, sync::Arc};
 
+/// The maximum number of snapshots a single canister can have.
+pub(crate) const MAX_SNAPSHOTS_PER_CANISTER: usize = 1;
+
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct InstallCodeResult {
     pub heap_delta: NumBytes,
@@ -94,8 +105,10 @@ pub(crate) enum StopCanisterResult {
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
 pub(crate) struct CanisterMgrConfig {
     pub(crate) subnet_memory_capacity: NumBytes,
+    pub(crate) subnet_memory_threshold: NumBytes,
     pub(crate) default_provisional_cycles_balance: Cycles,
     pub(crate) default_freeze_threshold: NumSeconds,
+    pub(crate) default_reserved_balance_limit: Cycles,
     pub(crate) compute_capacity: u64,
     pub(crate) own_subnet_id: SubnetId,
     pub(crate) own_subnet_type: SubnetType,
@@ -107,8 +120,10 @@ impl CanisterMgrConfig {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         subnet_memory_capacity: NumBytes,
+        subnet_memory_threshold: NumBytes,
         default_provisional_cycles_balance: Cycles,
         default_freeze_threshold: NumSeconds,
+        default_reserved_balance_limit: Cycles,
         own_subnet_id: SubnetId,
         own_subnet_type: SubnetType,
         max_controllers: usize,
@@ -118,8 +133,10 @@ impl CanisterMgrConfig {
     ) -> Self {
         Self {
             subnet_memory_capacity,
+            subnet_memory_threshold,
             default_provisional_cycles_balance,
             default_freeze_threshold,
+            default_reserved_balance_limit,
             own_subnet_id,
             own_subnet_type,
             max_controllers,
@@ -132,7 +149,7 @@ impl CanisterMgrConfig {
 
 #[derive(Clone, Debug)]
 pub struct InstallCodeContext {
-    pub sender: PrincipalId,
+    pub origin: CanisterChangeOrigin,
     pub mode: CanisterInstallMode,
     pub canister_id: CanisterId,
     pub wasm_module: CanisterModule,
@@ -142,7 +159,13 @@ pub struct InstallCodeContext {
     pub query_allocation: QueryAllocation,
 }
 
-/// Errors that can occur when converting from (sender, [`InstallCodeArgs`]) to
+impl InstallCodeContext {
+    pub fn sender(&self) -> PrincipalId {
+        self.origin.origin()
+    }
+}
+
+/// Errors that can occur when converting from (origin, [`InstallCodeArgs`]) to
 /// an [`InstallCodeContext`].
 #[derive(Debug)]
 pub enum InstallCodeContextError {
@@ -207,11 +230,11 @@ impl From<InvalidMemoryAllocationError> for InstallCodeContextError {
     }
 }
 
-impl TryFrom<(PrincipalId, InstallCodeArgs)> for InstallCodeContext {
+impl TryFrom<(CanisterChangeOrigin, InstallCodeArgs)> for InstallCodeContext {
     type Error = InstallCodeContextError;
 
-    fn try_from(input: (PrincipalId, InstallCodeArgs)) -> Result<Self, Self::Error> {
-        let (sender, args) = input;
+    fn try_from(input: (CanisterChangeOrigin, InstallCodeArgs)) -> Result<Self, Self::Error> {
+        let (origin, args) = input;
         let canister_id = CanisterId::new(args.canister_id).map_err(|err| {
             InstallCodeContextError::InvalidCanisterId(format!(
                 "Converting canister id {} failed with {}",
@@ -241,7 +264,7 @@ impl TryFrom<(PrincipalId, InstallCodeArgs)> for InstallCodeContext {
         let query_allocation = QueryAllocation::default();
 
         Ok(InstallCodeContext {
-            sender,
+            origin,
             mode: args.mode,
             canister_id,
             wasm_module: CanisterModule::new(args.wasm_module),
@@ -279,6 +302,22 @@ impl CanisterManager {
         }
     }
 
+    /// Returns the saturation of the subnet memory given the memory that is
+    /// still available on the subnet.
+    pub(crate) fn subnet_memory_saturation(
+        &self,
+        subnet_available_memory: &SubnetAvailableMemory,
+    ) -> ResourceSaturation {
+        let subnet_memory_capacity = self.config.subnet_memory_capacity.get();
+        let subnet_memory_usage = subnet_memory_capacity
+            .saturating_sub(subnet_available_memory.get_total_memory().max(0) as u64);
+        ResourceSaturation::new(
+            subnet_memory_usage,
+            self.config.subnet_memory_threshold.get(),
+            subnet_memory_capacity,
+        )
+    }
+
     /// Checks if a given ingress message directed to the management canister
     /// should be accepted or not.
     pub(crate) fn should_accept_ingress_message(
@@ -297,6 +336,7 @@ impl CanisterManager {
             // The method is either invalid or it is of a type that users
             // are not allowed to send.
             Err(_)
+            | Ok(Ic00Method::CanisterInfo)
             | Ok(Ic00Method::CreateCanister)
             | Ok(Ic00Method::ECDSAPublicKey)
             | Ok(Ic00Method::SetupInitialDKG)
@@ -318,6 +358,14 @@ impl CanisterManager {
                 format!("Only canisters can call ic00 method {}", method_name),
             )),
 
+            // `fetch_canister_logs` can only be called as a query.
+            Ok(Ic00Method::FetchCanisterLogs) => Err(UserError::new(
+                ErrorCode::CanisterRejectedMessage,
+                format!(
+                    "ic00 method {} can only be called as a non-replicated query",
+                    method_name
+                ),
+            )),
 
             // These methods are only valid if they are sent by the controller
             // of the canister. We assume that the canister always wants to
@@ -329,7 +377,15 @@ impl CanisterManager {
             | Ok(Ic00Method::DeleteCanister) |
             Ok(Ic00Method::UpdateSettings)|
             Ok(Ic00Method::InstallCode) |
-            Ok(Ic00Method::SetController) => {
+            Ok(Ic00Method::SetController) |
+            Ok(Ic00Method::TakeCanisterSnapshot) |
+            Ok(Ic00Method::LoadCanisterSnapshot) |
+            Ok(Ic00Method::ListCanisterSnapshots) |
+            Ok(Ic00Method::DeleteCanisterSnapshot) |
+            Ok(Ic00Method::UploadChunk) |
+            Ok(Ic00Method::ClearChunkStore) |
+            Ok(Ic00Method::StoredChunks) |
+            Ok(Ic00Method::InstallChunkedCode) => {
                 match effective_canister_id {
                     Some(canister_id) => {
                         let canister = state.canister_state(&canister_id).ok_or_else(|| UserError::new(
@@ -450,19 +506,38 @@ impl CanisterManager {
         if let Some(freezing_threshold) = settings.freezing_threshold {
             canister.system_state.freeze_threshold = freezing_threshold;
         }
+        if let Some(log_visibility) = settings.log_visibility {
+            canister.system_state.log_visibility = log_visibility;
+        }
+        if let Some(wasm_memory_limit) = settings.wasm_memory_limit {
+            // A limit of zero removes the limit.
+            canister.system_state.wasm_memory_limit = if wasm_memory_limit.get() == 0 {
+                None
+            } else {
+                Some(wasm_memory_limit)
+            };
+        }
+        if let Some(wasm_memory_threshold) = settings.wasm_memory_threshold {
+            canister.system_state.wasm_memory_threshold = wasm_memory_threshold;
+        }
+        if let Some(reserved_cycles_limit) = settings.reserved_cycles_limit {
+            canister.system_state.reserved_balance_limit = Some(reserved_cycles_limit);
+        }
     }
 
     /// Tries to apply the requested settings on the canister identified by
     /// `canister_id`.
     pub(crate) fn update_settings(
         &self,
-        sender: PrincipalId,
+        timestamp: Time,
+        origin: CanisterChangeOrigin,
         settings: CanisterSettings,
         canister: &mut CanisterState,
         round_limits: &mut RoundLimits,
+        subnet_size: usize,
     ) -> Result<(), CanisterManagerError> {
         // Verify controller.
-        validate_controller(canister, &sender)?;
+        validate_controller(canister, &origin.origin())?;
         validate_compute_allocation(
             round_limits.compute_allocation_used,
             canister,
@@ -486,8 +561,37 @@ impl CanisterManager {
             .bytes()
             .max(old_usage);
         let old_compute_allocation = canister.scheduler_state.compute_allocation.as_percent();
+        let controllers_changed =
+            validated_settings.controller.is_some() || validated_settings.controllers.is_some();
+
+        let reservation_cycles = match validated_settings.memory_allocation {
+            Some(memory_allocation) if memory_allocation.bytes().max(old_usage) > old_mem => {
+                let allocated_bytes = memory_allocation.bytes().max(old_usage) - old_mem;
+                self.cycles_account_manager.storage_reservation_cycles(
+                    allocated_bytes,
+                    &self.subnet_memory_saturation(&round_limits.subnet_available_memory),
+                    subnet_size,
+                )
+            }
+            _ => Cycles::zero(),
+        };
+        let reserved_balance_limit = validated_settings
+            .reserved_cycles_limit
+            .or(canister.system_state.reserved_balance_limit);
+        validate_reserved_cycles(
+            canister,
+            validated_settings.memory_allocation,
+            validated_settings.reserved_cycles_limit,
+            reservation_cycles,
+            reserved_balance_limit,
+        )?;
 
         self.do_update_settings(validated_settings, canister);
+        // The reservation was validated above, so it cannot fail here.
+        canister
+            .system_state
+            .reserve_cycles(reservation_cycles)
+            .expect("Failed to reserve cycles for a validated memory allocation");
 
         let new_compute_allocation = canister.scheduler_state.compute_allocation.as_percent();
         if old_compute_allocation < new_compute_allocation {
@@ -520,6 +624,14 @@ impl CanisterManager {
         }
 
         canister.system_state.canister_version += 1;
+        if controllers_changed {
+            let controllers = canister.controllers().iter().copied().collect();
+            canister.system_state.add_canister_change(
+                timestamp,
+                origin,
+                CanisterChangeDetails::controllers_change(controllers),
+            );
+        }
 
         Ok(())
     }
@@ -529,7 +641,7 @@ impl CanisterManager {
     /// Returns the auto-generated id the new canister that has been created.
     pub(crate) fn create_canister(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         sender_subnet_id: SubnetId,
         cycles: Cycles,
         settings: CanisterSettings,
@@ -573,7 +685,7 @@ impl CanisterManager {
             Err(err) => (Err(err), cycles),
             Ok(validate_settings) => {
                 let canister_id = match self.create_canister_helper(
-                    sender,
+                    origin,
                     cycles,
                     fee,
                     validate_settings,
@@ -697,7 +809,7 @@ impl CanisterManager {
         execution_refund_error_counter: &IntCounter,
         subnet_size: usize,
     ) -> DtsInstallCodeResult {
-        if let Err(err) = validate_controller(&canister, &context.sender) {
+        if let Err(err) = validate_controller(&canister, &context.sender()) {
             return DtsInstallCodeResult::Finished {
                 canister,
                 message,
@@ -742,7 +854,7 @@ impl CanisterManager {
             subnet_size,
             requested_compute_allocation: context.compute_allocation,
             requested_memory_allocation: context.memory_allocation,
-            sender: context.sender,
+            origin: context.origin.clone(),
             canister_id: canister.canister_id(),
         };
 
@@ -753,6 +865,8 @@ impl CanisterManager {
             execution_refund_error_counter,
             log: &self.log,
             time,
+            subnet_memory_saturation: self
+                .subnet_memory_saturation(&round_limits.subnet_available_memory),
         };
 
         match context.mode {
@@ -770,10 +884,11 @@ impl CanisterManager {
     /// See https://sdk.dfinity.org/docs/interface-spec/index.html#ic-uninstall_code
     pub(crate) fn uninstall_code(
         &self,
+        origin: CanisterChangeOrigin,
         canister_id: CanisterId,
-        sender: PrincipalId,
         state: &mut ReplicatedState,
     ) -> Result<(), CanisterManagerError> {
+        let sender = origin.origin();
         let time = state.time();
         let canister = match state.canister_state_mut(&canister_id) {
             Some(canister) => canister,
@@ -787,7 +902,12 @@ impl CanisterManager {
             validate_controller(canister, &sender)?
         }
 
-        let rejects = uninstall_canister(&self.log, canister, time);
+        let rejects = uninstall_canister(
+            &self.log,
+            canister,
+            time,
+            AddCanisterChangeToHistory::Yes(origin),
+        );
         crate::util::process_responses(
             rejects,
             state,
@@ -908,6 +1028,33 @@ impl CanisterManager {
         Ok(stop_contexts)
     }
 
+    /// Returns the controllers, the module hash and up to
+    /// `num_requested_changes` most recent changes of the canister. Unlike
+    /// `canister_status`, this information is available to any canister.
+    pub(crate) fn get_canister_info(
+        &self,
+        num_requested_changes: Option<u64>,
+        canister: &CanisterState,
+    ) -> CanisterInfoResponse {
+        let canister_history = canister.system_state.get_canister_history();
+        let num_requested_changes = num_requested_changes.unwrap_or(0);
+        let changes = canister_history
+            .get_changes(num_requested_changes.min(MAX_CANISTER_HISTORY_CHANGES as u64) as usize)
+            .cloned()
+            .collect();
+        let module_hash = canister
+            .execution_state
+            .as_ref()
+            .map(|execution_state| execution_state.wasm_binary.binary.module_hash().to_vec());
+        let controllers = canister.controllers().iter().copied().collect();
+        CanisterInfoResponse::new(
+            canister_history.get_total_num_changes(),
+            changes,
+            module_hash,
+            controllers,
+        )
+    }
+
     /// Fetches the current status of the canister.
     pub(crate) fn get_canister_status(
         &self,
@@ -954,6 +1101,27 @@ impl CanisterManager {
                     subnet_size,
                 )
                 .get(),
+        )
+        .with_wasm_memory_limit(canister.system_state.wasm_memory_limit)
+        .with_wasm_memory_threshold(canister.system_state.wasm_memory_threshold)
+        .with_reserved_cycles(
+            canister.system_state.reserved_balance().get(),
+            canister
+                .system_state
+                .reserved_balance_limit
+                .map(|limit| limit.get()),
+        )
+        .with_query_stats(
+            canister.scheduler_state.total_query_stats.num_calls,
+            canister.scheduler_state.total_query_stats.num_instructions,
+            canister
+                .scheduler_state
+                .total_query_stats
+                .ingress_payload_size,
+            canister
+                .scheduler_state
+                .total_query_stats
+                .egress_payload_size,
         ))
     }
 
@@ -961,12 +1129,14 @@ impl CanisterManager {
     /// the canister is able to run this, otherwise an error is returned.
     pub(crate) fn set_controller(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         canister_id: CanisterId,
         new_controller: PrincipalId,
         state: &mut ReplicatedState,
         round_limits: &mut RoundLimits,
+        subnet_size: usize,
     ) -> Result<(), CanisterManagerError> {
+        let time = state.time();
         let canister = state
             .canister_state_mut(&canister_id)
             .ok_or(CanisterManagerError::CanisterNotFound(canister_id))?;
@@ -974,7 +1144,7 @@ impl CanisterManager {
         let settings = CanisterSettingsBuilder::new()
             .with_controller(new_controller)
             .build();
-        self.update_settings(sender, settings, canister, round_limits)
+        self.update_settings(time, origin, settings, canister, round_limits, subnet_size)
     }
 
     /// Permanently deletes a canister from `ReplicatedState`.
@@ -1020,6 +1190,10 @@ impl CanisterManager {
 
         // Take out the canister from `ReplicatedState`.
         let canister_to_delete = state.take_canister_state(&canister_id_to_delete).unwrap();
+        // Snapshots of a deleted canister can never be loaded again.
+        state
+            .canister_snapshots
+            .delete_snapshots(canister_id_to_delete);
         // Leftover cycles in the balance are considered `consumed`.
         let consumed_cycles_by_canister_to_delete =
             NominalCycles::from(canister_to_delete.system_state.balance())
@@ -1038,6 +1212,383 @@ impl CanisterManager {
         Ok(())
     }
 
+    /// Takes a snapshot of the given canister and stores it in the
+    /// `ReplicatedState`.
+    ///
+    /// Only the controllers of the canister can take a snapshot. A canister
+    /// can have at most `MAX_SNAPSHOTS_PER_CANISTER` snapshots; taking another
+    /// one requires replacing an existing snapshot via `replace_snapshot`.
+    pub(crate) fn take_canister_snapshot(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        replace_snapshot: Option<&[u8]>,
+        state: &mut ReplicatedState,
+        round_limits: &mut RoundLimits,
+    ) -> Result<CanisterSnapshotResponse, CanisterManagerError> {
+        let time = state.time();
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        let replace_snapshot = match replace_snapshot {
+            Some(snapshot_id) => {
+                Some(self.validate_snapshot_belongs_to_canister(state, canister_id, snapshot_id)?)
+            }
+            None => {
+                if state.canister_snapshots.list_snapshots(canister_id).len()
+                    >= MAX_SNAPSHOTS_PER_CANISTER
+                {
+                    return Err(CanisterManagerError::CanisterSnapshotLimitExceeded {
+                        canister_id,
+                        limit: MAX_SNAPSHOTS_PER_CANISTER,
+                    });
+                }
+                None
+            }
+        };
+
+        let snapshot = CanisterSnapshot::from_canister(canister, time).ok_or(
+            CanisterManagerError::CanisterSnapshotCanisterEmpty(canister_id),
+        )?;
+
+        // A replaced snapshot frees its memory, so only the difference to the
+        // new snapshot is charged.
+        let snapshot_size = snapshot.size();
+        let replaced_size = replace_snapshot
+            .and_then(|snapshot_id| state.canister_snapshots.get(snapshot_id))
+            .map_or(NumBytes::from(0), |replaced| replaced.size());
+        let requested = snapshot_size - snapshot_size.min(replaced_size);
+        if round_limits
+            .subnet_available_memory
+            .try_decrement(requested, NumBytes::from(0))
+            .is_err()
+        {
+            return Err(CanisterManagerError::SubnetMemoryCapacityOverSubscribed {
+                requested,
+                available: NumBytes::from(
+                    round_limits
+                        .subnet_available_memory
+                        .get_total_memory()
+                        .max(0) as u64,
+                ),
+            });
+        }
+
+        if let Some(snapshot_id) = replace_snapshot {
+            state.canister_snapshots.remove(snapshot_id);
+            round_limits.subnet_available_memory.increment(
+                replaced_size - replaced_size.min(snapshot_size),
+                NumBytes::from(0),
+            );
+        }
+
+        let snapshot_id = SnapshotId::new(state.metadata.next_snapshot_id);
+        state.metadata.next_snapshot_id += 1;
+        state
+            .canister_snapshots
+            .push(snapshot_id, Arc::new(snapshot));
+
+        Ok(CanisterSnapshotResponse::new(
+            snapshot_id_to_bytes(snapshot_id),
+            time.as_nanos_since_unix_epoch(),
+            snapshot_size.get(),
+        ))
+    }
+
+    /// Replaces the Wasm module, memories, globals and certified data of the
+    /// given canister with the ones stored in the snapshot.
+    ///
+    /// Only the controllers of the canister can load a snapshot and the
+    /// canister must be stopped. The load is recorded in the canister history
+    /// as a reinstall of the snapshot's module.
+    pub(crate) fn load_canister_snapshot(
+        &self,
+        origin: CanisterChangeOrigin,
+        canister_id: CanisterId,
+        snapshot_id: &[u8],
+        state: &mut ReplicatedState,
+        round_limits: &mut RoundLimits,
+    ) -> Result<(), CanisterManagerError> {
+        let sender = origin.origin();
+        let time = state.time();
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+        if canister.status() != CanisterStatusType::Stopped {
+            return Err(CanisterManagerError::LoadCanisterSnapshotNotStopped(
+                canister_id,
+            ));
+        }
+
+        let snapshot_id =
+            self.validate_snapshot_belongs_to_canister(state, canister_id, snapshot_id)?;
+        let snapshot = Arc::clone(state.canister_snapshots.get(snapshot_id).unwrap());
+
+        let canister = state.canister_state_mut(&canister_id).unwrap();
+        let old_usage = canister.memory_usage(self.config.own_subnet_type);
+
+        let canister_root = match canister.execution_state.as_ref() {
+            Some(execution_state) => execution_state.canister_root.clone(),
+            None => canister_layout(Path::new("NOT_USED"), &canister_id).raw_path(),
+        };
+        // The memories are wrapped into new `Memory` objects so that the
+        // canister does not share sandbox memory handles with the snapshot.
+        let execution_state = ExecutionState::new(
+            canister_root,
+            Arc::clone(&snapshot.wasm_binary),
+            snapshot.exports.clone(),
+            Memory::new(
+                snapshot.wasm_memory.page_map.clone(),
+                snapshot.wasm_memory.size,
+            ),
+            Memory::new(
+                snapshot.stable_memory.page_map.clone(),
+                snapshot.stable_memory.size,
+            ),
+            snapshot.exported_globals.clone(),
+            snapshot.metadata.clone(),
+        );
+
+        let new_usage = execution_state.memory_usage();
+        if let MemoryAllocation::Reserved(bytes) = canister.memory_allocation() {
+            if new_usage > bytes {
+                return Err(CanisterManagerError::NotEnoughMemoryAllocationGiven {
+                    canister_id,
+                    memory_allocation_given: canister.memory_allocation(),
+                    memory_usage_needed: new_usage,
+                });
+            }
+        } else if new_usage > old_usage
+            && round_limits
+                .subnet_available_memory
+                .try_decrement(new_usage - old_usage, NumBytes::from(0))
+                .is_err()
+        {
+            return Err(CanisterManagerError::SubnetMemoryCapacityOverSubscribed {
+                requested: new_usage - old_usage,
+                available: NumBytes::from(
+                    round_limits
+                        .subnet_available_memory
+                        .get_total_memory()
+                        .max(0) as u64,
+                ),
+            });
+        }
+
+        canister.execution_state = Some(execution_state);
+        canister.system_state.certified_data = snapshot.certified_data().clone();
+        // The timer of the current module must not fire on the restored one.
+        canister.system_state.global_timer = CanisterTimer::Inactive;
+        canister.system_state.canister_version += 1;
+        canister.system_state.add_canister_change(
+            time,
+            origin,
+            CanisterChangeDetails::code_deployment(
+                CanisterInstallMode::Reinstall,
+                snapshot.wasm_binary.binary.module_hash(),
+            ),
+        );
+
+        Ok(())
+    }
+
+    /// Lists the snapshots of the given canister.
+    pub(crate) fn list_canister_snapshots(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        state: &ReplicatedState,
+    ) -> Result<Vec<CanisterSnapshotResponse>, CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        Ok(state
+            .canister_snapshots
+            .list_snapshots(canister_id)
+            .into_iter()
+            .map(|(snapshot_id, snapshot)| {
+                CanisterSnapshotResponse::new(
+                    snapshot_id_to_bytes(snapshot_id),
+                    snapshot.taken_at_timestamp().as_nanos_since_unix_epoch(),
+                    snapshot.size().get(),
+                )
+            })
+            .collect())
+    }
+
+    /// Deletes the given snapshot of the canister.
+    pub(crate) fn delete_canister_snapshot(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        snapshot_id: &[u8],
+        state: &mut ReplicatedState,
+    ) -> Result<(), CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        let snapshot_id =
+            self.validate_snapshot_belongs_to_canister(state, canister_id, snapshot_id)?;
+        state.canister_snapshots.remove(snapshot_id);
+        Ok(())
+    }
+
+    /// Stores the given chunk in the Wasm chunk store of the canister and
+    /// returns its hash.
+    ///
+    /// Only the controllers of the canister can upload chunks. The chunk is
+    /// accounted like canister memory, so it has to fit into the memory
+    /// allocation of the canister or the available subnet memory.
+    pub(crate) fn upload_chunk(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        chunk: Vec<u8>,
+        state: &mut ReplicatedState,
+        round_limits: &mut RoundLimits,
+    ) -> Result<UploadChunkReply, CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        let new_bytes = canister
+            .system_state
+            .wasm_chunk_store
+            .can_insert_chunk(wasm_chunk_store::DEFAULT_MAX_SIZE, &chunk)
+            .map_err(|err| CanisterManagerError::WasmChunkStoreError {
+                message: err.to_string(),
+            })?;
+
+        if let MemoryAllocation::Reserved(bytes) = canister.memory_allocation() {
+            let memory_usage_needed =
+                canister.memory_usage(self.config.own_subnet_type) + new_bytes;
+            if memory_usage_needed > bytes {
+                return Err(CanisterManagerError::NotEnoughMemoryAllocationGiven {
+                    canister_id,
+                    memory_allocation_given: canister.memory_allocation(),
+                    memory_usage_needed,
+                });
+            }
+        } else if round_limits
+            .subnet_available_memory
+            .try_decrement(new_bytes, NumBytes::from(0))
+            .is_err()
+        {
+            return Err(CanisterManagerError::SubnetMemoryCapacityOverSubscribed {
+                requested: new_bytes,
+                available: NumBytes::from(
+                    round_limits
+                        .subnet_available_memory
+                        .get_total_memory()
+                        .max(0) as u64,
+                ),
+            });
+        }
+
+        let canister = state.canister_state_mut(&canister_id).unwrap();
+        let hash = canister
+            .system_state
+            .wasm_chunk_store
+            .insert_chunk(wasm_chunk_store::DEFAULT_MAX_SIZE, chunk)
+            .map_err(|err| CanisterManagerError::WasmChunkStoreError {
+                message: err.to_string(),
+            })?;
+        Ok(UploadChunkReply {
+            hash: hash.to_vec(),
+        })
+    }
+
+    /// Removes all chunks from the Wasm chunk store of the canister.
+    pub(crate) fn clear_chunk_store(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        state: &mut ReplicatedState,
+    ) -> Result<(), CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        let canister = state.canister_state_mut(&canister_id).unwrap();
+        canister.system_state.wasm_chunk_store.clear();
+        Ok(())
+    }
+
+    /// Lists the hashes of the chunks in the Wasm chunk store of the canister.
+    pub(crate) fn stored_chunks(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        state: &ReplicatedState,
+    ) -> Result<StoredChunksReply, CanisterManagerError> {
+        let canister = self.validate_canister_exists(state, canister_id)?;
+        validate_controller(canister, &sender)?;
+
+        Ok(StoredChunksReply(
+            canister
+                .system_state
+                .wasm_chunk_store
+                .keys()
+                .map(|hash| ChunkHash {
+                    hash: hash.to_vec(),
+                })
+                .collect(),
+        ))
+    }
+
+    /// Assembles the Wasm module of an `install_chunked_code` message from the
+    /// chunk store of the store canister and returns the context for
+    /// installing it on the target canister.
+    ///
+    /// The sender must control the store canister, which has to be on this
+    /// subnet. Controllership of the target canister is checked later by the
+    /// regular `install_code` validation.
+    pub(crate) fn install_chunked_code_context(
+        &self,
+        origin: CanisterChangeOrigin,
+        args: InstallChunkedCodeArgs,
+        state: &ReplicatedState,
+    ) -> Result<InstallCodeContext, CanisterManagerError> {
+        let store_canister_id = args.store_canister_id();
+        let store_canister = self.validate_canister_exists(state, store_canister_id)?;
+        validate_controller(store_canister, &origin.origin())?;
+
+        let store = &store_canister.system_state.wasm_chunk_store;
+        let mut wasm_module = Vec::new();
+        for chunk_hash in args.chunk_hashes_list.iter() {
+            let chunk = WasmChunkHash::try_from(chunk_hash.hash.as_slice())
+                .ok()
+                .and_then(|hash| store.get_chunk(&hash))
+                .ok_or_else(|| CanisterManagerError::WasmChunkStoreError {
+                    message: format!(
+                        "Chunk {} not found in the Wasm chunk store of canister {}",
+                        hex::encode(&chunk_hash.hash),
+                        store_canister_id
+                    ),
+                })?;
+            wasm_module.extend_from_slice(chunk);
+        }
+
+        let wasm_module_hash = wasm_chunk_store::hash_chunk(&wasm_module);
+        if wasm_module_hash[..] != args.wasm_module_hash[..] {
+            return Err(CanisterManagerError::WasmChunkStoreError {
+                message: format!(
+                    "Wasm module hash {} does not match the hash {} of the assembled module",
+                    hex::encode(&args.wasm_module_hash),
+                    hex::encode(wasm_module_hash)
+                ),
+            });
+        }
+
+        Ok(InstallCodeContext {
+            origin,
+            mode: args.mode,
+            canister_id: args.target_canister_id(),
+            wasm_module: CanisterModule::new(wasm_module),
+            arg: args.arg,
+            compute_allocation: None,
+            memory_allocation: None,
+            query_allocation: QueryAllocation::default(),
+        })
+    }
+
     /// Creates a new canister with the cycles amount specified and inserts it
     /// into `ReplicatedState`.
     ///
@@ -1047,7 +1598,7 @@ impl CanisterManager {
     /// Returns the auto-generated id the new canister that has been created.
     pub(crate) fn create_canister_with_cycles(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         cycles_amount: Option<u128>,
         settings: CanisterSettings,
         specified_id: Option<PrincipalId>,
@@ -1056,6 +1607,7 @@ impl CanisterManager {
         max_number_of_canisters: u64,
         round_limits: &mut RoundLimits,
     ) -> Result<CanisterId, CanisterManagerError> {
+        let sender = origin.origin();
         if !provisional_whitelist.contains(&sender) {
             return Err(CanisterManagerError::SenderNotInWhitelist(sender));
         }
@@ -1074,7 +1626,7 @@ impl CanisterManager {
         ) {
             Err(err) => Err(err),
             Ok(validated_settings) => self.create_canister_helper(
-                sender,
+                origin,
                 cycles,
                 Cycles::new(0),
                 validated_settings,
@@ -1122,7 +1674,7 @@ impl CanisterManager {
 
     fn create_canister_helper(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         cycles: Cycles,
         creation_fee: Cycles,
         settings: ValidatedCanisterSettings,
@@ -1151,18 +1703,26 @@ impl CanisterManager {
         let cycles = cycles - creation_fee;
 
         // Canister id available. Create the new canister.
+        let sender = origin.origin();
         let mut system_state = SystemState::new_running(
             new_canister_id,
             sender,
             cycles,
             self.config.default_freeze_threshold,
         );
+        system_state.reserved_balance_limit = Some(self.config.default_reserved_balance_limit);
 
         system_state.observe_consumed_cycles(creation_fee);
         let scheduler_state = SchedulerState::new(state.metadata.batch_time);
         let mut new_canister = CanisterState::new(system_state, None, scheduler_state);
 
         self.do_update_settings(settings, &mut new_canister);
+        let controllers = new_canister.controllers().iter().copied().collect();
+        new_canister.system_state.add_canister_change(
+            state.time(),
+            origin,
+            CanisterChangeDetails::canister_creation(controllers),
+        );
         let new_usage = new_canister.memory_usage(self.config.own_subnet_type);
         let new_mem = new_canister
             .system_state
@@ -1253,6 +1813,25 @@ impl CanisterManager {
         Ok(canister_id)
     }
 
+    /// Decodes the snapshot id and checks that it refers to a snapshot of the
+    /// given canister.
+    fn validate_snapshot_belongs_to_canister(
+        &self,
+        state: &ReplicatedState,
+        canister_id: CanisterId,
+        snapshot_id: &[u8],
+    ) -> Result<SnapshotId, CanisterManagerError> {
+        let not_found = || CanisterManagerError::CanisterSnapshotNotFound {
+            canister_id,
+            snapshot_id: snapshot_id.to_vec(),
+        };
+        let id = snapshot_id_from_bytes(snapshot_id).ok_or_else(not_found)?;
+        match state.canister_snapshots.get(id) {
+            Some(snapshot) if snapshot.canister_id() == canister_id => Ok(id),
+            _ => Err(not_found()),
+        }
+    }
+
     fn validate_canister_exists<'a>(
         &self,
         state: &'a ReplicatedState,
@@ -1311,6 +1890,33 @@ pub(crate) enum CanisterManagerError {
     CanisterNotHostedBySubnet {
         message: String,
     },
+    CanisterSnapshotNotFound {
+        canister_id: CanisterId,
+        snapshot_id: Vec<u8>,
+    },
+    CanisterSnapshotLimitExceeded {
+        canister_id: CanisterId,
+        limit: usize,
+    },
+    CanisterSnapshotCanisterEmpty(CanisterId),
+    LoadCanisterSnapshotNotStopped(CanisterId),
+    WasmChunkStoreError {
+        message: String,
+    },
+    InsufficientCyclesInMemoryAllocation {
+        memory_allocation: MemoryAllocation,
+        available: Cycles,
+        requested: Cycles,
+    },
+    ReservedCyclesLimitExceededInMemoryAllocation {
+        memory_allocation: MemoryAllocation,
+        requested: Cycles,
+        limit: Cycles,
+    },
+    ReservedCyclesLimitIsTooLow {
+        cycles: Cycles,
+        limit: Cycles,
+    },
 }
 
 impl From<CanisterManagerError> for UserError {
@@ -1467,6 +2073,69 @@ impl From<CanisterManagerError> for UserError {
                     format!("Unsuccessful validation of specified ID: {}", message),
                 )
             }
+            CanisterSnapshotNotFound { canister_id, snapshot_id } => {
+                Self::new(
+                    ErrorCode::CanisterSnapshotNotFound,
+                    format!("Could not find the snapshot ID {} for canister {}.", hex::encode(snapshot_id), canister_id),
+                )
+            }
+            CanisterSnapshotLimitExceeded { canister_id, limit } => {
+                Self::new(
+                    ErrorCode::CanisterSnapshotLimitExceeded,
+                    format!("Canister {} has reached the maximum number of {} snapshots. Use `replace_snapshot` to replace an existing snapshot.", canister_id, limit),
+                )
+            }
+            CanisterSnapshotCanisterEmpty(canister_id) => {
+                Self::new(
+                    ErrorCode::CanisterWasmModuleNotFound,
+                    format!("Cannot take a snapshot of canister {} because it has no Wasm module installed.", canister_id),
+                )
+            }
+            LoadCanisterSnapshotNotStopped(canister_id) => {
+                Self::new(
+                    ErrorCode::CanisterNotStopped,
+                    format!(
+                        "Canister {} must be stopped before a snapshot is loaded.",
+                        canister_id,
+                    )
+                )
+            }
+            WasmChunkStoreError { message } => {
+                Self::new(
+                    ErrorCode::CanisterContractViolation,
+                    format!("Error from Wasm chunk store: {}", message),
+                )
+            }
+            InsufficientCyclesInMemoryAllocation { memory_allocation, available, requested } => {
+                Self::new(
+                    ErrorCode::CanisterOutOfCycles,
+                    format!(
+                        "Cannot increase memory allocation to {} due to insufficient cycles. At least {} additional cycles are required.",
+                        memory_allocation.bytes(),
+                        requested - available,
+                    )
+                )
+            }
+            ReservedCyclesLimitExceededInMemoryAllocation { memory_allocation, requested, limit } => {
+                Self::new(
+                    ErrorCode::ReservedCyclesLimitExceeded,
+                    format!(
+                        "Cannot increase memory allocation to {} due to its reserved cycles limit. The current limit ({}) would be exceeded by {}.",
+                        memory_allocation.bytes(),
+                        limit,
+                        requested - limit,
+                    )
+                )
+            }
+            ReservedCyclesLimitIsTooLow { cycles, limit } => {
+                Self::new(
+                    ErrorCode::ReservedCyclesLimitExceeded,
+                    format!(
+                        "Cannot set the reserved cycles limit {} below the reserved cycles balance of the canister {}.",
+                        limit, cycles,
+                    )
+                )
+            }
         }
     }
 }
@@ -1484,6 +2153,14 @@ impl From<CanisterManagerError> for RejectContext {
     }
 }
 
+/// Indicates whether `uninstall_canister` should record the uninstallation in
+/// the canister history.
+#[doc(hidden)]
+pub enum AddCanisterChangeToHistory {
+    Yes(CanisterChangeOrigin),
+    No,
+}
+
 /// Uninstalls a canister.
 ///
 /// See https://sdk.dfinity.org/docs/interface-spec/index.html#ic-uninstall_code
@@ -1494,6 +2171,7 @@ pub fn uninstall_canister(
     log: &ReplicaLogger,
     canister: &mut CanisterState,
     time: Time,
+    add_canister_change: AddCanisterChangeToHistory,
 ) -> Vec<Response> {
     // Drop the canister's execution state.
     canister.execution_state = None;
@@ -1501,10 +2179,20 @@ pub fn uninstall_canister(
     // Drop its certified data.
     canister.system_state.certified_data = Vec::new();
 
+    // Drop the chunks uploaded for chunked installs.
+    canister.system_state.wasm_chunk_store.clear();
+
     // Deactivate global timer.
     canister.system_state.global_timer = CanisterTimer::Inactive;
     // Increment canister version.
     canister.system_state.canister_version += 1;
+    if let AddCanisterChangeToHistory::Yes(origin) = add_canister_change {
+        canister.system_state.add_canister_change(
+            time,
+            origin,
+            CanisterChangeDetails::CodeUninstall,
+        );
+    }
 
     let mut rejects = Vec::new();
     let canister_id = canister.canister_id();
@@ -1537,7 +2225,7 @@ pub fn uninstall_canister(
                         },
                     }));
                 }
-                CallOrigin::CanisterUpdate(caller_canister_id, callback_id) => {
+                CallOrigin::CanisterUpdate(caller_canister_id, callback_id, deadline) => {
                     rejects.push(Response::Canister(CanisterResponse {
                         originator: *caller_canister_id,
                         respondent: canister_id,
@@ -1547,6 +2235,7 @@ pub fn uninstall_canister(
                             code: RejectCode::CanisterReject,
                             message: String::from("Canister has been uninstalled."),
                         }),
+                        deadline: *deadline,
                     }));
                 }
                 CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => fatal!(
@@ -1572,6 +2261,10 @@ struct ValidatedCanisterSettings {
     pub compute_allocation: Option<ComputeAllocation>,
     pub memory_allocation: Option<MemoryAllocation>,
     pub freezing_threshold: Option<NumSeconds>,
+    pub log_visibility: Option<LogVisibility>,
+    pub wasm_memory_limit: Option<NumBytes>,
+    pub wasm_memory_threshold: Option<NumBytes>,
+    pub reserved_cycles_limit: Option<Cycles>,
 }
 
 impl TryFrom<(CanisterSettings, usize)> for ValidatedCanisterSettings {
@@ -1607,6 +2300,10 @@ impl TryFrom<(CanisterSettings, usize)> for ValidatedCanisterSettings {
             compute_allocation: settings.compute_allocation(),
             memory_allocation: settings.memory_allocation(),
             freezing_threshold: settings.freezing_threshold(),
+            log_visibility: settings.log_visibility(),
+            wasm_memory_limit: settings.wasm_memory_limit(),
+            wasm_memory_threshold: settings.wasm_memory_threshold(),
+            reserved_cycles_limit: settings.reserved_cycles_limit(),
         })
     }
 }
@@ -1,8 +1,8 @@
 use crate::{
     as_num_instructions,
     canister_manager::{
-        uninstall_canister, CanisterManager, CanisterManagerError, CanisterMgrConfig,
-        InstallCodeContext, StopCanisterResult,
+        uninstall_canister, AddCanisterChangeToHistory, CanisterManager, CanisterManagerError,
+        CanisterMgrConfig, InstallCodeContext, StopCanisterResult,
     },
     canister_settings::{CanisterSettings, CanisterSettingsBuilder},
     execution_environment::as_round_instructions,
@@ -14,14 +14,17 @@ use assert_matches::assert_matches;
 use candid::Decode;
 use ic_base_types::{NumSeconds, PrincipalId};
 use ic_config::{
-    execution_environment::Config, flag_status::FlagStatus, subnet_config::SchedulerConfig,
+    execution_environment::{Config, DEFAULT_RESERVED_BALANCE_LIMIT},
+    flag_status::FlagStatus,
+    subnet_config::SchedulerConfig,
 };
 use ic_constants::SMALL_APP_SUBNET_MAX_SIZE;
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_error_types::{ErrorCode, UserError};
 use ic_ic00_types::{
-    CanisterIdRecord, CanisterInstallMode, CanisterSettingsArgs, CanisterStatusType,
-    CreateCanisterArgs, EmptyBlob, InstallCodeArgs, Method, Payload, UpdateSettingsArgs,
+    CanisterChange, CanisterChangeDetails, CanisterChangeOrigin, CanisterIdRecord,
+    CanisterInstallMode, CanisterSettingsArgs, CanisterStatusType, CreateCanisterArgs, EmptyBlob,
+    InstallCodeArgs, Method, Payload, UpdateSettingsArgs,
 };
 use ic_interfaces::{
     execution_environment::{
@@ -36,7 +39,7 @@ use ic_registry_routing_table::{CanisterIdRange, RoutingTable, CANISTER_IDS_PER_
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
     page_map, testing::CanisterQueuesTesting, CallContextManager, CallOrigin, CanisterState,
-    CanisterStatus, NumWasmPages, PageMap, ReplicatedState,
+    CanisterStatus, NumWasmPages, PageMap, ReplicatedState, TotalQueryStats,
 };
 use ic_system_api::{ExecutionParameters, InstructionLimits};
 use ic_test_utilities::{
@@ -101,19 +104,26 @@ lazy_static! {
             MAX_NUM_INSTRUCTIONS
         ),
         canister_memory_limit: NumBytes::new(u64::MAX / 2),
+        wasm_memory_limit: None,
         compute_allocation: ComputeAllocation::default(),
         subnet_type: SubnetType::Application,
         execution_mode: ExecutionMode::Replicated,
     };
 }
 
+// The origin only matters for the recorded canister history, so all test
+// senders are treated as users.
+fn canister_change_origin_from_principal(sender: &PrincipalId) -> CanisterChangeOrigin {
+    CanisterChangeOrigin::from_user(*sender)
+}
+
 pub struct InstallCodeContextBuilder {
     ctx: InstallCodeContext,
 }
 
 impl InstallCodeContextBuilder {
     pub fn sender(mut self, sender: PrincipalId) -> Self {
-        self.ctx.sender = sender;
+        self.ctx.origin = canister_change_origin_from_principal(&sender);
         self
     }
 
@@ -162,7 +172,7 @@ impl Default for InstallCodeContextBuilder {
     fn default() -> Self {
         Self {
             ctx: InstallCodeContext {
-                sender: PrincipalId::new_user_test_id(0),
+                origin: canister_change_origin_from_principal(&PrincipalId::new_user_test_id(0)),
                 canister_id: canister_test_id(0),
                 wasm_module: CanisterModule::new(wat::parse_str(EMPTY_WAT).unwrap()),
                 arg: vec![],
@@ -242,9 +252,11 @@ fn canister_manager_config(
     rate_limiting_of_instructions: FlagStatus,
 ) -> CanisterMgrConfig {
     CanisterMgrConfig::new(
+        MEMORY_CAPACITY,
         MEMORY_CAPACITY,
         DEFAULT_PROVISIONAL_BALANCE,
         NumSeconds::from(100_000),
+        DEFAULT_RESERVED_BALANCE_LIMIT,
         subnet_id,
         subnet_type,
         MAX_CONTROLLERS,
@@ -307,7 +319,7 @@ fn install_code(
         None,
     );
     let ingress = IngressBuilder::new()
-        .source(UserId::from(context.sender))
+        .source(UserId::from(context.sender()))
         .receiver(CanisterId::ic_00())
         .method_name(Method::InstallCode)
         .method_payload(args.encode())
@@ -351,7 +363,7 @@ fn install_canister_makes_subnet_oversubscribed() {
         };
         let canister_id1 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -364,7 +376,7 @@ fn install_canister_makes_subnet_oversubscribed() {
             .unwrap();
         let canister_id2 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -377,7 +389,7 @@ fn install_canister_makes_subnet_oversubscribed() {
             .unwrap();
         let canister_id3 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -489,7 +501,7 @@ fn upgrade_canister_with_no_wasm_fails() {
         };
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -540,7 +552,7 @@ fn can_update_compute_allocation_during_upgrade() {
         };
         let canister_id1 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 Cycles::new(2_000_000_000_000_000),
                 CanisterSettings::default(),
@@ -611,7 +623,7 @@ fn upgrading_canister_makes_subnet_oversubscribed() {
         let initial_cycles = Cycles::new(30_000_000_000_000);
         let canister_id1 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 initial_cycles,
                 CanisterSettings::default(),
@@ -624,7 +636,7 @@ fn upgrading_canister_makes_subnet_oversubscribed() {
             .unwrap();
         let canister_id2 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 initial_cycles,
                 CanisterSettings::default(),
@@ -637,7 +649,7 @@ fn upgrading_canister_makes_subnet_oversubscribed() {
             .unwrap();
         let canister_id3 = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 initial_cycles,
                 CanisterSettings::default(),
@@ -813,7 +825,7 @@ fn can_update_memory_allocation_during_upgrade() {
         };
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -949,7 +961,7 @@ fn can_create_canister() {
         assert_eq!(
             canister_manager
                 .create_canister(
-                    canister,
+                    canister_change_origin_from_principal(&canister),
                     sender_subnet_id,
                     *INITIAL_CYCLES,
                     CanisterSettings::default(),
@@ -965,7 +977,7 @@ fn can_create_canister() {
         assert_eq!(
             canister_manager
                 .create_canister(
-                    canister,
+                    canister_change_origin_from_principal(&canister),
                     sender_subnet_id,
                     *INITIAL_CYCLES,
                     CanisterSettings::default(),
@@ -996,7 +1008,7 @@ fn create_canister_fails_if_not_enough_cycles_are_sent_with_the_request() {
 
         assert_eq!(
             canister_manager.create_canister(
-                canister,
+                canister_change_origin_from_principal(&canister),
                 sender_subnet_id,
                 Cycles::new(100),
                 CanisterSettings::default(),
@@ -1033,7 +1045,7 @@ fn can_create_canister_with_extra_cycles() {
         assert_eq!(
             canister_manager
                 .create_canister(
-                    canister,
+                    canister_change_origin_from_principal(&canister),
                     sender_subnet_id,
                     Cycles::from(cycles),
                     CanisterSettings::default(),
@@ -1063,7 +1075,7 @@ fn cannot_install_non_empty_canister() {
         };
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1128,7 +1140,7 @@ fn install_code_with_wrong_controller_fails() {
         // Create a canister with canister_test_id 1 as controller.
         let canister_id = canister_manager
             .create_canister(
-                canister_test_id(1).get(),
+                canister_change_origin_from_principal(&canister_test_id(1).get()),
                 subnet_test_id(1),
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1193,7 +1205,7 @@ fn create_canister_sets_correct_allocations() {
         };
         let canister_id = canister_manager
             .create_canister(
-                canister_test_id(1).get(),
+                canister_change_origin_from_principal(&canister_test_id(1).get()),
                 subnet_test_id(1),
                 *INITIAL_CYCLES,
                 settings,
@@ -1222,7 +1234,7 @@ fn create_canister_updates_consumed_cycles_metric_correctly() {
         };
         let canister_id = canister_manager
             .create_canister(
-                canister_test_id(1).get(),
+                canister_change_origin_from_principal(&canister_test_id(1).get()),
                 subnet_test_id(1),
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1263,7 +1275,7 @@ fn provisional_create_canister_has_no_creation_fee() {
         };
         let canister_id = canister_manager
             .create_canister_with_cycles(
-                canister_test_id(1).get(),
+                canister_change_origin_from_principal(&canister_test_id(1).get()),
                 Some(INITIAL_CYCLES.get()),
                 CanisterSettings::default(),
                 None,
@@ -1299,7 +1311,7 @@ fn reinstall_on_empty_canister_succeeds() {
         let sender = canister_test_id(42).get();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_test_id(1),
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1407,7 +1419,7 @@ fn install_puts_canister_back_after_invalid_wasm() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1463,7 +1475,7 @@ fn reinstall_clears_stable_memory() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1557,7 +1569,7 @@ fn stop_a_running_canister() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender.get(),
+                canister_change_origin_from_principal(&sender.get()),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1690,7 +1702,7 @@ fn stop_a_canister_with_incorrect_controller() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1764,7 +1776,7 @@ fn start_a_canister_with_incorrect_controller() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1803,7 +1815,7 @@ fn starting_an_already_running_canister_keeps_it_running() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1911,7 +1923,7 @@ fn get_canister_status_with_incorrect_controller() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1950,7 +1962,7 @@ fn get_canister_status_of_running_canister() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -1984,7 +1996,7 @@ fn get_canister_status_of_self() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2039,6 +2051,41 @@ fn get_canister_status_of_stopping_canister() {
     });
 }
 
+#[test]
+fn get_canister_status_reports_query_stats() {
+    with_setup(|canister_manager, mut state, _| {
+        let sender = user_test_id(1).get();
+        let canister_id = canister_test_id(0);
+        let mut canister = get_stopped_canister(canister_id);
+        canister.scheduler_state.total_query_stats = TotalQueryStats {
+            num_calls: 4,
+            num_instructions: 40_000,
+            ingress_payload_size: 400,
+            egress_payload_size: 4_000,
+        };
+        state.put_canister_state(canister);
+
+        let canister = state.canister_state_mut(&canister_id).unwrap();
+        let status = canister_manager
+            .get_canister_status(sender, canister, SMALL_APP_SUBNET_MAX_SIZE)
+            .unwrap();
+        let query_stats = status.query_stats();
+        assert_eq!(query_stats.num_calls_total, candid::Nat::from(4));
+        assert_eq!(
+            query_stats.num_instructions_total,
+            candid::Nat::from(40_000)
+        );
+        assert_eq!(
+            query_stats.request_payload_bytes_total,
+            candid::Nat::from(400)
+        );
+        assert_eq!(
+            query_stats.response_payload_bytes_total,
+            candid::Nat::from(4_000)
+        );
+    });
+}
+
 #[test]
 fn set_controller_with_incorrect_controller() {
     with_setup(|canister_manager, mut state, _| {
@@ -2060,11 +2107,12 @@ fn set_controller_with_incorrect_controller() {
         // Set the controller from the wrong controller. Should fail.
         assert_eq!(
             canister_manager.set_controller(
-                wrong_controller,
+                canister_change_origin_from_principal(&wrong_controller),
                 canister_id,
                 new_controller,
                 &mut state,
                 &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
             ),
             Err(CanisterManagerError::CanisterInvalidController {
                 canister_id,
@@ -2104,11 +2152,12 @@ fn set_controller_with_correct_controller() {
         // Set the controller from the correct controller. Should succeed.
         assert!(canister_manager
             .set_controller(
-                controller,
+                canister_change_origin_from_principal(&controller),
                 canister_id,
                 new_controller,
                 &mut state,
-                &mut round_limits
+                &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
             )
             .is_ok());
 
@@ -2248,7 +2297,7 @@ fn install_canister_with_query_allocation() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2318,7 +2367,7 @@ fn create_canister_with_cycles_sender_in_whitelist() {
     let sender = canister_test_id(1).get();
     let canister_id = canister_manager
         .create_canister_with_cycles(
-            sender,
+            canister_change_origin_from_principal(&sender),
             Some(123),
             CanisterSettings::default(),
             None,
@@ -2354,7 +2403,7 @@ fn create_canister_with_specified_id(
     let creator = canister_test_id(1).get();
 
     let creation_result = canister_manager.create_canister_with_cycles(
-        creator,
+        canister_change_origin_from_principal(&creator),
         Some(123),
         CanisterSettings::default(),
         Some(specified_id),
@@ -2486,7 +2535,7 @@ fn installing_a_canister_with_not_enough_memory_allocation_fails() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2580,7 +2629,7 @@ fn upgrading_canister_with_not_enough_memory_allocation_fails() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2705,7 +2754,7 @@ fn installing_a_canister_with_not_enough_cycles_fails() {
         let sender_subnet_id = subnet_test_id(1);
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 // Give the new canister a relatively small number of cycles so it doesn't have
                 // enough to be installed.
@@ -2748,6 +2797,7 @@ fn uninstall_canister_doesnt_respond_to_responded_call_contexts() {
                 .with_call_context(CallContextBuilder::new().with_responded(true).build())
                 .build(),
             mock_time(),
+            AddCanisterChangeToHistory::No,
         ),
         Vec::new()
     );
@@ -2771,6 +2821,7 @@ fn uninstall_canister_responds_to_unresponded_call_contexts() {
                 )
                 .build(),
             mock_time(),
+            AddCanisterChangeToHistory::No,
         )[0],
         Response::Ingress(IngressResponse {
             message_id: message_test_id(456),
@@ -2815,7 +2866,7 @@ fn failed_upgrade_hooks_consume_instructions() {
         let sender = canister_test_id(100).get();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2830,7 +2881,7 @@ fn failed_upgrade_hooks_consume_instructions() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(initial_wasm),
                 arg: vec![],
@@ -2856,7 +2907,7 @@ fn failed_upgrade_hooks_consume_instructions() {
         let (instructions_left, result, _) = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(upgrade_wasm),
                 arg: vec![],
@@ -2959,7 +3010,7 @@ fn failed_install_hooks_consume_instructions() {
         let sender = canister_test_id(100).get();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -2975,7 +3026,7 @@ fn failed_install_hooks_consume_instructions() {
         let (instructions_left, result, _) = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3044,7 +3095,7 @@ fn install_code_respects_instruction_limit() {
     let sender = canister_test_id(100).get();
     let canister_id = canister_manager
         .create_canister(
-            sender,
+            canister_change_origin_from_principal(&sender),
             subnet_id,
             *INITIAL_CYCLES,
             CanisterSettings::default(),
@@ -3093,7 +3144,7 @@ fn install_code_respects_instruction_limit() {
     let (instructions_left, result, canister) = install_code(
         &canister_manager,
         InstallCodeContext {
-            sender,
+            origin: canister_change_origin_from_principal(&sender),
             canister_id,
             wasm_module: CanisterModule::new(wasm.clone()),
             arg: vec![],
@@ -3125,7 +3176,7 @@ fn install_code_respects_instruction_limit() {
     let (instructions_left, result, canister) = install_code(
         &canister_manager,
         InstallCodeContext {
-            sender,
+            origin: canister_change_origin_from_principal(&sender),
             canister_id,
             wasm_module: CanisterModule::new(wasm.clone()),
             arg: vec![],
@@ -3151,7 +3202,7 @@ fn install_code_respects_instruction_limit() {
     let (instructions_left, result, canister) = install_code(
         &canister_manager,
         InstallCodeContext {
-            sender,
+            origin: canister_change_origin_from_principal(&sender),
             canister_id,
             wasm_module: CanisterModule::new(wasm.clone()),
             arg: vec![],
@@ -3183,7 +3234,7 @@ fn install_code_respects_instruction_limit() {
     let (instructions_left, result, _) = install_code(
         &canister_manager,
         InstallCodeContext {
-            sender,
+            origin: canister_change_origin_from_principal(&sender),
             canister_id,
             wasm_module: CanisterModule::new(wasm),
             arg: vec![],
@@ -3394,7 +3445,7 @@ fn lower_memory_allocation_than_usage_fails() {
         let sender = canister_test_id(100).get();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -3409,7 +3460,7 @@ fn lower_memory_allocation_than_usage_fails() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3431,7 +3482,14 @@ fn lower_memory_allocation_than_usage_fails() {
         let canister = state.canister_state_mut(&canister_id).unwrap();
 
         assert_matches!(
-            canister_manager.update_settings(sender, settings, canister, &mut round_limits,),
+            canister_manager.update_settings(
+                mock_time(),
+                canister_change_origin_from_principal(&sender),
+                settings,
+                canister,
+                &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
+            ),
             Err(CanisterManagerError::NotEnoughMemoryAllocationGiven { .. })
         );
     })
@@ -3454,7 +3512,7 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
             .build();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 settings,
@@ -3470,7 +3528,7 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm.clone()),
                 arg: vec![],
@@ -3499,13 +3557,20 @@ fn test_install_when_updating_memory_allocation_via_canister_settings() {
         let canister = state.canister_state_mut(&canister_id).unwrap();
 
         canister_manager
-            .update_settings(sender, settings, canister, &mut round_limits)
+            .update_settings(
+                mock_time(),
+                canister_change_origin_from_principal(&sender),
+                settings,
+                canister,
+                &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
+            )
             .unwrap();
 
         install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3544,7 +3609,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
         let wasm = wat::parse_str(wat).unwrap();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 settings,
@@ -3559,7 +3624,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3585,7 +3650,7 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm.clone()),
                 arg: vec![],
@@ -3615,13 +3680,20 @@ fn test_upgrade_when_updating_memory_allocation_via_canister_settings() {
         let canister = state.canister_state_mut(&canister_id).unwrap();
 
         canister_manager
-            .update_settings(sender, settings, canister, &mut round_limits)
+            .update_settings(
+                mock_time(),
+                canister_change_origin_from_principal(&sender),
+                settings,
+                canister,
+                &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
+            )
             .unwrap();
 
         install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3662,8 +3734,8 @@ fn uninstall_code_can_be_invoked_by_governance_canister() {
 
     canister_manager
         .uninstall_code(
+            canister_change_origin_from_principal(&GOVERNANCE_CANISTER_ID.get()),
             canister_test_id(0),
-            GOVERNANCE_CANISTER_ID.get(),
             &mut state,
         )
         .unwrap();
@@ -3693,7 +3765,7 @@ fn test_install_when_setting_memory_allocation_to_zero() {
         let settings = CanisterSettings::default();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 settings,
@@ -3714,18 +3786,20 @@ fn test_install_when_setting_memory_allocation_to_zero() {
 
         canister_manager
             .update_settings(
-                sender,
+                mock_time(),
+                canister_change_origin_from_principal(&sender),
                 settings,
                 canister,
                 //memory_allocation_used,
                 &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
             )
             .unwrap();
 
         install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3761,7 +3835,7 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
             .build();
         let canister_id = canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 subnet_id,
                 *INITIAL_CYCLES,
                 settings,
@@ -3776,7 +3850,7 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
         let res = install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm.clone()),
                 arg: vec![],
@@ -3799,13 +3873,20 @@ fn test_upgrade_when_setting_memory_allocation_to_zero() {
         let canister = state.canister_state_mut(&canister_id).unwrap();
 
         canister_manager
-            .update_settings(sender, settings, canister, &mut round_limits)
+            .update_settings(
+                mock_time(),
+                canister_change_origin_from_principal(&sender),
+                settings,
+                canister,
+                &mut round_limits,
+                SMALL_APP_SUBNET_MAX_SIZE,
+            )
             .unwrap();
 
         install_code(
             &canister_manager,
             InstallCodeContext {
-                sender,
+                origin: canister_change_origin_from_principal(&sender),
                 canister_id,
                 wasm_module: CanisterModule::new(wasm),
                 arg: vec![],
@@ -3837,7 +3918,7 @@ fn max_number_of_canisters_is_respected_when_creating_canisters() {
         // Create 3 canisters with `max_number_of_canisters = 3`, should succeed.
         canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -3850,7 +3931,7 @@ fn max_number_of_canisters_is_respected_when_creating_canisters() {
             .unwrap();
         canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -3863,7 +3944,7 @@ fn max_number_of_canisters_is_respected_when_creating_canisters() {
             .unwrap();
         canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -3879,7 +3960,7 @@ fn max_number_of_canisters_is_respected_when_creating_canisters() {
         // Creating a fourth canister with 3 already created and
         // `max_number_of_canisters = 3` should fail.
         let (res, _) = canister_manager.create_canister(
-            sender,
+            canister_change_origin_from_principal(&sender),
             sender_subnet_id,
             *INITIAL_CYCLES,
             CanisterSettings::default(),
@@ -3897,7 +3978,7 @@ fn max_number_of_canisters_is_respected_when_creating_canisters() {
         // `max_number_of_canisters = 10` should succeed.
         canister_manager
             .create_canister(
-                sender,
+                canister_change_origin_from_principal(&sender),
                 sender_subnet_id,
                 *INITIAL_CYCLES,
                 CanisterSettings::default(),
@@ -5051,3 +5132,166 @@ fn install_code_cannot_switch_from_reserved_to_best_effort_memory_allocation() {
         NumWasmPages::from(10)
     )
 }
+
+fn state_with_stopped_canister(controller: PrincipalId) -> ReplicatedState {
+    ReplicatedStateBuilder::new()
+        .with_canister(
+            CanisterStateBuilder::new()
+                .with_canister_id(canister_test_id(0))
+                .with_controller(controller)
+                .with_status(CanisterStatusType::Stopped)
+                .with_wasm(vec![1, 2, 3])
+                .build(),
+        )
+        .build()
+}
+
+#[test]
+fn take_load_list_and_delete_canister_snapshot() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let controller = canister_test_id(1).get();
+    let canister_id = canister_test_id(0);
+    let mut state = state_with_stopped_canister(controller);
+    let mut round_limits = RoundLimits {
+        instructions: as_round_instructions(EXECUTION_PARAMETERS.instruction_limits.message()),
+        execution_complexity: ExecutionComplexity::MAX,
+        subnet_available_memory: (*MAX_SUBNET_AVAILABLE_MEMORY),
+        compute_allocation_used: state.total_compute_allocation(),
+    };
+    state
+        .canister_state_mut(&canister_id)
+        .unwrap()
+        .system_state
+        .certified_data = vec![1];
+
+    let snapshot = canister_manager
+        .take_canister_snapshot(controller, canister_id, None, &mut state, &mut round_limits)
+        .unwrap();
+    assert_eq!(state.metadata.next_snapshot_id, 1);
+    assert_eq!(
+        canister_manager
+            .list_canister_snapshots(controller, canister_id, &state)
+            .unwrap(),
+        vec![snapshot.clone()]
+    );
+
+    // Change the canister and restore it from the snapshot.
+    let canister = state.canister_state_mut(&canister_id).unwrap();
+    canister.system_state.certified_data = vec![2];
+    canister.execution_state = None;
+    let canister_version = canister.system_state.canister_version;
+    canister_manager
+        .load_canister_snapshot(
+            canister_change_origin_from_principal(&controller),
+            canister_id,
+            &snapshot.id,
+            &mut state,
+            &mut round_limits,
+        )
+        .unwrap();
+    let canister = state.canister_state(&canister_id).unwrap();
+    assert_eq!(canister.system_state.certified_data, vec![1]);
+    assert_eq!(
+        canister
+            .execution_state
+            .as_ref()
+            .unwrap()
+            .wasm_binary
+            .binary
+            .as_slice(),
+        &[1, 2, 3]
+    );
+    assert_eq!(canister.system_state.canister_version, canister_version + 1);
+    assert_eq!(
+        canister
+            .system_state
+            .get_canister_history()
+            .get_changes(1)
+            .next()
+            .unwrap(),
+        &CanisterChange::new(
+            state.time().as_nanos_since_unix_epoch(),
+            canister_version + 1,
+            canister_change_origin_from_principal(&controller),
+            CanisterChangeDetails::code_deployment(
+                CanisterInstallMode::Reinstall,
+                CanisterModule::new(vec![1, 2, 3]).module_hash(),
+            ),
+        )
+    );
+
+    canister_manager
+        .delete_canister_snapshot(controller, canister_id, &snapshot.id, &mut state)
+        .unwrap();
+    assert!(canister_manager
+        .list_canister_snapshots(controller, canister_id, &state)
+        .unwrap()
+        .is_empty());
+    assert_matches!(
+        canister_manager.delete_canister_snapshot(
+            controller,
+            canister_id,
+            &snapshot.id,
+            &mut state
+        ),
+        Err(CanisterManagerError::CanisterSnapshotNotFound { .. })
+    );
+}
+
+#[test]
+fn take_canister_snapshot_checks_controller_and_limit() {
+    let canister_manager = CanisterManagerBuilder::default().build();
+    let controller = canister_test_id(1).get();
+    let canister_id = canister_test_id(0);
+    let mut state = state_with_stopped_canister(controller);
+    let mut round_limits = RoundLimits {
+        instructions: as_round_instructions(EXECUTION_PARAMETERS.instruction_limits.message()),
+        execution_complexity: ExecutionComplexity::MAX,
+        subnet_available_memory: (*MAX_SUBNET_AVAILABLE_MEMORY),
+        compute_allocation_used: state.total_compute_allocation(),
+    };
+
+    assert_matches!(
+        canister_manager.take_canister_snapshot(
+            user_test_id(1).get(),
+            canister_id,
+            None,
+            &mut state,
+            &mut round_limits,
+        ),
+        Err(CanisterManagerError::CanisterInvalidController { .. })
+    );
+
+    let snapshot = canister_manager
+        .take_canister_snapshot(controller, canister_id, None, &mut state, &mut round_limits)
+        .unwrap();
+    assert_matches!(
+        canister_manager.take_canister_snapshot(
+            controller,
+            canister_id,
+            None,
+            &mut state,
+            &mut round_limits,
+        ),
+        Err(CanisterManagerError::CanisterSnapshotLimitExceeded { .. })
+    );
+
+    // Replacing the existing snapshot is allowed and yields a new id. The
+    // canister didn't change, so the subnet memory usage stays the same.
+    let available_memory = round_limits.subnet_available_memory.get_total_memory();
+    let new_snapshot = canister_manager
+        .take_canister_snapshot(
+            controller,
+            canister_id,
+            Some(&snapshot.id),
+            &mut state,
+            &mut round_limits,
+        )
+        .unwrap();
+    assert_ne!(new_snapshot.id, snapshot.id);
+    assert_eq!(state.canister_snapshots.len(), 1);
+    assert_eq!(
+        available_memory,
+        round_limits.subnet_available_memory.get_total_memory()
+    );
+}
@@ -1,13 +1,17 @@
 use ic_base_types::{NumBytes, NumSeconds};
 use ic_error_types::{ErrorCode, UserError};
-use ic_ic00_types::CanisterSettingsArgs;
+use ic_ic00_types::{CanisterSettingsArgs, LogVisibility};
 use ic_types::{
-    ComputeAllocation, InvalidComputeAllocationError, InvalidMemoryAllocationError,
+    ComputeAllocation, Cycles, InvalidComputeAllocationError, InvalidMemoryAllocationError,
     MemoryAllocation, PrincipalId,
 };
 use num_traits::cast::ToPrimitive;
 use std::convert::TryFrom;
 
+/// The maximum value of the `wasm_memory_limit` setting, which matches the
+/// maximum size of a 64-bit Wasm heap supported by the replica.
+pub(crate) const MAX_WASM_MEMORY_LIMIT: u64 = 1 << 48;
+
 /// Struct used for decoding CanisterSettingsArgs
 #[derive(Default)]
 pub(crate) struct CanisterSettings {
@@ -16,6 +20,10 @@ pub(crate) struct CanisterSettings {
     pub(crate) compute_allocation: Option<ComputeAllocation>,
     pub(crate) memory_allocation: Option<MemoryAllocation>,
     pub(crate) freezing_threshold: Option<NumSeconds>,
+    pub(crate) log_visibility: Option<LogVisibility>,
+    pub(crate) wasm_memory_limit: Option<NumBytes>,
+    pub(crate) wasm_memory_threshold: Option<NumBytes>,
+    pub(crate) reserved_cycles_limit: Option<Cycles>,
 }
 
 impl CanisterSettings {
@@ -25,6 +33,10 @@ impl CanisterSettings {
         compute_allocation: Option<ComputeAllocation>,
         memory_allocation: Option<MemoryAllocation>,
         freezing_threshold: Option<NumSeconds>,
+        log_visibility: Option<LogVisibility>,
+        wasm_memory_limit: Option<NumBytes>,
+        wasm_memory_threshold: Option<NumBytes>,
+        reserved_cycles_limit: Option<Cycles>,
     ) -> Self {
         Self {
             controller,
@@ -32,6 +44,10 @@ impl CanisterSettings {
             compute_allocation,
             memory_allocation,
             freezing_threshold,
+            log_visibility,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            reserved_cycles_limit,
         }
     }
 
@@ -54,6 +70,22 @@ impl CanisterSettings {
     pub fn freezing_threshold(&self) -> Option<NumSeconds> {
         self.freezing_threshold
     }
+
+    pub fn log_visibility(&self) -> Option<LogVisibility> {
+        self.log_visibility
+    }
+
+    pub fn wasm_memory_limit(&self) -> Option<NumBytes> {
+        self.wasm_memory_limit
+    }
+
+    pub fn wasm_memory_threshold(&self) -> Option<NumBytes> {
+        self.wasm_memory_threshold
+    }
+
+    pub fn reserved_cycles_limit(&self) -> Option<Cycles> {
+        self.reserved_cycles_limit
+    }
 }
 
 impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
@@ -84,12 +116,47 @@ impl TryFrom<CanisterSettingsArgs> for CanisterSettings {
             None => None,
         };
 
+        let wasm_memory_limit = match input.wasm_memory_limit {
+            Some(limit) => match limit.0.to_u64() {
+                Some(limit) if limit <= MAX_WASM_MEMORY_LIMIT => Some(NumBytes::from(limit)),
+                _ => {
+                    return Err(UpdateSettingsError::WasmMemoryLimitOutOfRange { provided: limit })
+                }
+            },
+            None => None,
+        };
+
+        let wasm_memory_threshold = match input.wasm_memory_threshold {
+            Some(threshold) => match threshold.0.to_u64() {
+                Some(threshold) if threshold <= MAX_WASM_MEMORY_LIMIT => {
+                    Some(NumBytes::from(threshold))
+                }
+                _ => {
+                    return Err(UpdateSettingsError::WasmMemoryThresholdOutOfRange {
+                        provided: threshold,
+                    })
+                }
+            },
+            None => None,
+        };
+
+        let reserved_cycles_limit = match input.reserved_cycles_limit {
+            Some(limit) => Some(Cycles::from(limit.0.to_u128().ok_or(
+                UpdateSettingsError::ReservedCyclesLimitOutOfRange { provided: limit },
+            )?)),
+            None => None,
+        };
+
         Ok(CanisterSettings::new(
             controller,
             input.controllers,
             compute_allocation,
             memory_allocation,
             freezing_threshold,
+            input.log_visibility,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            reserved_cycles_limit,
         ))
     }
 }
@@ -111,6 +178,10 @@ pub(crate) struct CanisterSettingsBuilder {
     compute_allocation: Option<ComputeAllocation>,
     memory_allocation: Option<MemoryAllocation>,
     freezing_threshold: Option<NumSeconds>,
+    log_visibility: Option<LogVisibility>,
+    wasm_memory_limit: Option<NumBytes>,
+    wasm_memory_threshold: Option<NumBytes>,
+    reserved_cycles_limit: Option<Cycles>,
 }
 
 #[allow(dead_code)]
@@ -122,6 +193,10 @@ impl CanisterSettingsBuilder {
             compute_allocation: None,
             memory_allocation: None,
             freezing_threshold: None,
+            log_visibility: None,
+            wasm_memory_limit: None,
+            wasm_memory_threshold: None,
+            reserved_cycles_limit: None,
         }
     }
 
@@ -132,6 +207,10 @@ impl CanisterSettingsBuilder {
             compute_allocation: self.compute_allocation,
             memory_allocation: self.memory_allocation,
             freezing_threshold: self.freezing_threshold,
+            log_visibility: self.log_visibility,
+            wasm_memory_limit: self.wasm_memory_limit,
+            wasm_memory_threshold: self.wasm_memory_threshold,
+            reserved_cycles_limit: self.reserved_cycles_limit,
         }
     }
 
@@ -169,12 +248,43 @@ impl CanisterSettingsBuilder {
             ..self
         }
     }
+
+    pub fn with_log_visibility(self, log_visibility: LogVisibility) -> Self {
+        Self {
+            log_visibility: Some(log_visibility),
+            ..self
+        }
+    }
+
+    pub fn with_wasm_memory_limit(self, wasm_memory_limit: NumBytes) -> Self {
+        Self {
+            wasm_memory_limit: Some(wasm_memory_limit),
+            ..self
+        }
+    }
+
+    pub fn with_wasm_memory_threshold(self, wasm_memory_threshold: NumBytes) -> Self {
+        Self {
+            wasm_memory_threshold: Some(wasm_memory_threshold),
+            ..self
+        }
+    }
+
+    pub fn with_reserved_cycles_limit(self, reserved_cycles_limit: Cycles) -> Self {
+        Self {
+            reserved_cycles_limit: Some(reserved_cycles_limit),
+            ..self
+        }
+    }
 }
 
 pub enum UpdateSettingsError {
     ComputeAllocation(InvalidComputeAllocationError),
     MemoryAllocation(InvalidMemoryAllocationError),
     FreezingThresholdOutOfRange { provided: candid::Nat },
+    WasmMemoryLimitOutOfRange { provided: candid::Nat },
+    WasmMemoryThresholdOutOfRange { provided: candid::Nat },
+    ReservedCyclesLimitOutOfRange { provided: candid::Nat },
 }
 
 impl From<UpdateSettingsError> for UserError {
@@ -203,6 +313,27 @@ impl From<UpdateSettingsError> for UserError {
                     provided
                 ),
             ),
+            UpdateSettingsError::WasmMemoryLimitOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Wasm memory limit expected to be in the range of [0..2^48], got {}",
+                    provided
+                ),
+            ),
+            UpdateSettingsError::WasmMemoryThresholdOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Wasm memory threshold expected to be in the range of [0..2^48], got {}",
+                    provided
+                ),
+            ),
+            UpdateSettingsError::ReservedCyclesLimitOutOfRange { provided } => UserError::new(
+                ErrorCode::CanisterContractViolation,
+                format!(
+                    "Reserved cycles limit expected to be in the range of [0..2^128-1], got {}",
+                    provided
+                ),
+            ),
         }
     }
 }
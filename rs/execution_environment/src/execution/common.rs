@@ -6,6 +6,8 @@ use lazy_static::lazy_static;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use ic_base_types::{CanisterId, NumBytes, SubnetId};
+use ic_constants::SMALL_APP_SUBNET_MAX_SIZE;
+use ic_cycles_account_manager::{CyclesAccountManager, ResourceSaturation};
 use ic_embedders::wasm_executor::{CanisterStateChanges, SliceExecutionOutput};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::CanisterStatusType;
@@ -16,12 +18,13 @@ use ic_interfaces::messages::{CanisterCall, CanisterCallOrTask};
 use ic_logger::{error, fatal, warn, ReplicaLogger};
 use ic_replicated_state::{
     CallContext, CallContextAction, CallOrigin, CanisterState, ExecutionState, NetworkTopology,
-    SystemState,
+    ReservationError, SystemState,
 };
 use ic_system_api::sandbox_safe_system_state::SystemStateChanges;
 use ic_types::ingress::{IngressState, IngressStatus, WasmResult};
 use ic_types::messages::{CallContextId, CallbackId, MessageId, Payload, RejectContext, Response};
 use ic_types::methods::{Callback, WasmMethod};
+use ic_types::time::CoarseTime;
 use ic_types::{Cycles, MemoryAllocation, NumInstructions, Time, UserId};
 
 use crate::execution_environment::ExecutionResponse;
@@ -68,8 +71,8 @@ pub(crate) fn action_to_response(
             time,
             log,
         ),
-        CallOrigin::CanisterUpdate(caller_canister_id, callback_id) => {
-            action_to_request_response(canister, action, caller_canister_id, callback_id)
+        CallOrigin::CanisterUpdate(caller_canister_id, callback_id, deadline) => {
+            action_to_request_response(canister, action, caller_canister_id, callback_id, deadline)
         }
         CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => fatal!(
             log,
@@ -90,6 +93,7 @@ pub(crate) fn action_to_request_response(
     action: CallContextAction,
     originator: CanisterId,
     reply_callback_id: CallbackId,
+    deadline: CoarseTime,
 ) -> ExecutionResponse {
     let response_payload_and_refund = match action {
         CallContextAction::NotYetResponded | CallContextAction::AlreadyResponded => None,
@@ -130,6 +134,7 @@ pub(crate) fn action_to_request_response(
             originator_reply_callback: reply_callback_id,
             refund,
             response_payload,
+            deadline,
         })
     } else {
         ExecutionResponse::Empty
@@ -239,13 +244,14 @@ pub(crate) fn wasm_result_to_query_response(
         CallOrigin::Ingress(user_id, message_id) => {
             wasm_result_to_ingress_response(result, canister, user_id, message_id, time)
         }
-        CallOrigin::CanisterUpdate(caller_canister_id, callback_id) => {
+        CallOrigin::CanisterUpdate(caller_canister_id, callback_id, deadline) => {
             let response = Response {
                 originator: caller_canister_id,
                 respondent: canister.canister_id(),
                 originator_reply_callback: callback_id,
                 refund: Cycles::zero(),
                 response_payload: Payload::from(result),
+                deadline,
             };
             ExecutionResponse::Request(response)
         }
@@ -397,9 +403,45 @@ pub fn update_round_limits(round_limits: &mut RoundLimits, slice: &SliceExecutio
         &round_limits.execution_complexity - &slice.execution_complexity;
 }
 
+/// Moves the cycles needed for allocating `allocated_bytes` of memory into
+/// the reserved balance of the canister.
+pub(crate) fn reserve_cycles_for_memory_grow(
+    system_state: &mut SystemState,
+    allocated_bytes: NumBytes,
+    subnet_memory_saturation: &ResourceSaturation,
+    subnet_size: usize,
+    cycles_account_manager: &CyclesAccountManager,
+) -> HypervisorResult<()> {
+    let reservation_cycles = cycles_account_manager.storage_reservation_cycles(
+        allocated_bytes,
+        subnet_memory_saturation,
+        subnet_size,
+    );
+    system_state
+        .reserve_cycles(reservation_cycles)
+        .map_err(|err| match err {
+            ReservationError::InsufficientCycles {
+                requested,
+                available,
+            } => HypervisorError::InsufficientCyclesInMemoryGrow {
+                bytes: allocated_bytes,
+                available,
+                requested,
+            },
+            ReservationError::ReservedLimitExceed { requested, limit } => {
+                HypervisorError::ReservedCyclesLimitExceededInMemoryGrow {
+                    bytes: allocated_bytes,
+                    requested,
+                    limit,
+                }
+            }
+        })
+}
+
 /// Tries to apply the given canister changes to the given system state and
 /// subnet available memory. In case of an error, the partially applied changes
 /// are not undone.
+#[allow(clippy::too_many_arguments)]
 fn try_apply_canister_state_changes(
     system_state_changes: SystemStateChanges,
     output: &WasmExecutionOutput,
@@ -408,6 +450,8 @@ fn try_apply_canister_state_changes(
     time: Time,
     network_topology: &NetworkTopology,
     subnet_id: SubnetId,
+    subnet_memory_saturation: &ResourceSaturation,
+    cycles_account_manager: &CyclesAccountManager,
     log: &ReplicaLogger,
 ) -> HypervisorResult<()> {
     match &system_state.memory_allocation {
@@ -417,7 +461,22 @@ fn try_apply_canister_state_changes(
         MemoryAllocation::Reserved(_) => (),
     }
 
-    system_state_changes.apply_changes(time, system_state, network_topology, subnet_id, log)
+    system_state_changes.apply_changes(time, system_state, network_topology, subnet_id, log)?;
+
+    // Canisters with a memory allocation have already reserved cycles when
+    // the allocation was set, so only best-effort canisters reserve here.
+    match &system_state.memory_allocation {
+        MemoryAllocation::BestEffort => reserve_cycles_for_memory_grow(
+            system_state,
+            output.allocated_bytes,
+            subnet_memory_saturation,
+            network_topology
+                .get_subnet_size(&subnet_id)
+                .unwrap_or(SMALL_APP_SUBNET_MAX_SIZE),
+            cycles_account_manager,
+        ),
+        MemoryAllocation::Reserved(_) => Ok(()),
+    }
 }
 
 /// Applies canister state change after Wasm execution if possible.
@@ -438,6 +497,8 @@ pub fn apply_canister_state_changes(
     time: Time,
     network_topology: &NetworkTopology,
     subnet_id: SubnetId,
+    subnet_memory_saturation: &ResourceSaturation,
+    cycles_account_manager: &CyclesAccountManager,
     log: &ReplicaLogger,
 ) {
     if let Some(CanisterStateChanges {
@@ -459,6 +520,8 @@ pub fn apply_canister_state_changes(
             time,
             network_topology,
             subnet_id,
+            subnet_memory_saturation,
+            cycles_account_manager,
             log,
         ) {
             Ok(()) => {
@@ -478,6 +541,11 @@ pub fn apply_canister_state_changes(
                     HypervisorError::OutOfMemory => {
                         warn!(log, "Failed to apply state changes due to DTS: {}", err)
                     }
+                    HypervisorError::InsufficientCyclesInMemoryGrow { .. }
+                    | HypervisorError::ReservedCyclesLimitExceededInMemoryGrow { .. } => {
+                        // These are expected errors: the canister did not
+                        // have enough cycles to reserve for the new memory.
+                    }
                     _ => {
                         // TODO(RUN-299): Increment a critical error counter here.
                         error!(
@@ -492,6 +560,8 @@ pub fn apply_canister_state_changes(
             }
         }
     }
+    // Log records are kept even if the execution failed.
+    system_state.canister_log.append(&mut output.canister_log);
 }
 
 pub(crate) fn finish_call_with_error(
@@ -511,6 +581,7 @@ pub(crate) fn finish_call_with_error(
                 originator_reply_callback: request.sender_reply_callback,
                 refund: request.payment,
                 response_payload: Payload::from(Err(user_error)),
+                deadline: request.deadline,
             };
             ExecutionResponse::Request(response)
         }
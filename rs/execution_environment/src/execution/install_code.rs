@@ -6,7 +6,7 @@ use std::path::{Path, PathBuf};
 use ic_base_types::{CanisterId, NumBytes, PrincipalId};
 use ic_config::flag_status::FlagStatus;
 use ic_embedders::wasm_executor::CanisterStateChanges;
-use ic_ic00_types::CanisterInstallMode;
+use ic_ic00_types::{CanisterChangeDetails, CanisterChangeOrigin, CanisterInstallMode};
 use ic_interfaces::{
     execution_environment::{
         HypervisorError, HypervisorResult, SubnetAvailableMemory, SubnetAvailableMemoryError,
@@ -28,6 +28,7 @@ use crate::{
     canister_manager::{
         CanisterManagerError, CanisterMgrConfig, DtsInstallCodeResult, InstallCodeResult,
     },
+    execution::common::reserve_cycles_for_memory_grow,
     execution_environment::RoundContext,
     CompilationCostHandling, RoundLimits,
 };
@@ -239,6 +240,25 @@ impl InstallCodeHelper {
             round_limits.compute_allocation_used = others + new_compute_allocation.as_percent();
         }
 
+        if let MemoryAllocation::BestEffort = self.canister.memory_allocation() {
+            if let Err(err) = reserve_cycles_for_memory_grow(
+                &mut self.canister.system_state,
+                self.allocated_bytes,
+                &round.subnet_memory_saturation,
+                original.subnet_size,
+                round.cycles_account_manager,
+            ) {
+                let canister_id = clean_canister.canister_id();
+                return finish_err(
+                    clean_canister,
+                    self.instructions_left(),
+                    original,
+                    round,
+                    CanisterManagerError::Hypervisor(canister_id, err),
+                );
+            }
+        }
+
         // After this point `install_code` is guaranteed to succeed.
         // Commit all the remaining state and round limit changes.
 
@@ -266,6 +286,13 @@ impl InstallCodeHelper {
 
         let old_wasm_hash = get_wasm_hash(&clean_canister);
         let new_wasm_hash = get_wasm_hash(&self.canister);
+        if let Some(module_hash) = new_wasm_hash {
+            self.canister.system_state.add_canister_change(
+                original.time,
+                original.origin,
+                CanisterChangeDetails::code_deployment(original.mode, module_hash),
+            );
+        }
         DtsInstallCodeResult::Finished {
             canister: self.canister,
             message: original.message,
@@ -303,7 +330,7 @@ impl InstallCodeHelper {
             config,
         )?;
 
-        validate_controller(&self.canister, &original.sender)?;
+        validate_controller(&self.canister, &original.origin.origin())?;
 
         match original.mode {
             CanisterInstallMode::Install => {
@@ -428,7 +455,7 @@ impl InstallCodeHelper {
     pub fn handle_wasm_execution(
         &mut self,
         canister_state_changes: Option<CanisterStateChanges>,
-        output: WasmExecutionOutput,
+        mut output: WasmExecutionOutput,
         original: &OriginalContext,
         round: &RoundContext,
     ) -> Result<(), CanisterManagerError> {
@@ -516,6 +543,10 @@ impl InstallCodeHelper {
             self.total_heap_delta +=
                 NumBytes::from((output.instance_stats.dirty_pages * PAGE_SIZE) as u64);
         }
+        self.canister
+            .system_state
+            .canister_log
+            .append(&mut output.canister_log);
         Ok(())
     }
 
@@ -562,7 +593,7 @@ pub(crate) struct OriginalContext {
     pub subnet_size: usize,
     pub requested_compute_allocation: Option<ComputeAllocation>,
     pub requested_memory_allocation: Option<MemoryAllocation>,
-    pub sender: PrincipalId,
+    pub origin: CanisterChangeOrigin,
     pub canister_id: CanisterId,
 }
 
@@ -641,6 +672,53 @@ pub(crate) fn validate_memory_allocation(
     Ok(())
 }
 
+// Ensures that the canister can reserve `reservation_cycles` for a new memory
+// allocation and that a new `reserved_cycles_limit` is not below the cycles
+// that are already reserved.
+pub(crate) fn validate_reserved_cycles(
+    canister: &CanisterState,
+    memory_allocation: Option<MemoryAllocation>,
+    new_reserved_cycles_limit: Option<Cycles>,
+    reservation_cycles: Cycles,
+    reserved_balance_limit: Option<Cycles>,
+) -> Result<(), CanisterManagerError> {
+    let reserved_balance = canister.system_state.reserved_balance();
+    if let Some(limit) = new_reserved_cycles_limit {
+        if reserved_balance > limit {
+            return Err(CanisterManagerError::ReservedCyclesLimitIsTooLow {
+                cycles: reserved_balance,
+                limit,
+            });
+        }
+    }
+    if reservation_cycles == Cycles::zero() {
+        return Ok(());
+    }
+    // A reservation is needed only when the memory allocation increases.
+    let memory_allocation = memory_allocation.unwrap_or_else(|| canister.memory_allocation());
+    if let Some(limit) = reserved_balance_limit {
+        let requested = reserved_balance + reservation_cycles;
+        if requested > limit {
+            return Err(
+                CanisterManagerError::ReservedCyclesLimitExceededInMemoryAllocation {
+                    memory_allocation,
+                    requested,
+                    limit,
+                },
+            );
+        }
+    }
+    let available = canister.system_state.debited_balance();
+    if reservation_cycles > available {
+        return Err(CanisterManagerError::InsufficientCyclesInMemoryAllocation {
+            memory_allocation,
+            available,
+            requested: reservation_cycles,
+        });
+    }
+    Ok(())
+}
+
 pub(crate) fn get_wasm_hash(canister: &CanisterState) -> Option<[u8; 32]> {
     canister
         .execution_state
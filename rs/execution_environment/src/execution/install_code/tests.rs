@@ -1,8 +1,13 @@
 use ic_error_types::{ErrorCode, UserError};
 use ic_types::{CanisterId, Cycles, NumInstructions};
 
-use ic_ic00_types::{CanisterInstallMode, EmptyBlob, InstallCodeArgs, Method, Payload};
-use ic_replicated_state::canister_state::NextExecution;
+use ic_ic00_types::{
+    CanisterIdRecord, CanisterInstallMode, EmptyBlob, InstallChunkedCodeArgs, InstallCodeArgs,
+    Method, Payload, StoredChunksReply, UploadChunkArgs, UploadChunkReply,
+};
+use ic_replicated_state::canister_state::{
+    system_state::wasm_chunk_store::hash_chunk, NextExecution,
+};
 use ic_test_utilities_execution_environment::{
     check_ingress_status, ExecutionTest, ExecutionTestBuilder,
 };
@@ -745,3 +750,113 @@ fn dts_uninstall_with_aborted_install_code() {
     let result = check_ingress_status(test.ingress_status(&message_id)).unwrap();
     assert_eq!(result, WasmResult::Reply(EmptyBlob.encode()));
 }
+
+fn upload_chunk(test: &mut ExecutionTest, canister_id: CanisterId, chunk: &[u8]) -> Vec<u8> {
+    let result = test.subnet_message(
+        Method::UploadChunk,
+        UploadChunkArgs::new(canister_id, chunk.to_vec()).encode(),
+    );
+    match result {
+        Ok(WasmResult::Reply(data)) => UploadChunkReply::decode(&data).unwrap().hash,
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+fn stored_chunks(test: &mut ExecutionTest, canister_id: CanisterId) -> StoredChunksReply {
+    let result = test.subnet_message(
+        Method::StoredChunks,
+        CanisterIdRecord::from(canister_id).encode(),
+    );
+    match result {
+        Ok(WasmResult::Reply(data)) => StoredChunksReply::decode(&data).unwrap(),
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn install_chunked_code_assembles_module_from_chunks() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000));
+    let binary = wat::parse_str(DTS_INSTALL_WAT).unwrap();
+    let (first, second) = binary.split_at(binary.len() / 2);
+
+    let first_hash = upload_chunk(&mut test, canister_id, first);
+    let second_hash = upload_chunk(&mut test, canister_id, second);
+    assert_eq!(first_hash, hash_chunk(first).to_vec());
+    assert_eq!(stored_chunks(&mut test, canister_id).0.len(), 2);
+
+    let args = InstallChunkedCodeArgs::new(
+        CanisterInstallMode::Install,
+        canister_id,
+        None,
+        vec![first_hash, second_hash],
+        hash_chunk(&binary).to_vec(),
+        vec![],
+    );
+    let result = test.subnet_message(Method::InstallChunkedCode, args.encode());
+    assert_eq!(result, Ok(WasmResult::Reply(EmptyBlob.encode())));
+    assert_eq!(
+        test.execution_state(canister_id)
+            .wasm_binary
+            .binary
+            .as_slice(),
+        binary.as_slice()
+    );
+
+    test.subnet_message(
+        Method::ClearChunkStore,
+        CanisterIdRecord::from(canister_id).encode(),
+    )
+    .unwrap();
+    assert!(stored_chunks(&mut test, canister_id).0.is_empty());
+}
+
+#[test]
+fn install_chunked_code_fails_on_wrong_module_hash() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000));
+    let binary = wat::parse_str("(module)").unwrap();
+    let hash = upload_chunk(&mut test, canister_id, &binary);
+
+    let args = InstallChunkedCodeArgs::new(
+        CanisterInstallMode::Install,
+        canister_id,
+        None,
+        vec![hash.clone()],
+        vec![0; 32],
+        vec![],
+    );
+    let err = test
+        .subnet_message(Method::InstallChunkedCode, args.encode())
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+
+    // Unknown chunks are rejected as well.
+    let args = InstallChunkedCodeArgs::new(
+        CanisterInstallMode::Install,
+        canister_id,
+        None,
+        vec![hash, vec![1; 32]],
+        hash_chunk(&binary).to_vec(),
+        vec![],
+    );
+    let err = test
+        .subnet_message(Method::InstallChunkedCode, args.encode())
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+    assert!(test.canister_state(canister_id).execution_state.is_none());
+}
+
+#[test]
+fn upload_chunk_rejects_too_large_chunks() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.create_canister(Cycles::new(1_000_000_000_000));
+    let chunk = vec![0; 1024 * 1024 + 1];
+    let err = test
+        .subnet_message(
+            Method::UploadChunk,
+            UploadChunkArgs::new(canister_id, chunk).encode(),
+        )
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+}
@@ -359,6 +359,8 @@ impl ResponseHelper {
             round.time,
             round.network_topology,
             round.hypervisor.subnet_id(),
+            &round.subnet_memory_saturation,
+            round.cycles_account_manager,
             round.log,
         );
         // Return total instructions: wasm executor leftovers + cleanup reservation.
@@ -405,6 +407,8 @@ impl ResponseHelper {
             round.time,
             round.network_topology,
             round.hypervisor.subnet_id(),
+            &round.subnet_memory_saturation,
+            round.cycles_account_manager,
             round.log,
         );
 
@@ -787,9 +791,9 @@ pub fn execute_response(
     };
 
     let func_ref = match original.call_origin {
-        CallOrigin::Ingress(_, _) | CallOrigin::CanisterUpdate(_, _) | CallOrigin::SystemTask => {
-            FuncRef::UpdateClosure(closure)
-        }
+        CallOrigin::Ingress(_, _)
+        | CallOrigin::CanisterUpdate(_, _, _)
+        | CallOrigin::SystemTask => FuncRef::UpdateClosure(closure),
         CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => FuncRef::QueryClosure(closure),
     };
 
@@ -799,6 +803,7 @@ pub fn execute_response(
             payload.to_vec(),
             helper.refund_for_sent_cycles(),
             call_context_id,
+            original.call_origin.deadline(),
             call_context.has_responded(),
             execution_parameters.execution_mode.clone(),
         ),
@@ -807,6 +812,7 @@ pub fn execute_response(
             context.clone(),
             helper.refund_for_sent_cycles(),
             call_context_id,
+            original.call_origin.deadline(),
             call_context.has_responded(),
             execution_parameters.execution_mode.clone(),
         ),
@@ -871,9 +877,9 @@ fn execute_response_cleanup(
         .instruction_limits
         .update(instructions_left);
     let func_ref = match original.call_origin {
-        CallOrigin::Ingress(_, _) | CallOrigin::CanisterUpdate(_, _) | CallOrigin::SystemTask => {
-            FuncRef::UpdateClosure(cleanup_closure)
-        }
+        CallOrigin::Ingress(_, _)
+        | CallOrigin::CanisterUpdate(_, _, _)
+        | CallOrigin::SystemTask => FuncRef::UpdateClosure(cleanup_closure),
         CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => {
             FuncRef::QueryClosure(cleanup_closure)
         }
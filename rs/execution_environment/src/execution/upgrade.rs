@@ -17,7 +17,7 @@ use ic_interfaces::execution_environment::{HypervisorError, WasmExecutionOutput}
 use ic_interfaces::messages::CanisterCall;
 use ic_logger::{info, warn, ReplicaLogger};
 use ic_replicated_state::{CanisterState, SystemState};
-use ic_system_api::ApiType;
+use ic_system_api::{ApiType, ExecutionParameters};
 use ic_types::funds::Cycles;
 use ic_types::methods::{FuncRef, SystemMethod, WasmMethod};
 
@@ -131,11 +131,11 @@ pub(crate) fn execute_upgrade(
         )
     } else {
         let wasm_execution_result = round.hypervisor.execute_dts(
-            ApiType::pre_upgrade(original.time, context.sender),
+            ApiType::pre_upgrade(original.time, context.origin.origin()),
             execution_state,
             &helper.canister().system_state,
             helper.canister_memory_usage(),
-            helper.execution_parameters().clone(),
+            upgrade_execution_parameters(&helper),
             FuncRef::Method(method),
             round_limits,
             round.network_topology,
@@ -261,7 +261,7 @@ fn upgrade_stage_2_and_3a_create_execution_state_and_call_start(
         // If the Wasm module does not export the method, then this execution
         // succeeds as a no-op.
         upgrade_stage_4a_call_post_upgrade(
-            context.sender,
+            context.origin.origin(),
             context.arg,
             clean_canister,
             helper,
@@ -275,7 +275,7 @@ fn upgrade_stage_2_and_3a_create_execution_state_and_call_start(
             execution_state,
             &SystemState::new_for_start(canister_id),
             helper.canister_memory_usage(),
-            helper.execution_parameters().clone(),
+            upgrade_execution_parameters(&helper),
             FuncRef::Method(method),
             round_limits,
             round.network_topology,
@@ -287,7 +287,7 @@ fn upgrade_stage_2_and_3a_create_execution_state_and_call_start(
                 upgrade_stage_3b_process_start_result(
                     canister_state_changes,
                     output,
-                    context.sender,
+                    context.origin.origin(),
                     context.arg,
                     clean_canister,
                     helper,
@@ -309,7 +309,7 @@ fn upgrade_stage_2_and_3a_create_execution_state_and_call_start(
                 let paused_execution = Box::new(PausedStartExecutionDuringUpgrade {
                     paused_wasm_execution,
                     paused_helper: helper.pause(),
-                    context_sender: context.sender,
+                    context_sender: context.origin.origin(),
                     context_arg: context.arg,
                     original,
                 });
@@ -389,7 +389,7 @@ fn upgrade_stage_4a_call_post_upgrade(
         execution_state,
         &helper.canister().system_state,
         helper.canister_memory_usage(),
-        helper.execution_parameters().clone(),
+        upgrade_execution_parameters(&helper),
         FuncRef::Method(method),
         round_limits,
         round.network_topology,
@@ -740,3 +740,14 @@ impl PausedInstallCodeExecution for PausedPostUpgradeExecution {
         (self.original.message, Cycles::zero())
     }
 }
+
+/// Returns the execution parameters for the Wasm executions of an upgrade.
+/// The `wasm_memory_limit` of the canister is not enforced during upgrades so
+/// that a canister that has reached its limit can always be upgraded to code
+/// that uses less memory.
+fn upgrade_execution_parameters(helper: &InstallCodeHelper) -> ExecutionParameters {
+    ExecutionParameters {
+        wasm_memory_limit: None,
+        ..helper.execution_parameters().clone()
+    }
+}
@@ -22,15 +22,17 @@ use ic_config::execution_environment::Config as ExecutionConfig;
 use ic_config::flag_status::FlagStatus;
 use ic_constants::{LOG_CANISTER_OPERATION_CYCLES_THRESHOLD, SMALL_APP_SUBNET_MAX_SIZE};
 use ic_crypto_tecdsa::derive_tecdsa_public_key;
-use ic_cycles_account_manager::{CyclesAccountManager, IngressInductionCost};
+use ic_cycles_account_manager::{CyclesAccountManager, IngressInductionCost, ResourceSaturation};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
-    CanisterHttpRequestArgs, CanisterIdRecord, CanisterSettingsArgs,
-    ComputeInitialEcdsaDealingsArgs, CreateCanisterArgs, ECDSAPublicKeyArgs,
-    ECDSAPublicKeyResponse, EcdsaKeyId, EmptyBlob, InstallCodeArgs, Method as Ic00Method,
-    Payload as Ic00Payload, ProvisionalCreateCanisterWithCyclesArgs, ProvisionalTopUpCanisterArgs,
-    SetControllerArgs, SetupInitialDKGArgs, SignWithECDSAArgs, UninstallCodeArgs,
-    UpdateSettingsArgs, IC_00,
+    CanisterChangeOrigin, CanisterHttpRequestArgs, CanisterIdRecord, CanisterInfoRequest,
+    CanisterSettingsArgs, ComputeInitialEcdsaDealingsArgs, CreateCanisterArgs,
+    DeleteCanisterSnapshotArgs, ECDSAPublicKeyArgs, ECDSAPublicKeyResponse, EcdsaKeyId, EmptyBlob,
+    InstallChunkedCodeArgs, InstallCodeArgs, ListCanisterSnapshotsResponse,
+    LoadCanisterSnapshotArgs, Method as Ic00Method, Payload as Ic00Payload,
+    ProvisionalCreateCanisterWithCyclesArgs, ProvisionalTopUpCanisterArgs, SetControllerArgs,
+    SetupInitialDKGArgs, SignWithECDSAArgs, TakeCanisterSnapshotArgs, UninstallCodeArgs,
+    UpdateSettingsArgs, UploadChunkArgs, IC_00,
 };
 use ic_interfaces::{
     execution_environment::{
@@ -65,7 +67,7 @@ use ic_types::{
     ingress::{IngressState, IngressStatus, WasmResult},
     messages::{
         extract_effective_canister_id, AnonymousQuery, Payload, RejectContext, Request, Response,
-        SignedIngressContent, StopCanisterContext,
+        SignedIngressContent, StopCanisterContext, NO_DEADLINE,
     },
     methods::SystemMethod,
     nominal_cycles::NominalCycles,
@@ -138,6 +140,10 @@ pub struct RoundContext<'a> {
     pub execution_refund_error_counter: &'a IntCounter,
     pub log: &'a ReplicaLogger,
     pub time: Time,
+    /// The saturation of the subnet memory at the start of the execution.
+    /// It determines how many cycles need to be reserved for newly
+    /// allocated memory.
+    pub subnet_memory_saturation: ResourceSaturation,
 }
 
 /// Keeps track of instruction remaining in the current execution round.
@@ -298,8 +304,10 @@ impl ExecutionEnvironment {
         );
         let canister_manager_config: CanisterMgrConfig = CanisterMgrConfig::new(
             config.subnet_memory_capacity,
+            config.subnet_memory_threshold,
             config.default_provisional_cycles_balance,
             config.default_freeze_threshold,
+            config.default_reserved_balance_limit,
             own_subnet_id,
             own_subnet_type,
             config.max_controllers,
@@ -407,6 +415,7 @@ impl ExecutionEnvironment {
                                 originator_reply_callback: request.sender_reply_callback,
                                 refund: request.payment,
                                 response_payload: response.response_payload.clone(),
+                                deadline: request.deadline,
                             }
                             .into(),
                         );
@@ -423,7 +432,7 @@ impl ExecutionEnvironment {
         let method = Ic00Method::from_str(msg.method_name());
         let payload = msg.method_payload();
         let result = match method {
-            Ok(Ic00Method::InstallCode) => {
+            Ok(Ic00Method::InstallCode) | Ok(Ic00Method::InstallChunkedCode) => {
                 // Tail call is needed for deterministic time slicing here to
                 // properly handle the case of a paused execution.
                 return self.execute_install_code(
@@ -459,6 +468,7 @@ impl ExecutionEnvironment {
                                         message: reject_message,
                                     },
                                 ),
+                                deadline: request.deadline,
                             }
                             .into(),
                         );
@@ -524,7 +534,7 @@ impl ExecutionEnvironment {
                                         let result = match CanisterSettings::try_from(settings) {
                                             Err(err) => Some((Err(err.into()), cycles)),
                                             Ok(settings) =>
-                                                Some(self.create_canister(*msg.sender(), cycles, settings, registry_settings.max_number_of_canisters, &mut state, registry_settings.subnet_size, round_limits))
+                                                Some(self.create_canister(msg.canister_change_origin(args.sender_canister_version), cycles, settings, registry_settings.max_number_of_canisters, &mut state, registry_settings.subnet_size, round_limits))
                                         };
                                         info!(
                                             self.log,
@@ -547,7 +557,11 @@ impl ExecutionEnvironment {
                     Err(err) => Err(candid_error_to_user_error(err)),
                     Ok(args) => self
                         .canister_manager
-                        .uninstall_code(args.get_canister_id(), *msg.sender(), &mut state)
+                        .uninstall_code(
+                            msg.canister_change_origin(args.get_sender_canister_version()),
+                            args.get_canister_id(),
+                            &mut state,
+                        )
                         .map(|()| EmptyBlob.encode())
                         .map_err(|err| err.into()),
                 };
@@ -562,14 +576,16 @@ impl ExecutionEnvironment {
                         let timer = Timer::start();
 
                         let canister_id = args.get_canister_id();
+                        let origin = msg.canister_change_origin(args.get_sender_canister_version());
                         let result = match CanisterSettings::try_from(args.settings) {
                             Err(err) => Err(err.into()),
                             Ok(settings) => self.update_settings(
-                                *msg.sender(),
+                                origin,
                                 settings,
                                 canister_id,
                                 &mut state,
                                 round_limits,
+                                registry_settings.subnet_size,
                             ),
                         };
                         // The induction cost of `UpdateSettings` is charged
@@ -618,11 +634,12 @@ impl ExecutionEnvironment {
                     Ok(args) => self
                         .canister_manager
                         .set_controller(
-                            *msg.sender(),
+                            msg.canister_change_origin(args.get_sender_canister_version()),
                             args.get_canister_id(),
                             args.get_new_controller(),
                             &mut state,
                             round_limits,
+                            registry_settings.subnet_size,
                         )
                         .map(|()| EmptyBlob.encode())
                         .map_err(|err| err.into()),
@@ -643,6 +660,19 @@ impl ExecutionEnvironment {
                 Some((res, msg.take_cycles()))
             }
 
+            Ok(Ic00Method::CanisterInfo) => match &msg {
+                CanisterCall::Request(_) => {
+                    let res = match CanisterInfoRequest::decode(payload) {
+                        Err(err) => Err(candid_error_to_user_error(err)),
+                        Ok(args) => self.get_canister_info(args, &state),
+                    };
+                    Some((res, msg.take_cycles()))
+                }
+                CanisterCall::Ingress(_) => {
+                    self.reject_unexpected_ingress(Ic00Method::CanisterInfo)
+                }
+            },
+
             Ok(Ic00Method::StartCanister) => {
                 let res = match CanisterIdRecord::decode(payload) {
                     Err(err) => Err(candid_error_to_user_error(err)),
@@ -684,6 +714,124 @@ impl ExecutionEnvironment {
                 Some((res, msg.take_cycles()))
             }
 
+            Ok(Ic00Method::TakeCanisterSnapshot) => {
+                let res = match TakeCanisterSnapshotArgs::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .take_canister_snapshot(
+                            *msg.sender(),
+                            args.get_canister_id(),
+                            args.replace_snapshot().map(|id| id.as_slice()),
+                            &mut state,
+                            round_limits,
+                        )
+                        .map(|response| response.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::LoadCanisterSnapshot) => {
+                let res = match LoadCanisterSnapshotArgs::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .load_canister_snapshot(
+                            msg.canister_change_origin(args.get_sender_canister_version()),
+                            args.get_canister_id(),
+                            args.snapshot_id(),
+                            &mut state,
+                            round_limits,
+                        )
+                        .map(|()| EmptyBlob.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::ListCanisterSnapshots) => {
+                let res = match CanisterIdRecord::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .list_canister_snapshots(*msg.sender(), args.get_canister_id(), &state)
+                        .map(|snapshots| ListCanisterSnapshotsResponse(snapshots).encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::DeleteCanisterSnapshot) => {
+                let res = match DeleteCanisterSnapshotArgs::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .delete_canister_snapshot(
+                            *msg.sender(),
+                            args.get_canister_id(),
+                            args.snapshot_id(),
+                            &mut state,
+                        )
+                        .map(|()| EmptyBlob.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::FetchCanisterLogs) => Some((
+                Err(UserError::new(
+                    ErrorCode::CanisterRejectedMessage,
+                    format!(
+                        "{} API is only accessible in non-replicated mode",
+                        Ic00Method::FetchCanisterLogs
+                    ),
+                )),
+                msg.take_cycles(),
+            )),
+
+            Ok(Ic00Method::UploadChunk) => {
+                let res = match UploadChunkArgs::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .upload_chunk(
+                            *msg.sender(),
+                            args.get_canister_id(),
+                            args.chunk,
+                            &mut state,
+                            round_limits,
+                        )
+                        .map(|reply| reply.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::ClearChunkStore) => {
+                let res = match CanisterIdRecord::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .clear_chunk_store(*msg.sender(), args.get_canister_id(), &mut state)
+                        .map(|()| EmptyBlob.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
+            Ok(Ic00Method::StoredChunks) => {
+                let res = match CanisterIdRecord::decode(payload) {
+                    Err(err) => Err(candid_error_to_user_error(err)),
+                    Ok(args) => self
+                        .canister_manager
+                        .stored_chunks(*msg.sender(), args.get_canister_id(), &state)
+                        .map(|reply| reply.encode())
+                        .map_err(|err| err.into()),
+                };
+                Some((res, msg.take_cycles()))
+            }
+
             Ok(Ic00Method::RawRand) => match &msg {
                 CanisterCall::Ingress(_) => Some((
                     Err(UserError::new(
@@ -866,11 +1014,12 @@ impl ExecutionEnvironment {
                     Err(err) => Err(candid_error_to_user_error(err)),
                     Ok(args) => {
                         let cycles_amount = args.to_u128();
+                        let origin = msg.canister_change_origin(args.get_sender_canister_version());
                         match CanisterSettings::try_from(args.settings) {
                             Ok(settings) => self
                                 .canister_manager
                                 .create_canister_with_cycles(
-                                    *msg.sender(),
+                                    origin,
                                     cycles_amount,
                                     settings,
                                     args.specified_id,
@@ -1039,6 +1188,9 @@ impl ExecutionEnvironment {
             execution_refund_error_counter: self.metrics.execution_cycles_refund_error_counter(),
             log: &self.log,
             time,
+            subnet_memory_saturation: self
+                .canister_manager
+                .subnet_memory_saturation(&round_limits.subnet_available_memory),
         };
 
         let req = match input {
@@ -1185,6 +1337,7 @@ impl ExecutionEnvironment {
         ExecutionParameters {
             instruction_limits,
             canister_memory_limit: canister.memory_limit(self.config.max_canister_memory_size),
+            wasm_memory_limit: canister.system_state.wasm_memory_limit,
             compute_allocation: canister.scheduler_state.compute_allocation,
             subnet_type: self.own_subnet_type,
             execution_mode,
@@ -1193,7 +1346,7 @@ impl ExecutionEnvironment {
 
     fn create_canister(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         cycles: Cycles,
         settings: CanisterSettings,
         max_number_of_canisters: u64,
@@ -1201,10 +1354,10 @@ impl ExecutionEnvironment {
         subnet_size: usize,
         round_limits: &mut RoundLimits,
     ) -> (Result<Vec<u8>, UserError>, Cycles) {
-        match state.find_subnet_id(sender) {
+        match state.find_subnet_id(origin.origin()) {
             Ok(sender_subnet_id) => {
                 let (res, cycles) = self.canister_manager.create_canister(
-                    sender,
+                    origin,
                     sender_subnet_id,
                     cycles,
                     settings,
@@ -1225,15 +1378,24 @@ impl ExecutionEnvironment {
 
     fn update_settings(
         &self,
-        sender: PrincipalId,
+        origin: CanisterChangeOrigin,
         settings: CanisterSettings,
         canister_id: CanisterId,
         state: &mut ReplicatedState,
         round_limits: &mut RoundLimits,
+        subnet_size: usize,
     ) -> Result<Vec<u8>, UserError> {
+        let timestamp = state.time();
         let canister = get_canister_mut(canister_id, state)?;
         self.canister_manager
-            .update_settings(sender, settings, canister, round_limits)
+            .update_settings(
+                timestamp,
+                origin,
+                settings,
+                canister,
+                round_limits,
+                subnet_size,
+            )
             .map(|()| EmptyBlob.encode())
             .map_err(|err| err.into())
     }
@@ -1305,6 +1467,21 @@ impl ExecutionEnvironment {
             .map_err(|err| err.into())
     }
 
+    fn get_canister_info(
+        &self,
+        args: CanisterInfoRequest,
+        state: &ReplicatedState,
+    ) -> Result<Vec<u8>, UserError> {
+        let canister_id = args.canister_id();
+        match state.canister_state(&canister_id) {
+            Some(canister) => Ok(self
+                .canister_manager
+                .get_canister_info(args.num_requested_changes(), canister)
+                .encode()),
+            None => Err(CanisterManagerError::CanisterNotFound(canister_id).into()),
+        }
+    }
+
     fn stop_canister(
         &self,
         canister_id: CanisterId,
@@ -1366,6 +1543,9 @@ impl ExecutionEnvironment {
             execution_refund_error_counter: self.metrics.execution_cycles_refund_error_counter(),
             log: &self.log,
             time,
+            subnet_memory_saturation: self
+                .canister_manager
+                .subnet_memory_saturation(&round_limits.subnet_available_memory),
         };
         execute_response(
             canister,
@@ -1554,6 +1734,7 @@ impl ExecutionEnvironment {
                     originator_reply_callback: req.sender_reply_callback,
                     refund,
                     response_payload: payload,
+                    deadline: req.deadline,
                 };
 
                 state.push_subnet_output_response(response.into());
@@ -1635,6 +1816,7 @@ impl ExecutionEnvironment {
                             code: RejectCode::CanisterReject,
                             message: format!("Canister {}'s stop request cancelled", canister_id),
                         }),
+                        deadline: NO_DEADLINE,
                     };
                     state.push_subnet_output_response(response.into());
                 }
@@ -1838,12 +2020,25 @@ impl ExecutionEnvironment {
     ) -> (ReplicatedState, Option<NumInstructions>) {
         // A helper function to make error handling more compact using `?`.
         fn decode_input_and_take_canister(
+            canister_manager: &CanisterManager,
             msg: &CanisterCall,
             state: &mut ReplicatedState,
         ) -> Result<(InstallCodeContext, CanisterState), UserError> {
             let payload = msg.method_payload();
-            let args = InstallCodeArgs::decode(payload).map_err(candid_error_to_user_error)?;
-            let install_context = InstallCodeContext::try_from((*msg.sender(), args))?;
+            let install_context = match Ic00Method::from_str(msg.method_name()) {
+                Ok(Ic00Method::InstallChunkedCode) => {
+                    let args = InstallChunkedCodeArgs::decode(payload)
+                        .map_err(candid_error_to_user_error)?;
+                    let origin = msg.canister_change_origin(args.get_sender_canister_version());
+                    canister_manager.install_chunked_code_context(origin, args, state)?
+                }
+                _ => {
+                    let args =
+                        InstallCodeArgs::decode(payload).map_err(candid_error_to_user_error)?;
+                    let origin = msg.canister_change_origin(args.get_sender_canister_version());
+                    InstallCodeContext::try_from((origin, args))?
+                }
+            };
             let canister = state
                 .take_canister_state(&install_context.canister_id)
                 .ok_or(CanisterManagerError::CanisterNotFound(
@@ -1855,16 +2050,16 @@ impl ExecutionEnvironment {
         // Start logging execution time for `install_code`.
         let timer = Timer::start();
 
-        let (install_context, old_canister) = match decode_input_and_take_canister(&msg, &mut state)
-        {
-            Ok(result) => result,
-            Err(err) => {
-                let refund = msg.take_cycles();
-                let state =
-                    self.finish_subnet_message_execution(state, msg, Err(err), refund, timer);
-                return (state, Some(NumInstructions::from(0)));
-            }
-        };
+        let (install_context, old_canister) =
+            match decode_input_and_take_canister(&self.canister_manager, &msg, &mut state) {
+                Ok(result) => result,
+                Err(err) => {
+                    let refund = msg.take_cycles();
+                    let state =
+                        self.finish_subnet_message_execution(state, msg, Err(err), refund, timer);
+                    return (state, Some(NumInstructions::from(0)));
+                }
+            };
 
         // Check the precondition.
         match old_canister.next_execution() {
@@ -2029,6 +2224,7 @@ impl ExecutionEnvironment {
         match task {
             ExecutionTask::Heartbeat
             | ExecutionTask::GlobalTimer
+            | ExecutionTask::OnLowWasmMemory
             | ExecutionTask::PausedExecution(_)
             | ExecutionTask::AbortedExecution { .. } => {
                 panic!(
@@ -2049,6 +2245,9 @@ impl ExecutionEnvironment {
                         .execution_cycles_refund_error_counter(),
                     log: &self.log,
                     time: state.metadata.time(),
+                    subnet_memory_saturation: self
+                        .canister_manager
+                        .subnet_memory_saturation(&round_limits.subnet_available_memory),
                 };
                 let dts_result = paused.resume(canister, round, round_limits);
                 let dts_status = DtsInstallCodeStatus::ResumingPausedOrAbortedExecution;
@@ -2115,7 +2314,8 @@ impl ExecutionEnvironment {
                     ExecutionTask::AbortedExecution { .. }
                     | ExecutionTask::AbortedInstallCode { .. }
                     | ExecutionTask::Heartbeat
-                    | ExecutionTask::GlobalTimer => task,
+                    | ExecutionTask::GlobalTimer
+                    | ExecutionTask::OnLowWasmMemory => task,
                     ExecutionTask::PausedExecution(id) => {
                         let paused = self.take_paused_execution(id).unwrap();
                         let (input, prepaid_execution_cycles) = paused.abort(log);
@@ -2371,6 +2571,9 @@ pub fn execute_canister(
                             .execution_cycles_refund_error_counter(),
                         log: &exec_env.log,
                         time,
+                        subnet_memory_saturation: exec_env
+                            .canister_manager
+                            .subnet_memory_saturation(&round_limits.subnet_available_memory),
                     };
                     let result = paused.resume(canister, round_context, round_limits, subnet_size);
                     let (canister, instructions_used, heap_delta, ingress_status) =
@@ -2403,6 +2606,16 @@ pub fn execute_canister(
                     let task = CanisterMessageOrTask::Task(CanisterTask::GlobalTimer);
                     (task, instruction_limits, None)
                 }
+                ExecutionTask::OnLowWasmMemory => {
+                    // Like heartbeats and timers, the hook does not support DTS.
+                    let instruction_limits = InstructionLimits::new(
+                        FlagStatus::Disabled,
+                        max_instructions_per_message_without_dts,
+                        max_instructions_per_message_without_dts,
+                    );
+                    let task = CanisterMessageOrTask::Task(CanisterTask::OnLowWasmMemory);
+                    (task, instruction_limits, None)
+                }
                 ExecutionTask::AbortedExecution {
                     input,
                     prepaid_execution_cycles,
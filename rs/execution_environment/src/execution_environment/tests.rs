@@ -5,10 +5,11 @@ use ic_types::nominal_cycles::NominalCycles;
 use ic_base_types::{NumBytes, NumSeconds};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
-    self as ic00, CanisterHttpRequestArgs, CanisterIdRecord, CanisterStatusResultV2,
-    CanisterStatusType, EcdsaCurve, EcdsaKeyId, EmptyBlob, HttpMethod, Method,
-    Payload as Ic00Payload, ProvisionalCreateCanisterWithCyclesArgs, ProvisionalTopUpCanisterArgs,
-    TransformContext, TransformFunc, IC_00,
+    self as ic00, CanisterChange, CanisterChangeDetails, CanisterChangeOrigin,
+    CanisterHttpRequestArgs, CanisterIdRecord, CanisterInfoRequest, CanisterInfoResponse,
+    CanisterInstallMode, CanisterStatusResultV2, CanisterStatusType, EcdsaCurve, EcdsaKeyId,
+    EmptyBlob, HttpMethod, Method, Payload as Ic00Payload, ProvisionalCreateCanisterWithCyclesArgs,
+    ProvisionalTopUpCanisterArgs, TransformContext, TransformFunc, IC_00,
 };
 use ic_registry_routing_table::canister_id_into_u64;
 use ic_registry_routing_table::CanisterIdRange;
@@ -29,6 +30,7 @@ use ic_types::{
     ingress::{IngressState, IngressStatus, WasmResult},
     messages::{
         CallbackId, Payload, RejectContext, RequestOrResponse, Response, MAX_RESPONSE_COUNT_BYTES,
+        NO_DEADLINE,
     },
     CanisterId, Cycles, PrincipalId, RegistryVersion,
 };
@@ -776,6 +778,194 @@ fn get_stopping_canister_status_from_another_canister() {
     assert_eq!(csr.status(), CanisterStatusType::Stopping);
 }
 
+#[test]
+fn get_canister_info_from_another_canister() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let caller = test.universal_canister().unwrap();
+    let canister = test.universal_canister().unwrap();
+    let user = test.user_id().get();
+    let module_hash = test
+        .execution_state(canister)
+        .wasm_binary
+        .binary
+        .module_hash();
+    test.set_controller(canister, caller.get()).unwrap();
+
+    let get_canister_info = |num_requested_changes| {
+        let args = Encode!(&CanisterInfoRequest::new(canister, num_requested_changes)).unwrap();
+        wasm()
+            .call_simple(
+                ic00::IC_00,
+                Method::CanisterInfo,
+                call_args().other_side(args),
+            )
+            .build()
+    };
+
+    // Without `num_requested_changes` no changes are returned.
+    let result = test.ingress(caller, "update", get_canister_info(None));
+    let info = CanisterInfoResponse::decode(&get_reply(result)).unwrap();
+    assert_eq!(info.total_num_changes(), 3);
+    assert_eq!(info.changes(), vec![]);
+    assert_eq!(info.module_hash(), Some(module_hash.to_vec()));
+    assert_eq!(info.controllers(), vec![caller.get()]);
+
+    let result = test.ingress(caller, "update", get_canister_info(Some(100)));
+    let info = CanisterInfoResponse::decode(&get_reply(result)).unwrap();
+    let details: Vec<_> = info
+        .changes()
+        .into_iter()
+        .map(|change: CanisterChange| {
+            assert_eq!(change.origin, CanisterChangeOrigin::from_user(user));
+            change.details
+        })
+        .collect();
+    assert_eq!(
+        details,
+        vec![
+            CanisterChangeDetails::canister_creation(vec![user]),
+            CanisterChangeDetails::code_deployment(CanisterInstallMode::Install, module_hash),
+            CanisterChangeDetails::controllers_change(vec![caller.get()]),
+        ]
+    );
+
+    // Only the most recent changes are returned.
+    let result = test.ingress(caller, "update", get_canister_info(Some(1)));
+    let info = CanisterInfoResponse::decode(&get_reply(result)).unwrap();
+    assert_eq!(info.total_num_changes(), 3);
+    assert_eq!(info.changes().len(), 1);
+    assert_eq!(
+        info.changes()[0].details,
+        CanisterChangeDetails::controllers_change(vec![caller.get()])
+    );
+}
+
+#[test]
+fn get_canister_info_from_ingress_fails() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    let args = CanisterInfoRequest::new(canister, None).encode();
+    let err = test.subnet_message(Method::CanisterInfo, args).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+}
+
+#[test]
+fn canister_status_reports_wasm_memory_limit() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.settings().wasm_memory_limit(), 0);
+
+    test.update_wasm_memory_limit(canister, NumBytes::from(1 << 30))
+        .unwrap();
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.settings().wasm_memory_limit(), 1 << 30);
+}
+
+#[test]
+fn update_settings_rejects_too_large_wasm_memory_limit() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    let err = test
+        .update_wasm_memory_limit(canister, NumBytes::from((1 << 48) + 1))
+        .unwrap_err();
+    assert_eq!(ErrorCode::CanisterContractViolation, err.code());
+    assert_eq!(
+        test.canister_state(canister).system_state.wasm_memory_limit,
+        None
+    );
+}
+
+#[test]
+fn canister_status_reports_wasm_memory_threshold() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.settings().wasm_memory_threshold(), 0);
+
+    test.update_wasm_memory_threshold(canister, NumBytes::from(1 << 20))
+        .unwrap();
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.settings().wasm_memory_threshold(), 1 << 20);
+}
+
+#[test]
+fn update_settings_rejects_too_large_wasm_memory_threshold() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    let err = test
+        .update_wasm_memory_threshold(canister, NumBytes::from((1 << 48) + 1))
+        .unwrap_err();
+    assert_eq!(ErrorCode::CanisterContractViolation, err.code());
+    assert_eq!(
+        test.canister_state(canister)
+            .system_state
+            .wasm_memory_threshold,
+        NumBytes::from(0)
+    );
+}
+
+#[test]
+fn canister_status_reports_reserved_cycles_limit() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister = test.universal_canister().unwrap();
+    test.update_reserved_cycles_limit(canister, Cycles::new(1_000_000))
+        .unwrap();
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.settings().reserved_cycles_limit(), 1_000_000);
+    assert_eq!(csr.reserved_cycles(), 0);
+}
+
+#[test]
+fn update_settings_reserves_cycles_for_memory_allocation() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_total_memory(100 * 1024 * 1024)
+        .with_subnet_memory_threshold(0)
+        .build();
+    let canister = test.universal_canister().unwrap();
+    let balance_before = test.canister_state(canister).system_state.balance();
+    test.canister_update_allocations_settings(canister, None, Some(20 * 1024 * 1024))
+        .unwrap();
+    let reserved = test
+        .canister_state(canister)
+        .system_state
+        .reserved_balance();
+    assert!(reserved > Cycles::zero());
+    assert_eq!(
+        test.canister_state(canister).system_state.balance(),
+        balance_before - reserved
+    );
+    let result = test.canister_status(canister);
+    let csr = CanisterStatusResultV2::decode(&get_reply(result)).unwrap();
+    assert_eq!(csr.reserved_cycles(), reserved.get());
+}
+
+#[test]
+fn update_settings_fails_when_reserved_cycles_limit_is_exceeded() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_total_memory(100 * 1024 * 1024)
+        .with_subnet_memory_threshold(0)
+        .build();
+    let canister = test.universal_canister().unwrap();
+    test.update_reserved_cycles_limit(canister, Cycles::new(1))
+        .unwrap();
+    let err = test
+        .canister_update_allocations_settings(canister, None, Some(20 * 1024 * 1024))
+        .unwrap_err();
+    assert_eq!(ErrorCode::ReservedCyclesLimitExceeded, err.code());
+    assert_eq!(
+        test.canister_state(canister)
+            .system_state
+            .reserved_balance(),
+        Cycles::zero()
+    );
+}
+
 #[test]
 fn start_a_non_existing_canister() {
     let mut test = ExecutionTestBuilder::new().build();
@@ -1107,7 +1297,8 @@ fn setup_initial_dkg_sender_not_on_nns() {
                     ic00::Method::SetupInitialDKG,
                     other_canister,
                 )
-            })
+            }),
+            deadline: NO_DEADLINE,
         }
         .into()
     );
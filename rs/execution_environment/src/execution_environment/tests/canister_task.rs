@@ -1,13 +1,15 @@
 use assert_matches::assert_matches;
+use ic_ic00_types::CanisterSettingsArgs;
 use ic_interfaces::messages::CanisterTask;
 use ic_registry_subnet_type::SubnetType;
+use ic_replicated_state::canister_state::WASM_PAGE_SIZE_IN_BYTES;
 use ic_replicated_state::NumWasmPages;
 use ic_replicated_state::{page_map::PAGE_SIZE, CanisterStatus};
 use ic_state_machine_tests::{Cycles, StateMachine};
 use ic_state_machine_tests::{StateMachineBuilder, WasmResult};
 use ic_test_utilities_execution_environment::{wat_compilation_cost, ExecutionTestBuilder};
 use ic_test_utilities_metrics::fetch_int_counter_vec;
-use ic_types::NumBytes;
+use ic_types::{CanisterId, NumBytes};
 use ic_universal_canister::{wasm, UNIVERSAL_CANISTER_WASM};
 use maplit::btreemap;
 use std::time::{Duration, UNIX_EPOCH};
@@ -46,6 +48,28 @@ fn global_timer_is_executed() {
     );
 }
 
+#[test]
+fn on_low_wasm_memory_is_executed() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"(module
+            (func (export "canister_on_low_wasm_memory")
+                (drop (memory.grow (i32.const 10)))
+            )
+            (memory 1 20)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    test.canister_task(canister_id, CanisterTask::OnLowWasmMemory);
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(11)
+    );
+    assert!(
+        test.canister_state(canister_id)
+            .system_state
+            .on_low_wasm_memory_hook_executed
+    );
+}
+
 #[test]
 fn ic0_global_timer_set_is_supported_in_pre_upgrade() {
     let env = StateMachine::new();
@@ -739,3 +763,104 @@ fn global_timer_resumes_after_canister_is_being_stopped_and_started_again() {
     let result = env.query(canister_id, "query", get_global_counter).unwrap();
     assert_eq!(result, WasmResult::Reply(10_u64.to_le_bytes().into()));
 }
+
+const ON_LOW_WASM_MEMORY_WAT: &str = r#"(module
+        (import "ic0" "msg_reply" (func $msg_reply))
+        (import "ic0" "msg_reply_data_append"
+            (func $msg_reply_data_append (param i32 i32)))
+        ;; Counts the number of times the hook has run.
+        (func (export "canister_on_low_wasm_memory")
+            (i64.store (i32.const 0) (i64.add (i64.load (i32.const 0)) (i64.const 1)))
+        )
+        (func (export "canister_update grow")
+            (drop (memory.grow (i32.const 1)))
+            (call $msg_reply)
+        )
+        (func (export "canister_query count")
+            (call $msg_reply_data_append (i32.const 0) (i32.const 8))
+            (call $msg_reply)
+        )
+        (memory 1)
+    )"#;
+
+fn on_low_wasm_memory_hook_count(env: &StateMachine, canister_id: CanisterId) -> u64 {
+    match env.query(canister_id, "count", vec![]).unwrap() {
+        WasmResult::Reply(bytes) => u64::from_le_bytes(bytes.try_into().unwrap()),
+        WasmResult::Reject(msg) => panic!("Unexpected reject: {}", msg),
+    }
+}
+
+fn update_wasm_memory_settings(
+    env: &StateMachine,
+    canister_id: CanisterId,
+    wasm_memory_limit_pages: u64,
+    wasm_memory_threshold_pages: u64,
+) {
+    let page_size = WASM_PAGE_SIZE_IN_BYTES as u64;
+    env.update_settings(
+        &canister_id,
+        CanisterSettingsArgs::new(None, None, None, None)
+            .with_wasm_memory_limit(wasm_memory_limit_pages * page_size)
+            .with_wasm_memory_threshold(wasm_memory_threshold_pages * page_size),
+    )
+    .unwrap();
+}
+
+#[test]
+fn on_low_wasm_memory_runs_once_when_free_memory_drops_below_threshold() {
+    let env = StateMachine::new();
+    let canister_id = env.install_canister_wat(ON_LOW_WASM_MEMORY_WAT, vec![], None);
+
+    // The heap has 1 page, so 3 pages are free.
+    update_wasm_memory_settings(&env, canister_id, 4, 2);
+    env.tick();
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 0);
+
+    // 2 pages are free, which is not below the threshold.
+    env.execute_ingress(canister_id, "grow", vec![]).unwrap();
+    env.tick();
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 0);
+
+    // 1 page is free, so the hook runs, but only once.
+    env.execute_ingress(canister_id, "grow", vec![]).unwrap();
+    for _ in 0..5 {
+        env.tick();
+    }
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 1);
+}
+
+#[test]
+fn on_low_wasm_memory_runs_again_after_free_memory_recovers() {
+    let env = StateMachine::new();
+    let canister_id = env.install_canister_wat(ON_LOW_WASM_MEMORY_WAT, vec![], None);
+
+    // The heap has 1 page, so 1 page is free.
+    update_wasm_memory_settings(&env, canister_id, 2, 2);
+    env.tick();
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 1);
+
+    // Raising the limit recovers the free memory and re-arms the hook.
+    update_wasm_memory_settings(&env, canister_id, 4, 2);
+    env.tick();
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 1);
+
+    update_wasm_memory_settings(&env, canister_id, 2, 2);
+    env.tick();
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 2);
+}
+
+#[test]
+fn on_low_wasm_memory_does_not_run_without_wasm_memory_limit() {
+    let env = StateMachine::new();
+    let canister_id = env.install_canister_wat(ON_LOW_WASM_MEMORY_WAT, vec![], None);
+
+    env.update_settings(
+        &canister_id,
+        CanisterSettingsArgs::new(None, None, None, None).with_wasm_memory_threshold(1 << 40),
+    )
+    .unwrap();
+    for _ in 0..5 {
+        env.tick();
+    }
+    assert_eq!(on_low_wasm_memory_hook_count(&env, canister_id), 0);
+}
@@ -257,6 +257,7 @@ fn dashboard_label_value_from(code: ErrorCode) -> &'static str {
         CanisterFunctionNotFound => "Canister Function Not Found",
         CanisterAlreadyInstalled => "Canister Already Installed",
         CanisterWasmModuleNotFound => "Canister WASM Module Not Found",
+        CanisterSnapshotNotFound => "Canister Snapshot Not Found",
         CanisterNonEmpty => "Canister Non-Empty",
         CanisterOutOfCycles => "Canister Out Of Cycles",
         CanisterTrapped => "Canister Trapped",
@@ -294,6 +295,8 @@ fn dashboard_label_value_from(code: ErrorCode) -> &'static str {
         QueryCallGraphTooDeep => "Query call graph contains too many nested calls",
         QueryCallGraphTotalInstructionLimitExceeded => "Total instructions limit exceeded for query call graph",
         CompositeQueryCalledInReplicatedMode => "Composite query cannot be called in replicated mode",
+        ReservedCyclesLimitExceeded => "Canister cannot reserve enough cycles within its reserved cycles limit",
+        CanisterSnapshotLimitExceeded => "Canister has reached the maximum number of snapshots",
         CanisterNotHostedBySubnet => "Canister is not hosted by subnet",
     }
 }
@@ -1,13 +1,13 @@
 use ic_canister_sandbox_replica_controller::sandboxed_execution_controller::SandboxedExecutionController;
 use ic_config::flag_status::FlagStatus;
 use ic_config::{embedders::Config as EmbeddersConfig, execution_environment::Config};
-use ic_cycles_account_manager::CyclesAccountManager;
+use ic_cycles_account_manager::{CyclesAccountManager, ResourceSaturation};
 use ic_embedders::wasm_executor::{WasmExecutionResult, WasmExecutor};
 use ic_embedders::wasm_utils::decoding::decoded_wasm_size;
 use ic_embedders::{wasm_executor::WasmExecutorImpl, WasmExecutionInput, WasmtimeEmbedder};
 use ic_embedders::{CompilationCache, CompilationResult};
 use ic_interfaces::execution_environment::{HypervisorResult, WasmExecutionOutput};
-use ic_logger::ReplicaLogger;
+use ic_logger::{warn, ReplicaLogger};
 use ic_metrics::buckets::decimal_buckets_with_zero;
 use ic_metrics::{buckets::exponential_buckets, MetricsRegistry};
 use ic_registry_subnet_type::SubnetType;
@@ -201,6 +201,12 @@ impl Hypervisor {
 
         let wasm_executor: Arc<dyn WasmExecutor> = match config.canister_sandboxing_flag {
             FlagStatus::Enabled => {
+                if config.instruction_profile_output.is_some() {
+                    warn!(
+                        log,
+                        "Instruction profiling is not supported with canister sandboxing enabled"
+                    );
+                }
                 let executor = SandboxedExecutionController::new(
                     log.clone(),
                     metrics_registry,
@@ -211,6 +217,8 @@ impl Hypervisor {
                 Arc::new(executor)
             }
             FlagStatus::Disabled => {
+                embedder_config.instruction_profile_output =
+                    config.instruction_profile_output.clone();
                 let executor = WasmExecutorImpl::new(
                     WasmtimeEmbedder::new(embedder_config, log.clone()),
                     metrics_registry,
@@ -314,6 +322,10 @@ impl Hypervisor {
             time,
             network_topology,
             self.own_subnet_id,
+            // This function is used only for queries whose memory changes
+            // are not persisted, so no cycles need to be reserved.
+            &ResourceSaturation::default(),
+            &self.cycles_account_manager,
             &self.log,
         );
         (output, execution_state, system_state)
@@ -941,6 +941,102 @@ fn ic0_global_timer_set_returns_previous_value() {
     assert_eq!(result, WasmResult::Reply(vec![]));
 }
 
+#[test]
+fn ic0_in_replicated_execution_works() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (import "ic0" "in_replicated_execution"
+                (func $in_replicated_execution (result i32)))
+            (import "ic0" "msg_reply" (func $msg_reply))
+            (import "ic0" "msg_reply_data_append"
+                (func $msg_reply_data_append (param i32 i32)))
+            (func $reply_in_replicated_execution
+                (i32.store8 (i32.const 0) (call $in_replicated_execution))
+                (call $msg_reply_data_append (i32.const 0) (i32.const 1))
+                (call $msg_reply)
+            )
+            (func (export "canister_update update") (call $reply_in_replicated_execution))
+            (func (export "canister_query query") (call $reply_in_replicated_execution))
+            (memory 1)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    let result = test.ingress(canister_id, "update", vec![]).unwrap();
+    assert_eq!(result, WasmResult::Reply(vec![1]));
+    let result = test.ingress(canister_id, "query", vec![]).unwrap();
+    assert_eq!(result, WasmResult::Reply(vec![1]));
+    let result = test.anonymous_query(canister_id, "query", vec![]).unwrap();
+    assert_eq!(result, WasmResult::Reply(vec![0]));
+}
+
+#[test]
+fn ic0_is_controller_works() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (import "ic0" "is_controller" (func $is_controller (param i32 i32) (result i32)))
+            (import "ic0" "msg_arg_data_size" (func $msg_arg_data_size (result i32)))
+            (import "ic0" "msg_arg_data_copy"
+                (func $msg_arg_data_copy (param i32 i32 i32)))
+            (import "ic0" "msg_reply" (func $msg_reply))
+            (import "ic0" "msg_reply_data_append"
+                (func $msg_reply_data_append (param i32 i32)))
+            (func (export "canister_update test")
+                ;; The argument is the principal to check.
+                (call $msg_arg_data_copy (i32.const 1) (i32.const 0) (call $msg_arg_data_size))
+                (i32.store8 (i32.const 0)
+                    (call $is_controller (i32.const 1) (call $msg_arg_data_size)))
+                (call $msg_reply_data_append (i32.const 0) (i32.const 1))
+                (call $msg_reply)
+            )
+            (memory 1)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    let controller = test.user_id().get();
+    let result = test
+        .ingress(canister_id, "test", controller.as_slice().to_vec())
+        .unwrap();
+    assert_eq!(result, WasmResult::Reply(vec![1]));
+    let result = test
+        .ingress(canister_id, "test", canister_id.get().as_slice().to_vec())
+        .unwrap();
+    assert_eq!(result, WasmResult::Reply(vec![0]));
+    // An invalid principal traps.
+    let err = test.ingress(canister_id, "test", vec![0; 30]).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterContractViolation);
+}
+
+#[test]
+fn ic0_cost_apis_match_fees() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (import "ic0" "cost_create_canister" (func $cost_create_canister (param i32)))
+            (import "ic0" "cost_http_request"
+                (func $cost_http_request (param i64 i64 i32)))
+            (import "ic0" "msg_reply" (func $msg_reply))
+            (import "ic0" "msg_reply_data_append"
+                (func $msg_reply_data_append (param i32 i32)))
+            (func (export "canister_update test")
+                (call $cost_create_canister (i32.const 0))
+                (call $cost_http_request (i64.const 100) (i64.const 2000) (i32.const 16))
+                (call $msg_reply_data_append (i32.const 0) (i32.const 32))
+                (call $msg_reply)
+            )
+            (memory 1)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    let result = test.ingress(canister_id, "test", vec![]).unwrap();
+    let expected = [
+        test.canister_creation_fee().get().to_le_bytes(),
+        test.http_request_fee(NumBytes::from(100), Some(NumBytes::from(2000)))
+            .get()
+            .to_le_bytes(),
+    ]
+    .concat();
+    assert_eq!(result, WasmResult::Reply(expected));
+}
+
 #[test]
 fn ic0_canister_version_returns_correct_value() {
     let mut test = ExecutionTestBuilder::new().build();
@@ -2048,6 +2144,77 @@ fn subnet_available_memory_is_respected_by_memory_grow() {
     assert_eq!(ErrorCode::CanisterOutOfMemory, err.code());
 }
 
+#[test]
+fn wasm_memory_limit_is_respected_by_memory_grow() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (func (export "canister_update grow_small")
+                (drop (memory.grow (i32.const 1)))
+            )
+            (func (export "canister_update grow_large")
+                (drop (memory.grow (i32.const 10)))
+            )
+            (memory 1 20)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    test.update_wasm_memory_limit(canister_id, NumBytes::from(5 * WASM_PAGE_SIZE as u64))
+        .unwrap();
+
+    let result = test.ingress(canister_id, "grow_small", vec![]);
+    assert_empty_reply(result);
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(2)
+    );
+
+    let err = test.ingress(canister_id, "grow_large", vec![]).unwrap_err();
+    assert_eq!(ErrorCode::CanisterOutOfMemory, err.code());
+    assert!(err.description().contains("wasm_memory_limit"));
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(2)
+    );
+
+    // A limit of zero removes the limit.
+    test.update_wasm_memory_limit(canister_id, NumBytes::from(0))
+        .unwrap();
+    let result = test.ingress(canister_id, "grow_large", vec![]);
+    assert_empty_reply(result);
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(12)
+    );
+}
+
+#[test]
+fn wasm_memory_limit_is_ignored_in_upgrade() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (func (export "canister_pre_upgrade")
+                (if (i32.eq (memory.grow (i32.const 10)) (i32.const -1))
+                    (then (unreachable))
+                )
+            )
+            (func (export "canister_post_upgrade")
+                (if (i32.eq (memory.grow (i32.const 10)) (i32.const -1))
+                    (then (unreachable))
+                )
+            )
+            (memory 1 20)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    test.update_wasm_memory_limit(canister_id, NumBytes::from(WASM_PAGE_SIZE as u64))
+        .unwrap();
+    test.upgrade_canister(canister_id, wat::parse_str(wat).unwrap())
+        .unwrap();
+    assert_eq!(
+        test.execution_state(canister_id).wasm_memory.size,
+        NumWasmPages::new(11)
+    );
+}
+
 #[test]
 fn subnet_available_memory_is_updated() {
     let mut test = ExecutionTestBuilder::new().build();
@@ -4681,3 +4848,74 @@ fn cycles_correct_if_update_fails() {
         initial_cycles - test.canister_execution_cost(b_id)
     );
 }
+
+#[test]
+fn canister_can_execute_simd_instructions() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (import "ic0" "msg_reply" (func $msg_reply))
+            (import "ic0" "msg_reply_data_append"
+                (func $msg_reply_data_append (param i32 i32)))
+            (func (export "canister_update test")
+                (v128.store (i32.const 0)
+                    (i32x4.add
+                        (v128.const i32x4 1 2 3 4)
+                        (v128.const i32x4 10 20 30 40)))
+                ;; 0/0 produces NaNs whose bit patterns depend on the hardware
+                ;; unless they are canonicalized.
+                (v128.store (i32.const 16)
+                    (f32x4.div
+                        (v128.const f32x4 0 0 0 0)
+                        (v128.const f32x4 0 0 0 0)))
+                (v128.store (i32.const 32)
+                    (f64x2.sqrt (v128.const f64x2 -1 -2)))
+                (call $msg_reply_data_append (i32.const 0) (i32.const 48))
+                (call $msg_reply))
+            (memory 1)
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+    let result = test.ingress(canister_id, "test", vec![]).unwrap();
+
+    let mut expected = vec![];
+    for value in [11_i32, 22, 33, 44] {
+        expected.extend_from_slice(&value.to_le_bytes());
+    }
+    for _ in 0..4 {
+        expected.extend_from_slice(&0x7fc0_0000_u32.to_le_bytes());
+    }
+    for _ in 0..2 {
+        expected.extend_from_slice(&0x7ff8_0000_0000_0000_u64.to_le_bytes());
+    }
+    assert_eq!(result, WasmResult::Reply(expected));
+}
+
+#[test]
+fn simd_instructions_are_charged_by_weight() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let wat = r#"
+        (module
+            (func (export "canister_update add")
+                (drop (f32x4.add (v128.const f32x4 1 2 3 4) (v128.const f32x4 1 2 3 4))))
+            (func (export "canister_update mul")
+                (drop (f32x4.mul (v128.const f32x4 1 2 3 4) (v128.const f32x4 1 2 3 4))))
+            (func (export "canister_update div")
+                (drop (f32x4.div (v128.const f32x4 1 2 3 4) (v128.const f32x4 1 2 3 4))))
+        )"#;
+    let canister_id = test.canister_from_wat(wat).unwrap();
+
+    let mut executed_instructions = vec![];
+    for method in ["add", "mul", "div"] {
+        let before = test.executed_instructions();
+        test.ingress(canister_id, method, vec![]).unwrap();
+        executed_instructions.push(test.executed_instructions() - before);
+    }
+    assert_eq!(
+        executed_instructions[1],
+        executed_instructions[0] + NumInstructions::from(1)
+    );
+    assert_eq!(
+        executed_instructions[2],
+        executed_instructions[0] + NumInstructions::from(3)
+    );
+}
@@ -35,13 +35,13 @@ use ic_metrics::MetricsRegistry;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::page_map::PageAllocatorFileDescriptor;
 use ic_replicated_state::{CallOrigin, NetworkTopology, ReplicatedState};
-use ic_types::{messages::CallContextId, SubnetId};
+use ic_types::{batch::LocalQueryStats, messages::CallContextId, SubnetId};
 use ingress_filter::IngressFilter;
-pub use query_handler::InternalHttpQueryHandler;
+pub use query_handler::{init_query_stats, InternalHttpQueryHandler, QueryStatsCollector};
 use query_handler::{HttpQueryHandler, QueryScheduler, QuerySchedulerFlag};
 pub use scheduler::RoundSchedule;
 use scheduler::SchedulerImpl;
-use std::sync::Arc;
+use std::sync::{mpsc::Receiver, Arc};
 use tower::limit::GlobalConcurrencyLimitLayer;
 
 const MAX_INFLIGHT_QUERIES_PER_THREAD: usize = 100;
@@ -86,6 +86,7 @@ pub struct ExecutionServices {
     pub async_query_handler: QueryExecutionService,
     pub anonymous_query_handler: AnonymousQueryService,
     pub scheduler: Box<dyn Scheduler<State = ReplicatedState>>,
+    pub query_stats_receiver: Receiver<LocalQueryStats>,
 }
 
 impl ExecutionServices {
@@ -133,6 +134,9 @@ impl ExecutionServices {
             config.clone(),
             Arc::clone(&cycles_account_manager),
         ));
+        let (query_stats_collector, query_stats_receiver) =
+            init_query_stats(config.query_stats_epoch_length);
+        let query_stats_collector = Arc::new(query_stats_collector);
         let sync_query_handler = Arc::new(InternalHttpQueryHandler::new(
             logger.clone(),
             hypervisor,
@@ -142,6 +146,7 @@ impl ExecutionServices {
             scheduler_config.max_instructions_per_message_without_dts,
             Arc::clone(&cycles_account_manager),
             config.composite_queries,
+            Arc::clone(&query_stats_collector),
         ));
 
         let query_scheduler = QueryScheduler::new(
@@ -161,6 +166,7 @@ impl ExecutionServices {
             Arc::clone(&sync_query_handler) as Arc<_>,
             query_scheduler.clone(),
             Arc::clone(&state_reader),
+            query_stats_collector,
         );
         let ingress_filter = IngressFilter::new_service(
             concurrency_buffer.clone(),
@@ -197,6 +203,7 @@ impl ExecutionServices {
             async_query_handler,
             anonymous_query_handler,
             scheduler,
+            query_stats_receiver,
         }
     }
 
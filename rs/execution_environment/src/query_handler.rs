@@ -3,6 +3,7 @@
 
 mod query_context;
 mod query_scheduler;
+mod query_stats;
 #[cfg(test)]
 mod tests;
 
@@ -16,6 +17,10 @@ use ic_config::flag_status::FlagStatus;
 use ic_crypto_tree_hash::{flatmap, Label, LabeledTree, LabeledTree::SubTree};
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_error_types::{ErrorCode, RejectCode, UserError};
+use ic_ic00_types::{
+    FetchCanisterLogsRequest, FetchCanisterLogsResponse, LogVisibility, Method as Ic00Method,
+    Payload,
+};
 use ic_interfaces::execution_environment::{QueryExecutionService, QueryHandler};
 use ic_interfaces_state_manager::StateReader;
 use ic_logger::ReplicaLogger;
@@ -28,13 +33,14 @@ use ic_types::{
         Blob, Certificate, CertificateDelegation, HttpQueryResponse, HttpQueryResponseReply,
         UserQuery,
     },
-    CanisterId, NumInstructions,
+    CanisterId, Height, NumInstructions, PrincipalId,
 };
 use serde::Serialize;
 use std::{
     convert::Infallible,
     future::Future,
     pin::Pin,
+    str::FromStr,
     sync::Arc,
     task::{Context, Poll},
 };
@@ -42,6 +48,7 @@ use tokio::sync::oneshot;
 use tower::{limit::GlobalConcurrencyLimitLayer, util::BoxCloneService, Service, ServiceBuilder};
 
 pub(crate) use self::query_scheduler::{QueryScheduler, QuerySchedulerFlag};
+pub use self::query_stats::{init_query_stats, QueryStatsCollector};
 
 /// Convert an object into CBOR binary.
 fn into_cbor<R: Serialize>(r: &R) -> Vec<u8> {
@@ -55,7 +62,7 @@ fn get_latest_certified_state_and_data_certificate(
     state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
     certificate_delegation: Option<CertificateDelegation>,
     canister_id: CanisterId,
-) -> Option<(Arc<ReplicatedState>, Vec<u8>)> {
+) -> Option<(Arc<ReplicatedState>, Vec<u8>, Height)> {
     // The path to fetch the data certificate for the canister.
     let path = SubTree(flatmap! {
         label("canister") => SubTree(
@@ -80,6 +87,7 @@ fn get_latest_certified_state_and_data_certificate(
                     signature: Blob(cert.signed.signature.signature.get().0),
                     delegation: certificate_delegation,
                 }),
+                cert.height,
             )
         })
 }
@@ -88,6 +96,47 @@ fn label<T: Into<Label>>(t: T) -> Label {
     t.into()
 }
 
+/// Returns the log of the given canister if the sender is allowed to see it.
+fn fetch_canister_logs(
+    sender: PrincipalId,
+    state: &ReplicatedState,
+    args: FetchCanisterLogsRequest,
+) -> Result<FetchCanisterLogsResponse, UserError> {
+    let canister_id = args.get_canister_id();
+    let canister = state.canister_state(&canister_id).ok_or_else(|| {
+        UserError::new(
+            ErrorCode::CanisterNotFound,
+            format!("Canister {} not found", canister_id),
+        )
+    })?;
+
+    match canister.system_state.log_visibility {
+        LogVisibility::Public => {}
+        LogVisibility::Controllers => {
+            if !canister.controllers().contains(&sender) {
+                return Err(UserError::new(
+                    ErrorCode::CanisterInvalidController,
+                    format!(
+                        "Caller {} is not allowed to query ic00 method {}",
+                        sender,
+                        Ic00Method::FetchCanisterLogs
+                    ),
+                ));
+            }
+        }
+    }
+
+    Ok(FetchCanisterLogsResponse {
+        canister_log_records: canister
+            .system_state
+            .canister_log
+            .records()
+            .iter()
+            .cloned()
+            .collect(),
+    })
+}
+
 pub struct InternalHttpQueryHandler {
     log: ReplicaLogger,
     hypervisor: Arc<Hypervisor>,
@@ -97,6 +146,7 @@ pub struct InternalHttpQueryHandler {
     max_instructions_per_query: NumInstructions,
     cycles_account_manager: Arc<CyclesAccountManager>,
     composite_queries: FlagStatus,
+    query_stats: Arc<QueryStatsCollector>,
 }
 
 #[derive(Clone)]
@@ -105,9 +155,11 @@ pub(crate) struct HttpQueryHandler {
     internal: Arc<dyn QueryHandler<State = ReplicatedState>>,
     state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
     query_scheduler: QueryScheduler,
+    query_stats: Arc<QueryStatsCollector>,
 }
 
 impl InternalHttpQueryHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         log: ReplicaLogger,
         hypervisor: Arc<Hypervisor>,
@@ -117,6 +169,7 @@ impl InternalHttpQueryHandler {
         max_instructions_per_query: NumInstructions,
         cycles_account_manager: Arc<CyclesAccountManager>,
         composite_queries: FlagStatus,
+        query_stats: Arc<QueryStatsCollector>,
     ) -> Self {
         Self {
             log,
@@ -127,6 +180,39 @@ impl InternalHttpQueryHandler {
             max_instructions_per_query,
             cycles_account_manager,
             composite_queries,
+            query_stats,
+        }
+    }
+}
+
+impl InternalHttpQueryHandler {
+    fn execute_management_query(
+        &self,
+        query: &UserQuery,
+        state: &ReplicatedState,
+    ) -> Result<WasmResult, UserError> {
+        match Ic00Method::from_str(&query.method_name) {
+            Ok(Ic00Method::FetchCanisterLogs) => {
+                let args =
+                    FetchCanisterLogsRequest::decode(&query.method_payload).map_err(|err| {
+                        UserError::new(
+                            ErrorCode::InvalidManagementPayload,
+                            format!(
+                                "Failed to decode payload for ic00 method {}: {}",
+                                query.method_name, err
+                            ),
+                        )
+                    })?;
+                fetch_canister_logs(query.source.get(), state, args)
+                    .map(|response| WasmResult::Reply(response.encode()))
+            }
+            _ => Err(UserError::new(
+                ErrorCode::CanisterMethodNotFound,
+                format!(
+                    "Query method {} not found in the management canister.",
+                    query.method_name
+                ),
+            )),
         }
     }
 }
@@ -140,6 +226,11 @@ impl QueryHandler for InternalHttpQueryHandler {
         state: Arc<ReplicatedState>,
         data_certificate: Vec<u8>,
     ) -> Result<WasmResult, UserError> {
+        // Queries to the management canister do not execute any Wasm code.
+        if query.receiver == CanisterId::ic_00() {
+            return self.execute_management_query(&query, &state);
+        }
+
         let measurement_scope = MeasurementScope::root(&self.metrics.query);
 
         // Letting the canister grow arbitrarily when executing the
@@ -160,6 +251,7 @@ impl QueryHandler for InternalHttpQueryHandler {
             self.config.max_instructions_per_composite_query_call,
             self.config.instruction_overhead_per_query_call,
             self.composite_queries,
+            &self.query_stats,
         );
         context.run(
             query,
@@ -176,11 +268,13 @@ impl HttpQueryHandler {
         internal: Arc<dyn QueryHandler<State = ReplicatedState>>,
         query_scheduler: QueryScheduler,
         state_reader: Arc<dyn StateReader<State = ReplicatedState>>,
+        query_stats: Arc<QueryStatsCollector>,
     ) -> QueryExecutionService {
         let base_service = BoxCloneService::new(Self {
             internal,
             state_reader,
             query_scheduler,
+            query_stats,
         });
         ServiceBuilder::new()
             .layer(concurrency_buffer)
@@ -217,6 +311,7 @@ impl Service<(UserQuery, Option<CertificateDelegation>)> for HttpQueryHandler {
     ) -> Self::Future {
         let internal = Arc::clone(&self.internal);
         let state_reader = Arc::clone(&self.state_reader);
+        let query_stats = Arc::clone(&self.query_stats);
         let (tx, rx) = oneshot::channel();
         let canister_id = query.receiver;
         self.query_scheduler.push(canister_id, move || {
@@ -230,7 +325,10 @@ impl Service<(UserQuery, Option<CertificateDelegation>)> for HttpQueryHandler {
                     certificate_delegation,
                     query.receiver,
                 ) {
-                    Some((state, cert)) => internal.query(query, state, cert),
+                    Some((state, cert, height)) => {
+                        query_stats.set_epoch_from_height(height);
+                        internal.query(query, state, cert)
+                    }
                     None => Err(UserError::new(
                         ErrorCode::CertifiedStateUnavailable,
                         "Certified state is not available yet. Please try again...",
@@ -7,9 +7,6 @@
 //! execution, i.e. the originator of the processing is a Query from an end-user
 //! and not an Ingress message.
 //!
-//! - Loops are not allowed. E.g. call graphs like A -> B -> C -> A are not
-//! supported.
-//!
 //! Some interesting factoids about inter-canister query execution to keep in
 //! mind:
 //!
@@ -21,16 +18,24 @@
 //!
 //! - Due to the point above, while a canister has not produced a response and
 //! has outstanding requests, we store its modified state in the query context.
-//! And as soon as it has produced a response, we drop its state. Once a
-//! canister's state has been dropped, it becomes available for being queried
-//! again.
+//! And as soon as it has produced a response, we drop its state.
+//!
+//! - Every call context executes on its own version of the canister state that
+//! starts from the state in the replicated state. This allows a canister to
+//! take part in the call graph more than once, e.g. in call graphs like
+//! A -> B -> A or A -> A. The versions of a canister's state that are waiting
+//! for responses are kept in the order in which they were created.
 //!
 //! - We process the outstanding requests in a depth first search manner. This
 //! means that if canister A sends canister B two requests (M1 and M2) back to
 //! back, we first fully traverse the branch from executing M1; drop the
 //! modified state of B after this branch finishes; and then start branch M2 on
 //! a clean version of B. If we did breadth first search, then we would have to
-//! maintain two distinct versions of B.
+//! maintain two distinct versions of B at the same time for the same caller.
+//!
+//! - Due to the depth first search, a canister's state version is only dropped
+//! after all versions created later in its branch were dropped. Hence, a
+//! response to a canister is always for its most recent version.
 //!
 //! - For a lack of a better strategy, always prioritise responses over
 //! requests.
@@ -41,6 +46,7 @@ use crate::{
     execution_environment::{as_round_instructions, RoundLimits},
     hypervisor::Hypervisor,
     metrics::{MeasurementScope, QueryHandlerMetrics},
+    query_handler::query_stats::QueryStatsCollector,
     NonReplicatedQueryKind,
 };
 use ic_base_types::NumBytes;
@@ -51,16 +57,18 @@ use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionMode, HypervisorError, SubnetAvailableMemory,
 };
-use ic_logger::{debug, error, fatal, warn, ReplicaLogger};
+use ic_logger::{debug, fatal, warn, ReplicaLogger};
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
     CallContextAction, CallOrigin, CanisterState, NetworkTopology, ReplicatedState,
 };
 use ic_system_api::{ApiType, ExecutionParameters, InstructionLimits};
 use ic_types::{
+    batch::QueryStats,
     ingress::WasmResult,
     messages::{
         CallbackId, Payload, RejectContext, Request, RequestOrResponse, Response, UserQuery,
+        NO_DEADLINE,
     },
     methods::WasmMethod,
     CanisterId, Cycles, NumInstructions, NumMessages, Time,
@@ -73,9 +81,6 @@ use std::{collections::BTreeMap, sync::Arc};
 
 const ENABLE_QUERY_OPTIMIZATION: bool = true;
 
-const LOOP_DETECTED_ERROR_MSG: &str =
-    "Loop detected.  MVP inter-canister queries do not support loops.";
-
 const CALL_GRAPH_TOO_DEEP_ERROR_MSG: &str =
     "Call exceeded the limit for maximum number of nested query calls.";
 
@@ -90,8 +95,6 @@ enum EnqueueRequestsResult {
     MessagesEnqueued,
     /// The canister had no messages to enqueue.
     NoMessages,
-    /// A loop in the callgraph was detected so no messages were enqueued.
-    LoopDetected,
     /// The call graph is too large
     CallGraphTooDeep,
     /// The total number of instructions executed in call context is too large.
@@ -106,6 +109,7 @@ fn generate_response(request: Arc<Request>, payload: Payload) -> Response {
         originator_reply_callback: request.sender_reply_callback,
         response_payload: payload,
         refund: Cycles::zero(),
+        deadline: request.deadline,
     }
 }
 
@@ -114,10 +118,6 @@ fn generate_response(request: Arc<Request>, payload: Payload) -> Response {
 /// Unless NoMessages or MessagesEnqueued, this is guaranteed to return Some.
 fn map_enqueue_error_to_user(enqueue_error: EnqueueRequestsResult) -> Option<UserError> {
     match enqueue_error {
-        EnqueueRequestsResult::LoopDetected => Some(UserError::new(
-            ErrorCode::QueryCallGraphLoopDetected,
-            LOOP_DETECTED_ERROR_MSG.to_string(),
-        )),
         EnqueueRequestsResult::CallGraphTooDeep => Some(UserError::new(
             ErrorCode::QueryCallGraphTooDeep,
             CALL_GRAPH_TOO_DEEP_ERROR_MSG.to_string(),
@@ -130,6 +130,13 @@ fn map_enqueue_error_to_user(enqueue_error: EnqueueRequestsResult) -> Option<Use
     }
 }
 
+/// A request waiting to be executed together with the version of the sender's
+/// state that sent it.
+struct OutstandingRequest {
+    request: Arc<Request>,
+    sender_version: usize,
+}
+
 /// Handles running a single UserQuery to completion by maintaining the call
 /// graph of the query execution between canisters.
 pub(super) struct QueryContext<'a> {
@@ -140,9 +147,10 @@ pub(super) struct QueryContext<'a> {
     state: Arc<ReplicatedState>,
     network_topology: Arc<NetworkTopology>,
     data_certificate: Vec<u8>,
-    // Contains all canisters that currently have pending calls.
-    call_stack: BTreeMap<CanisterId, CanisterState>,
-    outstanding_requests: Vec<Arc<Request>>,
+    // Contains the state versions of all canisters that currently have pending
+    // calls, from the oldest to the most recent version of each canister.
+    call_stack: BTreeMap<CanisterId, Vec<CanisterState>>,
+    outstanding_requests: Vec<OutstandingRequest>,
     // Response (if available) waiting to be executed. We always process
     // responses first if one is available hence, there will never be more than
     // one outstanding response.
@@ -155,6 +163,8 @@ pub(super) struct QueryContext<'a> {
     instructions_per_composite_query_call: NumInstructions,
     round_limits: RoundLimits,
     composite_queries: FlagStatus,
+    // Collects the statistics of the executed queries.
+    query_stats: &'a QueryStatsCollector,
 }
 
 impl<'a> QueryContext<'a> {
@@ -172,6 +182,7 @@ impl<'a> QueryContext<'a> {
         initial_instructions_for_composite_query: NumInstructions,
         instructions_per_composite_query_call: NumInstructions,
         composite_queries: FlagStatus,
+        query_stats: &'a QueryStatsCollector,
     ) -> Self {
         let network_topology = Arc::new(state.metadata.network_topology.clone());
         let round_limits = RoundLimits {
@@ -198,6 +209,7 @@ impl<'a> QueryContext<'a> {
             instructions_per_composite_query_call,
             round_limits,
             composite_queries,
+            query_stats,
         }
     }
 
@@ -337,7 +349,7 @@ impl<'a> QueryContext<'a> {
                         ),
                     )),
                     EnqueueRequestsResult::MessagesEnqueued => {
-                        self.call_stack.insert(canister.canister_id(), canister);
+                        self.push_call_stack(canister);
                         self.run_loop(canister_id, metrics, measurement_scope)
                     }
                     _ => Err(map_enqueue_error_to_user(r).unwrap()),
@@ -367,7 +379,7 @@ impl<'a> QueryContext<'a> {
                 continue;
             }
 
-            if let Some(request) = self.outstanding_requests.pop() {
+            if let Some(OutstandingRequest { request, .. }) = self.outstanding_requests.pop() {
                 debug!(self.log, "Executing request for {}", request.receiver);
                 if let Some(err) = self.handle_request(request, &measurement_scope) {
                     return Err(err);
@@ -396,6 +408,10 @@ impl<'a> QueryContext<'a> {
     fn enqueue_requests(&mut self, canister: &mut CanisterState) -> EnqueueRequestsResult {
         let mut sent_messages = false;
         let canister_id = canister.canister_id();
+        // The canister is pushed to the call stack after its requests are
+        // enqueued, so its version is the next free one.
+        let sender_version = self.next_version(&canister_id);
+        let call_depth = self.call_depth();
 
         let outgoing_messages: Vec<_> = canister.output_into_iter().map(|(_, msg)| msg).collect();
         let call_context_manager = canister
@@ -430,7 +446,7 @@ impl<'a> QueryContext<'a> {
                         // Messages of these types are not produced by this
                         // module so must have existed on the canister's output
                         // queue from before.
-                        CallOrigin::CanisterUpdate(_, _)
+                        CallOrigin::CanisterUpdate(_, _, _)
                         | CallOrigin::SystemTask
                         | CallOrigin::Ingress(_, _) => continue,
 
@@ -440,16 +456,7 @@ impl<'a> QueryContext<'a> {
                         CallOrigin::Query(_) | CallOrigin::CanisterQuery(_, _) => {}
                     }
 
-                    if msg.receiver == canister_id || self.call_stack.contains_key(&msg.receiver) {
-                        // Call graph loop detector. There is already a canister
-                        // in the call graph that is waiting for some responses
-                        // to come back and this canister is trying to send it
-                        // messages. The MVP inter-canister queries
-                        // implementation does not support loops.
-                        return EnqueueRequestsResult::LoopDetected;
-                    }
-
-                    if self.call_stack.len() + 1 > self.max_query_call_depth {
+                    if call_depth + 1 > self.max_query_call_depth {
                         return EnqueueRequestsResult::CallGraphTooDeep;
                     }
 
@@ -466,7 +473,10 @@ impl<'a> QueryContext<'a> {
                     );
 
                     sent_messages = true;
-                    self.outstanding_requests.push(msg);
+                    self.outstanding_requests.push(OutstandingRequest {
+                        request: msg,
+                        sender_version,
+                    });
                 }
 
                 // Messages of these types are not produced by this
@@ -482,6 +492,36 @@ impl<'a> QueryContext<'a> {
         }
     }
 
+    // Returns the version that the state of the given canister gets when it is
+    // pushed to the call stack next.
+    fn next_version(&self, canister_id: &CanisterId) -> usize {
+        self.call_stack.get(canister_id).map_or(0, Vec::len)
+    }
+
+    // Returns the number of calls in the call graph that are waiting for a
+    // response.
+    fn call_depth(&self) -> usize {
+        self.call_stack.values().map(Vec::len).sum::<usize>()
+    }
+
+    // Pushes the most recent version of the canister's state to the call stack.
+    fn push_call_stack(&mut self, canister: CanisterState) {
+        self.call_stack
+            .entry(canister.canister_id())
+            .or_default()
+            .push(canister);
+    }
+
+    // Pops the most recent version of the canister's state from the call stack.
+    fn pop_call_stack(&mut self, canister_id: &CanisterId) -> Option<CanisterState> {
+        let versions = self.call_stack.get_mut(canister_id)?;
+        let canister = versions.pop();
+        if versions.is_empty() {
+            self.call_stack.remove(canister_id);
+        }
+        canister
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn execute_query(
         &mut self,
@@ -521,6 +561,19 @@ impl<'a> QueryContext<'a> {
             NumSlices::from(1),
             NumMessages::from(1),
         );
+        let egress_payload_size = match &result {
+            Ok(Some(WasmResult::Reply(reply))) => reply.len() as u64,
+            Ok(Some(WasmResult::Reject(_))) | Ok(None) | Err(_) => 0,
+        };
+        self.query_stats.register_query_statistics(
+            canister.canister_id(),
+            &QueryStats {
+                num_calls: 1,
+                num_instructions: instructions_executed.get(),
+                ingress_payload_size: method_payload.len() as u64,
+                egress_payload_size,
+            },
+        );
         (canister, result)
     }
 
@@ -565,7 +618,7 @@ impl<'a> QueryContext<'a> {
         };
         let func_ref = match call_origin {
             CallOrigin::Ingress(_, _)
-            | CallOrigin::CanisterUpdate(_, _)
+            | CallOrigin::CanisterUpdate(_, _, _)
             | CallOrigin::SystemTask => unreachable!("Unreachable in the QueryContext."),
             CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => {
                 FuncRef::QueryClosure(closure)
@@ -588,6 +641,7 @@ impl<'a> QueryContext<'a> {
                 payload.to_vec(),
                 incoming_cycles,
                 call_context_id,
+                call_origin.deadline(),
                 call_responded,
                 execution_parameters.execution_mode.clone(),
             ),
@@ -596,6 +650,7 @@ impl<'a> QueryContext<'a> {
                 context,
                 incoming_cycles,
                 call_context_id,
+                call_origin.deadline(),
                 call_responded,
                 execution_parameters.execution_mode.clone(),
             ),
@@ -664,6 +719,15 @@ impl<'a> QueryContext<'a> {
             NumSlices::from(1),
             NumMessages::from(1),
         );
+        // Callbacks are part of the call that they belong to, only the
+        // instructions that they execute are accounted for.
+        self.query_stats.register_query_statistics(
+            canister_id,
+            &QueryStats {
+                num_instructions: instructions_executed.get(),
+                ..QueryStats::default()
+            },
+        );
         (canister, call_origin, action)
     }
 
@@ -685,7 +749,7 @@ impl<'a> QueryContext<'a> {
     ) -> (NumInstructions, Result<Option<WasmResult>, HypervisorError>) {
         let func_ref = match call_origin {
             CallOrigin::Ingress(_, _)
-            | CallOrigin::CanisterUpdate(_, _)
+            | CallOrigin::CanisterUpdate(_, _, _)
             | CallOrigin::SystemTask => unreachable!("Unreachable in the QueryContext."),
             CallOrigin::CanisterQuery(_, _) | CallOrigin::Query(_) => {
                 FuncRef::QueryClosure(cleanup_closure)
@@ -743,13 +807,8 @@ impl<'a> QueryContext<'a> {
             );
         }
 
-        let canister_id = request.receiver;
-        // As we do not support loops in the call graph, the canister that we
-        // want to execute a request on should not already be loaded.
-        if self.call_stack.contains_key(&canister_id) {
-            error!(self.log, "[EXC-BUG] The canister that we want to execute a request on should not already be loaded.");
-        }
-
+        // The request is executed on a new version of the canister's state,
+        // even if the canister already has pending calls in the call graph.
         let canister = match self.state.get_active_canister(&request.receiver) {
             Ok(canister) => canister,
             Err(err) => {
@@ -821,7 +880,7 @@ impl<'a> QueryContext<'a> {
                                 None
                             }
                             EnqueueRequestsResult::MessagesEnqueued => {
-                                self.call_stack.insert(canister.canister_id(), canister);
+                                self.push_call_stack(canister);
                                 None
                             }
                             _ => map_enqueue_error_to_user(r),
@@ -891,7 +950,7 @@ impl<'a> QueryContext<'a> {
                 let r = self.enqueue_requests(&mut canister);
                 match r {
                     EnqueueRequestsResult::NoMessages | EnqueueRequestsResult::MessagesEnqueued => {
-                        self.call_stack.insert(canister.canister_id(), canister);
+                        self.push_call_stack(canister);
                         None
                     }
                     _ => map_enqueue_error_to_user(r).map(|s| Err(s)),
@@ -931,6 +990,7 @@ impl<'a> QueryContext<'a> {
                 originator_reply_callback: callback_id,
                 response_payload: payload,
                 refund: Cycles::zero(),
+                deadline: NO_DEADLINE,
             };
             self.outstanding_response = Some(response);
         };
@@ -948,8 +1008,7 @@ impl<'a> QueryContext<'a> {
                 // The canister has produced a response so remove any other
                 // requests that it may have produced to minimize unnecessary
                 // work.
-                self.outstanding_requests
-                    .retain(|request| request.sender != canister_id);
+                self.remove_outstanding_requests(canister_id);
                 None
             }
 
@@ -967,8 +1026,7 @@ impl<'a> QueryContext<'a> {
                 // The canister has produced a response so remove any other
                 // requests that it may have produced to minimize unnecessary
                 // work.
-                self.outstanding_requests
-                    .retain(|request| request.sender != canister_id);
+                self.remove_outstanding_requests(canister_id);
                 None
             }
 
@@ -1005,7 +1063,7 @@ impl<'a> QueryContext<'a> {
                 let r = self.enqueue_requests(&mut canister);
                 match r {
                     EnqueueRequestsResult::NoMessages | EnqueueRequestsResult::MessagesEnqueued => {
-                        self.call_stack.insert(canister.canister_id(), canister);
+                        self.push_call_stack(canister);
                         None
                     }
                     _ => map_enqueue_error_to_user(r).map(|s| Err(s)),
@@ -1045,7 +1103,7 @@ impl<'a> QueryContext<'a> {
         // As we are executing a response, we must have executed a request on
         // the canister before and must have stored its state so the following
         // should not fail.
-        let canister = self.call_stack.remove(&canister_id).unwrap_or_else(|| {
+        let canister = self.pop_call_stack(&canister_id).unwrap_or_else(|| {
             fatal!(
                 self.log,
                 "Expected to find canister {} in the cache",
@@ -1059,7 +1117,7 @@ impl<'a> QueryContext<'a> {
         match call_origin {
             CallOrigin::Query(_) => self.handle_response_with_query_origin(canister, action),
 
-            CallOrigin::CanisterUpdate(_, _)
+            CallOrigin::CanisterUpdate(_, _, _)
             | CallOrigin::Ingress(_, _)
             | CallOrigin::SystemTask => fatal!(
                 self.log,
@@ -1073,6 +1131,15 @@ impl<'a> QueryContext<'a> {
         }
     }
 
+    // Removes the outstanding requests sent by the most recent version of the
+    // canister's state, which has just been popped from the call stack.
+    fn remove_outstanding_requests(&mut self, canister_id: CanisterId) {
+        let version = self.next_version(&canister_id);
+        self.outstanding_requests.retain(|outstanding| {
+            outstanding.request.sender != canister_id || outstanding.sender_version != version
+        });
+    }
+
     fn execution_parameters(
         &self,
         canister: &CanisterState,
@@ -1081,6 +1148,7 @@ impl<'a> QueryContext<'a> {
         ExecutionParameters {
             instruction_limits,
             canister_memory_limit: canister.memory_limit(self.max_canister_memory_size),
+            wasm_memory_limit: canister.system_state.wasm_memory_limit,
             compute_allocation: canister.scheduler_state.compute_allocation,
             subnet_type: self.own_subnet_type,
             execution_mode: ExecutionMode::NonReplicated,
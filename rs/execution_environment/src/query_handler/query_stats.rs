@@ -0,0 +1,142 @@
+//! Collection of the statistics of the query calls that this replica executes.
+//!
+//! The statistics are accumulated per canister over an epoch of consecutive
+//! heights. Once the certified height moves past the end of an epoch, the
+//! statistics of that epoch are handed to the query stats payload builder,
+//! which gossips them to the other replicas through consensus.
+
+use ic_types::{
+    batch::{epoch_from_height, CanisterQueryStats, LocalQueryStats, QueryStats, QueryStatsEpoch},
+    CanisterId, Height,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Mutex,
+    },
+};
+
+/// Creates a [`QueryStatsCollector`] together with the receiving end of the
+/// channel on which the statistics of finished epochs are sent.
+pub fn init_query_stats(epoch_length: u64) -> (QueryStatsCollector, Receiver<LocalQueryStats>) {
+    let (sender, receiver) = channel();
+    (
+        QueryStatsCollector {
+            epoch_length,
+            inner: Mutex::new(QueryStatsCollectorInner {
+                current_epoch: None,
+                stats: BTreeMap::new(),
+                sender,
+            }),
+        },
+        receiver,
+    )
+}
+
+struct QueryStatsCollectorInner {
+    current_epoch: Option<QueryStatsEpoch>,
+    stats: BTreeMap<CanisterId, QueryStats>,
+    sender: Sender<LocalQueryStats>,
+}
+
+/// Accumulates the statistics of the query calls executed by this replica
+/// during the current epoch.
+pub struct QueryStatsCollector {
+    epoch_length: u64,
+    inner: Mutex<QueryStatsCollectorInner>,
+}
+
+impl QueryStatsCollector {
+    /// Moves the collector to the epoch that `height` belongs to.
+    ///
+    /// If this starts a new epoch, the statistics of the previous epoch are
+    /// sent to the payload builder.
+    pub fn set_epoch_from_height(&self, height: Height) {
+        let epoch = epoch_from_height(height, self.epoch_length);
+        let mut inner = self.inner.lock().unwrap();
+        match inner.current_epoch {
+            Some(current_epoch) if current_epoch >= epoch => {}
+            Some(current_epoch) => {
+                let stats = std::mem::take(&mut inner.stats)
+                    .into_iter()
+                    .map(|(canister_id, stats)| CanisterQueryStats { canister_id, stats })
+                    .collect();
+                // The receiver is gone if no payload builder is running (e.g.
+                // in tests). The statistics are simply dropped in that case.
+                let _ = inner.sender.send(LocalQueryStats {
+                    epoch: current_epoch,
+                    stats,
+                });
+                inner.current_epoch = Some(epoch);
+            }
+            None => inner.current_epoch = Some(epoch),
+        }
+    }
+
+    /// Adds the statistics of a single execution on `canister_id` to the
+    /// statistics of the current epoch.
+    pub fn register_query_statistics(&self, canister_id: CanisterId, stats: &QueryStats) {
+        self.inner
+            .lock()
+            .unwrap()
+            .stats
+            .entry(canister_id)
+            .or_default()
+            .saturating_accumulate(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_test_utilities::types::ids::canister_test_id;
+
+    fn stats(num_instructions: u64) -> QueryStats {
+        QueryStats {
+            num_calls: 1,
+            num_instructions,
+            ingress_payload_size: 10,
+            egress_payload_size: 20,
+        }
+    }
+
+    #[test]
+    fn stats_are_sent_when_epoch_ends() {
+        let (collector, receiver) = init_query_stats(10);
+        collector.set_epoch_from_height(Height::from(5));
+        collector.register_query_statistics(canister_test_id(1), &stats(100));
+        collector.register_query_statistics(canister_test_id(1), &stats(200));
+        collector.register_query_statistics(canister_test_id(2), &stats(300));
+
+        // Still the same epoch, nothing is sent.
+        collector.set_epoch_from_height(Height::from(9));
+        assert!(receiver.try_recv().is_err());
+
+        collector.set_epoch_from_height(Height::from(10));
+        let local_stats = receiver.try_recv().unwrap();
+        assert_eq!(local_stats.epoch, QueryStatsEpoch::from(0));
+        assert_eq!(
+            local_stats.stats,
+            vec![
+                CanisterQueryStats {
+                    canister_id: canister_test_id(1),
+                    stats: QueryStats {
+                        num_calls: 2,
+                        num_instructions: 300,
+                        ingress_payload_size: 20,
+                        egress_payload_size: 40,
+                    },
+                },
+                CanisterQueryStats {
+                    canister_id: canister_test_id(2),
+                    stats: stats(300),
+                },
+            ]
+        );
+
+        // Going back to an older height does not start a new epoch.
+        collector.set_epoch_from_height(Height::from(3));
+        assert!(receiver.try_recv().is_err());
+    }
+}
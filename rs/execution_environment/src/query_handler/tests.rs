@@ -2,13 +2,16 @@ use crate::InternalHttpQueryHandler;
 use ic_base_types::NumSeconds;
 use ic_config::execution_environment::INSTRUCTION_OVERHEAD_PER_QUERY_CALL;
 use ic_error_types::{ErrorCode, UserError};
+use ic_ic00_types::{FetchCanisterLogsRequest, FetchCanisterLogsResponse, LogVisibility, Payload};
 use ic_registry_subnet_type::SubnetType;
 use ic_test_utilities::{
     types::ids::user_test_id,
     universal_canister::{call_args, wasm},
 };
 use ic_test_utilities_execution_environment::{ExecutionTest, ExecutionTestBuilder};
-use ic_types::{ingress::WasmResult, messages::UserQuery, Cycles, NumInstructions};
+use ic_types::{
+    ingress::WasmResult, messages::UserQuery, CanisterId, Cycles, NumInstructions, UserId,
+};
 use std::sync::Arc;
 
 const CYCLES_BALANCE: Cycles = Cycles::new(100_000_000_000_000);
@@ -344,6 +347,125 @@ fn query_callgraph_depth_is_enforced() {
     }
 }
 
+#[test]
+fn query_call_graph_supports_self_loop() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_type(SubnetType::VerifiedApplication)
+        .build();
+    let canister = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+
+    let output = test.query(
+        UserQuery {
+            source: user_test_id(2),
+            receiver: canister,
+            method_name: "query".to_string(),
+            method_payload: wasm()
+                .inter_query(
+                    canister,
+                    call_args().other_side(wasm().reply_data(b"pong".as_ref())),
+                )
+                .build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    assert_eq!(output, Ok(WasmResult::Reply(b"pong".to_vec())));
+}
+
+#[test]
+fn query_call_graph_loop_executes_on_separate_state_versions() {
+    // In this test canister A calls canister B, which calls canister A again.
+    // The second call to A must not see the modifications of the first one.
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_type(SubnetType::VerifiedApplication)
+        .build();
+    let canister_a = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let canister_b = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+
+    let output = test.query(
+        UserQuery {
+            source: user_test_id(2),
+            receiver: canister_a,
+            method_name: "query".to_string(),
+            method_payload: wasm()
+                .set_global_data(b"outer")
+                .inter_query(
+                    canister_b,
+                    call_args()
+                        .other_side(
+                            wasm().inter_query(
+                                canister_a,
+                                call_args().other_side(
+                                    wasm()
+                                        .push_bytes(b"inner:")
+                                        .reply_data_append()
+                                        .get_global_data()
+                                        .append_and_reply(),
+                                ),
+                            ),
+                        )
+                        .on_reply(
+                            wasm()
+                                .message_payload()
+                                .reply_data_append()
+                                .push_bytes(b"|")
+                                .reply_data_append()
+                                .get_global_data()
+                                .append_and_reply(),
+                        ),
+                )
+                .build(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    );
+    assert_eq!(output, Ok(WasmResult::Reply(b"inner:|outer".to_vec())));
+}
+
+#[test]
+fn query_call_graph_depth_is_enforced_for_loops() {
+    let mut test = ExecutionTestBuilder::new()
+        .with_subnet_type(SubnetType::VerifiedApplication)
+        .build();
+    let canister = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+
+    // A chain of `num_calls` nested calls of the canister to itself.
+    fn nested_self_calls(canister: CanisterId, num_calls: usize) -> Vec<u8> {
+        let mut payload = wasm().reply_data(b"pong".as_ref()).build();
+        for _ in 0..num_calls {
+            payload = wasm()
+                .inter_query(canister, call_args().other_side(payload))
+                .build();
+        }
+        payload
+    }
+
+    let query = |num_calls| {
+        test.query(
+            UserQuery {
+                source: user_test_id(2),
+                receiver: canister,
+                method_name: "query".to_string(),
+                method_payload: nested_self_calls(canister, num_calls),
+                ingress_expiry: 0,
+                nonce: None,
+            },
+            Arc::new(test.state().clone()),
+            vec![],
+        )
+    };
+
+    assert_eq!(query(6), Ok(WasmResult::Reply(b"pong".to_vec())));
+    assert_eq!(
+        query(7).unwrap_err().code(),
+        ErrorCode::QueryCallGraphTooDeep
+    );
+}
+
 #[test]
 fn query_callgraph_max_instructions_is_enforced() {
     const NUM_CANISTERS: u64 = 20;
@@ -1000,3 +1122,99 @@ fn composite_query_no_canister_response() {
         WasmResult::Reject(msg) => assert_eq!(msg, "Canister did not reply"),
     }
 }
+
+fn fetch_canister_logs(
+    test: &ExecutionTest,
+    sender: UserId,
+    canister_id: CanisterId,
+) -> Result<WasmResult, UserError> {
+    test.query(
+        UserQuery {
+            source: sender,
+            receiver: CanisterId::ic_00(),
+            method_name: "fetch_canister_logs".to_string(),
+            method_payload: FetchCanisterLogsRequest::new(canister_id).encode(),
+            ingress_expiry: 0,
+            nonce: None,
+        },
+        Arc::new(test.state().clone()),
+        vec![],
+    )
+}
+
+#[test]
+fn fetch_canister_logs_returns_debug_prints_and_traps() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+
+    test.ingress(
+        canister_id,
+        "update",
+        wasm().debug_print(b"hello").reply().build(),
+    )
+    .unwrap();
+    // The log records of a trapped execution are kept.
+    let err = test
+        .ingress(
+            canister_id,
+            "update",
+            wasm().debug_print(b"world").trap_with_blob(b"boom").build(),
+        )
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterCalledTrap);
+
+    let result = fetch_canister_logs(&test, test.user_id(), canister_id);
+    let data = match result {
+        Ok(WasmResult::Reply(data)) => data,
+        other => panic!("Unexpected result: {:?}", other),
+    };
+    let response = FetchCanisterLogsResponse::decode(&data).unwrap();
+    let records: Vec<_> = response
+        .canister_log_records
+        .into_iter()
+        .map(|r| (r.idx, r.content))
+        .collect();
+    assert_eq!(
+        records,
+        vec![
+            (0, b"hello".to_vec()),
+            (1, b"world".to_vec()),
+            (2, b"[TRAP]: boom".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn fetch_canister_logs_respects_log_visibility() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+    let not_a_controller = user_test_id(42);
+
+    let err = fetch_canister_logs(&test, not_a_controller, canister_id).unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterInvalidController);
+
+    test.canister_state_mut(canister_id)
+        .system_state
+        .log_visibility = LogVisibility::Public;
+    let result = fetch_canister_logs(&test, not_a_controller, canister_id);
+    assert_eq!(
+        result,
+        Ok(WasmResult::Reply(
+            FetchCanisterLogsResponse::default().encode()
+        ))
+    );
+}
+
+#[test]
+fn fetch_canister_logs_is_rejected_in_replicated_mode() {
+    let mut test = ExecutionTestBuilder::new().build();
+    let canister_id = test.universal_canister_with_cycles(CYCLES_BALANCE).unwrap();
+
+    let err = test
+        .subnet_message(
+            "fetch_canister_logs",
+            FetchCanisterLogsRequest::new(canister_id).encode(),
+        )
+        .unwrap_err();
+    assert_eq!(err.code(), ErrorCode::CanisterRejectedMessage);
+}
@@ -1,5 +1,5 @@
 use crate::{
-    canister_manager::uninstall_canister,
+    canister_manager::{uninstall_canister, AddCanisterChangeToHistory},
     execution_environment::{
         as_num_instructions, as_round_instructions, execute_canister, ExecuteCanisterResult,
         ExecutionEnvironment, RoundInstructions, RoundLimits,
@@ -515,7 +515,8 @@ impl SchedulerImpl {
 
         let mut total_heap_delta = NumBytes::from(0);
 
-        // Add `Heartbeat` and `GlobalTimer` tasks to be executed before input messages.
+        // Add `Heartbeat`, `GlobalTimer` and `OnLowWasmMemory` tasks to be
+        // executed before input messages.
         let mut heartbeat_and_timer_canister_ids = BTreeSet::new();
         let mut non_zero_priority_credit_canister_ids = BTreeSet::new();
         {
@@ -530,7 +531,8 @@ impl SchedulerImpl {
                     non_zero_priority_credit_canister_ids.insert(canister.system_state.canister_id);
                 }
 
-                // Add `Heartbeat` or `GlobalTimer` for running canisters only.
+                // Add `Heartbeat`, `GlobalTimer` or `OnLowWasmMemory` for
+                // running canisters only.
                 match canister.system_state.status {
                     CanisterStatus::Running { .. } => {}
                     CanisterStatus::Stopping { .. } | CanisterStatus::Stopped => {
@@ -540,6 +542,11 @@ impl SchedulerImpl {
 
                 let global_timer_has_reached_deadline =
                     canister.system_state.global_timer.has_reached_deadline(now);
+                let is_low_wasm_memory = canister.is_low_wasm_memory();
+                if !is_low_wasm_memory {
+                    // Re-arm the hook once the canister has enough free memory.
+                    canister.system_state.on_low_wasm_memory_hook_executed = false;
+                }
                 match canister.next_execution() {
                     NextExecution::ContinueLong | NextExecution::ContinueInstallCode => {
                         // Do not add a heartbeat task if a long execution
@@ -562,6 +569,16 @@ impl SchedulerImpl {
                                 .push_front(ExecutionTask::GlobalTimer);
                             heartbeat_and_timer_canister_ids.insert(canister.canister_id());
                         }
+                        if is_low_wasm_memory
+                            && !canister.system_state.on_low_wasm_memory_hook_executed
+                            && canister.exports_on_low_wasm_memory_method()
+                        {
+                            canister
+                                .system_state
+                                .task_queue
+                                .push_front(ExecutionTask::OnLowWasmMemory);
+                            heartbeat_and_timer_canister_ids.insert(canister.canister_id());
+                        }
                     }
                 }
             }
@@ -665,12 +682,15 @@ impl SchedulerImpl {
                 .metrics
                 .round_inner_heartbeat_overhead_duration
                 .start_timer();
-            // Remove all remaining `Heartbeat` and `GlobalTimer` tasks
-            // because they will be added again in the next round.
+            // Remove all remaining `Heartbeat`, `GlobalTimer` and
+            // `OnLowWasmMemory` tasks because they will be added again in the
+            // next round if needed.
             for canister_id in &heartbeat_and_timer_canister_ids {
                 let canister = state.canister_state_mut(canister_id).unwrap();
                 canister.system_state.task_queue.retain(|task| match task {
-                    ExecutionTask::Heartbeat | ExecutionTask::GlobalTimer => false,
+                    ExecutionTask::Heartbeat
+                    | ExecutionTask::GlobalTimer
+                    | ExecutionTask::OnLowWasmMemory => false,
                     ExecutionTask::PausedExecution(..)
                     | ExecutionTask::PausedInstallCode(..)
                     | ExecutionTask::AbortedExecution { .. }
@@ -921,6 +941,9 @@ impl SchedulerImpl {
         self.metrics
             .canister_balance
             .observe(canister.system_state.balance().get() as f64);
+        self.metrics
+            .canister_reserved_balance
+            .observe(canister.system_state.reserved_balance().get() as f64);
         if let Some(es) = &canister.execution_state {
             self.metrics
                 .canister_binary_size
@@ -956,7 +979,8 @@ impl SchedulerImpl {
     ) {
         let state_time = state.time();
         let mut all_rejects = Vec::new();
-        for canister in state.canisters_iter_mut() {
+        let canister_snapshots = &state.canister_snapshots;
+        for canister in state.canister_states.values_mut() {
             // Postpone charging for resources when a canister has a paused execution
             // to avoid modifying the balance of a canister during an unfinished operation.
             if canister.has_paused_execution() || canister.has_paused_install_code() {
@@ -982,12 +1006,18 @@ impl SchedulerImpl {
                     .charge_canister_for_resource_allocation_and_usage(
                         &self.log,
                         canister,
+                        canister_snapshots.memory_taken_by_canister(canister.canister_id()),
                         duration_since_last_charge,
                         subnet_size,
                     )
                     .is_err()
                 {
-                    all_rejects.push(uninstall_canister(&self.log, canister, state_time));
+                    all_rejects.push(uninstall_canister(
+                        &self.log,
+                        canister,
+                        state_time,
+                        AddCanisterChangeToHistory::No,
+                    ));
                     canister.scheduler_state.compute_allocation = ComputeAllocation::zero();
                     canister.system_state.memory_allocation = MemoryAllocation::BestEffort;
                     // Burn the remaining balance of the canister.
@@ -1223,7 +1253,7 @@ impl SchedulerImpl {
             .iter()
             .filter(|(_, canister)| !canister.system_state.task_queue.is_empty());
 
-        // 1. Heartbeat and GlobalTimer tasks exist only during the round
+        // 1. Heartbeat, GlobalTimer and OnLowWasmMemory tasks exist only during the round
         //    and must not exist after the round.
         // 2. Paused executions can exist only in ordinary rounds (not checkpoint rounds).
         // 3. If deterministic time slicing is disabled, then there are no paused tasks.
@@ -1245,6 +1275,12 @@ impl SchedulerImpl {
                             id
                         );
                     }
+                    ExecutionTask::OnLowWasmMemory => {
+                        panic!(
+                            "Unexpected on low Wasm memory task after a round in canister {:?}",
+                            id
+                        );
+                    }
                     ExecutionTask::PausedExecution(_) | ExecutionTask::PausedInstallCode(_) => {
                         assert_eq!(
                             self.deterministic_time_slicing,
@@ -1351,6 +1387,7 @@ impl Scheduler for SchedulerImpl {
 
             for canister in state.canisters_iter_mut() {
                 cycles_in_sum += canister.system_state.balance();
+                cycles_in_sum += canister.system_state.reserved_balance();
                 cycles_in_sum += canister.system_state.queues().input_queue_cycles();
             }
         }
@@ -1498,6 +1535,7 @@ impl Scheduler for SchedulerImpl {
         {
             let mut cycles_out_sum = Cycles::zero();
             let mut total_canister_balance = Cycles::zero();
+            let mut total_canister_reserved_balance = Cycles::zero();
             let mut total_canister_memory_usage = NumBytes::new(0);
             let _timer = self.metrics.round_finalization_duration.start_timer();
             let own_subnet_type = state.metadata.own_subnet_type;
@@ -1527,13 +1565,18 @@ impl Scheduler for SchedulerImpl {
                     };
                 total_canister_memory_usage += canister.memory_usage(own_subnet_type);
                 total_canister_balance += canister.system_state.balance();
+                total_canister_reserved_balance += canister.system_state.reserved_balance();
                 cycles_out_sum += canister.system_state.queues().output_queue_cycles();
             }
             cycles_out_sum += total_canister_balance;
+            cycles_out_sum += total_canister_reserved_balance;
 
             self.metrics
                 .total_canister_balance
                 .set(total_canister_balance.get() as f64);
+            self.metrics
+                .total_canister_reserved_balance
+                .set(total_canister_reserved_balance.get() as f64);
 
             // TODO(EXC-1124): Re-enable the check below once it's fixed.
             //
@@ -1873,7 +1916,10 @@ fn observe_replicated_state_metrics(
             Some(&ExecutionTask::AbortedInstallCode { .. }) => {
                 num_aborted_install += 1;
             }
-            Some(&ExecutionTask::Heartbeat) | Some(&ExecutionTask::GlobalTimer) | None => {}
+            Some(&ExecutionTask::Heartbeat)
+            | Some(&ExecutionTask::GlobalTimer)
+            | Some(&ExecutionTask::OnLowWasmMemory)
+            | None => {}
         }
         consumed_cycles_total += canister
             .system_state
@@ -2020,7 +2066,9 @@ fn can_execute_msg(
         };
 
         // Only one install code message allowed at a time.
-        if let Some(Ic00Method::InstallCode) = maybe_instal_code_method {
+        if let Some(Ic00Method::InstallCode) | Some(Ic00Method::InstallChunkedCode) =
+            maybe_instal_code_method
+        {
             return false;
         }
     }
@@ -2076,8 +2124,16 @@ fn get_instructions_limits_for_subnet_message(
             | BitcoinGetCurrentFeePercentiles
             | BitcoinGetSuccessors
             | ProvisionalCreateCanisterWithCycles
-            | ProvisionalTopUpCanister => default_limits,
-            InstallCode => InstructionLimits::new(
+            | ProvisionalTopUpCanister
+            | TakeCanisterSnapshot
+            | LoadCanisterSnapshot
+            | ListCanisterSnapshots
+            | DeleteCanisterSnapshot
+            | FetchCanisterLogs
+            | UploadChunk
+            | ClearChunkStore
+            | StoredChunks => default_limits,
+            InstallCode | InstallChunkedCode => InstructionLimits::new(
                 dts,
                 config.max_instructions_per_install_code,
                 config.max_instructions_per_install_code_slice,
@@ -23,6 +23,7 @@ pub(super) struct SchedulerMetrics {
     pub(super) canister_age: Histogram,
     pub(super) canister_compute_allocation_violation: IntCounter,
     pub(super) canister_balance: Histogram,
+    pub(super) canister_reserved_balance: Histogram,
     pub(super) canister_binary_size: Histogram,
     pub(super) canister_wasm_memory_usage: Histogram,
     pub(super) canister_stable_memory_usage: Histogram,
@@ -82,6 +83,7 @@ pub(super) struct SchedulerMetrics {
     pub(super) scheduler_accumulated_priority_deviation: Gauge,
     pub(super) subnet_memory_usage_invariant: IntCounter,
     pub(super) total_canister_balance: Gauge,
+    pub(super) total_canister_reserved_balance: Gauge,
     pub(super) canister_paused_execution: Histogram,
     pub(super) canister_aborted_execution: Histogram,
     pub(super) canister_paused_install_code: Histogram,
@@ -116,6 +118,11 @@ impl SchedulerMetrics {
                 "Canisters balance distribution in Cycles.",
                 metrics_registry,
             ),
+            canister_reserved_balance: cycles_histogram(
+                "canister_reserved_balance_cycles",
+                "Canisters reserved balance distribution in Cycles.",
+                metrics_registry,
+            ),
             canister_binary_size: memory_histogram(
                 "canister_binary_size_bytes",
                 "Canisters WASM binary size distribution in bytes.",
@@ -544,6 +551,10 @@ impl SchedulerMetrics {
                 "scheduler_canister_balance_cycles_total",
                 "Total canister balance in Cycles.",
             ),
+            total_canister_reserved_balance: metrics_registry.gauge(
+                "scheduler_canister_reserved_balance_cycles_total",
+                "Total canister reserved balance in Cycles.",
+            ),
             canister_paused_execution: dts_pause_or_abort_histogram(
                 "scheduler_canister_paused_execution",
                 "Number of canisters that have a paused execution.",
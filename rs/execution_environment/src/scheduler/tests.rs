@@ -39,7 +39,9 @@ use ic_test_utilities::{
 use ic_test_utilities_metrics::{
     fetch_counter, fetch_gauge, fetch_int_gauge, fetch_int_gauge_vec, metric_vec,
 };
-use ic_types::messages::{CallbackId, Payload, RejectContext, Response, MAX_RESPONSE_COUNT_BYTES};
+use ic_types::messages::{
+    CallbackId, Payload, RejectContext, Response, MAX_RESPONSE_COUNT_BYTES, NO_DEADLINE,
+};
 use ic_types::methods::SystemMethod;
 use ic_types::{time::UNIX_EPOCH, ComputeAllocation, Cycles, NumBytes};
 use proptest::prelude::*;
@@ -2870,6 +2872,7 @@ fn ecdsa_signature_agreements_metric_is_updated() {
             code: RejectCode::SysFatal,
             message: "".into(),
         }),
+        deadline: NO_DEADLINE,
     };
 
     test.state_mut().consensus_queue.push(response);
@@ -2918,6 +2921,7 @@ fn ecdsa_signature_agreements_metric_is_updated() {
             }
             .encode(),
         ),
+        deadline: NO_DEADLINE,
     };
 
     test.state_mut().consensus_queue.push(response);
@@ -4,10 +4,10 @@ use ic_config::{
 };
 use ic_registry_subnet_type::SubnetType;
 use ic_state_machine_tests::{
-    CanisterSettingsArgs, ErrorCode, StateMachine, StateMachineConfig, UserError,
+    CanisterSettingsArgs, ErrorCode, StateMachine, StateMachineConfig, StateMachineEnv, UserError,
 };
 use ic_types::{ingress::WasmResult, Cycles, NumBytes};
-use ic_universal_canister::{wasm, UNIVERSAL_CANISTER_WASM};
+use ic_universal_canister::{call_args, wasm, UNIVERSAL_CANISTER_WASM};
 use std::{convert::TryInto, time::Duration};
 
 const INITIAL_CYCLES_BALANCE: Cycles = Cycles::new(100_000_000_000_000);
@@ -749,3 +749,36 @@ fn max_canister_memory_respected_even_when_no_memory_allocation_is_set() {
     );
     assert_replied(res, 0);
 }
+
+#[test]
+fn inter_canister_call_across_subnets() {
+    let env = StateMachineEnv::new(2);
+    let subnet_ids = env.subnet_ids();
+    let install = |subnet_id| {
+        env.subnet(subnet_id)
+            .install_canister_with_cycles(
+                UNIVERSAL_CANISTER_WASM.into(),
+                vec![],
+                None,
+                INITIAL_CYCLES_BALANCE,
+            )
+            .unwrap()
+    };
+    let a_id = install(subnet_ids[0]);
+    let b_id = install(subnet_ids[1]);
+    assert_eq!(env.route(b_id).unwrap().get_subnet_id(), subnet_ids[1]);
+
+    let result = env.execute_ingress(
+        a_id,
+        "update",
+        wasm()
+            .inter_update(
+                b_id.get(),
+                call_args().other_side(wasm().reply_data(b"pong")),
+            )
+            .build(),
+    );
+    assert_eq!(result, Ok(WasmResult::Reply(b"pong".to_vec())));
+
+    env.run_until_completion(10);
+}
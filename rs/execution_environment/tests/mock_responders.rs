@@ -0,0 +1,213 @@
+use candid::{Decode, Encode};
+use ic_crypto_ecdsa_secp256k1::PublicKey;
+use ic_crypto_sha::Sha256;
+use ic_ic00_types::{
+    self as ic00, CanisterHttpRequestArgs, CanisterHttpResponsePayload, ECDSAPublicKeyArgs,
+    ECDSAPublicKeyResponse, EcdsaCurve, EcdsaKeyId, HttpHeader, HttpMethod, SignWithECDSAArgs,
+    SignWithECDSAReply,
+};
+use ic_registry_subnet_features::SubnetFeatures;
+use ic_state_machine_tests::{
+    CanisterId, Cycles, IngressState, IngressStatus, MockResponse, PrincipalId, RejectCode,
+    StateMachine, StateMachineBuilder, UserError, WasmResult,
+};
+use ic_types::canister_http::CANISTER_HTTP_TIMEOUT_INTERVAL;
+use ic_universal_canister::{call_args, wasm, UNIVERSAL_CANISTER_WASM};
+use std::{str::FromStr, time::Duration};
+
+const INITIAL_CYCLES_BALANCE: Cycles = Cycles::new(100_000_000_000_000);
+const PAYMENT: Cycles = Cycles::new(1_000_000_000_000);
+
+fn install_universal_canister(env: &StateMachine) -> CanisterId {
+    env.install_canister_with_cycles(
+        UNIVERSAL_CANISTER_WASM.into(),
+        vec![],
+        None,
+        INITIAL_CYCLES_BALANCE,
+    )
+    .unwrap()
+}
+
+/// Returns a universal canister payload that calls `method` of the management
+/// canister and relays the reply or the reject message.
+fn call_management_canister(method: ic00::Method, args: Vec<u8>) -> Vec<u8> {
+    wasm()
+        .call_with_cycles(
+            ic00::IC_00,
+            method,
+            call_args()
+                .other_side(args)
+                .on_reject(wasm().reject_message().reject()),
+            PAYMENT.into_parts(),
+        )
+        .build()
+}
+
+fn http_request_args(url: &str) -> Vec<u8> {
+    Encode!(&CanisterHttpRequestArgs {
+        url: url.to_string(),
+        max_response_bytes: None,
+        headers: vec![],
+        body: None,
+        method: HttpMethod::GET,
+        transform: None,
+    })
+    .unwrap()
+}
+
+fn http_outcalls_env() -> StateMachine {
+    StateMachineBuilder::new()
+        .with_features(SubnetFeatures::from_str("http_requests").unwrap())
+        .build()
+}
+
+#[test]
+fn http_outcall_is_answered_by_responder() {
+    let env = http_outcalls_env();
+    env.set_http_outcall_responder(|context| {
+        MockResponse::Reply(CanisterHttpResponsePayload {
+            status: 200,
+            headers: vec![HttpHeader {
+                name: "content-type".to_string(),
+                value: "text/plain".to_string(),
+            }],
+            body: context.url.as_bytes().to_vec(),
+        })
+    });
+    let canister_id = install_universal_canister(&env);
+
+    let result = env
+        .execute_ingress(
+            canister_id,
+            "update",
+            call_management_canister(
+                ic00::Method::HttpRequest,
+                http_request_args("https://example.com"),
+            ),
+        )
+        .unwrap();
+    let response = match result {
+        WasmResult::Reply(data) => Decode!(&data, CanisterHttpResponsePayload).unwrap(),
+        WasmResult::Reject(message) => panic!("Unexpected reject: {}", message),
+    };
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"https://example.com".to_vec());
+    assert!(env.canister_http_request_contexts().is_empty());
+}
+
+#[test]
+fn http_outcall_is_rejected_by_responder() {
+    let env = http_outcalls_env();
+    env.set_http_outcall_responder(|_| {
+        MockResponse::Reject(RejectCode::SysTransient, "connection refused".to_string())
+    });
+    let canister_id = install_universal_canister(&env);
+
+    let result = env.execute_ingress(
+        canister_id,
+        "update",
+        call_management_canister(
+            ic00::Method::HttpRequest,
+            http_request_args("https://example.com"),
+        ),
+    );
+    assert_eq!(
+        result,
+        Ok(WasmResult::Reject("connection refused".to_string()))
+    );
+}
+
+#[test]
+fn pending_http_outcall_times_out() {
+    let env = http_outcalls_env();
+    env.set_http_outcall_responder(|_| MockResponse::Pending);
+    let canister_id = install_universal_canister(&env);
+
+    let msg_id = env.send_ingress(
+        PrincipalId::new_anonymous(),
+        canister_id,
+        "update",
+        call_management_canister(
+            ic00::Method::HttpRequest,
+            http_request_args("https://example.com"),
+        ),
+    );
+    env.tick();
+    env.tick();
+    assert_eq!(env.canister_http_request_contexts().len(), 1);
+    assert!(matches!(
+        env.ingress_status(&msg_id),
+        IngressStatus::Known {
+            state: IngressState::Processing,
+            ..
+        }
+    ));
+
+    env.advance_time(CANISTER_HTTP_TIMEOUT_INTERVAL + Duration::from_secs(1));
+    let result = env.await_ingress(msg_id, 10);
+    assert_eq!(
+        result,
+        Ok(WasmResult::Reject(
+            "Canister http request timed out".to_string()
+        ))
+    );
+}
+
+#[test]
+fn sign_with_ecdsa_is_signed_with_derived_test_key() {
+    let key_id = EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: "test_key".to_string(),
+    };
+    let env = StateMachineBuilder::new()
+        .with_ecdsa_key(key_id.clone())
+        .build();
+    env.set_sign_with_ecdsa_responder(|_| MockResponse::Reply(()));
+    let canister_id = install_universal_canister(&env);
+    let derivation_path = vec![b"path".to_vec()];
+
+    let reply = |result: Result<WasmResult, UserError>| match result {
+        Ok(WasmResult::Reply(data)) => data,
+        result => panic!("Unexpected result: {:?}", result),
+    };
+    let public_key = reply(
+        env.execute_ingress(
+            canister_id,
+            "update",
+            call_management_canister(
+                ic00::Method::ECDSAPublicKey,
+                Encode!(&ECDSAPublicKeyArgs {
+                    canister_id: None,
+                    derivation_path: derivation_path.clone(),
+                    key_id: key_id.clone(),
+                })
+                .unwrap(),
+            ),
+        ),
+    );
+    let public_key = Decode!(&public_key, ECDSAPublicKeyResponse)
+        .unwrap()
+        .public_key;
+
+    let message = b"message to sign";
+    let signature = reply(
+        env.execute_ingress(
+            canister_id,
+            "update",
+            call_management_canister(
+                ic00::Method::SignWithECDSA,
+                Encode!(&SignWithECDSAArgs {
+                    message_hash: Sha256::hash(message),
+                    derivation_path,
+                    key_id,
+                })
+                .unwrap(),
+            ),
+        ),
+    );
+    let signature = Decode!(&signature, SignWithECDSAReply).unwrap().signature;
+
+    let public_key = PublicKey::deserialize_sec1(&public_key).unwrap();
+    assert!(public_key.verify_signature(message, &signature));
+    assert!(env.sign_with_ecdsa_contexts().is_empty());
+}
@@ -88,6 +88,7 @@ pub(crate) fn make_response(user_error: UserError) -> Response<Body> {
         C::CanisterMethodNotFound => StatusCode::NOT_FOUND,
         C::CanisterAlreadyInstalled => StatusCode::PRECONDITION_FAILED,
         C::CanisterWasmModuleNotFound => StatusCode::SERVICE_UNAVAILABLE,
+        C::CanisterSnapshotNotFound => StatusCode::NOT_FOUND,
         C::InsufficientMemoryAllocation => StatusCode::SERVICE_UNAVAILABLE,
         C::InsufficientCyclesForCreateCanister => StatusCode::SERVICE_UNAVAILABLE,
         C::SubnetNotFound => StatusCode::NOT_FOUND,
@@ -118,6 +119,8 @@ pub(crate) fn make_response(user_error: UserError) -> Response<Body> {
         C::QueryCallGraphTooDeep => StatusCode::INTERNAL_SERVER_ERROR,
         C::QueryCallGraphTotalInstructionLimitExceeded => StatusCode::INTERNAL_SERVER_ERROR,
         C::CompositeQueryCalledInReplicatedMode => StatusCode::INTERNAL_SERVER_ERROR,
+        C::ReservedCyclesLimitExceeded => StatusCode::SERVICE_UNAVAILABLE,
+        C::CanisterSnapshotLimitExceeded => StatusCode::SERVICE_UNAVAILABLE,
         C::CanisterNotHostedBySubnet => StatusCode::NOT_FOUND,
     };
     make_plaintext_response(status, user_error.description().to_string())
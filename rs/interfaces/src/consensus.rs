@@ -9,6 +9,10 @@ use crate::{
         IngressPayloadValidationError, IngressPermanentError, IngressTransientError,
     },
     messaging::{InvalidXNetPayload, XNetPayloadValidationError, XNetTransientValidationError},
+    query_stats::{
+        InvalidQueryStatsPayload, QueryStatsPayloadValidationError,
+        QueryStatsTransientValidationError,
+    },
     self_validating_payload::{
         InvalidSelfValidatingPayload, SelfValidatingPayloadValidationError,
         SelfValidatingTransientValidationError,
@@ -47,6 +51,7 @@ pub enum PayloadPermanentError {
     },
     SelfValidatingPayloadValidationError(InvalidSelfValidatingPayload),
     CanisterHttpPayloadValidationError(CanisterHttpPermanentValidationError),
+    QueryStatsPayloadValidationError(InvalidQueryStatsPayload),
 }
 
 #[derive(Debug)]
@@ -57,6 +62,7 @@ pub enum PayloadTransientError {
     SubnetNotFound(SubnetId),
     SelfValidatingPayloadValidationError(SelfValidatingTransientValidationError),
     CanisterHttpPayloadValidationError(CanisterHttpTransientValidationError),
+    QueryStatsPayloadValidationError(QueryStatsTransientValidationError),
 }
 
 /// Payload validation error
@@ -97,3 +103,12 @@ impl From<CanisterHttpPayloadValidationError> for PayloadValidationError {
         )
     }
 }
+
+impl From<QueryStatsPayloadValidationError> for PayloadValidationError {
+    fn from(err: QueryStatsPayloadValidationError) -> Self {
+        err.map(
+            PayloadPermanentError::QueryStatsPayloadValidationError,
+            PayloadTransientError::QueryStatsPayloadValidationError,
+        )
+    }
+}
@@ -1,6 +1,6 @@
 use ic_base_types::{CanisterIdError, PrincipalIdBlobParseError};
 use ic_error_types::UserError;
-use ic_types::{methods::WasmMethod, CanisterId, Cycles, NumInstructions};
+use ic_types::{methods::WasmMethod, CanisterId, Cycles, NumBytes, NumInstructions};
 use ic_wasm_types::{WasmEngineError, WasmInstrumentationError, WasmValidationError};
 use serde::{Deserialize, Serialize};
 
@@ -135,6 +135,26 @@ pub enum HypervisorError {
     },
     /// A canister has written too much new data in a single message.
     MemoryAccessLimitExceeded(String),
+    /// An attempt was made to grow the Wasm heap of the canister above its
+    /// `wasm_memory_limit` setting.
+    WasmMemoryLimitExceeded {
+        bytes: NumBytes,
+        limit: NumBytes,
+    },
+    /// The canister does not have enough cycles to reserve for allocating
+    /// `bytes` of memory on a subnet whose memory usage is above the threshold.
+    InsufficientCyclesInMemoryGrow {
+        bytes: NumBytes,
+        available: Cycles,
+        requested: Cycles,
+    },
+    /// Reserving cycles for allocating `bytes` of memory would exceed the
+    /// canister's `reserved_cycles_limit` setting.
+    ReservedCyclesLimitExceededInMemoryGrow {
+        bytes: NumBytes,
+        requested: Cycles,
+        limit: Cycles,
+    },
 }
 
 impl From<WasmInstrumentationError> for HypervisorError {
@@ -314,6 +334,41 @@ impl HypervisorError {
                 format!("Canister exceeded memory access limits: {}", s)
 
             ),
+            Self::WasmMemoryLimitExceeded { bytes, limit } => UserError::new(
+                E::CanisterOutOfMemory,
+                format!(
+                    "Canister {} attempted to grow its Wasm memory to {} bytes \
+                    which exceeds its wasm_memory_limit of {} bytes",
+                    canister_id, bytes, limit
+                ),
+            ),
+            Self::InsufficientCyclesInMemoryGrow {
+                bytes,
+                available,
+                requested,
+            } => UserError::new(
+                E::CanisterOutOfCycles,
+                format!(
+                    "Canister cannot grow memory by {} bytes due to insufficient cycles. \
+                    At least {} additional cycles are required.",
+                    bytes,
+                    requested - available
+                ),
+            ),
+            Self::ReservedCyclesLimitExceededInMemoryGrow {
+                bytes,
+                requested,
+                limit,
+            } => UserError::new(
+                E::ReservedCyclesLimitExceeded,
+                format!(
+                    "Canister cannot grow memory by {} bytes due to its reserved cycles limit. \
+                    The current limit ({}) would be exceeded by {}.",
+                    bytes,
+                    limit,
+                    requested - limit,
+                ),
+            ),
         }
     }
 
@@ -344,6 +399,13 @@ impl HypervisorError {
             HypervisorError::Aborted => "Aborted",
             HypervisorError::SliceOverrun { .. } => "SliceOverrun",
             HypervisorError::MemoryAccessLimitExceeded(_) => "MemoryAccessLimitExceeded",
+            HypervisorError::WasmMemoryLimitExceeded { .. } => "WasmMemoryLimitExceeded",
+            HypervisorError::InsufficientCyclesInMemoryGrow { .. } => {
+                "InsufficientCyclesInMemoryGrow"
+            }
+            HypervisorError::ReservedCyclesLimitExceededInMemoryGrow { .. } => {
+                "ReservedCyclesLimitExceededInMemoryGrow"
+            }
         }
     }
 }
@@ -17,6 +17,7 @@ pub mod ingress_manager;
 pub mod ingress_pool;
 pub mod messages;
 pub mod messaging;
+pub mod query_stats;
 pub mod self_validating_payload;
 pub mod time_source;
 pub mod validation;
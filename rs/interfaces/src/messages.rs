@@ -1,7 +1,9 @@
 //! Messages used in various components.
+use ic_ic00_types::CanisterChangeOrigin;
 use ic_types::{
-    messages::{Ingress, Request, Response, StopCanisterContext},
+    messages::{Ingress, Request, Response, StopCanisterContext, NO_DEADLINE},
     methods::SystemMethod,
+    time::CoarseTime,
     CanisterId, Cycles, PrincipalId,
 };
 use std::{convert::TryFrom, sync::Arc};
@@ -78,6 +80,15 @@ impl CanisterCall {
         }
     }
 
+    /// Returns the deadline of the call: that of the request for best-effort
+    /// calls; `NO_DEADLINE` for guaranteed response calls and ingress messages.
+    pub fn deadline(&self) -> CoarseTime {
+        match self {
+            CanisterCall::Request(request) => request.deadline,
+            CanisterCall::Ingress(_) => NO_DEADLINE,
+        }
+    }
+
     /// Extracts the cycles received with this message.
     pub fn take_cycles(&mut self) -> Cycles {
         match self {
@@ -85,6 +96,20 @@ impl CanisterCall {
             CanisterCall::Ingress(_) => Cycles::zero(),
         }
     }
+
+    /// Returns the origin of a canister change triggered by this message.
+    /// `sender_canister_version` is only recorded for calls from canisters.
+    pub fn canister_change_origin(
+        &self,
+        sender_canister_version: Option<u64>,
+    ) -> CanisterChangeOrigin {
+        match self {
+            CanisterCall::Request(request) => {
+                CanisterChangeOrigin::from_canister(request.sender.get(), sender_canister_version)
+            }
+            CanisterCall::Ingress(ingress) => CanisterChangeOrigin::from_user(ingress.source.get()),
+        }
+    }
 }
 
 impl From<CanisterCall> for StopCanisterContext {
@@ -122,6 +147,7 @@ impl TryFrom<CanisterMessage> for CanisterCall {
 pub enum CanisterTask {
     Heartbeat,
     GlobalTimer,
+    OnLowWasmMemory,
 }
 
 impl From<CanisterTask> for SystemMethod {
@@ -129,6 +155,7 @@ impl From<CanisterTask> for SystemMethod {
         match task {
             CanisterTask::Heartbeat => SystemMethod::CanisterHeartbeat,
             CanisterTask::GlobalTimer => SystemMethod::CanisterGlobalTimer,
+            CanisterTask::OnLowWasmMemory => SystemMethod::CanisterOnLowWasmMemory,
         }
     }
 }
@@ -138,6 +165,7 @@ impl Display for CanisterTask {
         match self {
             Self::Heartbeat => write!(f, "Heartbeat task"),
             Self::GlobalTimer => write!(f, "Global timer task"),
+            Self::OnLowWasmMemory => write!(f, "On low Wasm memory task"),
         }
     }
 }
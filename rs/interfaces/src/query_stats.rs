@@ -0,0 +1,88 @@
+//! Query stats related public interfaces.
+use crate::validation::ValidationError;
+use ic_types::{
+    batch::{QueryStatsEpoch, QueryStatsPayload, ValidationContext},
+    consensus::Payload,
+    registry::RegistryClientError,
+    CanisterId, Height, NodeId, NumBytes, Time,
+};
+
+/// A [`QueryStatsPayload`] error from which it is not possible to recover.
+#[derive(Debug)]
+pub enum InvalidQueryStatsPayload {
+    /// The payload reports statistics of an epoch that has not ended yet at
+    /// the certified height of the validation context.
+    EpochNotFinished {
+        epoch: QueryStatsEpoch,
+        certified_epoch: QueryStatsEpoch,
+    },
+    /// The proposer is not a member of the subnet.
+    ProposerNotMember(NodeId),
+    /// The proposer is not the maker of the block that includes the payload.
+    ProposerNotBlockMaker {
+        proposer: NodeId,
+        block_maker: NodeId,
+    },
+    /// The statistics of the canister were already included by the same
+    /// proposer for the same epoch.
+    DuplicateCanisterId(CanisterId),
+}
+
+/// A [`QueryStatsPayload`] error from which it may be possible to recover.
+#[derive(Debug)]
+pub enum QueryStatsTransientValidationError {
+    /// The subnet membership could not be retrieved from the registry.
+    RegistryUnavailable(RegistryClientError),
+}
+
+/// A [`QueryStatsPayload`] error that results from payload validation.
+pub type QueryStatsPayloadValidationError =
+    ValidationError<InvalidQueryStatsPayload, QueryStatsTransientValidationError>;
+
+/// Builds and validates the payloads that gossip the locally collected query
+/// statistics through consensus.
+pub trait QueryStatsPayloadBuilder: Send + Sync {
+    /// Produces a [`QueryStatsPayload`] of maximum byte size `byte_limit`
+    /// containing the query statistics that this replica collected during
+    /// the most recent finished epoch and that have not yet been included in
+    /// `past_payloads` or in the state at the certified height.
+    ///
+    /// Returns `None` if there is nothing left to include.
+    fn get_query_stats_payload(
+        &self,
+        validation_context: &ValidationContext,
+        past_payloads: &[&QueryStatsPayload],
+        byte_limit: NumBytes,
+    ) -> Option<QueryStatsPayload>;
+
+    /// Checks whether the provided [`QueryStatsPayload`] is valid given a
+    /// [`ValidationContext`] and `past_payloads` (the query stats payloads
+    /// from all blocks above the certified height, in descending block height
+    /// order).
+    ///
+    /// If valid, returns the payload's `CountBytes` size; else returns a
+    /// permanent or transient [`ValidationError`].
+    fn validate_query_stats_payload(
+        &self,
+        payload: Option<&QueryStatsPayload>,
+        validation_context: &ValidationContext,
+        past_payloads: &[&QueryStatsPayload],
+    ) -> Result<NumBytes, QueryStatsPayloadValidationError>;
+
+    /// Extracts the sequence of past [`QueryStatsPayload`]s from `past_payloads`.
+    fn filter_past_payloads<'a>(
+        &self,
+        past_payloads: &'a [(Height, Time, Payload)],
+    ) -> Vec<&'a QueryStatsPayload> {
+        past_payloads
+            .iter()
+            .filter_map(|(_, _, payload)| {
+                if payload.is_summary() {
+                    None
+                } else {
+                    payload.as_ref().as_data().batch.query_stats.as_ref()
+                }
+            })
+            .collect()
+    }
+}
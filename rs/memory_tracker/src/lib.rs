@@ -796,13 +796,17 @@ mod checksum {
             access_addr: *const libc::c_void,
             access_kind: AccessKind,
         ) {
+            // Offsets into 64-bit heaps can exceed 4 GiB, so the products
+            // below may overflow.
             self.index += 1;
-            self.value += self.index
-                * (access_addr as usize - base_addr)
-                * match access_kind {
-                    AccessKind::Read => 1,
-                    AccessKind::Write => 1 << 32,
-                };
+            self.value = self.value.wrapping_add(
+                self.index
+                    .wrapping_mul(access_addr as usize - base_addr)
+                    .wrapping_mul(match access_kind {
+                        AccessKind::Read => 1,
+                        AccessKind::Write => 1 << 32,
+                    }),
+            );
         }
     }
 
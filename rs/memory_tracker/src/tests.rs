@@ -3,7 +3,7 @@ use std::{io::Write, ops::Range};
 use ic_logger::replica_logger::no_op_logger;
 use ic_replicated_state::{page_map::TestPageAllocatorFileDescriptorImpl, PageIndex, PageMap};
 use ic_sys::{PageBytes, PAGE_SIZE};
-use ic_types::Height;
+use ic_types::{Height, MAX_WASM64_MEMORY_IN_BYTES};
 use libc::c_void;
 use nix::sys::mman::{mmap, MapFlags, ProtFlags};
 use std::sync::Arc;
@@ -612,6 +612,34 @@ fn prefetch_for_writes_after_reads_unordered() {
     );
 }
 
+#[test]
+fn tracks_pages_above_4_gib() {
+    // A 64-bit heap can be larger than 4 GiB, so page offsets no longer fit
+    // in a `u32`.
+    let memory_pages = MAX_WASM64_MEMORY_IN_BYTES as usize / PAGE_SIZE;
+    let page = PageIndex::new((4 << 30) / PAGE_SIZE as u64 + 1);
+    with_setup(
+        1,
+        memory_pages,
+        vec![page],
+        DirtyPageTracking::Track,
+        |tracker, page_map| {
+            sigsegv(&tracker, page, AccessKind::Read);
+            assert!(tracker.accessed_pages().borrow().is_marked(page));
+            let contents = unsafe {
+                std::slice::from_raw_parts(
+                    (tracker.area().addr() as *const u8).add(page.get() as usize * PAGE_SIZE),
+                    PAGE_SIZE,
+                )
+            };
+            assert_eq!(contents, page_map.get_page(page));
+
+            sigsegv(&tracker, page, AccessKind::Write);
+            assert_eq!(tracker.take_dirty_pages(), vec![page]);
+        },
+    );
+}
+
 #[test]
 fn page_bitmap_restrict_to_unaccessed() {
     let mut bitmap = PageBitmap::new(10);
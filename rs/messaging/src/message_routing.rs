@@ -73,6 +73,8 @@ const PHASE_COMMIT: &str = "commit";
 const METRIC_PROCESS_BATCH_DURATION: &str = "mr_process_batch_duration_seconds";
 const METRIC_PROCESS_BATCH_PHASE_DURATION: &str = "mr_process_batch_phase_duration_seconds";
 const METRIC_TIMED_OUT_REQUESTS_TOTAL: &str = "mr_timed_out_requests_total";
+const METRIC_TIMED_OUT_CALLBACKS_TOTAL: &str = "mr_timed_out_callbacks_total";
+const METRIC_SHED_MESSAGES_TOTAL: &str = "mr_shed_messages_total";
 
 const CRITICAL_ERROR_MISSING_SUBNET_SIZE: &str = "cycles_account_manager_missing_subnet_size_error";
 const CRITICAL_ERROR_NO_CANISTER_ALLOCATION_RANGE: &str = "mr_empty_canister_allocation_range";
@@ -253,6 +255,10 @@ pub(crate) struct MessageRoutingMetrics {
     critical_error_no_canister_allocation_range: IntCounter,
     /// Number of timed out requests.
     pub timed_out_requests_total: IntCounter,
+    /// Number of expired best-effort callbacks.
+    pub timed_out_callbacks_total: IntCounter,
+    /// Number of best-effort messages shed due to memory pressure.
+    pub shed_messages_total: IntCounter,
 }
 
 impl MessageRoutingMetrics {
@@ -300,6 +306,14 @@ impl MessageRoutingMetrics {
                 METRIC_TIMED_OUT_REQUESTS_TOTAL,
                 "Count of timed out requests.",
             ),
+            timed_out_callbacks_total: metrics_registry.int_counter(
+                METRIC_TIMED_OUT_CALLBACKS_TOTAL,
+                "Count of expired best-effort callbacks.",
+            ),
+            shed_messages_total: metrics_registry.int_counter(
+                METRIC_SHED_MESSAGES_TOTAL,
+                "Count of best-effort messages shed due to memory pressure.",
+            ),
         }
     }
 
@@ -943,6 +957,7 @@ impl MessageRoutingImpl {
             stream_builder,
             log.clone(),
             Arc::clone(&metrics),
+            hypervisor_config.query_stats_epoch_length,
         ));
 
         let batch_processor = Box::new(BatchProcessorImpl::new(
@@ -1,5 +1,6 @@
 use crate::message_routing::LatencyMetrics;
 use ic_base_types::NumBytes;
+use ic_certification_version::CertificationVersion;
 use ic_constants::SYSTEM_SUBNET_STREAM_MSG_LIMIT;
 use ic_error_types::RejectCode;
 use ic_logger::{error, warn, ReplicaLogger};
@@ -14,7 +15,7 @@ use ic_replicated_state::{
 use ic_types::{
     messages::{
         Payload, RejectContext, Request, RequestOrResponse, Response,
-        MAX_INTER_CANISTER_PAYLOAD_IN_BYTES,
+        MAX_INTER_CANISTER_PAYLOAD_IN_BYTES, NO_DEADLINE,
     },
     xnet::QueueId,
     CountBytes, SubnetId,
@@ -74,6 +75,7 @@ const LABEL_VALUE_TYPE_RESPONSE: &str = "response";
 const LABEL_VALUE_STATUS_SUCCESS: &str = "success";
 const LABEL_VALUE_STATUS_CANISTER_NOT_FOUND: &str = "canister_not_found";
 const LABEL_VALUE_STATUS_PAYLOAD_TOO_LARGE: &str = "payload_too_large";
+const LABEL_VALUE_STATUS_BEST_EFFORT_NOT_SUPPORTED: &str = "best_effort_not_supported";
 
 const CRITICAL_ERROR_INFINITE_LOOP: &str = "mr_stream_builder_infinite_loop";
 const CRITICAL_ERROR_PAYLOAD_TOO_LARGE: &str = "mr_stream_builder_payload_too_large";
@@ -198,6 +200,7 @@ impl StreamBuilderImpl {
                             MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN,
                         ),
                     ),
+                    deadline: req.deadline,
                 }
                 .into(),
                 // Arbitrary large amounts, pushing a response always returns memory.
@@ -300,6 +303,14 @@ impl StreamBuilderImpl {
 
         let mut requests_to_reject = Vec::new();
         let mut oversized_requests = Vec::new();
+        let mut best_effort_requests = Vec::new();
+
+        // Deadlines are only encoded into streams from certification version V12
+        // on. Until then, a remote subnet would treat a best-effort request as a
+        // guaranteed response call and respond with a guaranteed response, for
+        // which no response slot was reserved.
+        let remote_best_effort_supported =
+            state.metadata.certification_version >= CertificationVersion::V12;
 
         let mut output_iter = state.output_into_iter();
         let mut last_output_size = usize::MAX;
@@ -368,6 +379,20 @@ impl StreamBuilderImpl {
                             oversized_requests.push(req);
                         }
 
+                        // Remote best-effort request, while deadlines are not yet
+                        // encoded into streams.
+                        RequestOrResponse::Request(req)
+                            if dst_net_id != self.subnet_id
+                                && req.deadline != NO_DEADLINE
+                                && !remote_best_effort_supported =>
+                        {
+                            self.observe_message_type_status(
+                                LABEL_VALUE_TYPE_REQUEST,
+                                LABEL_VALUE_STATUS_BEST_EFFORT_NOT_SUPPORTED,
+                            );
+                            best_effort_requests.push(req);
+                        }
+
                         // Response above the payload size limit.
                         RequestOrResponse::Response(ref mut rep)
                             if rep.payload_size_bytes() > MAX_INTER_CANISTER_PAYLOAD_IN_BYTES =>
@@ -473,6 +498,19 @@ impl StreamBuilderImpl {
             );
         }
 
+        for req in best_effort_requests {
+            let dst_canister_id = req.receiver;
+            self.reject_local_request(
+                &mut state,
+                &req,
+                RejectCode::DestinationInvalid,
+                format!(
+                    "Best-effort calls to canister {} on another subnet are not supported yet",
+                    dst_canister_id
+                ),
+            );
+        }
+
         // Export the total number of enqueued messages and byte size, per stream.
         streams
             .iter()
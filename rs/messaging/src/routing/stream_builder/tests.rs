@@ -1,5 +1,6 @@
 use super::*;
 use ic_base_types::NumSeconds;
+use ic_certification_version::CertificationVersion;
 use ic_error_types::RejectCode;
 use ic_ic00_types::Method;
 use ic_registry_routing_table::{CanisterIdRange, RoutingTable};
@@ -24,8 +25,9 @@ use ic_test_utilities_metrics::{
 use ic_types::{
     messages::{
         CallbackId, Payload, RejectContext, Request, RequestOrResponse, Response,
-        MAX_INTER_CANISTER_PAYLOAD_IN_BYTES_U64,
+        MAX_INTER_CANISTER_PAYLOAD_IN_BYTES_U64, NO_DEADLINE,
     },
+    time::CoarseTime,
     xnet::{StreamIndex, StreamIndexedQueue},
     CanisterId, Cycles, SubnetId, Time,
 };
@@ -120,6 +122,7 @@ fn reject_local_request() {
                             .safe_truncate(MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN)
                             .to_string(),
                     }),
+                    deadline: msg.deadline,
                 }
                 .into(),
                 (u64::MAX / 2).into(),
@@ -184,6 +187,7 @@ fn reject_local_request_for_subnet() {
                         code: RejectCode::SysFatal,
                         message: reject_message.to_string(),
                     }),
+                    deadline: msg.deadline,
                 }
                 .into(),
                 (u64::MAX / 2).into(),
@@ -710,6 +714,7 @@ fn build_streams_with_oversized_payloads() {
             payment: Cycles::new(1),
             method_name: method_name.clone(),
             method_payload: oversized_request_payload.clone(),
+            deadline: NO_DEADLINE,
         };
         assert!(local_request.payload_size_bytes() > MAX_INTER_CANISTER_PAYLOAD_IN_BYTES);
 
@@ -721,6 +726,7 @@ fn build_streams_with_oversized_payloads() {
             payment: Cycles::new(2),
             method_name,
             method_payload: oversized_request_payload,
+            deadline: NO_DEADLINE,
         };
         assert!(remote_request.payload_size_bytes() > MAX_INTER_CANISTER_PAYLOAD_IN_BYTES);
         let remote_request_reject = Response {
@@ -737,6 +743,7 @@ fn build_streams_with_oversized_payloads() {
                     MAX_INTER_CANISTER_PAYLOAD_IN_BYTES
                 ),
             )),
+            deadline: NO_DEADLINE,
         };
 
         // Oversized response: will be replaced with a reject response.
@@ -746,6 +753,7 @@ fn build_streams_with_oversized_payloads() {
             originator_reply_callback: CallbackId::from(3),
             refund: Cycles::new(3),
             response_payload: Payload::Data(oversized_response_payload),
+            deadline: NO_DEADLINE,
         };
         assert!(data_response.payload_size_bytes() > MAX_INTER_CANISTER_PAYLOAD_IN_BYTES);
         let data_response_reject = Response {
@@ -762,6 +770,7 @@ fn build_streams_with_oversized_payloads() {
                     MAX_INTER_CANISTER_PAYLOAD_IN_BYTES
                 ),
             )),
+            deadline: NO_DEADLINE,
         };
 
         // Oversized reject response: will be replaced with a reject response.
@@ -774,6 +783,7 @@ fn build_streams_with_oversized_payloads() {
                 RejectCode::SysTransient,
                 oversized_error_message,
             )),
+            deadline: NO_DEADLINE,
         };
         assert!(reject_response.payload_size_bytes() > MAX_INTER_CANISTER_PAYLOAD_IN_BYTES);
         let reject_response_reject = Response {
@@ -785,6 +795,7 @@ fn build_streams_with_oversized_payloads() {
                 RejectCode::SysTransient,
                 "x".repeat(5 * 1024) + "..." + &"x".repeat(2 * 1024),
             )),
+            deadline: NO_DEADLINE,
         };
 
         let (stream_builder, mut provided_state, metrics_registry) = new_fixture(&log);
@@ -877,6 +888,132 @@ fn build_streams_with_oversized_payloads() {
     });
 }
 
+// Tests that remote best-effort requests are rejected locally for as long as
+// deadlines are not encoded into streams; and routed normally afterwards.
+#[test]
+fn build_streams_with_remote_best_effort_requests() {
+    with_test_replica_logger(|log| {
+        let local_canister = canister_test_id(0);
+        let remote_canister = canister_test_id(1);
+        let deadline = CoarseTime::from_secs_since_unix_epoch(1234);
+
+        // Local best-effort request: routed into the loopback stream.
+        let local_request = RequestBuilder::default()
+            .sender(local_canister)
+            .receiver(local_canister)
+            .sender_reply_callback(CallbackId::from(1))
+            .payment(Cycles::new(1))
+            .deadline(deadline)
+            .build();
+        // Remote best-effort request.
+        let remote_request = RequestBuilder::default()
+            .sender(local_canister)
+            .receiver(remote_canister)
+            .sender_reply_callback(CallbackId::from(2))
+            .payment(Cycles::new(2))
+            .deadline(deadline)
+            .build();
+
+        for (certification_version, remote_best_effort_supported) in [
+            (CertificationVersion::V11, false),
+            (CertificationVersion::V12, true),
+        ] {
+            let (stream_builder, mut provided_state, metrics_registry) = new_fixture(&log);
+            provided_state.metadata.certification_version = certification_version;
+
+            // Map local canister to `LOCAL_SUBNET` and remote canister to `REMOTE_SUBNET`.
+            provided_state.metadata.network_topology.routing_table = Arc::new(
+                RoutingTable::try_from(btreemap! {
+                    CanisterIdRange{ start: local_canister, end: local_canister } => LOCAL_SUBNET,
+                    CanisterIdRange{ start: remote_canister, end: remote_canister } => REMOTE_SUBNET,
+                })
+                .unwrap(),
+            );
+
+            let provided_canister_states = canister_states_with_outputs::<RequestOrResponse>(vec![
+                local_request.clone().into(),
+                remote_request.clone().into(),
+            ]);
+            provided_state.put_canister_states(provided_canister_states);
+
+            // Expecting all canister outputs to have been consumed.
+            let mut expected_state = consume_output_queues(&provided_state);
+
+            let mut expected_loopback_messages = StreamIndexedQueue::with_begin(0.into());
+            expected_loopback_messages.push(local_request.clone().into());
+            let mut expected_streams = btreemap! {
+                LOCAL_SUBNET => Stream::new(expected_loopback_messages, Default::default()),
+            };
+            if remote_best_effort_supported {
+                // Expecting the remote request in the stream to `REMOTE_SUBNET`.
+                let mut expected_remote_messages = StreamIndexedQueue::with_begin(0.into());
+                expected_remote_messages.push(remote_request.clone().into());
+                expected_streams.insert(
+                    REMOTE_SUBNET,
+                    Stream::new(expected_remote_messages, Default::default()),
+                );
+            } else {
+                // Expecting a best-effort reject response for the remote request.
+                let remote_request_reject = Response {
+                    originator: local_canister,
+                    respondent: remote_canister,
+                    originator_reply_callback: CallbackId::from(2),
+                    refund: Cycles::new(2),
+                    response_payload: Payload::Reject(RejectContext::new(
+                        RejectCode::DestinationInvalid,
+                        format!(
+                            "Best-effort calls to canister {} on another subnet are not supported yet",
+                            remote_canister
+                        ),
+                    )),
+                    deadline,
+                };
+                let local_canister = expected_state.canister_state_mut(&local_canister).unwrap();
+                push_input(local_canister, remote_request_reject.into());
+            }
+            expected_state.modify_streams(|streams| {
+                for (subnet_id, stream) in expected_streams {
+                    streams.insert(subnet_id, stream);
+                }
+            });
+
+            // Act
+            let result_state = stream_builder.build_streams(provided_state);
+
+            assert_eq!(expected_state, result_state);
+
+            let expected_routed_messages = if remote_best_effort_supported {
+                metric_vec(&[(
+                    &[
+                        (LABEL_TYPE, LABEL_VALUE_TYPE_REQUEST),
+                        (LABEL_STATUS, LABEL_VALUE_STATUS_SUCCESS),
+                    ],
+                    2,
+                )])
+            } else {
+                metric_vec(&[
+                    (
+                        &[
+                            (LABEL_TYPE, LABEL_VALUE_TYPE_REQUEST),
+                            (LABEL_STATUS, LABEL_VALUE_STATUS_SUCCESS),
+                        ],
+                        1,
+                    ),
+                    (
+                        &[
+                            (LABEL_TYPE, LABEL_VALUE_TYPE_REQUEST),
+                            (LABEL_STATUS, LABEL_VALUE_STATUS_BEST_EFFORT_NOT_SUPPORTED),
+                        ],
+                        1,
+                    ),
+                ])
+            };
+            assert_routed_messages_eq(expected_routed_messages, &metrics_registry);
+            assert_eq_critical_errors(0, 0, &metrics_registry);
+        }
+    });
+}
+
 /// Sets up the `StreamHandlerImpl`, `ReplicatedState` and `MetricsRegistry` to
 /// be used by a test.
 fn new_fixture(log: &ReplicaLogger) -> (StreamBuilderImpl, ReplicatedState, MetricsRegistry) {
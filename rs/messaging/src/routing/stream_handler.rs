@@ -567,6 +567,8 @@ impl StreamHandlerImpl {
     ///    to the reverse stream;
     ///  * `Response` not inducted (canister migrated): reject signal appended
     ///    to loopback stream (canonical versions 9+ only).
+    ///  * best-effort `Response` not inducted (any other reason): silently
+    ///    dropped.
     ///  * `Request` or `Response` silently dropped and accept signal appended
     ///    to loopback stream iff:
     ///     * the sender and source subnet do not match (according to the
@@ -630,8 +632,18 @@ impl StreamHandlerImpl {
                                 let code = reject_code_for_state_error(&err);
                                 stream.push(generate_reject_response(msg, code, err.to_string()))
                             }
+                            RequestOrResponse::Response(response) if response.is_best_effort() => {
+                                // Best-effort responses may be dropped.
+                                debug!(
+                                    self.log,
+                                    "Dropping best-effort response after induction failed with error '{}': {:?}",
+                                    &err,
+                                    response
+                                );
+                            }
                             RequestOrResponse::Response(response) => {
-                                // Critical error, responses should always be inducted successfully.
+                                // Critical error, guaranteed responses should always be inducted
+                                // successfully.
                                 error!(
                                     self.log,
                                     "{}: Inducting response failed: {:?}",
@@ -857,6 +869,7 @@ fn generate_reject_response(
                 message,
                 MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN,
             )),
+            deadline: msg.deadline,
         }
         .into()
     } else {
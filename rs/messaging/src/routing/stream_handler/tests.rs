@@ -31,6 +31,7 @@ use ic_test_utilities_metrics::{
 };
 use ic_types::{
     messages::{CallbackId, Payload, Request, MAX_RESPONSE_COUNT_BYTES},
+    time::CoarseTime,
     xnet::{testing::StreamSliceTesting, StreamIndex, StreamIndexedQueue},
     CanisterId, Cycles,
 };
@@ -1276,6 +1277,7 @@ fn generate_reject_response_queue_full() {
                 RejectCode::SysTransient,
                 err.to_string(),
             )),
+            deadline: msg.deadline,
         }
         .into(),
     );
@@ -1289,6 +1291,33 @@ fn generate_reject_response_queue_full() {
     assert_eq!(expected_stream, stream);
 }
 
+#[test]
+fn generate_reject_response_best_effort_request() {
+    // A best-effort request that failed to be inducted.
+    let msg = RequestBuilder::new()
+        .receiver(*LOCAL_CANISTER)
+        .sender(*REMOTE_CANISTER)
+        .sender_reply_callback(CallbackId::from(1))
+        .deadline(CoarseTime::from_secs_since_unix_epoch(1234))
+        .build();
+    let err = StateError::CanisterNotFound(*LOCAL_CANISTER);
+
+    let response = generate_reject_response(
+        msg.clone().into(),
+        RejectCode::DestinationInvalid,
+        err.to_string(),
+    );
+
+    // The reject `Response` carries over the deadline of the `Request`.
+    match response {
+        RequestOrResponse::Response(response) => {
+            assert_eq!(msg.deadline, response.deadline);
+            assert!(response.is_best_effort());
+        }
+        RequestOrResponse::Request(_) => panic!("Expected a response, got {:?}", response),
+    }
+}
+
 #[test]
 fn generate_reject_response_canister_not_found() {
     // Arbitrary initial output stream.
@@ -1316,6 +1345,7 @@ fn generate_reject_response_canister_not_found() {
                 RejectCode::DestinationInvalid,
                 err.to_string(),
             )),
+            deadline: msg.deadline,
         }
         .into(),
     );
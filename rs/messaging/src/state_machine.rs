@@ -7,7 +7,10 @@ use ic_logger::{fatal, ReplicaLogger};
 use ic_metrics::Timer;
 use ic_registry_subnet_features::SubnetFeatures;
 use ic_replicated_state::{NetworkTopology, ReplicatedState};
-use ic_types::{batch::Batch, ExecutionRound};
+use ic_types::{
+    batch::{epoch_from_height, Batch, QueryStatsEpoch},
+    ExecutionRound,
+};
 use std::sync::Arc;
 
 #[cfg(test)]
@@ -17,6 +20,7 @@ const PHASE_INDUCTION: &str = "induction";
 const PHASE_EXECUTION: &str = "execution";
 const PHASE_MESSAGE_ROUTING: &str = "message_routing";
 const PHASE_TIME_OUT_REQUESTS: &str = "time_out_requests";
+const PHASE_SHED_MESSAGES: &str = "shed_messages";
 
 pub(crate) trait StateMachine: Send {
     fn execute_round(
@@ -34,6 +38,7 @@ pub(crate) struct StateMachineImpl {
     stream_builder: Box<dyn StreamBuilder>,
     log: ReplicaLogger,
     metrics: Arc<MessageRoutingMetrics>,
+    query_stats_epoch_length: u64,
 }
 
 impl StateMachineImpl {
@@ -43,6 +48,7 @@ impl StateMachineImpl {
         stream_builder: Box<dyn StreamBuilder>,
         log: ReplicaLogger,
         metrics: Arc<MessageRoutingMetrics>,
+        query_stats_epoch_length: u64,
     ) -> Self {
         Self {
             scheduler,
@@ -50,6 +56,7 @@ impl StateMachineImpl {
             stream_builder,
             log,
             metrics,
+            query_stats_epoch_length,
         }
     }
 
@@ -95,8 +102,24 @@ impl StateMachine for StateMachineImpl {
         self.metrics
             .timed_out_requests_total
             .inc_by(timed_out_requests);
+        // Expire best-effort callbacks.
+        let timed_out_callbacks = state.time_out_callbacks(batch.time);
+        self.metrics
+            .timed_out_callbacks_total
+            .inc_by(timed_out_callbacks);
         self.observe_phase_duration(PHASE_TIME_OUT_REQUESTS, &phase_timer);
 
+        // Record the query statistics included in the batch. The statistics of
+        // an epoch are aggregated once a full epoch has passed after its end,
+        // giving all nodes the chance to report them.
+        if let Some(query_stats) = batch.payload.query_stats.take() {
+            state.deliver_query_stats(query_stats);
+        }
+        let epoch = epoch_from_height(batch.batch_number, self.query_stats_epoch_length);
+        if epoch >= QueryStatsEpoch::from(2) {
+            state.aggregate_query_stats(epoch - QueryStatsEpoch::from(2));
+        }
+
         // Preprocess messages and add messages to the induction pool through the Demux.
         let phase_timer = Timer::start();
         let mut state_with_messages = self.demux.process_payload(state, batch.payload);
@@ -128,9 +151,16 @@ impl StateMachine for StateMachineImpl {
 
         let phase_timer = Timer::start();
         // Postprocess the state and consolidate the Streams.
-        let state_after_stream_builder = self.stream_builder.build_streams(state_after_execution);
+        let mut state_after_stream_builder =
+            self.stream_builder.build_streams(state_after_execution);
         self.observe_phase_duration(PHASE_MESSAGE_ROUTING, &phase_timer);
 
+        // Shed best-effort requests that could not be routed, if over the limit.
+        let phase_timer = Timer::start();
+        let shed_messages = state_after_stream_builder.shed_best_effort_messages();
+        self.metrics.shed_messages_total.inc_by(shed_messages);
+        self.observe_phase_duration(PHASE_SHED_MESSAGES, &phase_timer);
+
         state_after_stream_builder
     }
 }
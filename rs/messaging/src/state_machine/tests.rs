@@ -3,6 +3,7 @@ use crate::{
     routing::demux::MockDemux, routing::stream_builder::MockStreamBuilder,
     state_machine::StateMachineImpl,
 };
+use ic_config::execution_environment::QUERY_STATS_EPOCH_LENGTH;
 use ic_ic00_types::EcdsaKeyId;
 use ic_interfaces::execution_environment::Scheduler;
 use ic_interfaces_state_manager::StateManager;
@@ -150,6 +151,7 @@ fn state_machine_populates_network_topology() {
             fixture.stream_builder,
             log,
             fixture.metrics,
+            QUERY_STATS_EPOCH_LENGTH,
         ));
 
         assert_ne!(
@@ -182,6 +184,7 @@ fn test_delivered_batch(provided_batch: Batch) {
             fixture.stream_builder,
             log,
             fixture.metrics,
+            QUERY_STATS_EPOCH_LENGTH,
         ));
 
         let _state_after = state_machine.execute_round(
@@ -18,6 +18,7 @@ use ic_test_utilities::{
     message_routing::FakeMessageRouting,
     p2p::*,
     port_allocation::allocate_ports,
+    query_stats::FakeQueryStatsPayloadBuilder,
     self_validating_payload_builder::FakeSelfValidatingPayloadBuilder,
     state_manager::FakeStateManager,
     thread_transport::*,
@@ -75,6 +76,7 @@ fn execute_test(
         let xnet_payload_builder = Arc::new(xnet_payload_builder);
         let self_validating_payload_builder = FakeSelfValidatingPayloadBuilder::new();
         let self_validating_payload_builder = Arc::new(self_validating_payload_builder);
+        let query_stats_payload_builder = Arc::new(FakeQueryStatsPayloadBuilder::new());
         let no_state_sync_client = P2PStateSyncClient::TestClient();
         let ingress_hist_reader = Box::new(IngressHistoryReaderImpl::new(
             Arc::clone(&state_manager) as Arc<_>,
@@ -114,6 +116,7 @@ fn execute_test(
             no_state_sync_client,
             xnet_payload_builder as Arc<_>,
             self_validating_payload_builder as Arc<_>,
+            query_stats_payload_builder as Arc<_>,
             message_router as Arc<_>,
             Arc::clone(&fake_crypto) as Arc<_>,
             Arc::clone(&fake_crypto) as Arc<_>,
@@ -235,6 +238,7 @@ fn execute_test_chunking_pool(
         let xnet_payload_builder = Arc::new(xnet_payload_builder);
         let self_validating_payload_builder = FakeSelfValidatingPayloadBuilder::new();
         let self_validating_payload_builder = Arc::new(self_validating_payload_builder);
+        let query_stats_payload_builder = Arc::new(FakeQueryStatsPayloadBuilder::new());
         let fake_crypto = CryptoReturningOk::default();
         let fake_crypto = Arc::new(fake_crypto);
         let node_pool_dir = test_synchronizer.get_test_group_directory();
@@ -276,6 +280,7 @@ fn execute_test_chunking_pool(
             state_sync_client,
             xnet_payload_builder,
             self_validating_payload_builder,
+            query_stats_payload_builder,
             message_router,
             Arc::clone(&fake_crypto) as Arc<_>,
             Arc::clone(&fake_crypto) as Arc<_>,
@@ -64,6 +64,7 @@ pub enum AlgorithmId {
     RsaSha256 = 14,
     ThresholdEcdsaSecp256k1 = 15,
     MegaSecp256k1 = 16,
+    ThresholdEcdsaSecp256r1 = 17,
 }
 impl AlgorithmId {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -89,6 +90,7 @@ impl AlgorithmId {
             AlgorithmId::RsaSha256 => "ALGORITHM_ID_RSA_SHA256",
             AlgorithmId::ThresholdEcdsaSecp256k1 => "ALGORITHM_ID_THRESHOLD_ECDSA_SECP_256K1",
             AlgorithmId::MegaSecp256k1 => "ALGORITHM_ID_MEGA_SECP_256K1",
+            AlgorithmId::ThresholdEcdsaSecp256r1 => "ALGORITHM_ID_THRESHOLD_ECDSA_SECP_256R1",
         }
     }
 }
@@ -100,6 +102,7 @@ impl AlgorithmId {
 pub enum EcdsaCurve {
     Unspecified = 0,
     Secp256k1 = 1,
+    Secp256r1 = 2,
 }
 impl EcdsaCurve {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -110,6 +113,7 @@ impl EcdsaCurve {
         match self {
             EcdsaCurve::Unspecified => "ECDSA_CURVE_UNSPECIFIED",
             EcdsaCurve::Secp256k1 => "ECDSA_CURVE_SECP256K1",
+            EcdsaCurve::Secp256r1 => "ECDSA_CURVE_SECP256R1",
         }
     }
 }
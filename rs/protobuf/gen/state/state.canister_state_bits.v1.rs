@@ -29,6 +29,8 @@ pub mod call_context {
         pub canister_id: ::core::option::Option<super::super::super::super::types::v1::CanisterId>,
         #[prost(uint64, tag = "2")]
         pub callback_id: u64,
+        #[prost(uint32, tag = "3")]
+        pub deadline_seconds: u32,
     }
     /// System task is either a Heartbeat or a GlobalTimer.
     #[allow(clippy::derive_partial_eq_without_eq)]
@@ -87,6 +89,8 @@ pub struct Callback {
     #[prost(message, optional, tag = "9")]
     pub prepayment_for_response_transmission:
         ::core::option::Option<super::super::queues::v1::Cycles>,
+    #[prost(uint32, tag = "10")]
+    pub deadline_seconds: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -107,6 +111,8 @@ pub struct CallContextManager {
     pub call_contexts: ::prost::alloc::vec::Vec<CallContextEntry>,
     #[prost(message, repeated, tag = "4")]
     pub callbacks: ::prost::alloc::vec::Vec<CallbackEntry>,
+    #[prost(uint64, repeated, tag = "5")]
+    pub unexpired_callbacks: ::prost::alloc::vec::Vec<u64>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -157,6 +163,7 @@ pub mod wasm_method {
         CanisterHeartbeat = 6,
         Empty = 7,
         CanisterGlobalTimer = 8,
+        CanisterOnLowWasmMemory = 9,
     }
     impl SystemMethod {
         /// String value of the enum field names used in the ProtoBuf definition.
@@ -174,6 +181,9 @@ pub mod wasm_method {
                 SystemMethod::CanisterHeartbeat => "SYSTEM_METHOD_CANISTER_HEARTBEAT",
                 SystemMethod::Empty => "SYSTEM_METHOD_EMPTY",
                 SystemMethod::CanisterGlobalTimer => "SYSTEM_METHOD_CANISTER_GLOBAL_TIMER",
+                SystemMethod::CanisterOnLowWasmMemory => {
+                    "SYSTEM_METHOD_CANISTER_ON_LOW_WASM_MEMORY"
+                }
             }
         }
     }
@@ -340,6 +350,7 @@ pub mod execution_task {
         Unspecified = 0,
         Heartbeat = 1,
         Timer = 2,
+        OnLowWasmMemory = 3,
     }
     impl CanisterTask {
         /// String value of the enum field names used in the ProtoBuf definition.
@@ -351,6 +362,7 @@ pub mod execution_task {
                 CanisterTask::Unspecified => "CANISTER_TASK_UNSPECIFIED",
                 CanisterTask::Heartbeat => "CANISTER_TASK_HEARTBEAT",
                 CanisterTask::Timer => "CANISTER_TASK_TIMER",
+                CanisterTask::OnLowWasmMemory => "CANISTER_TASK_ON_LOW_WASM_MEMORY",
             }
         }
     }
@@ -365,6 +377,113 @@ pub mod execution_task {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterLogRecord {
+    #[prost(uint64, tag = "1")]
+    pub idx: u64,
+    #[prost(uint64, tag = "2")]
+    pub timestamp_nanos: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub content: ::prost::alloc::vec::Vec<u8>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterChangeFromUser {
+    #[prost(message, optional, tag = "1")]
+    pub user_id: ::core::option::Option<super::super::super::types::v1::PrincipalId>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterChangeFromCanister {
+    #[prost(message, optional, tag = "1")]
+    pub canister_id: ::core::option::Option<super::super::super::types::v1::PrincipalId>,
+    #[prost(uint64, optional, tag = "2")]
+    pub canister_version: ::core::option::Option<u64>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterCreation {
+    #[prost(message, repeated, tag = "1")]
+    pub controllers: ::prost::alloc::vec::Vec<super::super::super::types::v1::PrincipalId>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterCodeUninstall {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterCodeDeployment {
+    #[prost(enumeration = "CanisterInstallMode", tag = "1")]
+    pub mode: i32,
+    /// SHA-256 hash of the deployed Wasm module.
+    #[prost(bytes = "vec", tag = "2")]
+    pub module_hash: ::prost::alloc::vec::Vec<u8>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterControllersChange {
+    #[prost(message, repeated, tag = "1")]
+    pub controllers: ::prost::alloc::vec::Vec<super::super::super::types::v1::PrincipalId>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterChange {
+    #[prost(uint64, tag = "1")]
+    pub timestamp_nanos: u64,
+    #[prost(uint64, tag = "2")]
+    pub canister_version: u64,
+    #[prost(oneof = "canister_change::ChangeOrigin", tags = "3, 4")]
+    pub change_origin: ::core::option::Option<canister_change::ChangeOrigin>,
+    #[prost(oneof = "canister_change::ChangeDetails", tags = "5, 6, 7, 8")]
+    pub change_details: ::core::option::Option<canister_change::ChangeDetails>,
+}
+/// Nested message and enum types in `CanisterChange`.
+pub mod canister_change {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum ChangeOrigin {
+        #[prost(message, tag = "3")]
+        CanisterChangeFromUser(super::CanisterChangeFromUser),
+        #[prost(message, tag = "4")]
+        CanisterChangeFromCanister(super::CanisterChangeFromCanister),
+    }
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum ChangeDetails {
+        #[prost(message, tag = "5")]
+        CanisterCreation(super::CanisterCreation),
+        #[prost(message, tag = "6")]
+        CanisterCodeUninstall(super::CanisterCodeUninstall),
+        #[prost(message, tag = "7")]
+        CanisterCodeDeployment(super::CanisterCodeDeployment),
+        #[prost(message, tag = "8")]
+        CanisterControllersChange(super::CanisterControllersChange),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterHistory {
+    /// The most recent changes, ordered from oldest to newest.
+    #[prost(message, repeated, tag = "1")]
+    pub changes: ::prost::alloc::vec::Vec<CanisterChange>,
+    /// The total number of changes ever recorded, including evicted ones.
+    #[prost(uint64, tag = "2")]
+    pub total_num_changes: u64,
+}
+/// Query statistics of a canister aggregated over all epochs. The `u128`
+/// totals are encoded as `NominalCycles`.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TotalQueryStats {
+    #[prost(message, optional, tag = "1")]
+    pub num_calls: ::core::option::Option<super::super::super::types::v1::NominalCycles>,
+    #[prost(message, optional, tag = "2")]
+    pub num_instructions: ::core::option::Option<super::super::super::types::v1::NominalCycles>,
+    #[prost(message, optional, tag = "3")]
+    pub ingress_payload_size: ::core::option::Option<super::super::super::types::v1::NominalCycles>,
+    #[prost(message, optional, tag = "4")]
+    pub egress_payload_size: ::core::option::Option<super::super::super::types::v1::NominalCycles>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CanisterStateBits {
     #[prost(uint64, tag = "2")]
     pub last_full_execution_round: u64,
@@ -425,6 +544,37 @@ pub struct CanisterStateBits {
     /// Canister version.
     #[prost(uint64, tag = "34")]
     pub canister_version: u64,
+    /// Log records of the canister, ordered by index.
+    #[prost(message, repeated, tag = "35")]
+    pub canister_log_records: ::prost::alloc::vec::Vec<CanisterLogRecord>,
+    /// The index of the next log record.
+    #[prost(uint64, tag = "36")]
+    pub next_canister_log_record_idx: u64,
+    /// Who is allowed to fetch the canister log.
+    #[prost(enumeration = "LogVisibility", tag = "37")]
+    pub log_visibility: i32,
+    /// Upper bound on the size of the Wasm heap, if set.
+    #[prost(uint64, optional, tag = "38")]
+    pub wasm_memory_limit: ::core::option::Option<u64>,
+    /// The most recent changes to the canister's code and controllers.
+    #[prost(message, optional, tag = "39")]
+    pub canister_history: ::core::option::Option<CanisterHistory>,
+    /// Free Wasm heap below which `canister_on_low_wasm_memory` is run.
+    #[prost(uint64, tag = "40")]
+    pub wasm_memory_threshold: u64,
+    /// Whether `canister_on_low_wasm_memory` has run since the free Wasm heap
+    /// last dropped below the threshold.
+    #[prost(bool, tag = "41")]
+    pub on_low_wasm_memory_hook_executed: bool,
+    /// Cycles reserved to pay for future storage.
+    #[prost(message, optional, tag = "42")]
+    pub reserved_balance: ::core::option::Option<super::super::queues::v1::Cycles>,
+    /// Upper bound on `reserved_balance`, if set.
+    #[prost(message, optional, tag = "43")]
+    pub reserved_balance_limit: ::core::option::Option<super::super::queues::v1::Cycles>,
+    /// Query statistics aggregated across replicas.
+    #[prost(message, optional, tag = "44")]
+    pub total_query_stats: ::core::option::Option<TotalQueryStats>,
     #[prost(oneof = "canister_state_bits::CanisterStatus", tags = "11, 12, 13")]
     pub canister_status: ::core::option::Option<canister_state_bits::CanisterStatus>,
 }
@@ -441,6 +591,43 @@ pub mod canister_state_bits {
         Stopped(super::CanisterStatusStopped),
     }
 }
+/// Bits of a canister snapshot that are not stored in separate files.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterSnapshotBits {
+    #[prost(message, optional, tag = "1")]
+    pub canister_id: ::core::option::Option<super::super::super::types::v1::CanisterId>,
+    /// Time at which the snapshot was taken, in nanoseconds since Unix epoch.
+    #[prost(uint64, tag = "2")]
+    pub taken_at_timestamp_nanos: u64,
+    /// Canister version at the time the snapshot was taken.
+    #[prost(uint64, tag = "3")]
+    pub canister_version: u64,
+    #[prost(bytes = "vec", tag = "4")]
+    pub certified_data: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "5")]
+    pub execution_state_bits: ::core::option::Option<ExecutionStateBits>,
+    /// Size of the stable memory, in Wasm pages.
+    #[prost(uint64, tag = "6")]
+    pub stable_memory_size64: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WasmChunk {
+    /// SHA-256 hash of `content`.
+    #[prost(bytes = "vec", tag = "1")]
+    pub hash: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub content: ::prost::alloc::vec::Vec<u8>,
+}
+/// Chunks uploaded to a canister's Wasm chunk store, stored in a separate file
+/// of the canister directory.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct WasmChunkStore {
+    #[prost(message, repeated, tag = "1")]
+    pub chunks: ::prost::alloc::vec::Vec<WasmChunk>,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -462,3 +649,47 @@ impl CustomSectionType {
         }
     }
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum LogVisibility {
+    Unspecified = 0,
+    Controllers = 1,
+    Public = 2,
+}
+impl LogVisibility {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            LogVisibility::Unspecified => "LOG_VISIBILITY_UNSPECIFIED",
+            LogVisibility::Controllers => "LOG_VISIBILITY_CONTROLLERS",
+            LogVisibility::Public => "LOG_VISIBILITY_PUBLIC",
+        }
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum CanisterInstallMode {
+    Unspecified = 0,
+    Install = 1,
+    Reinstall = 2,
+    Upgrade = 3,
+}
+impl CanisterInstallMode {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            CanisterInstallMode::Unspecified => "CANISTER_INSTALL_MODE_UNSPECIFIED",
+            CanisterInstallMode::Install => "CANISTER_INSTALL_MODE_INSTALL",
+            CanisterInstallMode::Reinstall => "CANISTER_INSTALL_MODE_REINSTALL",
+            CanisterInstallMode::Upgrade => "CANISTER_INSTALL_MODE_UPGRADE",
+        }
+    }
+}
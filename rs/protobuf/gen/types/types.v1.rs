@@ -610,6 +610,8 @@ pub struct Block {
     pub ecdsa_payload: ::core::option::Option<EcdsaPayload>,
     #[prost(message, optional, tag = "14")]
     pub canister_http_payload: ::core::option::Option<CanisterHttpPayload>,
+    #[prost(message, optional, tag = "15")]
+    pub query_stats_payload: ::core::option::Option<QueryStatsPayload>,
     #[prost(bytes = "vec", tag = "11")]
     pub payload_hash: ::prost::alloc::vec::Vec<u8>,
 }
@@ -727,6 +729,32 @@ pub struct CanisterHttpPayload {
 #[derive(serde::Serialize, serde::Deserialize)]
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CanisterQueryStats {
+    #[prost(message, optional, tag = "1")]
+    pub canister_id: ::core::option::Option<CanisterId>,
+    #[prost(uint32, tag = "2")]
+    pub num_calls: u32,
+    #[prost(uint64, tag = "3")]
+    pub num_instructions: u64,
+    #[prost(uint64, tag = "4")]
+    pub ingress_payload_size: u64,
+    #[prost(uint64, tag = "5")]
+    pub egress_payload_size: u64,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct QueryStatsPayload {
+    #[prost(uint64, tag = "1")]
+    pub epoch: u64,
+    #[prost(message, optional, tag = "2")]
+    pub proposer: ::core::option::Option<NodeId>,
+    #[prost(message, repeated, tag = "3")]
+    pub canister_stats: ::prost::alloc::vec::Vec<CanisterQueryStats>,
+}
+#[derive(serde::Serialize, serde::Deserialize)]
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct IngressIdOffset {
     #[prost(uint64, tag = "1")]
     pub expiry: u64,
@@ -1,4 +1,8 @@
-use crate::{common::LOG_PREFIX, mutations::common::encode_or_panic, registry::Registry};
+use crate::{
+    common::LOG_PREFIX,
+    mutations::{common::encode_or_panic, subnet::validate_ecdsa_key_curve},
+    registry::Registry,
+};
 use std::collections::HashSet;
 
 use candid::{CandidType, Deserialize};
@@ -70,6 +74,9 @@ impl Registry {
 
         let ecdsa_subnet_map = self.get_ecdsa_keys_to_subnets_map();
         new_keys.iter().for_each(|key_id| {
+            if let Err(message) = validate_ecdsa_key_curve(key_id) {
+                panic!("{}{}", LOG_PREFIX, message);
+            }
             if ecdsa_subnet_map.contains_key(key_id) {
                 panic!(
                     "{}ECDSA key with id '{}' already exists.  ID must be globally unique.",
@@ -1043,6 +1050,43 @@ mod tests {
         registry.do_update_subnet(payload);
     }
 
+    #[test]
+    #[should_panic(
+        expected = "ECDSA key 'Secp256r1:p256_key_id' is on a curve for which nodes do not \
+                    register dealing encryption keys yet."
+    )]
+    fn test_ecdsa_keys_on_secp256r1_cannot_be_added() {
+        let mut registry = invariant_compliant_registry();
+
+        let (mutate_request, mut node_ids) = prepare_registry_with_nodes(1);
+        registry.maybe_apply_mutation_internal(mutate_request.mutations);
+
+        let mut subnet_list_record = registry.get_subnet_list_record();
+
+        let subnet_record = get_invariant_compliant_subnet_record(vec![node_ids.pop().unwrap()]);
+        let subnet_id = subnet_test_id(1000);
+        registry.maybe_apply_mutation_internal(add_fake_subnet(
+            subnet_id,
+            &mut subnet_list_record,
+            subnet_record,
+        ));
+
+        let mut payload = make_empty_update_payload(subnet_id);
+        payload.ecdsa_config = Some(EcdsaConfig {
+            quadruples_to_create_in_advance: 1,
+            key_ids: vec![EcdsaKeyId {
+                curve: EcdsaCurve::Secp256r1,
+                name: "p256_key_id".to_string(),
+            }],
+            max_queue_size: Some(DEFAULT_ECDSA_MAX_QUEUE_SIZE),
+            signature_request_timeout_ns: None,
+            idkg_key_rotation_period_ms: None,
+        });
+
+        // Should panic because nodes can't create dealings for P-256 keys yet.
+        registry.do_update_subnet(payload);
+    }
+
     #[test]
     #[should_panic(
         expected = "ECDSA key with id 'Secp256k1:existing_key_id' already exists.  \
@@ -12,7 +12,7 @@ use ic_base_types::{
     subnet_id_into_protobuf, CanisterId, NodeId, PrincipalId, RegistryVersion, SubnetId,
 };
 use ic_ic00_types::{
-    ComputeInitialEcdsaDealingsArgs, ComputeInitialEcdsaDealingsResponse, EcdsaKeyId,
+    ComputeInitialEcdsaDealingsArgs, ComputeInitialEcdsaDealingsResponse, EcdsaCurve, EcdsaKeyId,
 };
 use ic_protobuf::registry::crypto::v1::EcdsaSigningSubnetList;
 use ic_protobuf::registry::subnet::v1::EcdsaInitialization;
@@ -338,6 +338,8 @@ impl Registry {
         let ecdsa_subnet_map = self.get_ecdsa_keys_to_subnets_map();
 
         for key_request in &ecdsa_initial_config.keys {
+            validate_ecdsa_key_curve(&key_request.key_id)?;
+
             // Requested key must be a known key.
             if !ecdsa_subnet_map.contains_key(&key_request.key_id) {
                 return Err(format!(
@@ -384,6 +386,20 @@ impl Registry {
     }
 }
 
+/// Nodes only register MEGa dealing encryption keys on secp256k1, and IDKG
+/// dealings for a key are encrypted to keys on the curve of that key. Keys on
+/// any other curve can therefore not be created or reshared yet.
+pub(crate) fn validate_ecdsa_key_curve(key_id: &EcdsaKeyId) -> Result<(), String> {
+    match key_id.curve {
+        EcdsaCurve::Secp256k1 => Ok(()),
+        EcdsaCurve::Secp256r1 => Err(format!(
+            "ECDSA key '{}' is on a curve for which nodes do not register dealing \
+             encryption keys yet.",
+            key_id
+        )),
+    }
+}
+
 fn vec_to_set<T: std::hash::Hash + std::cmp::Eq>(vector: Vec<T>) -> HashSet<T> {
     HashSet::from_iter(vector.into_iter())
 }
@@ -1,7 +1,7 @@
 use ic_btc_adapter_client::{setup_bitcoin_adapter_clients, BitcoinAdapterClients};
 use ic_btc_consensus::BitcoinPayloadBuilder;
 use ic_config::{artifact_pool::ArtifactPoolConfig, subnet_config::SubnetConfig, Config};
-use ic_consensus::certification::VerifierImpl;
+use ic_consensus::{certification::VerifierImpl, query_stats::QueryStatsPayloadBuilderImpl};
 use ic_crypto::CryptoComponent;
 use ic_cycles_account_manager::CyclesAccountManager;
 use ic_execution_environment::ExecutionServices;
@@ -139,6 +139,7 @@ pub fn construct_ic_stack(
     ));
     // Get the file descriptor factory object and pass it down the line to the hypervisor
     let fd_factory = state_manager.get_fd_factory();
+    let query_stats_epoch_length = config.hypervisor.query_stats_epoch_length;
     let execution_services = ExecutionServices::setup_execution(
         replica_logger.clone(),
         &metrics_registry,
@@ -230,6 +231,17 @@ pub fn construct_ic_stack(
     );
     let self_validating_payload_builder = Arc::new(self_validating_payload_builder);
 
+    let query_stats_payload_builder = QueryStatsPayloadBuilderImpl::new(
+        state_manager.clone(),
+        Arc::clone(&registry) as Arc<_>,
+        node_id,
+        subnet_id,
+        query_stats_epoch_length,
+        execution_services.query_stats_receiver,
+        replica_logger.clone(),
+    );
+    let query_stats_payload_builder = Arc::new(query_stats_payload_builder);
+
     let canister_http_adapter_client = ic_https_outcalls_adapter_client::setup_canister_http_client(
         rt_handle.clone(),
         &metrics_registry,
@@ -257,6 +269,7 @@ pub fn construct_ic_stack(
         P2PStateSyncClient::Client(state_sync),
         xnet_payload_builder as Arc<_>,
         self_validating_payload_builder as Arc<_>,
+        query_stats_payload_builder as Arc<_>,
         message_router as Arc<_>,
         // TODO(SCL-213)
         Arc::clone(&crypto) as Arc<_>,
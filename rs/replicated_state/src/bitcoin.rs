@@ -7,7 +7,7 @@ use ic_error_types::RejectCode;
 use ic_ic00_types::{BitcoinGetSuccessorsResponse, EmptyBlob, Payload as _};
 use ic_registry_subnet_features::BitcoinFeatureStatus;
 use ic_types::{
-    messages::{CallbackId, Payload, RejectContext, Response},
+    messages::{CallbackId, Payload, RejectContext, Response, NO_DEADLINE},
     CanisterId,
 };
 use std::cmp::min;
@@ -64,6 +64,7 @@ pub fn push_response(
                 originator_reply_callback: callback_id,
                 refund: context.request.take_cycles(),
                 response_payload,
+                deadline: NO_DEADLINE,
             });
 
             Ok(())
@@ -106,6 +107,7 @@ pub fn push_response(
                 originator_reply_callback: callback_id,
                 refund: context.request.take_cycles(),
                 response_payload,
+                deadline: NO_DEADLINE,
             });
 
             Ok(())
@@ -0,0 +1,474 @@
+use crate::{
+    canister_state::execution_state::{Memory, WasmBinary, WasmMetadata},
+    num_bytes_try_from, CanisterState, ExportedFunctions, Global,
+};
+use ic_registry_routing_table::RoutingTable;
+use ic_types::{CanisterId, NumBytes, SubnetId, Time};
+use phantom_newtype::Id;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+
+pub struct SnapshotIdTag;
+/// A subnet-wide unique identifier of a canister snapshot.
+pub type SnapshotId = Id<SnapshotIdTag, u64>;
+
+/// Encodes a snapshot id as the opaque blob exposed by the management canister
+/// API.
+pub fn snapshot_id_to_bytes(snapshot_id: SnapshotId) -> Vec<u8> {
+    snapshot_id.get().to_be_bytes().to_vec()
+}
+
+/// Decodes a snapshot id from the blob exposed by the management canister
+/// API. Returns `None` if the blob is malformed.
+pub fn snapshot_id_from_bytes(bytes: &[u8]) -> Option<SnapshotId> {
+    let bytes: [u8; 8] = bytes.try_into().ok()?;
+    Some(SnapshotId::new(u64::from_be_bytes(bytes)))
+}
+
+/// A snapshot of the state of a canister, taken via the
+/// `take_canister_snapshot` method of the management canister.
+///
+/// A snapshot captures the Wasm module, the heap, the stable memory, the
+/// exported globals and the certified data of the canister. Snapshots are
+/// immutable: the memories share the pages of the `PageMap`s they were taken
+/// from and are therefore cheap to create.
+#[derive(Clone, Debug)]
+pub struct CanisterSnapshot {
+    /// The canister the snapshot belongs to.
+    canister_id: CanisterId,
+    /// The time at which the snapshot was taken.
+    taken_at_timestamp: Time,
+    /// The version of the canister at the time the snapshot was taken.
+    canister_version: u64,
+    /// The certified data of the canister.
+    certified_data: Vec<u8>,
+    /// The Wasm module of the canister.
+    pub wasm_binary: Arc<WasmBinary>,
+    /// The heap of the canister.
+    pub wasm_memory: Memory,
+    /// The stable memory of the canister.
+    pub stable_memory: Memory,
+    /// The exported globals of the canister.
+    pub exported_globals: Vec<Global>,
+    /// The functions exported by the Wasm module.
+    pub exports: ExportedFunctions,
+    /// Metadata extracted from the Wasm module.
+    pub metadata: WasmMetadata,
+}
+
+// We have to implement it by hand as the embedder cache of the Wasm binary
+// can not be compared for equality (and doesn't need to be).
+impl PartialEq for CanisterSnapshot {
+    fn eq(&self, rhs: &Self) -> bool {
+        (
+            &self.canister_id,
+            &self.taken_at_timestamp,
+            &self.canister_version,
+            &self.certified_data,
+            &self.wasm_binary.binary,
+            &self.wasm_memory,
+            &self.stable_memory,
+            &self.exported_globals,
+            &self.exports,
+        ) == (
+            &rhs.canister_id,
+            &rhs.taken_at_timestamp,
+            &rhs.canister_version,
+            &rhs.certified_data,
+            &rhs.wasm_binary.binary,
+            &rhs.wasm_memory,
+            &rhs.stable_memory,
+            &rhs.exported_globals,
+            &rhs.exports,
+        )
+    }
+}
+
+impl CanisterSnapshot {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        canister_id: CanisterId,
+        taken_at_timestamp: Time,
+        canister_version: u64,
+        certified_data: Vec<u8>,
+        wasm_binary: Arc<WasmBinary>,
+        wasm_memory: Memory,
+        stable_memory: Memory,
+        exported_globals: Vec<Global>,
+        exports: ExportedFunctions,
+        metadata: WasmMetadata,
+    ) -> Self {
+        Self {
+            canister_id,
+            taken_at_timestamp,
+            canister_version,
+            certified_data,
+            wasm_binary,
+            wasm_memory,
+            stable_memory,
+            exported_globals,
+            exports,
+            metadata,
+        }
+    }
+
+    /// Takes a snapshot of the given canister. Returns `None` if the canister
+    /// is empty, i.e. has no Wasm module installed.
+    pub fn from_canister(canister: &CanisterState, taken_at_timestamp: Time) -> Option<Self> {
+        let execution_state = canister.execution_state.as_ref()?;
+        Some(Self::new(
+            canister.canister_id(),
+            taken_at_timestamp,
+            canister.system_state.canister_version,
+            canister.system_state.certified_data.clone(),
+            Arc::clone(&execution_state.wasm_binary),
+            // Create new `Memory` objects so that the snapshot does not share
+            // the sandbox memory handles with the canister.
+            Memory::new(
+                execution_state.wasm_memory.page_map.clone(),
+                execution_state.wasm_memory.size,
+            ),
+            Memory::new(
+                execution_state.stable_memory.page_map.clone(),
+                execution_state.stable_memory.size,
+            ),
+            execution_state.exported_globals.clone(),
+            execution_state.exports.clone(),
+            execution_state.metadata.clone(),
+        ))
+    }
+
+    pub fn canister_id(&self) -> CanisterId {
+        self.canister_id
+    }
+
+    pub fn taken_at_timestamp(&self) -> Time {
+        self.taken_at_timestamp
+    }
+
+    pub fn canister_version(&self) -> u64 {
+        self.canister_version
+    }
+
+    pub fn certified_data(&self) -> &Vec<u8> {
+        &self.certified_data
+    }
+
+    /// Returns the amount of memory taken by the snapshot.
+    pub fn size(&self) -> NumBytes {
+        // We use 8 bytes per global, same as `ExecutionState::memory_usage()`.
+        let globals_size_bytes = 8 * self.exported_globals.len() as u64;
+        num_bytes_try_from(self.wasm_memory.size)
+            .expect("could not convert from wasm memory number of pages to bytes")
+            + num_bytes_try_from(self.stable_memory.size)
+                .expect("could not convert from stable memory number of pages to bytes")
+            + NumBytes::from(globals_size_bytes)
+            + NumBytes::from(self.wasm_binary.binary.len() as u64)
+            + NumBytes::from(self.certified_data.len() as u64)
+    }
+}
+
+/// All canister snapshots held by the subnet, indexed by snapshot id.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CanisterSnapshots {
+    snapshots: BTreeMap<SnapshotId, Arc<CanisterSnapshot>>,
+    /// The ids of the snapshots of each canister, so that looking up the
+    /// snapshots of one canister doesn't require scanning all snapshots.
+    canister_snapshot_ids: BTreeMap<CanisterId, BTreeSet<SnapshotId>>,
+    /// The memory taken by the snapshots of each canister.
+    canister_memory_taken: BTreeMap<CanisterId, NumBytes>,
+}
+
+impl CanisterSnapshots {
+    pub fn new(snapshots: BTreeMap<SnapshotId, Arc<CanisterSnapshot>>) -> Self {
+        let mut canister_snapshots = Self::default();
+        for (snapshot_id, snapshot) in snapshots {
+            canister_snapshots.push(snapshot_id, snapshot);
+        }
+        canister_snapshots
+    }
+
+    /// Adds a new snapshot under the given id.
+    pub fn push(&mut self, snapshot_id: SnapshotId, snapshot: Arc<CanisterSnapshot>) {
+        let canister_id = snapshot.canister_id();
+        let size = snapshot.size();
+        if let Some(replaced) = self.snapshots.insert(snapshot_id, snapshot) {
+            self.remove_from_index(snapshot_id, &replaced);
+        }
+        self.canister_snapshot_ids
+            .entry(canister_id)
+            .or_default()
+            .insert(snapshot_id);
+        *self
+            .canister_memory_taken
+            .entry(canister_id)
+            .or_insert_with(|| NumBytes::from(0)) += size;
+    }
+
+    /// Returns the snapshot with the given id, if any.
+    pub fn get(&self, snapshot_id: SnapshotId) -> Option<&Arc<CanisterSnapshot>> {
+        self.snapshots.get(&snapshot_id)
+    }
+
+    /// Removes the snapshot with the given id and returns it, if any.
+    pub fn remove(&mut self, snapshot_id: SnapshotId) -> Option<Arc<CanisterSnapshot>> {
+        let snapshot = self.snapshots.remove(&snapshot_id)?;
+        self.remove_from_index(snapshot_id, &snapshot);
+        Some(snapshot)
+    }
+
+    fn remove_from_index(&mut self, snapshot_id: SnapshotId, snapshot: &CanisterSnapshot) {
+        let canister_id = snapshot.canister_id();
+        if let Some(snapshot_ids) = self.canister_snapshot_ids.get_mut(&canister_id) {
+            snapshot_ids.remove(&snapshot_id);
+            if snapshot_ids.is_empty() {
+                self.canister_snapshot_ids.remove(&canister_id);
+                self.canister_memory_taken.remove(&canister_id);
+                return;
+            }
+        }
+        if let Some(memory_taken) = self.canister_memory_taken.get_mut(&canister_id) {
+            *memory_taken -= snapshot.size();
+        }
+    }
+
+    /// Removes all snapshots of the given canister.
+    pub fn delete_snapshots(&mut self, canister_id: CanisterId) {
+        for snapshot_id in self
+            .canister_snapshot_ids
+            .remove(&canister_id)
+            .unwrap_or_default()
+        {
+            self.snapshots.remove(&snapshot_id);
+        }
+        self.canister_memory_taken.remove(&canister_id);
+    }
+
+    /// Returns all snapshots of the given canister, ordered by id.
+    pub fn list_snapshots(
+        &self,
+        canister_id: CanisterId,
+    ) -> Vec<(SnapshotId, &Arc<CanisterSnapshot>)> {
+        self.canister_snapshot_ids
+            .get(&canister_id)
+            .into_iter()
+            .flatten()
+            .map(|snapshot_id| (*snapshot_id, &self.snapshots[snapshot_id]))
+            .collect()
+    }
+
+    /// Returns an iterator over all snapshots, ordered by id.
+    pub fn iter(&self) -> impl Iterator<Item = (&SnapshotId, &Arc<CanisterSnapshot>)> {
+        self.snapshots.iter()
+    }
+
+    /// Returns the ids of all snapshots.
+    pub fn snapshot_ids(&self) -> impl Iterator<Item = &SnapshotId> {
+        self.snapshots.keys()
+    }
+
+    /// Returns the number of snapshots.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Returns `true` if there are no snapshots.
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// Returns the memory taken by the snapshots of the given canister.
+    pub fn memory_taken_by_canister(&self, canister_id: CanisterId) -> NumBytes {
+        self.canister_memory_taken
+            .get(&canister_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the memory taken by all snapshots.
+    pub fn memory_taken(&self) -> NumBytes {
+        self.canister_memory_taken.values().copied().sum()
+    }
+
+    /// Splits the canister snapshots (as part of subnet splitting), retaining
+    /// only the snapshots of canisters hosted by `new_subnet_id` (as determined
+    /// by the provided routing table), so that snapshots end up on the same
+    /// subnet as their canisters.
+    #[allow(dead_code)]
+    pub(crate) fn split(self, new_subnet_id: SubnetId, routing_table: &RoutingTable) -> Self {
+        // Take apart `self` and put it back together, in order for the compiler to
+        // enforce an explicit decision whenever any structural changes are made.
+        let Self {
+            snapshots,
+            canister_snapshot_ids: _,
+            canister_memory_taken: _,
+        } = self;
+
+        Self::new(
+            snapshots
+                .into_iter()
+                .filter(|(_, snapshot)| {
+                    routing_table.route(snapshot.canister_id().get()) == Some(new_subnet_id)
+                })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_registry_routing_table::CanisterIdRange;
+    use ic_test_utilities::types::ids::{canister_test_id, subnet_test_id};
+    use ic_wasm_types::CanisterModule;
+    use maplit::btreemap;
+
+    fn fake_snapshot(canister_id: CanisterId, wasm: Vec<u8>) -> Arc<CanisterSnapshot> {
+        Arc::new(CanisterSnapshot::new(
+            canister_id,
+            Time::from_nanos_since_unix_epoch(0),
+            0,
+            vec![1, 2, 3],
+            WasmBinary::new(CanisterModule::new(wasm)),
+            Memory::new_for_testing(),
+            Memory::new_for_testing(),
+            vec![],
+            ExportedFunctions::new(BTreeSet::new()),
+            WasmMetadata::default(),
+        ))
+    }
+
+    #[test]
+    fn list_and_delete_snapshots_of_canister() {
+        let mut snapshots = CanisterSnapshots::default();
+        snapshots.push(
+            SnapshotId::new(0),
+            fake_snapshot(canister_test_id(1), vec![]),
+        );
+        snapshots.push(
+            SnapshotId::new(1),
+            fake_snapshot(canister_test_id(2), vec![]),
+        );
+        snapshots.push(
+            SnapshotId::new(2),
+            fake_snapshot(canister_test_id(1), vec![]),
+        );
+
+        let ids: Vec<_> = snapshots
+            .list_snapshots(canister_test_id(1))
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, vec![SnapshotId::new(0), SnapshotId::new(2)]);
+
+        snapshots.delete_snapshots(canister_test_id(1));
+        assert_eq!(snapshots.len(), 1);
+        assert!(snapshots.get(SnapshotId::new(1)).is_some());
+    }
+
+    #[test]
+    fn memory_taken_accounts_for_all_snapshots() {
+        let mut snapshots = CanisterSnapshots::default();
+        snapshots.push(
+            SnapshotId::new(0),
+            fake_snapshot(canister_test_id(1), vec![0; 10]),
+        );
+        snapshots.push(
+            SnapshotId::new(1),
+            fake_snapshot(canister_test_id(2), vec![0; 20]),
+        );
+
+        // Wasm binary plus 3 bytes of certified data.
+        assert_eq!(
+            snapshots.memory_taken_by_canister(canister_test_id(1)),
+            NumBytes::from(13)
+        );
+        assert_eq!(snapshots.memory_taken(), NumBytes::from(36));
+    }
+
+    #[test]
+    fn memory_taken_by_canister_is_updated_on_replace_and_remove() {
+        let mut snapshots = CanisterSnapshots::default();
+        snapshots.push(
+            SnapshotId::new(0),
+            fake_snapshot(canister_test_id(1), vec![0; 10]),
+        );
+        snapshots.push(
+            SnapshotId::new(1),
+            fake_snapshot(canister_test_id(1), vec![0; 20]),
+        );
+        assert_eq!(
+            snapshots.memory_taken_by_canister(canister_test_id(1)),
+            NumBytes::from(36)
+        );
+
+        // Replacing a snapshot under the same id only accounts for the new one.
+        snapshots.push(
+            SnapshotId::new(0),
+            fake_snapshot(canister_test_id(1), vec![0; 5]),
+        );
+        assert_eq!(
+            snapshots.memory_taken_by_canister(canister_test_id(1)),
+            NumBytes::from(31)
+        );
+
+        snapshots.remove(SnapshotId::new(1));
+        assert_eq!(
+            snapshots.memory_taken_by_canister(canister_test_id(1)),
+            NumBytes::from(8)
+        );
+        snapshots.remove(SnapshotId::new(0));
+        assert_eq!(
+            snapshots.memory_taken_by_canister(canister_test_id(1)),
+            NumBytes::from(0)
+        );
+        assert!(snapshots.list_snapshots(canister_test_id(1)).is_empty());
+        assert_eq!(snapshots, CanisterSnapshots::default());
+    }
+
+    #[test]
+    fn split_retains_snapshots_of_hosted_canisters() {
+        let mut snapshots = CanisterSnapshots::default();
+        snapshots.push(
+            SnapshotId::new(0),
+            fake_snapshot(canister_test_id(1), vec![0; 10]),
+        );
+        snapshots.push(
+            SnapshotId::new(1),
+            fake_snapshot(canister_test_id(2), vec![0; 20]),
+        );
+        snapshots.push(
+            SnapshotId::new(2),
+            fake_snapshot(canister_test_id(1), vec![0; 30]),
+        );
+
+        // Canister 1 stays on subnet A, canister 2 is migrated to subnet B.
+        let (subnet_a, subnet_b) = (subnet_test_id(1), subnet_test_id(2));
+        let routing_table = RoutingTable::try_from(btreemap! {
+            CanisterIdRange{ start: canister_test_id(1), end: canister_test_id(1) } => subnet_a,
+            CanisterIdRange{ start: canister_test_id(2), end: canister_test_id(2) } => subnet_b,
+        })
+        .unwrap();
+
+        let snapshots_a = snapshots.clone().split(subnet_a, &routing_table);
+        let ids: Vec<_> = snapshots_a.snapshot_ids().copied().collect();
+        assert_eq!(ids, vec![SnapshotId::new(0), SnapshotId::new(2)]);
+        assert!(snapshots_a.list_snapshots(canister_test_id(2)).is_empty());
+        assert_eq!(snapshots_a.memory_taken(), NumBytes::from(46));
+
+        let snapshots_b = snapshots.split(subnet_b, &routing_table);
+        let ids: Vec<_> = snapshots_b.snapshot_ids().copied().collect();
+        assert_eq!(ids, vec![SnapshotId::new(1)]);
+        assert!(snapshots_b.list_snapshots(canister_test_id(1)).is_empty());
+        assert_eq!(snapshots_b.memory_taken(), NumBytes::from(23));
+    }
+
+    #[test]
+    fn snapshot_id_bytes_roundtrip() {
+        let id = SnapshotId::new(0x0102_0304_0506_0708);
+        let bytes = snapshot_id_to_bytes(id);
+        assert_eq!(bytes, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(snapshot_id_from_bytes(&bytes), Some(id));
+        assert_eq!(snapshot_id_from_bytes(&bytes[1..]), None);
+    }
+}
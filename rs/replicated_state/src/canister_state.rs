@@ -10,6 +10,9 @@ use crate::{InputQueueType, StateError};
 pub use execution_state::{EmbedderCache, ExecutionState, ExportedFunctions, Global};
 use ic_ic00_types::CanisterStatusType;
 use ic_interfaces::messages::CanisterMessage;
+use ic_protobuf::{
+    proxy::ProxyDecodeError, state::canister_state_bits::v1 as pb, types::v1 as pb_types,
+};
 use ic_registry_subnet_type::SubnetType;
 use ic_types::methods::SystemMethod;
 use ic_types::time::UNIX_EPOCH;
@@ -19,11 +22,11 @@ use ic_types::{
     AccumulatedPriority, CanisterId, ComputeAllocation, ExecutionRound, MemoryAllocation, NumBytes,
     PrincipalId, Time,
 };
-use ic_types::{LongExecutionMode, NumInstructions};
+use ic_types::{nominal_cycles::NominalCycles, LongExecutionMode, NumInstructions};
 use phantom_newtype::AmountOf;
 pub use queues::{CanisterQueues, DEFAULT_QUEUE_CAPACITY};
 use std::collections::BTreeSet;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -70,6 +73,10 @@ pub struct SchedulerState {
     /// needed to calculate how much time should be considered when charging
     /// occurs.
     pub time_of_last_allocation_charge: Time,
+
+    /// Query statistics of the canister, aggregated across all replicas of
+    /// the subnet over all epochs so far.
+    pub total_query_stats: TotalQueryStats,
 }
 
 impl Default for SchedulerState {
@@ -83,6 +90,7 @@ impl Default for SchedulerState {
             heap_delta_debit: 0.into(),
             install_code_debit: 0.into(),
             time_of_last_allocation_charge: UNIX_EPOCH,
+            total_query_stats: TotalQueryStats::default(),
         }
     }
 }
@@ -96,6 +104,60 @@ impl SchedulerState {
     }
 }
 
+/// Query statistics of a canister accumulated over all aggregated epochs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TotalQueryStats {
+    pub num_calls: u128,
+    pub num_instructions: u128,
+    pub ingress_payload_size: u128,
+    pub egress_payload_size: u128,
+}
+
+impl TotalQueryStats {
+    /// Adds the statistics of `other` to `self`, saturating on overflow.
+    pub fn saturating_accumulate(&mut self, other: &TotalQueryStats) {
+        self.num_calls = self.num_calls.saturating_add(other.num_calls);
+        self.num_instructions = self.num_instructions.saturating_add(other.num_instructions);
+        self.ingress_payload_size = self
+            .ingress_payload_size
+            .saturating_add(other.ingress_payload_size);
+        self.egress_payload_size = self
+            .egress_payload_size
+            .saturating_add(other.egress_payload_size);
+    }
+}
+
+impl From<&TotalQueryStats> for pb::TotalQueryStats {
+    fn from(item: &TotalQueryStats) -> Self {
+        Self {
+            num_calls: Some((&NominalCycles::from(item.num_calls)).into()),
+            num_instructions: Some((&NominalCycles::from(item.num_instructions)).into()),
+            ingress_payload_size: Some((&NominalCycles::from(item.ingress_payload_size)).into()),
+            egress_payload_size: Some((&NominalCycles::from(item.egress_payload_size)).into()),
+        }
+    }
+}
+
+impl TryFrom<pb::TotalQueryStats> for TotalQueryStats {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::TotalQueryStats) -> Result<Self, Self::Error> {
+        let decode = |value: Option<pb_types::NominalCycles>| -> Result<u128, ProxyDecodeError> {
+            Ok(value
+                .map(NominalCycles::try_from)
+                .transpose()?
+                .unwrap_or_default()
+                .get())
+        };
+        Ok(Self {
+            num_calls: decode(value.num_calls)?,
+            num_instructions: decode(value.num_instructions)?,
+            ingress_payload_size: decode(value.ingress_payload_size)?,
+            egress_payload_size: decode(value.egress_payload_size)?,
+        })
+    }
+}
+
 /// The full state of a single canister.
 #[derive(Clone, Debug, PartialEq)]
 pub struct CanisterState {
@@ -231,6 +293,7 @@ impl CanisterState {
             (None, true) => NextExecution::StartNew,
             (Some(ExecutionTask::Heartbeat), _) => NextExecution::StartNew,
             (Some(ExecutionTask::GlobalTimer), _) => NextExecution::StartNew,
+            (Some(ExecutionTask::OnLowWasmMemory), _) => NextExecution::StartNew,
             (Some(ExecutionTask::AbortedExecution { .. }), _)
             | (Some(ExecutionTask::PausedExecution(..)), _) => NextExecution::ContinueLong,
             (Some(ExecutionTask::AbortedInstallCode { .. }), _)
@@ -250,6 +313,7 @@ impl CanisterState {
             None
             | Some(ExecutionTask::Heartbeat)
             | Some(ExecutionTask::GlobalTimer)
+            | Some(ExecutionTask::OnLowWasmMemory)
             | Some(ExecutionTask::PausedExecution(..))
             | Some(ExecutionTask::PausedInstallCode(..))
             | Some(ExecutionTask::AbortedInstallCode { .. }) => false,
@@ -263,6 +327,7 @@ impl CanisterState {
             None
             | Some(ExecutionTask::Heartbeat)
             | Some(ExecutionTask::GlobalTimer)
+            | Some(ExecutionTask::OnLowWasmMemory)
             | Some(ExecutionTask::PausedInstallCode(..))
             | Some(ExecutionTask::AbortedExecution { .. })
             | Some(ExecutionTask::AbortedInstallCode { .. }) => false,
@@ -276,6 +341,7 @@ impl CanisterState {
             None
             | Some(ExecutionTask::Heartbeat)
             | Some(ExecutionTask::GlobalTimer)
+            | Some(ExecutionTask::OnLowWasmMemory)
             | Some(ExecutionTask::PausedExecution(..))
             | Some(ExecutionTask::AbortedExecution { .. })
             | Some(ExecutionTask::AbortedInstallCode { .. }) => false,
@@ -289,6 +355,7 @@ impl CanisterState {
             None
             | Some(ExecutionTask::Heartbeat)
             | Some(ExecutionTask::GlobalTimer)
+            | Some(ExecutionTask::OnLowWasmMemory)
             | Some(ExecutionTask::PausedExecution(..))
             | Some(ExecutionTask::PausedInstallCode(..))
             | Some(ExecutionTask::AbortedExecution { .. }) => false,
@@ -437,11 +504,14 @@ impl CanisterState {
 
     /// Returns the amount of raw memory currently used by the canister in bytes.
     ///
-    /// This only includes execution memory (heap, stable, globals, Wasm).
+    /// This only includes execution memory (heap, stable, globals, Wasm), the
+    /// canister log and the Wasm chunk store.
     pub(crate) fn raw_memory_usage(&self) -> NumBytes {
         self.execution_state
             .as_ref()
             .map_or(NumBytes::from(0), |es| es.memory_usage())
+            + NumBytes::from(self.system_state.canister_log.used_space() as u64)
+            + self.system_state.wasm_chunk_store.memory_usage()
     }
 
     /// Returns the amount of system state memory used by the canister in bytes
@@ -493,6 +563,27 @@ impl CanisterState {
         self.exports_method(&WasmMethod::System(SystemMethod::CanisterGlobalTimer))
     }
 
+    /// Returns true if the canister exports the `canister_on_low_wasm_memory`
+    /// system method.
+    pub fn exports_on_low_wasm_memory_method(&self) -> bool {
+        self.exports_method(&WasmMethod::System(SystemMethod::CanisterOnLowWasmMemory))
+    }
+
+    /// Returns true if the free Wasm heap of the canister, i.e. its
+    /// `wasm_memory_limit` minus the size of its Wasm heap, is below its
+    /// `wasm_memory_threshold`. Always false if either of them is not set.
+    pub fn is_low_wasm_memory(&self) -> bool {
+        let threshold = self.system_state.wasm_memory_threshold;
+        match (self.system_state.wasm_memory_limit, &self.execution_state) {
+            (Some(limit), Some(execution_state)) if threshold.get() > 0 => {
+                let heap_size = num_bytes_try_from(execution_state.wasm_memory.size)
+                    .expect("could not convert from wasm memory number of pages to bytes");
+                limit.get().saturating_sub(heap_size.get()) < threshold.get()
+            }
+            _ => false,
+        }
+    }
+
     /// Returns true if the canister exports the given Wasm method.
     pub fn exports_method(&self, method: &WasmMethod) -> bool {
         match &self.execution_state {
@@ -360,8 +360,9 @@ pub struct ExecutionState {
     /// properly when loading a state from checkpoint.
     pub wasm_binary: Arc<WasmBinary>,
 
-    /// The persistent heap of the module. The size of this memory is expected
-    /// to fit in a `u32`.
+    /// The persistent heap of the module. The size of this memory is at most
+    /// `MAX_WASM_MEMORY_IN_BYTES` for 32-bit heaps and
+    /// `MAX_WASM64_MEMORY_IN_BYTES` for 64-bit (memory64) heaps.
     #[debug_stub = "PageMap"]
     pub wasm_memory: Memory,
 
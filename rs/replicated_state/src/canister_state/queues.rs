@@ -15,9 +15,10 @@ use ic_protobuf::{
 };
 use ic_types::{
     messages::{
-        Ingress, Payload, RejectContext, Request, RequestOrResponse, Response,
+        CallbackId, Ingress, Payload, RejectContext, Request, RequestOrResponse, Response,
         MAX_RESPONSE_COUNT_BYTES,
     },
+    time::CoarseTime,
     xnet::{QueueId, SessionId},
     CanisterId, CountBytes, Cycles, Time,
 };
@@ -320,12 +321,13 @@ impl CanisterQueues {
 
     /// Pushes a canister-to-canister message into the induction pool.
     ///
-    /// If the message is a `Request` this will also reserve a slot in the
-    /// corresponding output queue for the eventual response.
+    /// If the message is a guaranteed response `Request` this will also reserve
+    /// a slot in the corresponding output queue for the eventual response.
     ///
-    /// If the message is a `Response` the protocol will have already reserved
-    /// space for it, so the push cannot fail due to the input queue being
-    /// full.
+    /// If the message is a guaranteed `Response` the protocol will have already
+    /// reserved space for it, so the push cannot fail due to the input queue
+    /// being full. No slots are reserved for best-effort responses, so pushing
+    /// one fails if the input queue has no available response slot.
     ///
     /// # Errors
     ///
@@ -335,8 +337,11 @@ impl CanisterQueues {
     ///  * `QueueFull` if pushing a `Request` and the corresponding input or
     ///    output queues are full.
     ///
-    ///  * `QueueFull` if pushing a `Response` and the receiving canister is not
-    ///  expecting one.
+    ///  * `QueueFull` if pushing a guaranteed `Response` and the receiving
+    ///  canister is not expecting one.
+    ///
+    ///  * `QueueFull` if pushing a best-effort `Response` and the input queue
+    ///  has no available response slot.
     pub(super) fn push_input(
         &mut self,
         msg: RequestOrResponse,
@@ -350,12 +355,19 @@ impl CanisterQueues {
                     return Err((e, msg));
                 }
                 // Safe to already (attempt to) reserve an output slot here, as the `push()`
-                // below is guaranteed to succeed due to the check above.
-                if let Err(e) = output_queue.reserve_slot() {
-                    return Err((e, msg));
+                // below is guaranteed to succeed due to the check above. Best-effort
+                // requests do not get a reservation.
+                if !msg.is_best_effort() {
+                    if let Err(e) = output_queue.reserve_slot() {
+                        return Err((e, msg));
+                    }
                 }
                 input_queue
             }
+            // Without a reservation, the queues may have been garbage collected.
+            RequestOrResponse::Response(_) if msg.is_best_effort() => {
+                self.get_or_insert_queues(&sender).0
+            }
             RequestOrResponse::Response(_) => match self.canister_queues.get_mut(&sender) {
                 Some((queue, _)) => queue,
                 None => return Err((StateError::QueueFull { capacity: 0 }, msg)),
@@ -559,7 +571,8 @@ impl CanisterQueues {
     }
 
     /// Pushes a `Request` type message into the relevant output queue. Also
-    /// reserves a slot for the eventual response on the matching input queue.
+    /// reserves a slot for the eventual response on the matching input queue,
+    /// unless this is a best-effort request.
     ///
     /// # Errors
     ///
@@ -575,19 +588,26 @@ impl CanisterQueues {
         if let Err(e) = output_queue.check_has_request_slot() {
             return Err((e, msg));
         }
-        if let Err(e) = input_queue.reserve_slot() {
-            return Err((e, msg));
+        if !msg.is_best_effort() {
+            if let Err(e) = input_queue.reserve_slot() {
+                return Err((e, msg));
+            }
+            self.input_queues_stats.reserved_slots += 1;
         }
 
         let mu_stats_delta = MemoryUsageStats::request_stats_delta(QueueOp::Push, &msg);
         let oq_stats_delta =
             OutputQueuesStats::stats_delta(&RequestOrResponse::Request(msg.clone()));
 
+        // Best-effort requests must not outlive their own deadline.
+        let mut deadline = time + REQUEST_LIFETIME;
+        if msg.is_best_effort() {
+            deadline = deadline.min(msg.deadline.as_time());
+        }
         output_queue
-            .push_request(msg, time + REQUEST_LIFETIME)
+            .push_request(msg, deadline)
             .expect("cannot fail due to the checks above");
 
-        self.input_queues_stats.reserved_slots += 1;
         self.output_queues_stats += oq_stats_delta;
         self.memory_usage_stats += mu_stats_delta;
         debug_assert!(self.stats_ok());
@@ -616,11 +636,14 @@ impl CanisterQueues {
             "reject_subnet_output_request can only be used to reject management canister requests"
         );
 
-        let (input_queue, _output_queue) = self.get_or_insert_queues(&request.receiver);
-        input_queue.reserve_slot()?;
-        self.input_queues_stats.reserved_slots += 1;
-        self.memory_usage_stats += MemoryUsageStats::response_slot_delta();
-        debug_assert!(self.stats_ok());
+        // A best-effort reject is pushed into an available slot, if any.
+        if !request.is_best_effort() {
+            let (input_queue, _output_queue) = self.get_or_insert_queues(&request.receiver);
+            input_queue.reserve_slot()?;
+            self.input_queues_stats.reserved_slots += 1;
+            self.memory_usage_stats += MemoryUsageStats::response_slot_delta();
+            debug_assert!(self.stats_ok());
+        }
 
         let response = RequestOrResponse::Response(Arc::new(Response {
             originator: request.sender,
@@ -628,6 +651,7 @@ impl CanisterQueues {
             originator_reply_callback: request.sender_reply_callback,
             refund: request.payment,
             response_payload: Payload::Reject(reject_context),
+            deadline: request.deadline,
         }));
         self.push_input(response, InputQueueType::LocalSubnet)
             .map_err(|(e, _msg)| e)
@@ -652,26 +676,40 @@ impl CanisterQueues {
             .collect()
     }
 
-    /// Pushes a `Response` type message into the relevant output queue. The
-    /// protocol should have already reserved a slot, so this cannot fail.
+    /// Pushes a `Response` type message into the relevant output queue. For a
+    /// guaranteed response, the protocol should have already reserved a slot, so
+    /// this cannot fail.
+    ///
+    /// A best-effort response is pushed into an available slot instead. If the
+    /// output queue has none, the response is dropped and the originator's
+    /// callback will eventually expire.
     ///
     /// # Panics
     ///
     /// Panics if the queue does not already exist or there is no reserved slot
-    /// to push the `Response` into.
+    /// to push a guaranteed `Response` into.
     pub fn push_output_response(&mut self, msg: Arc<Response>) {
         let mu_stats_delta = MemoryUsageStats::response_stats_delta(QueueOp::Push, &msg);
         let oq_stats_delta =
             OutputQueuesStats::stats_delta(&RequestOrResponse::Response(msg.clone()));
 
-        // Since we make an output queue reservation whenever we induct a request; and
-        // we would never garbage collect a non-empty queue (including one with just a
-        // reservation); we are guaranteed that the output queue exists.
-        self.canister_queues
-            .get_mut(&msg.originator)
-            .expect("pushing response into inexistent output queue")
-            .1
-            .push_response(msg);
+        if msg.is_best_effort() {
+            // No reservation was made, so the queues may have been garbage collected.
+            let (_, output_queue) = self.get_or_insert_queues(&msg.originator);
+            if output_queue.push_response(msg).is_err() {
+                return;
+            }
+        } else {
+            // Since we make an output queue reservation whenever we induct a request; and
+            // we would never garbage collect a non-empty queue (including one with just a
+            // reservation); we are guaranteed that the output queue exists.
+            self.canister_queues
+                .get_mut(&msg.originator)
+                .expect("pushing response into inexistent output queue")
+                .1
+                .push_response(msg)
+                .expect("pushing response without a reserved slot");
+        }
 
         self.memory_usage_stats += mu_stats_delta;
         self.output_queues_stats += oq_stats_delta;
@@ -969,18 +1007,26 @@ impl CanisterQueues {
     fn calculate_memory_usage_stats(
         canister_queues: &BTreeMap<CanisterId, (InputQueue, OutputQueue)>,
     ) -> MemoryUsageStats {
-        // Actual byte size for responses, 0 for requests.
+        // Actual byte size for guaranteed responses, 0 for everything else.
         let response_size_bytes = |msg: &RequestOrResponse| match *msg {
-            RequestOrResponse::Request(_) => 0,
-            RequestOrResponse::Response(_) => msg.count_bytes(),
+            RequestOrResponse::Response(_) if !msg.is_best_effort() => msg.count_bytes(),
+            _ => 0,
         };
-        // `max(0, msg.count_bytes() - MAX_RESPONSE_COUNT_BYTES)` for requests, 0 for
-        // responses.
+        // `max(0, msg.count_bytes() - MAX_RESPONSE_COUNT_BYTES)` for guaranteed
+        // response requests, 0 for everything else.
         let request_overhead_bytes = |msg: &RequestOrResponse| match *msg {
-            RequestOrResponse::Request(_) => {
+            RequestOrResponse::Request(_) if !msg.is_best_effort() => {
                 msg.count_bytes().saturating_sub(MAX_RESPONSE_COUNT_BYTES)
             }
-            RequestOrResponse::Response(_) => 0,
+            _ => 0,
+        };
+        // Actual byte size for best-effort messages, 0 for everything else.
+        let best_effort_size_bytes = |msg: &RequestOrResponse| {
+            if msg.is_best_effort() {
+                msg.count_bytes()
+            } else {
+                0
+            }
         };
 
         let mut stats = MemoryUsageStats::default();
@@ -988,10 +1034,12 @@ impl CanisterQueues {
             stats.responses_size_bytes += iq.calculate_stat_sum(response_size_bytes);
             stats.reserved_slots += iq.reserved_slots() as i64;
             stats.oversized_requests_extra_bytes += iq.calculate_stat_sum(request_overhead_bytes);
+            stats.best_effort_messages_size_bytes += iq.calculate_stat_sum(best_effort_size_bytes);
 
             stats.responses_size_bytes += oq.calculate_stat_sum(response_size_bytes);
             stats.reserved_slots += oq.reserved_slots() as i64;
-            stats.oversized_requests_extra_bytes += oq.calculate_stat_sum(request_overhead_bytes)
+            stats.oversized_requests_extra_bytes += oq.calculate_stat_sum(request_overhead_bytes);
+            stats.best_effort_messages_size_bytes += oq.calculate_stat_sum(best_effort_size_bytes);
         }
         stats
     }
@@ -1007,26 +1055,44 @@ impl CanisterQueues {
     /// Times out requests in `OutputQueues` given a current time, enqueuing a reject response
     /// for each into the matching `InputQueue`.
     ///
+    /// Best-effort requests are rejected with `SYS_UNKNOWN`, but only if the input
+    /// queue has an available response slot and `mark_best_effort_responded`
+    /// returns `true` for them (i.e. if their callback has not already been
+    /// answered). Otherwise they are silently dropped and their callback is left
+    /// to expire.
+    ///
     /// Updating the correct input queues schedule after enqueuing a reject response into a
     /// previously empty queue also requires the full set of local canisters to decide whether
     /// the destination canister was local or remote.
     ///
     /// Returns the number of requests that were timed out.
-    pub fn time_out_requests(
+    pub fn time_out_requests<F>(
         &mut self,
         current_time: Time,
         own_canister_id: &CanisterId,
         local_canisters: &BTreeMap<CanisterId, CanisterState>,
-    ) -> u64 {
+        mut mark_best_effort_responded: F,
+    ) -> u64
+    where
+        F: FnMut(&Request) -> bool,
+    {
         let mut timed_out_requests_count = 0;
         for (canister_id, (input_queue, output_queue)) in self.canister_queues.iter_mut() {
             for request in output_queue.time_out_requests(current_time) {
+                let push_response = !request.is_best_effort()
+                    || (input_queue.available_response_slots() > 0
+                        && mark_best_effort_responded(&request));
                 let response = generate_timeout_response(&request);
 
                 // Request was dropped, update stats.
                 let request = RequestOrResponse::Request(request);
                 self.memory_usage_stats -= MemoryUsageStats::stats_delta(QueueOp::Pop, &request);
                 self.output_queues_stats -= OutputQueuesStats::stats_delta(&request);
+                timed_out_requests_count += 1;
+
+                if !push_response {
+                    continue;
+                }
 
                 // Push response, update stats.
                 let iq_stats_delta = InputQueuesStats::stats_delta(QueueOp::Push, &response);
@@ -1043,8 +1109,6 @@ impl CanisterQueues {
                         self.remote_subnet_input_schedule.push_back(*canister_id);
                     }
                 }
-
-                timed_out_requests_count += 1;
             }
         }
 
@@ -1054,6 +1118,151 @@ impl CanisterQueues {
         timed_out_requests_count
     }
 
+    /// Returns the total byte size of best-effort messages across input and
+    /// output queues.
+    pub fn best_effort_messages_size_bytes(&self) -> usize {
+        self.memory_usage_stats.best_effort_messages_size_bytes
+    }
+
+    /// Sheds the largest sheddable best-effort message across all input and
+    /// output queues. Returns `false` if there was no message to shed.
+    ///
+    ///  * A shed output request is replaced with `None`. It is rejected with
+    ///    `SYS_UNKNOWN` under the same conditions as a timed out best-effort
+    ///    request (see `time_out_requests()`).
+    ///  * A shed input request is removed from its input queue. It is rejected
+    ///    with `SYS_UNKNOWN` if the matching output queue has an available
+    ///    response slot; otherwise its sender's callback is left to expire.
+    ///  * A shed response is replaced in place with a `SYS_UNKNOWN` reject
+    ///    carrying the same refund. Such rejects are never shed.
+    pub fn shed_largest_best_effort_message<F>(
+        &mut self,
+        own_canister_id: &CanisterId,
+        local_canisters: &BTreeMap<CanisterId, CanisterState>,
+        mark_best_effort_responded: F,
+    ) -> bool
+    where
+        F: FnOnce(&Request) -> bool,
+    {
+        let largest = self
+            .canister_queues
+            .iter()
+            .flat_map(|(canister_id, (input_queue, output_queue))| {
+                [
+                    input_queue
+                        .largest_sheddable_message()
+                        .map(|(size_bytes, i)| (size_bytes, *canister_id, QueueType::Input, i)),
+                    output_queue
+                        .largest_sheddable_message()
+                        .map(|(size_bytes, i)| (size_bytes, *canister_id, QueueType::Output, i)),
+                ]
+            })
+            .flatten()
+            .max();
+        let (_, canister_id, queue_type, i) = match largest {
+            Some(largest) => largest,
+            None => return false,
+        };
+
+        let (input_queue, output_queue) = self
+            .canister_queues
+            .get_mut(&canister_id)
+            .expect("Queues existed above so should not fail.");
+        let msg = match queue_type {
+            QueueType::Input => input_queue.get(i),
+            QueueType::Output => output_queue.get(i),
+        }
+        .expect("Message found above so should not fail.");
+
+        match (queue_type, msg) {
+            (QueueType::Output, RequestOrResponse::Request(_)) => {
+                let request = output_queue.take_request(i);
+                let has_response_slot = input_queue.available_response_slots() > 0;
+
+                let msg = RequestOrResponse::Request(Arc::clone(&request));
+                self.memory_usage_stats -= MemoryUsageStats::stats_delta(QueueOp::Pop, &msg);
+                self.output_queues_stats -= OutputQueuesStats::stats_delta(&msg);
+
+                if has_response_slot && mark_best_effort_responded(&request) {
+                    let response = shed_request_response(&request);
+                    self.push_synthetic_reject(response, own_canister_id, local_canisters)
+                        .expect("Response slot checked above so should not fail.");
+                }
+            }
+
+            (QueueType::Output, RequestOrResponse::Response(response)) => {
+                let shed = Arc::new(shed_response(response));
+                let original = output_queue.replace_response(i, Arc::clone(&shed));
+
+                let original = RequestOrResponse::Response(original);
+                let shed = RequestOrResponse::Response(shed);
+                self.memory_usage_stats -= MemoryUsageStats::stats_delta(QueueOp::Pop, &original);
+                self.memory_usage_stats += MemoryUsageStats::stats_delta(QueueOp::Push, &shed);
+                self.output_queues_stats -= OutputQueuesStats::stats_delta(&original);
+                self.output_queues_stats += OutputQueuesStats::stats_delta(&shed);
+            }
+
+            (QueueType::Input, RequestOrResponse::Request(_)) => {
+                let request = input_queue.remove_request(i);
+                if input_queue.num_messages() == 0 {
+                    // Only non-empty input queues may be scheduled.
+                    self.local_subnet_input_schedule
+                        .retain(|sender| sender != &canister_id);
+                    self.remote_subnet_input_schedule
+                        .retain(|sender| sender != &canister_id);
+                }
+
+                let msg = RequestOrResponse::Request(Arc::clone(&request));
+                self.input_queues_stats -= InputQueuesStats::stats_delta(QueueOp::Pop, &msg);
+                self.memory_usage_stats -= MemoryUsageStats::stats_delta(QueueOp::Pop, &msg);
+
+                // Dropped if the output queue has no available response slot.
+                self.push_output_response(Arc::new(shed_request_response(&request)));
+            }
+
+            (QueueType::Input, RequestOrResponse::Response(response)) => {
+                let shed = Arc::new(shed_response(response));
+                let original = input_queue.replace_response(i, Arc::clone(&shed));
+
+                let original = RequestOrResponse::Response(original);
+                let shed = RequestOrResponse::Response(shed);
+                self.input_queues_stats -= InputQueuesStats::stats_delta(QueueOp::Pop, &original);
+                self.input_queues_stats += InputQueuesStats::stats_delta(QueueOp::Push, &shed);
+                self.memory_usage_stats -= MemoryUsageStats::stats_delta(QueueOp::Pop, &original);
+                self.memory_usage_stats += MemoryUsageStats::stats_delta(QueueOp::Push, &shed);
+            }
+        }
+
+        debug_assert!(self.stats_ok());
+        debug_assert!(self.schedules_ok(own_canister_id, local_canisters));
+        true
+    }
+
+    /// Enqueues a synthetic reject response (e.g. for an expired best-effort
+    /// callback or a shed best-effort request) into an available slot of the
+    /// input queue from the respondent. Fails if there is no such slot.
+    ///
+    /// Whether the respondent is local is decided based on `own_canister_id`
+    /// and `local_canisters`.
+    pub(super) fn push_synthetic_reject(
+        &mut self,
+        response: Response,
+        own_canister_id: &CanisterId,
+        local_canisters: &BTreeMap<CanisterId, CanisterState>,
+    ) -> Result<(), (StateError, RequestOrResponse)> {
+        let input_queue_type = if &response.respondent == own_canister_id
+            || local_canisters.contains_key(&response.respondent)
+        {
+            InputQueueType::LocalSubnet
+        } else {
+            InputQueueType::RemoteSubnet
+        };
+        self.push_input(
+            RequestOrResponse::Response(Arc::new(response)),
+            input_queue_type,
+        )
+    }
+
     /// Re-partitions `self.local_subnet_input_schedule` and
     /// `self.remote_subnet_input_schedule` based on the set of all local canisters
     /// plus `own_canister_id` (since Rust's ownership rules would prevent us from
@@ -1106,13 +1315,74 @@ fn generate_timeout_response(request: &Arc<Request>) -> RequestOrResponse {
         originator_reply_callback: request.sender_reply_callback,
         refund: request.payment,
         response_payload: Payload::Reject(RejectContext::new_with_message_length_limit(
-            RejectCode::SysTransient,
+            if request.is_best_effort() {
+                RejectCode::SysUnknown
+            } else {
+                RejectCode::SysTransient
+            },
             "Request timed out.".to_string(),
             MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN,
         )),
+        deadline: request.deadline,
     }))
 }
 
+/// Builds a synthetic `SYS_UNKNOWN` reject response for a best-effort call.
+pub(super) fn synthetic_reject_response(
+    originator: CanisterId,
+    respondent: CanisterId,
+    originator_reply_callback: CallbackId,
+    refund: Cycles,
+    message: &str,
+    deadline: CoarseTime,
+) -> Response {
+    Response {
+        originator,
+        respondent,
+        originator_reply_callback,
+        refund,
+        response_payload: Payload::Reject(RejectContext::new_with_message_length_limit(
+            RejectCode::SysUnknown,
+            message.to_string(),
+            MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN,
+        )),
+        deadline,
+    }
+}
+
+/// Generates the reject response for a best-effort request shed under memory
+/// pressure.
+fn shed_request_response(request: &Request) -> Response {
+    synthetic_reject_response(
+        request.sender,
+        request.receiver,
+        request.sender_reply_callback,
+        request.payment,
+        "Request was dropped due to memory pressure.",
+        request.deadline,
+    )
+}
+
+/// Generates the reject response that replaces a best-effort response shed
+/// under memory pressure, carrying the same refund.
+fn shed_response(response: &Response) -> Response {
+    synthetic_reject_response(
+        response.originator,
+        response.respondent,
+        response.originator_reply_callback,
+        response.refund,
+        "Response was dropped due to memory pressure.",
+        response.deadline,
+    )
+}
+
+/// The type of a queue: input or output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum QueueType {
+    Input,
+    Output,
+}
+
 impl From<&CanisterQueues> for pb_queues::CanisterQueues {
     fn from(item: &CanisterQueues) -> Self {
         Self {
@@ -1259,9 +1529,9 @@ impl InputQueuesStats {
             RequestOrResponse::Response(_) => 1,
             RequestOrResponse::Request(_) => 0,
         };
-        // Consume one reservation iff pushing a response.
+        // Consume one reservation iff pushing a guaranteed response.
         let reserved_slots = match (op, msg) {
-            (QueueOp::Push, RequestOrResponse::Response(_)) => -1,
+            (QueueOp::Push, RequestOrResponse::Response(rep)) if !rep.is_best_effort() => -1,
             _ => 0,
         };
 
@@ -1335,8 +1605,9 @@ impl SubAssign<OutputQueuesStats> for OutputQueuesStats {
 }
 
 /// Running memory utilization stats for input and output queues: total byte
-/// size of all responses in input and output queues; and total reservations in
-/// input and output queues.
+/// size of all guaranteed responses in input and output queues; total
+/// reservations in input and output queues; and total byte size of all
+/// best-effort messages (which have no reservations).
 ///
 /// Memory allocation of output responses in streams is tracked separately, at
 /// the replicated state level (as the canister may be migrated to a different
@@ -1348,8 +1619,8 @@ impl SubAssign<OutputQueuesStats> for OutputQueuesStats {
 /// adding lots of zeros in lots of places.
 #[derive(Clone, Debug, Default, Eq)]
 struct MemoryUsageStats {
-    /// Sum total of the byte size of every response across input and output
-    /// queues.
+    /// Sum total of the byte size of every guaranteed response across input and
+    /// output queues.
     responses_size_bytes: usize,
 
     /// Sum total of reserved slots across input and output queues. This is
@@ -1366,6 +1637,10 @@ struct MemoryUsageStats {
     /// `MAX_RESPONSE_COUNT_BYTES`.
     oversized_requests_extra_bytes: usize,
 
+    /// Sum total of the byte size of every best-effort request and response
+    /// across input and output queues.
+    best_effort_messages_size_bytes: usize,
+
     /// Transient: size in bytes of responses routed from `output_queues` into
     /// streams and not yet garbage collected.
     ///
@@ -1380,6 +1655,7 @@ impl MemoryUsageStats {
         self.responses_size_bytes
             + self.reserved_slots as usize * MAX_RESPONSE_COUNT_BYTES
             + self.oversized_requests_extra_bytes
+            + self.best_effort_messages_size_bytes
             + self.transient_stream_responses_size_bytes
     }
 
@@ -1395,6 +1671,9 @@ impl MemoryUsageStats {
     /// Calculates the change in stats caused by pushing (+) or popping (-) a
     /// request.
     fn request_stats_delta(op: QueueOp, req: &Request) -> MemoryUsageStats {
+        if req.is_best_effort() {
+            return Self::best_effort_stats_delta(req.count_bytes());
+        }
         MemoryUsageStats {
             // No change in responses byte size (as this is a request).
             responses_size_bytes: 0,
@@ -1406,6 +1685,7 @@ impl MemoryUsageStats {
             oversized_requests_extra_bytes: req
                 .count_bytes()
                 .saturating_sub(MAX_RESPONSE_COUNT_BYTES),
+            best_effort_messages_size_bytes: 0,
             transient_stream_responses_size_bytes: 0,
         }
     }
@@ -1413,6 +1693,9 @@ impl MemoryUsageStats {
     /// Calculates the change in stats caused by pushing (+) or popping (-) the
     /// given response.
     fn response_stats_delta(op: QueueOp, rep: &Response) -> MemoryUsageStats {
+        if rep.is_best_effort() {
+            return Self::best_effort_stats_delta(rep.count_bytes());
+        }
         MemoryUsageStats {
             // Adjust responses byte size by this response's byte size.
             responses_size_bytes: rep.count_bytes(),
@@ -1423,6 +1706,20 @@ impl MemoryUsageStats {
             },
             // No change in requests overhead (as this is a response).
             oversized_requests_extra_bytes: 0,
+            best_effort_messages_size_bytes: 0,
+            transient_stream_responses_size_bytes: 0,
+        }
+    }
+
+    /// Calculates the change in stats caused by pushing (+) or popping (-) a
+    /// best-effort message of the given byte size. Best-effort messages neither
+    /// make nor consume reservations.
+    fn best_effort_stats_delta(size_bytes: usize) -> MemoryUsageStats {
+        MemoryUsageStats {
+            responses_size_bytes: 0,
+            reserved_slots: 0,
+            oversized_requests_extra_bytes: 0,
+            best_effort_messages_size_bytes: size_bytes,
             transient_stream_responses_size_bytes: 0,
         }
     }
@@ -1434,6 +1731,7 @@ impl MemoryUsageStats {
             responses_size_bytes: 0,
             reserved_slots: 1,
             oversized_requests_extra_bytes: 0,
+            best_effort_messages_size_bytes: 0,
             transient_stream_responses_size_bytes: 0,
         }
     }
@@ -1444,6 +1742,7 @@ impl AddAssign<MemoryUsageStats> for MemoryUsageStats {
         self.responses_size_bytes += rhs.responses_size_bytes;
         self.reserved_slots += rhs.reserved_slots;
         self.oversized_requests_extra_bytes += rhs.oversized_requests_extra_bytes;
+        self.best_effort_messages_size_bytes += rhs.best_effort_messages_size_bytes;
         debug_assert!(self.reserved_slots >= 0);
     }
 }
@@ -1453,6 +1752,7 @@ impl SubAssign<MemoryUsageStats> for MemoryUsageStats {
         self.responses_size_bytes -= rhs.responses_size_bytes;
         self.reserved_slots -= rhs.reserved_slots;
         self.oversized_requests_extra_bytes -= rhs.oversized_requests_extra_bytes;
+        self.best_effort_messages_size_bytes -= rhs.best_effort_messages_size_bytes;
         debug_assert!(self.reserved_slots >= 0);
     }
 }
@@ -1463,6 +1763,7 @@ impl PartialEq for MemoryUsageStats {
         self.responses_size_bytes == rhs.responses_size_bytes
             && self.reserved_slots == rhs.reserved_slots
             && self.oversized_requests_extra_bytes == rhs.oversized_requests_extra_bytes
+            && self.best_effort_messages_size_bytes == rhs.best_effort_messages_size_bytes
     }
 }
 
@@ -1470,28 +1771,34 @@ impl PartialEq for MemoryUsageStats {
 /// an input or output queue.
 ///
 /// Returns:
-///  * `Ok(())` if `msg` is a `Response`, as responses always return memory.
-///  * `Ok(())` if `msg` is a `Request` and `available_memory` is sufficient.
-///  * `Err(required_memory)` if `msg` is a `Request` and `required_memory >
-///    available_memory`.
+///  * `Ok(())` if `msg` is a guaranteed `Response`, as responses always return
+///    memory.
+///  * `Ok(())` if `msg` is a `Request` or best-effort `Response` and
+///    `available_memory` is sufficient.
+///  * `Err(required_memory)` if `msg` is a `Request` or best-effort `Response`
+///    and `required_memory > available_memory`.
 pub fn can_push(msg: &RequestOrResponse, available_memory: i64) -> Result<(), usize> {
-    match msg {
-        RequestOrResponse::Request(req) => {
-            let required = memory_required_to_push_request(req);
-            if required as i64 <= available_memory {
-                Ok(())
-            } else {
-                Err(required)
-            }
-        }
-        RequestOrResponse::Response(_) => Ok(()),
+    let required = match msg {
+        RequestOrResponse::Request(req) => memory_required_to_push_request(req),
+        // Best-effort responses have no memory reservation to consume.
+        RequestOrResponse::Response(rep) if rep.is_best_effort() => rep.count_bytes(),
+        RequestOrResponse::Response(_) => return Ok(()),
+    };
+    if required as i64 <= available_memory {
+        Ok(())
+    } else {
+        Err(required)
     }
 }
 
 /// Returns the memory required to push `req` onto an input or output queue.
 /// This is the maximum of `MAX_RESPONSE_COUNT_BYTES` (to be reserved for a
-/// response) and `req.count_bytes()` (if larger).
+/// response) and `req.count_bytes()` (if larger). Best-effort requests make no
+/// reservation, so they only require `req.count_bytes()`.
 pub fn memory_required_to_push_request(req: &Request) -> usize {
+    if req.is_best_effort() {
+        return req.count_bytes();
+    }
     req.count_bytes().max(MAX_RESPONSE_COUNT_BYTES)
 }
 
@@ -2,9 +2,10 @@ use crate::StateError;
 #[cfg(test)]
 mod tests;
 
+use ic_error_types::RejectCode;
 use ic_protobuf::proxy::ProxyDecodeError;
 use ic_protobuf::state::{ingress::v1 as pb_ingress, queues::v1 as pb_queues};
-use ic_types::messages::{Ingress, Request, RequestOrResponse, Response};
+use ic_types::messages::{Ingress, Payload, Request, RequestOrResponse, Response};
 use ic_types::{CountBytes, Cycles, Time};
 use std::{
     collections::VecDeque,
@@ -54,6 +55,22 @@ impl QueueItem<Option<RequestOrResponse>> for Option<RequestOrResponse> {
     }
 }
 
+/// Returns `true` if `msg` may be shed under memory pressure: any best-effort
+/// request; or any best-effort response, except for `SYS_UNKNOWN` rejects
+/// (which is what shed responses are replaced with).
+pub(super) fn is_sheddable(msg: &RequestOrResponse) -> bool {
+    match msg {
+        RequestOrResponse::Request(request) => request.is_best_effort(),
+        RequestOrResponse::Response(response) => {
+            response.is_best_effort()
+                && !matches!(
+                    &response.response_payload,
+                    Payload::Reject(context) if context.code() == RejectCode::SysUnknown
+                )
+        }
+    }
+}
+
 /// A FIFO queue with equal but separate capacities for requests and responses,
 /// ensuring full-duplex communication up to the capacity; and providing a
 /// backpressure mechanism in either direction, once the limit is reached. This
@@ -68,6 +85,9 @@ impl QueueItem<Option<RequestOrResponse>> for Option<RequestOrResponse> {
 /// first make a reservation for a response; and later push the response into
 /// the reserved slot, consuming the reservation. Attempting to push a response
 /// with no reservations available will produce an error.
+///
+/// Best-effort responses are the exception: no reservations are made for them,
+/// so they are pushed into an available response slot instead, if any.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct QueueWithReservation<T: QueueItem<T> + std::clone::Clone> {
     /// A FIFO queue of all requests and responses. Since responses may be enqueued
@@ -150,23 +170,34 @@ impl<T: QueueItem<T> + std::clone::Clone> QueueWithReservation<T> {
 
     /// Pushes a response into a reserved slot, consuming the reservation or
     /// returns an error if there is no reservation available.
+    ///
+    /// A best-effort response is pushed into an available response slot
+    /// instead; or an error is returned if there is none.
     fn push_response(
         &mut self,
         response: Arc<Response>,
     ) -> Result<(), (StateError, Arc<Response>)> {
-        if self.reserved_slots() > 0 {
-            self.queue
-                .push_back(<T as QueueItem<T>>::from_response(response));
-            debug_assert!(self.check_invariants());
-            Ok(())
+        let has_slot = if response.is_best_effort() {
+            self.available_response_slots() > 0
         } else {
-            Err((
+            self.reserved_slots() > 0
+        };
+        if !has_slot {
+            return Err((
                 StateError::QueueFull {
                     capacity: self.capacity,
                 },
                 response,
-            ))
+            ));
+        }
+
+        if response.is_best_effort() {
+            self.num_response_slots += 1;
         }
+        self.queue
+            .push_back(<T as QueueItem<T>>::from_response(response));
+        debug_assert!(self.check_invariants());
+        Ok(())
     }
 
     /// Pops an item from the queue. Returns `None` if the queue is empty.
@@ -365,6 +396,11 @@ impl InputQueue {
         self.queue.peek()
     }
 
+    /// Returns a reference to the message at queue position `i`, if any.
+    pub(super) fn get(&self, i: usize) -> Option<&RequestOrResponse> {
+        self.queue.queue.get(i)
+    }
+
     pub(super) fn reserve_slot(&mut self) -> Result<(), StateError> {
         self.queue.reserve_slot()
     }
@@ -410,6 +446,48 @@ impl InputQueue {
     pub(super) fn calculate_stat_sum(&self, stat: fn(&RequestOrResponse) -> usize) -> usize {
         self.queue.calculate_stat_sum(stat)
     }
+
+    /// Returns the byte size and queue position of the largest sheddable
+    /// message (see `is_sheddable()`) in the queue, if any.
+    ///
+    /// Time complexity: O(num_messages).
+    pub(super) fn largest_sheddable_message(&self) -> Option<(usize, usize)> {
+        self.queue
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, msg)| is_sheddable(msg))
+            .map(|(i, msg)| (msg.count_bytes(), i))
+            .max()
+    }
+
+    /// Removes the request at queue position `i` and returns it.
+    ///
+    /// # Panics
+    ///
+    /// If there is no request at position `i`.
+    pub(super) fn remove_request(&mut self, i: usize) -> Arc<Request> {
+        let request = match self.queue.queue.remove(i) {
+            Some(RequestOrResponse::Request(request)) => request,
+            _ => panic!("No request at input queue position {}", i),
+        };
+        self.queue.num_request_slots -= 1;
+        debug_assert!(self.queue.check_invariants());
+        request
+    }
+
+    /// Replaces the response at queue position `i` with `response` and returns
+    /// the original response.
+    ///
+    /// # Panics
+    ///
+    /// If there is no response at position `i`.
+    pub(super) fn replace_response(&mut self, i: usize, response: Arc<Response>) -> Arc<Response> {
+        match &mut self.queue.queue[i] {
+            RequestOrResponse::Response(original) => std::mem::replace(original, response),
+            RequestOrResponse::Request(_) => panic!("No response at input queue position {}", i),
+        }
+    }
 }
 
 impl From<&InputQueue> for pb_queues::InputOutputQueue {
@@ -524,10 +602,20 @@ impl OutputQueue {
         Ok(())
     }
 
-    pub(super) fn push_response(&mut self, response: Arc<Response>) {
-        self.queue.push_response(response).unwrap();
+    /// Pushes a response into a reserved slot (or into an available slot, for
+    /// a best-effort response). Returns an error if there is no such slot.
+    pub(super) fn push_response(
+        &mut self,
+        response: Arc<Response>,
+    ) -> Result<(), (StateError, Arc<Response>)> {
+        self.queue.push_response(response)?;
         self.num_messages += 1;
         debug_assert!(self.check_invariants());
+        Ok(())
+    }
+
+    pub(super) fn available_response_slots(&self) -> usize {
+        self.queue.available_response_slots()
     }
 
     pub(super) fn reserve_slot(&mut self) -> Result<(), StateError> {
@@ -644,6 +732,11 @@ impl OutputQueue {
         self.queue.peek().map(|msg| msg.as_ref().unwrap())
     }
 
+    /// Returns a reference to the message at queue position `i`, if any.
+    pub(super) fn get(&self, i: usize) -> Option<&RequestOrResponse> {
+        self.queue.queue.get(i).and_then(Option::as_ref)
+    }
+
     /// Number of actual messages in the queue (`None` are ignored).
     pub fn num_messages(&self) -> usize {
         self.num_messages
@@ -694,6 +787,53 @@ impl OutputQueue {
         }
     }
 
+    /// Returns the byte size and queue position of the largest sheddable
+    /// message (see `is_sheddable()`) in the queue, if any.
+    ///
+    /// Time complexity: O(queue length).
+    pub(super) fn largest_sheddable_message(&self) -> Option<(usize, usize)> {
+        self.queue
+            .queue
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| match item {
+                Some(msg) if is_sheddable(msg) => Some((msg.count_bytes(), i)),
+                _ => None,
+            })
+            .max()
+    }
+
+    /// Removes the request at queue position `i`, leaving `None` in its place,
+    /// and returns it.
+    ///
+    /// # Panics
+    ///
+    /// If there is no request at position `i`.
+    pub(super) fn take_request(&mut self, i: usize) -> Arc<Request> {
+        let request = match self.queue.queue[i].take() {
+            Some(RequestOrResponse::Request(request)) => request,
+            _ => panic!("No request at output queue position {}", i),
+        };
+        self.num_messages -= 1;
+        self.advance_to_next_message();
+        debug_assert!(self.check_invariants());
+
+        request
+    }
+
+    /// Replaces the response at queue position `i` with `response` and returns
+    /// the original response.
+    ///
+    /// # Panics
+    ///
+    /// If there is no response at position `i`.
+    pub(super) fn replace_response(&mut self, i: usize, response: Arc<Response>) -> Arc<Response> {
+        match &mut self.queue.queue[i] {
+            Some(RequestOrResponse::Response(original)) => std::mem::replace(original, response),
+            _ => panic!("No response at output queue position {}", i),
+        }
+    }
+
     /// Returns an iterator over the underlying messages.
     ///
     /// For testing purposes only.
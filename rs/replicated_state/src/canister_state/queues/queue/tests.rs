@@ -192,7 +192,9 @@ fn output_queue_push_response_succeeds() {
     assert_eq!(output_queue.queue.available_response_slots(), 1);
     output_queue.reserve_slot().unwrap();
     assert_eq!(output_queue.queue.available_response_slots(), 0);
-    output_queue.push_response(ResponseBuilder::default().build().into());
+    output_queue
+        .push_response(ResponseBuilder::default().build().into())
+        .unwrap();
     assert_eq!(output_queue.queue.available_response_slots(), 0);
 
     assert_eq!(1, output_queue.num_messages());
@@ -234,7 +236,9 @@ fn output_queue_push_to_full_queue_fails() {
 #[should_panic(expected = "called `Result::unwrap()` on an `Err` value")]
 fn output_push_without_reserved_slot_fails() {
     let mut queue = OutputQueue::new(10);
-    queue.push_response(ResponseBuilder::default().build().into());
+    queue
+        .push_response(ResponseBuilder::default().build().into())
+        .unwrap();
 }
 
 /// An explicit example of deadlines in OutputQueue, where we manually fill
@@ -253,7 +257,7 @@ fn output_queue_explicit_push_and_pop_test() {
 
     q.push_request(test_request.clone(), deadline1).unwrap();
     q.reserve_slot().unwrap();
-    q.push_response(test_response);
+    q.push_response(test_response).unwrap();
     q.push_request(test_request.clone(), deadline1).unwrap();
     q.push_request(test_request, deadline2).unwrap();
 
@@ -366,7 +370,7 @@ prop_compose! {
                 }
                 RequestOrResponse::Response(response) => {
                     q.reserve_slot().unwrap();
-                    q.push_response(response);
+                    q.push_response(response).unwrap();
                 }
             }
         }
@@ -475,7 +479,8 @@ fn output_queue_check_back_pressure_with_timed_out_requests() {
     let mut q = OutputQueue::new(1);
 
     q.reserve_slot().unwrap();
-    q.push_response(Arc::new(ResponseBuilder::default().build()));
+    q.push_response(Arc::new(ResponseBuilder::default().build()))
+        .unwrap();
 
     q.push_request(
         Arc::new(RequestBuilder::default().build()),
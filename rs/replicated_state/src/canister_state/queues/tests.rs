@@ -16,7 +16,10 @@ use ic_test_utilities::{
         messages::{IngressBuilder, RequestBuilder, ResponseBuilder},
     },
 };
-use ic_types::{messages::CallbackId, time::current_time_and_expiry_time};
+use ic_types::{
+    messages::{CallbackId, NO_DEADLINE},
+    time::{current_time_and_expiry_time, CoarseTime},
+};
 use maplit::btreemap;
 use proptest::prelude::*;
 use std::convert::TryInto;
@@ -108,6 +111,7 @@ impl CanisterQueuesFixture {
             Time::from_nanos_since_unix_epoch(u64::MAX),
             &self.this,
             &local_canisters,
+            |_| true,
         )
     }
 
@@ -1051,6 +1055,7 @@ fn test_stats() {
         reserved_slots: -1,
         responses_size_bytes: msg_size[3],
         oversized_requests_extra_bytes: 0,
+        best_effort_messages_size_bytes: 0,
         transient_stream_responses_size_bytes: 0,
     };
     assert_eq!(expected_mu_stats, queues.memory_usage_stats);
@@ -1161,6 +1166,7 @@ fn test_stats() {
         reserved_slots: -1,
         responses_size_bytes: msg_size[5],
         oversized_requests_extra_bytes: 0,
+        best_effort_messages_size_bytes: 0,
         transient_stream_responses_size_bytes: 0,
     };
     assert_eq!(expected_mu_stats, queues.memory_usage_stats);
@@ -1303,6 +1309,7 @@ fn test_stats_induct_message_to_self() {
         reserved_slots: -1,
         responses_size_bytes: response_size,
         oversized_requests_extra_bytes: 0,
+        best_effort_messages_size_bytes: 0,
         transient_stream_responses_size_bytes: 0,
     };
     assert_eq!(expected_mu_stats, queues.memory_usage_stats);
@@ -1809,6 +1816,7 @@ fn time_out_requests_pushes_correct_reject_responses() {
                     payment: Cycles::from(cycles as u64),
                     method_name: "No-Op".to_string(),
                     method_payload: vec![],
+                    deadline: NO_DEADLINE,
                 }),
                 deadline,
             )
@@ -1831,7 +1839,8 @@ fn time_out_requests_pushes_correct_reject_responses() {
     let current_time = deadline1 + REQUEST_LIFETIME;
     assert_eq!(
         3,
-        canister_queues.time_out_requests(current_time, &own_canister_id, &local_canisters),
+        canister_queues
+            .time_out_requests(current_time, &own_canister_id, &local_canisters, |_| true),
     );
 
     // Check that each canister has one request timed out and removed from the output queue and one
@@ -1864,7 +1873,8 @@ fn time_out_requests_pushes_correct_reject_responses() {
                     RejectCode::SysTransient,
                     "Request timed out.".to_string(),
                     MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN
-                ))
+                )),
+                deadline: NO_DEADLINE,
             }),
             *reject_response,
         );
@@ -1883,7 +1893,8 @@ fn time_out_requests_pushes_correct_reject_responses() {
     let current_time = deadline2 + REQUEST_LIFETIME;
     assert_eq!(
         1,
-        canister_queues.time_out_requests(current_time, &own_canister_id, &local_canisters),
+        canister_queues
+            .time_out_requests(current_time, &own_canister_id, &local_canisters, |_| true),
     );
 
     if let Some((input_queue, output_queue)) =
@@ -1898,3 +1909,260 @@ fn time_out_requests_pushes_correct_reject_responses() {
         VecDeque::from(vec![remote_canister_id]),
     );
 }
+
+/// Tests that best-effort requests are timed out no later than their deadline;
+/// and that they are rejected with `SYS_UNKNOWN` only if their callback had not
+/// already been answered.
+#[test]
+fn time_out_requests_best_effort() {
+    let mut canister_queues = CanisterQueues::default();
+
+    let own_canister_id = canister_test_id(67);
+    let remote_canister_id = canister_test_id(97);
+
+    let deadline = CoarseTime::from_secs_since_unix_epoch(10);
+    for callback_id in [1, 2] {
+        canister_queues
+            .push_output_request(
+                RequestBuilder::default()
+                    .sender(own_canister_id)
+                    .receiver(remote_canister_id)
+                    .sender_reply_callback(CallbackId::from(callback_id))
+                    .payment(Cycles::new(7))
+                    .deadline(deadline)
+                    .build()
+                    .into(),
+                mock_time(),
+            )
+            .unwrap();
+    }
+    assert_ne!(0, canister_queues.best_effort_messages_size_bytes());
+    // Best-effort requests do not reserve response slots.
+    assert_eq!(0, canister_queues.input_queues_reservation_count());
+
+    // Not yet timed out, even though `REQUEST_LIFETIME` would not have elapsed.
+    let local_canisters = BTreeMap::new();
+    assert_eq!(
+        0,
+        canister_queues.time_out_requests(
+            Time::from_nanos_since_unix_epoch(9_000_000_000),
+            &own_canister_id,
+            &local_canisters,
+            |_| true
+        ),
+    );
+
+    // Both requests time out, but only the one whose callback is still pending is
+    // rejected.
+    assert_eq!(
+        2,
+        canister_queues.time_out_requests(
+            deadline.as_time(),
+            &own_canister_id,
+            &local_canisters,
+            |request| request.sender_reply_callback == CallbackId::from(1)
+        ),
+    );
+
+    let (input_queue, output_queue) = canister_queues
+        .canister_queues
+        .get(&remote_canister_id)
+        .unwrap();
+    assert_eq!(0, output_queue.num_messages());
+    assert_eq!(1, input_queue.num_messages());
+    assert_eq!(0, input_queue.reserved_slots());
+    assert_eq!(
+        Some(&RequestOrResponse::Response(Arc::new(Response {
+            originator: own_canister_id,
+            respondent: remote_canister_id,
+            originator_reply_callback: CallbackId::from(1),
+            refund: Cycles::new(7),
+            response_payload: Payload::Reject(RejectContext::new_with_message_length_limit(
+                RejectCode::SysUnknown,
+                "Request timed out.".to_string(),
+                MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN
+            )),
+            deadline,
+        }))),
+        input_queue.peek()
+    );
+}
+
+/// Tests that shedding picks the largest best-effort request across all output
+/// queues, rejects it and leaves guaranteed response requests alone.
+#[test]
+fn shed_largest_best_effort_message_output_requests() {
+    let mut canister_queues = CanisterQueues::default();
+
+    let own_canister_id = canister_test_id(67);
+    let deadline = CoarseTime::from_secs_since_unix_epoch(10);
+
+    for (receiver, payload_size, deadline) in [
+        (canister_test_id(1), 1000, NO_DEADLINE),
+        (canister_test_id(2), 10, deadline),
+        (canister_test_id(3), 100, deadline),
+    ] {
+        canister_queues
+            .push_output_request(
+                RequestBuilder::default()
+                    .sender(own_canister_id)
+                    .receiver(receiver)
+                    .method_payload(vec![0; payload_size])
+                    .payment(Cycles::new(7))
+                    .deadline(deadline)
+                    .build()
+                    .into(),
+                mock_time(),
+            )
+            .unwrap();
+    }
+    // Only the guaranteed response request reserved a response slot.
+    assert_eq!(1, canister_queues.input_queues_reservation_count());
+
+    let local_canisters = BTreeMap::new();
+    let mut shed = vec![];
+    while canister_queues.shed_largest_best_effort_message(
+        &own_canister_id,
+        &local_canisters,
+        |request| {
+            shed.push(request.receiver);
+            true
+        },
+    ) {}
+    assert_eq!(vec![canister_test_id(3), canister_test_id(2)], shed);
+
+    // The guaranteed response request is still there.
+    assert_eq!(1, canister_queues.output_queues_message_count());
+    assert_eq!(1, canister_queues.input_queues_reservation_count());
+
+    // Both best-effort requests were rejected, refunding their payment.
+    assert_eq!(2, canister_queues.input_queues_message_count());
+    assert_eq!(Cycles::new(14), canister_queues.input_queue_cycles());
+    for respondent in [canister_test_id(2), canister_test_id(3)] {
+        let (input_queue, _) = canister_queues.canister_queues.get(&respondent).unwrap();
+        assert_matches!(
+            input_queue.peek(),
+            Some(RequestOrResponse::Response(response))
+                if matches!(&response.response_payload, Payload::Reject(context)
+                    if context.code() == RejectCode::SysUnknown)
+        );
+    }
+}
+
+/// Tests that shedding also covers input queues and responses: a shed response
+/// is replaced with a `SYS_UNKNOWN` reject; and a shed input request is removed
+/// and rejected via the output queue.
+#[test]
+fn shed_largest_best_effort_message_input_queues_and_responses() {
+    let mut canister_queues = CanisterQueues::default();
+
+    let own_canister_id = canister_test_id(67);
+    let caller = canister_test_id(1);
+    let callee = canister_test_id(2);
+    let deadline = CoarseTime::from_secs_since_unix_epoch(10);
+
+    // A best-effort response from `callee`, pushed without a reservation.
+    canister_queues
+        .push_input(
+            ResponseBuilder::default()
+                .originator(own_canister_id)
+                .respondent(callee)
+                .response_payload(Payload::Data(vec![0; 1000]))
+                .refund(Cycles::new(3))
+                .deadline(deadline)
+                .build()
+                .into(),
+            RemoteSubnet,
+        )
+        .unwrap();
+    // A smaller best-effort request from `caller`.
+    canister_queues
+        .push_input(
+            RequestBuilder::default()
+                .sender(caller)
+                .receiver(own_canister_id)
+                .method_payload(vec![0; 100])
+                .payment(Cycles::new(5))
+                .deadline(deadline)
+                .build()
+                .into(),
+            RemoteSubnet,
+        )
+        .unwrap();
+    // Neither made nor consumed a reservation.
+    assert_eq!(0, canister_queues.reserved_slots());
+    let size_before = canister_queues.best_effort_messages_size_bytes();
+
+    // The response is shed first, replaced in place by a reject.
+    assert!(canister_queues.shed_largest_best_effort_message(
+        &own_canister_id,
+        &BTreeMap::new(),
+        |_| unreachable!("No output request to shed")
+    ));
+    assert_eq!(2, canister_queues.input_queues_message_count());
+    assert!(canister_queues.best_effort_messages_size_bytes() < size_before);
+    let (input_queue, _) = canister_queues.canister_queues.get(&callee).unwrap();
+    assert_matches!(
+        input_queue.peek(),
+        Some(RequestOrResponse::Response(response))
+            if response.refund == Cycles::new(3)
+                && matches!(&response.response_payload, Payload::Reject(context)
+                    if context.code() == RejectCode::SysUnknown)
+    );
+
+    // Then the request, which is rejected via the output queue to `caller`.
+    assert!(canister_queues.shed_largest_best_effort_message(
+        &own_canister_id,
+        &BTreeMap::new(),
+        |_| unreachable!("No output request to shed")
+    ));
+    assert_eq!(1, canister_queues.input_queues_message_count());
+    assert_eq!(
+        VecDeque::from(vec![callee]),
+        canister_queues.remote_subnet_input_schedule
+    );
+    assert_matches!(
+        canister_queues.peek_output(&caller),
+        Some(RequestOrResponse::Response(response))
+            if response.refund == Cycles::new(5)
+                && matches!(&response.response_payload, Payload::Reject(context)
+                    if context.code() == RejectCode::SysUnknown)
+    );
+
+    // The rejects themselves are never shed.
+    assert!(!canister_queues.shed_largest_best_effort_message(
+        &own_canister_id,
+        &BTreeMap::new(),
+        |_| unreachable!("No output request to shed")
+    ));
+}
+
+/// Tests that best-effort responses take an available slot instead of a
+/// reservation; and are refused once the input queue has no available slot.
+#[test]
+fn push_input_best_effort_response_without_reservation() {
+    let mut canister_queues = CanisterQueues::default();
+
+    let own_canister_id = canister_test_id(67);
+    let callee = canister_test_id(2);
+    let response = |callback_id| -> RequestOrResponse {
+        ResponseBuilder::default()
+            .originator(own_canister_id)
+            .respondent(callee)
+            .originator_reply_callback(CallbackId::from(callback_id))
+            .deadline(CoarseTime::from_secs_since_unix_epoch(10))
+            .build()
+            .into()
+    };
+
+    for callback_id in 0..DEFAULT_QUEUE_CAPACITY as u64 {
+        canister_queues
+            .push_input(response(callback_id), RemoteSubnet)
+            .unwrap();
+    }
+    assert_eq!(0, canister_queues.reserved_slots());
+    assert_matches!(
+        canister_queues.push_input(response(DEFAULT_QUEUE_CAPACITY as u64), RemoteSubnet),
+        Err((StateError::QueueFull { .. }, _))
+    );
+}
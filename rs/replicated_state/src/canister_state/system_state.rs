@@ -1,11 +1,13 @@
 mod call_context_manager;
+pub mod wasm_chunk_store;
 
-use super::queues::can_push;
 pub use super::queues::memory_required_to_push_request;
+use super::queues::{can_push, synthetic_reject_response};
 pub use crate::canister_state::queues::CanisterOutputQueuesIterator;
 use crate::{CanisterQueues, CanisterState, InputQueueType, StateError};
 pub use call_context_manager::{CallContext, CallContextAction, CallContextManager, CallOrigin};
 use ic_base_types::NumSeconds;
+use ic_ic00_types::{CanisterChange, CanisterChangeDetails, CanisterChangeOrigin, LogVisibility};
 use ic_interfaces::messages::{CanisterCall, CanisterMessage, CanisterMessageOrTask, CanisterTask};
 use ic_logger::{error, ReplicaLogger};
 use ic_protobuf::{
@@ -14,8 +16,11 @@ use ic_protobuf::{
 };
 use ic_registry_subnet_type::SubnetType;
 use ic_types::{
+    canister_log::CanisterLog,
     messages::{Ingress, RejectContext, Request, RequestOrResponse, Response, StopCanisterContext},
+    methods::Callback,
     nominal_cycles::NominalCycles,
+    time::CoarseTime,
     CanisterId, CanisterTimer, Cycles, MemoryAllocation, NumBytes, PrincipalId, Time,
 };
 use lazy_static::lazy_static;
@@ -27,6 +32,7 @@ use std::{
 };
 use std::{collections::BTreeSet, sync::Arc};
 use std::{collections::VecDeque, str::FromStr};
+use wasm_chunk_store::WasmChunkStore;
 
 lazy_static! {
     static ref DEFAULT_PRINCIPAL_MULTIPLE_CONTROLLERS: PrincipalId =
@@ -103,6 +109,18 @@ pub struct SystemState {
     /// it will apply `cycles_debit` to `cycles_balance`.
     cycles_debit: Cycles,
 
+    /// Cycles reserved to pay for the future storage of memory that was
+    /// allocated while the subnet memory usage was above the threshold.
+    ///
+    /// Reserved cycles cannot be withdrawn or transferred: they can only be
+    /// spent on storage. Should only be modified through
+    /// `CyclesAccountManager` and `reserve_cycles()`.
+    reserved_balance: Cycles,
+
+    /// Upper bound on `reserved_balance`. Allocations that would need to
+    /// reserve more cycles fail. `None` means that no limit is set.
+    pub reserved_balance_limit: Option<Cycles>,
+
     /// Tasks to execute before processing input messages.
     /// Currently the task queue is empty outside of execution rounds.
     pub task_queue: VecDeque<ExecutionTask>,
@@ -112,6 +130,47 @@ pub struct SystemState {
 
     /// Canister version.
     pub canister_version: u64,
+
+    /// Log records produced by the canister via `ic0.debug_print` and
+    /// `ic0.trap`.
+    pub canister_log: CanisterLog,
+
+    /// Determines who is allowed to fetch the canister log.
+    pub log_visibility: LogVisibility,
+
+    /// Upper bound on the size of the Wasm heap of the canister. `None`
+    /// means that no limit is set. The limit is not enforced during upgrades.
+    pub wasm_memory_limit: Option<NumBytes>,
+
+    /// The `canister_on_low_wasm_memory` hook is run when the free Wasm heap
+    /// (`wasm_memory_limit` minus the heap size) drops below this threshold.
+    /// A threshold of zero disables the hook.
+    pub wasm_memory_threshold: NumBytes,
+
+    /// Whether the `canister_on_low_wasm_memory` hook has run since the free
+    /// Wasm heap last dropped below `wasm_memory_threshold`. It is reset once
+    /// the condition no longer holds, so that the hook can run again.
+    pub on_low_wasm_memory_hook_executed: bool,
+
+    /// Chunks uploaded via `upload_chunk` that can be assembled into a Wasm
+    /// module by `install_chunked_code`.
+    pub wasm_chunk_store: WasmChunkStore,
+
+    /// The most recent changes to the canister's code and controllers. Can
+    /// only be extended via `add_canister_change`.
+    canister_history: CanisterHistory,
+}
+
+/// Errors returned when moving cycles into the reserved balance.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReservationError {
+    /// The main balance does not have enough cycles for the reservation.
+    InsufficientCycles {
+        requested: Cycles,
+        available: Cycles,
+    },
+    /// The reservation would exceed `reserved_balance_limit`.
+    ReservedLimitExceed { requested: Cycles, limit: Cycles },
 }
 
 /// A wrapper around the different canister statuses.
@@ -211,6 +270,10 @@ pub enum ExecutionTask {
     /// The task exists only within an execution round, it never gets serialized.
     GlobalTimer,
 
+    /// Task that runs the `canister_on_low_wasm_memory` hook.
+    /// The task exists only within an execution round, it never gets serialized.
+    OnLowWasmMemory,
+
     // A paused execution task exists only within an epoch (between
     // checkpoints). It is never serialized, and it turns into `AbortedExecution`
     // before the checkpoint or when there are too many long-running executions.
@@ -249,6 +312,7 @@ impl From<&ExecutionTask> for pb::ExecutionTask {
         match item {
             ExecutionTask::Heartbeat
             | ExecutionTask::GlobalTimer
+            | ExecutionTask::OnLowWasmMemory
             | ExecutionTask::PausedExecution(_)
             | ExecutionTask::PausedInstallCode(_) => {
                 panic!("Attempt to serialize ephemeral task: {:?}.", item);
@@ -276,6 +340,9 @@ impl From<&ExecutionTask> for pb::ExecutionTask {
                     CanisterMessageOrTask::Task(CanisterTask::GlobalTimer) => {
                         PbInput::Task(PbCanisterTask::Timer as i32)
                     }
+                    CanisterMessageOrTask::Task(CanisterTask::OnLowWasmMemory) => {
+                        PbInput::Task(PbCanisterTask::OnLowWasmMemory as i32)
+                    }
                 };
                 Self {
                     task: Some(pb::execution_task::Task::AbortedExecution(
@@ -349,6 +416,7 @@ impl TryFrom<pb::ExecutionTask> for ExecutionTask {
                             }
                             PbCanisterTask::Heartbeat => CanisterTask::Heartbeat,
                             PbCanisterTask::Timer => CanisterTask::GlobalTimer,
+                            PbCanisterTask::OnLowWasmMemory => CanisterTask::OnLowWasmMemory,
                         };
                         CanisterMessageOrTask::Task(task)
                     }
@@ -387,6 +455,82 @@ impl TryFrom<pb::ExecutionTask> for ExecutionTask {
     }
 }
 
+/// The maximum number of changes kept in a canister's history. Older changes
+/// are evicted, but still counted in `CanisterHistory::total_num_changes`.
+pub const MAX_CANISTER_HISTORY_CHANGES: usize = 20;
+
+/// The most recent changes to a canister's code and controllers, ordered from
+/// oldest to newest.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CanisterHistory {
+    /// At most `MAX_CANISTER_HISTORY_CHANGES` most recent changes.
+    changes: VecDeque<CanisterChange>,
+    /// The total number of changes ever recorded, including evicted ones.
+    total_num_changes: u64,
+}
+
+impl CanisterHistory {
+    /// Creates a canister history from the given changes, e.g. when loading it
+    /// from a checkpoint. Changes beyond the limit are evicted.
+    pub fn new(changes: Vec<CanisterChange>, total_num_changes: u64) -> Self {
+        let mut changes: VecDeque<_> = changes.into();
+        while changes.len() > MAX_CANISTER_HISTORY_CHANGES {
+            changes.pop_front();
+        }
+        Self {
+            changes,
+            total_num_changes,
+        }
+    }
+
+    /// Records the given change, evicting the oldest one if the history is
+    /// full.
+    pub fn add_canister_change(&mut self, canister_change: CanisterChange) {
+        if self.changes.len() >= MAX_CANISTER_HISTORY_CHANGES {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(canister_change);
+        self.total_num_changes += 1;
+    }
+
+    /// Returns up to `num_requested_changes` most recent changes, ordered from
+    /// oldest to newest.
+    pub fn get_changes(
+        &self,
+        num_requested_changes: usize,
+    ) -> impl Iterator<Item = &CanisterChange> {
+        let num_skipped = self.changes.len().saturating_sub(num_requested_changes);
+        self.changes.iter().skip(num_skipped)
+    }
+
+    /// Returns the total number of changes ever recorded.
+    pub fn get_total_num_changes(&self) -> u64 {
+        self.total_num_changes
+    }
+}
+
+impl From<&CanisterHistory> for pb::CanisterHistory {
+    fn from(item: &CanisterHistory) -> Self {
+        Self {
+            changes: item.changes.iter().map(|change| change.into()).collect(),
+            total_num_changes: item.total_num_changes,
+        }
+    }
+}
+
+impl TryFrom<pb::CanisterHistory> for CanisterHistory {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::CanisterHistory) -> Result<Self, Self::Error> {
+        let changes = value
+            .changes
+            .into_iter()
+            .map(CanisterChange::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(changes, value.total_num_changes))
+    }
+}
+
 impl SystemState {
     pub fn new_running(
         canister_id: CanisterId,
@@ -449,6 +593,8 @@ impl SystemState {
             queues: CanisterQueues::default(),
             cycles_balance: initial_cycles,
             cycles_debit: Cycles::zero(),
+            reserved_balance: Cycles::zero(),
+            reserved_balance_limit: None,
             memory_allocation: MemoryAllocation::BestEffort,
             freeze_threshold,
             status,
@@ -457,6 +603,13 @@ impl SystemState {
             task_queue: Default::default(),
             global_timer: CanisterTimer::Inactive,
             canister_version: 0,
+            canister_log: Default::default(),
+            log_visibility: Default::default(),
+            wasm_memory_limit: None,
+            wasm_memory_threshold: NumBytes::from(0),
+            on_low_wasm_memory_hook_executed: false,
+            wasm_chunk_store: Default::default(),
+            canister_history: Default::default(),
         }
     }
 
@@ -488,9 +641,18 @@ impl SystemState {
         canister_metrics: CanisterMetrics,
         cycles_balance: Cycles,
         cycles_debit: Cycles,
+        reserved_balance: Cycles,
+        reserved_balance_limit: Option<Cycles>,
         task_queue: VecDeque<ExecutionTask>,
         global_timer: CanisterTimer,
         canister_version: u64,
+        canister_log: CanisterLog,
+        log_visibility: LogVisibility,
+        wasm_memory_limit: Option<NumBytes>,
+        wasm_memory_threshold: NumBytes,
+        on_low_wasm_memory_hook_executed: bool,
+        wasm_chunk_store: WasmChunkStore,
+        canister_history: CanisterHistory,
     ) -> Self {
         Self {
             controllers,
@@ -503,9 +665,18 @@ impl SystemState {
             canister_metrics,
             cycles_balance,
             cycles_debit,
+            reserved_balance,
+            reserved_balance_limit,
             task_queue,
             global_timer,
             canister_version,
+            canister_log,
+            log_visibility,
+            wasm_memory_limit,
+            wasm_memory_threshold,
+            on_low_wasm_memory_hook_executed,
+            wasm_chunk_store,
+            canister_history,
         }
     }
 
@@ -513,6 +684,28 @@ impl SystemState {
         self.canister_id
     }
 
+    /// Records a change to the canister's code or controllers, tagged with the
+    /// current canister version.
+    pub fn add_canister_change(
+        &mut self,
+        timestamp: Time,
+        origin: CanisterChangeOrigin,
+        details: CanisterChangeDetails,
+    ) {
+        self.canister_history
+            .add_canister_change(CanisterChange::new(
+                timestamp.as_nanos_since_unix_epoch(),
+                self.canister_version,
+                origin,
+                details,
+            ));
+    }
+
+    /// Returns the history of changes to the canister's code and controllers.
+    pub fn get_canister_history(&self) -> &CanisterHistory {
+        &self.canister_history
+    }
+
     /// Returns a mutable reference to the balance of the canister.
     pub fn balance_mut(&mut self) -> &mut Cycles {
         &mut self.cycles_balance
@@ -523,6 +716,45 @@ impl SystemState {
         self.cycles_balance
     }
 
+    /// Returns the amount of cycles in the reserved balance.
+    pub fn reserved_balance(&self) -> Cycles {
+        self.reserved_balance
+    }
+
+    /// Moves `amount` cycles from the main balance to the reserved balance.
+    ///
+    /// Fails without any changes if the main balance is insufficient or if
+    /// the new reserved balance would exceed `reserved_balance_limit`.
+    pub fn reserve_cycles(&mut self, amount: Cycles) -> Result<(), ReservationError> {
+        if amount == Cycles::zero() {
+            return Ok(());
+        }
+        if let Some(limit) = self.reserved_balance_limit {
+            let requested = self.reserved_balance + amount;
+            if requested > limit {
+                return Err(ReservationError::ReservedLimitExceed { requested, limit });
+            }
+        }
+        if amount > self.debited_balance() {
+            return Err(ReservationError::InsufficientCycles {
+                requested: amount,
+                available: self.debited_balance(),
+            });
+        }
+        self.cycles_balance -= amount;
+        self.reserved_balance += amount;
+        Ok(())
+    }
+
+    /// Removes up to `amount` cycles from the reserved balance and returns
+    /// the number of removed cycles. The caller is responsible for recording
+    /// the removed cycles as consumed.
+    pub fn remove_reserved_cycles(&mut self, amount: Cycles) -> Cycles {
+        let removed = std::cmp::min(amount, self.reserved_balance);
+        self.reserved_balance -= removed;
+        removed
+    }
+
     /// Returns the balance after applying the pending debit.
     /// Returns 0 if the balance is smaller than the pending debit.
     pub fn debited_balance(&self) -> Cycles {
@@ -622,7 +854,8 @@ impl SystemState {
     /// This is preceded by withdrawing the cycles for sending the `Request` and
     /// receiving and processing the corresponding `Response`.
     /// If cycles withdrawal succeeds, the function also reserves a slot on the
-    /// matching input queue for the `Response`.
+    /// matching input queue for the `Response` (unless this is a best-effort
+    /// `Request`).
     ///
     /// # Errors
     ///
@@ -653,8 +886,17 @@ impl SystemState {
             "Expected `Request` to have been sent from canister ID {}, but instead got {}",
             self.canister_id, request.sender
         );
+        let best_effort_callback_id = request
+            .is_best_effort()
+            .then_some(request.sender_reply_callback);
         self.queues
-            .reject_subnet_output_request(request, reject_context, subnet_ids)
+            .reject_subnet_output_request(request, reject_context, subnet_ids)?;
+        if let (Some(callback_id), Some(call_context_manager)) =
+            (best_effort_callback_id, self.call_context_manager_mut())
+        {
+            call_context_manager.mark_callback_responded(callback_id);
+        }
+        Ok(())
     }
 
     /// Returns the number of output requests that can be pushed onto the queue
@@ -670,10 +912,14 @@ impl SystemState {
     /// canister is also refunded the excess cycles that was reserved for
     /// sending this response when the original request was received.
     ///
+    /// Best-effort responses have no reserved slot and are dropped if the
+    /// output queue has no available slot (see
+    /// [`CanisterQueues::push_output_response`]).
+    ///
     /// # Panics
     ///
     /// Panics if the queue does not already exist or there is no reserved slot
-    /// to push the `Response` into.
+    /// to push a guaranteed `Response` into.
     pub fn push_output_response(&mut self, msg: Arc<Response>) {
         assert_eq!(
             msg.respondent, self.canister_id,
@@ -700,6 +946,11 @@ impl SystemState {
     /// cycles cost for sending the `Response` back. If it is a `Response`,
     /// the protocol should have already reserved a slot and memory for it.
     ///
+    /// Best-effort messages get no reservations: a best-effort `Request` only
+    /// requires memory for itself; and a best-effort `Response` requires memory
+    /// and an available input queue slot. A best-effort `Response` for a
+    /// callback that was already responded to is silently dropped.
+    ///
     /// Updates `subnet_available_memory` to reflect any change in memory usage.
     ///
     /// # Notes
@@ -737,15 +988,15 @@ impl SystemState {
             msg.receiver()
         );
 
-        match (&msg, &self.status) {
+        match (&msg, &mut self.status) {
             // Requests and responses are both rejected when stopped.
             (_, CanisterStatus::Stopped { .. }) => {
-                Err((StateError::CanisterStopped(self.canister_id()), msg))
+                Err((StateError::CanisterStopped(self.canister_id), msg))
             }
 
             // Requests (only) are rejected while stopping.
             (RequestOrResponse::Request(_), CanisterStatus::Stopping { .. }) => {
-                Err((StateError::CanisterStopping(self.canister_id()), msg))
+                Err((StateError::CanisterStopping(self.canister_id), msg))
             }
 
             // Everything else is accepted iff there is available memory and queue slots.
@@ -762,7 +1013,17 @@ impl SystemState {
                     ..
                 },
             ) => {
+                let mut best_effort_callback_id = None;
                 if let RequestOrResponse::Response(response) = &msg {
+                    if response.is_best_effort() {
+                        let callback_id = response.originator_reply_callback;
+                        if !call_context_manager.is_unexpired_callback(callback_id) {
+                            // The callback has already expired or been responded to (e.g.
+                            // with a synthetic reject), silently drop the response.
+                            return Ok(());
+                        }
+                        best_effort_callback_id = Some(callback_id);
+                    }
                     call_context_manager
                         .validate_response(response)
                         .map_err(|err| (err, msg.clone()))?;
@@ -774,7 +1035,11 @@ impl SystemState {
                     subnet_available_memory,
                     own_subnet_type,
                     input_queue_type,
-                )
+                )?;
+                if let Some(callback_id) = best_effort_callback_id {
+                    call_context_manager.mark_callback_responded(callback_id);
+                }
+                Ok(())
             }
         }
     }
@@ -883,10 +1148,12 @@ impl SystemState {
         own_subnet_type: SubnetType,
     ) {
         // Bail out if the canister is not running.
-        match self.status {
-            CanisterStatus::Running { .. } => (),
+        let call_context_manager = match &mut self.status {
+            CanisterStatus::Running {
+                call_context_manager,
+            } => call_context_manager,
             CanisterStatus::Stopped | CanisterStatus::Stopping { .. } => return,
-        }
+        };
 
         let mut available_memory = canister_available_memory.min(*subnet_available_memory);
         let mut memory_usage = self.queues.memory_usage() as i64;
@@ -898,6 +1165,20 @@ impl SystemState {
                 return;
             }
 
+            // Best-effort responses to expired callbacks are left to be routed via the
+            // loopback stream, where they will be dropped.
+            let best_effort_callback_id = match msg {
+                RequestOrResponse::Response(response) if response.is_best_effort() => {
+                    if !call_context_manager
+                        .is_unexpired_callback(response.originator_reply_callback)
+                    {
+                        return;
+                    }
+                    Some(response.originator_reply_callback)
+                }
+                _ => None,
+            };
+
             // Attempt inducting `msg`. May fail if the input queue is full.
             if self
                 .queues
@@ -906,6 +1187,9 @@ impl SystemState {
             {
                 return;
             }
+            if let Some(callback_id) = best_effort_callback_id {
+                call_context_manager.mark_callback_responded(callback_id);
+            }
 
             // Adjust both `available_memory` and `subnet_available_memory` by
             // `memory_usage_before - memory_usage_after`. Defer the accounting
@@ -939,8 +1223,133 @@ impl SystemState {
         own_canister_id: &CanisterId,
         local_canisters: &BTreeMap<CanisterId, CanisterState>,
     ) -> u64 {
+        let status = &mut self.status;
         self.queues
-            .time_out_requests(current_time, own_canister_id, local_canisters)
+            .time_out_requests(current_time, own_canister_id, local_canisters, |request| {
+                match status {
+                    CanisterStatus::Running {
+                        call_context_manager,
+                    }
+                    | CanisterStatus::Stopping {
+                        call_context_manager,
+                        ..
+                    } => {
+                        call_context_manager.mark_callback_responded(request.sender_reply_callback)
+                    }
+                    CanisterStatus::Stopped => false,
+                }
+            })
+    }
+
+    /// Queries whether any best-effort callbacks have expired at `current_time`.
+    pub fn has_expired_callbacks(&self, current_time: Time) -> bool {
+        self.call_context_manager()
+            .map(|ccm| ccm.has_expired_callbacks(CoarseTime::floor(current_time)))
+            .unwrap_or(false)
+    }
+
+    /// Expires the best-effort callbacks whose deadlines are before
+    /// `current_time`, enqueuing a `SYS_UNKNOWN` reject response for each into
+    /// the input queue from the respective respondent. Any response arriving
+    /// after this will be silently dropped.
+    ///
+    /// Since no slots are reserved for best-effort responses, a callback whose
+    /// respondent's input queue has no available slot is only expired on a
+    /// later call, once the queue has made progress.
+    ///
+    /// Returns the number of expired callbacks.
+    pub fn time_out_callbacks(
+        &mut self,
+        current_time: Time,
+        own_canister_id: &CanisterId,
+        local_canisters: &BTreeMap<CanisterId, CanisterState>,
+    ) -> u64 {
+        let call_context_manager = match &mut self.status {
+            CanisterStatus::Running {
+                call_context_manager,
+            }
+            | CanisterStatus::Stopping {
+                call_context_manager,
+                ..
+            } => call_context_manager,
+            CanisterStatus::Stopped => return 0,
+        };
+
+        let mut expired_callbacks_count = 0;
+        for callback_id in call_context_manager.expired_callbacks(CoarseTime::floor(current_time)) {
+            let (respondent, deadline) = match call_context_manager.callback(&callback_id) {
+                Some(Callback {
+                    respondent: Some(respondent),
+                    deadline,
+                    ..
+                }) => (*respondent, *deadline),
+                _ => continue,
+            };
+            let response = synthetic_reject_response(
+                self.canister_id,
+                respondent,
+                callback_id,
+                Cycles::zero(),
+                "Call deadline has expired.",
+                deadline,
+            );
+            if self
+                .queues
+                .push_synthetic_reject(response, own_canister_id, local_canisters)
+                .is_err()
+            {
+                // No available slot in the input queue, retry on the next call.
+                continue;
+            }
+            call_context_manager.mark_callback_responded(callback_id);
+            expired_callbacks_count += 1;
+        }
+        expired_callbacks_count
+    }
+
+    /// Returns the total byte size of best-effort messages across input and
+    /// output queues.
+    pub fn best_effort_messages_size_bytes(&self) -> usize {
+        self.queues.best_effort_messages_size_bytes()
+    }
+
+    /// Sheds best-effort messages from input and output queues, largest first,
+    /// until their total byte size is at most `limit_bytes`.
+    ///
+    /// See [`CanisterQueues::shed_largest_best_effort_message`] for how each
+    /// kind of message is rejected.
+    ///
+    /// Returns the number of shed messages.
+    pub fn shed_best_effort_messages(
+        &mut self,
+        limit_bytes: usize,
+        own_canister_id: &CanisterId,
+        local_canisters: &BTreeMap<CanisterId, CanisterState>,
+    ) -> u64 {
+        let mut shed_messages_count = 0;
+        while self.queues.best_effort_messages_size_bytes() > limit_bytes {
+            let status = &mut self.status;
+            if !self.queues.shed_largest_best_effort_message(
+                own_canister_id,
+                local_canisters,
+                |request| match status {
+                    CanisterStatus::Running {
+                        call_context_manager,
+                    }
+                    | CanisterStatus::Stopping {
+                        call_context_manager,
+                        ..
+                    } => {
+                        call_context_manager.mark_callback_responded(request.sender_reply_callback)
+                    }
+                    CanisterStatus::Stopped => false,
+                },
+            ) {
+                break;
+            }
+            shed_messages_count += 1;
+        }
+        shed_messages_count
     }
 
     /// Re-partitions the local and remote input schedules of `self.queues`
@@ -11,12 +11,13 @@ use ic_types::messages::Response;
 use ic_types::Time;
 use ic_types::{
     ingress::WasmResult,
-    messages::{CallContextId, CallbackId, MessageId},
+    messages::{CallContextId, CallbackId, MessageId, NO_DEADLINE},
     methods::Callback,
+    time::CoarseTime,
     user_id_into_protobuf, user_id_try_from_protobuf, CanisterId, Cycles, Funds, UserId,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::{From, TryFrom, TryInto};
 use std::time::Duration;
 
@@ -211,18 +212,38 @@ pub struct CallContextManager {
     // maps call context to its responded status
     call_contexts: BTreeMap<CallContextId, CallContext>,
     callbacks: BTreeMap<CallbackId, Callback>,
+    /// Best-effort callbacks that have not received a response (or a synthetic
+    /// reject) yet, ordered by deadline. A callback whose deadline has passed
+    /// remains here until a reject response could be enqueued for it.
+    unexpired_callbacks: BTreeSet<(CoarseTime, CallbackId)>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CallOrigin {
     Ingress(UserId, MessageId),
-    CanisterUpdate(CanisterId, CallbackId),
+    /// A canister call, with the deadline of the originating request
+    /// (`NO_DEADLINE` for guaranteed response calls).
+    CanisterUpdate(CanisterId, CallbackId, CoarseTime),
     Query(UserId),
     CanisterQuery(CanisterId, CallbackId),
     /// System task is either a Heartbeat or a GlobalTimer.
     SystemTask,
 }
 
+impl CallOrigin {
+    /// Returns the deadline of the originating call: that of the request for
+    /// best-effort canister calls; `NO_DEADLINE` for everything else.
+    pub fn deadline(&self) -> CoarseTime {
+        match self {
+            CallOrigin::CanisterUpdate(_, _, deadline) => *deadline,
+            CallOrigin::Ingress(..)
+            | CallOrigin::Query(_)
+            | CallOrigin::CanisterQuery(..)
+            | CallOrigin::SystemTask => NO_DEADLINE,
+        }
+    }
+}
+
 impl From<&CallOrigin> for pb::call_context::CallOrigin {
     fn from(item: &CallOrigin) -> Self {
         match item {
@@ -230,10 +251,11 @@ impl From<&CallOrigin> for pb::call_context::CallOrigin {
                 user_id: Some(user_id_into_protobuf(*user_id)),
                 message_id: message_id.as_bytes().to_vec(),
             }),
-            CallOrigin::CanisterUpdate(canister_id, callback_id) => {
+            CallOrigin::CanisterUpdate(canister_id, callback_id, deadline) => {
                 Self::CanisterUpdate(pb::call_context::CanisterUpdateOrQuery {
                     canister_id: Some(pb_types::CanisterId::from(*canister_id)),
                     callback_id: callback_id.get(),
+                    deadline_seconds: deadline.as_secs_since_unix_epoch(),
                 })
             }
             CallOrigin::Query(user_id) => Self::Query(user_id_into_protobuf(*user_id)),
@@ -241,6 +263,7 @@ impl From<&CallOrigin> for pb::call_context::CallOrigin {
                 Self::CanisterQuery(pb::call_context::CanisterUpdateOrQuery {
                     canister_id: Some(pb_types::CanisterId::from(*canister_id)),
                     callback_id: callback_id.get(),
+                    deadline_seconds: NO_DEADLINE.as_secs_since_unix_epoch(),
                 })
             }
             CallOrigin::SystemTask => Self::SystemTask(pb::call_context::SystemTask {}),
@@ -266,10 +289,12 @@ impl TryFrom<pb::call_context::CallOrigin> for CallOrigin {
                 pb::call_context::CanisterUpdateOrQuery {
                     canister_id,
                     callback_id,
+                    deadline_seconds,
                 },
             ) => Self::CanisterUpdate(
                 try_from_option_field(canister_id, "CallOrigin::CanisterUpdate::canister_id")?,
                 callback_id.into(),
+                CoarseTime::from_secs_since_unix_epoch(deadline_seconds),
             ),
             pb::call_context::CallOrigin::Query(user_id) => {
                 Self::Query(user_id_try_from_protobuf(user_id)?)
@@ -278,6 +303,7 @@ impl TryFrom<pb::call_context::CallOrigin> for CallOrigin {
                 pb::call_context::CanisterUpdateOrQuery {
                     canister_id,
                     callback_id,
+                    ..
                 },
             ) => Self::CanisterQuery(
                 try_from_option_field(canister_id, "CallOrigin::CanisterQuery::canister_id")?,
@@ -489,6 +515,10 @@ impl CallContextManager {
     pub fn register_callback(&mut self, callback: Callback) -> CallbackId {
         self.next_callback_id += 1;
         let callback_id = CallbackId::from(self.next_callback_id);
+        if callback.deadline != NO_DEADLINE {
+            self.unexpired_callbacks
+                .insert((callback.deadline, callback_id));
+        }
         self.callbacks.insert(callback_id, callback);
         callback_id
     }
@@ -501,7 +531,57 @@ impl CallContextManager {
     /// If we get a response for one of the outstanding calls, we unregister
     /// the callback and return it.
     pub fn unregister_callback(&mut self, callback_id: CallbackId) -> Option<Callback> {
-        self.callbacks.remove(&callback_id)
+        let callback = self.callbacks.remove(&callback_id)?;
+        self.unexpired_callbacks
+            .remove(&(callback.deadline, callback_id));
+        Some(callback)
+    }
+
+    /// Returns `true` if `callback_id` is a best-effort callback that has
+    /// neither received a response nor expired yet.
+    pub fn is_unexpired_callback(&self, callback_id: CallbackId) -> bool {
+        match self.callbacks.get(&callback_id) {
+            Some(callback) => self
+                .unexpired_callbacks
+                .contains(&(callback.deadline, callback_id)),
+            None => false,
+        }
+    }
+
+    /// Marks the best-effort callback `callback_id` as having received a
+    /// response (or a synthetic reject), so that it will not expire anymore.
+    ///
+    /// Returns `false` if the callback had already been responded to or had
+    /// expired.
+    pub fn mark_callback_responded(&mut self, callback_id: CallbackId) -> bool {
+        match self.callbacks.get(&callback_id) {
+            Some(callback) => self
+                .unexpired_callbacks
+                .remove(&(callback.deadline, callback_id)),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if any best-effort callbacks have deadlines before `now`.
+    pub fn has_expired_callbacks(&self, now: CoarseTime) -> bool {
+        self.unexpired_callbacks
+            .iter()
+            .next()
+            .map_or(false, |(deadline, _)| *deadline < now)
+    }
+
+    /// Returns the IDs of all best-effort callbacks with deadlines before `now`,
+    /// in deadline order.
+    ///
+    /// The callbacks only expire once the caller has enqueued a synthetic
+    /// reject response for them and called `mark_callback_responded()`. Until
+    /// then, a late response is still accepted.
+    pub fn expired_callbacks(&self, now: CoarseTime) -> Vec<CallbackId> {
+        self.unexpired_callbacks
+            .iter()
+            .take_while(|(deadline, _)| *deadline < now)
+            .map(|(_, callback_id)| *callback_id)
+            .collect()
     }
 
     /// Returns the call origin, which is either the message id of the ingress
@@ -576,9 +656,11 @@ impl CallContextManager {
 impl From<&CanisterCall> for CallOrigin {
     fn from(msg: &CanisterCall) -> Self {
         match msg {
-            CanisterCall::Request(request) => {
-                CallOrigin::CanisterUpdate(request.sender, request.sender_reply_callback)
-            }
+            CanisterCall::Request(request) => CallOrigin::CanisterUpdate(
+                request.sender,
+                request.sender_reply_callback,
+                request.deadline,
+            ),
             CanisterCall::Ingress(ingress) => {
                 CallOrigin::Ingress(ingress.source, ingress.message_id.clone())
             }
@@ -616,6 +698,11 @@ impl From<&CallContextManager> for pb::CallContextManager {
                     callback: Some(callback.into()),
                 })
                 .collect(),
+            unexpired_callbacks: item
+                .unexpired_callbacks
+                .iter()
+                .map(|(_, id)| id.get())
+                .collect(),
         }
     }
 }
@@ -646,11 +733,23 @@ impl TryFrom<pb::CallContextManager> for CallContextManager {
             );
         }
 
+        let mut unexpired_callbacks = BTreeSet::new();
+        for callback_id in value.unexpired_callbacks.into_iter().map(CallbackId::from) {
+            let callback = callbacks.get(&callback_id).ok_or_else(|| {
+                ProxyDecodeError::Other(format!(
+                    "CallContextManager::unexpired_callbacks: unknown callback {}",
+                    callback_id
+                ))
+            })?;
+            unexpired_callbacks.insert((callback.deadline, callback_id));
+        }
+
         Ok(Self {
             next_call_context_id: value.next_call_context_id,
             next_callback_id: value.next_callback_id,
             call_contexts,
             callbacks,
+            unexpired_callbacks,
         })
     }
 }
@@ -8,13 +8,13 @@ fn call_context_origin() {
     let id = canister_test_id(42);
     let cb_id = CallbackId::from(1);
     let cc_id = ccm.new_call_context(
-        CallOrigin::CanisterUpdate(id, cb_id),
+        CallOrigin::CanisterUpdate(id, cb_id, NO_DEADLINE),
         Cycles::new(10),
         Time::from_nanos_since_unix_epoch(0),
     );
     assert_eq!(
         ccm.call_contexts().get(&cc_id).unwrap().call_origin,
-        CallOrigin::CanisterUpdate(id, cb_id)
+        CallOrigin::CanisterUpdate(id, cb_id, NO_DEADLINE)
     );
 }
 
@@ -26,18 +26,18 @@ fn call_context_handling() {
 
     // On two incoming calls
     let call_context_id1 = call_context_manager.new_call_context(
-        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(1)),
+        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(1), NO_DEADLINE),
         Cycles::zero(),
         Time::from_nanos_since_unix_epoch(0),
     );
     let call_context_id2 = call_context_manager.new_call_context(
-        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(2)),
+        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(2), NO_DEADLINE),
         Cycles::zero(),
         Time::from_nanos_since_unix_epoch(0),
     );
 
     let call_context_id3 = call_context_manager.new_call_context(
-        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(3)),
+        CallOrigin::CanisterUpdate(canister_test_id(123), CallbackId::from(3), NO_DEADLINE),
         Cycles::zero(),
         Time::from_nanos_since_unix_epoch(0),
     );
@@ -78,6 +78,7 @@ fn call_context_handling() {
         WasmClosure::new(0, 1),
         WasmClosure::new(2, 3),
         None,
+        NO_DEADLINE,
     ));
     let callback_id2 = call_context_manager.register_callback(Callback::new(
         call_context_id1,
@@ -89,6 +90,7 @@ fn call_context_handling() {
         WasmClosure::new(4, 5),
         WasmClosure::new(6, 7),
         None,
+        NO_DEADLINE,
     ));
 
     // There are 2 ougoing calls
@@ -105,6 +107,7 @@ fn call_context_handling() {
         WasmClosure::new(8, 9),
         WasmClosure::new(10, 11),
         None,
+        NO_DEADLINE,
     ));
     // There is 1 outgoing call
     assert_eq!(call_context_manager.outstanding_calls(call_context_id2), 1);
@@ -254,7 +257,7 @@ fn withdraw_cycles_fails_when_not_enough_available_cycles() {
     let id = canister_test_id(42);
     let cb_id = CallbackId::from(1);
     let cc_id = ccm.new_call_context(
-        CallOrigin::CanisterUpdate(id, cb_id),
+        CallOrigin::CanisterUpdate(id, cb_id, NO_DEADLINE),
         Cycles::new(30),
         Time::from_nanos_since_unix_epoch(0),
     );
@@ -276,7 +279,7 @@ fn withdraw_cycles_succeeds_when_enough_available_cycles() {
     let id = canister_test_id(42);
     let cb_id = CallbackId::from(1);
     let cc_id = ccm.new_call_context(
-        CallOrigin::CanisterUpdate(id, cb_id),
+        CallOrigin::CanisterUpdate(id, cb_id, NO_DEADLINE),
         Cycles::new(30),
         Time::from_nanos_since_unix_epoch(0),
     );
@@ -288,3 +291,61 @@ fn withdraw_cycles_succeeds_when_enough_available_cycles() {
         Ok(())
     );
 }
+
+#[test]
+fn best_effort_callbacks_expire() {
+    let mut ccm = CallContextManager::default();
+    let call_context_id = ccm.new_call_context(
+        CallOrigin::CanisterUpdate(canister_test_id(1), CallbackId::from(1), NO_DEADLINE),
+        Cycles::zero(),
+        Time::from_nanos_since_unix_epoch(0),
+    );
+    let register_callback = |ccm: &mut CallContextManager, deadline: CoarseTime| {
+        ccm.register_callback(Callback::new(
+            call_context_id,
+            Some(canister_test_id(1)),
+            Some(canister_test_id(2)),
+            Cycles::zero(),
+            None,
+            None,
+            WasmClosure::new(0, 1),
+            WasmClosure::new(2, 3),
+            None,
+            deadline,
+        ))
+    };
+    let guaranteed = register_callback(&mut ccm, NO_DEADLINE);
+    let best_effort1 = register_callback(&mut ccm, CoarseTime::from_secs_since_unix_epoch(10));
+    let best_effort2 = register_callback(&mut ccm, CoarseTime::from_secs_since_unix_epoch(20));
+    let responded = register_callback(&mut ccm, CoarseTime::from_secs_since_unix_epoch(5));
+
+    assert!(!ccm.is_unexpired_callback(guaranteed));
+    assert!(ccm.is_unexpired_callback(best_effort1));
+    assert!(ccm.mark_callback_responded(responded));
+    assert!(!ccm.mark_callback_responded(responded));
+
+    // Unexpired callbacks survive a round trip through protobuf.
+    let pb_ccm: pb::CallContextManager = (&ccm).into();
+    assert_eq!(ccm, CallContextManager::try_from(pb_ccm).unwrap());
+
+    // A deadline expires once it is strictly in the past.
+    let now = CoarseTime::from_secs_since_unix_epoch(10);
+    assert!(!ccm.has_expired_callbacks(now));
+    assert!(ccm.expired_callbacks(now).is_empty());
+
+    let now = CoarseTime::from_secs_since_unix_epoch(11);
+    assert!(ccm.has_expired_callbacks(now));
+    assert_eq!(vec![best_effort1], ccm.expired_callbacks(now));
+
+    // Still pending until a reject response was enqueued for it.
+    assert!(ccm.is_unexpired_callback(best_effort1));
+    assert!(ccm.mark_callback_responded(best_effort1));
+    assert!(!ccm.has_expired_callbacks(now));
+    assert!(!ccm.is_unexpired_callback(best_effort1));
+    assert!(ccm.is_unexpired_callback(best_effort2));
+
+    // Expired callbacks are retained until the reject response is processed.
+    assert_eq!(4, ccm.callbacks().len());
+    ccm.unregister_callback(best_effort2);
+    assert!(!ccm.has_expired_callbacks(CoarseTime::from_secs_since_unix_epoch(100)));
+}
@@ -0,0 +1,218 @@
+use ic_protobuf::{proxy::ProxyDecodeError, state::canister_state_bits::v1 as pb};
+use ic_types::NumBytes;
+use std::{collections::BTreeMap, convert::TryFrom, sync::Arc};
+
+/// The maximum size of a single chunk that can be uploaded to the store.
+pub const MAX_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// The maximum total size of the chunks kept in the store of a canister.
+pub const DEFAULT_MAX_SIZE: NumBytes = NumBytes::new(100 * MAX_CHUNK_SIZE);
+
+/// The SHA-256 hash of a chunk, which is also its key in the store.
+pub type WasmChunkHash = [u8; 32];
+
+/// Errors that can occur when inserting a chunk into the store.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WasmChunkStoreError {
+    ChunkTooLarge { size: u64, max_size: u64 },
+    StoreFull { used: NumBytes, max_size: NumBytes },
+}
+
+impl std::fmt::Display for WasmChunkStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ChunkTooLarge { size, max_size } => write!(
+                f,
+                "Chunk size {} exceeds the maximum chunk size of {} bytes",
+                size, max_size
+            ),
+            Self::StoreFull { used, max_size } => write!(
+                f,
+                "Wasm chunk store is full: {} of {} bytes used",
+                used, max_size
+            ),
+        }
+    }
+}
+
+/// The chunks uploaded to a canister via the `upload_chunk` management method,
+/// keyed by their SHA-256 hash. The chunks can later be assembled into a Wasm
+/// module by `install_chunked_code`.
+///
+/// Chunks are shared behind `Arc`s so that cloning the store, e.g. as part of
+/// cloning the canister state, does not copy their contents.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WasmChunkStore {
+    chunks: BTreeMap<WasmChunkHash, Arc<Vec<u8>>>,
+    /// The total size of `chunks` in bytes.
+    size: NumBytes,
+}
+
+impl WasmChunkStore {
+    /// Returns the chunk with the given hash, if present.
+    pub fn get_chunk(&self, hash: &WasmChunkHash) -> Option<&[u8]> {
+        self.chunks.get(hash).map(|chunk| chunk.as_slice())
+    }
+
+    /// Returns the hashes of all chunks in the store in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = &WasmChunkHash> {
+        self.chunks.keys()
+    }
+
+    /// Returns the number of chunks in the store.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Returns the total size of the chunks in the store.
+    pub fn memory_usage(&self) -> NumBytes {
+        self.size
+    }
+
+    /// Returns the number of bytes by which the store would grow if the given
+    /// chunk was inserted, or an error if it cannot be inserted.
+    pub fn can_insert_chunk(
+        &self,
+        max_size: NumBytes,
+        chunk: &[u8],
+    ) -> Result<NumBytes, WasmChunkStoreError> {
+        let size = chunk.len() as u64;
+        if size > MAX_CHUNK_SIZE {
+            return Err(WasmChunkStoreError::ChunkTooLarge {
+                size,
+                max_size: MAX_CHUNK_SIZE,
+            });
+        }
+        if self.chunks.contains_key(&hash_chunk(chunk)) {
+            return Ok(NumBytes::from(0));
+        }
+        if self.size.get() + size > max_size.get() {
+            return Err(WasmChunkStoreError::StoreFull {
+                used: self.size,
+                max_size,
+            });
+        }
+        Ok(NumBytes::from(size))
+    }
+
+    /// Inserts the chunk into the store and returns its hash. Inserting a
+    /// chunk that is already present is a no-op.
+    pub fn insert_chunk(
+        &mut self,
+        max_size: NumBytes,
+        chunk: Vec<u8>,
+    ) -> Result<WasmChunkHash, WasmChunkStoreError> {
+        let added = self.can_insert_chunk(max_size, &chunk)?;
+        let hash = hash_chunk(&chunk);
+        self.chunks.entry(hash).or_insert_with(|| Arc::new(chunk));
+        self.size += added;
+        Ok(hash)
+    }
+
+    /// Removes all chunks from the store.
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.size = NumBytes::from(0);
+    }
+}
+
+/// Computes the SHA-256 hash of the given chunk.
+pub fn hash_chunk(chunk: &[u8]) -> WasmChunkHash {
+    ic_crypto_sha::Sha256::hash(chunk)
+}
+
+impl From<&WasmChunkStore> for pb::WasmChunkStore {
+    fn from(item: &WasmChunkStore) -> Self {
+        Self {
+            chunks: item
+                .chunks
+                .iter()
+                .map(|(hash, chunk)| pb::WasmChunk {
+                    hash: hash.to_vec(),
+                    content: chunk.as_ref().clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::WasmChunkStore> for WasmChunkStore {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb::WasmChunkStore) -> Result<Self, Self::Error> {
+        let mut store = WasmChunkStore::default();
+        for chunk in value.chunks {
+            let hash: WasmChunkHash = chunk.hash.try_into().map_err(|hash: Vec<u8>| {
+                ProxyDecodeError::InvalidDigestLength {
+                    expected: 32,
+                    actual: hash.len(),
+                }
+            })?;
+            store.size += NumBytes::from(chunk.content.len() as u64);
+            store.chunks.insert(hash, Arc::new(chunk.content));
+        }
+        Ok(store)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_chunk_returns_hash_and_deduplicates() {
+        let mut store = WasmChunkStore::default();
+        let hash = store.insert_chunk(DEFAULT_MAX_SIZE, vec![1, 2, 3]).unwrap();
+        assert_eq!(hash, hash_chunk(&[1, 2, 3]));
+        assert_eq!(store.get_chunk(&hash), Some(&[1, 2, 3][..]));
+
+        assert_eq!(
+            store.insert_chunk(DEFAULT_MAX_SIZE, vec![1, 2, 3]),
+            Ok(hash)
+        );
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.memory_usage(), NumBytes::from(3));
+    }
+
+    #[test]
+    fn insert_chunk_respects_limits() {
+        let mut store = WasmChunkStore::default();
+        let too_large = vec![0; MAX_CHUNK_SIZE as usize + 1];
+        assert_eq!(
+            store.insert_chunk(DEFAULT_MAX_SIZE, too_large),
+            Err(WasmChunkStoreError::ChunkTooLarge {
+                size: MAX_CHUNK_SIZE + 1,
+                max_size: MAX_CHUNK_SIZE
+            })
+        );
+
+        let max_size = NumBytes::from(5);
+        store.insert_chunk(max_size, vec![0; 4]).unwrap();
+        assert_eq!(
+            store.insert_chunk(max_size, vec![1; 2]),
+            Err(WasmChunkStoreError::StoreFull {
+                used: NumBytes::from(4),
+                max_size
+            })
+        );
+
+        store.clear();
+        assert!(store.is_empty());
+        assert_eq!(store.memory_usage(), NumBytes::from(0));
+        store.insert_chunk(max_size, vec![1; 2]).unwrap();
+    }
+
+    #[test]
+    fn proto_round_trip() {
+        let mut store = WasmChunkStore::default();
+        store.insert_chunk(DEFAULT_MAX_SIZE, vec![1; 10]).unwrap();
+        store.insert_chunk(DEFAULT_MAX_SIZE, vec![2; 20]).unwrap();
+
+        let pb_store = pb::WasmChunkStore::from(&store);
+        assert_eq!(WasmChunkStore::try_from(pb_store).unwrap(), store);
+    }
+}
@@ -2,7 +2,9 @@ use super::*;
 use crate::canister_state::execution_state::WasmMetadata;
 use crate::CallOrigin;
 use crate::Memory;
+use assert_matches::assert_matches;
 use ic_base_types::NumSeconds;
+use ic_error_types::RejectCode;
 use ic_logger::replica_logger::no_op_logger;
 use ic_test_utilities::mock_time;
 use ic_test_utilities::types::{
@@ -10,16 +12,18 @@ use ic_test_utilities::types::{
     messages::{RequestBuilder, ResponseBuilder},
 };
 use ic_types::messages::CallContextId;
-use ic_types::{
-    messages::CallbackId,
-    methods::{Callback, WasmClosure},
-    Time,
-};
 use ic_types::{
     messages::MAX_RESPONSE_COUNT_BYTES, nominal_cycles::NominalCycles, xnet::QueueId, CountBytes,
     Cycles,
 };
+use ic_types::{
+    messages::{CallbackId, Payload, NO_DEADLINE},
+    methods::{Callback, WasmClosure},
+    time::CoarseTime,
+    Time,
+};
 use ic_wasm_types::CanisterModule;
+use std::collections::BTreeMap;
 
 const CANISTER_ID: CanisterId = CanisterId::from_u64(42);
 const OTHER_CANISTER_ID: CanisterId = CanisterId::from_u64(13);
@@ -71,13 +75,17 @@ impl CanisterStateFixture {
     }
 
     fn make_callback(&mut self) -> CallbackId {
+        self.make_callback_with_deadline(NO_DEADLINE)
+    }
+
+    fn make_callback_with_deadline(&mut self, deadline: CoarseTime) -> CallbackId {
         let call_context_id = self
             .canister_state
             .system_state
             .call_context_manager_mut()
             .unwrap()
             .new_call_context(
-                CallOrigin::CanisterUpdate(CANISTER_ID, CallbackId::from(1)),
+                CallOrigin::CanisterUpdate(CANISTER_ID, CallbackId::from(1), NO_DEADLINE),
                 Cycles::zero(),
                 Time::from_nanos_since_unix_epoch(0),
             );
@@ -95,6 +103,7 @@ impl CanisterStateFixture {
                 WasmClosure::new(0, 2),
                 WasmClosure::new(0, 2),
                 None,
+                deadline,
             ))
     }
 
@@ -168,6 +177,63 @@ fn canister_state_push_input_response_success() {
         .unwrap();
 }
 
+#[test]
+fn canister_state_time_out_callbacks_drops_late_response() {
+    let mut fixture = CanisterStateFixture::new();
+    fixture.with_input_reservation();
+    let deadline = CoarseTime::from_secs_since_unix_epoch(10);
+    let callback_id = fixture.make_callback_with_deadline(deadline);
+
+    // Nothing expires before the deadline has passed.
+    let system_state = &mut fixture.canister_state.system_state;
+    assert!(!system_state.has_expired_callbacks(deadline.as_time()));
+    let current_time = Time::from_nanos_since_unix_epoch(11_000_000_000);
+    assert!(system_state.has_expired_callbacks(current_time));
+    assert_eq!(
+        1,
+        system_state.time_out_callbacks(current_time, &CANISTER_ID, &BTreeMap::new())
+    );
+    assert!(!system_state.has_expired_callbacks(current_time));
+
+    // A `SYS_UNKNOWN` reject response was enqueued.
+    assert_eq!(1, system_state.queues().input_queues_message_count());
+    match system_state.pop_input() {
+        Some(CanisterMessage::Response(response)) => {
+            assert_eq!(callback_id, response.originator_reply_callback);
+            assert_eq!(deadline, response.deadline);
+            assert_matches!(
+                &response.response_payload,
+                Payload::Reject(context) if context.code() == RejectCode::SysUnknown
+            );
+        }
+        msg => panic!("Expected a reject response, got {:?}", msg),
+    }
+
+    // The late response is silently dropped.
+    let late_response: RequestOrResponse = ResponseBuilder::default()
+        .originator(CANISTER_ID)
+        .respondent(OTHER_CANISTER_ID)
+        .originator_reply_callback(callback_id)
+        .deadline(deadline)
+        .build()
+        .into();
+    fixture
+        .push_input(
+            late_response,
+            SubnetType::Application,
+            InputQueueType::RemoteSubnet,
+        )
+        .unwrap();
+    assert_eq!(
+        0,
+        fixture
+            .canister_state
+            .system_state
+            .queues()
+            .input_queues_message_count()
+    );
+}
+
 #[test]
 #[should_panic(expected = "Expected `RequestOrResponse` to be targeted to canister ID")]
 fn canister_state_push_input_request_mismatched_receiver() {
@@ -552,6 +618,7 @@ fn canister_state_callback_round_trip() {
         WasmClosure::new(0, 2),
         WasmClosure::new(0, 2),
         None,
+        NO_DEADLINE,
     );
 
     let pb_callback = pb::Callback::from(&callback);
@@ -560,3 +627,36 @@ fn canister_state_callback_round_trip() {
 
     assert_eq!(callback, round_trip);
 }
+
+#[test]
+fn canister_history_evicts_oldest_changes() {
+    use crate::canister_state::system_state::{CanisterHistory, MAX_CANISTER_HISTORY_CHANGES};
+    use ic_ic00_types::{CanisterChange, CanisterChangeDetails, CanisterChangeOrigin};
+
+    let change = |i: u64| {
+        CanisterChange::new(
+            i,
+            i,
+            CanisterChangeOrigin::from_user(user_test_id(1).get()),
+            CanisterChangeDetails::CodeUninstall,
+        )
+    };
+
+    let mut canister_history = CanisterHistory::default();
+    let num_changes = MAX_CANISTER_HISTORY_CHANGES as u64 + 5;
+    for i in 0..num_changes {
+        canister_history.add_canister_change(change(i));
+    }
+
+    assert_eq!(canister_history.get_total_num_changes(), num_changes);
+    assert_eq!(
+        canister_history.get_changes(usize::MAX).count(),
+        MAX_CANISTER_HISTORY_CHANGES
+    );
+    // The most recent changes are returned, oldest first.
+    assert_eq!(
+        canister_history.get_changes(2).cloned().collect::<Vec<_>>(),
+        vec![change(num_changes - 2), change(num_changes - 1)]
+    );
+    assert_eq!(canister_history.get_changes(0).count(), 0);
+}
@@ -1,8 +1,11 @@
+pub mod query_stats;
 pub mod subnet_call_context_manager;
 #[cfg(test)]
 mod tests;
 
-use crate::metadata_state::subnet_call_context_manager::SubnetCallContextManager;
+use crate::metadata_state::{
+    query_stats::RawQueryStats, subnet_call_context_manager::SubnetCallContextManager,
+};
 use ic_base_types::CanisterId;
 use ic_btc_types::Network as BitcoinNetwork;
 use ic_btc_types_internal::BlockBlob;
@@ -141,6 +144,14 @@ pub struct SystemMetadata {
     /// response limit. To work around this limitation, large responses are paginated
     /// and are stored here temporarily until they're fetched by the calling canister.
     pub bitcoin_get_successors_follow_up_responses: BTreeMap<CanisterId, Vec<BlockBlob>>,
+
+    /// The id of the next canister snapshot taken on this subnet. Snapshot ids
+    /// are never reused, even after the snapshot has been deleted.
+    pub next_snapshot_id: u64,
+
+    /// Query statistics received through consensus that have not been
+    /// aggregated into the canisters' totals yet.
+    pub query_stats: RawQueryStats,
 }
 
 /// Full description of the IC network toplogy.
@@ -497,6 +508,8 @@ impl From<&SystemMetadata> for pb_metadata::SystemMetadata {
                     },
                 )
                 .collect(),
+            next_snapshot_id: item.next_snapshot_id,
+            query_stats: Some((&item.query_stats).into()),
         }
     }
 }
@@ -593,6 +606,11 @@ impl TryFrom<pb_metadata::SystemMetadata> for SystemMetadata {
             },
             expected_compiled_wasms: BTreeSet::new(),
             bitcoin_get_successors_follow_up_responses,
+            next_snapshot_id: item.next_snapshot_id,
+            query_stats: match item.query_stats {
+                Some(query_stats) => query_stats.try_into()?,
+                None => RawQueryStats::default(),
+            },
         })
     }
 }
@@ -626,6 +644,8 @@ impl SystemMetadata {
             subnet_metrics: Default::default(),
             expected_compiled_wasms: BTreeSet::new(),
             bitcoin_get_successors_follow_up_responses: BTreeMap::default(),
+            next_snapshot_id: 0,
+            query_stats: RawQueryStats::default(),
         }
     }
 
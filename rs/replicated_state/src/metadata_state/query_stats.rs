@@ -0,0 +1,168 @@
+use crate::canister_state::TotalQueryStats;
+use ic_base_types::CanisterId;
+use ic_protobuf::{
+    proxy::{try_from_option_field, ProxyDecodeError},
+    state::system_metadata::v1 as pb_metadata,
+    types::v1 as pb_types,
+};
+use ic_types::{
+    batch::{CanisterQueryStats, QueryStats, QueryStatsEpoch, QueryStatsPayload},
+    node_id_into_protobuf, node_id_try_from_protobuf, NodeId,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::TryFrom,
+};
+
+/// Query statistics that the nodes of the subnet reported through consensus
+/// and that have not been aggregated yet, indexed by epoch, canister and
+/// reporting node.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RawQueryStats {
+    /// The highest epoch whose statistics were aggregated; or `None` if no
+    /// epoch has been aggregated yet.
+    pub highest_aggregated_epoch: Option<QueryStatsEpoch>,
+    pub stats: BTreeMap<QueryStatsEpoch, BTreeMap<CanisterId, BTreeMap<NodeId, QueryStats>>>,
+}
+
+impl RawQueryStats {
+    /// Records the statistics reported by `payload.proposer`.
+    ///
+    /// Statistics of epochs that were already aggregated are ignored, as are
+    /// repeated reports of the same node for the same canister and epoch.
+    pub fn insert(&mut self, payload: QueryStatsPayload) {
+        if self.is_aggregated(payload.epoch) {
+            return;
+        }
+        let canisters = self.stats.entry(payload.epoch).or_default();
+        for CanisterQueryStats { canister_id, stats } in payload.stats {
+            canisters
+                .entry(canister_id)
+                .or_default()
+                .entry(payload.proposer)
+                .or_insert(stats);
+        }
+    }
+
+    /// Returns `true` if the statistics of `epoch` were already aggregated.
+    pub fn is_aggregated(&self, epoch: QueryStatsEpoch) -> bool {
+        self.highest_aggregated_epoch
+            .map_or(false, |aggregated| epoch <= aggregated)
+    }
+
+    /// Removes the statistics of all epochs up to and including `epoch` and
+    /// returns the estimated statistics of the whole subnet for each canister.
+    ///
+    /// For every value, the median across `nodes` (where a node that did not
+    /// report counts as zero) is taken and multiplied by the number of nodes.
+    /// Using the median means that a minority of nodes cannot skew the result
+    /// by reporting made-up statistics.
+    pub fn aggregate(
+        &mut self,
+        epoch: QueryStatsEpoch,
+        nodes: &BTreeSet<NodeId>,
+    ) -> BTreeMap<CanisterId, TotalQueryStats> {
+        let mut totals: BTreeMap<CanisterId, TotalQueryStats> = BTreeMap::new();
+        let newer_epochs = self.stats.split_off(&(epoch + QueryStatsEpoch::from(1)));
+        let aggregated_epochs = std::mem::replace(&mut self.stats, newer_epochs);
+        self.highest_aggregated_epoch = Some(
+            self.highest_aggregated_epoch
+                .map_or(epoch, |aggregated| aggregated.max(epoch)),
+        );
+
+        if nodes.is_empty() {
+            return totals;
+        }
+        for canisters in aggregated_epochs.into_values() {
+            for (canister_id, reports) in canisters {
+                let reports: Vec<QueryStats> = nodes
+                    .iter()
+                    .map(|node_id| reports.get(node_id).cloned().unwrap_or_default())
+                    .collect();
+                totals
+                    .entry(canister_id)
+                    .or_default()
+                    .saturating_accumulate(&TotalQueryStats {
+                        num_calls: estimate(&reports, |stats| stats.num_calls as u128),
+                        num_instructions: estimate(&reports, |stats| {
+                            stats.num_instructions as u128
+                        }),
+                        ingress_payload_size: estimate(&reports, |stats| {
+                            stats.ingress_payload_size as u128
+                        }),
+                        egress_payload_size: estimate(&reports, |stats| {
+                            stats.egress_payload_size as u128
+                        }),
+                    });
+            }
+        }
+        totals
+    }
+}
+
+/// Returns the (lower) median of the given field across `reports`, multiplied
+/// by the number of reports.
+fn estimate(reports: &[QueryStats], field: impl Fn(&QueryStats) -> u128) -> u128 {
+    let mut values: Vec<u128> = reports.iter().map(field).collect();
+    values.sort_unstable();
+    values[(values.len() - 1) / 2] * values.len() as u128
+}
+
+impl From<&RawQueryStats> for pb_metadata::RawQueryStats {
+    fn from(item: &RawQueryStats) -> Self {
+        let mut stats = vec![];
+        for (epoch, canisters) in &item.stats {
+            for (canister_id, reports) in canisters {
+                for (node_id, report) in reports {
+                    stats.push(pb_metadata::QueryStatsInner {
+                        epoch: epoch.get(),
+                        proposer: Some(node_id_into_protobuf(*node_id)),
+                        canister_id: Some(pb_types::CanisterId::from(*canister_id)),
+                        num_calls: report.num_calls,
+                        num_instructions: report.num_instructions,
+                        ingress_payload_size: report.ingress_payload_size,
+                        egress_payload_size: report.egress_payload_size,
+                    });
+                }
+            }
+        }
+        Self {
+            highest_aggregated_epoch: item.highest_aggregated_epoch.map(|epoch| epoch.get()),
+            stats,
+        }
+    }
+}
+
+impl TryFrom<pb_metadata::RawQueryStats> for RawQueryStats {
+    type Error = ProxyDecodeError;
+
+    fn try_from(item: pb_metadata::RawQueryStats) -> Result<Self, Self::Error> {
+        let mut stats: BTreeMap<_, BTreeMap<_, BTreeMap<_, _>>> = BTreeMap::new();
+        for entry in item.stats {
+            let node_id = node_id_try_from_protobuf(try_from_option_field(
+                entry.proposer,
+                "QueryStatsInner::proposer",
+            )?)?;
+            let canister_id: pb_types::CanisterId =
+                try_from_option_field(entry.canister_id, "QueryStatsInner::canister_id")?;
+            stats
+                .entry(QueryStatsEpoch::from(entry.epoch))
+                .or_default()
+                .entry(CanisterId::try_from(canister_id)?)
+                .or_default()
+                .insert(
+                    node_id,
+                    QueryStats {
+                        num_calls: entry.num_calls,
+                        num_instructions: entry.num_instructions,
+                        ingress_payload_size: entry.ingress_payload_size,
+                        egress_payload_size: entry.egress_payload_size,
+                    },
+                );
+        }
+        Ok(Self {
+            highest_aggregated_epoch: item.highest_aggregated_epoch.map(QueryStatsEpoch::from),
+            stats,
+        })
+    }
+}
@@ -6,7 +6,9 @@ use checkpoint::Checkpoint;
 pub use checkpoint::{CheckpointSerialization, MappingSerialization};
 use ic_sys::PageBytes;
 pub use ic_sys::{PageIndex, PAGE_SIZE};
-use ic_utils::{deterministic_operations::deterministic_copy_from_slice, fs::write_all_vectored};
+use ic_utils::{
+    deterministic_operations::deterministic_copy_from_slice, fs::write_all_vectored, layered_file,
+};
 pub use page_allocator::{
     allocated_pages_count, PageAllocator, PageAllocatorRegistry, PageAllocatorSerialization,
     PageDeltaSerialization, PageSerialization,
@@ -199,6 +201,11 @@ pub enum PersistenceError {
     },
     /// (Slice) size is not equal to page size.
     BadPageSize { expected: usize, actual: usize },
+    /// Overlay file is malformed.
+    InvalidOverlayFile {
+        path: String,
+        internal_error: String,
+    },
 }
 
 impl PersistenceError {
@@ -244,6 +251,10 @@ impl std::fmt::Display for PersistenceError {
                 "Bad slice size: expected {}, actual {}",
                 expected, actual
             ),
+            PersistenceError::InvalidOverlayFile {
+                path,
+                internal_error,
+            } => write!(f, "Invalid overlay file {}: {}", path, internal_error),
         }
     }
 }
@@ -434,12 +445,59 @@ impl PageMap {
         self.persist_to_file(&self.page_delta, dst)
     }
 
+    /// Persists the heap delta contained in this page map as an overlay of the
+    /// heap file `dst` written at the given height. Unlike `persist_delta()`,
+    /// this leaves `dst` itself untouched and writes only the dirty pages.
+    pub fn persist_delta_to_overlay(
+        &self,
+        dst: &Path,
+        height: Height,
+    ) -> Result<(), PersistenceError> {
+        if self.page_delta.is_empty() {
+            return Ok(());
+        }
+        let path = layered_file::overlay_path(dst, height.get());
+        layered_file::write_overlay(
+            &path,
+            self.page_delta
+                .iter()
+                .map(|(index, page)| (index.get(), &page.contents()[..])),
+        )
+        .map_err(|err| PersistenceError::FileSystemError {
+            path: path.display().to_string(),
+            context: "Failed to write overlay file".to_string(),
+            internal_error: err.to_string(),
+        })
+    }
+
     /// Persists the unflushed delta contained in this page map to the specified
     /// destination.
     pub fn persist_unflushed_delta(&self, dst: &Path) -> Result<(), PersistenceError> {
         self.persist_to_file(&self.unflushed_delta, dst)
     }
 
+    /// Persists all pages of this page map, including the pages backed by the
+    /// checkpoint file, to the specified destination. This is needed when the
+    /// destination does not contain the checkpoint file of this page map, e.g.
+    /// when persisting a canister snapshot for the first time.
+    pub fn persist_all(&self, dst: &Path) -> Result<(), PersistenceError> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(dst)
+            .map_err(|err| PersistenceError::FileSystemError {
+                path: dst.display().to_string(),
+                context: "Failed to open file".to_string(),
+                internal_error: err.to_string(),
+            })?;
+        let mut buffer = WriteBuffer {
+            content: self.host_pages_iter().map(|(_, page)| &page[..]).collect(),
+            start_index: PageIndex::new(0),
+        };
+        buffer.apply_to_file(&mut file, dst)
+    }
+
     /// Returns the iterator over host pages managed by this `PageMap`.
     pub fn host_pages_iter(&self) -> impl Iterator<Item = (PageIndex, &PageBytes)> + '_ {
         (0..self.num_host_pages()).map(move |i| {
@@ -486,12 +544,14 @@ impl PageMap {
         }
     }
 
-    /// Returns the whole checkpoint memory region.
+    /// Returns the whole memory region of the checkpoint file. Pages of the
+    /// overlay files on top of it are not included and have to be copied
+    /// as reported by `get_memory_region()`.
     pub fn get_checkpoint_memory_region(&self) -> MemoryRegion {
         let start = PageIndex::new(0);
         let end = PageIndex::new(u64::MAX);
         self.checkpoint
-            .get_memory_region(start, Range { start, end })
+            .get_base_memory_region(start, Range { start, end })
     }
 
     /// Removes the page delta from this page map.
@@ -1,6 +1,7 @@
 use crate::page_map::{FileDescriptor, MemoryRegion, PageIndex, PersistenceError};
 use ic_sys::{mmap::ScopedMmap, PAGE_SIZE};
 use ic_sys::{page_bytes_from_ptr, PageBytes};
+use ic_utils::layered_file::{self, OverlayIndex};
 use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
@@ -16,14 +17,22 @@ lazy_static! {
     static ref ZEROED_PAGE: Box<PageBytes> = Box::new([0; PAGE_SIZE]);
 }
 
+// Overlay files store pages at offsets that are multiples of `PAGE_SIZE`.
+const _: () = assert!(PAGE_SIZE == layered_file::PAGE_SIZE);
+
 /// Checkpoint represents a full snapshot of the heap of a single Wasm
 /// module.
 ///
-/// Conceptually it's an immutable byte array backed by a file and
-/// aligned to a page boundary.
+/// Conceptually it's an immutable byte array backed by a base file and
+/// aligned to a page boundary. The base file may be overlaid by a stack of
+/// overlay files that contain the pages modified by later checkpoints (see
+/// `ic_utils::layered_file`), in which case the checkpoint serves the merged
+/// view of all layers.
 #[derive(Clone)]
 pub(crate) struct Checkpoint {
     mapping: Option<Arc<Mapping>>,
+    /// The overlays ordered from the newest to the oldest.
+    overlays: Arc<Vec<Overlay>>,
 }
 
 struct Mapping {
@@ -64,6 +73,20 @@ impl Mapping {
     }
 
     fn open(path: &Path) -> Result<Option<Mapping>, PersistenceError> {
+        let (file, len) = Self::open_file(path)?;
+        if len % PAGE_SIZE != 0 {
+            return Err(PersistenceError::InvalidHeapFile {
+                path: path.display().to_string(),
+                file_size: len,
+                page_size: PAGE_SIZE,
+            });
+        }
+        Self::new(file, len, Some(path))
+    }
+
+    /// Opens the file at the given path for reading and returns it together
+    /// with its length.
+    fn open_file(path: &Path) -> Result<(File, usize), PersistenceError> {
         let file = OpenOptions::new().read(true).open(path).map_err(|err| {
             PersistenceError::FileSystemError {
                 path: path.display().to_string(),
@@ -79,15 +102,7 @@ impl Mapping {
                 internal_error: err.to_string(),
             })?;
 
-        let len = metadata.len() as usize;
-        if len % PAGE_SIZE != 0 {
-            return Err(PersistenceError::InvalidHeapFile {
-                path: path.display().to_string(),
-                file_size: len,
-                page_size: PAGE_SIZE,
-            });
-        }
-        Self::new(file, len, Some(path))
+        Ok((file, metadata.len() as usize))
     }
 
     /// Returns a serialization-friendly representation of `Mapping`.
@@ -130,7 +145,7 @@ impl Mapping {
         let num_pages = (self.mmap.len() / PAGE_SIZE) as u64;
         if page_index.get() >= num_pages {
             MemoryRegion::Zeros(Range {
-                start: PageIndex::new(num_pages),
+                start: PageIndex::new(std::cmp::max(num_pages, page_range.start.get())),
                 end: page_range.end,
             })
         } else {
@@ -149,17 +164,72 @@ impl Mapping {
     }
 }
 
+/// A memory-mapped overlay file together with its parsed index.
+struct Overlay {
+    mapping: Mapping,
+    index: OverlayIndex,
+}
+
+impl Overlay {
+    fn open(path: &Path) -> Result<Overlay, PersistenceError> {
+        let (file, len) = Mapping::open_file(path)?;
+        Self::from_mapping(
+            Mapping::new(file, len, Some(path))?,
+            &path.display().to_string(),
+        )
+    }
+
+    fn deserialize(serialized_mapping: MappingSerialization) -> Result<Overlay, PersistenceError> {
+        let path = format!("/proc/self/fd/{}", serialized_mapping.file_descriptor.fd);
+        Self::from_mapping(Mapping::deserialize(serialized_mapping)?, &path)
+    }
+
+    fn from_mapping(mapping: Option<Mapping>, path: &str) -> Result<Overlay, PersistenceError> {
+        let invalid_overlay = |internal_error: String| PersistenceError::InvalidOverlayFile {
+            path: path.to_string(),
+            internal_error,
+        };
+        let mapping = mapping.ok_or_else(|| invalid_overlay("the file is empty".to_string()))?;
+        let index = OverlayIndex::parse(mapping.mmap.as_slice())
+            .map_err(|err| invalid_overlay(err.to_string()))?;
+        Ok(Overlay { mapping, index })
+    }
+
+    fn get_page(&self, page_index: PageIndex) -> Option<&PageBytes> {
+        self.index
+            .slot(page_index.get())
+            .map(|slot| self.mapping.get_page(PageIndex::new(slot as u64)))
+    }
+}
+
 impl Checkpoint {
     /// Returns an empty checkpoint, not backed by any file. It serves
     /// zeroed pages.
     pub fn empty() -> Checkpoint {
-        Checkpoint { mapping: None }
+        Checkpoint {
+            mapping: None,
+            overlays: Default::default(),
+        }
     }
 
-    /// Opens an existing heap file located at the specified path.
+    /// Opens an existing heap file located at the specified path together
+    /// with all overlay files stacked on top of it.
     pub fn open(path: &Path) -> Result<Checkpoint, PersistenceError> {
-        Mapping::open(path).map(|mapping| Checkpoint {
+        let mapping = Mapping::open(path)?;
+        let overlay_paths =
+            layered_file::overlay_paths(path).map_err(|err| PersistenceError::FileSystemError {
+                path: path.display().to_string(),
+                context: "Failed to list overlay files".to_string(),
+                internal_error: err.to_string(),
+            })?;
+        let overlays = overlay_paths
+            .iter()
+            .rev()
+            .map(|path| Overlay::open(path))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Checkpoint {
             mapping: mapping.map(Arc::new),
+            overlays: Arc::new(overlays),
         })
     }
 
@@ -167,6 +237,11 @@ impl Checkpoint {
     pub fn serialize(&self) -> CheckpointSerialization {
         CheckpointSerialization {
             mapping: self.mapping.as_ref().map(|mapping| mapping.serialize()),
+            overlays: self
+                .overlays
+                .iter()
+                .map(|overlay| overlay.mapping.serialize())
+                .collect(),
         }
     }
 
@@ -179,24 +254,67 @@ impl Checkpoint {
             None => None,
             Some(mapping) => Mapping::deserialize(mapping)?,
         };
+        let overlays = serialized_checkpoint
+            .overlays
+            .into_iter()
+            .map(Overlay::deserialize)
+            .collect::<Result<Vec<_>, _>>()?;
         Ok(Checkpoint {
             mapping: mapping.map(Arc::new),
+            overlays: Arc::new(overlays),
         })
     }
 
     /// Returns the page with the specified `page_number`.
     pub fn get_page(&self, page_index: PageIndex) -> &PageBytes {
+        if let Some(page) = self.get_overlay_page(page_index) {
+            return page;
+        }
         match self.mapping {
             Some(ref mapping) => mapping.get_page(page_index),
             None => &ZEROED_PAGE,
         }
     }
 
+    /// Returns the page from the newest overlay that contains it.
+    fn get_overlay_page(&self, page_index: PageIndex) -> Option<&PageBytes> {
+        self.overlays
+            .iter()
+            .find_map(|overlay| overlay.get_page(page_index))
+    }
+
     /// See the comments of `PageMap::get_memory_region()`.
+    ///
+    /// Pages stored in overlays are returned as `MemoryRegion::BackedByPage`
+    /// and other regions never extend over them, because only the base file
+    /// can be mapped at the offsets that match the page indices.
     pub fn get_memory_region(
         &self,
         page_index: PageIndex,
         page_range: Range<PageIndex>,
+    ) -> MemoryRegion {
+        assert!(page_range.contains(&page_index));
+        if let Some(page) = self.get_overlay_page(page_index) {
+            return MemoryRegion::BackedByPage(page);
+        }
+        let mut page_range = page_range;
+        for overlay in self.overlays.iter() {
+            if let Some(prev) = overlay.index.prev_page(page_index.get()) {
+                page_range.start = std::cmp::max(page_range.start, PageIndex::new(prev + 1));
+            }
+            if let Some(next) = overlay.index.next_page(page_index.get()) {
+                page_range.end = std::cmp::min(page_range.end, PageIndex::new(next));
+            }
+        }
+        self.get_base_memory_region(page_index, page_range)
+    }
+
+    /// Same as `get_memory_region()` but ignores the overlays. The result
+    /// describes how the base file maps into memory.
+    pub fn get_base_memory_region(
+        &self,
+        page_index: PageIndex,
+        page_range: Range<PageIndex>,
     ) -> MemoryRegion {
         assert!(page_range.contains(&page_index));
         match self.mapping {
@@ -208,10 +326,15 @@ impl Checkpoint {
     /// Returns the max number of (possibly) non-zero pages in this
     /// checkpoint.
     pub fn num_pages(&self) -> usize {
-        match self.mapping {
+        let base_pages = match self.mapping {
             Some(ref mapping) => mapping.num_pages(),
             None => 0,
-        }
+        };
+        self.overlays
+            .iter()
+            .filter_map(|overlay| overlay.index.max_page_index())
+            .map(|max_page_index| max_page_index as usize + 1)
+            .fold(base_pages, std::cmp::max)
     }
 }
 
@@ -238,4 +361,6 @@ pub struct MappingSerialization {
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CheckpointSerialization {
     pub mapping: Option<MappingSerialization>,
+    /// The mappings of the overlay files, from the newest to the oldest.
+    pub overlays: Vec<MappingSerialization>,
 }
@@ -1,12 +1,12 @@
 use super::{
     checkpoint::{Checkpoint, MappingSerialization},
     page_allocator::PageAllocatorSerialization,
-    Buffer, FileDescriptor, PageAllocator, PageAllocatorRegistry, PageDelta, PageIndex, PageMap,
-    PageMapSerialization,
+    Buffer, FileDescriptor, MemoryRegion, PageAllocator, PageAllocatorRegistry, PageDelta,
+    PageIndex, PageMap, PageMapSerialization,
 };
 use crate::page_map::TestPageAllocatorFileDescriptorImpl;
 use ic_sys::PAGE_SIZE;
-use ic_types::{Height, MAX_STABLE_MEMORY_IN_BYTES};
+use ic_types::{Height, MAX_STABLE_MEMORY_IN_BYTES, MAX_WASM64_MEMORY_IN_BYTES};
 use nix::unistd::dup;
 use std::fs::OpenOptions;
 use std::sync::Arc;
@@ -26,16 +26,19 @@ fn assert_equal_page_maps(page_map1: &PageMap, page_map2: &PageMap) {
 fn duplicate_file_descriptors(
     mut serialized_page_map: PageMapSerialization,
 ) -> PageMapSerialization {
-    serialized_page_map.checkpoint.mapping =
-        serialized_page_map
-            .checkpoint
-            .mapping
-            .map(|mapping| MappingSerialization {
-                file_descriptor: FileDescriptor {
-                    fd: dup(mapping.file_descriptor.fd).unwrap(),
-                },
-                ..mapping
-            });
+    let duplicate = |mapping: MappingSerialization| MappingSerialization {
+        file_descriptor: FileDescriptor {
+            fd: dup(mapping.file_descriptor.fd).unwrap(),
+        },
+        ..mapping
+    };
+    serialized_page_map.checkpoint.mapping = serialized_page_map.checkpoint.mapping.map(duplicate);
+    serialized_page_map.checkpoint.overlays = serialized_page_map
+        .checkpoint
+        .overlays
+        .into_iter()
+        .map(duplicate)
+        .collect();
     serialized_page_map.page_allocator = PageAllocatorSerialization {
         id: serialized_page_map.page_allocator.id,
         fd: FileDescriptor {
@@ -159,6 +162,178 @@ fn persisted_map_is_equivalent_to_the_original() {
     assert_eq!(persisted_map, original_map);
 }
 
+#[test]
+fn persisted_map_with_pages_above_4_gib() {
+    let tmp = tempfile::Builder::new()
+        .prefix("checkpoints")
+        .tempdir()
+        .unwrap();
+    let heap_file = tmp.path().join("heap");
+
+    let pages_per_gib = (1 << 30) / PAGE_SIZE as u64;
+    let below_4_gib = PageIndex::new(4 * pages_per_gib - 1);
+    let at_4_gib = PageIndex::new(4 * pages_per_gib);
+    let last_page = PageIndex::new(MAX_WASM64_MEMORY_IN_BYTES / PAGE_SIZE as u64 - 1);
+
+    let page_1 = [1u8; PAGE_SIZE];
+    let page_2 = [2u8; PAGE_SIZE];
+    let page_3 = [3u8; PAGE_SIZE];
+
+    let mut original_map = PageMap::new_for_testing();
+    original_map.update(&[
+        (below_4_gib, &page_1),
+        (at_4_gib, &page_2),
+        (last_page, &page_3),
+    ]);
+    original_map.persist_delta(&heap_file).unwrap();
+
+    let persisted_map = PageMap::open(
+        &heap_file,
+        Height::new(0),
+        Arc::new(TestPageAllocatorFileDescriptorImpl::new()),
+    )
+    .unwrap();
+
+    assert_eq!(persisted_map.num_host_pages(), last_page.get() as usize + 1);
+    assert_eq!(persisted_map.get_page(below_4_gib), &page_1);
+    assert_eq!(persisted_map.get_page(at_4_gib), &page_2);
+    assert_eq!(persisted_map.get_page(last_page), &page_3);
+    assert_eq!(
+        persisted_map.get_page(PageIndex::new(at_4_gib.get() + 1)),
+        &[0u8; PAGE_SIZE]
+    );
+}
+
+#[test]
+fn page_map_with_overlays_is_equivalent_to_the_original() {
+    let tmp = tempfile::Builder::new()
+        .prefix("checkpoints")
+        .tempdir()
+        .unwrap();
+    let heap_file = tmp.path().join("heap.bin");
+    let fd_factory = Arc::new(TestPageAllocatorFileDescriptorImpl::new());
+
+    let page_1 = [1u8; PAGE_SIZE];
+    let page_2 = [2u8; PAGE_SIZE];
+    let page_3 = [3u8; PAGE_SIZE];
+
+    let mut base_map = PageMap::new_for_testing();
+    base_map.update(&[(PageIndex::new(0), &page_1), (PageIndex::new(1), &page_1)]);
+    base_map.persist_delta(&heap_file).unwrap();
+
+    // The first overlay overrides a page of the base and extends the map.
+    let mut original_map = PageMap::open(&heap_file, Height::new(1), fd_factory.clone()).unwrap();
+    original_map.update(&[(PageIndex::new(1), &page_2), (PageIndex::new(5), &page_2)]);
+    original_map
+        .persist_delta_to_overlay(&heap_file, Height::new(2))
+        .unwrap();
+    let base_len = std::fs::metadata(&heap_file).unwrap().len();
+    assert_eq!(base_len, 2 * PAGE_SIZE as u64);
+
+    // The second overlay overrides a page of the first one.
+    let mut original_map = PageMap::open(&heap_file, Height::new(2), fd_factory.clone()).unwrap();
+    original_map.update(&[(PageIndex::new(5), &page_3)]);
+    original_map
+        .persist_delta_to_overlay(&heap_file, Height::new(3))
+        .unwrap();
+    assert_eq!(std::fs::metadata(&heap_file).unwrap().len(), base_len);
+
+    let persisted_map = PageMap::open(&heap_file, Height::new(3), fd_factory).unwrap();
+    assert_eq!(persisted_map, original_map);
+    assert_eq!(persisted_map.num_host_pages(), 6);
+    assert_eq!(persisted_map.get_page(PageIndex::new(0)), &page_1);
+    assert_eq!(persisted_map.get_page(PageIndex::new(1)), &page_2);
+    assert_eq!(persisted_map.get_page(PageIndex::new(5)), &page_3);
+
+    // Pages from overlays are copied, the base file is mapped only where
+    // no overlay covers it.
+    match persisted_map.get_memory_region(PageIndex::new(0)) {
+        MemoryRegion::BackedByFile(range, _) => {
+            assert_eq!(range, PageIndex::new(0)..PageIndex::new(1))
+        }
+        _ => panic!("page 0 must be backed by the base file"),
+    }
+    assert!(matches!(
+        persisted_map.get_memory_region(PageIndex::new(1)),
+        MemoryRegion::BackedByPage(page) if page == &page_2
+    ));
+    match persisted_map.get_memory_region(PageIndex::new(3)) {
+        MemoryRegion::Zeros(range) => assert_eq!(range, PageIndex::new(2)..PageIndex::new(5)),
+        _ => panic!("page 3 must be zero"),
+    }
+
+    // Overlays are transferred to the sandbox together with the base file.
+    let serialized_page_map = duplicate_file_descriptors(persisted_map.serialize());
+    let deserialized_page_map =
+        PageMap::deserialize(serialized_page_map, &PageAllocatorRegistry::new()).unwrap();
+    assert_equal_page_maps(&persisted_map, &deserialized_page_map);
+}
+
+#[test]
+fn returns_an_error_if_overlay_is_corrupted() {
+    let tmp = tempfile::Builder::new()
+        .prefix("checkpoints")
+        .tempdir()
+        .unwrap();
+    let heap_file = tmp.path().join("heap.bin");
+    std::fs::write(&heap_file, []).unwrap();
+    std::fs::write(
+        ic_utils::layered_file::overlay_path(&heap_file, 1),
+        [0u8; PAGE_SIZE],
+    )
+    .unwrap();
+
+    match PageMap::open(
+        &heap_file,
+        Height::new(1),
+        Arc::new(TestPageAllocatorFileDescriptorImpl::new()),
+    ) {
+        Err(err) => assert!(
+            matches!(err, super::PersistenceError::InvalidOverlayFile { .. }),
+            "unexpected error: {}",
+            err
+        ),
+        Ok(_) => panic!("expected a corrupted overlay to be rejected"),
+    }
+}
+
+#[test]
+fn persist_all_includes_pages_from_checkpoint() {
+    let tmp = tempfile::Builder::new()
+        .prefix("checkpoints")
+        .tempdir()
+        .unwrap();
+    let heap_file = tmp.path().join("heap");
+    let copy_file = tmp.path().join("copy");
+
+    let page_1 = [1u8; PAGE_SIZE];
+    let page_5 = [5u8; PAGE_SIZE];
+
+    let mut base_map = PageMap::new_for_testing();
+    base_map.update(&[(PageIndex::new(1), &page_1)]);
+    base_map.persist_delta(&heap_file).unwrap();
+
+    let mut original_map = PageMap::open(
+        &heap_file,
+        Height::new(0),
+        Arc::new(TestPageAllocatorFileDescriptorImpl::new()),
+    )
+    .unwrap();
+    original_map.update(&[(PageIndex::new(5), &page_5)]);
+
+    // Unlike `persist_delta`, `persist_all` also writes the pages that are
+    // only present in the checkpoint file.
+    original_map.persist_all(&copy_file).unwrap();
+    let persisted_map = PageMap::open(
+        &copy_file,
+        Height::new(0),
+        Arc::new(TestPageAllocatorFileDescriptorImpl::new()),
+    )
+    .unwrap();
+
+    assert_equal_page_maps(&persisted_map, &original_map);
+}
+
 #[test]
 fn can_persist_and_load_an_empty_page_map() {
     let tmp = tempfile::Builder::new()
@@ -4,6 +4,7 @@ use super::{
 };
 use crate::{
     bitcoin_state::{BitcoinState, BitcoinStateError},
+    canister_snapshots::CanisterSnapshots,
     canister_state::queues::CanisterQueuesLoopDetector,
     canister_state::system_state::{push_input, CanisterOutputQueuesIterator},
     metadata_state::StreamMap,
@@ -18,21 +19,27 @@ use ic_registry_subnet_features::BitcoinFeatureStatus;
 use ic_registry_subnet_type::SubnetType;
 use ic_types::messages::Ingress;
 use ic_types::{
+    batch::{QueryStatsEpoch, QueryStatsPayload},
     ingress::IngressStatus,
     messages::{CallbackId, MessageId, RequestOrResponse, Response},
     xnet::QueueId,
-    CanisterId, MemoryAllocation, NumBytes, SubnetId, Time,
+    CanisterId, MemoryAllocation, NodeId, NumBytes, SubnetId, Time,
 };
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::sync::Arc;
 
 /// Maximum message length of a synthetic reject response produced by message
 /// routing.
 pub const MR_SYNTHETIC_REJECT_MESSAGE_MAX_LEN: usize = 255;
 
+/// Maximum total byte size of best-effort messages that a canister may have
+/// in its input and output queues. Beyond this, the largest best-effort
+/// messages are shed (and rejected) until the total drops back under the limit.
+pub const BEST_EFFORT_MESSAGES_MAX_BYTES_PER_CANISTER: usize = 10 * 1024 * 1024;
+
 /// Input queue type: local or remote subnet.
 #[derive(Clone, Copy, Eq, Debug, PartialEq)]
 pub enum InputQueueType {
@@ -329,6 +336,9 @@ pub struct ReplicatedState {
     pub consensus_queue: Vec<Response>,
 
     bitcoin: BitcoinState,
+
+    /// Snapshots of canister states taken via the management canister.
+    pub canister_snapshots: CanisterSnapshots,
 }
 
 impl ReplicatedState {
@@ -340,6 +350,7 @@ impl ReplicatedState {
             subnet_queues: CanisterQueues::default(),
             consensus_queue: Vec::new(),
             bitcoin: BitcoinState::default(),
+            canister_snapshots: CanisterSnapshots::default(),
         }
     }
 
@@ -356,6 +367,7 @@ impl ReplicatedState {
             subnet_queues,
             consensus_queue: Vec::new(),
             bitcoin,
+            canister_snapshots: CanisterSnapshots::default(),
         };
         res.update_stream_responses_size_bytes();
         res
@@ -523,8 +535,8 @@ impl ReplicatedState {
     }
 
     /// Returns:
-    ///   * the raw total memory taken by canisters in bytes, i.e. without
-    ///     including memory taken by canister messages
+    ///   * the raw total memory taken by canisters and their snapshots in
+    ///     bytes, i.e. without including memory taken by canister messages
     ///   * the memory taken by canister messages in bytes
     pub fn raw_total_and_message_memory_taken(&self) -> (NumBytes, NumBytes) {
         let (raw_memory_taken, mut message_memory_taken) = self
@@ -543,7 +555,10 @@ impl ReplicatedState {
 
         message_memory_taken += (self.subnet_queues.memory_usage() as u64).into();
 
-        (raw_memory_taken, message_memory_taken)
+        (
+            raw_memory_taken + self.canister_snapshots.memory_taken(),
+            message_memory_taken,
+        )
     }
 
     /// Returns the total memory taken by the ingress history in bytes.
@@ -780,6 +795,50 @@ impl ReplicatedState {
         self.bitcoin = bitcoin;
     }
 
+    /// Records the query statistics that a node of this subnet reported
+    /// through consensus. Statistics reported by nodes that are not members of
+    /// the subnet are ignored.
+    pub fn deliver_query_stats(&mut self, payload: QueryStatsPayload) {
+        let is_member = self
+            .metadata
+            .network_topology
+            .subnets
+            .get(&self.metadata.own_subnet_id)
+            .map_or(false, |subnet| subnet.nodes.contains_key(&payload.proposer));
+        if is_member {
+            self.metadata.query_stats.insert(payload);
+        }
+    }
+
+    /// Aggregates the query statistics of all epochs up to and including
+    /// `epoch` and adds them to the totals of the respective canisters.
+    ///
+    /// See `RawQueryStats::aggregate` for how the statistics reported by the
+    /// individual nodes are combined.
+    pub fn aggregate_query_stats(&mut self, epoch: QueryStatsEpoch) {
+        if self.metadata.query_stats.is_aggregated(epoch) {
+            return;
+        }
+        let nodes: BTreeSet<NodeId> = self
+            .metadata
+            .network_topology
+            .subnets
+            .get(&self.metadata.own_subnet_id)
+            .map(|subnet| subnet.nodes.keys().cloned().collect())
+            .unwrap_or_default();
+        let totals = self.metadata.query_stats.aggregate(epoch, &nodes);
+        for (canister_id, total) in totals {
+            // Statistics of canisters that were deleted in the meantime are
+            // dropped.
+            if let Some(canister) = self.canister_states.get_mut(&canister_id) {
+                canister
+                    .scheduler_state
+                    .total_query_stats
+                    .saturating_accumulate(&total);
+            }
+        }
+    }
+
     /// Times out requests in all `OutputQueues` found in the replicated state (except the subnet
     /// queues). Returns the number of requests that were timed out.
     ///
@@ -815,6 +874,74 @@ impl ReplicatedState {
 
         timed_out_requests_count
     }
+
+    /// Expires best-effort callbacks across all canisters, enqueuing a
+    /// `SYS_UNKNOWN` reject response for each. Returns the number of expired
+    /// callbacks.
+    ///
+    /// See `SystemState::time_out_callbacks` for further details.
+    #[allow(clippy::needless_collect)]
+    pub fn time_out_callbacks(&mut self, current_time: Time) -> u64 {
+        // Only apply the costly remove-call-replace to canisters with expired
+        // callbacks (see `time_out_requests()`).
+        let canister_ids_with_expired_callbacks = self
+            .canister_states
+            .iter()
+            .filter(|(_, canister_state)| {
+                canister_state
+                    .system_state
+                    .has_expired_callbacks(current_time)
+            })
+            .map(|(canister_id, _)| *canister_id)
+            .collect::<Vec<_>>();
+
+        let mut expired_callbacks_count = 0;
+        for canister_id in canister_ids_with_expired_callbacks {
+            let mut canister = self.canister_states.remove(&canister_id).unwrap();
+            expired_callbacks_count += canister.system_state.time_out_callbacks(
+                current_time,
+                &canister_id,
+                &self.canister_states,
+            );
+            self.canister_states.insert(canister_id, canister);
+        }
+
+        expired_callbacks_count
+    }
+
+    /// Sheds best-effort messages from the input and output queues of every
+    /// canister whose best-effort messages exceed
+    /// `BEST_EFFORT_MESSAGES_MAX_BYTES_PER_CANISTER`, rejecting them with
+    /// `SYS_UNKNOWN`. Returns the number of shed messages.
+    ///
+    /// See `SystemState::shed_best_effort_messages` for further details.
+    #[allow(clippy::needless_collect)]
+    pub fn shed_best_effort_messages(&mut self) -> u64 {
+        let canister_ids_over_limit = self
+            .canister_states
+            .iter()
+            .filter(|(_, canister_state)| {
+                canister_state
+                    .system_state
+                    .best_effort_messages_size_bytes()
+                    > BEST_EFFORT_MESSAGES_MAX_BYTES_PER_CANISTER
+            })
+            .map(|(canister_id, _)| *canister_id)
+            .collect::<Vec<_>>();
+
+        let mut shed_messages_count = 0;
+        for canister_id in canister_ids_over_limit {
+            let mut canister = self.canister_states.remove(&canister_id).unwrap();
+            shed_messages_count += canister.system_state.shed_best_effort_messages(
+                BEST_EFFORT_MESSAGES_MAX_BYTES_PER_CANISTER,
+                &canister_id,
+                &self.canister_states,
+            );
+            self.canister_states.insert(canister_id, canister);
+        }
+
+        shed_messages_count
+    }
 }
 
 /// A trait exposing `ReplicatedState` functionality for the exclusive use of
@@ -14,15 +14,17 @@ use ic_replicated_state::testing::{CanisterQueuesTesting, SystemStateTesting};
 use ic_replicated_state::{
     metadata_state::subnet_call_context_manager::BitcoinGetSuccessorsContext,
     replicated_state::PeekableOutputIterator, replicated_state::ReplicatedStateMessageRouting,
-    BitcoinStateError, CanisterState, ReplicatedState, SchedulerState, StateError, SystemState,
+    BitcoinStateError, CanisterState, NodeTopology, ReplicatedState, SchedulerState, StateError,
+    SubnetTopology, SystemState, TotalQueryStats,
 };
 use ic_test_utilities::mock_time;
 use ic_test_utilities::state::arb_replicated_state_with_queues;
 use ic_test_utilities::types::{
-    ids::user_test_id,
+    ids::{node_test_id, user_test_id},
     messages::{RequestBuilder, ResponseBuilder},
 };
 use ic_types::{
+    batch::{CanisterQueryStats, QueryStats, QueryStatsEpoch, QueryStatsPayload},
     messages::{Payload, Request, RequestOrResponse, Response, MAX_RESPONSE_COUNT_BYTES},
     CountBytes, Cycles, Time,
 };
@@ -687,6 +689,82 @@ fn time_out_requests_updates_subnet_input_schedules_correctly() {
     );
 }
 
+#[test]
+fn query_stats_are_aggregated_as_median_across_nodes() {
+    let mut fixture = ReplicatedStateFixture::from_canister_ids(&[CANISTER_ID, OTHER_CANISTER_ID]);
+    let nodes: Vec<_> = (0..4).map(node_test_id).collect();
+    fixture.state.metadata.network_topology.subnets.insert(
+        SUBNET_ID,
+        SubnetTopology {
+            nodes: nodes
+                .iter()
+                .map(|node_id| (*node_id, NodeTopology::default()))
+                .collect(),
+            ..SubnetTopology::default()
+        },
+    );
+
+    let query_stats = |num_calls: u32| QueryStats {
+        num_calls,
+        num_instructions: 1_000 * num_calls as u64,
+        ingress_payload_size: 10,
+        egress_payload_size: 100,
+    };
+    let payload = |proposer, num_calls| QueryStatsPayload {
+        epoch: QueryStatsEpoch::from(1),
+        proposer,
+        stats: vec![CanisterQueryStats {
+            canister_id: CANISTER_ID,
+            stats: query_stats(num_calls),
+        }],
+    };
+    // Three of four nodes report statistics, one of them made-up.
+    fixture.state.deliver_query_stats(payload(nodes[0], 2));
+    fixture.state.deliver_query_stats(payload(nodes[1], 3));
+    fixture.state.deliver_query_stats(payload(nodes[2], 1_000));
+    // A repeated report of the same node is ignored.
+    fixture.state.deliver_query_stats(payload(nodes[1], 1_000));
+    // A report of a node that is not a member of the subnet is ignored.
+    fixture
+        .state
+        .deliver_query_stats(payload(node_test_id(7), 1_000));
+
+    fixture
+        .state
+        .aggregate_query_stats(QueryStatsEpoch::from(1));
+
+    // The lower median of (0, 2, 3, 1000) is 2, times 4 nodes.
+    let total = &fixture
+        .state
+        .canister_state(&CANISTER_ID)
+        .unwrap()
+        .scheduler_state
+        .total_query_stats;
+    assert_eq!(
+        total,
+        &TotalQueryStats {
+            num_calls: 8,
+            num_instructions: 8_000,
+            ingress_payload_size: 40,
+            egress_payload_size: 400,
+        }
+    );
+    assert_eq!(
+        fixture
+            .state
+            .canister_state(&OTHER_CANISTER_ID)
+            .unwrap()
+            .scheduler_state
+            .total_query_stats,
+        TotalQueryStats::default()
+    );
+    assert!(fixture.state.metadata.query_stats.stats.is_empty());
+
+    // Statistics of an aggregated epoch are no longer accepted.
+    fixture.state.deliver_query_stats(payload(nodes[3], 5));
+    assert!(fixture.state.metadata.query_stats.stats.is_empty());
+}
+
 proptest! {
     #[test]
     fn peek_and_next_consistent(
@@ -4,7 +4,7 @@ use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
     canister_state::DEFAULT_QUEUE_CAPACITY,
     testing::{CanisterQueuesTesting, SystemStateTesting},
-    InputQueueType, StateError, SystemState,
+    InputQueueType, ReservationError, StateError, SystemState,
 };
 use ic_test_utilities::mock_time;
 use ic_test_utilities::types::{
@@ -374,3 +374,44 @@ fn induct_messages_to_self_full_queue() {
     assert_eq!(None, fixture.pop_input());
     assert_eq!(0, fixture.system_state.queues().output_message_count());
 }
+
+#[test]
+fn reserve_cycles_moves_cycles_to_reserved_balance() {
+    let mut system_state = SystemState::new_running(
+        CANISTER_ID,
+        user_test_id(1).get(),
+        Cycles::new(1_000),
+        NumSeconds::from(0),
+    );
+
+    system_state.reserve_cycles(Cycles::new(400)).unwrap();
+    assert_eq!(system_state.balance(), Cycles::new(600));
+    assert_eq!(system_state.reserved_balance(), Cycles::new(400));
+
+    assert_eq!(
+        system_state.reserve_cycles(Cycles::new(601)),
+        Err(ReservationError::InsufficientCycles {
+            requested: Cycles::new(601),
+            available: Cycles::new(600),
+        })
+    );
+
+    system_state.reserved_balance_limit = Some(Cycles::new(500));
+    assert_eq!(
+        system_state.reserve_cycles(Cycles::new(101)),
+        Err(ReservationError::ReservedLimitExceed {
+            requested: Cycles::new(501),
+            limit: Cycles::new(500),
+        })
+    );
+
+    // Failed reservations leave the balances unchanged.
+    assert_eq!(system_state.balance(), Cycles::new(600));
+    assert_eq!(system_state.reserved_balance(), Cycles::new(400));
+
+    assert_eq!(
+        system_state.remove_reserved_cycles(Cycles::new(1_000)),
+        Cycles::new(400)
+    );
+    assert_eq!(system_state.reserved_balance(), Cycles::zero());
+}
@@ -3,6 +3,7 @@ use crate::utils::do_copy;
 
 use bitcoin::{hashes::Hash, Network, OutPoint, Script, TxOut, Txid};
 use ic_base_types::{NumBytes, NumSeconds};
+use ic_ic00_types::LogVisibility;
 use ic_logger::{error, info, ReplicaLogger};
 use ic_metrics::{buckets::decimal_buckets, MetricsRegistry};
 use ic_protobuf::{
@@ -15,12 +16,14 @@ use ic_protobuf::{
 };
 use ic_replicated_state::{
     bitcoin_state, canister_state::execution_state::WasmMetadata, CallContextManager,
-    CanisterStatus, ExecutionTask, ExportedFunctions, Global, NumWasmPages,
+    CanisterHistory, CanisterStatus, ExecutionTask, ExportedFunctions, Global, NumWasmPages,
+    SnapshotId, TotalQueryStats,
 };
 use ic_sys::mmap::ScopedMmap;
 use ic_types::{
-    nominal_cycles::NominalCycles, AccumulatedPriority, CanisterId, ComputeAllocation, Cycles,
-    ExecutionRound, Height, MemoryAllocation, NumInstructions, PrincipalId,
+    canister_log::CanisterLog, nominal_cycles::NominalCycles, AccumulatedPriority, CanisterId,
+    ComputeAllocation, Cycles, ExecutionRound, Height, MemoryAllocation, NumInstructions,
+    PrincipalId, Time,
 };
 use ic_utils::fs::sync_path;
 use ic_utils::thread::parallel_map;
@@ -138,6 +141,27 @@ pub struct CanisterStateBits {
     pub time_of_last_allocation_charge_nanos: u64,
     pub global_timer_nanos: Option<u64>,
     pub canister_version: u64,
+    pub canister_log: CanisterLog,
+    pub log_visibility: LogVisibility,
+    pub wasm_memory_limit: Option<NumBytes>,
+    pub canister_history: CanisterHistory,
+    pub wasm_memory_threshold: NumBytes,
+    pub on_low_wasm_memory_hook_executed: bool,
+    pub reserved_balance: Cycles,
+    pub reserved_balance_limit: Option<Cycles>,
+    pub total_query_stats: TotalQueryStats,
+}
+
+/// This struct contains bits of a `CanisterSnapshot` that are not already
+/// covered somewhere else and are too small to be serialized separately.
+#[derive(Debug)]
+pub struct CanisterSnapshotBits {
+    pub canister_id: CanisterId,
+    pub taken_at_timestamp: Time,
+    pub canister_version: u64,
+    pub certified_data: Vec<u8>,
+    pub execution_state_bits: ExecutionStateBits,
+    pub stable_memory_size: NumWasmPages,
 }
 
 /// This struct contains bits of the `BitcoinState` that are not already
@@ -210,12 +234,18 @@ struct CheckpointRefData {
 /// |   |       └── utxos_small.bin
 /// |   |       └── utxos_medium.bin
 /// |   |       └── address_outpoints.bin
-/// │   └── canister_states
-/// │       └── <hex(canister_id)>
-/// │           ├── queues.pbuf
+/// │   ├── canister_states
+/// │   │   └── <hex(canister_id)>
+/// │   │       ├── queues.pbuf
+/// │   │       ├── vmemory_0.bin
+/// │   │       ├── canister.pbuf
+/// │   │       ├── stable_memory.(pbuf|bin)
+/// │   │       └── software.wasm
+/// │   └── snapshots
+/// │       └── <hex(snapshot_id)>
+/// │           ├── snapshot.pbuf
 /// │           ├── vmemory_0.bin
-/// │           ├── canister.pbuf
-/// │           ├── stable_memory.(pbuf|bin)
+/// │           ├── stable_memory.bin
 /// │           └── software.wasm
 /// │
 /// ├── [checkpoints, backups, diverged_checkpoints]
@@ -228,17 +258,26 @@ struct CheckpointRefData {
 /// |      |       └── utxos_small.bin
 /// |      |       └── utxos_medium.bin
 /// |      |       └── address_outpoints.bin
-/// │      └── canister_states
-/// │          └── <hex(canister_id)>
-/// │              ├── queues.pbuf
+/// │      ├── canister_states
+/// │      │   └── <hex(canister_id)>
+/// │      │       ├── queues.pbuf
+/// │      │       ├── vmemory_0.bin
+/// │      │       ├── canister.pbuf
+/// │      │       ├── stable_memory.(pbuf|bin)
+/// │      │       └── software.wasm
+/// │      └── snapshots
+/// │          └── <hex(snapshot_id)>
+/// │              ├── snapshot.pbuf
 /// │              ├── vmemory_0.bin
-/// │              ├── canister.pbuf
-/// │              ├── stable_memory.(pbuf|bin)
+/// │              ├── stable_memory.bin
 /// │              └── software.wasm
 /// │
 /// └── diverged_state_markers
 /// │   └──<hex(round)>
 /// │
+/// └── state_sync
+/// │   └──[scratchpad, cache]_<hex(round)>
+/// │
 /// └── tmp
 /// └── fs_tmp
 /// ```
@@ -273,15 +312,18 @@ struct CheckpointRefData {
 /// ## Promoting a State Sync artifact to a checkpoint
 ///
 ///   1. Create state files directly in
-///      "<state_root>/fs_tmp/state_sync_scratchpad_<height>".
+///      "<state_root>/state_sync/scratchpad_<height>".
 ///
 ///   2. When all the writes are complete, call sync_and_mark_files_readonly()
-///      on "<state_root>/fs_tmp/state_sync_scratchpad_<height>".  This function
+///      on "<state_root>/state_sync/scratchpad_<height>".  This function
 ///      syncs all the files and directories under the scratchpad directory,
 ///      including the scratchpad directory itself.
 ///
-///   3. Rename "<state_root>/fs_tmp/state_sync_scratchpad_<height>" to
+///   3. Rename "<state_root>/state_sync/scratchpad_<height>" to
 ///      "<state_root>/checkpoints/<height>", sync "<state_root>/checkpoints".
+///
+/// Unlike "tmp" and "fs_tmp", the "state_sync" directory is not cleaned on
+/// restart, so that an interrupted state sync can be resumed.
 
 #[derive(Clone)]
 pub struct StateLayout {
@@ -360,6 +402,27 @@ impl TipHandler {
         }
     }
 
+    /// Deletes canister snapshots from tip if they are not in ids.
+    pub fn filter_tip_snapshots(
+        &mut self,
+        height: Height,
+        ids: &BTreeSet<SnapshotId>,
+    ) -> Result<(), LayoutError> {
+        let tip = self.tip(height)?;
+        let snapshots_on_disk = tip.snapshot_ids()?;
+        for id in snapshots_on_disk {
+            if !ids.contains(&id) {
+                let snapshot_path = tip.snapshot(&id)?.raw_path();
+                std::fs::remove_dir_all(&snapshot_path).map_err(|err| LayoutError::IoError {
+                    path: snapshot_path,
+                    message: "Cannot remove canister snapshot.".to_string(),
+                    io_err: err,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
     /// Deletes canisters from tip if they are not in ids.
     pub fn filter_tip_canisters(
         &mut self,
@@ -416,6 +479,7 @@ impl StateLayout {
         WriteOnly::check_dir(&self.diverged_checkpoints())?;
         WriteOnly::check_dir(&self.diverged_state_markers())?;
         WriteOnly::check_dir(&self.fs_tmp())?;
+        WriteOnly::check_dir(&self.state_sync_dir())?;
         WriteOnly::check_dir(&self.tip_path())?;
         WriteOnly::check_dir(&self.tmp())?;
         for path in [
@@ -513,17 +577,25 @@ impl StateLayout {
         self.root.join("states_metadata.pbuf")
     }
 
+    /// Returns the path to the directory holding the scratchpads and caches of
+    /// state syncs. Unlike the temporary directories, this directory survives
+    /// restarts of a node.
+    pub fn state_sync_dir(&self) -> PathBuf {
+        self.root.join("state_sync")
+    }
+
     /// Returns scratchpad used during statesync
     pub fn state_sync_scratchpad(&self, height: Height) -> Result<PathBuf, LayoutError> {
         Ok(self
-            .tmp()
-            .join(format!("state_sync_scratchpad_{:016x}", height.get())))
+            .state_sync_dir()
+            .join(format!("scratchpad_{:016x}", height.get())))
     }
 
     /// Returns the path to cache an unfinished statesync at `height`
     pub fn state_sync_cache(&self, height: Height) -> Result<PathBuf, LayoutError> {
-        let tmp = self.tmp();
-        Ok(tmp.join(format!("state_sync_cache_{:016x}", height.get())))
+        Ok(self
+            .state_sync_dir()
+            .join(format!("cache_{:016x}", height.get())))
     }
 
     fn cleanup_tip(&self) -> Result<(), LayoutError> {
@@ -1257,6 +1329,31 @@ impl<Permissions: AccessPolicy> CheckpointLayout<Permissions> {
         )
     }
 
+    pub fn snapshot_ids(&self) -> Result<Vec<SnapshotId>, LayoutError> {
+        let snapshots_dir = self.root.join("snapshots");
+        Permissions::check_dir(&snapshots_dir)?;
+        collect_subdirs(snapshots_dir.as_path(), |p| {
+            let id = u64::from_str_radix(p, 16).unwrap_or_else(|err| {
+                panic!(
+                    "Failed to convert directory name {} into a snapshot id: {}",
+                    p, err
+                )
+            });
+            SnapshotId::new(id)
+        })
+    }
+
+    pub fn snapshot(
+        &self,
+        snapshot_id: &SnapshotId,
+    ) -> Result<SnapshotLayout<Permissions>, LayoutError> {
+        SnapshotLayout::new(
+            self.root
+                .join("snapshots")
+                .join(format!("{:016x}", snapshot_id.get())),
+        )
+    }
+
     pub fn bitcoin(&self) -> Result<BitcoinStateLayout<Permissions>, LayoutError> {
         // TODO(EXC-1113): Rename this path to "bitcoin", as it stores data for either network.
         BitcoinStateLayout::new(self.root.join("bitcoin").join("testnet"))
@@ -1310,6 +1407,51 @@ impl<Permissions: AccessPolicy> CanisterLayout<Permissions> {
     pub fn stable_memory_blob(&self) -> PathBuf {
         self.canister_root.join("stable_memory.bin")
     }
+
+    /// The chunks uploaded to the canister's Wasm chunk store. The file only
+    /// exists if the store is not empty.
+    pub fn wasm_chunk_store(
+        &self,
+    ) -> ProtoFileWith<pb_canister_state_bits::WasmChunkStore, Permissions> {
+        self.canister_root.join("wasm_chunk_store.pbuf").into()
+    }
+}
+
+pub struct SnapshotLayout<Permissions: AccessPolicy> {
+    snapshot_root: PathBuf,
+    permissions_tag: PhantomData<Permissions>,
+}
+
+impl<Permissions: AccessPolicy> SnapshotLayout<Permissions> {
+    pub fn new(snapshot_root: PathBuf) -> Result<Self, LayoutError> {
+        Permissions::check_dir(&snapshot_root)?;
+        Ok(Self {
+            snapshot_root,
+            permissions_tag: PhantomData,
+        })
+    }
+
+    pub fn raw_path(&self) -> PathBuf {
+        self.snapshot_root.clone()
+    }
+
+    pub fn snapshot(
+        &self,
+    ) -> ProtoFileWith<pb_canister_state_bits::CanisterSnapshotBits, Permissions> {
+        self.snapshot_root.join("snapshot.pbuf").into()
+    }
+
+    pub fn wasm(&self) -> WasmFile<Permissions> {
+        self.snapshot_root.join("software.wasm").into()
+    }
+
+    pub fn vmemory_0(&self) -> PathBuf {
+        self.snapshot_root.join("vmemory_0.bin")
+    }
+
+    pub fn stable_memory_blob(&self) -> PathBuf {
+        self.snapshot_root.join("stable_memory.bin")
+    }
 }
 
 pub struct BitcoinStateLayout<Permissions: AccessPolicy> {
@@ -1409,6 +1551,18 @@ where
 
         Ok(())
     }
+
+    /// Removes the file if it exists.
+    pub fn delete_file(&self) -> Result<(), LayoutError> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path).map_err(|err| LayoutError::IoError {
+                path: self.path.clone(),
+                message: "failed to delete protobuf file from disk".to_string(),
+                io_err: err,
+            })?
+        }
+        Ok(())
+    }
 }
 
 impl<T, P> ProtoFileWith<T, P>
@@ -1578,6 +1732,21 @@ impl From<CanisterStateBits> for pb_canister_state_bits::CanisterStateBits {
             task_queue: item.task_queue.iter().map(|v| v.into()).collect(),
             global_timer_nanos: item.global_timer_nanos,
             canister_version: item.canister_version,
+            canister_log_records: item
+                .canister_log
+                .records()
+                .iter()
+                .map(|record| record.into())
+                .collect(),
+            next_canister_log_record_idx: item.canister_log.next_idx(),
+            log_visibility: pb_canister_state_bits::LogVisibility::from(item.log_visibility) as i32,
+            wasm_memory_limit: item.wasm_memory_limit.map(|limit| limit.get()),
+            canister_history: Some((&item.canister_history).into()),
+            wasm_memory_threshold: item.wasm_memory_threshold.get(),
+            on_low_wasm_memory_hook_executed: item.on_low_wasm_memory_hook_executed,
+            reserved_balance: Some(item.reserved_balance.into()),
+            reserved_balance_limit: item.reserved_balance_limit.map(|limit| limit.into()),
+            total_query_stats: Some((&item.total_query_stats).into()),
         }
     }
 }
@@ -1617,12 +1786,49 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             .transpose()?
             .unwrap_or_else(Cycles::zero);
 
+        let reserved_balance = value
+            .reserved_balance
+            .map(|c| c.try_into())
+            .transpose()?
+            .unwrap_or_else(Cycles::zero);
+
+        let reserved_balance_limit = value
+            .reserved_balance_limit
+            .map(|c| c.try_into())
+            .transpose()?;
+
         let task_queue = value
             .task_queue
             .into_iter()
             .map(|v| v.try_into())
             .collect::<Result<_, _>>()?;
 
+        // Checkpoints written before the canister history was introduced have
+        // no history.
+        let canister_history = value
+            .canister_history
+            .map(CanisterHistory::try_from)
+            .transpose()?
+            .unwrap_or_default();
+
+        // Checkpoints written before query statistics were introduced have no
+        // statistics.
+        let total_query_stats = value
+            .total_query_stats
+            .map(TotalQueryStats::try_from)
+            .transpose()?
+            .unwrap_or_default();
+
+        let log_visibility = pb_canister_state_bits::LogVisibility::from_i32(value.log_visibility)
+            .ok_or(ProxyDecodeError::ValueOutOfRange {
+                typ: "LogVisibility",
+                err: format!(
+                    "Unexpected value of log visibility: {}",
+                    value.log_visibility
+                ),
+            })?
+            .into();
+
         Ok(Self {
             controllers,
             last_full_execution_round: value.last_full_execution_round.into(),
@@ -1663,6 +1869,22 @@ impl TryFrom<pb_canister_state_bits::CanisterStateBits> for CanisterStateBits {
             task_queue,
             global_timer_nanos: value.global_timer_nanos,
             canister_version: value.canister_version,
+            canister_log: CanisterLog::new(
+                value.next_canister_log_record_idx,
+                value
+                    .canister_log_records
+                    .into_iter()
+                    .map(|record| record.into())
+                    .collect(),
+            ),
+            log_visibility,
+            wasm_memory_limit: value.wasm_memory_limit.map(NumBytes::from),
+            canister_history,
+            wasm_memory_threshold: NumBytes::from(value.wasm_memory_threshold),
+            on_low_wasm_memory_hook_executed: value.on_low_wasm_memory_hook_executed,
+            reserved_balance,
+            reserved_balance_limit,
+            total_query_stats,
         })
     }
 }
@@ -1720,6 +1942,40 @@ impl TryFrom<pb_canister_state_bits::ExecutionStateBits> for ExecutionStateBits
     }
 }
 
+impl From<CanisterSnapshotBits> for pb_canister_state_bits::CanisterSnapshotBits {
+    fn from(item: CanisterSnapshotBits) -> Self {
+        Self {
+            canister_id: Some(item.canister_id.into()),
+            taken_at_timestamp_nanos: item.taken_at_timestamp.as_nanos_since_unix_epoch(),
+            canister_version: item.canister_version,
+            certified_data: item.certified_data,
+            execution_state_bits: Some((&item.execution_state_bits).into()),
+            stable_memory_size64: item.stable_memory_size.get() as u64,
+        }
+    }
+}
+
+impl TryFrom<pb_canister_state_bits::CanisterSnapshotBits> for CanisterSnapshotBits {
+    type Error = ProxyDecodeError;
+
+    fn try_from(value: pb_canister_state_bits::CanisterSnapshotBits) -> Result<Self, Self::Error> {
+        Ok(Self {
+            canister_id: try_from_option_field(
+                value.canister_id,
+                "CanisterSnapshotBits::canister_id",
+            )?,
+            taken_at_timestamp: Time::from_nanos_since_unix_epoch(value.taken_at_timestamp_nanos),
+            canister_version: value.canister_version,
+            certified_data: value.certified_data,
+            execution_state_bits: try_from_option_field(
+                value.execution_state_bits,
+                "CanisterSnapshotBits::execution_state_bits",
+            )?,
+            stable_memory_size: NumWasmPages::from(value.stable_memory_size64 as usize),
+        })
+    }
+}
+
 impl From<&BitcoinStateBits> for pb_bitcoin::BitcoinStateBits {
     fn from(item: &BitcoinStateBits) -> Self {
         pb_bitcoin::BitcoinStateBits {
@@ -2056,7 +2312,9 @@ fn build_copy_plan(src: &Path, dst: &Path, plan: &mut CopyPlan) -> std::io::Resu
 mod test {
     use super::*;
 
-    use ic_ic00_types::IC_00;
+    use ic_ic00_types::{
+        CanisterChange, CanisterChangeDetails, CanisterChangeOrigin, CanisterInstallMode, IC_00,
+    };
     use ic_interfaces::messages::{CanisterCall, CanisterMessage, CanisterMessageOrTask};
     use ic_test_utilities::{
         mock_time,
@@ -2095,6 +2353,15 @@ mod test {
             task_queue: vec![],
             global_timer_nanos: None,
             canister_version: 0,
+            canister_log: Default::default(),
+            log_visibility: Default::default(),
+            wasm_memory_limit: None,
+            canister_history: CanisterHistory::default(),
+            wasm_memory_threshold: NumBytes::from(0),
+            on_low_wasm_memory_hook_executed: false,
+            reserved_balance: Cycles::zero(),
+            reserved_balance_limit: None,
+            total_query_stats: TotalQueryStats::default(),
         }
     }
 
@@ -2203,6 +2470,149 @@ mod test {
         assert_eq!(canister_state_bits.task_queue, task_queue);
     }
 
+    #[test]
+    fn test_encode_decode_canister_log() {
+        let mut canister_log = CanisterLog::new_with_next_index(7);
+        canister_log.add_record(100, b"hello".to_vec());
+        canister_log.add_record(200, b"world".to_vec());
+        let canister_state_bits = CanisterStateBits {
+            canister_log: canister_log.clone(),
+            log_visibility: LogVisibility::Public,
+            ..default_canister_state_bits()
+        };
+
+        let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+        let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+        assert_eq!(canister_state_bits.canister_log, canister_log);
+        assert_eq!(canister_state_bits.log_visibility, LogVisibility::Public);
+    }
+
+    #[test]
+    fn test_encode_decode_wasm_memory_limit() {
+        for wasm_memory_limit in [None, Some(NumBytes::from(0)), Some(NumBytes::from(1 << 30))] {
+            let canister_state_bits = CanisterStateBits {
+                wasm_memory_limit,
+                ..default_canister_state_bits()
+            };
+
+            let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+            let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+            assert_eq!(canister_state_bits.wasm_memory_limit, wasm_memory_limit);
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_reserved_balance() {
+        for reserved_balance_limit in [None, Some(Cycles::zero()), Some(Cycles::new(1 << 40))] {
+            let canister_state_bits = CanisterStateBits {
+                reserved_balance: Cycles::new(1 << 30),
+                reserved_balance_limit,
+                ..default_canister_state_bits()
+            };
+
+            let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+            let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+            assert_eq!(canister_state_bits.reserved_balance, Cycles::new(1 << 30));
+            assert_eq!(
+                canister_state_bits.reserved_balance_limit,
+                reserved_balance_limit
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_total_query_stats() {
+        let total_query_stats = TotalQueryStats {
+            num_calls: 3,
+            num_instructions: u128::MAX,
+            ingress_payload_size: 1 << 70,
+            egress_payload_size: 42,
+        };
+        let canister_state_bits = CanisterStateBits {
+            total_query_stats: total_query_stats.clone(),
+            ..default_canister_state_bits()
+        };
+
+        let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+        let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+        assert_eq!(canister_state_bits.total_query_stats, total_query_stats);
+    }
+
+    #[test]
+    fn test_encode_decode_wasm_memory_threshold() {
+        for on_low_wasm_memory_hook_executed in [false, true] {
+            let canister_state_bits = CanisterStateBits {
+                wasm_memory_threshold: NumBytes::from(1 << 20),
+                on_low_wasm_memory_hook_executed,
+                ..default_canister_state_bits()
+            };
+
+            let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+            let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+            assert_eq!(
+                canister_state_bits.wasm_memory_threshold,
+                NumBytes::from(1 << 20)
+            );
+            assert_eq!(
+                canister_state_bits.on_low_wasm_memory_hook_executed,
+                on_low_wasm_memory_hook_executed
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_canister_history() {
+        let mut canister_history = CanisterHistory::default();
+        canister_history.add_canister_change(CanisterChange::new(
+            1,
+            0,
+            CanisterChangeOrigin::from_user(PrincipalId::new_user_test_id(1)),
+            CanisterChangeDetails::canister_creation(vec![PrincipalId::new_user_test_id(1)]),
+        ));
+        canister_history.add_canister_change(CanisterChange::new(
+            2,
+            1,
+            CanisterChangeOrigin::from_canister(canister_test_id(2).get(), Some(7)),
+            CanisterChangeDetails::code_deployment(CanisterInstallMode::Upgrade, [3; 32]),
+        ));
+        canister_history.add_canister_change(CanisterChange::new(
+            3,
+            2,
+            CanisterChangeOrigin::from_canister(canister_test_id(2).get(), None),
+            CanisterChangeDetails::CodeUninstall,
+        ));
+        canister_history.add_canister_change(CanisterChange::new(
+            4,
+            3,
+            CanisterChangeOrigin::from_user(PrincipalId::new_user_test_id(1)),
+            CanisterChangeDetails::controllers_change(vec![]),
+        ));
+        let canister_state_bits = CanisterStateBits {
+            canister_history: canister_history.clone(),
+            ..default_canister_state_bits()
+        };
+
+        let pb_bits = pb_canister_state_bits::CanisterStateBits::from(canister_state_bits);
+        let canister_state_bits = CanisterStateBits::try_from(pb_bits).unwrap();
+        assert_eq!(canister_state_bits.canister_history, canister_history);
+    }
+
+    #[test]
+    fn test_snapshot_ids_are_read_from_layout() {
+        let tempdir = tmpdir("state_layout");
+        let layout: CheckpointLayout<WriteOnly> =
+            CheckpointLayout::new_untracked(tempdir.path().to_path_buf(), Height::new(0)).unwrap();
+        assert!(layout.snapshot_ids().unwrap().is_empty());
+
+        let ids = vec![SnapshotId::new(1), SnapshotId::new(0xff)];
+        for id in &ids {
+            layout.snapshot(id).unwrap();
+        }
+        let mut ids_on_disk = layout.snapshot_ids().unwrap();
+        ids_on_disk.sort();
+        assert_eq!(ids_on_disk, ids);
+    }
+
     #[test]
     fn test_removal_when_last_dropped() {
         with_test_replica_logger(|log| {
@@ -1,3 +1,4 @@
+use ic_btc_types_internal::{BitcoinAdapterResponse, BitcoinAdapterResponseWrapper};
 use ic_config::flag_status::FlagStatus;
 use ic_config::{
     execution_environment::Config as HypervisorConfig,
@@ -9,12 +10,17 @@ use ic_crypto_internal_threshold_sig_bls12381::api::{
     combine_signatures, combined_public_key, generate_threshold_key, sign_message,
 };
 use ic_crypto_internal_threshold_sig_bls12381::types::SecretKeyBytes;
+use ic_crypto_internal_threshold_sig_ecdsa::{DerivationPath, EccCurveType, EccPoint, EccScalar};
 use ic_crypto_internal_types::sign::threshold_sig::public_key::CspThresholdSigPublicKey;
 use ic_crypto_tree_hash::{flatmap, Label, LabeledTree, LabeledTree::SubTree};
 use ic_cycles_account_manager::CyclesAccountManager;
-pub use ic_error_types::{ErrorCode, UserError};
+pub use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_execution_environment::ExecutionServices;
-use ic_ic00_types::{self as ic00, CanisterIdRecord, InstallCodeArgs, Method, Payload};
+use ic_ic00_types::{
+    self as ic00, BitcoinGetSuccessorsResponseComplete, CanisterHttpResponsePayload,
+    CanisterIdRecord, EcdsaCurve, InstallCodeArgs, Method, Payload, SignWithECDSAReply,
+    TransformArgs,
+};
 pub use ic_ic00_types::{
     CanisterInstallMode, CanisterSettingsArgs, EcdsaKeyId, UpdateSettingsArgs,
 };
@@ -36,6 +42,7 @@ use ic_protobuf::registry::{
     provisional_whitelist::v1::ProvisionalWhitelist as PbProvisionalWhitelist,
     routing_table::v1::CanisterMigrations as PbCanisterMigrations,
     routing_table::v1::RoutingTable as PbRoutingTable,
+    subnet::v1::SubnetListRecord,
 };
 use ic_protobuf::types::v1::PrincipalId as PrincipalIdIdProto;
 use ic_protobuf::types::v1::SubnetId as SubnetIdProto;
@@ -43,7 +50,8 @@ use ic_registry_client_fake::FakeRegistryClient;
 use ic_registry_client_helpers::subnet::SubnetListRegistry;
 use ic_registry_keys::{
     make_canister_migrations_record_key, make_ecdsa_signing_subnet_list_key, make_node_record_key,
-    make_provisional_whitelist_record_key, make_routing_table_record_key, ROOT_SUBNET_ID_KEY,
+    make_provisional_whitelist_record_key, make_routing_table_record_key,
+    make_subnet_list_record_key, make_subnet_record_key, ROOT_SUBNET_ID_KEY,
 };
 use ic_registry_proto_data_provider::ProtoRegistryDataProvider;
 use ic_registry_provisional_whitelist::ProvisionalWhitelist;
@@ -52,32 +60,37 @@ use ic_registry_routing_table::{
 };
 use ic_registry_subnet_features::{EcdsaConfig, SubnetFeatures, DEFAULT_ECDSA_MAX_QUEUE_SIZE};
 use ic_registry_subnet_type::SubnetType;
-use ic_replicated_state::metadata_state::subnet_call_context_manager::SignWithEcdsaContext;
+use ic_replicated_state::metadata_state::subnet_call_context_manager::{
+    BitcoinGetSuccessorsContext, SignWithEcdsaContext,
+};
 use ic_replicated_state::page_map::Buffer;
 use ic_replicated_state::{
     canister_state::{NumWasmPages, WASM_PAGE_SIZE_IN_BYTES},
     Memory, PageMap, ReplicatedState,
 };
 use ic_state_layout::{CheckpointLayout, RwPolicy};
-use ic_state_manager::StateManagerImpl;
+use ic_state_manager::{canister_archive::import_canister_archive, StateManagerImpl};
 use ic_test_utilities_metrics::{fetch_histogram_stats, fetch_int_counter};
-use ic_test_utilities_registry::{
-    add_subnet_record, insert_initial_dkg_transcript, SubnetRecordBuilder,
-};
+use ic_test_utilities_registry::{insert_initial_dkg_transcript, SubnetRecordBuilder};
 use ic_types::consensus::certification::CertificationContent;
 use ic_types::crypto::threshold_sig::ni_dkg::{NiDkgId, NiDkgTag, NiDkgTargetSubnet};
 pub use ic_types::crypto::threshold_sig::ThresholdSigPublicKey;
 use ic_types::crypto::{
-    canister_threshold_sig::MasterEcdsaPublicKey, AlgorithmId, CombinedThresholdSig,
-    CombinedThresholdSigOf, Signable, Signed,
+    canister_threshold_sig::{ExtendedDerivationPath, MasterEcdsaPublicKey},
+    AlgorithmId, CombinedThresholdSig, CombinedThresholdSigOf, Signable, Signed,
 };
 use ic_types::malicious_flags::MaliciousFlags;
-use ic_types::messages::{CallbackId, Certificate};
+use ic_types::messages::{
+    CallbackId, Certificate, Payload as MessagePayload, RejectContext, Response, NO_DEADLINE,
+};
 use ic_types::signature::ThresholdSignature;
 use ic_types::time::GENESIS;
 use ic_types::{
-    batch::{Batch, BatchPayload, IngressPayload, XNetPayload},
-    canister_http::CanisterHttpRequestContext,
+    batch::{Batch, BatchPayload, IngressPayload, SelfValidatingPayload, XNetPayload},
+    canister_http::{
+        CanisterHttpRequestContext, CANISTER_HTTP_TIMEOUT_INTERVAL,
+        MAX_CANISTER_HTTP_RESPONSE_BYTES,
+    },
     consensus::certification::Certification,
     messages::{
         Blob, HttpCallContent, HttpCanisterUpdate, HttpRequestEnvelope, SignedIngress, UserQuery,
@@ -91,11 +104,12 @@ pub use ic_types::{
     time::Time,
     CanisterId, CryptoHashOfState, Cycles, PrincipalId, SubnetId, UserId,
 };
+use ic_utils::layered_file;
 use maplit::btreemap;
 use serde::Serialize;
 pub use slog::Level;
 use std::io::stderr;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::string::ToString;
 use std::sync::Arc;
@@ -118,16 +132,22 @@ impl Verifier for FakeVerifier {
     }
 }
 
-/// Constructs the initial version of the registry containing a subnet with the
-/// specified SUBNET_ID, with the nodes with the specified NODE_IDs.
-fn make_nodes_registry(
-    nns_subnet_id: SubnetId,
+/// The registry records of a single subnet.
+struct SubnetRegistryConfig {
     subnet_id: SubnetId,
     subnet_type: SubnetType,
-    mut routing_table: RoutingTable,
-    node_ids: &[NodeId],
-    ecdsa_keys: &[EcdsaKeyId],
+    node_ids: Vec<NodeId>,
+    ecdsa_keys: Vec<EcdsaKeyId>,
     features: SubnetFeatures,
+}
+
+/// Constructs the initial version of the registry containing the specified
+/// subnets, each with its nodes. If the routing table is empty, every subnet
+/// is assigned a range of canister IDs.
+fn make_nodes_registry(
+    nns_subnet_id: SubnetId,
+    mut routing_table: RoutingTable,
+    subnets: &[SubnetRegistryConfig],
 ) -> (Arc<ProtoRegistryDataProvider>, Arc<FakeRegistryClient>) {
     let registry_version = RegistryVersion::from(1);
     let data_provider = Arc::new(ProtoRegistryDataProvider::new());
@@ -147,26 +167,35 @@ fn make_nodes_registry(
 
     // ECDSA subnet_id must be different from nns_subnet_id, otherwise
     // `sign_with_ecdsa` won't be charged.
-    let subnet_id_proto = SubnetIdProto {
-        principal_id: Some(PrincipalIdIdProto {
-            raw: subnet_id.get_ref().to_vec(),
-        }),
-    };
-    for key_id in ecdsa_keys {
+    let mut ecdsa_signing_subnets: BTreeMap<&EcdsaKeyId, Vec<SubnetIdProto>> = BTreeMap::new();
+    for subnet in subnets {
+        let subnet_id_proto = SubnetIdProto {
+            principal_id: Some(PrincipalIdIdProto {
+                raw: subnet.subnet_id.get_ref().to_vec(),
+            }),
+        };
+        for key_id in &subnet.ecdsa_keys {
+            ecdsa_signing_subnets
+                .entry(key_id)
+                .or_default()
+                .push(subnet_id_proto.clone());
+        }
+    }
+    for (key_id, subnets) in ecdsa_signing_subnets {
         let id = make_ecdsa_signing_subnet_list_key(key_id);
         data_provider
             .add(
                 &id.clone(),
                 registry_version,
-                Some(EcdsaSigningSubnetList {
-                    subnets: vec![subnet_id_proto.clone()],
-                }),
+                Some(EcdsaSigningSubnetList { subnets }),
             )
             .unwrap();
     }
 
     if routing_table.is_empty() {
-        routing_table_insert_subnet(&mut routing_table, subnet_id).unwrap();
+        for subnet in subnets {
+            routing_table_insert_subnet(&mut routing_table, subnet.subnet_id).unwrap();
+        }
     }
     let pb_routing_table = PbRoutingTable::from(routing_table);
     data_provider
@@ -185,7 +214,7 @@ fn make_nodes_registry(
         )
         .unwrap();
 
-    for node_id in node_ids {
+    for node_id in subnets.iter().flat_map(|subnet| subnet.node_ids.iter()) {
         let node_record = NodeRecord {
             node_operator_id: vec![0],
             xnet: None,
@@ -211,27 +240,67 @@ fn make_nodes_registry(
             .unwrap();
     }
 
-    // Set subnetwork list(needed for filling network_topology.nns_subnet_id)
-    let record = SubnetRecordBuilder::from(node_ids)
-        .with_subnet_type(subnet_type)
-        .with_ecdsa_config(EcdsaConfig {
-            quadruples_to_create_in_advance: 1,
-            key_ids: ecdsa_keys.to_vec(),
-            max_queue_size: Some(DEFAULT_ECDSA_MAX_QUEUE_SIZE),
-            signature_request_timeout_ns: None,
-            idkg_key_rotation_period_ms: None,
-        })
-        .with_features(features.into())
-        .build();
+    for subnet in subnets {
+        let record = SubnetRecordBuilder::from(&subnet.node_ids[..])
+            .with_subnet_type(subnet.subnet_type)
+            .with_ecdsa_config(EcdsaConfig {
+                quadruples_to_create_in_advance: 1,
+                key_ids: subnet.ecdsa_keys.clone(),
+                max_queue_size: Some(DEFAULT_ECDSA_MAX_QUEUE_SIZE),
+                signature_request_timeout_ns: None,
+                idkg_key_rotation_period_ms: None,
+            })
+            .with_features(subnet.features.into())
+            .build();
+
+        insert_initial_dkg_transcript(
+            registry_version.get(),
+            subnet.subnet_id,
+            &record,
+            &data_provider,
+        );
+        data_provider
+            .add(
+                &make_subnet_record_key(subnet.subnet_id),
+                registry_version,
+                Some(record),
+            )
+            .unwrap();
+    }
 
-    insert_initial_dkg_transcript(registry_version.get(), subnet_id, &record, &data_provider);
-    add_subnet_record(&data_provider, registry_version.get(), subnet_id, record);
+    // Set subnetwork list(needed for filling network_topology.nns_subnet_id)
+    data_provider
+        .add(
+            &make_subnet_list_record_key(),
+            registry_version,
+            Some(SubnetListRecord {
+                subnets: subnets
+                    .iter()
+                    .map(|subnet| subnet.subnet_id.get().into_vec())
+                    .collect(),
+            }),
+        )
+        .unwrap();
 
     let registry_client = Arc::new(FakeRegistryClient::new(Arc::clone(&data_provider) as _));
     registry_client.update_to_latest_version();
     (data_provider, registry_client)
 }
 
+/// Returns the response that consensus delivers for the subnet call with the
+/// given callback ID.
+fn consensus_response(callback_id: CallbackId, response_payload: MessagePayload) -> Response {
+    Response {
+        // The originator and respondent are not needed for these responses.
+        originator: CanisterId::ic_00(),
+        respondent: CanisterId::ic_00(),
+        originator_reply_callback: callback_id,
+        refund: Cycles::zero(),
+        response_payload,
+        deadline: NO_DEADLINE,
+    }
+}
+
 /// Convert an object into CBOR binary.
 fn into_cbor<R: Serialize>(r: &R) -> Vec<u8> {
     let mut ser = serde_cbor::Serializer::new(Vec::new());
@@ -276,6 +345,36 @@ impl StateMachineConfig {
     }
 }
 
+/// The answer of a mocked component to a request that a canister made to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MockResponse<T> {
+    /// Answers the request with the given value.
+    Reply(T),
+    /// Rejects the request.
+    Reject(RejectCode, String),
+    /// Leaves the request unanswered. The request is passed to the responder
+    /// again in the next round.
+    Pending,
+}
+
+type HttpOutcallResponder =
+    Box<dyn Fn(&CanisterHttpRequestContext) -> MockResponse<CanisterHttpResponsePayload> + Send>;
+type SignWithEcdsaResponder = Box<dyn Fn(&SignWithEcdsaContext) -> MockResponse<()> + Send>;
+type BitcoinGetSuccessorsResponder = Box<
+    dyn Fn(&BitcoinGetSuccessorsContext) -> MockResponse<BitcoinGetSuccessorsResponseComplete>
+        + Send,
+>;
+
+/// The responders that stand in for the components outside of the
+/// deterministic state machine (the HTTPS outcalls adapter, threshold ECDSA
+/// signing and the Bitcoin adapter).
+#[derive(Default)]
+struct MockResponders {
+    http_outcalls: Option<HttpOutcallResponder>,
+    sign_with_ecdsa: Option<SignWithEcdsaResponder>,
+    bitcoin_get_successors: Option<BitcoinGetSuccessorsResponder>,
+}
+
 /// Represents a replicated state machine detached from the network layer that
 /// can be used to test this part of the stack in isolation.
 pub struct StateMachine {
@@ -295,6 +394,8 @@ pub struct StateMachine {
     nonce: std::cell::Cell<u64>,
     time: std::cell::Cell<Time>,
     ecdsa_subnet_public_keys: BTreeMap<EcdsaKeyId, MasterEcdsaPublicKey>,
+    ecdsa_secret_keys: BTreeMap<EcdsaKeyId, EccScalar>,
+    mock_responders: std::cell::RefCell<MockResponders>,
 }
 
 impl Default for StateMachine {
@@ -326,6 +427,8 @@ pub struct StateMachineBuilder {
     use_cost_scaling_flag: bool,
     ecdsa_keys: Vec<EcdsaKeyId>,
     features: SubnetFeatures,
+    instruction_profile_output: Option<PathBuf>,
+    registry: Option<(Arc<ProtoRegistryDataProvider>, Arc<FakeRegistryClient>)>,
 }
 
 impl StateMachineBuilder {
@@ -345,6 +448,8 @@ impl StateMachineBuilder {
             routing_table: RoutingTable::new(),
             ecdsa_keys: Vec::new(),
             features: SubnetFeatures::default(),
+            instruction_profile_output: None,
+            registry: None,
         }
     }
 
@@ -360,6 +465,19 @@ impl StateMachineBuilder {
         Self { time, ..self }
     }
 
+    /// Makes the state machine use the given registry instead of creating one
+    /// of its own.
+    fn with_registry(
+        self,
+        data_provider: Arc<ProtoRegistryDataProvider>,
+        registry_client: Arc<FakeRegistryClient>,
+    ) -> Self {
+        Self {
+            registry: Some((data_provider, registry_client)),
+            ..self
+        }
+    }
+
     pub fn with_config(self, config: Option<StateMachineConfig>) -> Self {
         Self { config, ..self }
     }
@@ -420,6 +538,17 @@ impl StateMachineBuilder {
         Self { features, ..self }
     }
 
+    /// Profiles all canister executions and writes the number of instructions
+    /// executed by each Wasm function to the given file in the folded stack
+    /// format, e.g. for rendering a flamegraph. The file is rewritten after
+    /// every execution.
+    pub fn with_instruction_profile_output(self, path: PathBuf) -> Self {
+        Self {
+            instruction_profile_output: Some(path),
+            ..self
+        }
+    }
+
     pub fn build(self) -> StateMachine {
         StateMachine::setup_from_dir(
             self.state_dir,
@@ -435,6 +564,8 @@ impl StateMachineBuilder {
             self.use_cost_scaling_flag,
             self.ecdsa_keys,
             self.features,
+            self.instruction_profile_output,
+            self.registry,
         )
     }
 }
@@ -475,14 +606,11 @@ impl StateMachine {
         use_cost_scaling_flag: bool,
         ecdsa_keys: Vec<EcdsaKeyId>,
         features: SubnetFeatures,
+        instruction_profile_output: Option<PathBuf>,
+        registry: Option<(Arc<ProtoRegistryDataProvider>, Arc<FakeRegistryClient>)>,
     ) -> Self {
         let replica_logger = replica_logger();
 
-        let mut node_ids = vec![];
-        for id in 0..subnet_size {
-            let node_id = NodeId::from(PrincipalId::new_node_test_id(id as u64));
-            node_ids.push(node_id);
-        }
         let metrics_registry = MetricsRegistry::new();
 
         let (subnet_config, mut hypervisor_config) = match config {
@@ -493,15 +621,22 @@ impl StateMachine {
             ),
         };
 
-        let (registry_data_provider, registry_client) = make_nodes_registry(
-            nns_subnet_id,
-            subnet_id,
-            subnet_type,
-            routing_table,
-            &node_ids,
-            &ecdsa_keys,
-            features,
-        );
+        let (registry_data_provider, registry_client) = match registry {
+            Some(registry) => registry,
+            None => make_nodes_registry(
+                nns_subnet_id,
+                routing_table,
+                &[SubnetRegistryConfig {
+                    subnet_id,
+                    subnet_type,
+                    node_ids: (0..subnet_size)
+                        .map(|id| NodeId::from(PrincipalId::new_node_test_id(id as u64)))
+                        .collect(),
+                    ecdsa_keys: ecdsa_keys.clone(),
+                    features,
+                }],
+            ),
+        };
 
         let sm_config = ic_config::state_manager::Config::new(state_dir.path().to_path_buf());
 
@@ -510,6 +645,13 @@ impl StateMachine {
             hypervisor_config.deterministic_time_slicing = FlagStatus::Disabled;
         }
 
+        // Profiling is only supported by the in-process executor.
+        if instruction_profile_output.is_some() {
+            hypervisor_config.canister_sandboxing_flag = FlagStatus::Disabled;
+            hypervisor_config.deterministic_time_slicing = FlagStatus::Disabled;
+            hypervisor_config.instruction_profile_output = instruction_profile_output;
+        }
+
         let mut cycles_account_manager = CyclesAccountManager::new(
             subnet_config.scheduler_config.max_instructions_per_message,
             subnet_type,
@@ -585,14 +727,23 @@ impl StateMachine {
         ));
 
         let mut ecdsa_subnet_public_keys = BTreeMap::new();
+        let mut ecdsa_secret_keys = BTreeMap::new();
         for ecdsa_key in ecdsa_keys {
+            let (curve, algorithm_id) = match ecdsa_key.curve {
+                EcdsaCurve::Secp256k1 => (EccCurveType::K256, AlgorithmId::EcdsaSecp256k1),
+                EcdsaCurve::Secp256r1 => (EccCurveType::P256, AlgorithmId::EcdsaP256),
+            };
+            // The key is derived from its name to keep tests reproducible.
+            let secret_key =
+                EccScalar::from_seed(curve, Seed::from_bytes(ecdsa_key.name.as_bytes()));
             ecdsa_subnet_public_keys.insert(
-                ecdsa_key,
+                ecdsa_key.clone(),
                 MasterEcdsaPublicKey {
-                    algorithm_id: AlgorithmId::EcdsaSecp256k1,
-                    public_key: b"master_ecdsa_public_key".to_vec(),
+                    algorithm_id,
+                    public_key: EccPoint::mul_by_g(&secret_key).unwrap().serialize(),
                 },
             );
+            ecdsa_secret_keys.insert(ecdsa_key, secret_key);
         }
 
         Self {
@@ -614,6 +765,8 @@ impl StateMachine {
             nonce: std::cell::Cell::new(nonce),
             time: std::cell::Cell::new(time),
             ecdsa_subnet_public_keys,
+            ecdsa_secret_keys,
+            mock_responders: Default::default(),
         }
     }
 
@@ -747,6 +900,12 @@ impl StateMachine {
         // use the batch number to seed randomness
         seed[..8].copy_from_slice(batch_number.get().to_le_bytes().as_slice());
 
+        let (consensus_responses, bitcoin_adapter_responses) = self.mock_responses();
+        let mut payload = payload;
+        if !bitcoin_adapter_responses.is_empty() {
+            payload.self_validating = SelfValidatingPayload::new(bitcoin_adapter_responses);
+        }
+
         let batch = Batch {
             batch_number,
             requires_full_state_hash: self.checkpoints_enabled.get(),
@@ -755,7 +914,7 @@ impl StateMachine {
             ecdsa_subnet_public_keys: self.ecdsa_subnet_public_keys.clone(),
             registry_version: self.registry_client.get_latest_version(),
             time: self.time.get(),
-            consensus_responses: vec![],
+            consensus_responses,
         };
         self.message_routing
             .deliver_batch(batch)
@@ -927,6 +1086,8 @@ impl StateMachine {
     }
 
     /// Imports a directory containing a canister snapshot into the state machine.
+    /// Instead of a directory, the path can also point to a canister archive
+    /// created by `state_tool export-canister`.
     ///
     /// After you import the canister, you can execute methods on it and upgrade it.
     /// The original directory is not modified.
@@ -943,6 +1104,23 @@ impl StateMachine {
         canister_id: CanisterId,
     ) {
         let canister_directory = canister_directory.as_ref();
+        let unpacked_archive;
+        let canister_directory = if canister_directory.is_file() {
+            unpacked_archive = TempDir::new().expect("failed to create a temporary directory");
+            let canister_dir = unpacked_archive.path().join("canister");
+            let info = import_canister_archive(canister_directory, &canister_dir)
+                .unwrap_or_else(|e| panic!("failed to unpack canister archive: {}", e));
+            assert_eq!(
+                info.canister_id,
+                canister_id,
+                "canister archive at {} contains a different canister",
+                canister_directory.display()
+            );
+            canister_dir
+        } else {
+            canister_directory.to_path_buf()
+        };
+        let canister_directory = canister_directory.as_path();
         assert!(
             canister_directory.is_dir(),
             "canister state at {} must be a directory",
@@ -975,12 +1153,23 @@ impl StateMachine {
                 .expect("failed to set file persmission");
         }
 
+        let mut copied_files = vec![];
         for entry in std::fs::read_dir(canister_directory).expect("failed to read_dir") {
             let entry = entry.expect("failed to get directory entry");
-            copy_as_writeable(
-                &entry.path(),
-                &tip_canister_layout.raw_path().join(entry.file_name()),
-            );
+            let dst = tip_canister_layout.raw_path().join(entry.file_name());
+            copy_as_writeable(&entry.path(), &dst);
+            copied_files.push(dst);
+        }
+
+        // The names of overlays encode the heights of the checkpoints that wrote
+        // them, which are unrelated to the heights of this state, so all layers
+        // are merged into their base files.
+        for path in copied_files {
+            if !layered_file::is_overlay(&path) {
+                layered_file::merge_into_base(&path).unwrap_or_else(|e| {
+                    panic!("failed to merge overlays of {}: {}", path.display(), e)
+                });
+            }
         }
 
         let canister_state = ic_state_manager::checkpoint::load_canister_state(
@@ -1622,6 +1811,206 @@ impl StateMachine {
         balance
     }
 
+    /// Makes every round answer the pending HTTPS outcalls with the response
+    /// returned by `responder`.
+    ///
+    /// A replied response is passed through the transform function of the
+    /// request (if any) like the HTTPS outcalls adapter client would do.
+    /// Requests that stay [`MockResponse::Pending`] time out once
+    /// `CANISTER_HTTP_TIMEOUT_INTERVAL` has passed since they were made.
+    pub fn set_http_outcall_responder(
+        &self,
+        responder: impl Fn(&CanisterHttpRequestContext) -> MockResponse<CanisterHttpResponsePayload>
+            + Send
+            + 'static,
+    ) {
+        self.mock_responders.borrow_mut().http_outcalls = Some(Box::new(responder));
+    }
+
+    /// Makes every round answer the pending `sign_with_ecdsa` requests as
+    /// decided by `responder`.
+    ///
+    /// On [`MockResponse::Reply`], the message hash is signed with the test
+    /// key of the subnet, derived for the caller and the derivation path of
+    /// the request in the same way as the public key returned by
+    /// `ecdsa_public_key`.
+    pub fn set_sign_with_ecdsa_responder(
+        &self,
+        responder: impl Fn(&SignWithEcdsaContext) -> MockResponse<()> + Send + 'static,
+    ) {
+        self.mock_responders.borrow_mut().sign_with_ecdsa = Some(Box::new(responder));
+    }
+
+    /// Makes every round answer the pending `bitcoin_get_successors` requests
+    /// with the response returned by `responder`, like the Bitcoin adapter
+    /// would do.
+    pub fn set_bitcoin_get_successors_responder(
+        &self,
+        responder: impl Fn(&BitcoinGetSuccessorsContext) -> MockResponse<BitcoinGetSuccessorsResponseComplete>
+            + Send
+            + 'static,
+    ) {
+        self.mock_responders.borrow_mut().bitcoin_get_successors = Some(Box::new(responder));
+    }
+
+    /// Returns the responses of the mock responders to the pending requests,
+    /// split into the responses that consensus would deliver and those that
+    /// the Bitcoin adapter would deliver.
+    fn mock_responses(&self) -> (Vec<Response>, Vec<BitcoinAdapterResponse>) {
+        let responders = self.mock_responders.borrow();
+        let state = self.state_manager.get_latest_state().take();
+        let contexts = &state.metadata.subnet_call_context_manager;
+        let mut consensus_responses = vec![];
+        let mut bitcoin_adapter_responses = vec![];
+
+        if let Some(responder) = &responders.http_outcalls {
+            for (callback_id, context) in &contexts.canister_http_request_contexts {
+                let response_payload = match responder(context) {
+                    MockResponse::Reply(response) => {
+                        self.transform_http_response(context, response)
+                    }
+                    MockResponse::Reject(code, message) => {
+                        MessagePayload::Reject(RejectContext { code, message })
+                    }
+                    MockResponse::Pending => {
+                        if context.time + CANISTER_HTTP_TIMEOUT_INTERVAL >= self.time.get() {
+                            continue;
+                        }
+                        MessagePayload::Reject(RejectContext {
+                            code: RejectCode::SysTransient,
+                            message: "Canister http request timed out".to_string(),
+                        })
+                    }
+                };
+                consensus_responses.push(consensus_response(*callback_id, response_payload));
+            }
+        }
+
+        if let Some(responder) = &responders.sign_with_ecdsa {
+            for (callback_id, context) in &contexts.sign_with_ecdsa_contexts {
+                let response_payload = match responder(context) {
+                    MockResponse::Reply(()) => MessagePayload::Data(
+                        SignWithECDSAReply {
+                            signature: self.sign_with_test_key(context),
+                        }
+                        .encode(),
+                    ),
+                    MockResponse::Reject(code, message) => {
+                        MessagePayload::Reject(RejectContext { code, message })
+                    }
+                    MockResponse::Pending => continue,
+                };
+                consensus_responses.push(consensus_response(*callback_id, response_payload));
+            }
+        }
+
+        if let Some(responder) = &responders.bitcoin_get_successors {
+            for (callback_id, context) in &contexts.bitcoin_get_successors_contexts {
+                match responder(context) {
+                    MockResponse::Reply(response) => {
+                        bitcoin_adapter_responses.push(BitcoinAdapterResponse {
+                            response: BitcoinAdapterResponseWrapper::CanisterGetSuccessorsResponse(
+                                response,
+                            ),
+                            callback_id: callback_id.get(),
+                        })
+                    }
+                    MockResponse::Reject(code, message) => {
+                        consensus_responses.push(consensus_response(
+                            *callback_id,
+                            MessagePayload::Reject(RejectContext { code, message }),
+                        ))
+                    }
+                    MockResponse::Pending => {}
+                }
+            }
+        }
+
+        (consensus_responses, bitcoin_adapter_responses)
+    }
+
+    /// Applies the transform function of the request to the response like the
+    /// HTTPS outcalls adapter client does.
+    fn transform_http_response(
+        &self,
+        context: &CanisterHttpRequestContext,
+        response: CanisterHttpResponsePayload,
+    ) -> MessagePayload {
+        let result = match &context.transform {
+            Some(transform) => {
+                let args = TransformArgs {
+                    response,
+                    context: transform.context.clone(),
+                };
+                match self.query(
+                    context.request.sender,
+                    &transform.method_name,
+                    candid::Encode!(&args).unwrap(),
+                ) {
+                    Ok(WasmResult::Reply(data)) => Ok(data),
+                    Ok(WasmResult::Reject(message)) => Err(RejectContext {
+                        code: RejectCode::CanisterReject,
+                        message,
+                    }),
+                    Err(err) => Err(RejectContext {
+                        code: err.reject_code(),
+                        message: err.description().to_string(),
+                    }),
+                }
+            }
+            None => Ok(candid::Encode!(&response).unwrap()),
+        };
+        match result {
+            Ok(data) if data.len() > MAX_CANISTER_HTTP_RESPONSE_BYTES as usize => {
+                MessagePayload::Reject(RejectContext {
+                    code: RejectCode::SysFatal,
+                    message: format!(
+                        "Http response exceeds limit: {}",
+                        MAX_CANISTER_HTTP_RESPONSE_BYTES
+                    ),
+                })
+            }
+            Ok(data) => MessagePayload::Data(data),
+            Err(reject) => MessagePayload::Reject(reject),
+        }
+    }
+
+    /// Signs the message hash of the request with the test key of the subnet,
+    /// derived for the caller and derivation path of the request.
+    fn sign_with_test_key(&self, context: &SignWithEcdsaContext) -> Vec<u8> {
+        let master_secret_key = self
+            .ecdsa_secret_keys
+            .get(&context.key_id)
+            .unwrap_or_else(|| panic!("Unknown ECDSA key {}", context.key_id));
+        let curve = master_secret_key.curve_type();
+        let master_public_key = EccPoint::mul_by_g(master_secret_key).unwrap();
+        let derivation_path = DerivationPath::from(&ExtendedDerivationPath {
+            caller: context.request.sender.get(),
+            derivation_path: context.derivation_path.clone(),
+        });
+        let (key_tweak, _chain_key) = derivation_path.derive_tweak(&master_public_key).unwrap();
+        let secret_key = master_secret_key.add(&key_tweak).unwrap();
+
+        // The nonce is derived from the pseudo-random ID of the request, which
+        // is unique, to keep signatures reproducible.
+        let nonce = EccScalar::from_seed(curve, Seed::from_bytes(&context.pseudo_random_id));
+        let r_bytes = EccPoint::mul_by_g(&nonce)
+            .unwrap()
+            .affine_x()
+            .unwrap()
+            .as_bytes();
+        let r = EccScalar::from_bytes_wide(curve, &r_bytes).unwrap();
+        let message = EccScalar::from_bytes_wide(curve, &context.message_hash).unwrap();
+        let s = nonce
+            .invert()
+            .unwrap()
+            .mul(&message.add(&r.mul(&secret_key).unwrap()).unwrap())
+            .unwrap();
+        // Signatures are normalized to have a low `s`.
+        let s = if s.is_high() { s.negate() } else { s };
+        [r.serialize(), s.serialize()].concat()
+    }
+
     /// Returns sign with ECDSA contexts from internal subnet call context manager.
     pub fn sign_with_ecdsa_contexts(&self) -> BTreeMap<CallbackId, SignWithEcdsaContext> {
         let state = self.state_manager.get_latest_state().take();
@@ -1645,6 +2034,310 @@ impl StateMachine {
     }
 }
 
+/// A set of subnets, each represented by a [`StateMachine`], that share one
+/// registry (and thus one routing table) and exchange XNet messages.
+///
+/// Canisters are installed on a specific subnet through [`Self::subnet`] and
+/// can make ordinary inter-canister calls to canisters on other subnets:
+/// every [`Self::tick`] executes a round on each subnet and induces the
+/// messages of the certified streams between them.
+pub struct StateMachineEnv {
+    subnets: BTreeMap<SubnetId, StateMachine>,
+    registry_client: Arc<FakeRegistryClient>,
+}
+
+impl fmt::Debug for StateMachineEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMachineEnv")
+            .field("subnets", &self.subnets)
+            .finish()
+    }
+}
+
+impl StateMachineEnv {
+    /// Constructs an environment with `num_subnets` subnets with default
+    /// settings.
+    pub fn new(num_subnets: usize) -> Self {
+        Self::from_builders(
+            (0..num_subnets)
+                .map(|i| {
+                    StateMachineBuilder::new().with_subnet_id(SubnetId::from(
+                        PrincipalId::new_subnet_test_id(i as u64 + 1),
+                    ))
+                })
+                .collect(),
+        )
+    }
+
+    /// Constructs an environment with a subnet for each of the given builders.
+    ///
+    /// The first subnet is the NNS subnet. The routing table of the first
+    /// builder is used for all subnets; if it is empty, every subnet is
+    /// assigned a range of canister IDs.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if no builders are given or if several builders
+    /// have the same subnet ID.
+    pub fn from_builders(builders: Vec<StateMachineBuilder>) -> Self {
+        let first = builders.first().expect("at least one subnet is required");
+        let nns_subnet_id = first.subnet_id;
+        let routing_table = first.routing_table.clone();
+
+        // Node IDs must be unique across subnets.
+        let mut next_node_id = 0;
+        let subnets: Vec<_> = builders
+            .iter()
+            .map(|builder| {
+                let node_ids = (next_node_id..next_node_id + builder.subnet_size as u64)
+                    .map(|id| NodeId::from(PrincipalId::new_node_test_id(id)))
+                    .collect();
+                next_node_id += builder.subnet_size as u64;
+                SubnetRegistryConfig {
+                    subnet_id: builder.subnet_id,
+                    subnet_type: builder.subnet_type,
+                    node_ids,
+                    ecdsa_keys: builder.ecdsa_keys.clone(),
+                    features: builder.features,
+                }
+            })
+            .collect();
+        let (registry_data_provider, registry_client) =
+            make_nodes_registry(nns_subnet_id, routing_table, &subnets);
+
+        let mut state_machines = BTreeMap::new();
+        for builder in builders {
+            let subnet_id = builder.subnet_id;
+            let state_machine = builder
+                .with_nns_subnet_id(nns_subnet_id)
+                .with_registry(
+                    Arc::clone(&registry_data_provider),
+                    Arc::clone(&registry_client),
+                )
+                .build();
+            assert!(
+                state_machines.insert(subnet_id, state_machine).is_none(),
+                "Subnet {} is specified more than once",
+                subnet_id
+            );
+        }
+
+        Self {
+            subnets: state_machines,
+            registry_client,
+        }
+    }
+
+    /// Returns the state machine of the specified subnet.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the subnet is not part of the environment.
+    pub fn subnet(&self, subnet_id: SubnetId) -> &StateMachine {
+        self.subnets
+            .get(&subnet_id)
+            .unwrap_or_else(|| panic!("Subnet {} is not part of the environment", subnet_id))
+    }
+
+    /// Returns the IDs of all subnets, ordered by subnet ID.
+    pub fn subnet_ids(&self) -> Vec<SubnetId> {
+        self.subnets.keys().cloned().collect()
+    }
+
+    /// Returns the state machine of the subnet that hosts the specified
+    /// canister according to the latest routing table.
+    pub fn route(&self, canister_id: CanisterId) -> Option<&StateMachine> {
+        use ic_registry_client_helpers::routing_table::RoutingTableRegistry;
+
+        let routing_table = self
+            .registry_client
+            .get_routing_table(self.registry_client.get_latest_version())
+            .expect("malformed routing table")
+            .expect("missing routing table");
+        routing_table
+            .route(canister_id.get())
+            .and_then(|subnet_id| self.subnets.get(&subnet_id))
+    }
+
+    /// Executes a round on every subnet. The round of each subnet inducts the
+    /// messages that the other subnets have sent it since the previous round.
+    pub fn tick(&self) {
+        let mut payloads = BTreeMap::new();
+        for (dst_subnet_id, dst) in &self.subnets {
+            let dst_state = dst.get_latest_state();
+            let mut stream_slices = BTreeMap::new();
+            for (src_subnet_id, src) in &self.subnets {
+                if src_subnet_id == dst_subnet_id {
+                    continue;
+                }
+                // The signals that `dst` sends back to `src` tell which message
+                // it expects next.
+                let begin = dst_state
+                    .metadata
+                    .streams()
+                    .get(src_subnet_id)
+                    .map(|stream| stream.signals_end());
+                match src.generate_xnet_payload(*dst_subnet_id, begin, begin, None, None) {
+                    Ok(payload) => stream_slices.extend(payload.stream_slices),
+                    Err(EncodeStreamError::NoStreamForSubnet(_)) => {}
+                    Err(err) => panic!(
+                        "Failed to encode the stream from {} to {}: {:?}",
+                        src_subnet_id, dst_subnet_id, err
+                    ),
+                }
+            }
+            payloads.insert(*dst_subnet_id, XNetPayload { stream_slices });
+        }
+
+        for (subnet_id, payload) in payloads {
+            self.subnets[&subnet_id].execute_block_with_xnet_payload(payload);
+        }
+    }
+
+    /// Makes all subnets tick until there are no more messages in the system,
+    /// including messages in streams that were not inducted yet.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the messages were not processed within
+    /// `max_ticks` iterations.
+    pub fn run_until_completion(&self, max_ticks: usize) {
+        for _tick in 0..max_ticks {
+            if !self.has_pending_messages() {
+                return;
+            }
+            self.tick();
+        }
+        if self.has_pending_messages() {
+            panic!(
+                "The subnets did not reach completion after {} ticks",
+                max_ticks
+            );
+        }
+    }
+
+    fn has_pending_messages(&self) -> bool {
+        let states: BTreeMap<_, _> = self
+            .subnets
+            .iter()
+            .map(|(subnet_id, subnet)| (*subnet_id, subnet.get_latest_state()))
+            .collect();
+        states.iter().any(|(subnet_id, state)| {
+            state
+                .canisters_iter()
+                .any(|canister| canister.has_input() || canister.has_output())
+                || state.subnet_queues().has_input()
+                || state.subnet_queues().has_output()
+                || state
+                    .metadata
+                    .streams()
+                    .iter()
+                    .any(|(dst_subnet_id, stream)| {
+                        states.get(dst_subnet_id).map_or(false, |dst_state| {
+                            let inducted = dst_state
+                                .metadata
+                                .streams()
+                                .get(subnet_id)
+                                .map_or(StreamIndex::new(0), |stream| stream.signals_end());
+                            inducted < stream.messages_end()
+                        })
+                    })
+        })
+    }
+
+    /// Executes an ingress message on the canister with the specified ID on
+    /// the subnet that hosts it, ticking all subnets until the result is known.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if no subnet hosts the canister or if the status
+    /// was not ready in a reasonable amount of time.
+    pub fn execute_ingress_as(
+        &self,
+        sender: PrincipalId,
+        canister_id: CanisterId,
+        method: impl ToString,
+        payload: Vec<u8>,
+    ) -> Result<WasmResult, UserError> {
+        const MAX_TICKS: usize = 100;
+        let subnet = self
+            .route(canister_id)
+            .unwrap_or_else(|| panic!("Canister {} is not hosted by any subnet", canister_id));
+        let msg_id = subnet.send_ingress(sender, canister_id, method, payload);
+        for _tick in 0..MAX_TICKS {
+            match subnet.ingress_status(&msg_id) {
+                IngressStatus::Known {
+                    state: IngressState::Completed(result),
+                    ..
+                } => return Ok(result),
+                IngressStatus::Known {
+                    state: IngressState::Failed(error),
+                    ..
+                } => return Err(error),
+                _ => self.tick(),
+            }
+        }
+        panic!(
+            "Did not get answer to ingress {} after {} ticks",
+            msg_id, MAX_TICKS
+        )
+    }
+
+    pub fn execute_ingress(
+        &self,
+        canister_id: CanisterId,
+        method: impl ToString,
+        payload: Vec<u8>,
+    ) -> Result<WasmResult, UserError> {
+        self.execute_ingress_as(PrincipalId::new_anonymous(), canister_id, method, payload)
+    }
+
+    /// Advances the time of all subnets by the given amount.
+    pub fn advance_time(&self, amount: Duration) {
+        for subnet in self.subnets.values() {
+            subnet.advance_time(amount);
+        }
+    }
+
+    /// Updates the routing table so that a range of canisters is assigned to
+    /// the specified destination subnet.
+    pub fn reroute_canister_range(
+        &self,
+        canister_range: std::ops::RangeInclusive<CanisterId>,
+        destination: SubnetId,
+    ) {
+        self.any_subnet()
+            .reroute_canister_range(canister_range, destination)
+    }
+
+    /// Marks canisters in the specified range as being migrated from `source`
+    /// to `destination`.
+    pub fn prepare_canister_migrations(
+        &self,
+        canister_range: std::ops::RangeInclusive<CanisterId>,
+        source: SubnetId,
+        destination: SubnetId,
+    ) {
+        self.any_subnet()
+            .prepare_canister_migrations(canister_range, source, destination)
+    }
+
+    /// Marks canisters in the specified range as successfully migrated.
+    pub fn complete_canister_migrations(
+        &self,
+        canister_range: std::ops::RangeInclusive<CanisterId>,
+        migration_trace: Vec<SubnetId>,
+    ) {
+        self.any_subnet()
+            .complete_canister_migrations(canister_range, migration_trace)
+    }
+
+    /// Returns some subnet, e.g. for updating the shared registry.
+    fn any_subnet(&self) -> &StateMachine {
+        self.subnets.values().next().unwrap()
+    }
+}
+
 #[derive(Clone)]
 pub struct PayloadBuilder {
     expiry_time: Time,
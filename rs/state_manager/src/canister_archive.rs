@@ -0,0 +1,570 @@
+//! Portable archives of a single canister.
+//!
+//! A canister archive packages the files of one canister of a checkpoint,
+//! i.e. `canister.pbuf`, `queues.pbuf`, the Wasm module, the Wasm chunk store
+//! and the heap and stable memory, into a single file that can be moved to
+//! another machine and loaded into a state there. Memory files are stored with
+//! their overlays applied, so an archive never contains overlay files.
+//!
+//! An archive has the following layout (all integers are little-endian):
+//!
+//! ```text
+//! ┌───────┬─────────┬────────────────┬─────────────┬─────────┬─────────┐
+//! │ magic │ version │ canister id    │ num_entries │ entries │ sha256  │
+//! │ 4 B   │ u32     │ u8 len + bytes │ u32         │ ...     │ 32 B    │
+//! └───────┴─────────┴────────────────┴─────────────┴─────────┴─────────┘
+//! ```
+//!
+//! Every entry consists of the file name (`u16` length followed by the UTF-8
+//! bytes), the file size as `u64` and the file contents. The trailing hash is
+//! the SHA-256 of all preceding bytes of the archive.
+//!
+//! Both exporting and importing validate that the canister's protobuf files
+//! can be decoded with the current protobuf definitions and that the sizes of
+//! the memory files agree with the sizes recorded in `canister.pbuf`.
+
+use ic_crypto_sha::Sha256;
+use ic_replicated_state::{
+    canister_state::{system_state::wasm_chunk_store::WasmChunkStore, WASM_PAGE_SIZE_IN_BYTES},
+    CanisterQueues, NumWasmPages,
+};
+use ic_state_layout::{
+    error::LayoutError, AccessPolicy, CanisterLayout, CanisterStateBits, ReadOnly, ReadPolicy,
+    WriteOnly,
+};
+use ic_sys::PAGE_SIZE;
+use ic_types::{CanisterId, PrincipalId};
+use ic_utils::layered_file::LayeredFile;
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// The version of the archive format written by `export_canister_archive`.
+pub const CANISTER_ARCHIVE_VERSION: u32 = 1;
+
+const CANISTER_ARCHIVE_MAGIC: [u8; 4] = *b"ICCA";
+
+/// Size of the buffer used to copy file contents in and out of archives.
+const COPY_BUFFER_SIZE: usize = 1 << 20;
+
+/// Errors that can occur when exporting or importing a canister archive.
+#[derive(Debug)]
+pub enum CanisterArchiveError {
+    /// Wraps a stringified `std::io::Error`, a message and the path of the
+    /// affected file/directory.
+    IoError {
+        path: PathBuf,
+        message: String,
+        io_err: String,
+    },
+    /// The archive is malformed.
+    InvalidArchive { path: PathBuf, message: String },
+    /// The archive was written in a format version this replica can't read.
+    UnsupportedVersion { path: PathBuf, version: u32 },
+    /// The hash stored in the archive doesn't match its contents.
+    HashMismatch {
+        path: PathBuf,
+        expected: [u8; 32],
+        actual: [u8; 32],
+    },
+    /// The canister files are inconsistent or can't be decoded.
+    InvalidCanister { path: PathBuf, message: String },
+}
+
+impl std::error::Error for CanisterArchiveError {}
+
+impl std::fmt::Display for CanisterArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CanisterArchiveError::IoError {
+                path,
+                message,
+                io_err,
+            } => write!(f, "{}: {}: {}", path.display(), message, io_err),
+
+            CanisterArchiveError::InvalidArchive { path, message } => {
+                write!(
+                    f,
+                    "{}: invalid canister archive: {}",
+                    path.display(),
+                    message
+                )
+            }
+
+            CanisterArchiveError::UnsupportedVersion { path, version } => write!(
+                f,
+                "{}: unsupported canister archive version {}, expected at most {}",
+                path.display(),
+                version,
+                CANISTER_ARCHIVE_VERSION
+            ),
+
+            CanisterArchiveError::HashMismatch {
+                path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: canister archive hash mismatch: expected {}, computed {}",
+                path.display(),
+                hex::encode(expected),
+                hex::encode(actual)
+            ),
+
+            CanisterArchiveError::InvalidCanister { path, message } => {
+                write!(f, "{}: invalid canister state: {}", path.display(), message)
+            }
+        }
+    }
+}
+
+impl From<LayoutError> for CanisterArchiveError {
+    fn from(err: LayoutError) -> Self {
+        match err {
+            LayoutError::IoError {
+                path,
+                message,
+                io_err,
+            } => CanisterArchiveError::IoError {
+                path,
+                message,
+                io_err: io_err.to_string(),
+            },
+            LayoutError::CorruptedLayout { path, message } => {
+                CanisterArchiveError::InvalidCanister { path, message }
+            }
+            err => CanisterArchiveError::InvalidCanister {
+                path: PathBuf::new(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+fn io_error(path: &Path, message: &str, err: std::io::Error) -> CanisterArchiveError {
+    CanisterArchiveError::IoError {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+        io_err: err.to_string(),
+    }
+}
+
+/// Describes an exported or imported canister archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CanisterArchiveInfo {
+    pub canister_id: CanisterId,
+    pub version: u32,
+    /// The SHA-256 hash stored at the end of the archive.
+    pub hash: [u8; 32],
+    /// The names of the files in the archive.
+    pub files: Vec<String>,
+}
+
+/// Returns the paths of the canister files that go into an archive, in the
+/// order in which they are archived.
+fn archived_files<P: AccessPolicy>(canister_layout: &CanisterLayout<P>) -> Vec<PathBuf> {
+    vec![
+        canister_layout.canister().raw_path().to_path_buf(),
+        canister_layout.queues().raw_path().to_path_buf(),
+        canister_layout.wasm().raw_path().to_path_buf(),
+        canister_layout.wasm_chunk_store().raw_path().to_path_buf(),
+        canister_layout.vmemory_0(),
+        canister_layout.stable_memory_blob(),
+    ]
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Writes the files of the canister at `canister_layout` into a new archive
+/// at `archive_path`. The archive must not exist yet.
+pub fn export_canister_archive<P: ReadPolicy>(
+    canister_layout: &CanisterLayout<P>,
+    canister_id: CanisterId,
+    archive_path: &Path,
+) -> Result<CanisterArchiveInfo, CanisterArchiveError> {
+    validate_canister(canister_layout)?;
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(archive_path)
+        .map_err(|err| io_error(archive_path, "failed to create canister archive", err))?;
+    let result = write_archive(
+        canister_layout,
+        canister_id,
+        ArchiveWriter {
+            inner: BufWriter::new(file),
+            hasher: Sha256::new(),
+            path: archive_path,
+        },
+    );
+    if result.is_err() {
+        let _ = std::fs::remove_file(archive_path);
+    }
+    result
+}
+
+fn write_archive<P: ReadPolicy>(
+    canister_layout: &CanisterLayout<P>,
+    canister_id: CanisterId,
+    mut writer: ArchiveWriter,
+) -> Result<CanisterArchiveInfo, CanisterArchiveError> {
+    let mut files = Vec::new();
+    for path in archived_files(canister_layout) {
+        let file = LayeredFile::open(&path)
+            .map_err(|err| io_error(&path, "failed to open canister file", err))?;
+        if path.exists() || file.num_overlays() > 0 {
+            files.push((file_name(&path), path, file));
+        }
+    }
+
+    writer.write(&CANISTER_ARCHIVE_MAGIC)?;
+    writer.write(&CANISTER_ARCHIVE_VERSION.to_le_bytes())?;
+    let canister_id_bytes = canister_id.get_ref().as_slice();
+    writer.write(&[canister_id_bytes.len() as u8])?;
+    writer.write(canister_id_bytes)?;
+    writer.write(&(files.len() as u32).to_le_bytes())?;
+
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    for (name, path, file) in files.iter() {
+        writer.write(&(name.len() as u16).to_le_bytes())?;
+        writer.write(name.as_bytes())?;
+        writer.write(&file.len().to_le_bytes())?;
+
+        let mut offset = 0;
+        while offset < file.len() {
+            let n = (file.len() - offset).min(COPY_BUFFER_SIZE as u64) as usize;
+            file.read_exact_at(&mut buf[..n], offset)
+                .map_err(|err| io_error(path, "failed to read canister file", err))?;
+            writer.write(&buf[..n])?;
+            offset += n as u64;
+        }
+    }
+
+    let hash = writer.finish()?;
+    Ok(CanisterArchiveInfo {
+        canister_id,
+        version: CANISTER_ARCHIVE_VERSION,
+        hash,
+        files: files.into_iter().map(|(name, _, _)| name).collect(),
+    })
+}
+
+/// Unpacks the archive at `archive_path` into the canister directory
+/// `canister_dir`, which must not exist yet, and validates the result. The
+/// directory is removed again if the archive turns out to be invalid.
+///
+/// Function is not crash-safe. Caller is responsible to follow guidelines
+/// regarding crash-safe I/O.
+pub fn import_canister_archive(
+    archive_path: &Path,
+    canister_dir: &Path,
+) -> Result<CanisterArchiveInfo, CanisterArchiveError> {
+    if canister_dir.exists() {
+        return Err(CanisterArchiveError::IoError {
+            path: canister_dir.to_path_buf(),
+            message: "failed to import canister archive".to_string(),
+            io_err: "directory already exists".to_string(),
+        });
+    }
+
+    let file = File::open(archive_path)
+        .map_err(|err| io_error(archive_path, "failed to open canister archive", err))?;
+    let result = read_archive(
+        ArchiveReader {
+            inner: BufReader::new(file),
+            hasher: Sha256::new(),
+            path: archive_path,
+        },
+        canister_dir,
+    )
+    .and_then(|info| {
+        validate_canister(&CanisterLayout::<ReadOnly>::new(
+            canister_dir.to_path_buf(),
+        )?)?;
+        Ok(info)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_dir_all(canister_dir);
+    }
+    result
+}
+
+fn read_archive(
+    mut reader: ArchiveReader,
+    canister_dir: &Path,
+) -> Result<CanisterArchiveInfo, CanisterArchiveError> {
+    let (version, canister_id) = reader.read_header()?;
+
+    let canister_layout = CanisterLayout::<WriteOnly>::new(canister_dir.to_path_buf())?;
+    let known_files: BTreeSet<String> = archived_files(&canister_layout)
+        .iter()
+        .map(|path| file_name(path))
+        .collect();
+
+    let num_entries = reader.read_u32()?;
+    let mut files = Vec::new();
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    for _ in 0..num_entries {
+        let mut name = vec![0; reader.read_u16()? as usize];
+        reader.read(&mut name)?;
+        let name = String::from_utf8(name)
+            .map_err(|err| reader.invalid(format!("invalid file name: {}", err)))?;
+        if !known_files.contains(&name) {
+            return Err(reader.invalid(format!("unexpected file {}", name)));
+        }
+        if files.contains(&name) {
+            return Err(reader.invalid(format!("duplicate file {}", name)));
+        }
+
+        let path = canister_dir.join(&name);
+        let mut file = BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+                .map_err(|err| io_error(&path, "failed to create canister file", err))?,
+        );
+        let mut remaining = reader.read_u64()?;
+        while remaining > 0 {
+            let n = remaining.min(COPY_BUFFER_SIZE as u64) as usize;
+            reader.read(&mut buf[..n])?;
+            file.write_all(&buf[..n])
+                .map_err(|err| io_error(&path, "failed to write canister file", err))?;
+            remaining -= n as u64;
+        }
+        file.flush()
+            .map_err(|err| io_error(&path, "failed to write canister file", err))?;
+        files.push(name);
+    }
+
+    let hash = reader.finish()?;
+    Ok(CanisterArchiveInfo {
+        canister_id,
+        version,
+        hash,
+        files,
+    })
+}
+
+/// Reads the ID of the canister in the archive at `archive_path` without
+/// unpacking or verifying the archive.
+pub fn canister_archive_id(archive_path: &Path) -> Result<CanisterId, CanisterArchiveError> {
+    let file = File::open(archive_path)
+        .map_err(|err| io_error(archive_path, "failed to open canister archive", err))?;
+    let (_version, canister_id) = ArchiveReader {
+        inner: BufReader::new(file),
+        hasher: Sha256::new(),
+        path: archive_path,
+    }
+    .read_header()?;
+    Ok(canister_id)
+}
+
+/// Checks that the protobuf files of the canister at `canister_layout` can be
+/// decoded and that its memory files fit into the memory sizes recorded in
+/// `canister.pbuf`.
+fn validate_canister<P: ReadPolicy>(
+    canister_layout: &CanisterLayout<P>,
+) -> Result<(), CanisterArchiveError> {
+    let invalid = |path: &Path, message: String| CanisterArchiveError::InvalidCanister {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let canister = canister_layout.canister();
+    let canister_state_bits = canister
+        .deserialize()
+        .map_err(|err| invalid(canister.raw_path(), err.to_string()))
+        .and_then(|bits| {
+            CanisterStateBits::try_from(bits)
+                .map_err(|err| invalid(canister.raw_path(), err.to_string()))
+        })?;
+
+    let queues = canister_layout.queues();
+    queues
+        .deserialize()
+        .map_err(|err| invalid(queues.raw_path(), err.to_string()))
+        .and_then(|queues_proto| {
+            CanisterQueues::try_from(queues_proto)
+                .map_err(|err| invalid(queues.raw_path(), err.to_string()))
+        })?;
+
+    let wasm_chunk_store = canister_layout.wasm_chunk_store();
+    if let Some(store) = wasm_chunk_store
+        .deserialize_opt()
+        .map_err(|err| invalid(wasm_chunk_store.raw_path(), err.to_string()))?
+    {
+        WasmChunkStore::try_from(store)
+            .map_err(|err| invalid(wasm_chunk_store.raw_path(), err.to_string()))?;
+    }
+
+    let (heap_size, stable_memory_size) = match canister_state_bits.execution_state_bits {
+        Some(execution_state_bits) => {
+            let wasm = canister_layout.wasm();
+            wasm.deserialize(execution_state_bits.binary_hash)
+                .map_err(|err| invalid(wasm.raw_path(), err.to_string()))?;
+            (
+                execution_state_bits.heap_size,
+                canister_state_bits.stable_memory_size,
+            )
+        }
+        // A canister without a Wasm module has no memory.
+        None => (NumWasmPages::from(0), NumWasmPages::from(0)),
+    };
+    validate_memory_size(&canister_layout.vmemory_0(), heap_size)?;
+    validate_memory_size(&canister_layout.stable_memory_blob(), stable_memory_size)
+}
+
+fn validate_memory_size(path: &Path, num_pages: NumWasmPages) -> Result<(), CanisterArchiveError> {
+    let len = LayeredFile::open(path)
+        .map_err(|err| io_error(path, "failed to open memory file", err))?
+        .len();
+    let max_len = num_pages.get() as u64 * WASM_PAGE_SIZE_IN_BYTES as u64;
+    if len % PAGE_SIZE as u64 != 0 || len > max_len {
+        return Err(CanisterArchiveError::InvalidCanister {
+            path: path.to_path_buf(),
+            message: format!(
+                "memory file has {} bytes, expected a multiple of {} of at most {} ({} Wasm pages)",
+                len,
+                PAGE_SIZE,
+                max_len,
+                num_pages.get()
+            ),
+        });
+    }
+    Ok(())
+}
+
+/// Writes an archive and hashes everything written.
+struct ArchiveWriter<'a> {
+    inner: BufWriter<File>,
+    hasher: Sha256,
+    path: &'a Path,
+}
+
+impl ArchiveWriter<'_> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), CanisterArchiveError> {
+        self.hasher.write(bytes);
+        self.inner
+            .write_all(bytes)
+            .map_err(|err| io_error(self.path, "failed to write canister archive", err))
+    }
+
+    /// Appends the hash of the archive and syncs it to disk.
+    fn finish(mut self) -> Result<[u8; 32], CanisterArchiveError> {
+        let hash = self.hasher.finish();
+        self.inner
+            .write_all(&hash)
+            .and_then(|()| self.inner.flush())
+            .and_then(|()| self.inner.get_ref().sync_all())
+            .map_err(|err| io_error(self.path, "failed to write canister archive", err))?;
+        Ok(hash)
+    }
+}
+
+/// Reads an archive and hashes everything read.
+struct ArchiveReader<'a> {
+    inner: BufReader<File>,
+    hasher: Sha256,
+    path: &'a Path,
+}
+
+impl ArchiveReader<'_> {
+    fn invalid(&self, message: String) -> CanisterArchiveError {
+        CanisterArchiveError::InvalidArchive {
+            path: self.path.to_path_buf(),
+            message,
+        }
+    }
+
+    /// Reads the archive format version and the canister ID.
+    fn read_header(&mut self) -> Result<(u32, CanisterId), CanisterArchiveError> {
+        let mut magic = [0; 4];
+        self.read(&mut magic)?;
+        if magic != CANISTER_ARCHIVE_MAGIC {
+            return Err(self.invalid(format!("unexpected magic {:?}", magic)));
+        }
+        let version = self.read_u32()?;
+        if version == 0 || version > CANISTER_ARCHIVE_VERSION {
+            return Err(CanisterArchiveError::UnsupportedVersion {
+                path: self.path.to_path_buf(),
+                version,
+            });
+        }
+
+        let mut canister_id_len = [0; 1];
+        self.read(&mut canister_id_len)?;
+        let mut canister_id_bytes = vec![0; canister_id_len[0] as usize];
+        self.read(&mut canister_id_bytes)?;
+        let canister_id = PrincipalId::try_from(&canister_id_bytes[..])
+            .map_err(|err| err.to_string())
+            .and_then(|principal| CanisterId::try_from(principal).map_err(|err| err.to_string()))
+            .map_err(|err| self.invalid(format!("invalid canister ID: {}", err)))?;
+
+        Ok((version, canister_id))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), CanisterArchiveError> {
+        self.inner
+            .read_exact(buf)
+            .map_err(|err| io_error(self.path, "failed to read canister archive", err))?;
+        self.hasher.write(buf);
+        Ok(())
+    }
+
+    fn read_u16(&mut self) -> Result<u16, CanisterArchiveError> {
+        let mut bytes = [0; 2];
+        self.read(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, CanisterArchiveError> {
+        let mut bytes = [0; 4];
+        self.read(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, CanisterArchiveError> {
+        let mut bytes = [0; 8];
+        self.read(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Reads the hash at the end of the archive and compares it to the hash
+    /// of the contents read so far.
+    fn finish(self) -> Result<[u8; 32], CanisterArchiveError> {
+        let ArchiveReader {
+            mut inner,
+            hasher,
+            path,
+        } = self;
+        let mut expected = [0; 32];
+        inner
+            .read_exact(&mut expected)
+            .map_err(|err| io_error(path, "failed to read canister archive", err))?;
+        let actual = hasher.finish();
+        if expected != actual {
+            return Err(CanisterArchiveError::HashMismatch {
+                path: path.to_path_buf(),
+                expected,
+                actual,
+            });
+        }
+        let mut trailing = [0; 1];
+        match inner.read(&mut trailing) {
+            Ok(0) => Ok(actual),
+            Ok(_) => Err(CanisterArchiveError::InvalidArchive {
+                path: path.to_path_buf(),
+                message: "unexpected data after the archive hash".to_string(),
+            }),
+            Err(err) => Err(io_error(path, "failed to read canister archive", err)),
+        }
+    }
+}
@@ -10,12 +10,14 @@ use ic_replicated_state::page_map::PageAllocatorFileDescriptor;
 use ic_replicated_state::Memory;
 use ic_replicated_state::{
     bitcoin_state::{BitcoinState, UtxoSet},
-    canister_state::execution_state::WasmBinary,
+    canister_state::{execution_state::WasmBinary, system_state::wasm_chunk_store::WasmChunkStore},
     page_map::PageMap,
-    CanisterMetrics, CanisterState, ExecutionState, ReplicatedState, SchedulerState, SystemState,
+    CanisterMetrics, CanisterSnapshot, CanisterSnapshots, CanisterState, ExecutionState,
+    ReplicatedState, SchedulerState, SystemState,
 };
 use ic_state_layout::{
-    BitcoinStateBits, CanisterLayout, CanisterStateBits, CheckpointLayout, ReadOnly, ReadPolicy,
+    BitcoinStateBits, CanisterLayout, CanisterSnapshotBits, CanisterStateBits, CheckpointLayout,
+    ReadOnly, ReadPolicy,
 };
 use ic_types::{CanisterTimer, Height, LongExecutionMode, Time};
 use ic_utils::thread::parallel_map;
@@ -65,6 +67,13 @@ pub fn make_checkpoint(
         })
         .unwrap();
 
+    tip_channel
+        .send(TipRequest::FilterTipSnapshots {
+            height,
+            ids: state.canister_snapshots.snapshot_ids().copied().collect(),
+        })
+        .unwrap();
+
     let cp = {
         let _timer = metrics
             .make_checkpoint_step_duration
@@ -92,6 +101,13 @@ pub fn make_checkpoint(
         })
         .unwrap();
 
+    tip_channel
+        .send(TipRequest::CompactTip {
+            height,
+            page_map_types: PageMapType::list_all(state),
+        })
+        .unwrap();
+
     let state = {
         let _timer = metrics
             .make_checkpoint_step_duration
@@ -222,8 +238,18 @@ pub fn load_checkpoint<P: ReadPolicy + Send + Sync>(
         load_bitcoin_state(checkpoint_layout)?
     };
 
-    let state =
+    let canister_snapshots = {
+        let _timer = metrics
+            .load_checkpoint_step_duration
+            .with_label_values(&["canister_snapshots"])
+            .start_timer();
+
+        load_canister_snapshots(checkpoint_layout, Arc::clone(&fd_factory))?
+    };
+
+    let mut state =
         ReplicatedState::new_from_checkpoint(canister_states, metadata, subnet_queues, bitcoin);
+    state.canister_snapshots = canister_snapshots;
 
     Ok(state)
 }
@@ -333,6 +359,21 @@ pub fn load_canister_state<P: ReadPolicy>(
             })?;
     durations.insert("canister_queues", starting_time.elapsed());
 
+    let starting_time = Instant::now();
+    let wasm_chunk_store = match canister_layout.wasm_chunk_store().deserialize_opt()? {
+        Some(wasm_chunk_store) => WasmChunkStore::try_from(wasm_chunk_store).map_err(|err| {
+            into_checkpoint_error(
+                format!(
+                    "canister_states[{}]::system_state::wasm_chunk_store",
+                    canister_id
+                ),
+                err,
+            )
+        })?,
+        None => WasmChunkStore::default(),
+    };
+    durations.insert("wasm_chunk_store", starting_time.elapsed());
+
     let canister_metrics = CanisterMetrics {
         scheduled_as_first: canister_state_bits.scheduled_as_first,
         skipped_round_due_to_no_messages: canister_state_bits.skipped_round_due_to_no_messages,
@@ -352,9 +393,18 @@ pub fn load_canister_state<P: ReadPolicy>(
         canister_metrics,
         canister_state_bits.cycles_balance,
         canister_state_bits.cycles_debit,
+        canister_state_bits.reserved_balance,
+        canister_state_bits.reserved_balance_limit,
         canister_state_bits.task_queue.into_iter().collect(),
         CanisterTimer::from_nanos_since_unix_epoch(canister_state_bits.global_timer_nanos),
         canister_state_bits.canister_version,
+        canister_state_bits.canister_log,
+        canister_state_bits.log_visibility,
+        canister_state_bits.wasm_memory_limit,
+        canister_state_bits.wasm_memory_threshold,
+        canister_state_bits.on_low_wasm_memory_hook_executed,
+        wasm_chunk_store,
+        canister_state_bits.canister_history,
     );
 
     let canister_state = CanisterState {
@@ -373,6 +423,7 @@ pub fn load_canister_state<P: ReadPolicy>(
             time_of_last_allocation_charge: Time::from_nanos_since_unix_epoch(
                 canister_state_bits.time_of_last_allocation_charge_nanos,
             ),
+            total_query_stats: canister_state_bits.total_query_stats,
         },
     };
 
@@ -395,6 +446,65 @@ fn load_canister_state_from_checkpoint<P: ReadPolicy>(
     )
 }
 
+fn load_canister_snapshots<P: ReadPolicy>(
+    checkpoint_layout: &CheckpointLayout<P>,
+    fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
+) -> Result<CanisterSnapshots, CheckpointError> {
+    let height = checkpoint_layout.height();
+    let mut snapshots = BTreeMap::new();
+    for snapshot_id in checkpoint_layout.snapshot_ids()? {
+        let snapshot_layout = checkpoint_layout.snapshot(&snapshot_id)?;
+        let snapshot_bits: CanisterSnapshotBits = CanisterSnapshotBits::try_from(
+            snapshot_layout.snapshot().deserialize()?,
+        )
+        .map_err(|err| CheckpointError::ProtoError {
+            path: snapshot_layout.raw_path(),
+            field: format!("snapshots[{}]::canister_snapshot_bits", snapshot_id),
+            proto_err: err.to_string(),
+        })?;
+        let execution_state_bits = snapshot_bits.execution_state_bits;
+
+        let wasm_memory = Memory::new(
+            PageMap::open(
+                &snapshot_layout.vmemory_0(),
+                height,
+                Arc::clone(&fd_factory),
+            )?,
+            execution_state_bits.heap_size,
+        );
+        let stable_memory = Memory::new(
+            PageMap::open(
+                &snapshot_layout.stable_memory_blob(),
+                height,
+                Arc::clone(&fd_factory),
+            )?,
+            snapshot_bits.stable_memory_size,
+        );
+        let wasm_binary = WasmBinary::new(
+            snapshot_layout
+                .wasm()
+                .deserialize(execution_state_bits.binary_hash)?,
+        );
+
+        snapshots.insert(
+            snapshot_id,
+            Arc::new(CanisterSnapshot::new(
+                snapshot_bits.canister_id,
+                snapshot_bits.taken_at_timestamp,
+                snapshot_bits.canister_version,
+                snapshot_bits.certified_data,
+                wasm_binary,
+                wasm_memory,
+                stable_memory,
+                execution_state_bits.exported_globals,
+                execution_state_bits.exports,
+                execution_state_bits.metadata,
+            )),
+        );
+    }
+    Ok(CanisterSnapshots::new(snapshots))
+}
+
 fn load_bitcoin_state<P: ReadPolicy>(
     checkpoint_layout: &CheckpointLayout<P>,
 ) -> Result<BitcoinState, CheckpointError> {
@@ -458,6 +568,7 @@ mod tests {
     use super::*;
     use crate::{spawn_tip_thread, StateManagerMetrics, NUMBER_OF_CHECKPOINT_THREADS};
     use ic_base_types::NumSeconds;
+    use ic_config::flag_status::FlagStatus;
     use ic_ic00_types::CanisterStatusType;
     use ic_metrics::MetricsRegistry;
     use ic_registry_subnet_type::SubnetType;
@@ -542,8 +653,13 @@ mod tests {
             let layout =
                 StateLayout::try_new(log.clone(), root.clone(), &MetricsRegistry::new()).unwrap();
             let tip_handler = layout.capture_tip_handler();
-            let (_tip_thread, tip_channel) =
-                spawn_tip_thread(log, tip_handler, layout.clone(), state_manager_metrics());
+            let (_tip_thread, tip_channel) = spawn_tip_thread(
+                log,
+                tip_handler,
+                layout.clone(),
+                state_manager_metrics(),
+                FlagStatus::Disabled,
+            );
 
             const HEIGHT: Height = Height::new(42);
             let canister_id = canister_test_id(10);
@@ -602,8 +718,13 @@ mod tests {
                 StateLayout::try_new(log.clone(), root.clone(), &MetricsRegistry::new()).unwrap();
             let tip_handler = layout.capture_tip_handler();
             let state_manager_metrics = state_manager_metrics();
-            let (_tip_thread, tip_channel) =
-                spawn_tip_thread(log, tip_handler, layout, state_manager_metrics.clone());
+            let (_tip_thread, tip_channel) = spawn_tip_thread(
+                log,
+                tip_handler,
+                layout,
+                state_manager_metrics.clone(),
+                FlagStatus::Disabled,
+            );
 
             const HEIGHT: Height = Height::new(42);
             let canister_id = canister_test_id(10);
@@ -652,6 +773,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -751,6 +873,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -834,6 +957,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -899,6 +1023,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -950,6 +1075,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -1001,6 +1127,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -1054,6 +1181,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
@@ -1107,6 +1235,7 @@ mod tests {
                 tip_handler,
                 layout.clone(),
                 state_manager_metrics.clone(),
+                FlagStatus::Disabled,
             );
 
             const HEIGHT: Height = Height::new(42);
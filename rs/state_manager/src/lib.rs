@@ -1,8 +1,10 @@
+pub mod canister_archive;
 // Needs to be `pub` so that the benchmarking code in `state_benches`
 // can access it.
 pub mod checkpoint;
 pub mod labeled_tree_visitor;
 pub mod manifest;
+mod scrubber;
 pub mod state_sync;
 pub mod stream_encoding;
 pub mod tip;
@@ -11,6 +13,7 @@ pub mod tree_hash;
 
 use crate::{
     manifest::{build_meta_manifest, compute_bundled_manifest, MAX_SUPPORTED_STATE_SYNC_VERSION},
+    scrubber::{spawn_scrubber_thread, ScrubRequest},
     state_sync::chunkable::cache::StateSyncCache,
     tip::{spawn_tip_thread, TipRequest},
 };
@@ -20,7 +23,7 @@ use ic_canonical_state::{
     hash_tree::{hash_lazy_tree, HashTree},
     lazy_tree::{materialize::materialize_partial, LazyTree},
 };
-use ic_config::state_manager::Config;
+use ic_config::{flag_status::FlagStatus, state_manager::Config};
 use ic_crypto_tree_hash::{recompute_digest, Digest, LabeledTree, MixedHashTree, Witness};
 use ic_interfaces::certification::Verifier;
 use ic_interfaces_certified_stream_store::{
@@ -50,7 +53,7 @@ use ic_types::{
     CryptoHashOfPartialState, CryptoHashOfState, Height, RegistryVersion, SubnetId,
 };
 use ic_utils::thread::JoinOnDrop;
-use prometheus::{HistogramVec, IntCounter, IntCounterVec, IntGauge};
+use prometheus::{Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge};
 use prost::Message;
 use std::convert::{From, TryFrom};
 use std::fs::File;
@@ -83,6 +86,10 @@ const CRITICAL_ERROR_REUSED_CHUNK_HASH: &str =
 /// Critical error tracking unexpectedly corrupted chunks.
 const CRITICAL_ERROR_STATE_SYNC_CORRUPTED_CHUNKS: &str = "state_sync_corrupted_chunks";
 
+/// Critical error tracking checkpoints whose files no longer match their
+/// manifest.
+const CRITICAL_ERROR_CHECKPOINT_CORRUPTED: &str = "state_manager_checkpoint_corrupted";
+
 /// How long to keep archived and diverged states.
 const ARCHIVED_CHECKPOINT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60); // 30 days
 const DIVERGED_CHECKPOINT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60); // 30 days
@@ -100,6 +107,11 @@ const LABEL_COPY_CHUNKS: &str = "copy_chunks";
 const LABEL_PREALLOCATE: &str = "preallocate";
 const LABEL_STATE_SYNC_MAKE_CHECKPOINT: &str = "state_sync_make_checkpoint";
 
+/// Labels for checkpoint scrubbing metrics
+const LABEL_SCRUB_OK: &str = "ok";
+const LABEL_SCRUB_CORRUPTED: &str = "corrupted";
+const LABEL_SCRUB_REMOVED: &str = "removed";
+
 #[derive(Clone)]
 pub struct StateManagerMetrics {
     state_manager_error_count: IntCounterVec,
@@ -118,6 +130,7 @@ pub struct StateManagerMetrics {
     checkpoint_metrics: CheckpointMetrics,
     manifest_metrics: ManifestMetrics,
     tip_handler_queue_length: IntGauge,
+    scrubber_metrics: ScrubberMetrics,
 }
 
 #[derive(Clone)]
@@ -137,6 +150,15 @@ pub struct StateSyncMetrics {
     corrupted_chunks: IntCounterVec,
 }
 
+#[derive(Clone)]
+pub struct ScrubberMetrics {
+    scrubbed_bytes: IntCounter,
+    scrubbed_checkpoints: IntCounterVec,
+    corrupted_chunks: IntCounter,
+    corrupted_checkpoint_critical: IntCounter,
+    duration: Histogram,
+}
+
 #[derive(Clone)]
 pub struct CheckpointMetrics {
     make_checkpoint_step_duration: HistogramVec,
@@ -200,6 +222,47 @@ impl CheckpointMetrics {
     }
 }
 
+impl ScrubberMetrics {
+    pub fn new(metrics_registry: &MetricsRegistry) -> Self {
+        let scrubbed_bytes = metrics_registry.int_counter(
+            "state_manager_scrubbed_bytes_total",
+            "Number of checkpoint bytes re-hashed by the checkpoint scrubber.",
+        );
+
+        let scrubbed_checkpoints = metrics_registry.int_counter_vec(
+            "state_manager_scrubbed_checkpoints_total",
+            "Number of checkpoints scrubbed by outcome ('ok', 'corrupted', 'removed').",
+            &["status"],
+        );
+
+        // Note [Metrics preallocation]
+        for status in &[LABEL_SCRUB_OK, LABEL_SCRUB_CORRUPTED, LABEL_SCRUB_REMOVED] {
+            scrubbed_checkpoints.with_label_values(&[*status]);
+        }
+
+        let corrupted_chunks = metrics_registry.int_counter(
+            "state_manager_scrubbed_corrupted_chunks_total",
+            "Number of checkpoint chunks found by the scrubber not to match the manifest.",
+        );
+
+        let duration = metrics_registry.histogram(
+            "state_manager_scrub_checkpoint_duration_seconds",
+            "Duration of scrubbing a single checkpoint in seconds.",
+            // 0.1s, 0.2s, 0.5s, 1s, 2s, 5s, …, 1000s, 2000s, 5000s
+            decimal_buckets(-1, 3),
+        );
+
+        Self {
+            scrubbed_bytes,
+            scrubbed_checkpoints,
+            corrupted_chunks,
+            corrupted_checkpoint_critical: metrics_registry
+                .error_counter(CRITICAL_ERROR_CHECKPOINT_CORRUPTED),
+            duration,
+        }
+    }
+}
+
 // Note [Metrics preallocation]
 // ============================
 //
@@ -310,6 +373,7 @@ impl StateManagerMetrics {
             checkpoint_metrics: CheckpointMetrics::new(metrics_registry),
             manifest_metrics: ManifestMetrics::new(metrics_registry),
             tip_handler_queue_length,
+            scrubber_metrics: ScrubberMetrics::new(metrics_registry),
         }
     }
 }
@@ -554,10 +618,10 @@ pub struct StateSyncRefs {
 }
 
 impl StateSyncRefs {
-    fn new(log: ReplicaLogger) -> Self {
+    fn new(cache: StateSyncCache) -> Self {
         Self {
             active: Arc::new(parking_lot::RwLock::new(BTreeMap::new())),
-            cache: Arc::new(parking_lot::RwLock::new(StateSyncCache::new(log))),
+            cache: Arc::new(parking_lot::RwLock::new(cache)),
         }
     }
 
@@ -656,8 +720,11 @@ pub struct StateManagerImpl {
     persist_metadata_guard: Arc<Mutex<()>>,
     tip_channel: Sender<TipRequest>,
     _tip_thread_handle: JoinOnDrop<()>,
+    scrub_channel: Sender<ScrubRequest>,
+    _scrubber_handle: JoinOnDrop<()>,
     fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
     malicious_flags: MaliciousFlags,
+    incremental_checkpoints: FlagStatus,
 }
 
 fn load_checkpoint(
@@ -741,9 +808,16 @@ fn initialize_tip(
 
     info!(log, "Recovering checkpoint @{} as tip", snapshot.height);
 
+    let height = checkpoint_layout.height();
     tip_channel
         .send(TipRequest::ResetTipTo { checkpoint_layout })
         .unwrap();
+    tip_channel
+        .send(TipRequest::CompactTip {
+            height,
+            page_map_types: PageMapType::list_all(&snapshot.state),
+        })
+        .unwrap();
 
     // Wait for reset_tip_to so that we don't reflink in parallel with other operations.
     let (send, recv) = unbounded();
@@ -1154,6 +1228,14 @@ fn switch_to_checkpoint(tip: &mut ReplicatedState, src: &ReplicatedState) {
             tip_state.stable_memory.sandbox_memory = SandboxMemory::new();
         }
     }
+
+    // Snapshots are immutable, so they can be taken over from the checkpoint
+    // as a whole. This releases the page deltas they held in memory.
+    assert_eq!(
+        tip.canister_snapshots.snapshot_ids().collect::<Vec<_>>(),
+        src.canister_snapshots.snapshot_ids().collect::<Vec<_>>(),
+    );
+    tip.canister_snapshots = src.canister_snapshots.clone();
 }
 
 /// Persists metadata after releasing the write lock
@@ -1241,6 +1323,17 @@ fn persist_metadata_or_die(
 }
 
 impl StateManagerImpl {
+    /// Re-hashes all checkpoints against their manifests right away and
+    /// returns the heights of the checkpoints that were found to be corrupted
+    /// and therefore marked diverged. Blocks until the scrubbing is done.
+    pub fn scrub_checkpoints(&self) -> Vec<Height> {
+        let (sender, recv) = unbounded();
+        self.scrub_channel
+            .send(ScrubRequest::Scrub { sender })
+            .expect("failed to send ScrubRequest");
+        recv.recv().expect("failed to wait for checkpoint scrubber")
+    }
+
     pub fn flush_manifest_thread(&self) {
         let (sender, recv) = unbounded();
         self.compute_manifest_request_sender
@@ -1284,6 +1377,7 @@ impl StateManagerImpl {
             state_layout.capture_tip_handler(),
             state_layout.clone(),
             metrics.clone(),
+            config.incremental_checkpoints(),
         );
 
         let starting_time = Instant::now();
@@ -1509,6 +1603,16 @@ impl StateManagerImpl {
                 .expect("failed to send ComputeManifestRequest");
         }
 
+        let (_scrubber_handle, scrub_channel) = spawn_scrubber_thread(
+            log.clone(),
+            state_layout.clone(),
+            Arc::clone(&states),
+            metrics.clone(),
+            persist_metadata_guard.clone(),
+            tip_channel.clone(),
+            config.checkpoint_scrubbing() == FlagStatus::Enabled,
+        );
+
         report_last_diverged_state(&log, &metrics, &state_layout);
 
         Self {
@@ -1528,8 +1632,11 @@ impl StateManagerImpl {
             persist_metadata_guard,
             tip_channel,
             _tip_thread_handle,
+            scrub_channel,
+            _scrubber_handle,
             fd_factory,
             malicious_flags,
+            incremental_checkpoints: config.incremental_checkpoints(),
         }
     }
     /// Returns the Page Allocator file descriptor factory. This will then be
@@ -1933,6 +2040,13 @@ impl StateManagerImpl {
         self.metrics.checkpoint_metrics.page_map_flushes.inc();
         for entry in PageMapType::list_all(tip_state) {
             if let Some(page_map) = entry.get_mut(tip_state) {
+                // With incremental checkpoints, the base files in the tip are left untouched and
+                // the whole page delta is written to a new overlay at the next checkpoint, so
+                // there is nothing to flush in between.
+                if self.incremental_checkpoints == FlagStatus::Enabled {
+                    page_map.strip_unflushed_delta();
+                    continue;
+                }
                 // In cases where a PageMap's data has to be wiped, execution will replace the PageMap with a newly
                 // created one. In these cases, we also need to wipe the data from the file on disk.
                 // If the PageMap represents a new file, then the base_height will be None, as we set base_height only
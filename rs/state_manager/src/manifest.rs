@@ -23,13 +23,14 @@ use ic_types::{
     crypto::CryptoHash,
     state_sync::{
         encode_manifest, ChunkInfo, FileGroupChunks, FileInfo, Manifest, MetaManifest,
-        FILE_GROUP_CHUNK_ID_OFFSET,
+        FILE_GROUP_CHUNK_ID_OFFSET, MIN_COMPRESSED_CHUNKS_VERSION,
     },
     CryptoHashOfState, Height,
 };
+use ic_utils::layered_file::{self, LayeredFile};
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaChaRng;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
@@ -41,13 +42,25 @@ pub const STATE_SYNC_V1: u32 = 1;
 /// Compute the manifest hash based on the encoded manifest.
 pub const STATE_SYNC_V2: u32 = 2;
 
+/// Compress chunk payloads with zstd and fetch or copy chunks with identical
+/// contents only once. The manifest itself is computed and hashed as in
+/// `STATE_SYNC_V2`.
+pub const STATE_SYNC_V3: u32 = 3;
+
+const _: () = assert!(STATE_SYNC_V3 == MIN_COMPRESSED_CHUNKS_VERSION);
+
 /// The version of StateSync protocol that should be used for all newly created manifests.
+///
+/// The version is recorded in the state metadata, so all replicas of a subnet
+/// agree on it and a peer never has to serve or fetch a manifest it doesn't
+/// support. Only bump it once all replicas support the new version (i.e. after
+/// `MAX_SUPPORTED_STATE_SYNC_VERSION` was bumped in an earlier replica version).
 pub const CURRENT_STATE_SYNC_VERSION: u32 = STATE_SYNC_V2;
 
 /// Maximum supported StateSync version.
 ///
 /// The replica will panic if trying to deal with a manifest with a version higher than this.
-pub const MAX_SUPPORTED_STATE_SYNC_VERSION: u32 = STATE_SYNC_V2;
+pub const MAX_SUPPORTED_STATE_SYNC_VERSION: u32 = STATE_SYNC_V3;
 
 pub const DEFAULT_CHUNK_SIZE: u32 = 1 << 20; // 1 MiB.
 
@@ -305,6 +318,36 @@ fn uses_chunk_size(manifest: &Manifest, max_chunk_size: u32) -> bool {
     })
 }
 
+/// The contents of a checkpoint file that chunk hashes are computed from.
+enum FileData {
+    /// A file without overlays, mapped into memory.
+    Mapped(ScopedMmap),
+    /// A file with overlays, read through its logical view.
+    Layered(LayeredFile),
+}
+
+impl FileData {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if layered_file::overlay_paths(path)?.is_empty() {
+            Ok(Self::Mapped(ScopedMmap::from_path(path)?))
+        } else {
+            Ok(Self::Layered(LayeredFile::open(path)?))
+        }
+    }
+
+    /// Calls `f` with the bytes in the given range of the file.
+    fn with_bytes<R>(&self, range: Range<usize>, f: impl FnOnce(&[u8]) -> R) -> std::io::Result<R> {
+        match self {
+            Self::Mapped(mmap) => Ok(f(&mmap.as_slice()[range])),
+            Self::Layered(file) => {
+                let mut buf = vec![0; range.len()];
+                file.read_exact_at(&mut buf, range.start as u64)?;
+                Ok(f(&buf))
+            }
+        }
+    }
+}
+
 // Computes file_table and chunk_table of a manifest using a parallel algorithm.
 // All the parallel work is spawned in the specified thread pool.
 fn build_chunk_table_parallel(
@@ -352,8 +395,7 @@ fn build_chunk_table_parallel(
     // and close the corresponding file.
     // This way we keep the number of files opened at the same time
     // low (it doesn't exceed the number of the threads).
-    let file_cache: Arc<Mutex<HashMap<u32, Weak<ScopedMmap>>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    let file_cache: Arc<Mutex<HashMap<u32, Weak<FileData>>>> = Arc::new(Mutex::new(HashMap::new()));
 
     // Compute real chunk hashes in parallel.
     // NB. We must populate hashes of all the chunks in a file before we compute
@@ -366,33 +408,34 @@ fn build_chunk_table_parallel(
             let file_cache = Arc::clone(&file_cache);
             scope.execute(move || {
                 let recompute_chunk_hash = || {
-                    let mmap: Arc<ScopedMmap> = if file_size > max_chunk_size as u64 {
+                    let file_data: Arc<FileData> = if file_size > max_chunk_size as u64 {
                         // We only use the file cache if there is more than one chunk in the file,
                         // otherwise the synchronization cost is unnecessary.
                         let mut cache = file_cache.lock().unwrap();
                         match cache.get(&chunk_info.file_index).and_then(Weak::upgrade) {
-                            Some(mmap) => mmap,
+                            Some(file_data) => file_data,
                             None => {
-                                let mmap = Arc::new(
-                                    ScopedMmap::from_path(&file_path)
-                                        .unwrap_or_else(|e| fatal!(log, "failed to mmap file {}: {}", file_path.display(), e)),
+                                let file_data = Arc::new(
+                                    FileData::open(&file_path)
+                                        .unwrap_or_else(|e| fatal!(log, "failed to open file {}: {}", file_path.display(), e)),
                                 );
-                                cache.insert(chunk_info.file_index, Arc::downgrade(&mmap));
-                                mmap
+                                cache.insert(chunk_info.file_index, Arc::downgrade(&file_data));
+                                file_data
                             }
                         }
                     } else {
                         Arc::new(
-                            ScopedMmap::from_path(&file_path)
-                                .unwrap_or_else(|e| fatal!(log, "failed to mmap file {}: {}", file_path.display(), e))
+                            FileData::open(&file_path)
+                                .unwrap_or_else(|e| fatal!(log, "failed to open file {}: {}", file_path.display(), e))
                         )
                     };
-                    let data = mmap.as_slice();
 
                     let mut hasher = chunk_hasher();
                     let chunk_start = chunk_info.offset as usize;
                     let chunk_end = chunk_start + chunk_info.size_bytes as usize;
-                    hasher.write(&data[chunk_start..chunk_end]);
+                    file_data
+                        .with_bytes(chunk_start..chunk_end, |data| hasher.write(data))
+                        .unwrap_or_else(|e| fatal!(log, "failed to read file {}: {}", file_path.display(), e));
                     hasher.finish()
                 };
 
@@ -544,9 +587,10 @@ fn build_chunk_table_sequential(
             });
         };
 
-        let mmap = ScopedMmap::from_path(root.join(&relative_path)).expect("failed to open file");
-        let data = mmap.as_slice();
-        compute_file_chunk_hashes(data);
+        FileData::open(&root.join(&relative_path))
+            .expect("failed to open file")
+            .with_bytes(0..size_bytes as usize, compute_file_chunk_hashes)
+            .expect("failed to read file");
     }
 
     assert_eq!(chunk_table.len(), chunk_actions.len());
@@ -571,7 +615,24 @@ fn files_with_sizes(
         })?;
 
     if metadata.is_file() {
-        files.push(FileWithSize(relative_path, metadata.len()))
+        // Overlays are part of the logical contents of their base file.
+        if layered_file::is_overlay(&relative_path) {
+            return Ok(());
+        }
+        let size = if layered_file::overlay_paths(&absolute_path)
+            .map_or(false, |overlays| !overlays.is_empty())
+        {
+            LayeredFile::open(&absolute_path)
+                .map_err(|io_err| CheckpointError::IoError {
+                    path: absolute_path.clone(),
+                    message: "failed to open layered file".to_string(),
+                    io_err: io_err.to_string(),
+                })?
+                .len()
+        } else {
+            metadata.len()
+        };
+        files.push(FileWithSize(relative_path, size))
     } else {
         if relative_path.ends_with("slot_db") {
             return Ok(());
@@ -1272,3 +1333,43 @@ pub fn filter_out_zero_chunks(manifest: &Manifest) -> HashSet<usize> {
         .collect();
     fetch_chunks
 }
+
+/// Removes from `fetch_chunks` every chunk that has the same hash as another
+/// chunk in the set, so that chunks with identical contents are only fetched
+/// once. File group chunks are left untouched.
+///
+/// `fetch_chunks` contains P2P chunk ids, i.e. chunk table indices shifted by
+/// one. Returns a map from the chunk table index of every chunk that is still
+/// fetched to the chunk table indices of the removed chunks with the same
+/// contents.
+pub(crate) fn deduplicate_fetch_chunks(
+    manifest: &Manifest,
+    fetch_chunks: &mut HashSet<usize>,
+) -> HashMap<usize, Vec<usize>> {
+    let mut chunk_ids: Vec<usize> = fetch_chunks
+        .iter()
+        .copied()
+        .filter(|id| *id < FILE_GROUP_CHUNK_ID_OFFSET as usize)
+        .collect();
+    // Sort the ids so that the chunk with the lowest index is always the one fetched.
+    chunk_ids.sort_unstable();
+
+    let mut fetched_by_hash: HashMap<[u8; 32], usize> = HashMap::new();
+    let mut duplicate_chunks: HashMap<usize, Vec<usize>> = HashMap::new();
+    for chunk_id in chunk_ids {
+        let chunk_index = chunk_id - 1;
+        match fetched_by_hash.entry(manifest.chunk_table[chunk_index].hash) {
+            Entry::Occupied(entry) => {
+                duplicate_chunks
+                    .entry(*entry.get())
+                    .or_default()
+                    .push(chunk_index);
+                fetch_chunks.remove(&chunk_id);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(chunk_index);
+            }
+        }
+    }
+    duplicate_chunks
+}
@@ -1,10 +1,11 @@
 use crate::manifest::{
-    build_file_group_chunks, build_meta_manifest, compute_manifest, diff_manifest,
-    file_chunk_range, filter_out_zero_chunks, hash::ManifestHash, manifest_hash, manifest_hash_v1,
-    manifest_hash_v2, meta_manifest_hash, validate_chunk, validate_manifest,
+    build_file_group_chunks, build_meta_manifest, compute_manifest, deduplicate_fetch_chunks,
+    diff_manifest, file_chunk_range, filter_out_zero_chunks, hash::ManifestHash, manifest_hash,
+    manifest_hash_v1, manifest_hash_v2, meta_manifest_hash, validate_chunk, validate_manifest,
     validate_meta_manifest, validate_sub_manifest, ChunkValidationError, DiffScript,
     ManifestMetrics, ManifestValidationError, CURRENT_STATE_SYNC_VERSION, DEFAULT_CHUNK_SIZE,
     MAX_FILE_SIZE_TO_GROUP, MAX_SUPPORTED_STATE_SYNC_VERSION, STATE_SYNC_V1, STATE_SYNC_V2,
+    STATE_SYNC_V3,
 };
 
 use ic_crypto_sha::Sha256;
@@ -710,6 +711,43 @@ fn test_filter_all_zero_chunks() {
     assert_eq!(filter_out_zero_chunks(&manifest), fetch_chunks);
 }
 
+#[test]
+fn test_deduplicate_fetch_chunks() {
+    let chunk = |file_index: u32, hash: u8| ChunkInfo {
+        file_index,
+        size_bytes: 1024,
+        offset: 0,
+        hash: [hash; 32],
+    };
+    let manifest = Manifest::new(
+        STATE_SYNC_V3,
+        vec![],
+        vec![
+            chunk(0, 1),
+            chunk(1, 2),
+            chunk(2, 1),
+            chunk(3, 3),
+            chunk(4, 1),
+            chunk(5, 2),
+        ],
+    );
+
+    // Chunk 3 (P2P id 4) is not fetched, so it is neither deduplicated nor
+    // used to deduplicate other chunks.
+    let mut fetch_chunks: HashSet<usize> =
+        maplit::hashset! {1, 2, 3, 5, 6, FILE_GROUP_CHUNK_ID_OFFSET as usize};
+    let duplicate_chunks = deduplicate_fetch_chunks(&manifest, &mut fetch_chunks);
+
+    assert_eq!(
+        fetch_chunks,
+        maplit::hashset! {1, 2, FILE_GROUP_CHUNK_ID_OFFSET as usize}
+    );
+    assert_eq!(
+        duplicate_chunks,
+        maplit::hashmap! {0 => vec![2, 4], 1 => vec![5]}
+    );
+}
+
 #[test]
 fn test_missing_simple_manifest() {
     let (_, manifest_old) = simple_manifest();
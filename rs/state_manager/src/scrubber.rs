@@ -0,0 +1,330 @@
+use crate::{
+    manifest::{file_chunk_range, validate_chunk},
+    release_lock_and_persist_metadata,
+    tip::TipRequest,
+    SharedState, StateManagerMetrics, CRITICAL_ERROR_CHECKPOINT_CORRUPTED, LABEL_SCRUB_CORRUPTED,
+    LABEL_SCRUB_OK, LABEL_SCRUB_REMOVED,
+};
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use ic_logger::{error, fatal, info, warn, ReplicaLogger};
+use ic_state_layout::{CheckpointLayout, ReadOnly, StateLayout};
+use ic_types::{state_sync::Manifest, Height};
+use ic_utils::layered_file::LayeredFile;
+use ic_utils::thread::JoinOnDrop;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pause between two consecutive scrubbing passes over all checkpoints.
+const SCRUB_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Upper bound on the read throughput of the scrubber, so that it doesn't
+/// compete with execution and state sync for disk bandwidth.
+const SCRUB_BYTES_PER_SECOND: u64 = 64 << 20;
+
+/// Request for the checkpoint scrubbing thread.
+pub enum ScrubRequest {
+    /// Scrub all checkpoints right away. Send the heights of the checkpoints
+    /// found to be corrupted into the sender.
+    Scrub { sender: Sender<Vec<Height>> },
+}
+
+/// Spawns a low-priority thread that re-hashes the files of all checkpoints
+/// chunk by chunk and compares the hashes against the checkpoint manifests.
+///
+/// Checkpoints that don't match their manifest are marked as diverged (see
+/// `Scrubber::mark_checkpoint_corrupted`). If `periodic_scrubbing` is disabled,
+/// checkpoints are only scrubbed on request.
+pub(crate) fn spawn_scrubber_thread(
+    log: ReplicaLogger,
+    state_layout: StateLayout,
+    states: Arc<parking_lot::RwLock<SharedState>>,
+    metrics: StateManagerMetrics,
+    persist_metadata_lock: Arc<Mutex<()>>,
+    tip_channel: Sender<TipRequest>,
+    periodic_scrubbing: bool,
+) -> (JoinOnDrop<()>, Sender<ScrubRequest>) {
+    let (scrub_sender, scrub_receiver) = unbounded();
+    let scrubber = Scrubber {
+        log,
+        state_layout,
+        states,
+        metrics,
+        persist_metadata_lock,
+        tip_channel,
+    };
+    let scrub_handle = JoinOnDrop::new(
+        std::thread::Builder::new()
+            .name("CheckpointScrubber".to_string())
+            .spawn(move || scrubber.run(scrub_receiver, periodic_scrubbing))
+            .expect("failed to spawn checkpoint scrubber thread"),
+    );
+    (scrub_handle, scrub_sender)
+}
+
+struct Scrubber {
+    log: ReplicaLogger,
+    state_layout: StateLayout,
+    states: Arc<parking_lot::RwLock<SharedState>>,
+    metrics: StateManagerMetrics,
+    persist_metadata_lock: Arc<Mutex<()>>,
+    tip_channel: Sender<TipRequest>,
+}
+
+impl Scrubber {
+    fn run(&self, receiver: Receiver<ScrubRequest>, periodic_scrubbing: bool) {
+        let mut waiters = Vec::new();
+        loop {
+            if waiters.is_empty() {
+                let req = if periodic_scrubbing {
+                    receiver.recv_timeout(SCRUB_INTERVAL)
+                } else {
+                    receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                };
+                match req {
+                    Ok(ScrubRequest::Scrub { sender }) => waiters.push(sender),
+                    Err(RecvTimeoutError::Timeout) => (),
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            }
+
+            // Requests that arrive during a pass are served by the next one,
+            // as the pass might have already looked at the affected files.
+            let mut next_waiters = Vec::new();
+            let corrupted = match self.scrub_checkpoints(&receiver, &mut next_waiters) {
+                Some(corrupted) => corrupted,
+                None => return,
+            };
+            for sender in waiters.drain(..) {
+                let _ = sender.send(corrupted.clone());
+            }
+            waiters = next_waiters;
+        }
+    }
+
+    /// Scrubs all checkpoints with a computed manifest and returns the heights
+    /// of the corrupted ones. Returns `None` if the state manager shuts down
+    /// in the meantime.
+    fn scrub_checkpoints(
+        &self,
+        receiver: &Receiver<ScrubRequest>,
+        waiters: &mut Vec<Sender<Vec<Height>>>,
+    ) -> Option<Vec<Height>> {
+        let checkpoints: Vec<(Height, Manifest)> = {
+            let states = self.states.read();
+            states
+                .states_metadata
+                .iter()
+                .filter(|(_, metadata)| metadata.checkpoint_layout.is_some())
+                .filter_map(|(height, metadata)| Some((*height, metadata.manifest()?.clone())))
+                .collect()
+        };
+
+        let mut corrupted = Vec::new();
+        for (height, manifest) in checkpoints {
+            // Holding on to the layout prevents the checkpoint from being
+            // removed while we read it.
+            let checkpoint_layout = match self.state_layout.checkpoint(height) {
+                Ok(checkpoint_layout) => checkpoint_layout,
+                Err(_) => {
+                    self.metrics
+                        .scrubber_metrics
+                        .scrubbed_checkpoints
+                        .with_label_values(&[LABEL_SCRUB_REMOVED])
+                        .inc();
+                    continue;
+                }
+            };
+
+            let corrupted_chunks =
+                self.scrub_checkpoint(&checkpoint_layout, &manifest, receiver, waiters)?;
+            drop(checkpoint_layout);
+
+            if corrupted_chunks == 0 {
+                self.metrics
+                    .scrubber_metrics
+                    .scrubbed_checkpoints
+                    .with_label_values(&[LABEL_SCRUB_OK])
+                    .inc();
+            } else {
+                self.metrics
+                    .scrubber_metrics
+                    .scrubbed_checkpoints
+                    .with_label_values(&[LABEL_SCRUB_CORRUPTED])
+                    .inc();
+                self.mark_checkpoint_corrupted(height, corrupted_chunks);
+                corrupted.push(height);
+            }
+        }
+        Some(corrupted)
+    }
+
+    /// Re-hashes all chunks of the checkpoint and returns the number of chunks
+    /// that don't match the manifest, or `None` on shutdown.
+    fn scrub_checkpoint(
+        &self,
+        checkpoint_layout: &CheckpointLayout<ReadOnly>,
+        manifest: &Manifest,
+        receiver: &Receiver<ScrubRequest>,
+        waiters: &mut Vec<Sender<Vec<Height>>>,
+    ) -> Option<usize> {
+        let _timer = self.metrics.scrubber_metrics.duration.start_timer();
+        let started_at = Instant::now();
+        let height = checkpoint_layout.height();
+
+        let mut scrubbed_bytes = 0;
+        let mut corrupted_chunks = 0;
+        let mut buf = Vec::new();
+        for (file_index, file_info) in manifest.file_table.iter().enumerate() {
+            let chunk_range = file_chunk_range(&manifest.chunk_table, file_index);
+            let path = checkpoint_layout.raw_path().join(&file_info.relative_path);
+            let file = match LayeredFile::open(&path) {
+                Ok(file) if file.len() == file_info.size_bytes => file,
+                Ok(file) => {
+                    warn!(
+                        self.log,
+                        "Checkpoint @{}: file {} has size {}, expected {}",
+                        height,
+                        path.display(),
+                        file.len(),
+                        file_info.size_bytes
+                    );
+                    corrupted_chunks += chunk_range.len();
+                    continue;
+                }
+                Err(err) => {
+                    warn!(
+                        self.log,
+                        "Checkpoint @{}: failed to open file {}: {}",
+                        height,
+                        path.display(),
+                        err
+                    );
+                    corrupted_chunks += chunk_range.len();
+                    continue;
+                }
+            };
+
+            for ix in chunk_range {
+                if shutdown_requested(receiver, waiters) {
+                    return None;
+                }
+
+                let chunk = &manifest.chunk_table[ix];
+                buf.resize(chunk.size_bytes as usize, 0);
+                let result = file
+                    .read_exact_at(&mut buf, chunk.offset)
+                    .map_err(|err| err.to_string())
+                    .and_then(|()| {
+                        validate_chunk(ix, &buf, manifest).map_err(|err| err.to_string())
+                    });
+                if let Err(err) = result {
+                    warn!(
+                        self.log,
+                        "Checkpoint @{}: corrupted chunk {} in file {}: {}",
+                        height,
+                        ix,
+                        path.display(),
+                        err
+                    );
+                    self.metrics.scrubber_metrics.corrupted_chunks.inc();
+                    corrupted_chunks += 1;
+                }
+
+                scrubbed_bytes += chunk.size_bytes as u64;
+                self.metrics
+                    .scrubber_metrics
+                    .scrubbed_bytes
+                    .inc_by(chunk.size_bytes as u64);
+                let target =
+                    Duration::from_secs_f64(scrubbed_bytes as f64 / SCRUB_BYTES_PER_SECOND as f64);
+                if let Some(delay) = target.checked_sub(started_at.elapsed()) {
+                    std::thread::sleep(delay);
+                }
+            }
+        }
+        Some(corrupted_chunks)
+    }
+
+    /// Moves the checkpoint at `height` to the diverged checkpoints and removes
+    /// it from the states metadata, so that it is neither loaded on restart nor
+    /// served to or used as a base by state sync. If the state is fetched from
+    /// peers again, the corrupted copy is backed up (see `on_synced_checkpoint`).
+    ///
+    /// The latest checkpoint backs the tip and the states in memory, so if it is
+    /// corrupted the replica restarts from an earlier checkpoint (or state syncs
+    /// if there is none), just like after a divergence.
+    fn mark_checkpoint_corrupted(&self, height: Height, corrupted_chunks: usize) {
+        let mut states = self.states.write();
+        let latest_checkpoint_height = states
+            .states_metadata
+            .iter()
+            .rev()
+            .find(|(_, metadata)| metadata.checkpoint_layout.is_some())
+            .map(|(height, _)| *height);
+        if states.states_metadata.remove(&height).is_none() {
+            // The checkpoint got removed while we were scrubbing it.
+            return;
+        }
+
+        error!(
+            self.log,
+            "{}: Checkpoint @{} has {} chunks that don't match its manifest, marking it diverged",
+            CRITICAL_ERROR_CHECKPOINT_CORRUPTED,
+            height,
+            corrupted_chunks
+        );
+        self.metrics
+            .scrubber_metrics
+            .corrupted_checkpoint_critical
+            .inc();
+
+        // The tip thread owns all changes to the checkpoints on disk; holding the
+        // states lock in the meantime keeps the checkpoint from being removed or
+        // advertised concurrently.
+        let (sender, recv) = unbounded();
+        self.tip_channel
+            .send(TipRequest::MarkCheckpointDiverged { height, sender })
+            .expect("failed to send MarkCheckpointDiverged request");
+        match recv
+            .recv()
+            .expect("failed to wait for MarkCheckpointDiverged response")
+        {
+            Ok(()) => info!(self.log, "Marked corrupted checkpoint @{} diverged", height),
+            Err(err) => error!(
+                self.log,
+                "Failed to mark corrupted checkpoint @{} diverged: {}", height, err
+            ),
+        }
+
+        release_lock_and_persist_metadata(
+            &self.log,
+            &self.metrics,
+            &self.state_layout,
+            states,
+            &self.persist_metadata_lock,
+        );
+
+        if latest_checkpoint_height == Some(height) {
+            fatal!(
+                self.log,
+                "Latest checkpoint @{} is corrupted, restarting from an earlier state",
+                height
+            );
+        }
+    }
+}
+
+/// Queues the scrub requests received in the meantime into `waiters` and
+/// returns true if the state manager is shutting down.
+fn shutdown_requested(
+    receiver: &Receiver<ScrubRequest>,
+    waiters: &mut Vec<Sender<Vec<Height>>>,
+) -> bool {
+    loop {
+        match receiver.try_recv() {
+            Ok(ScrubRequest::Scrub { sender }) => waiters.push(sender),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => return true,
+        }
+    }
+}
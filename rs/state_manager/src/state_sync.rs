@@ -2,8 +2,8 @@ pub(crate) mod chunkable;
 
 use super::StateManagerImpl;
 use crate::{
-    manifest::build_file_group_chunks, StateSyncRefs, EXTRA_CHECKPOINTS_TO_KEEP,
-    NUMBER_OF_CHECKPOINT_THREADS,
+    manifest::build_file_group_chunks, state_sync::chunkable::cache::StateSyncCache, StateSyncRefs,
+    EXTRA_CHECKPOINTS_TO_KEEP, NUMBER_OF_CHECKPOINT_THREADS,
 };
 use ic_interfaces::{
     artifact_manager::{ArtifactClient, ArtifactProcessor, ProcessingResult},
@@ -33,9 +33,11 @@ pub struct StateSync {
 
 impl StateSync {
     pub fn new(state_manager: Arc<StateManagerImpl>, log: ReplicaLogger) -> Self {
+        // Pick up the state sync that was in progress when the replica stopped, if any.
+        let cache = StateSyncCache::load(log.clone(), &state_manager.state_layout);
         Self {
             state_manager,
-            state_sync_refs: StateSyncRefs::new(log.clone()),
+            state_sync_refs: StateSyncRefs::new(cache),
             log,
         }
     }
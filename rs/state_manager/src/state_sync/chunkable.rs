@@ -1,5 +1,8 @@
 use crate::{
-    manifest::{build_file_group_chunks, filter_out_zero_chunks, DiffScript},
+    manifest::{
+        build_file_group_chunks, deduplicate_fetch_chunks, filter_out_zero_chunks, DiffScript,
+        STATE_SYNC_V3,
+    },
     StateManagerMetrics, StateSyncMetrics, StateSyncRefs,
     CRITICAL_ERROR_STATE_SYNC_CORRUPTED_CHUNKS, LABEL_COPY_CHUNKS, LABEL_COPY_FILES, LABEL_FETCH,
     LABEL_PREALLOCATE, LABEL_STATE_SYNC_MAKE_CHECKPOINT,
@@ -19,10 +22,13 @@ use ic_types::{
     },
     malicious_flags::MaliciousFlags,
     state_sync::{
-        decode_manifest, FileGroupChunks, Manifest, FILE_GROUP_CHUNK_ID_OFFSET, MANIFEST_CHUNK,
+        decode_chunk_payload, decode_manifest, FileGroupChunks, Manifest,
+        FILE_GROUP_CHUNK_ID_OFFSET, MANIFEST_CHUNK,
     },
     CryptoHashOfState, Height,
 };
+use ic_utils::layered_file::{self, LayeredFile};
+use std::ops::Range;
 use std::os::unix::fs::FileExt;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
@@ -32,6 +38,7 @@ use std::{
 };
 
 pub mod cache;
+mod progress;
 
 // If set to true, we validate chunks even in situations where it might not be
 // necessary.
@@ -51,6 +58,10 @@ enum DownloadState {
         /// set chunk 0 is the manifest. To get indices into the manifests's
         /// chunk table subtract 1.
         fetch_chunks: HashSet<usize>,
+        /// Chunks that are not fetched themselves because they have the same
+        /// contents as a chunk in `fetch_chunks`. Maps the chunk table index
+        /// of the fetched chunk to the chunk table indices of its duplicates.
+        duplicate_chunks: HashMap<usize, Vec<usize>>,
     },
     /// Successfully completed and returned the artifact to P2P, nothing else to
     /// do.
@@ -79,10 +90,114 @@ pub struct IncompleteState {
     thread_pool: Arc<Mutex<scoped_threadpool::Pool>>,
     state_sync_refs: StateSyncRefs,
     fd_factory: Arc<dyn PageAllocatorFileDescriptor>,
+    /// Persisted record of the chunks present in the scratchpad, used to
+    /// resume the state sync after a restart. Only set while loading chunks.
+    chunk_bitmap: Option<progress::ChunkBitmap>,
     #[allow(dead_code)]
     malicious_flags: MaliciousFlags,
 }
 
+/// Converts the chunk ids in `fetch_chunks`, which count the manifest as
+/// chunk 0 and may refer to file group chunks, into the indices of all the
+/// chunks in the manifest's chunk table that are still missing.
+fn missing_chunk_table_indices(
+    fetch_chunks: &HashSet<usize>,
+    state_sync_file_group: &FileGroupChunks,
+    duplicate_chunks: &HashMap<usize, Vec<usize>>,
+) -> HashSet<usize> {
+    debug_assert!(!fetch_chunks.contains(&0));
+    let mut missing_chunks: HashSet<usize> = Default::default();
+    for &i in fetch_chunks.iter() {
+        assert_ne!(0, i);
+        if i < FILE_GROUP_CHUNK_ID_OFFSET as usize {
+            missing_chunks.insert(i - 1);
+            // Duplicates are only written once the chunk they copy is fetched.
+            if let Some(duplicates) = duplicate_chunks.get(&(i - 1)) {
+                missing_chunks.extend(duplicates);
+            }
+        } else {
+            // If it's a chunk group, the individual chunks are missing in the manifest,
+            // not the group
+            let chunks = state_sync_file_group
+                .get(&(i as u32))
+                .expect("Unknown chunk group");
+            missing_chunks.extend(chunks.iter().map(|i| *i as usize));
+        }
+    }
+    missing_chunks
+}
+
+/// A file of an old checkpoint that chunks are copied from.
+enum SourceFile {
+    /// A file without overlays, mapped into memory.
+    Plain {
+        #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+        file: std::fs::File,
+        map: ScopedMmap,
+    },
+    /// A file with overlays. Its contents on disk differ from its logical
+    /// contents, so chunks are rebuilt from its layers.
+    Layered(LayeredFile),
+}
+
+impl SourceFile {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if layered_file::overlay_paths(path)?.is_empty() {
+            let file = std::fs::File::open(path)?;
+            let len = file.metadata()?.len() as usize;
+            let map = ScopedMmap::from_readonly_file(&file, len)?;
+            Ok(Self::Plain { file, map })
+        } else {
+            Ok(Self::Layered(LayeredFile::open(path)?))
+        }
+    }
+
+    fn is_layered(&self) -> bool {
+        matches!(self, Self::Layered(_))
+    }
+
+    /// Returns the length of the logical contents of the file.
+    fn len(&self) -> usize {
+        match self {
+            Self::Plain { map, .. } => map.len(),
+            Self::Layered(file) => file.len() as usize,
+        }
+    }
+
+    /// Calls `f` with the logical bytes in the given range of the file.
+    fn with_bytes<R>(&self, range: Range<usize>, f: impl FnOnce(&[u8]) -> R) -> std::io::Result<R> {
+        match self {
+            Self::Plain { map, .. } => Ok(f(&map.as_slice()[range])),
+            Self::Layered(file) => {
+                let mut buf = vec![0; range.len()];
+                file.read_exact_at(&mut buf, range.start as u64)?;
+                Ok(f(&buf))
+            }
+        }
+    }
+
+    /// Copies the logical bytes in the given range of the file to `dst` at
+    /// `dst_offset`.
+    fn copy_to(
+        &self,
+        range: Range<usize>,
+        dst: &std::fs::File,
+        dst_offset: u64,
+    ) -> std::io::Result<()> {
+        match self {
+            #[cfg(target_os = "linux")]
+            Self::Plain { file, .. } => ic_utils::fs::copy_file_range_all(
+                file,
+                range.start as i64,
+                dst,
+                dst_offset as i64,
+                range.len(),
+            ),
+            _ => self.with_bytes(range, |bytes| dst.write_all_at(bytes, dst_offset))?,
+        }
+    }
+}
+
 impl Drop for IncompleteState {
     fn drop(&mut self) {
         if self.state_sync_refs.remove(&self.height).is_none() {
@@ -107,6 +222,7 @@ impl Drop for IncompleteState {
                 manifest: _,
                 state_sync_file_group,
                 fetch_chunks,
+                duplicate_chunks,
             } => {
                 self.metrics
                     .state_sync_metrics
@@ -118,7 +234,7 @@ impl Drop for IncompleteState {
                     .iter()
                     .map(|ix| {
                         if (*ix as u32) < FILE_GROUP_CHUNK_ID_OFFSET {
-                            1
+                            1 + duplicate_chunks.get(&(ix - 1)).map_or(0, |vec| vec.len())
                         } else {
                             state_sync_file_group
                                 .get(&(*ix as u32))
@@ -193,6 +309,7 @@ impl IncompleteState {
             thread_pool,
             state_sync_refs,
             fd_factory,
+            chunk_bitmap: None,
             malicious_flags,
         }
     }
@@ -285,9 +402,20 @@ impl IncompleteState {
                         )
                     })
                         .permissions();
-                    if validate_data || ALWAYS_VALIDATE {
-
-                        let src = std::fs::File::open(&src_path).unwrap_or_else(|err| {
+                    // The contents of a file with overlays on disk differ from its logical
+                    // contents, so such a file is always copied chunk by chunk.
+                    let src_has_overlays = !layered_file::overlay_paths(&src_path)
+                        .unwrap_or_else(|err| {
+                            fatal!(
+                                log,
+                                "Failed to list overlays of file {}: {}",
+                                src_path.display(),
+                                err
+                            )
+                        })
+                        .is_empty();
+                    if validate_data || ALWAYS_VALIDATE || src_has_overlays {
+                        let src = SourceFile::open(&src_path).unwrap_or_else(|err| {
                             fatal!(
                                 log,
                                 "Failed to open file {} for read: {}",
@@ -295,22 +423,6 @@ impl IncompleteState {
                                 err
                             )
                         });
-                        let src_len = src
-                            .metadata()
-                            .unwrap_or_else(|err| {
-                                fatal!(
-                                    log,
-                                    "Failed to get metadata of file {}: {}",
-                                    src_path.display(),
-                                    err
-                                )
-                            })
-                            .len() as usize;
-
-                        let src_map = ScopedMmap::from_readonly_file(&src, src_len).unwrap_or_else(|err| {
-                            fatal!(log, "Failed to mmap file {}: {}", src_path.display(), err)
-                        });
-                        let src_data = src_map.as_slice();
 
                         let old_chunk_range = crate::manifest::file_chunk_range(
                             &manifest_old.chunk_table,
@@ -324,15 +436,15 @@ impl IncompleteState {
                         let mut bad_chunks = vec![];
 
                         // Go through all the chunks of the local file and validate
-                        // each one.  If the validation fails, add the corresponding new
-                        // chunk ids to the set of chunks to fetch.
+                        // each one if requested.  If the validation fails, add the
+                        // corresponding new chunk ids to the set of chunks to fetch.
                         for idx in old_chunk_range.clone() {
                             let chunk = &manifest_old.chunk_table[idx];
                             let chunk_offset = idx - old_chunk_range.start;
                             let new_chunk_idx = new_chunk_range.start + chunk_offset;
                             let byte_range = chunk.byte_range();
 
-                            if src_data.len() < byte_range.end {
+                            if src.len() < byte_range.end {
                                 warn!(
                                     log,
                                     "Local chunk {} ({}@{}—{}) is out of range (file len = {}), \
@@ -341,7 +453,7 @@ impl IncompleteState {
                                     src_path.display(),
                                     byte_range.start,
                                     byte_range.end,
-                                    src_data.len(),
+                                    src.len(),
                                     new_chunk_idx + 1
                                 );
                                 bad_chunks.push(idx);
@@ -359,11 +471,24 @@ impl IncompleteState {
                                 continue;
                             }
 
-                            if let Err(err) = crate::manifest::validate_chunk(
-                                idx,
-                                &src_data[byte_range.clone()],
-                                manifest_old,
-                            ) {
+                            if !(validate_data || ALWAYS_VALIDATE) {
+                                continue;
+                            }
+
+                            let validation_result = src
+                                .with_bytes(byte_range.clone(), |bytes| {
+                                    crate::manifest::validate_chunk(idx, bytes, manifest_old)
+                                })
+                                .unwrap_or_else(|err| {
+                                    fatal!(
+                                        log,
+                                        "Failed to read chunk {} from file {}: {}",
+                                        idx,
+                                        src_path.display(),
+                                        err
+                                    )
+                                });
+                            if let Err(err) = validation_result {
                                 warn!(
                                     log,
                                     "Local chunk {} ({}@{}–{}) doesn't pass validation: {}, \
@@ -392,7 +517,8 @@ impl IncompleteState {
                         }
 
                         if bad_chunks.is_empty()
-                            && src_data.len()
+                            && !src.is_layered()
+                            && src.len()
                                 == manifest_old.file_table[*old_index].size_bytes as usize
                         {
                             // All the hash sums and the file size match, so we can
@@ -434,47 +560,19 @@ impl IncompleteState {
 
                                 let chunk = &manifest_old.chunk_table[idx];
 
-                                #[cfg(target_os = "linux")]
-                                {
-                                    // The source and the destination offsets are the same because we are copying
-                                    // over uncorrupted chunks of the file into the new checkpoint.
-                                    let src_offset = chunk.offset as i64;
-                                    let dst_offset = chunk.offset as i64;
-
-                                    ic_utils::fs::copy_file_range_all(
-                                        &src,
-                                        src_offset,
-                                        &dst,
-                                        dst_offset,
-                                        chunk.size_bytes as usize
-                                    ).unwrap_or_else(|err| {
-                                        fatal!(
-                                            log,
-                                            "Failed to copy file range from {} => {} (offset = {}, size = {}): {}",
-                                            src_path.display(),
-                                            dst_path.display(),
-                                            chunk.offset,
-                                            chunk.size_bytes,
-                                            err
-                                        )
-                                    });
-                                }
-
-                                #[cfg(not(target_os = "linux"))]
-                                {
-                                    let data = &src_data[chunk.byte_range()];
-
-                                    dst.write_all_at(data, chunk.offset).unwrap_or_else(|err| {
-                                        fatal!(
-                                            log,
-                                            "Failed to write chunk (offset = {}, size = {}) to file {}: {}",
-                                            chunk.offset,
-                                            chunk.size_bytes,
-                                            dst_path.display(),
-                                            err
-                                        )
-                                    });
-                                }
+                                // The source and the destination offsets are the same because we are copying
+                                // over uncorrupted chunks of the file into the new checkpoint.
+                                src.copy_to(chunk.byte_range(), &dst, chunk.offset).unwrap_or_else(|err| {
+                                    fatal!(
+                                        log,
+                                        "Failed to copy file range from {} => {} (offset = {}, size = {}): {}",
+                                        src_path.display(),
+                                        dst_path.display(),
+                                        chunk.offset,
+                                        chunk.size_bytes,
+                                        err
+                                    )
+                                });
                                 metrics.remaining.sub(1);
                             }
                         }
@@ -542,19 +640,47 @@ impl IncompleteState {
 
         type DstIndex = usize;
         type SrcIndex = usize;
-        type ChunkGroup = Vec<(DstIndex, SrcIndex)>;
+        type ChunkGroup = Vec<(SrcIndex, Vec<DstIndex>)>;
+        // Starting from `STATE_SYNC_V3`, all dst chunks with the same contents (and
+        // hence the same src chunk) are copied together, so that the src chunk is
+        // only validated once. Before that, every dst chunk is copied on its own.
+        let copies: Vec<(SrcIndex, Vec<DstIndex>)> = if manifest_new.version >= STATE_SYNC_V3 {
+            let mut dst_chunks: HashMap<SrcIndex, Vec<DstIndex>> = HashMap::default();
+            for (dst_chunk_index, src_chunk_index) in &diff_script.copy_chunks {
+                dst_chunks
+                    .entry(*src_chunk_index)
+                    .or_default()
+                    .push(*dst_chunk_index);
+            }
+            dst_chunks
+                .into_iter()
+                .map(|(src_chunk_index, mut dst_chunk_indices)| {
+                    dst_chunk_indices.sort_unstable();
+                    (src_chunk_index, dst_chunk_indices)
+                })
+                .collect()
+        } else {
+            diff_script
+                .copy_chunks
+                .iter()
+                .map(|(dst_chunk_index, src_chunk_index)| {
+                    (*src_chunk_index, vec![*dst_chunk_index])
+                })
+                .collect()
+        };
+
         // Group chunks by the file index to lower cost of opening files.
-        // Key is the pair of dst file index and src file index,
-        // value is vector of pairs of the dst chunk index and src chunk index.
+        // Key is the pair of dst file index (of the first dst chunk) and src file index,
+        // value is vector of pairs of the src chunk index and the dst chunk indices.
         let mut chunk_groups: HashMap<(DstIndex, SrcIndex), ChunkGroup> = HashMap::default();
 
-        for (dst_chunk_index, src_chunk_index) in &diff_script.copy_chunks {
-            let dst_file_index = manifest_new.chunk_table[*dst_chunk_index].file_index;
-            let src_file_index = manifest_old.chunk_table[*src_chunk_index].file_index;
+        for (src_chunk_index, dst_chunk_indices) in copies {
+            let dst_file_index = manifest_new.chunk_table[dst_chunk_indices[0]].file_index;
+            let src_file_index = manifest_old.chunk_table[src_chunk_index].file_index;
             let entry = chunk_groups
                 .entry((dst_file_index as usize, src_file_index as usize))
                 .or_insert_with(Vec::new);
-            entry.push((*dst_chunk_index, *src_chunk_index));
+            entry.push((src_chunk_index, dst_chunk_indices));
         }
 
         let corrupted_chunks = Arc::new(Mutex::new(Vec::new()));
@@ -567,7 +693,7 @@ impl IncompleteState {
                     root_old.join(&manifest_old.file_table[*src_file_index].relative_path);
                 let corrupted_chunks = Arc::clone(&corrupted_chunks);
                 scope.execute(move || {
-                    let src = std::fs::File::open(&src_path).unwrap_or_else(|err| {
+                    let src = SourceFile::open(&src_path).unwrap_or_else(|err| {
                         fatal!(
                             log,
                             "Failed to open file {} for read: {}",
@@ -576,77 +702,82 @@ impl IncompleteState {
                         )
                     });
 
-                    let src_len = src
-                        .metadata()
-                        .unwrap_or_else(|err| {
-                            fatal!(
-                                log,
-                                "Failed to get metadata of file {}: {}",
-                                src_path.display(),
-                                err
-                            )
-                        })
-                        .len() as usize;
-                    let dst = std::fs::OpenOptions::new()
-                        .write(true)
-                        .create(false)
-                        .open(&dst_path)
-                        .unwrap_or_else(|err| {
-                            fatal!(log, "Failed to open file {}: {}", dst_path.display(), err)
-                        });
-
-                    let src_map = ScopedMmap::from_readonly_file(&src, src_len).unwrap_or_else(|err| {
-                        fatal!(log, "Failed to mmap file {}: {}", src_path.display(), err)
-                    });
+                    let open_dst = |dst_path: &Path| {
+                        std::fs::OpenOptions::new()
+                            .write(true)
+                            .create(false)
+                            .open(dst_path)
+                            .unwrap_or_else(|err| {
+                                fatal!(log, "Failed to open file {}: {}", dst_path.display(), err)
+                            })
+                    };
+                    // Dst files are opened once, starting with the one of the group.
+                    let mut dst_files: HashMap<DstIndex, std::fs::File> = HashMap::default();
+                    dst_files.insert(*dst_file_index, open_dst(&dst_path));
 
                     // Validate each chunk that we happen to have locally.  If the
-                    // validation passes, copy it to the corresponding location, otherwise
-                    // add the destination chunk id to the set of chunks to fetch.
-                    for (dst_chunk_index, src_chunk_index) in chunk_group {
-                        let dst_chunk = &manifest_new.chunk_table[*dst_chunk_index];
+                    // validation passes, copy it to the corresponding locations, otherwise
+                    // add the destination chunk ids to the set of chunks to fetch.
+                    for (src_chunk_index, dst_chunk_indices) in chunk_group {
                         let src_chunk = &manifest_old.chunk_table[*src_chunk_index];
                         let byte_range = src_chunk.byte_range();
 
-                        if src_map.len() < byte_range.end {
+                        if src.len() < byte_range.end {
                             warn!(
                                 log,
                                 "Local chunk {} ({}@{}—{}) is out of range (file len = {}), \
-                                 will request chunk {} instead",
+                                 will request chunks {:?} instead",
                                 *src_chunk_index,
                                 src_path.display(),
                                 byte_range.start,
                                 byte_range.end,
-                                src_map.len(),
-                                *dst_chunk_index + 1
+                                src.len(),
+                                dst_chunk_indices.iter().map(|i| i + 1).collect::<Vec<_>>()
                             );
-                            corrupted_chunks.lock().unwrap().push(*dst_chunk_index + 1);
+                            corrupted_chunks
+                                .lock()
+                                .unwrap()
+                                .extend(dst_chunk_indices.iter().map(|i| i + 1));
                             continue;
                         }
-                        #[cfg(not(target_os = "linux"))]
-                        let src_data = &src_map.as_slice()[byte_range];
                         if validate_data || ALWAYS_VALIDATE {
-                            #[cfg(target_os = "linux")]
-                            let src_data = &src_map.as_slice()[byte_range];
-
-                            if let Err(err) = crate::manifest::validate_chunk(
-                                *dst_chunk_index,
-                                src_data,
-                                manifest_new,
-                            ) {
+                            // All dst chunks have the same hash, so validating against the
+                            // first one is enough.
+                            let validation_result = src
+                                .with_bytes(byte_range, |bytes| {
+                                    crate::manifest::validate_chunk(
+                                        dst_chunk_indices[0],
+                                        bytes,
+                                        manifest_new,
+                                    )
+                                })
+                                .unwrap_or_else(|err| {
+                                    fatal!(
+                                        log,
+                                        "Failed to read chunk {} from file {}: {}",
+                                        *src_chunk_index,
+                                        src_path.display(),
+                                        err
+                                    )
+                                });
+                            if let Err(err) = validation_result {
                                 let byte_range = src_chunk.byte_range();
                                 warn!(
                                     log,
                                     "Local chunk {} ({}@{}–{}) doesn't pass validation: {}, \
-                                     will request chunk {} instead",
+                                     will request chunks {:?} instead",
                                     *src_chunk_index,
                                     src_path.display(),
                                     byte_range.start,
                                     byte_range.end,
                                     err,
-                                    *dst_chunk_index + 1
+                                    dst_chunk_indices.iter().map(|i| i + 1).collect::<Vec<_>>()
                                 );
 
-                                corrupted_chunks.lock().unwrap().push(*dst_chunk_index + 1);
+                                corrupted_chunks
+                                    .lock()
+                                    .unwrap()
+                                    .extend(dst_chunk_indices.iter().map(|i| i + 1));
                                 if !validate_data && ALWAYS_VALIDATE {
                                     error!(
                                         log,
@@ -663,18 +794,15 @@ impl IncompleteState {
                                 continue;
                             }
                         }
-                        #[cfg(target_os = "linux")]
-                        {
-                            let src_offset = src_chunk.offset as i64;
-                            let dst_offset = dst_chunk.offset as i64;
-
-                            ic_utils::fs::copy_file_range_all(
-                                &src,
-                                src_offset,
-                                &dst,
-                                dst_offset,
-                                dst_chunk.size_bytes as usize,
-                            )
+                        for dst_chunk_index in dst_chunk_indices {
+                            let dst_chunk = &manifest_new.chunk_table[*dst_chunk_index];
+                            let dst_path = root_new
+                                .join(&manifest_new.file_table[dst_chunk.file_index as usize].relative_path);
+                            let dst = dst_files
+                                .entry(dst_chunk.file_index as usize)
+                                .or_insert_with(|| open_dst(&dst_path));
+
+                            src.copy_to(src_chunk.byte_range(), dst, dst_chunk.offset)
                                 .unwrap_or_else(|err| {
                                     fatal!(
                                         log,
@@ -686,23 +814,8 @@ impl IncompleteState {
                                         err
                                     )
                                 });
+                            metrics.remaining.sub(1);
                         }
-
-                        #[cfg(not(target_os = "linux"))]
-                        {
-                            dst.write_all_at(src_data, dst_chunk.offset)
-                                .unwrap_or_else(|err| {
-                                    fatal!(
-                                        log,
-                                        "Failed to write chunk (offset = {}, size = {}) to file {}: {}",
-                                        dst_chunk.offset,
-                                        dst_chunk.size_bytes,
-                                        dst_path.display(),
-                                        err
-                                    )
-                                });
-                        }
-                        metrics.remaining.sub(1);
                     }
                 });
             }
@@ -939,7 +1052,7 @@ impl IncompleteState {
                         // StateSyncCacheEntry, so cloning the path is safe
                         root_old: cache_entry.path().to_path_buf(),
                         height_old: cache_entry.height,
-                        validate_data: false,
+                        validate_data: cache_entry.validate_data,
                     })
                 } else {
                     // This should be a special case that can only happen if the source of the
@@ -959,7 +1072,7 @@ impl IncompleteState {
                 missing_chunks: cache_entry.missing_chunks.clone(),
                 root_old: cache_entry.path().to_path_buf(),
                 height_old: cache_entry.height,
-                validate_data: false,
+                validate_data: cache_entry.validate_data,
             }),
             (None, Some((checkpoint_manifest, checkpoint_old))) => {
                 let checkpoint_height = checkpoint_old.height();
@@ -1100,6 +1213,7 @@ impl Chunkable for IncompleteState {
                 manifest: _,
                 state_sync_file_group: _,
                 ref fetch_chunks,
+                duplicate_chunks: _,
             } => {
                 #[allow(clippy::needless_collect)]
                 let ids: Vec<_> = fetch_chunks
@@ -1212,11 +1326,35 @@ impl Chunkable for IncompleteState {
                             //     2. `canister.pbuf` files are small so there will be only a handful of chunks after grouping.
                             fetch_chunks.insert(chunk_id as usize);
                         }
+                        let duplicate_chunks = if manifest.version >= STATE_SYNC_V3 {
+                            deduplicate_fetch_chunks(&manifest, &mut fetch_chunks)
+                        } else {
+                            Default::default()
+                        };
                         let num_fetch_chunks = fetch_chunks.len();
+                        self.chunk_bitmap = progress::ChunkBitmap::create(
+                            &self.root,
+                            &manifest,
+                            &missing_chunk_table_indices(
+                                &fetch_chunks,
+                                &state_sync_file_group,
+                                &duplicate_chunks,
+                            ),
+                        )
+                        .map_err(|err| {
+                            warn!(
+                                self.log,
+                                "Failed to persist the progress of state sync @{}: {}",
+                                self.height,
+                                err
+                            )
+                        })
+                        .ok();
                         self.state = DownloadState::Loading {
                             manifest,
                             state_sync_file_group,
                             fetch_chunks,
+                            duplicate_chunks,
                         };
                         self.fetch_started_at = Some(Instant::now());
                         info!(
@@ -1238,6 +1376,7 @@ impl Chunkable for IncompleteState {
                 ref manifest,
                 ref mut fetch_chunks,
                 ref state_sync_file_group,
+                ref duplicate_chunks,
             } => {
                 if artifact_chunk.chunk_id == MANIFEST_CHUNK {
                     // Have already seen the manifest chunk
@@ -1256,34 +1395,60 @@ impl Chunkable for IncompleteState {
                     return Err(ChunksMoreNeeded);
                 }
 
+                let chunk_table_indices = if ix < FILE_GROUP_CHUNK_ID_OFFSET as usize {
+                    vec![ix as u32 - 1]
+                } else {
+                    state_sync_file_group
+                        .get(&(ix as u32))
+                        .ok_or(ChunkVerificationFailed)?
+                        .clone()
+                };
+
+                // Starting from `STATE_SYNC_V3`, chunk payloads are encoded (compressed,
+                // unless that doesn't help). The decoded payload can't be larger than the
+                // chunks it contains. Payloads of older manifests are taken as is.
+                let decoded_payload;
+                let payload = if manifest.version >= STATE_SYNC_V3 {
+                    let max_size: usize = chunk_table_indices
+                        .iter()
+                        .map(|i| {
+                            manifest
+                                .chunk_table
+                                .get(*i as usize)
+                                .map_or(0, |chunk| chunk.size_bytes as usize)
+                        })
+                        .sum();
+                    decoded_payload = decode_chunk_payload(payload, max_size).map_err(|err| {
+                        warn!(self.log, "Failed to decode chunk {}: {}", ix, err);
+                        ChunkVerificationFailed
+                    })?;
+                    &decoded_payload
+                } else {
+                    payload
+                };
+
                 // Each index in `chunk_table_indices` is mapped to a piece of payload bytes
                 // with its corresponding start and end position.
-                let (chunk_table_indices, payload_pieces) =
-                    if ix < FILE_GROUP_CHUNK_ID_OFFSET as usize {
-                        // If it is a normal chunk, there is only one index mapped to the whole payload.
-                        (vec![ix as u32 - 1], vec![(0, payload.len())])
-                    } else {
-                        // If it is a file group chunk, divide it into pieces according to the `FileGroupChunks`.
-                        let chunk_table_indices = state_sync_file_group
-                            .get(&(ix as u32))
-                            .ok_or(ChunkVerificationFailed)?
-                            .clone();
-
-                        let mut cur_offset = 0;
-                        let mut payload_pieces: Vec<(usize, usize)> = Vec::new();
-                        for chunk_table_index in &chunk_table_indices {
-                            let chunk_size = manifest.chunk_table[*chunk_table_index as usize]
-                                .size_bytes as usize;
-                            payload_pieces.push((cur_offset, cur_offset + chunk_size));
-                            cur_offset += chunk_size;
-                        }
+                let payload_pieces = if ix < FILE_GROUP_CHUNK_ID_OFFSET as usize {
+                    // If it is a normal chunk, there is only one index mapped to the whole payload.
+                    vec![(0, payload.len())]
+                } else {
+                    // If it is a file group chunk, divide it into pieces according to the `FileGroupChunks`.
+                    let mut cur_offset = 0;
+                    let mut payload_pieces: Vec<(usize, usize)> = Vec::new();
+                    for chunk_table_index in &chunk_table_indices {
+                        let chunk_size =
+                            manifest.chunk_table[*chunk_table_index as usize].size_bytes as usize;
+                        payload_pieces.push((cur_offset, cur_offset + chunk_size));
+                        cur_offset += chunk_size;
+                    }
 
-                        if cur_offset != payload.len() {
-                            warn!(self.log, "Received invalid file group chunk {}", ix);
-                            return Err(ChunkVerificationFailed);
-                        }
-                        (chunk_table_indices, payload_pieces)
-                    };
+                    if cur_offset != payload.len() {
+                        warn!(self.log, "Received invalid file group chunk {}", ix);
+                        return Err(ChunkVerificationFailed);
+                    }
+                    payload_pieces
+                };
 
                 let log = &self.log;
                 let metrics = &self.metrics;
@@ -1320,6 +1485,45 @@ impl Chunkable for IncompleteState {
                         &payload[start..end],
                         manifest,
                     );
+                    // Chunks with the same hash have the same contents, so the
+                    // duplicates don't need to be validated separately.
+                    for duplicate in duplicate_chunks
+                        .get(&(*chunk_table_index as usize))
+                        .into_iter()
+                        .flatten()
+                    {
+                        Self::apply_chunk(
+                            &self.log,
+                            &self.metrics.state_sync_metrics,
+                            &self.root,
+                            *duplicate,
+                            &payload[start..end],
+                            manifest,
+                        );
+                    }
+                }
+
+                if let Some(chunk_bitmap) = self.chunk_bitmap.as_mut() {
+                    let written_chunks = chunk_table_indices.iter().flat_map(|ix| {
+                        std::iter::once(*ix as usize).chain(
+                            duplicate_chunks
+                                .get(&(*ix as usize))
+                                .into_iter()
+                                .flatten()
+                                .copied(),
+                        )
+                    });
+                    if let Err(err) = written_chunks
+                        .map(|ix| chunk_bitmap.mark_present(ix))
+                        .collect::<std::io::Result<()>>()
+                    {
+                        // Not recording a chunk only means that it will be fetched again
+                        // if the replica restarts.
+                        warn!(
+                            self.log,
+                            "Failed to record chunk {} of state sync @{}: {}", ix, self.height, err
+                        );
+                    }
                 }
 
                 fetch_chunks.remove(&ix);
@@ -1346,6 +1550,16 @@ impl Chunkable for IncompleteState {
                         )
                     }
 
+                    self.chunk_bitmap = None;
+                    if let Err(err) = progress::remove(&self.root) {
+                        fatal!(
+                            self.log,
+                            "Failed to remove the progress of state sync @{}: {}",
+                            self.height,
+                            err
+                        );
+                    }
+
                     Self::make_checkpoint(
                         &self.log,
                         &self.metrics,
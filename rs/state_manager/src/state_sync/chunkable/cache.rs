@@ -36,6 +36,10 @@ pub struct StateSyncCacheEntry {
     pub height: Height,
     path: PathBuf,
     pub missing_chunks: HashSet<usize>,
+    /// True if the chunks on disk have to be validated before they are reused.
+    /// This is the case for entries restored after a restart, as the files
+    /// might not have been synced to disk.
+    pub validate_data: bool,
     log: ReplicaLogger,
 }
 
@@ -63,6 +67,117 @@ impl StateSyncCache {
         }
     }
 
+    /// Creates a cache from the unfinished state syncs that were persisted
+    /// under `state_layout` before a restart.
+    ///
+    /// The newest state sync that is above the latest checkpoint and whose
+    /// progress can be read becomes the cached entry, all the others are
+    /// deleted.
+    pub fn load(log: ReplicaLogger, state_layout: &StateLayout) -> Self {
+        let mut cache = Self::new(log);
+        let latest_checkpoint = state_layout
+            .checkpoint_heights()
+            .ok()
+            .and_then(|heights| heights.last().copied());
+
+        let dir = state_layout.state_sync_dir();
+        let mut syncs: Vec<(Height, PathBuf)> = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter_map(|path| {
+                    let name = path.file_name()?.to_str()?;
+                    let hex = name
+                        .strip_prefix("scratchpad_")
+                        .or_else(|| name.strip_prefix("cache_"));
+                    match hex.and_then(|hex| u64::from_str_radix(hex, 16).ok()) {
+                        Some(height) => Some((Height::new(height), path)),
+                        None => {
+                            warn!(
+                                cache.log,
+                                "Unexpected entry {} in the state sync directory. Deleting.",
+                                path.display()
+                            );
+                            delete_folder(&cache.log, &path);
+                            None
+                        }
+                    }
+                })
+                .collect(),
+            Err(err) => {
+                warn!(
+                    cache.log,
+                    "Failed to list state syncs at {}: {}",
+                    dir.display(),
+                    err
+                );
+                return cache;
+            }
+        };
+        syncs.sort_by(|(h1, _), (h2, _)| h2.cmp(h1));
+
+        for (height, path) in syncs {
+            if cache.entry.is_none() && latest_checkpoint.map_or(true, |h| h < height) {
+                if let Some(entry) = cache.restore_entry(state_layout, height, &path) {
+                    cache.entry = Some(Arc::new(entry));
+                    continue;
+                }
+            }
+            delete_folder(&cache.log, &path);
+        }
+        cache
+    }
+
+    /// Turns the state sync data persisted at `path` into a cache entry.
+    fn restore_entry(
+        &self,
+        state_layout: &StateLayout,
+        height: Height,
+        path: &Path,
+    ) -> Option<StateSyncCacheEntry> {
+        let (manifest, missing_chunks) = match progress::load(path) {
+            Ok(progress) => progress,
+            Err(err) => {
+                warn!(
+                    self.log,
+                    "Failed to load the progress of state sync @{} at {}: {}",
+                    height,
+                    path.display(),
+                    err
+                );
+                return None;
+            }
+        };
+        let cache_root = state_layout
+            .state_sync_cache(height)
+            .expect("failed to create directory for state sync cache");
+        if cache_root != path {
+            if let Err(err) = std::fs::rename(path, &cache_root) {
+                warn!(
+                    self.log,
+                    "Failed to create state sync cache at {}: {}",
+                    cache_root.display(),
+                    err
+                );
+                return None;
+            }
+        }
+        info!(
+            self.log,
+            "Restored state sync @{} with {} of {} chunks missing",
+            height,
+            missing_chunks.len(),
+            manifest.chunk_table.len()
+        );
+        Some(StateSyncCacheEntry {
+            manifest,
+            height,
+            path: cache_root,
+            missing_chunks,
+            validate_data: true,
+            log: self.log.clone(),
+        })
+    }
+
     /// Returns a reference to the cached entry if there is one available.
     pub fn get(&self) -> Option<Arc<StateSyncCacheEntry>> {
         self.entry.as_ref().map(Arc::clone)
@@ -96,25 +211,13 @@ impl StateSyncCache {
         manifest: Manifest,
         fetch_chunks: HashSet<usize>,
         state_sync_file_group: FileGroupChunks,
+        duplicate_chunks: HashMap<usize, Vec<usize>>,
     ) {
         // fetch_chunks, as stored by IncompleteState considers the manifest as chunk 0
         // For the cache we store indices into the manifest's chunk table as
         // missing_chunks.
-        debug_assert!(!fetch_chunks.contains(&0));
-        let mut missing_chunks: HashSet<usize> = Default::default();
-        for i in fetch_chunks.into_iter() {
-            assert_ne!(0, i);
-            if i < FILE_GROUP_CHUNK_ID_OFFSET as usize {
-                missing_chunks.insert(i - 1);
-            } else {
-                // If it's a chunk group, the individual chunks are missing in the manifest,
-                // not the group
-                let chunks = state_sync_file_group
-                    .get(&(i as u32))
-                    .expect("Unknown chunk group");
-                missing_chunks.extend(chunks.iter().map(|i| *i as usize));
-            }
-        }
+        let missing_chunks =
+            missing_chunk_table_indices(&fetch_chunks, &state_sync_file_group, &duplicate_chunks);
 
         debug_assert!(missing_chunks
             .iter()
@@ -147,6 +250,7 @@ impl StateSyncCache {
             height: sync.height,
             path: cache_root,
             missing_chunks,
+            validate_data: false,
             log: self.log.clone(),
         };
         self.entry = Some(Arc::new(entry));
@@ -184,12 +288,19 @@ impl StateSyncCache {
                 manifest,
                 state_sync_file_group,
                 fetch_chunks,
+                duplicate_chunks,
             } => {
                 if self.entry.is_some() {
                     // The current cache is newer
                     delete_folder(&self.log, &sync.root);
                 } else {
-                    self.push_inner(sync, manifest, fetch_chunks, state_sync_file_group);
+                    self.push_inner(
+                        sync,
+                        manifest,
+                        fetch_chunks,
+                        state_sync_file_group,
+                        duplicate_chunks,
+                    );
                 }
             }
             DownloadState::Complete(_) | DownloadState::Blank => {
@@ -3,6 +3,7 @@ use ic_metrics::MetricsRegistry;
 use ic_replicated_state::page_map::TestPageAllocatorFileDescriptorImpl;
 use ic_test_utilities_logger::with_test_replica_logger;
 use ic_types::crypto::CryptoHash;
+use ic_types::state_sync::ChunkInfo;
 use tempfile::TempDir;
 
 const NUM_THREADS: u32 = 3;
@@ -52,6 +53,7 @@ fn fake_loading(seed: u32) -> (DownloadState, Manifest, HashSet<usize>, FileGrou
         manifest: manifest.clone(),
         state_sync_file_group: state_sync_file_group.clone(),
         fetch_chunks: fetch_chunks.clone(),
+        duplicate_chunks: Default::default(),
     };
     (state, manifest, fetch_chunks, state_sync_file_group)
 }
@@ -120,6 +122,7 @@ fn incomplete_state_for_tests(
         ref manifest,
         state_sync_file_group: _,
         fetch_chunks: _,
+        duplicate_chunks: _,
     } = &result.state
     {
         std::fs::create_dir(&result.root).unwrap();
@@ -349,3 +352,58 @@ fn existing_folder() {
         assert!(env.cache.read().get().is_none());
     })
 }
+
+// State syncs persisted before a restart are restored as cache entries, the
+// newest one wins and everything else in the state sync directory is deleted.
+#[test]
+fn restored_sync() {
+    with_test_replica_logger(|log| {
+        let env = TestEnvironment::new(log.clone());
+        let manifest = Manifest::new(
+            STATE_SYNC_V3,
+            vec![],
+            vec![
+                ChunkInfo {
+                    file_index: 0,
+                    size_bytes: 0,
+                    offset: 0,
+                    hash: [0; 32],
+                };
+                3
+            ],
+        );
+
+        let older_sync = env.state_layout.state_sync_cache(Height::new(3)).unwrap();
+        std::fs::create_dir(&older_sync).unwrap();
+        progress::ChunkBitmap::create(&older_sync, &manifest, &Default::default()).unwrap();
+
+        let newer_sync = env
+            .state_layout
+            .state_sync_scratchpad(Height::new(5))
+            .unwrap();
+        std::fs::create_dir(&newer_sync).unwrap();
+        progress::ChunkBitmap::create(&newer_sync, &manifest, &maplit::hashset! {1}).unwrap();
+
+        // A scratchpad without progress can't be restored.
+        let broken_sync = env
+            .state_layout
+            .state_sync_scratchpad(Height::new(7))
+            .unwrap();
+        std::fs::create_dir(&broken_sync).unwrap();
+
+        let cache = StateSyncCache::load(log, &env.state_layout);
+        let entry = cache.get().expect("state sync was not restored");
+        assert_eq!(entry.height, Height::new(5));
+        assert_eq!(entry.manifest, manifest);
+        assert_eq!(entry.missing_chunks, maplit::hashset! {1});
+        assert!(entry.validate_data);
+        assert_eq!(
+            entry.path(),
+            env.state_layout.state_sync_cache(Height::new(5)).unwrap()
+        );
+
+        assert!(!older_sync.exists());
+        assert!(!newer_sync.exists());
+        assert!(!broken_sync.exists());
+    })
+}
@@ -0,0 +1,146 @@
+//! Persists the progress of a state sync next to its scratchpad, so that the
+//! chunks fetched so far can be reused after the replica restarts.
+//!
+//! The progress consists of the manifest of the state being synced and a
+//! bitmap with one bit per entry of the manifest's chunk table. A set bit means
+//! that the chunk has been written to the scratchpad. The bitmap is not synced
+//! to disk after every chunk, so a restored scratchpad has to be validated
+//! against the manifest before its chunks are reused.
+
+use ic_types::state_sync::{decode_manifest, encode_manifest, Manifest};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Write};
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Name of the file storing the manifest of the synced state.
+const MANIFEST_FILE: &str = "state_sync_manifest.pbuf";
+
+/// Name of the file storing the bitmap of written chunks.
+const CHUNK_BITMAP_FILE: &str = "state_sync_chunks.bitmap";
+
+/// A file-backed bitmap of the chunks that are present in a scratchpad.
+pub(crate) struct ChunkBitmap {
+    file: File,
+    bits: Vec<u8>,
+}
+
+impl ChunkBitmap {
+    /// Persists `manifest` in `root` together with a bitmap in which all
+    /// chunks except `missing_chunks` are marked as present.
+    pub(crate) fn create(
+        root: &Path,
+        manifest: &Manifest,
+        missing_chunks: &HashSet<usize>,
+    ) -> std::io::Result<Self> {
+        let mut manifest_file = File::create(root.join(MANIFEST_FILE))?;
+        manifest_file.write_all(&encode_manifest(manifest))?;
+        manifest_file.sync_all()?;
+
+        let num_chunks = manifest.chunk_table.len();
+        let mut bits = vec![0; (num_chunks + 7) / 8];
+        for ix in (0..num_chunks).filter(|ix| !missing_chunks.contains(ix)) {
+            bits[ix / 8] |= 1 << (ix % 8);
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(root.join(CHUNK_BITMAP_FILE))?;
+        file.write_all_at(&bits, 0)?;
+        file.sync_all()?;
+
+        Ok(Self { file, bits })
+    }
+
+    /// Marks the chunk with the given chunk table index as present.
+    pub(crate) fn mark_present(&mut self, ix: usize) -> std::io::Result<()> {
+        let byte = ix / 8;
+        self.bits[byte] |= 1 << (ix % 8);
+        self.file.write_all_at(&self.bits[byte..=byte], byte as u64)
+    }
+}
+
+/// Loads the manifest and the chunk table indices of the chunks that are
+/// missing from the scratchpad at `root`.
+pub(crate) fn load(root: &Path) -> std::io::Result<(Manifest, HashSet<usize>)> {
+    let manifest = decode_manifest(&std::fs::read(root.join(MANIFEST_FILE))?)
+        .map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+    let bits = std::fs::read(root.join(CHUNK_BITMAP_FILE))?;
+
+    let num_chunks = manifest.chunk_table.len();
+    if bits.len() != (num_chunks + 7) / 8 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "chunk bitmap has {} bytes, expected {} for {} chunks",
+                bits.len(),
+                (num_chunks + 7) / 8,
+                num_chunks
+            ),
+        ));
+    }
+    let missing_chunks = (0..num_chunks)
+        .filter(|ix| bits[ix / 8] & (1 << (ix % 8)) == 0)
+        .collect();
+
+    Ok((manifest, missing_chunks))
+}
+
+/// Removes the progress files from the scratchpad at `root`, so that they
+/// don't end up in the checkpoint.
+pub(crate) fn remove(root: &Path) -> std::io::Result<()> {
+    for name in [MANIFEST_FILE, CHUNK_BITMAP_FILE] {
+        match std::fs::remove_file(root.join(name)) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::CURRENT_STATE_SYNC_VERSION;
+    use ic_types::state_sync::{ChunkInfo, FileInfo};
+
+    fn manifest_with_chunks(num_chunks: usize) -> Manifest {
+        let file_table = vec![FileInfo {
+            relative_path: "file".into(),
+            size_bytes: num_chunks as u64,
+            hash: [0; 32],
+        }];
+        let chunk_table = (0..num_chunks)
+            .map(|ix| ChunkInfo {
+                file_index: 0,
+                size_bytes: 1,
+                offset: ix as u64,
+                hash: [ix as u8; 32],
+            })
+            .collect();
+        Manifest::new(CURRENT_STATE_SYNC_VERSION, file_table, chunk_table)
+    }
+
+    #[test]
+    fn chunk_bitmap_roundtrip() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let manifest = manifest_with_chunks(11);
+
+        let mut bitmap =
+            ChunkBitmap::create(tmp.path(), &manifest, &maplit::hashset! {0, 3, 8, 10}).unwrap();
+        bitmap.mark_present(8).unwrap();
+        drop(bitmap);
+
+        let (loaded_manifest, missing_chunks) = load(tmp.path()).unwrap();
+        assert_eq!(loaded_manifest, manifest);
+        assert_eq!(missing_chunks, maplit::hashset! {0, 3, 10});
+
+        remove(tmp.path()).unwrap();
+        assert!(load(tmp.path()).is_err());
+        // Removing twice is fine.
+        remove(tmp.path()).unwrap();
+    }
+}
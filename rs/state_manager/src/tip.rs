@@ -1,17 +1,20 @@
 use crate::{CheckpointError, PageMapType, StateManagerMetrics, NUMBER_OF_CHECKPOINT_THREADS};
 use crossbeam_channel::{unbounded, Sender};
+use ic_config::flag_status::FlagStatus;
 use ic_logger::{fatal, ReplicaLogger};
 #[allow(unused)]
 use ic_replicated_state::{
-    canister_state::execution_state::SandboxMemory, BitcoinState, CanisterState, NumWasmPages,
-    PageMap, ReplicatedState,
+    canister_state::execution_state::SandboxMemory, BitcoinState, CanisterSnapshot, CanisterState,
+    NumWasmPages, PageMap, ReplicatedState, SnapshotId,
 };
 use ic_state_layout::{
-    error::LayoutError, BitcoinStateBits, BitcoinStateLayout, CanisterStateBits, CheckpointLayout,
-    ExecutionStateBits, ReadOnly, RwPolicy, StateLayout, TipHandler,
+    error::LayoutError, BitcoinStateBits, BitcoinStateLayout, CanisterSnapshotBits,
+    CanisterStateBits, CheckpointLayout, ExecutionStateBits, ReadOnly, RwPolicy, StateLayout,
+    TipHandler,
 };
-use ic_types::{CanisterId, Height};
+use ic_types::{CanisterId, ExecutionRound, Height};
 use ic_utils::fs::defrag_file_partially;
+use ic_utils::layered_file;
 use ic_utils::thread::parallel_map;
 use ic_utils::thread::JoinOnDrop;
 use prometheus::HistogramTimer;
@@ -24,6 +27,9 @@ use std::path::{Path, PathBuf};
 
 const DEFRAG_SIZE: u64 = 1 << 29; // 500 MB
 const DEFRAG_SAMPLE: usize = 100;
+/// Maximum number of overlays stacked on top of a PageMap file in the tip
+/// before they are compacted.
+const MAX_OVERLAY_DEPTH: usize = 8;
 
 /// Request for the Tip directory handling thread.
 pub enum TipRequest {
@@ -38,6 +44,11 @@ pub enum TipRequest {
         height: Height,
         ids: BTreeSet<CanisterId>,
     },
+    /// Filter canister snapshots in tip. Remove ones not present in the set.
+    FilterTipSnapshots {
+        height: Height,
+        ids: BTreeSet<SnapshotId>,
+    },
     /// Truncate PageMaps's path.
     TruncatePageMapsPath {
         height: Height,
@@ -63,6 +74,17 @@ pub enum TipRequest {
         height: Height,
         page_map_types: Vec<PageMapType>,
     },
+    /// Compact the overlay files of the given PageMaps in the tip.
+    CompactTip {
+        height: Height,
+        page_map_types: Vec<PageMapType>,
+    },
+    /// Move the checkpoint at the given height to the diverged checkpoints.
+    /// Return the result into the sender.
+    MarkCheckpointDiverged {
+        height: Height,
+        sender: Sender<Result<(), LayoutError>>,
+    },
     Wait {
         sender: Sender<()>,
     },
@@ -96,6 +118,7 @@ pub fn spawn_tip_thread(
     mut tip_handler: TipHandler,
     state_layout: StateLayout,
     metrics: StateManagerMetrics,
+    incremental_checkpoints: FlagStatus,
 ) -> (JoinOnDrop<()>, Sender<TipRequest>) {
     let (tip_sender, tip_receiver) = unbounded();
     let mut thread_pool = scoped_threadpool::Pool::new(NUMBER_OF_CHECKPOINT_THREADS);
@@ -118,6 +141,19 @@ pub fn spawn_tip_thread(
                                     )
                                 });
                         }
+                        TipRequest::FilterTipSnapshots { height, ids } => {
+                            let _timer = request_timer(&metrics, "filter_tip_snapshots");
+                            tip_handler
+                                .filter_tip_snapshots(height, &ids)
+                                .unwrap_or_else(|err| {
+                                    fatal!(
+                                        log,
+                                        "Failed to filter tip snapshots for height @{}: {}",
+                                        height,
+                                        err
+                                    )
+                                });
+                        }
                         TipRequest::TipToCheckpoint { height, sender } => {
                             let cp = {
                                 let _timer =
@@ -209,6 +245,7 @@ pub fn spawn_tip_thread(
                                     );
                                 }),
                                 &mut thread_pool,
+                                incremental_checkpoints,
                             )
                             .unwrap_or_else(|err| {
                                 fatal!(log, "Failed to serialize to tip @{}: {}", height, err);
@@ -249,6 +286,34 @@ pub fn spawn_tip_thread(
                                 fatal!(log, "Failed to defrag tip @{}: {}", height, err);
                             });
                         }
+                        TipRequest::CompactTip {
+                            height,
+                            page_map_types,
+                        } => {
+                            let _timer = request_timer(&metrics, "compact_tip");
+                            compact_tip(
+                                &tip_handler.tip(height).unwrap_or_else(|err| {
+                                    fatal!(
+                                        log,
+                                        "Failed to get tip @{} to compact: {}",
+                                        height,
+                                        err
+                                    );
+                                }),
+                                &page_map_types,
+                                incremental_checkpoints,
+                            )
+                            .unwrap_or_else(|err| {
+                                fatal!(log, "Failed to compact tip @{}: {}", height, err);
+                            });
+                        }
+
+                        TipRequest::MarkCheckpointDiverged { height, sender } => {
+                            let _timer = request_timer(&metrics, "mark_checkpoint_diverged");
+                            sender
+                                .send(state_layout.mark_checkpoint_diverged(height))
+                                .expect("Failed to return MarkCheckpointDiverged result");
+                        }
 
                         TipRequest::Wait { sender } => {
                             let _timer = request_timer(&metrics, "wait");
@@ -267,6 +332,7 @@ fn serialize_to_tip(
     state: &ReplicatedState,
     tip: &CheckpointLayout<RwPolicy<TipHandler>>,
     thread_pool: &mut scoped_threadpool::Pool,
+    incremental_checkpoints: FlagStatus,
 ) -> Result<(), CheckpointError> {
     tip.system_metadata()
         .serialize(state.system_metadata().into())?;
@@ -275,28 +341,113 @@ fn serialize_to_tip(
         .serialize((state.subnet_queues()).into())?;
 
     let results = parallel_map(thread_pool, state.canisters_iter(), |canister_state| {
-        serialize_canister_to_tip(log, canister_state, tip)
+        serialize_canister_to_tip(log, canister_state, tip, incremental_checkpoints)
     });
 
     for result in results.into_iter() {
         result?;
     }
 
-    serialize_bitcoin_state_to_tip(state.bitcoin(), &tip.bitcoin()?)?;
+    for (snapshot_id, snapshot) in state.canister_snapshots.iter() {
+        serialize_snapshot_to_tip(log, snapshot_id, snapshot, tip)?;
+    }
+
+    serialize_bitcoin_state_to_tip(
+        log,
+        state.bitcoin(),
+        &tip.bitcoin()?,
+        tip.height(),
+        incremental_checkpoints,
+    )?;
 
     Ok(())
 }
 
+/// Snapshots are immutable, so a snapshot only needs to be written once. Tip
+/// is reset to the latest checkpoint after every checkpoint, so snapshots that
+/// are already part of a checkpoint are found in tip and skipped.
+fn serialize_snapshot_to_tip(
+    log: &ReplicaLogger,
+    snapshot_id: &SnapshotId,
+    snapshot: &CanisterSnapshot,
+    tip: &CheckpointLayout<RwPolicy<TipHandler>>,
+) -> Result<(), CheckpointError> {
+    let snapshot_layout = tip.snapshot(snapshot_id)?;
+    // The protobuf file is written last, so its presence means that the
+    // snapshot has been completely written.
+    if snapshot_layout.snapshot().raw_path().exists() {
+        return Ok(());
+    }
+
+    match snapshot.wasm_binary.binary.file() {
+        Some(path) => {
+            let wasm = snapshot_layout.wasm();
+            ic_state_layout::utils::do_copy(log, path, wasm.raw_path()).map_err(|io_err| {
+                CheckpointError::IoError {
+                    path: path.to_path_buf(),
+                    message: "failed to copy Wasm file".to_string(),
+                    io_err: io_err.to_string(),
+                }
+            })?;
+        }
+        None => {
+            snapshot_layout
+                .wasm()
+                .serialize(&snapshot.wasm_binary.binary)?;
+        }
+    }
+    snapshot
+        .wasm_memory
+        .page_map
+        .persist_all(&snapshot_layout.vmemory_0())?;
+    snapshot
+        .stable_memory
+        .page_map
+        .persist_all(&snapshot_layout.stable_memory_blob())?;
+
+    snapshot_layout
+        .snapshot()
+        .serialize(
+            CanisterSnapshotBits {
+                canister_id: snapshot.canister_id(),
+                taken_at_timestamp: snapshot.taken_at_timestamp(),
+                canister_version: snapshot.canister_version(),
+                certified_data: snapshot.certified_data().clone(),
+                execution_state_bits: ExecutionStateBits {
+                    exported_globals: snapshot.exported_globals.clone(),
+                    heap_size: snapshot.wasm_memory.size,
+                    exports: snapshot.exports.clone(),
+                    last_executed_round: ExecutionRound::from(0),
+                    metadata: snapshot.metadata.clone(),
+                    binary_hash: Some(snapshot.wasm_binary.binary.module_hash().into()),
+                },
+                stable_memory_size: snapshot.stable_memory.size,
+            }
+            .into(),
+        )
+        .map_err(CheckpointError::from)
+}
+
 fn serialize_canister_to_tip(
     log: &ReplicaLogger,
     canister_state: &CanisterState,
     tip: &CheckpointLayout<RwPolicy<TipHandler>>,
+    incremental_checkpoints: FlagStatus,
 ) -> Result<(), CheckpointError> {
     let canister_layout = tip.canister(&canister_state.canister_id())?;
     canister_layout
         .queues()
         .serialize(canister_state.system_state.queues().into())?;
 
+    let wasm_chunk_store = &canister_state.system_state.wasm_chunk_store;
+    if wasm_chunk_store.is_empty() {
+        canister_layout.wasm_chunk_store().delete_file()?;
+    } else {
+        canister_layout
+            .wasm_chunk_store()
+            .serialize(wasm_chunk_store.into())?;
+    }
+
     let execution_state_bits = match &canister_state.execution_state {
         Some(execution_state) => {
             let wasm_binary = &execution_state.wasm_binary.binary;
@@ -320,14 +471,20 @@ fn serialize_canister_to_tip(
                         .serialize(&execution_state.wasm_binary.binary)?;
                 }
             }
-            execution_state
-                .wasm_memory
-                .page_map
-                .persist_delta(&canister_layout.vmemory_0())?;
-            execution_state
-                .stable_memory
-                .page_map
-                .persist_delta(&canister_layout.stable_memory_blob())?;
+            persist_page_map(
+                log,
+                &execution_state.wasm_memory.page_map,
+                &canister_layout.vmemory_0(),
+                tip.height(),
+                incremental_checkpoints,
+            )?;
+            persist_page_map(
+                log,
+                &execution_state.stable_memory.page_map,
+                &canister_layout.stable_memory_blob(),
+                tip.height(),
+                incremental_checkpoints,
+            )?;
 
             Some(ExecutionStateBits {
                 exported_globals: execution_state.exported_globals.clone(),
@@ -402,6 +559,17 @@ fn serialize_canister_to_tip(
                     .global_timer
                     .to_nanos_since_unix_epoch(),
                 canister_version: canister_state.system_state.canister_version,
+                canister_log: canister_state.system_state.canister_log.clone(),
+                log_visibility: canister_state.system_state.log_visibility,
+                wasm_memory_limit: canister_state.system_state.wasm_memory_limit,
+                canister_history: canister_state.system_state.get_canister_history().clone(),
+                wasm_memory_threshold: canister_state.system_state.wasm_memory_threshold,
+                on_low_wasm_memory_hook_executed: canister_state
+                    .system_state
+                    .on_low_wasm_memory_hook_executed,
+                reserved_balance: canister_state.system_state.reserved_balance(),
+                reserved_balance_limit: canister_state.system_state.reserved_balance_limit,
+                total_query_stats: canister_state.scheduler_state.total_query_stats.clone(),
             }
             .into(),
         )
@@ -409,23 +577,35 @@ fn serialize_canister_to_tip(
 }
 
 fn serialize_bitcoin_state_to_tip(
+    log: &ReplicaLogger,
     state: &BitcoinState,
     layout: &BitcoinStateLayout<RwPolicy<TipHandler>>,
+    height: Height,
+    incremental_checkpoints: FlagStatus,
 ) -> Result<(), CheckpointError> {
-    state
-        .utxo_set
-        .utxos_small
-        .persist_delta(&layout.utxos_small())?;
-
-    state
-        .utxo_set
-        .utxos_medium
-        .persist_delta(&layout.utxos_medium())?;
-
-    state
-        .utxo_set
-        .address_outpoints
-        .persist_delta(&layout.address_outpoints())?;
+    persist_page_map(
+        log,
+        &state.utxo_set.utxos_small,
+        &layout.utxos_small(),
+        height,
+        incremental_checkpoints,
+    )?;
+
+    persist_page_map(
+        log,
+        &state.utxo_set.utxos_medium,
+        &layout.utxos_medium(),
+        height,
+        incremental_checkpoints,
+    )?;
+
+    persist_page_map(
+        log,
+        &state.utxo_set.address_outpoints,
+        &layout.address_outpoints(),
+        height,
+        incremental_checkpoints,
+    )?;
 
     layout
         .bitcoin_state()
@@ -508,7 +688,103 @@ pub fn defrag_tip(
     Ok(())
 }
 
+/// Persists the delta of `page_map` to the file at `path` in the tip.
+///
+/// With incremental checkpoints enabled, the delta is written to a new
+/// overlay file, unless the PageMap does not originate from a checkpoint, in
+/// which case the file is rewritten from scratch. Otherwise the delta is
+/// written in place; any overlays in the tip have already been merged into
+/// the file by `compact_tip`.
+fn persist_page_map(
+    log: &ReplicaLogger,
+    page_map: &PageMap,
+    path: &Path,
+    height: Height,
+    incremental_checkpoints: FlagStatus,
+) -> Result<(), CheckpointError> {
+    match incremental_checkpoints {
+        FlagStatus::Enabled if page_map.base_height.is_some() => {
+            // The overlays are only discovered next to an existing base file.
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .open(path)
+                .map_err(|err| io_err(path, "failed to create base file", err))?;
+            page_map.persist_delta_to_overlay(path, height)?;
+        }
+        FlagStatus::Enabled => {
+            // The PageMap was created or wiped since the last checkpoint, so
+            // neither the base file nor its overlays are relevant anymore.
+            truncate_path(log, path);
+            page_map.persist_delta(path)?;
+        }
+        FlagStatus::Disabled => {
+            page_map.persist_delta(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Compacts the overlays of the given PageMaps in the tip.
+///
+/// With incremental checkpoints disabled, all overlays are merged into their
+/// base files. Otherwise, once a file has more than `MAX_OVERLAY_DEPTH`
+/// overlays, they are combined into a single overlay, or merged into the base
+/// file if they cover at least half of it.
+fn compact_tip(
+    tip: &CheckpointLayout<RwPolicy<TipHandler>>,
+    page_map_types: &[PageMapType],
+    incremental_checkpoints: FlagStatus,
+) -> Result<(), CheckpointError> {
+    for page_map_type in page_map_types {
+        let path = page_map_type.path(tip)?;
+        let overlays = layered_file::overlay_paths(&path)
+            .map_err(|err| io_err(&path, "failed to list overlays", err))?;
+        if overlays.is_empty() {
+            continue;
+        }
+        if incremental_checkpoints == FlagStatus::Disabled {
+            layered_file::merge_into_base(&path)
+                .map_err(|err| io_err(&path, "failed to merge overlays", err))?;
+            continue;
+        }
+        if overlays.len() <= MAX_OVERLAY_DEPTH {
+            continue;
+        }
+        let overlays_size = overlays
+            .iter()
+            .map(|overlay| overlay.metadata().map(|metadata| metadata.len()))
+            .sum::<std::io::Result<u64>>()
+            .map_err(|err| io_err(&path, "failed to get overlay size", err))?;
+        let base_size = path.metadata().map_or(0, |metadata| metadata.len());
+        if overlays_size.saturating_mul(2) >= base_size {
+            layered_file::merge_into_base(&path)
+                .map_err(|err| io_err(&path, "failed to merge overlays", err))?;
+        } else {
+            layered_file::merge_overlays(&path)
+                .map_err(|err| io_err(&path, "failed to merge overlays", err))?;
+        }
+    }
+    Ok(())
+}
+
+fn io_err(path: &Path, message: &str, err: std::io::Error) -> CheckpointError {
+    CheckpointError::IoError {
+        path: path.to_path_buf(),
+        message: message.to_string(),
+        io_err: err.to_string(),
+    }
+}
+
 fn truncate_path(log: &ReplicaLogger, path: &Path) {
+    if let Err(err) = layered_file::remove_overlays(path) {
+        fatal!(
+            log,
+            "failed to remove overlays of page map stored at {}: {}",
+            path.display(),
+            err
+        )
+    }
     if let Err(err) = nix::unistd::truncate(path, 0) {
         // It's OK if the file doesn't exist, everything else is a fatal error.
         if err != nix::errno::Errno::ENOENT {
@@ -540,7 +816,8 @@ mod test {
             let metrics_registry = ic_metrics::MetricsRegistry::new();
             let metrics = StateManagerMetrics::new(&metrics_registry);
             let tip_handler = layout.capture_tip_handler();
-            let (_h, _s) = spawn_tip_thread(log, tip_handler, layout, metrics);
+            let (_h, _s) =
+                spawn_tip_thread(log, tip_handler, layout, metrics, FlagStatus::Disabled);
         });
     }
 
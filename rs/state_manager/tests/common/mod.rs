@@ -1,6 +1,6 @@
 use assert_matches::assert_matches;
 use ic_base_types::NumSeconds;
-use ic_config::state_manager::Config;
+use ic_config::{flag_status::FlagStatus, state_manager::Config};
 use ic_interfaces::{
     certification::{CertificationPermanentError, Verifier, VerifierError},
     validation::ValidationResult,
@@ -484,10 +484,12 @@ fn state_manager_test_with_state_sync_and_verifier_result<
     F: FnOnce(&MetricsRegistry, Arc<StateManagerImpl>, StateSync),
 >(
     should_pass_verification: bool,
+    incremental_checkpoints: FlagStatus,
     f: F,
 ) {
     let tmp = tmpdir("sm");
-    let config = Config::new(tmp.path().into());
+    let config =
+        Config::new(tmp.path().into()).with_incremental_checkpoints(incremental_checkpoints);
     let metrics_registry = MetricsRegistry::new();
     let own_subnet = subnet_test_id(42);
     let verifier: Arc<dyn Verifier> = if should_pass_verification {
@@ -520,7 +522,15 @@ pub fn state_manager_test_with_state_sync<
 >(
     f: F,
 ) {
-    state_manager_test_with_state_sync_and_verifier_result(true, f)
+    state_manager_test_with_state_sync_and_verifier_result(true, FlagStatus::Disabled, f)
+}
+
+pub fn state_manager_test_with_incremental_checkpoints_and_state_sync<
+    F: FnOnce(&MetricsRegistry, Arc<StateManagerImpl>, StateSync),
+>(
+    f: F,
+) {
+    state_manager_test_with_state_sync_and_verifier_result(true, FlagStatus::Enabled, f)
 }
 
 pub fn state_manager_restart_test_deleting_metadata<Test>(test: Test)
@@ -608,6 +618,56 @@ where
     });
 }
 
+pub fn state_manager_restart_test_with_state_sync<Test>(test: Test)
+where
+    Test: FnOnce(
+        &MetricsRegistry,
+        Arc<StateManagerImpl>,
+        StateSync,
+        Box<
+            dyn Fn(
+                Arc<StateManagerImpl>,
+                StateSync,
+            ) -> (MetricsRegistry, Arc<StateManagerImpl>, StateSync),
+        >,
+    ),
+{
+    let tmp = tmpdir("sm");
+    let config = Config::new(tmp.path().into());
+    let own_subnet = subnet_test_id(42);
+    let verifier: Arc<dyn Verifier> = Arc::new(FakeVerifier::new());
+
+    with_test_replica_logger(|log| {
+        let make_state_manager = move || {
+            let metrics_registry = MetricsRegistry::new();
+
+            let state_manager = Arc::new(StateManagerImpl::new(
+                Arc::clone(&verifier),
+                own_subnet,
+                SubnetType::Application,
+                log.clone(),
+                &metrics_registry,
+                &config,
+                None,
+                ic_types::malicious_flags::MaliciousFlags::default(),
+            ));
+            let state_sync = StateSync::new(state_manager.clone(), log.clone());
+
+            (metrics_registry, state_manager, state_sync)
+        };
+
+        let (metrics_registry, state_manager, state_sync) = make_state_manager();
+
+        let restart_fn = Box::new(move |state_manager, state_sync| {
+            drop(state_sync);
+            drop(state_manager);
+            make_state_manager()
+        });
+
+        test(&metrics_registry, state_manager, state_sync, restart_fn);
+    });
+}
+
 pub fn state_manager_restart_test<Test>(test: Test)
 where
     Test:
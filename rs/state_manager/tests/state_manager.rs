@@ -1,5 +1,5 @@
 use ic_base_types::NumBytes;
-use ic_config::state_manager::Config;
+use ic_config::{flag_status::FlagStatus, state_manager::Config};
 use ic_crypto_tree_hash::{flatmap, Label, LabeledTree, MixedHashTree};
 use ic_interfaces::artifact_manager::{ArtifactClient, ArtifactProcessor};
 use ic_interfaces::{artifact_pool::UnvalidatedArtifact, certification::Verifier};
@@ -13,7 +13,12 @@ use ic_replicated_state::{
 };
 use ic_state_machine_tests::StateMachineBuilder;
 use ic_state_manager::{
-    tip::TipRequest, BitcoinPageMap, DirtyPageMap, FileType, PageMapType, StateManagerImpl,
+    canister_archive::{
+        export_canister_archive, import_canister_archive, CanisterArchiveError,
+        CANISTER_ARCHIVE_VERSION,
+    },
+    tip::TipRequest,
+    BitcoinPageMap, DirtyPageMap, FileType, PageMapType, StateManagerImpl,
 };
 use ic_sys::PAGE_SIZE;
 use ic_test_utilities::{
@@ -1807,6 +1812,84 @@ fn can_do_simple_state_sync_transfer() {
     })
 }
 
+/// Syncs the same checkpoint once with a `STATE_SYNC_V2` and once with a
+/// `STATE_SYNC_V3` manifest: only the chunks of the latter are encoded (and
+/// deduplicated), but both result in the same state.
+#[test]
+fn can_state_sync_v2_and_v3_manifests() {
+    use ic_state_manager::manifest::{manifest_hash, STATE_SYNC_V2, STATE_SYNC_V3};
+    use ic_types::{chunkable::ChunkableArtifact, state_sync::Manifest};
+
+    state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
+        let (_height, mut state) = src_state_manager.take_tip();
+        // Two canisters with identical contents, so there are duplicate chunks.
+        insert_dummy_canister(&mut state, canister_test_id(100));
+        insert_dummy_canister(&mut state, canister_test_id(200));
+        let time_source = ic_test_utilities::FastForwardTimeSource::new();
+
+        src_state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+        let hash = wait_for_checkpoint(&*src_state_manager, height(1));
+        let id_v2 = StateSyncArtifactId {
+            height: height(1),
+            hash,
+        };
+
+        let state = src_state_manager.get_latest_state().take();
+
+        let msg_v2 = src_state_sync
+            .get_validated_by_identifier(&id_v2)
+            .expect("failed to get state sync messages");
+        assert_eq!(STATE_SYNC_V2, msg_v2.manifest.version);
+
+        // The same checkpoint, as served by a replica producing V3 manifests.
+        let mut msg_v3 = msg_v2.clone();
+        msg_v3.manifest = Manifest::new(
+            STATE_SYNC_V3,
+            msg_v2.manifest.file_table.clone(),
+            msg_v2.manifest.chunk_table.clone(),
+        );
+        msg_v3.root_hash =
+            CryptoHashOfState::from(CryptoHash(manifest_hash(&msg_v3.manifest).to_vec()));
+        let id_v3 = StateSyncArtifactId {
+            height: height(1),
+            hash: msg_v3.root_hash.clone(),
+        };
+
+        let chunk_v2 = Box::new(msg_v2.clone()).get_chunk(ChunkId::new(1)).unwrap();
+        let chunk_v3 = Box::new(msg_v3.clone()).get_chunk(ChunkId::new(1)).unwrap();
+        assert_ne!(chunk_v2.artifact_chunk_data, chunk_v3.artifact_chunk_data);
+
+        assert_error_counters(src_metrics);
+
+        for (id, msg) in [(id_v2, msg_v2), (id_v3, msg_v3)] {
+            state_manager_test_with_state_sync(|dst_metrics, dst_state_manager, dst_state_sync| {
+                let chunkable = dst_state_sync.create_chunkable_state(&id);
+
+                let dst_msg = pipe_state_sync(msg, chunkable);
+                dst_state_sync.process_changes(
+                    time_source.as_ref(),
+                    vec![UnvalidatedArtifact {
+                        message: dst_msg,
+                        peer_id: node_test_id(0),
+                        timestamp: mock_time(),
+                    }],
+                );
+
+                let recovered_state = dst_state_manager
+                    .get_state_at(height(1))
+                    .expect("Destination state manager didn't receive the state")
+                    .take();
+
+                assert_eq!(height(1), dst_state_manager.latest_state_height());
+                assert_eq!(state, recovered_state);
+
+                assert_error_counters(dst_metrics);
+                assert_no_remaining_chunks(dst_metrics);
+            })
+        }
+    })
+}
+
 #[test]
 fn can_state_sync_from_cache() {
     state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
@@ -2003,6 +2086,102 @@ fn can_state_sync_into_existing_checkpoint() {
     })
 }
 
+#[test]
+fn can_resume_state_sync_after_restart() {
+    state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
+        let (_height, mut state) = src_state_manager.take_tip();
+        insert_dummy_canister(&mut state, canister_test_id(100));
+        insert_dummy_canister(&mut state, canister_test_id(200));
+
+        // Modify the first canister to ensure that its chunks are not identical to the
+        // other canister
+        let canister_state = state.canister_state_mut(&canister_test_id(100)).unwrap();
+        let execution_state = canister_state.execution_state.as_mut().unwrap();
+        execution_state
+            .stable_memory
+            .page_map
+            .update(&[(PageIndex::new(0), &[1u8; PAGE_SIZE])]);
+        execution_state
+            .wasm_memory
+            .page_map
+            .update(&[(PageIndex::new(0), &[2u8; PAGE_SIZE])]);
+
+        let time_source = ic_test_utilities::FastForwardTimeSource::new();
+
+        src_state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+        let hash = wait_for_checkpoint(&*src_state_manager, height(1));
+        let id = StateSyncArtifactId {
+            height: height(1),
+            hash,
+        };
+
+        let state = src_state_manager.get_latest_state().take();
+
+        let msg = src_state_sync
+            .get_validated_by_identifier(&id)
+            .expect("failed to get state sync messages");
+
+        assert_error_counters(src_metrics);
+
+        state_manager_restart_test_with_state_sync(
+            |_dst_metrics, dst_state_manager, dst_state_sync, restart_fn| {
+                let omit: HashSet<ChunkId> =
+                    maplit::hashset! {ChunkId::new(4), ChunkId::new(FILE_GROUP_CHUNK_ID_OFFSET)};
+
+                let mut chunkable = dst_state_sync.create_chunkable_state(&id);
+                let completion = pipe_partial_state_sync(&msg, &mut *chunkable, &omit);
+                assert!(completion.is_none(), "Unexpectedly completed state sync");
+                // Simulate a crash: the incomplete state is never dropped, so it
+                // doesn't get the chance to hand its chunks over to the cache.
+                std::mem::forget(chunkable);
+
+                let (dst_metrics, dst_state_manager, dst_state_sync) =
+                    restart_fn(dst_state_manager, dst_state_sync);
+
+                let mut chunkable = dst_state_sync.create_chunkable_state(&id);
+
+                let result = pipe_manifest(&msg, &mut *chunkable);
+                assert!(result.is_none());
+
+                let file_group_chunks: HashSet<ChunkId> = msg
+                    .state_sync_file_group
+                    .keys()
+                    .copied()
+                    .map(ChunkId::from)
+                    .collect();
+
+                let fetch_chunks: HashSet<ChunkId> =
+                    omit.union(&file_group_chunks).copied().collect();
+
+                // Only the chunks not fetched before the restart plus chunks of the file group
+                // should still be requested
+                assert_eq!(fetch_chunks, chunkable.chunks_to_download().collect());
+
+                let dst_msg = pipe_state_sync(msg.clone(), chunkable);
+                dst_state_sync.process_changes(
+                    time_source.as_ref(),
+                    vec![UnvalidatedArtifact {
+                        message: dst_msg,
+                        peer_id: node_test_id(0),
+                        timestamp: mock_time(),
+                    }],
+                );
+
+                let recovered_state = dst_state_manager
+                    .get_state_at(height(1))
+                    .expect("Destination state manager didn't receive the state")
+                    .take();
+
+                assert_eq!(height(1), dst_state_manager.latest_state_height());
+                assert_eq!(state, recovered_state);
+
+                assert_no_remaining_chunks(&dst_metrics);
+                assert_error_counters(&dst_metrics);
+            },
+        )
+    })
+}
+
 #[test]
 fn can_group_small_files_in_state_sync() {
     state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
@@ -2264,6 +2443,108 @@ fn can_state_sync_based_on_old_checkpoint() {
     });
 }
 
+#[test]
+fn can_state_sync_based_on_old_checkpoint_with_overlays() {
+    fn populate_state(state_manager: &StateManagerImpl) {
+        for (i, value) in [1u8, 2].into_iter().enumerate() {
+            let (_height, mut state) = state_manager.take_tip();
+            if i == 0 {
+                insert_dummy_canister(&mut state, canister_test_id(100));
+            }
+            let execution_state = state
+                .canister_state_mut(&canister_test_id(100))
+                .unwrap()
+                .execution_state
+                .as_mut()
+                .unwrap();
+            execution_state.wasm_memory.page_map.update(&[
+                (PageIndex::new(i as u64), &[value; PAGE_SIZE]),
+                (PageIndex::new(300), &[value; PAGE_SIZE]),
+            ]);
+            state_manager.commit_and_certify(state, height(i as u64 + 1), CertificationScope::Full);
+        }
+    }
+
+    state_manager_test_with_state_sync(|src_metrics, src_state_manager, src_state_sync| {
+        populate_state(&src_state_manager);
+        let time_source = ic_test_utilities::FastForwardTimeSource::new();
+
+        let (_height, mut state) = src_state_manager.take_tip();
+        insert_dummy_canister(&mut state, canister_test_id(200));
+        src_state_manager.commit_and_certify(state, height(3), CertificationScope::Full);
+
+        let hash = wait_for_checkpoint(&*src_state_manager, height(3));
+        let id = StateSyncArtifactId {
+            height: height(3),
+            hash,
+        };
+        let msg = src_state_sync
+            .get_validated_by_identifier(&id)
+            .expect("failed to get state sync message");
+
+        assert_error_counters(src_metrics);
+
+        // The chunks of the memory of the canister are the same in the checkpoint of the
+        // destination, where the memory is stored as a base file with an overlay.
+        let memory_chunks: HashSet<ChunkId> = msg
+            .manifest
+            .file_table
+            .iter()
+            .enumerate()
+            .filter(|(_, file_info)| file_info.relative_path.ends_with("vmemory_0.bin"))
+            .flat_map(|(file_index, _)| {
+                ic_state_manager::manifest::file_chunk_range(&msg.manifest.chunk_table, file_index)
+            })
+            .map(|chunk_index| ChunkId::new(chunk_index as u32 + 1))
+            .collect();
+        assert!(!memory_chunks.is_empty());
+
+        state_manager_test_with_incremental_checkpoints_and_state_sync(
+            |dst_metrics, dst_state_manager, dst_state_sync| {
+                populate_state(&dst_state_manager);
+                wait_for_checkpoint(&*dst_state_manager, height(2));
+
+                let memory_path = dst_state_manager
+                    .state_layout()
+                    .checkpoint(height(2))
+                    .unwrap()
+                    .canister(&canister_test_id(100))
+                    .unwrap()
+                    .vmemory_0();
+                assert!(!ic_utils::layered_file::overlay_paths(&memory_path)
+                    .unwrap()
+                    .is_empty());
+
+                let mut chunkable = dst_state_sync.create_chunkable_state(&id);
+                let result = pipe_manifest(&msg, &mut *chunkable);
+                assert!(result.is_none());
+
+                // The memory is copied from the local checkpoint instead of being fetched.
+                let fetch_chunks: HashSet<ChunkId> = chunkable.chunks_to_download().collect();
+                assert!(fetch_chunks.is_disjoint(&memory_chunks));
+
+                let dst_msg = pipe_state_sync(msg, chunkable);
+                dst_state_sync.process_changes(
+                    time_source.as_ref(),
+                    vec![UnvalidatedArtifact {
+                        message: dst_msg,
+                        peer_id: node_test_id(0),
+                        timestamp: mock_time(),
+                    }],
+                );
+
+                assert_eq!(
+                    dst_state_manager.get_latest_state(),
+                    src_state_manager.get_latest_state()
+                );
+
+                assert_no_remaining_chunks(dst_metrics);
+                assert_error_counters(dst_metrics);
+            },
+        )
+    });
+}
+
 #[test]
 fn can_recover_from_corruption_on_state_sync() {
     use ic_state_layout::{CheckpointLayout, RwPolicy};
@@ -3375,6 +3656,274 @@ fn can_reset_memory() {
     });
 }
 
+#[test]
+fn incremental_checkpoints_produce_the_same_state_hashes() {
+    fn run(incremental_checkpoints: FlagStatus) -> Vec<CryptoHashOfState> {
+        let tmp = tmpdir("sm");
+        let config =
+            Config::new(tmp.path().into()).with_incremental_checkpoints(incremental_checkpoints);
+        with_test_replica_logger(|log| {
+            let state_manager = StateManagerImpl::new(
+                Arc::new(FakeVerifier::new()),
+                subnet_test_id(42),
+                SubnetType::Application,
+                log,
+                &MetricsRegistry::new(),
+                &config,
+                None,
+                ic_types::malicious_flags::MaliciousFlags::default(),
+            );
+
+            let writes: Vec<Vec<(u64, u8)>> = vec![
+                vec![(1, 1), (300, 1)],
+                vec![(1, 2)],
+                vec![(5, 3), (300, 3)],
+                vec![(2, 4)],
+            ];
+            let mut hashes = vec![];
+            for (i, pages) in writes.into_iter().enumerate() {
+                let (_height, mut state) = state_manager.take_tip();
+                if i == 0 {
+                    insert_dummy_canister(&mut state, canister_test_id(100));
+                }
+                let execution_state = state
+                    .canister_state_mut(&canister_test_id(100))
+                    .unwrap()
+                    .execution_state
+                    .as_mut()
+                    .unwrap();
+                let contents: Vec<_> = pages
+                    .iter()
+                    .map(|(index, value)| (PageIndex::new(*index), [*value; PAGE_SIZE]))
+                    .collect();
+                let updates: Vec<_> = contents
+                    .iter()
+                    .map(|(index, page)| (*index, page))
+                    .collect();
+                execution_state.wasm_memory.page_map.update(&updates);
+
+                // Commit without a checkpoint first, so that the delta is flushed in between.
+                let metadata_height = height(2 * i as u64 + 1);
+                state_manager.commit_and_certify(
+                    state,
+                    metadata_height,
+                    CertificationScope::Metadata,
+                );
+                let (_height, state) = state_manager.take_tip();
+                let checkpoint_height = metadata_height.increment();
+                state_manager.commit_and_certify(
+                    state,
+                    checkpoint_height,
+                    CertificationScope::Full,
+                );
+                hashes.push(wait_for_checkpoint(&state_manager, checkpoint_height));
+            }
+
+            let memory_path = state_manager
+                .state_layout()
+                .checkpoint(height(8))
+                .unwrap()
+                .canister(&canister_test_id(100))
+                .unwrap()
+                .vmemory_0();
+            let num_overlays = ic_utils::layered_file::overlay_paths(&memory_path)
+                .unwrap()
+                .len();
+            match incremental_checkpoints {
+                FlagStatus::Enabled => assert_eq!(num_overlays, 3),
+                FlagStatus::Disabled => assert_eq!(num_overlays, 0),
+            }
+
+            hashes
+        })
+    }
+
+    assert_eq!(run(FlagStatus::Disabled), run(FlagStatus::Enabled));
+}
+
+/// Commits checkpoints @1 and @2 with a canister with a non-empty memory,
+/// checks that they pass scrubbing and corrupts the memory of the canister in
+/// the checkpoint at `corrupted_height`.
+fn commit_and_corrupt_checkpoint(
+    metrics: &MetricsRegistry,
+    state_manager: &StateManagerImpl,
+    corrupted_height: Height,
+) {
+    let (_height, mut state) = state_manager.take_tip();
+    insert_dummy_canister(&mut state, canister_test_id(100));
+    let canister_state = state.canister_state_mut(&canister_test_id(100)).unwrap();
+    canister_state
+        .execution_state
+        .as_mut()
+        .unwrap()
+        .wasm_memory
+        .page_map
+        .update(&[(PageIndex::new(0), &[1u8; PAGE_SIZE])]);
+    state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+    wait_for_checkpoint(state_manager, height(1));
+
+    let (_height, state) = state_manager.take_tip();
+    state_manager.commit_and_certify(state, height(2), CertificationScope::Full);
+    wait_for_checkpoint(state_manager, height(2));
+
+    assert!(state_manager.scrub_checkpoints().is_empty());
+    assert_error_counters(metrics);
+
+    let canister_memory = state_manager
+        .state_layout()
+        .checkpoint(corrupted_height)
+        .unwrap()
+        .canister(&canister_test_id(100))
+        .unwrap()
+        .vmemory_0();
+    make_mutable(&canister_memory).unwrap();
+    write_all_at(&canister_memory, &[3u8; 8], 4).unwrap();
+}
+
+#[test]
+fn scrubbing_marks_corrupted_checkpoints_diverged() {
+    state_manager_restart_test_with_metrics(|metrics, state_manager, restart_fn| {
+        commit_and_corrupt_checkpoint(metrics, &state_manager, height(1));
+
+        assert_eq!(vec![height(1)], state_manager.scrub_checkpoints());
+
+        let critical_errors = fetch_int_counter_vec(metrics, "critical_errors");
+        let key: Labels = maplit::btreemap! {
+            "error".to_string() => "state_manager_checkpoint_corrupted".to_string()
+        };
+        assert_eq!(Some(&1), critical_errors.get(&key));
+
+        // The corrupted checkpoint is neither advertised nor loaded again.
+        assert_eq!(vec![height(2)], state_manager.checkpoint_heights());
+        assert_eq!(
+            vec![height(1)],
+            state_manager
+                .state_layout()
+                .diverged_checkpoint_heights()
+                .unwrap()
+        );
+        assert!(state_manager.scrub_checkpoints().is_empty());
+
+        let (_metrics, state_manager) = restart_fn(state_manager, None);
+        assert_eq!(height(2), state_manager.latest_state_height());
+    });
+}
+
+#[test]
+fn scrubbing_restarts_from_earlier_checkpoint_if_latest_is_corrupted() {
+    state_manager_restart_test_with_metrics(|metrics, state_manager, restart_fn| {
+        commit_and_corrupt_checkpoint(metrics, &state_manager, height(2));
+
+        // The scrubber brings the replica down instead of answering.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            state_manager.scrub_checkpoints()
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            vec![height(2)],
+            state_manager
+                .state_layout()
+                .diverged_checkpoint_heights()
+                .unwrap()
+        );
+
+        let (_metrics, state_manager) = restart_fn(state_manager, None);
+        assert_eq!(height(1), state_manager.latest_state_height());
+    });
+}
+
+#[test]
+fn canister_archive_roundtrip() {
+    state_manager_test(|_metrics, state_manager| {
+        let (_height, mut state) = state_manager.take_tip();
+        insert_dummy_canister(&mut state, canister_test_id(100));
+        let execution_state = state
+            .canister_state_mut(&canister_test_id(100))
+            .unwrap()
+            .execution_state
+            .as_mut()
+            .unwrap();
+        execution_state.wasm_memory.size = NumWasmPages::new(1);
+        execution_state
+            .wasm_memory
+            .page_map
+            .update(&[(PageIndex::new(1), &[7u8; PAGE_SIZE])]);
+        state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+        wait_for_checkpoint(&state_manager, height(1));
+
+        let canister_layout = state_manager
+            .state_layout()
+            .checkpoint(height(1))
+            .unwrap()
+            .canister(&canister_test_id(100))
+            .unwrap();
+        let tmp = tmpdir("canister_archive");
+        let archive = tmp.path().join("canister.archive");
+        let exported =
+            export_canister_archive(&canister_layout, canister_test_id(100), &archive).unwrap();
+        assert_eq!(canister_test_id(100), exported.canister_id);
+        assert_eq!(CANISTER_ARCHIVE_VERSION, exported.version);
+
+        let imported_dir = tmp.path().join("imported");
+        let imported = import_canister_archive(&archive, &imported_dir).unwrap();
+        assert_eq!(exported, imported);
+        for file in &imported.files {
+            assert_eq!(
+                std::fs::read(canister_layout.raw_path().join(file)).unwrap(),
+                std::fs::read(imported_dir.join(file)).unwrap(),
+                "{} differs after the roundtrip",
+                file
+            );
+        }
+
+        // A corrupted archive is rejected and leaves nothing behind.
+        let mut bytes = std::fs::read(&archive).unwrap();
+        let len = bytes.len();
+        bytes[len / 2] ^= 1;
+        std::fs::write(&archive, bytes).unwrap();
+        let corrupted_dir = tmp.path().join("corrupted");
+        assert!(matches!(
+            import_canister_archive(&archive, &corrupted_dir),
+            Err(CanisterArchiveError::HashMismatch { .. })
+        ));
+        assert!(!corrupted_dir.exists());
+    });
+}
+
+#[test]
+fn canister_archive_export_rejects_oversized_memory() {
+    state_manager_test(|_metrics, state_manager| {
+        let (_height, mut state) = state_manager.take_tip();
+        insert_dummy_canister(&mut state, canister_test_id(100));
+        // The memory has a size of zero Wasm pages but a non-empty page map.
+        state
+            .canister_state_mut(&canister_test_id(100))
+            .unwrap()
+            .execution_state
+            .as_mut()
+            .unwrap()
+            .wasm_memory
+            .page_map
+            .update(&[(PageIndex::new(0), &[1u8; PAGE_SIZE])]);
+        state_manager.commit_and_certify(state, height(1), CertificationScope::Full);
+        wait_for_checkpoint(&state_manager, height(1));
+
+        let canister_layout = state_manager
+            .state_layout()
+            .checkpoint(height(1))
+            .unwrap()
+            .canister(&canister_test_id(100))
+            .unwrap();
+        let tmp = tmpdir("canister_archive");
+        let archive = tmp.path().join("canister.archive");
+        assert!(matches!(
+            export_canister_archive(&canister_layout, canister_test_id(100), &archive),
+            Err(CanisterArchiveError::InvalidCanister { .. })
+        ));
+        assert!(!archive.exists());
+    });
+}
+
 #[test]
 fn can_delete_canister() {
     state_manager_test(|metrics, state_manager| {
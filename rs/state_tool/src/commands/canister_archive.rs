@@ -0,0 +1,70 @@
+//! Exports a single canister of a checkpoint into a portable archive and
+//! imports such an archive into a checkpoint.
+
+use ic_state_layout::{CheckpointLayout, ReadOnly};
+use ic_state_manager::canister_archive::{
+    canister_archive_id, export_canister_archive, import_canister_archive, CanisterArchiveInfo,
+};
+use ic_types::{CanisterId, Height};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+fn print_archive_info(info: &CanisterArchiveInfo) {
+    println!("canister: {}", info.canister_id);
+    println!("version: {}", info.version);
+    println!("hash: {}", hex::encode(info.hash));
+    for file in &info.files {
+        println!("file: {}", file);
+    }
+}
+
+/// Writes the files of `canister_id` in the checkpoint at `state_path` into a
+/// new archive at `archive_path`.
+pub fn do_export_canister(
+    state_path: PathBuf,
+    canister_id: String,
+    archive_path: PathBuf,
+) -> Result<(), String> {
+    let canister_id = CanisterId::from_str(&canister_id).map_err(|e| e.to_string())?;
+    let cp_layout = CheckpointLayout::<ReadOnly>::new_untracked(state_path, Height::new(0))
+        .map_err(|e| format!("Failed to create checkpoint layout: {}", e))?;
+    let canister_layout = cp_layout
+        .canister(&canister_id)
+        .map_err(|e| format!("Failed to create canister layout: {}", e))?;
+    if !canister_layout.raw_path().is_dir() {
+        return Err(format!(
+            "Canister {} not found in checkpoint {}",
+            canister_id,
+            cp_layout.raw_path().display()
+        ));
+    }
+
+    let info = export_canister_archive(&canister_layout, canister_id, &archive_path)
+        .map_err(|e| e.to_string())?;
+    print_archive_info(&info);
+    Ok(())
+}
+
+/// Unpacks the canister archive at `archive_path` into the checkpoint at
+/// `state_path`, e.g. to debug the canister with `drun`.
+///
+/// The checkpoint must not contain the canister yet. Since this changes the
+/// checkpoint, it must not be a checkpoint managed by a replica.
+pub fn do_import_canister(archive_path: PathBuf, state_path: PathBuf) -> Result<(), String> {
+    let canister_id = canister_archive_id(&archive_path).map_err(|e| e.to_string())?;
+    let cp_layout = CheckpointLayout::<ReadOnly>::new_untracked(state_path, Height::new(0))
+        .map_err(|e| format!("Failed to create checkpoint layout: {}", e))?;
+    let canister_dir = cp_layout
+        .canister(&canister_id)
+        .map_err(|e| format!("Failed to create canister layout: {}", e))?
+        .raw_path();
+
+    let info = import_canister_archive(&archive_path, &canister_dir).map_err(|e| e.to_string())?;
+    print_archive_info(&info);
+    println!(
+        "Successfully imported canister {} into {}",
+        info.canister_id,
+        canister_dir.display()
+    );
+    Ok(())
+}
@@ -2,7 +2,8 @@
 //!
 //! A command-line tool to manage Internet Computer replicated states (decode
 //! persisted state files, diff checkpoints, compute partial state hashes and
-//! checkpoint manifests, import state trees).
+//! checkpoint manifests, import state trees, export and import single
+//! canisters).
 
 use clap::Parser;
 use std::path::PathBuf;
@@ -41,6 +42,35 @@ enum Opt {
         height: u64,
     },
 
+    /// Exports a canister of a checkpoint into a portable archive.
+    #[clap(name = "export-canister")]
+    ExportCanister {
+        /// Path to a checkpoint.
+        #[clap(long = "state")]
+        state: PathBuf,
+
+        /// The canister to export.
+        #[clap(long = "canister")]
+        canister: String,
+
+        /// Path of the archive to create.
+        #[clap(long = "output")]
+        output: PathBuf,
+    },
+
+    /// Imports a canister archive into a checkpoint that is not managed by a
+    /// replica, e.g. one used as a `drun` snapshot.
+    #[clap(name = "import-canister")]
+    ImportCanister {
+        /// Path to the archive to import.
+        #[clap(long = "archive")]
+        archive: PathBuf,
+
+        /// Path to the checkpoint to import the canister into.
+        #[clap(long = "state")]
+        state: PathBuf,
+    },
+
     /// Computes manifest of a checkpoint.
     #[clap(name = "manifest")]
     Manifest {
@@ -154,6 +184,14 @@ fn main() {
             config,
             height,
         } => commands::import_state::do_import(state, config, height),
+        Opt::ExportCanister {
+            state,
+            canister,
+            output,
+        } => commands::canister_archive::do_export_canister(state, canister, output),
+        Opt::ImportCanister { archive, state } => {
+            commands::canister_archive::do_import_canister(archive, state)
+        }
         Opt::Manifest { path } => commands::manifest::do_compute_manifest(path),
         Opt::VerifyManifest { file, version } => {
             commands::verify_manifest::do_verify_manifest(&file, version)
@@ -7,6 +7,7 @@ pub mod system_api_empty;
 
 use ic_config::flag_status::FlagStatus;
 use ic_error_types::RejectCode;
+use ic_ic00_types::{EcdsaCurve, EcdsaKeyId};
 use ic_interfaces::execution_environment::{
     ExecutionComplexity, ExecutionMode,
     HypervisorError::{self, *},
@@ -16,12 +17,17 @@ use ic_interfaces::execution_environment::{
 };
 use ic_logger::{error, ReplicaLogger};
 use ic_registry_subnet_type::SubnetType;
-use ic_replicated_state::{memory_required_to_push_request, Memory, NumWasmPages, PageIndex};
+use ic_replicated_state::{
+    canister_state::WASM_PAGE_SIZE_IN_BYTES, memory_required_to_push_request, Memory, NumWasmPages,
+    PageIndex,
+};
 use ic_sys::PageBytes;
 use ic_types::{
+    canister_log::{CanisterLog, MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE},
     ingress::WasmResult,
     messages::{CallContextId, RejectContext, Request, MAX_INTER_CANISTER_PAYLOAD_IN_BYTES},
     methods::{SystemMethod, WasmClosure},
+    time::CoarseTime,
     CanisterId, CanisterTimer, ComputeAllocation, Cycles, NumBytes, NumInstructions, NumPages,
     PrincipalId, SubnetId, Time,
 };
@@ -37,7 +43,9 @@ use std::{
 
 const MULTIPLIER_MAX_SIZE_LOCAL_SUBNET: u64 = 5;
 const MAX_NON_REPLICATED_QUERY_REPLY_SIZE: NumBytes = NumBytes::new(3 << 20);
-const CERTIFIED_DATA_MAX_LENGTH: u32 = 32;
+const CERTIFIED_DATA_MAX_LENGTH: usize = 32;
+/// Maximum timeout of a best-effort call, in seconds.
+pub const MAX_CALL_TIMEOUT_SECONDS: u32 = 300;
 
 // Enables tracing of system calls for local debugging.
 const TRACE_SYSCALLS: bool = false;
@@ -74,10 +82,10 @@ macro_rules! trace_syscall {
 
 // This helper is used in system calls for displaying a summary hash of a heap region.
 #[inline]
-fn summarize(heap: &[u8], start: u32, size: u32) -> u64 {
+fn summarize(heap: &[u8], start: usize, size: usize) -> u64 {
     if TRACE_SYSCALLS {
-        let start = (start as usize).min(heap.len());
-        let end = (start + (size as usize)).min(heap.len());
+        let start = start.min(heap.len());
+        let end = start.saturating_add(size).min(heap.len());
         // The actual hash function doesn't matter much as long as it is
         // cheap to compute and maps the input to u64 reasonably well.
         let mut sum = 0;
@@ -150,6 +158,9 @@ impl InstructionLimits {
 pub struct ExecutionParameters {
     pub instruction_limits: InstructionLimits,
     pub canister_memory_limit: NumBytes,
+    /// The `wasm_memory_limit` setting of the canister, which bounds the size
+    /// of the Wasm heap. `None` means that there is no limit.
+    pub wasm_memory_limit: Option<NumBytes>,
     pub compute_allocation: ComputeAllocation,
     pub subnet_type: SubnetType,
     pub execution_mode: ExecutionMode,
@@ -218,6 +229,9 @@ pub enum ApiType {
         incoming_cycles: Cycles,
         caller: PrincipalId,
         call_context_id: CallContextId,
+        /// Deadline of the incoming call; `NO_DEADLINE` for guaranteed
+        /// response calls and ingress messages.
+        deadline: CoarseTime,
         /// Begins as empty and used to accumulate data for sending replies.
         #[serde(with = "serde_bytes")]
         response_data: Vec<u8>,
@@ -263,6 +277,8 @@ pub enum ApiType {
         incoming_payload: Vec<u8>,
         incoming_cycles: Cycles,
         call_context_id: CallContextId,
+        /// Deadline of the call being responded to by the callback.
+        deadline: CoarseTime,
         // Begins as empty and used to accumulate data for sending replies.
         #[serde(with = "serde_bytes")]
         response_data: Vec<u8>,
@@ -280,6 +296,8 @@ pub enum ApiType {
         reject_context: RejectContext,
         incoming_cycles: Cycles,
         call_context_id: CallContextId,
+        /// Deadline of the call being responded to by the callback.
+        deadline: CoarseTime,
         // Begins as empty and used to accumulate data for sending replies.
         #[serde(with = "serde_bytes")]
         response_data: Vec<u8>,
@@ -308,10 +326,12 @@ pub enum ApiType {
         message_accepted: bool,
     },
 
-    // For executing the `canister_heartbeat` or `canister_global_timer` methods
+    // For executing the `canister_heartbeat`, `canister_global_timer` or
+    // `canister_on_low_wasm_memory` methods
     SystemTask {
         /// System task to execute.
-        /// Only `canister_heartbeat` and `canister_global_timer` are allowed.
+        /// Only `canister_heartbeat`, `canister_global_timer` and
+        /// `canister_on_low_wasm_memory` are allowed.
         system_task: SystemMethod,
         time: Time,
         call_context_id: CallContextId,
@@ -364,6 +384,7 @@ impl ApiType {
         incoming_cycles: Cycles,
         caller: PrincipalId,
         call_context_id: CallContextId,
+        deadline: CoarseTime,
     ) -> Self {
         Self::Update {
             time,
@@ -371,6 +392,7 @@ impl ApiType {
             incoming_cycles,
             caller,
             call_context_id,
+            deadline,
             response_data: vec![],
             response_status: ResponseStatus::NotRepliedYet,
             outgoing_request: None,
@@ -423,6 +445,7 @@ impl ApiType {
         incoming_payload: Vec<u8>,
         incoming_cycles: Cycles,
         call_context_id: CallContextId,
+        deadline: CoarseTime,
         replied: bool,
         execution_mode: ExecutionMode,
     ) -> Self {
@@ -431,6 +454,7 @@ impl ApiType {
             incoming_payload,
             incoming_cycles,
             call_context_id,
+            deadline,
             response_data: vec![],
             response_status: if replied {
                 ResponseStatus::AlreadyReplied
@@ -449,6 +473,7 @@ impl ApiType {
         reject_context: RejectContext,
         incoming_cycles: Cycles,
         call_context_id: CallContextId,
+        deadline: CoarseTime,
         replied: bool,
         execution_mode: ExecutionMode,
     ) -> Self {
@@ -457,6 +482,7 @@ impl ApiType {
             reject_context,
             incoming_cycles,
             call_context_id,
+            deadline,
             response_data: vec![],
             response_status: if replied {
                 ResponseStatus::AlreadyReplied
@@ -488,6 +514,24 @@ impl ApiType {
         }
     }
 
+    /// Returns the time of the current execution. The `start` method is
+    /// executed without a time.
+    pub fn time(&self) -> Option<Time> {
+        match self {
+            ApiType::Start => None,
+            ApiType::Init { time, .. }
+            | ApiType::SystemTask { time, .. }
+            | ApiType::Update { time, .. }
+            | ApiType::Cleanup { time, .. }
+            | ApiType::NonReplicatedQuery { time, .. }
+            | ApiType::ReplicatedQuery { time, .. }
+            | ApiType::PreUpgrade { time, .. }
+            | ApiType::ReplyCallback { time, .. }
+            | ApiType::RejectCallback { time, .. }
+            | ApiType::InspectMessage { time, .. } => Some(*time),
+        }
+    }
+
     /// Indicates whether state modifications are important for this API type or
     /// not.
     pub fn modification_tracking(&self) -> ModificationTracking {
@@ -522,7 +566,11 @@ impl ApiType {
             ApiType::SystemTask { system_task, .. } => match system_task {
                 SystemMethod::CanisterHeartbeat => "heartbeat",
                 SystemMethod::CanisterGlobalTimer => "global timer",
-                _ => panic!("Only `canister_heartbeat` and `canister_global_timer` are allowed."),
+                SystemMethod::CanisterOnLowWasmMemory => "on low wasm memory",
+                _ => panic!(
+                    "Only `canister_heartbeat`, `canister_global_timer` and \
+                    `canister_on_low_wasm_memory` are allowed."
+                ),
             },
             ApiType::Update { .. } => "update",
             ApiType::ReplicatedQuery { .. } => "replicated query",
@@ -739,6 +787,10 @@ pub struct SystemApiImpl {
 
     /// Tracks the complexity accumulated during the message execution.
     execution_complexity: ExecutionComplexity,
+
+    /// Log records produced during the message execution. They are kept even
+    /// if the execution fails.
+    canister_log: CanisterLog,
 }
 
 impl SystemApiImpl {
@@ -774,6 +826,7 @@ impl SystemApiImpl {
             current_slice_instruction_limit: i64::try_from(slice_limit).unwrap_or(i64::MAX),
             instructions_executed_before_current_slice: 0,
             execution_complexity: ExecutionComplexity::default(),
+            canister_log: CanisterLog::default(),
         }
     }
 
@@ -1120,6 +1173,19 @@ impl SystemApiImpl {
         self.sandbox_safe_system_state.take_changes()
     }
 
+    /// Returns the log records produced during the execution so far.
+    pub fn take_canister_log(&mut self) -> CanisterLog {
+        std::mem::take(&mut self.canister_log)
+    }
+
+    fn add_log_record(&mut self, content: Vec<u8>) {
+        let timestamp_nanos = self
+            .api_type
+            .time()
+            .map_or(0, |time| time.as_nanos_since_unix_epoch());
+        self.canister_log.add_record(timestamp_nanos, content);
+    }
+
     pub fn stable_memory_size(&self) -> NumWasmPages {
         self.stable_memory
             .as_ref()
@@ -1259,9 +1325,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_msg_caller_copy(
         &self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match self.get_msg_caller_id("ic0_msg_caller_copy") {
@@ -1269,7 +1335,6 @@ impl SystemApi for SystemApiImpl {
                 let id_bytes = caller_id.as_slice();
                 valid_subslice("ic0.msg_caller_copy heap", dst, size, heap)?;
                 let slice = valid_subslice("ic0.msg_caller_copy id", offset, size, id_bytes)?;
-                let (dst, size) = (dst as usize, size as usize);
                 deterministic_copy_from_slice(&mut heap[dst..dst + size], slice);
                 Ok(())
             }
@@ -1319,9 +1384,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_msg_arg_data_copy(
         &self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match &self.api_type {
@@ -1355,7 +1420,6 @@ impl SystemApi for SystemApiImpl {
                     size,
                     incoming_payload,
                 )?;
-                let (dst, size) = (dst as usize, size as usize);
                 deterministic_copy_from_slice(&mut heap[dst..dst + size], payload_subslice);
                 Ok(())
             }
@@ -1392,9 +1456,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_msg_method_name_copy(
         &self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match &self.api_type {
@@ -1416,7 +1480,6 @@ impl SystemApi for SystemApiImpl {
                     size,
                     method_name.as_bytes(),
                 )?;
-                let (dst, size) = (dst as usize, size as usize);
                 deterministic_copy_from_slice(&mut heap[dst..dst + size], payload_subslice);
                 Ok(())
             }
@@ -1483,15 +1546,15 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_msg_reply_data_append(
         &mut self,
-        src: u32,
-        size: u32,
+        src: usize,
+        size: usize,
         heap: &[u8],
     ) -> HypervisorResult<()> {
         let result = match self.get_response_info() {
             None => Err(self.error_for("ic0_msg_reply_data_append")),
             Some((data, max_reply_size, response_status)) => match response_status {
                 ResponseStatus::NotRepliedYet => {
-                    let payload_size = (data.len() + size as usize) as u64;
+                    let payload_size = data.len().saturating_add(size) as u64;
                     if payload_size > max_reply_size.get() {
                         let string = format!(
                             "ic0.msg_reply_data_append: application payload size ({}) cannot be larger than {}",
@@ -1521,7 +1584,7 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_msg_reject(&mut self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
+    fn ic0_msg_reject(&mut self, src: usize, size: usize, heap: &[u8]) -> HypervisorResult<()> {
         let result = match self.get_response_info() {
             None => Err(self.error_for("ic0_msg_reject")),
             Some((_, max_reply_size, response_status)) => match response_status {
@@ -1567,6 +1630,26 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
+    fn ic0_msg_deadline(&self) -> HypervisorResult<u64> {
+        let result = match &self.api_type {
+            ApiType::Start { .. }
+            | ApiType::Init { .. }
+            | ApiType::SystemTask { .. }
+            | ApiType::Cleanup { .. }
+            | ApiType::PreUpgrade { .. }
+            | ApiType::InspectMessage { .. } => Err(self.error_for("ic0_msg_deadline")),
+            // Queries never carry a deadline.
+            ApiType::ReplicatedQuery { .. } | ApiType::NonReplicatedQuery { .. } => Ok(0),
+            ApiType::Update { deadline, .. }
+            | ApiType::ReplyCallback { deadline, .. }
+            | ApiType::RejectCallback { deadline, .. } => {
+                Ok(deadline.as_time().as_nanos_since_unix_epoch())
+            }
+        };
+        trace_syscall!(self, ic0_msg_deadline, result);
+        result
+    }
+
     fn ic0_msg_reject_msg_size(&self) -> HypervisorResult<u32> {
         let reject_context = self
             .get_reject_context()
@@ -1578,9 +1661,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_msg_reject_msg_copy(
         &self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = {
@@ -1590,10 +1673,8 @@ impl SystemApi for SystemApiImpl {
             valid_subslice("ic0.msg_reject_msg_copy heap", dst, size, heap)?;
 
             let msg = reject_context.message();
-            let dst = dst as usize;
             let msg_bytes =
                 valid_subslice("ic0.msg_reject_msg_copy msg", offset, size, msg.as_bytes())?;
-            let size = size as usize;
             deterministic_copy_from_slice(&mut heap[dst..dst + size], msg_bytes);
             Ok(())
         };
@@ -1634,9 +1715,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_canister_self_copy(
         &mut self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match &self.api_type {
@@ -1655,7 +1736,6 @@ impl SystemApi for SystemApiImpl {
                 let canister_id = self.sandbox_safe_system_state.canister_id;
                 let id_bytes = canister_id.get_ref().as_slice();
                 let slice = valid_subslice("ic0.canister_self_copy id", offset, size, id_bytes)?;
-                let (dst, size) = (dst as usize, size as usize);
                 deterministic_copy_from_slice(&mut heap[dst..dst + size], slice);
                 Ok(())
             }
@@ -1694,9 +1774,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_controller_copy(
         &mut self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match &self.api_type {
@@ -1715,7 +1795,6 @@ impl SystemApi for SystemApiImpl {
                 let controller = self.sandbox_safe_system_state.controller;
                 let id_bytes = controller.as_slice();
                 let slice = valid_subslice("ic0.controller_copy id", offset, size, id_bytes)?;
-                let (dst, size) = (dst as usize, size as usize);
                 deterministic_copy_from_slice(&mut heap[dst..dst + size], slice);
                 Ok(())
             }
@@ -1726,10 +1805,10 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_call_new(
         &mut self,
-        callee_src: u32,
-        callee_size: u32,
-        name_src: u32,
-        name_len: u32,
+        callee_src: usize,
+        callee_size: usize,
+        name_src: usize,
+        name_len: usize,
         reply_fun: u32,
         reply_env: u32,
         reject_fun: u32,
@@ -1804,7 +1883,12 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_call_data_append(&mut self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
+    fn ic0_call_data_append(
+        &mut self,
+        src: usize,
+        size: usize,
+        heap: &[u8],
+    ) -> HypervisorResult<()> {
         let result = match &mut self.api_type {
             ApiType::Start { .. }
             | ApiType::Init { .. }
@@ -1892,6 +1976,56 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
+    fn ic0_call_with_best_effort_response(&mut self, timeout_seconds: u32) -> HypervisorResult<()> {
+        let result = match &mut self.api_type {
+            ApiType::Start { .. }
+            | ApiType::Init { .. }
+            | ApiType::ReplicatedQuery { .. }
+            | ApiType::NonReplicatedQuery { .. }
+            | ApiType::Cleanup { .. }
+            | ApiType::PreUpgrade { .. }
+            | ApiType::InspectMessage { .. } => {
+                Err(self.error_for("ic0_call_with_best_effort_response"))
+            }
+            ApiType::Update {
+                time,
+                outgoing_request,
+                ..
+            }
+            | ApiType::SystemTask {
+                time,
+                outgoing_request,
+                ..
+            }
+            | ApiType::ReplyCallback {
+                time,
+                outgoing_request,
+                ..
+            }
+            | ApiType::RejectCallback {
+                time,
+                outgoing_request,
+                ..
+            } => match outgoing_request {
+                None => Err(HypervisorError::ContractViolation(
+                    "ic0.call_with_best_effort_response called when no call is under construction."
+                        .to_string(),
+                )),
+                Some(request) => request.set_deadline(
+                    CoarseTime::floor(*time)
+                        .saturating_add_secs(timeout_seconds.min(MAX_CALL_TIMEOUT_SECONDS)),
+                ),
+            },
+        };
+        trace_syscall!(
+            self,
+            ic0_call_with_best_effort_response,
+            result,
+            timeout_seconds
+        );
+        result
+    }
+
     fn ic0_call_cycles_add(&mut self, amount: u64) -> HypervisorResult<()> {
         let result = self.ic0_call_cycles_add_helper("ic0_call_cycles_add", Cycles::from(amount));
         trace_syscall!(self, ic0_call_cycles_add, result, amount);
@@ -2075,7 +2209,7 @@ impl SystemApi for SystemApiImpl {
             dst,
             offset,
             size,
-            summarize(heap, dst, size)
+            summarize(heap, dst as usize, size as usize)
         );
         result
     }
@@ -2114,7 +2248,7 @@ impl SystemApi for SystemApiImpl {
             offset,
             src,
             size,
-            summarize(heap, src, size)
+            summarize(heap, src as usize, size as usize)
         );
         result
     }
@@ -2217,7 +2351,7 @@ impl SystemApi for SystemApiImpl {
             dst,
             offset,
             size,
-            summarize(heap, dst as u32, size as u32)
+            summarize(heap, dst as usize, size as usize)
         );
         result
     }
@@ -2256,7 +2390,7 @@ impl SystemApi for SystemApiImpl {
             offset,
             src,
             size,
-            summarize(heap, src as u32, size as u32)
+            summarize(heap, src as usize, size as usize)
         );
         result
     }
@@ -2378,6 +2512,16 @@ impl SystemApi for SystemApiImpl {
             if native_memory_grow_res == -1 {
                 return Ok(());
             }
+            if let Some(limit) = self.execution_parameters.wasm_memory_limit {
+                let bytes = NumBytes::from(
+                    (native_memory_grow_res as u64)
+                        .saturating_add(additional_pages)
+                        .saturating_mul(WASM_PAGE_SIZE_IN_BYTES as u64),
+                );
+                if bytes > limit {
+                    return Err(HypervisorError::WasmMemoryLimitExceeded { bytes, limit });
+                }
+            }
             match self.memory_usage.allocate_pages(additional_pages as usize) {
                 Ok(()) => Ok(()),
                 Err(_err) => Err(HypervisorError::OutOfMemory),
@@ -2434,7 +2578,7 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_canister_cycle_balance128(&self, dst: u32, heap: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_canister_cycle_balance128(&self, dst: usize, heap: &mut [u8]) -> HypervisorResult<()> {
         let result = {
             let method_name = "ic0_canister_cycle_balance128";
             let cycles = self.ic0_canister_cycle_balance_helper(method_name)?;
@@ -2464,7 +2608,7 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_msg_cycles_available128(&self, dst: u32, heap: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_cycles_available128(&self, dst: usize, heap: &mut [u8]) -> HypervisorResult<()> {
         let result = {
             let method_name = "ic0_msg_cycles_available128";
             let cycles = self.ic0_msg_cycles_available_helper(method_name)?;
@@ -2489,7 +2633,7 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_msg_cycles_refunded128(&self, dst: u32, heap: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_cycles_refunded128(&self, dst: usize, heap: &mut [u8]) -> HypervisorResult<()> {
         let result = {
             let method_name = "ic0_msg_cycles_refunded128";
             let cycles = self.ic0_msg_cycles_refunded_helper(method_name)?;
@@ -2528,7 +2672,7 @@ impl SystemApi for SystemApiImpl {
     fn ic0_msg_cycles_accept128(
         &mut self,
         max_amount: Cycles,
-        dst: u32,
+        dst: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = {
@@ -2593,9 +2737,9 @@ impl SystemApi for SystemApiImpl {
 
     fn ic0_data_certificate_copy(
         &self,
-        dst: u32,
-        offset: u32,
-        size: u32,
+        dst: usize,
+        offset: usize,
+        size: usize,
         heap: &mut [u8],
     ) -> HypervisorResult<()> {
         let result = match &self.api_type {
@@ -2615,8 +2759,6 @@ impl SystemApi for SystemApiImpl {
                 data_certificate, ..
             } => match data_certificate {
                 Some(data_certificate) => {
-                    let (dst, offset, size) = (dst as usize, offset as usize, size as usize);
-
                     let (upper_bound, overflow) = offset.overflowing_add(size);
                     if overflow || upper_bound > data_certificate.len() {
                         return Err(ContractViolation(format!(
@@ -2662,7 +2804,12 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_certified_data_set(&mut self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
+    fn ic0_certified_data_set(
+        &mut self,
+        src: usize,
+        size: usize,
+        heap: &[u8],
+    ) -> HypervisorResult<()> {
         let result = match &mut self.api_type {
             ApiType::Start { .. }
             | ApiType::ReplicatedQuery { .. }
@@ -2683,7 +2830,6 @@ impl SystemApi for SystemApiImpl {
                     )));
                 }
 
-                let (src, size) = (src as usize, size as usize);
                 let (upper_bound, overflow) = src.overflowing_add(size);
                 if overflow || upper_bound > heap.len() {
                     return Err(ContractViolation(format!(
@@ -2758,8 +2904,137 @@ impl SystemApi for SystemApiImpl {
         result
     }
 
-    fn ic0_debug_print(&self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
-        const MAX_DEBUG_MESSAGE_SIZE: u32 = 32 * 1024;
+    fn ic0_is_controller(&self, src: usize, size: usize, heap: &[u8]) -> HypervisorResult<u32> {
+        let result = {
+            let bytes = valid_subslice("ic0.is_controller", src, size, heap)?;
+            let principal_id = PrincipalId::try_from(bytes).map_err(|err| {
+                ContractViolation(format!(
+                    "ic0.is_controller: invalid principal id {:?}: {}",
+                    bytes, err
+                ))
+            })?;
+            Ok(self.sandbox_safe_system_state.is_controller(&principal_id) as u32)
+        };
+        trace_syscall!(
+            self,
+            ic0_is_controller,
+            result,
+            src,
+            size,
+            summarize(heap, src, size)
+        );
+        result
+    }
+
+    fn ic0_in_replicated_execution(&self) -> HypervisorResult<i32> {
+        let result = match self.execution_parameters.execution_mode {
+            ExecutionMode::Replicated => Ok(1),
+            ExecutionMode::NonReplicated => Ok(0),
+        };
+        trace_syscall!(self, ic0_in_replicated_execution, result);
+        result
+    }
+
+    fn ic0_cost_call(
+        &self,
+        method_name_size: u64,
+        payload_size: u64,
+        dst: usize,
+        heap: &mut [u8],
+    ) -> HypervisorResult<()> {
+        let cost = self.sandbox_safe_system_state.call_cost(NumBytes::from(
+            method_name_size.saturating_add(payload_size),
+        ));
+        let result = copy_cycles_to_heap(cost, dst, heap, "ic0_cost_call");
+        trace_syscall!(
+            self,
+            ic0_cost_call,
+            result,
+            method_name_size,
+            payload_size,
+            summarize(heap, dst, 16)
+        );
+        result
+    }
+
+    fn ic0_cost_create_canister(&self, dst: usize, heap: &mut [u8]) -> HypervisorResult<()> {
+        let cost = self.sandbox_safe_system_state.create_canister_cost();
+        let result = copy_cycles_to_heap(cost, dst, heap, "ic0_cost_create_canister");
+        trace_syscall!(
+            self,
+            ic0_cost_create_canister,
+            result,
+            summarize(heap, dst, 16)
+        );
+        result
+    }
+
+    fn ic0_cost_http_request(
+        &self,
+        request_size: u64,
+        max_res_bytes: u64,
+        dst: usize,
+        heap: &mut [u8],
+    ) -> HypervisorResult<()> {
+        let cost = self
+            .sandbox_safe_system_state
+            .http_request_cost(NumBytes::from(request_size), NumBytes::from(max_res_bytes));
+        let result = copy_cycles_to_heap(cost, dst, heap, "ic0_cost_http_request");
+        trace_syscall!(
+            self,
+            ic0_cost_http_request,
+            result,
+            request_size,
+            max_res_bytes,
+            summarize(heap, dst, 16)
+        );
+        result
+    }
+
+    fn ic0_cost_sign_with_ecdsa(
+        &self,
+        src: usize,
+        size: usize,
+        curve: u32,
+        dst: usize,
+        heap: &mut [u8],
+    ) -> HypervisorResult<u32> {
+        let result = {
+            let name = valid_subslice("ic0.cost_sign_with_ecdsa", src, size, heap)
+                .map(|bytes| String::from_utf8_lossy(bytes).to_string())?;
+            let curve = match curve {
+                0 => Some(EcdsaCurve::Secp256k1),
+                1 => Some(EcdsaCurve::Secp256r1),
+                _ => None,
+            };
+            match curve {
+                None => Ok(1),
+                Some(curve) => {
+                    let key_id = EcdsaKeyId { curve, name };
+                    match self.sandbox_safe_system_state.sign_with_ecdsa_cost(&key_id) {
+                        None => Ok(2),
+                        Some(cost) => {
+                            copy_cycles_to_heap(cost, dst, heap, "ic0_cost_sign_with_ecdsa")?;
+                            Ok(0)
+                        }
+                    }
+                }
+            }
+        };
+        trace_syscall!(
+            self,
+            ic0_cost_sign_with_ecdsa,
+            result,
+            src,
+            size,
+            curve,
+            summarize(heap, dst, 16)
+        );
+        result
+    }
+
+    fn ic0_debug_print(&self, src: usize, size: usize, heap: &[u8]) -> HypervisorResult<()> {
+        const MAX_DEBUG_MESSAGE_SIZE: usize = 32 * 1024;
         let size = size.min(MAX_DEBUG_MESSAGE_SIZE);
         let msg = match valid_subslice("ic0.debug_print", src, size, heap) {
             Ok(bytes) => String::from_utf8_lossy(bytes).to_string(),
@@ -2778,13 +3053,23 @@ impl SystemApi for SystemApiImpl {
         Ok(())
     }
 
-    fn ic0_trap(&self, src: u32, size: u32, heap: &[u8]) -> HypervisorResult<()> {
-        const MAX_ERROR_MESSAGE_SIZE: u32 = 16 * 1024;
+    fn save_log_message(&mut self, src: usize, size: usize, heap: &[u8]) {
+        let size = size.min(MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE);
+        let content = match valid_subslice("save_log_message", src, size, heap) {
+            Ok(bytes) => bytes.to_vec(),
+            Err(_) => b"(debug message out of memory bounds)".to_vec(),
+        };
+        self.add_log_record(content);
+    }
+
+    fn ic0_trap(&mut self, src: usize, size: usize, heap: &[u8]) -> HypervisorResult<()> {
+        const MAX_ERROR_MESSAGE_SIZE: usize = 16 * 1024;
         let size = size.min(MAX_ERROR_MESSAGE_SIZE);
         let result = {
             let msg = valid_subslice("trap", src, size, heap)
                 .map(|bytes| String::from_utf8_lossy(bytes).to_string())
                 .unwrap_or_else(|_| "(trap message out of memory bounds)".to_string());
+            self.add_log_record(format!("[TRAP]: {}", msg).into_bytes());
             CalledTrap(msg)
         };
         trace_syscall!(self, ic0_trap, src, size, summarize(heap, src, size));
@@ -2808,7 +3093,7 @@ impl OutOfInstructionsHandler for DefaultOutOfInstructionsHandler {
 
 pub(crate) fn copy_cycles_to_heap(
     cycles: Cycles,
-    dst: u32,
+    dst: usize,
     heap: &mut [u8],
     method_name: &str,
 ) -> HypervisorResult<()> {
@@ -2817,7 +3102,6 @@ pub(crate) fn copy_cycles_to_heap(
     let size = bytes.len();
     assert_eq!(size, 16);
 
-    let dst = dst as usize;
     let (upper_bound, overflow) = dst.overflowing_add(size);
     if overflow || upper_bound > heap.len() {
         return Err(ContractViolation(format!(
@@ -2835,13 +3119,11 @@ pub(crate) fn copy_cycles_to_heap(
 
 pub(crate) fn valid_subslice<'a>(
     ctx: &str,
-    src: u32,
-    len: u32,
+    src: usize,
+    len: usize,
     slice: &'a [u8],
 ) -> HypervisorResult<&'a [u8]> {
-    let len = len as usize;
-    let src = src as usize;
-    if slice.len() < src + len {
+    if src.checked_add(len).map_or(true, |end| end > slice.len()) {
         return Err(ContractViolation(format!(
             "{}: src={} + length={} exceeds the slice size={}",
             ctx,
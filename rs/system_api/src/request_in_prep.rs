@@ -2,8 +2,9 @@ use crate::{sandbox_safe_system_state::SandboxSafeSystemState, valid_subslice};
 use ic_interfaces::execution_environment::{HypervisorError, HypervisorResult};
 use ic_logger::ReplicaLogger;
 use ic_types::{
-    messages::{CallContextId, Request},
+    messages::{CallContextId, Request, NO_DEADLINE},
     methods::{Callback, WasmClosure},
+    time::CoarseTime,
     CanisterId, Cycles, NumBytes, PrincipalId,
 };
 use serde::{Deserialize, Serialize};
@@ -47,16 +48,19 @@ pub struct RequestInPrep {
     /// them up creating tricky bugs. Storing this an integer means that the two
     /// limits are stored as different types and are more difficult to mix up.
     multiplier_max_size_local_subnet: u64,
+    /// Deadline of a best-effort call; `NO_DEADLINE` for a guaranteed response
+    /// call.
+    deadline: CoarseTime,
 }
 
 impl RequestInPrep {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         sender: CanisterId,
-        callee_src: u32,
-        callee_size: u32,
-        method_name_src: u32,
-        method_name_len: u32,
+        callee_src: usize,
+        callee_size: usize,
+        method_name_src: usize,
+        method_name_len: usize,
         heap: &[u8],
         on_reply: WasmClosure,
         on_reject: WasmClosure,
@@ -96,6 +100,7 @@ impl RequestInPrep {
             method_payload: Vec::new(),
             max_size_remote_subnet,
             multiplier_max_size_local_subnet,
+            deadline: NO_DEADLINE,
         })
     }
 
@@ -111,14 +116,28 @@ impl RequestInPrep {
         }
     }
 
+    /// Turns the call under construction into a best-effort call with the
+    /// given deadline.
+    pub(crate) fn set_deadline(&mut self, deadline: CoarseTime) -> HypervisorResult<()> {
+        if self.deadline != NO_DEADLINE {
+            Err(HypervisorError::ContractViolation(
+                "ic0.call_with_best_effort_response can be called at most once between `ic0.call_new` and `ic0.call_perform`"
+                    .to_string(),
+            ))
+        } else {
+            self.deadline = deadline;
+            Ok(())
+        }
+    }
+
     pub(crate) fn take_cycles(self) -> Cycles {
         self.cycles
     }
 
     pub(crate) fn extend_method_payload(
         &mut self,
-        src: u32,
-        size: u32,
+        src: usize,
+        size: usize,
         heap: &[u8],
     ) -> HypervisorResult<()> {
         let current_size = self.method_name.len() + self.method_payload.len();
@@ -161,6 +180,7 @@ pub(crate) fn into_request(
         method_payload,
         max_size_remote_subnet,
         multiplier_max_size_local_subnet,
+        deadline,
     }: RequestInPrep,
     call_context_id: CallContextId,
     sandbox_safe_system_state: &mut SandboxSafeSystemState,
@@ -195,6 +215,7 @@ pub(crate) fn into_request(
         on_reply,
         on_reject,
         on_cleanup,
+        deadline,
     ))?;
 
     let req = Request {
@@ -204,6 +225,7 @@ pub(crate) fn into_request(
         method_payload,
         sender_reply_callback: callback_id,
         payment: cycles,
+        deadline,
     };
     // We cannot call `Request::payload_size_bytes()` before constructing the
     // request, so ensure our separate calculation matches the actual size.
@@ -6,7 +6,8 @@ use ic_constants::{LOG_CANISTER_OPERATION_CYCLES_THRESHOLD, SMALL_APP_SUBNET_MAX
 use ic_cycles_account_manager::{CyclesAccountManager, CyclesAccountManagerError};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 use ic_ic00_types::{
-    CreateCanisterArgs, InstallCodeArgs, Method as Ic00Method, Payload,
+    CreateCanisterArgs, EcdsaKeyId, InstallChunkedCodeArgs, InstallCodeArgs,
+    LoadCanisterSnapshotArgs, Method as Ic00Method, Payload,
     ProvisionalCreateCanisterWithCyclesArgs, SetControllerArgs, UninstallCodeArgs,
     UpdateSettingsArgs, IC_00,
 };
@@ -222,8 +223,15 @@ impl SystemStateChanges {
                     .map(|record| record.get_sender_canister_version())
                     .map_err(Self::candid_error_to_user_error)
             }
+            Ok(Ic00Method::LoadCanisterSnapshot) => LoadCanisterSnapshotArgs::decode(payload)
+                .map(|record| record.get_sender_canister_version())
+                .map_err(|err| Self::candid_error_to_user_error(err)),
+            Ok(Ic00Method::InstallChunkedCode) => InstallChunkedCodeArgs::decode(payload)
+                .map(|record| record.get_sender_canister_version())
+                .map_err(|err| Self::candid_error_to_user_error(err)),
             Ok(Ic00Method::SignWithECDSA)
             | Ok(Ic00Method::CanisterStatus)
+            | Ok(Ic00Method::CanisterInfo)
             | Ok(Ic00Method::StartCanister)
             | Ok(Ic00Method::StopCanister)
             | Ok(Ic00Method::DeleteCanister)
@@ -239,7 +247,14 @@ impl SystemStateChanges {
             | Ok(Ic00Method::BitcoinGetBalance)
             | Ok(Ic00Method::BitcoinGetUtxos)
             | Ok(Ic00Method::BitcoinSendTransaction)
-            | Ok(Ic00Method::BitcoinGetCurrentFeePercentiles) => Ok(None),
+            | Ok(Ic00Method::BitcoinGetCurrentFeePercentiles)
+            | Ok(Ic00Method::TakeCanisterSnapshot)
+            | Ok(Ic00Method::ListCanisterSnapshots)
+            | Ok(Ic00Method::DeleteCanisterSnapshot)
+            | Ok(Ic00Method::FetchCanisterLogs)
+            | Ok(Ic00Method::UploadChunk)
+            | Ok(Ic00Method::ClearChunkStore)
+            | Ok(Ic00Method::StoredChunks) => Ok(None),
             Err(_) => Err(UserError::new(
                 ErrorCode::CanisterMethodNotFound,
                 format!("Management canister has no method '{}'", msg.method_name),
@@ -302,7 +317,7 @@ impl SystemStateChanges {
                 })?;
                 if (*amount_taken).get() > LOG_CANISTER_OPERATION_CYCLES_THRESHOLD {
                     match call_context.call_origin() {
-                        CallOrigin::CanisterUpdate(origin_canister_id, _)
+                        CallOrigin::CanisterUpdate(origin_canister_id, _, _)
                         | CallOrigin::CanisterQuery(origin_canister_id, _) => info!(
                             logger,
                             "Canister {} accepted {} cycles from canister {}.",
@@ -447,6 +462,7 @@ pub struct SandboxSafeSystemState {
     pub system_state_changes: SystemStateChanges,
     pub(super) canister_id: CanisterId,
     pub(super) controller: PrincipalId,
+    controllers: BTreeSet<PrincipalId>,
     pub(super) status: CanisterStatusView,
     pub(super) subnet_type: SubnetType,
     pub(super) subnet_size: usize,
@@ -463,6 +479,9 @@ pub struct SandboxSafeSystemState {
     available_request_slots: BTreeMap<CanisterId, usize>,
     ic00_available_request_slots: usize,
     ic00_aliases: BTreeSet<CanisterId>,
+    // Fees for signing with the ECDSA keys that can be used for signing on
+    // some subnet.
+    ecdsa_signature_fees: BTreeMap<EcdsaKeyId, Cycles>,
     global_timer: CanisterTimer,
     canister_version: u64,
 }
@@ -474,6 +493,7 @@ impl SandboxSafeSystemState {
     pub fn new_internal(
         canister_id: CanisterId,
         controller: PrincipalId,
+        controllers: BTreeSet<PrincipalId>,
         status: CanisterStatusView,
         freeze_threshold: NumSeconds,
         memory_allocation: MemoryAllocation,
@@ -484,6 +504,7 @@ impl SandboxSafeSystemState {
         available_request_slots: BTreeMap<CanisterId, usize>,
         ic00_available_request_slots: usize,
         ic00_aliases: BTreeSet<CanisterId>,
+        ecdsa_signature_fees: BTreeMap<EcdsaKeyId, Cycles>,
         subnet_size: usize,
         dirty_page_overhead: NumInstructions,
         global_timer: CanisterTimer,
@@ -492,6 +513,7 @@ impl SandboxSafeSystemState {
         Self {
             canister_id,
             controller,
+            controllers,
             status,
             subnet_type: cycles_account_manager.subnet_type(),
             subnet_size,
@@ -506,6 +528,7 @@ impl SandboxSafeSystemState {
             available_request_slots,
             ic00_available_request_slots,
             ic00_aliases,
+            ecdsa_signature_fees,
             global_timer,
             canister_version,
         }
@@ -548,10 +571,31 @@ impl SandboxSafeSystemState {
         let subnet_size = network_topology
             .get_subnet_size(&cycles_account_manager.get_subnet_id())
             .unwrap_or(SMALL_APP_SUBNET_MAX_SIZE);
+        // Signing requests are routed to the first signing subnet of a key,
+        // which charges according to its own size. Requests from the NNS are
+        // not charged.
+        let ecdsa_signature_fees = network_topology
+            .ecdsa_signing_subnets
+            .iter()
+            .map(|(key_id, signing_subnets)| {
+                let fee =
+                    if cycles_account_manager.get_subnet_id() == network_topology.nns_subnet_id {
+                        Cycles::zero()
+                    } else {
+                        let signing_subnet_size = signing_subnets
+                            .first()
+                            .and_then(|subnet_id| network_topology.get_subnet_size(subnet_id))
+                            .unwrap_or(SMALL_APP_SUBNET_MAX_SIZE);
+                        cycles_account_manager.ecdsa_signature_fee(signing_subnet_size)
+                    };
+                (key_id.clone(), fee)
+            })
+            .collect();
 
         Self::new_internal(
             system_state.canister_id,
             *system_state.controller(),
+            system_state.controllers.clone(),
             CanisterStatusView::from_full_status(&system_state.status),
             system_state.freeze_threshold,
             system_state.memory_allocation,
@@ -564,6 +608,7 @@ impl SandboxSafeSystemState {
             available_request_slots,
             ic00_available_request_slots,
             ic00_aliases,
+            ecdsa_signature_fees,
             subnet_size,
             dirty_page_overhead,
             system_state.global_timer,
@@ -718,6 +763,40 @@ impl SandboxSafeSystemState {
         amount_to_accept
     }
 
+    pub(super) fn is_controller(&self, principal_id: &PrincipalId) -> bool {
+        self.controllers.contains(principal_id)
+    }
+
+    /// Returns the amount of cycles charged for sending a request with the
+    /// given payload size, see `CyclesAccountManager::xnet_call_total_fee`.
+    pub(super) fn call_cost(&self, payload_size: NumBytes) -> Cycles {
+        self.cycles_account_manager
+            .xnet_call_total_fee(payload_size, self.subnet_size)
+    }
+
+    pub(super) fn create_canister_cost(&self) -> Cycles {
+        self.cycles_account_manager
+            .canister_creation_fee(self.subnet_size)
+    }
+
+    pub(super) fn http_request_cost(
+        &self,
+        request_size: NumBytes,
+        max_response_bytes: NumBytes,
+    ) -> Cycles {
+        self.cycles_account_manager.http_request_fee(
+            request_size,
+            Some(max_response_bytes),
+            self.subnet_size,
+        )
+    }
+
+    /// Returns the fee for signing with the given ECDSA key or `None` if no
+    /// subnet can sign with it.
+    pub(super) fn sign_with_ecdsa_cost(&self, key_id: &EcdsaKeyId) -> Option<Cycles> {
+        self.ecdsa_signature_fees.get(key_id).copied()
+    }
+
     pub fn prepayment_for_response_execution(&self) -> Cycles {
         self.cycles_account_manager
             .prepayment_for_response_execution(self.subnet_size)
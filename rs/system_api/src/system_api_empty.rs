@@ -52,7 +52,13 @@ impl SystemApi for SystemApiEmpty {
     fn slice_instructions_executed(&self, _instruction_counter: i64) -> NumInstructions {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_caller_copy(&self, _: u32, _: u32, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_caller_copy(
+        &self,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: &mut [u8],
+    ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_caller_size(&self) -> HypervisorResult<u32> {
@@ -61,7 +67,13 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_msg_arg_data_size(&self) -> HypervisorResult<u32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_arg_data_copy(&self, _: u32, _: u32, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_arg_data_copy(
+        &self,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: &mut [u8],
+    ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_method_name_size(&self) -> HypervisorResult<u32> {
@@ -69,9 +81,9 @@ impl SystemApi for SystemApiEmpty {
     }
     fn ic0_msg_method_name_copy(
         &self,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
@@ -79,7 +91,7 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_accept_message(&mut self) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_reply_data_append(&mut self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn ic0_msg_reply_data_append(&mut self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_reply(&mut self) -> HypervisorResult<()> {
@@ -88,7 +100,10 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_msg_reject_code(&self) -> HypervisorResult<i32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_reject(&mut self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn ic0_msg_deadline(&self) -> HypervisorResult<u64> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_msg_reject(&mut self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_reject_msg_size(&self) -> HypervisorResult<u32> {
@@ -96,9 +111,9 @@ impl SystemApi for SystemApiEmpty {
     }
     fn ic0_msg_reject_msg_copy(
         &self,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
@@ -108,9 +123,9 @@ impl SystemApi for SystemApiEmpty {
     }
     fn ic0_canister_self_copy(
         &mut self,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
@@ -120,25 +135,28 @@ impl SystemApi for SystemApiEmpty {
     }
     fn ic0_controller_copy(
         &mut self,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_debug_print(&self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn ic0_debug_print(&self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_trap(&self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn save_log_message(&mut self, _: usize, _: usize, _: &[u8]) {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_trap(&mut self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_call_new(
         &mut self,
-        _: u32,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
+        _: usize,
         _: u32,
         _: u32,
         _: u32,
@@ -147,12 +165,15 @@ impl SystemApi for SystemApiEmpty {
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_call_data_append(&mut self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn ic0_call_data_append(&mut self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_call_on_cleanup(&mut self, _: u32, _: u32) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
+    fn ic0_call_with_best_effort_response(&mut self, _: u32) -> HypervisorResult<()> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
     fn ic0_call_cycles_add(&mut self, _: u64) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
@@ -165,7 +186,7 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_stable_size(&self) -> HypervisorResult<u32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_stable_grow(&mut self, _: u32) -> HypervisorResult<i32> {
+    fn ic0_stable_grow(&mut self, _: usize) -> HypervisorResult<i32> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_stable_read(&self, _: u32, _: u32, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
@@ -221,19 +242,19 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_canister_cycle_balance(&self) -> HypervisorResult<u64> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_canister_cycle_balance128(&self, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_canister_cycle_balance128(&self, _: usize, _: &mut [u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_cycles_available(&self) -> HypervisorResult<u64> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_cycles_available128(&self, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_cycles_available128(&self, _: usize, _: &mut [u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_cycles_refunded(&self) -> HypervisorResult<u64> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_msg_cycles_refunded128(&self, _: u32, _: &mut [u8]) -> HypervisorResult<()> {
+    fn ic0_msg_cycles_refunded128(&self, _: usize, _: &mut [u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_msg_cycles_accept(&mut self, _: u64) -> HypervisorResult<u64> {
@@ -242,12 +263,12 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_msg_cycles_accept128(
         &mut self,
         _: Cycles,
-        _: u32,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
-    fn ic0_certified_data_set(&mut self, _: u32, _: u32, _: &[u8]) -> HypervisorResult<()> {
+    fn ic0_certified_data_set(&mut self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
     fn ic0_data_certificate_present(&self) -> HypervisorResult<i32> {
@@ -258,9 +279,9 @@ impl SystemApi for SystemApiEmpty {
     }
     fn ic0_data_certificate_copy(
         &self,
-        _: u32,
-        _: u32,
-        _: u32,
+        _: usize,
+        _: usize,
+        _: usize,
         _: &mut [u8],
     ) -> HypervisorResult<()> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
@@ -271,6 +292,37 @@ impl SystemApi for SystemApiEmpty {
     fn ic0_mint_cycles(&mut self, _: u64) -> HypervisorResult<u64> {
         unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
     }
+    fn ic0_is_controller(&self, _: usize, _: usize, _: &[u8]) -> HypervisorResult<u32> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_in_replicated_execution(&self) -> HypervisorResult<i32> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_cost_call(&self, _: u64, _: u64, _: usize, _: &mut [u8]) -> HypervisorResult<()> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_cost_create_canister(&self, _: usize, _: &mut [u8]) -> HypervisorResult<()> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_cost_http_request(
+        &self,
+        _: u64,
+        _: u64,
+        _: usize,
+        _: &mut [u8],
+    ) -> HypervisorResult<()> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
+    fn ic0_cost_sign_with_ecdsa(
+        &self,
+        _: usize,
+        _: usize,
+        _: u32,
+        _: usize,
+        _: &mut [u8],
+    ) -> HypervisorResult<u32> {
+        unimplemented!("{}", MESSAGE_UNIMPLEMENTED)
+    }
     fn dirty_pages_from_stable_write(
         &self,
         _: u64,
@@ -20,7 +20,7 @@ use ic_test_utilities::{
 };
 use ic_test_utilities_execution_environment::default_memory_for_system_api;
 use ic_types::{
-    messages::{CallContextId, CallbackId, RejectContext},
+    messages::{CallContextId, CallbackId, RejectContext, NO_DEADLINE},
     methods::SystemMethod,
     ComputeAllocation, Cycles, NumInstructions, Time,
 };
@@ -36,6 +36,7 @@ pub fn execution_parameters() -> ExecutionParameters {
             NumInstructions::from(5_000_000_000),
         ),
         canister_memory_limit: NumBytes::new(4 << 30),
+        wasm_memory_limit: None,
         compute_allocation: ComputeAllocation::default(),
         subnet_type: SubnetType::Application,
         execution_mode: ExecutionMode::Replicated,
@@ -77,6 +78,7 @@ impl ApiTypeBuilder {
             Cycles::zero(),
             user_test_id(1).get(),
             CallContextId::from(1),
+            NO_DEADLINE,
         )
     }
 
@@ -94,6 +96,7 @@ impl ApiTypeBuilder {
             vec![],
             incoming_cycles,
             CallContextId::new(1),
+            NO_DEADLINE,
             false,
             ExecutionMode::Replicated,
         )
@@ -105,6 +108,7 @@ impl ApiTypeBuilder {
             reject_context,
             Cycles::zero(),
             call_context_test_id(1),
+            NO_DEADLINE,
             false,
             ExecutionMode::Replicated,
         )
@@ -140,7 +144,7 @@ pub fn get_system_state() -> SystemState {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::new(50),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1,37 +1,42 @@
+use assert_matches::assert_matches;
 use ic_base_types::NumSeconds;
 use ic_config::{
     embedders::Config as EmbeddersConfig, flag_status::FlagStatus, subnet_config::SchedulerConfig,
 };
 use ic_constants::SMALL_APP_SUBNET_MAX_SIZE;
 use ic_error_types::RejectCode;
+use ic_ic00_types::{EcdsaCurve, EcdsaKeyId};
 use ic_interfaces::execution_environment::{
-    CanisterOutOfCyclesError, HypervisorError, HypervisorResult, PerformanceCounterType,
-    SubnetAvailableMemory, SystemApi, TrapCode,
+    CanisterOutOfCyclesError, ExecutionMode, HypervisorError, HypervisorResult,
+    PerformanceCounterType, SubnetAvailableMemory, SystemApi, TrapCode,
 };
 use ic_logger::replica_logger::no_op_logger;
 use ic_registry_subnet_type::SubnetType;
 use ic_replicated_state::{
-    testing::CanisterQueuesTesting, CallOrigin, NetworkTopology, SystemState,
+    testing::CanisterQueuesTesting, CallOrigin, NetworkTopology, NodeTopology, SubnetTopology,
+    SystemState,
 };
 use ic_system_api::{
     sandbox_safe_system_state::SandboxSafeSystemState, ApiType, DefaultOutOfInstructionsHandler,
-    NonReplicatedQueryKind, SystemApiImpl,
+    ExecutionParameters, NonReplicatedQueryKind, SystemApiImpl, MAX_CALL_TIMEOUT_SECONDS,
 };
 use ic_test_utilities::{
     cycles_account_manager::CyclesAccountManagerBuilder,
     mock_time,
     state::SystemStateBuilder,
     types::{
-        ids::{call_context_test_id, canister_test_id, subnet_test_id, user_test_id},
+        ids::{call_context_test_id, canister_test_id, node_test_id, subnet_test_id, user_test_id},
         messages::RequestBuilder,
     },
 };
 use ic_test_utilities_execution_environment::default_memory_for_system_api;
 use ic_types::{
-    messages::{CallContextId, CallbackId, RejectContext, MAX_RESPONSE_COUNT_BYTES},
+    messages::{CallContextId, CallbackId, RejectContext, MAX_RESPONSE_COUNT_BYTES, NO_DEADLINE},
     methods::{Callback, WasmClosure},
-    time, CanisterTimer, CountBytes, Cycles, NumBytes, NumInstructions, Time,
+    time::{self, CoarseTime},
+    CanisterTimer, CountBytes, Cycles, NumBytes, NumInstructions, Time,
 };
+use maplit::btreemap;
 use std::{
     convert::{From, TryInto},
     panic::{catch_unwind, UnwindSafe},
@@ -158,6 +163,7 @@ fn test_canister_init_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -169,6 +175,7 @@ fn test_canister_init_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -215,6 +222,7 @@ fn test_canister_update_support() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -226,6 +234,7 @@ fn test_canister_update_support() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -272,6 +281,7 @@ fn test_canister_replicated_query_support() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -283,6 +293,7 @@ fn test_canister_replicated_query_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -329,6 +340,7 @@ fn test_canister_pure_query_support() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -340,6 +352,7 @@ fn test_canister_pure_query_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -396,6 +409,7 @@ fn test_canister_stateful_query_support() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -407,6 +421,7 @@ fn test_canister_stateful_query_support() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -453,6 +468,7 @@ fn test_reply_api_support_on_nns() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -464,6 +480,7 @@ fn test_reply_api_support_on_nns() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -510,6 +527,7 @@ fn test_reply_api_support_non_nns() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -521,6 +539,7 @@ fn test_reply_api_support_non_nns() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -570,6 +589,7 @@ fn test_reject_api_support_on_nns() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_supported(api.ic0_msg_reject_msg_size());
     assert_api_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -581,6 +601,7 @@ fn test_reject_api_support_on_nns() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -630,6 +651,7 @@ fn test_reject_api_support_non_nns() {
     assert_api_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_supported(api.ic0_msg_reject_code());
+    assert_api_supported(api.ic0_msg_deadline());
     assert_api_supported(api.ic0_msg_reject_msg_size());
     assert_api_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -641,6 +663,7 @@ fn test_reject_api_support_non_nns() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -687,6 +710,7 @@ fn test_pre_upgrade_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -698,6 +722,7 @@ fn test_pre_upgrade_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -744,6 +769,7 @@ fn test_start_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_not_supported(api.ic0_canister_self_size());
@@ -755,6 +781,7 @@ fn test_start_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -801,6 +828,7 @@ fn test_cleanup_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -812,6 +840,7 @@ fn test_cleanup_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -863,6 +892,7 @@ fn test_inspect_message_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -874,6 +904,7 @@ fn test_inspect_message_support() {
     assert_api_not_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_not_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_not_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_not_supported(api.ic0_call_cycles_add(0));
     assert_api_not_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_not_supported(api.ic0_call_perform());
@@ -921,6 +952,7 @@ fn test_canister_heartbeat_support() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -932,6 +964,7 @@ fn test_canister_heartbeat_support() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -978,6 +1011,7 @@ fn test_canister_heartbeat_support_nns() {
     assert_api_not_supported(api.ic0_msg_reply_data_append(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject(0, 0, &[]));
     assert_api_not_supported(api.ic0_msg_reject_code());
+    assert_api_not_supported(api.ic0_msg_deadline());
     assert_api_not_supported(api.ic0_msg_reject_msg_size());
     assert_api_not_supported(api.ic0_msg_reject_msg_copy(0, 0, 0, &mut []));
     assert_api_supported(api.ic0_canister_self_size());
@@ -989,6 +1023,7 @@ fn test_canister_heartbeat_support_nns() {
     assert_api_supported(api.ic0_call_new(0, 0, 0, 0, 0, 0, 0, 0, &[]));
     assert_api_supported(api.ic0_call_data_append(0, 0, &[]));
     assert_api_supported(api.ic0_call_on_cleanup(0, 0));
+    assert_api_supported(api.ic0_call_with_best_effort_response(0));
     assert_api_supported(api.ic0_call_cycles_add(0));
     assert_api_supported(api.ic0_call_cycles_add128(Cycles::new(0)));
     assert_api_supported(api.ic0_call_perform());
@@ -1162,7 +1197,7 @@ fn test_canister_balance() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::new(50),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1190,7 +1225,7 @@ fn test_canister_cycle_balance() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::new(50),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1224,7 +1259,7 @@ fn test_msg_cycles_available_traps() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             available_cycles,
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1375,6 +1410,196 @@ fn canister_status() {
     assert_eq!(api.ic0_canister_status(), Ok(3));
 }
 
+#[test]
+fn is_controller() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let api = get_system_api(
+        ApiTypeBuilder::build_update_api(),
+        &get_system_state_with_cycles(INITIAL_CYCLES),
+        cycles_account_manager,
+    );
+    let controller = user_test_id(24).get();
+    let other = user_test_id(25).get();
+    let heap = [controller.as_slice(), other.as_slice()].concat();
+    let size = controller.as_slice().len();
+
+    assert_eq!(api.ic0_is_controller(0, size, &heap), Ok(1));
+    assert_eq!(api.ic0_is_controller(size, heap.len() - size, &heap), Ok(0));
+    // Out of bounds and invalid principals trap.
+    assert!(api.ic0_is_controller(size, heap.len(), &heap).is_err());
+    assert!(api.ic0_is_controller(0, 30, &[0; 30]).is_err());
+}
+
+#[test]
+fn in_replicated_execution() {
+    let system_state = get_system_state_with_cycles(INITIAL_CYCLES);
+    for (execution_mode, expected) in [
+        (ExecutionMode::Replicated, 1),
+        (ExecutionMode::NonReplicated, 0),
+    ] {
+        let api = SystemApiImpl::new(
+            ApiTypeBuilder::build_update_api(),
+            SandboxSafeSystemState::new(
+                &system_state,
+                CyclesAccountManagerBuilder::new().build(),
+                &NetworkTopology::default(),
+                SchedulerConfig::application_subnet().dirty_page_overhead,
+            ),
+            CANISTER_CURRENT_MEMORY_USAGE,
+            ExecutionParameters {
+                execution_mode,
+                ..execution_parameters()
+            },
+            SubnetAvailableMemory::new(i64::MAX / 2, i64::MAX / 2),
+            default_memory_for_system_api(),
+            Arc::new(DefaultOutOfInstructionsHandler {}),
+            no_op_logger(),
+        );
+        assert_eq!(api.ic0_in_replicated_execution(), Ok(expected));
+    }
+}
+
+#[test]
+fn cost_apis_match_cycles_account_manager() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let api = get_system_api(
+        ApiTypeBuilder::build_update_api(),
+        &get_system_state_with_cycles(INITIAL_CYCLES),
+        cycles_account_manager,
+    );
+    let subnet_size = SMALL_APP_SUBNET_MAX_SIZE;
+    let mut heap = vec![0; 16];
+    let read_cycles = |heap: &[u8]| Cycles::new(u128::from_le_bytes(heap.try_into().unwrap()));
+
+    api.ic0_cost_call(10, 1000, 0, &mut heap).unwrap();
+    assert_eq!(
+        read_cycles(&heap),
+        cycles_account_manager.xnet_call_total_fee(NumBytes::from(1010), subnet_size)
+    );
+
+    api.ic0_cost_create_canister(0, &mut heap).unwrap();
+    assert_eq!(
+        read_cycles(&heap),
+        cycles_account_manager.canister_creation_fee(subnet_size)
+    );
+
+    api.ic0_cost_http_request(100, 2000, 0, &mut heap).unwrap();
+    assert_eq!(
+        read_cycles(&heap),
+        cycles_account_manager.http_request_fee(
+            NumBytes::from(100),
+            Some(NumBytes::from(2000)),
+            subnet_size
+        )
+    );
+
+    // Writing out of bounds traps.
+    assert!(api.ic0_cost_create_canister(1, &mut heap).is_err());
+}
+
+#[test]
+fn cost_sign_with_ecdsa() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let key_id = EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: "key".to_string(),
+    };
+    let network_topology = NetworkTopology {
+        ecdsa_signing_subnets: btreemap! { key_id.clone() => vec![subnet_test_id(1)] },
+        ..NetworkTopology::default()
+    };
+    let api = SystemApiImpl::new(
+        ApiTypeBuilder::build_update_api(),
+        SandboxSafeSystemState::new(
+            &get_system_state_with_cycles(INITIAL_CYCLES),
+            cycles_account_manager,
+            &network_topology,
+            SchedulerConfig::application_subnet().dirty_page_overhead,
+        ),
+        CANISTER_CURRENT_MEMORY_USAGE,
+        execution_parameters(),
+        SubnetAvailableMemory::new(i64::MAX / 2, i64::MAX / 2),
+        default_memory_for_system_api(),
+        Arc::new(DefaultOutOfInstructionsHandler {}),
+        no_op_logger(),
+    );
+    let mut heap = [b"key".to_vec(), b"other".to_vec(), vec![0; 16]].concat();
+    let dst = 8;
+
+    // Unknown curve.
+    assert_eq!(api.ic0_cost_sign_with_ecdsa(0, 3, 2, dst, &mut heap), Ok(1));
+    // Known curve, but no key with this name on it.
+    assert_eq!(api.ic0_cost_sign_with_ecdsa(0, 3, 1, dst, &mut heap), Ok(2));
+    // Unknown key.
+    assert_eq!(api.ic0_cost_sign_with_ecdsa(3, 5, 0, dst, &mut heap), Ok(2));
+    assert_eq!(&heap[dst..], &[0; 16]);
+
+    assert_eq!(api.ic0_cost_sign_with_ecdsa(0, 3, 0, dst, &mut heap), Ok(0));
+    assert_eq!(
+        Cycles::new(u128::from_le_bytes(heap[dst..].try_into().unwrap())),
+        cycles_account_manager.ecdsa_signature_fee(SMALL_APP_SUBNET_MAX_SIZE)
+    );
+}
+
+#[test]
+fn cost_sign_with_ecdsa_matches_charge_of_signing_subnet() {
+    let key_id = EcdsaKeyId {
+        curve: EcdsaCurve::Secp256r1,
+        name: "key".to_string(),
+    };
+    let signing_subnet = subnet_test_id(1);
+    let signing_subnet_size = 34;
+    let network_topology = NetworkTopology {
+        ecdsa_signing_subnets: btreemap! { key_id.clone() => vec![signing_subnet] },
+        subnets: btreemap! {
+            signing_subnet => SubnetTopology {
+                nodes: (0..signing_subnet_size)
+                    .map(|i| (node_test_id(i as u64), NodeTopology::default()))
+                    .collect(),
+                ..SubnetTopology::default()
+            },
+        },
+        nns_subnet_id: subnet_test_id(2),
+        ..NetworkTopology::default()
+    };
+    let cost_on_subnet = |own_subnet| {
+        let cycles_account_manager = CyclesAccountManagerBuilder::new()
+            .with_subnet_id(own_subnet)
+            .build();
+        let api = SystemApiImpl::new(
+            ApiTypeBuilder::build_update_api(),
+            SandboxSafeSystemState::new(
+                &get_system_state_with_cycles(INITIAL_CYCLES),
+                cycles_account_manager,
+                &network_topology,
+                SchedulerConfig::application_subnet().dirty_page_overhead,
+            ),
+            CANISTER_CURRENT_MEMORY_USAGE,
+            execution_parameters(),
+            SubnetAvailableMemory::new(i64::MAX / 2, i64::MAX / 2),
+            default_memory_for_system_api(),
+            Arc::new(DefaultOutOfInstructionsHandler {}),
+            no_op_logger(),
+        );
+        let mut heap = [b"key".to_vec(), vec![0; 16]].concat();
+        assert_eq!(api.ic0_cost_sign_with_ecdsa(0, 3, 1, 3, &mut heap), Ok(0));
+        Cycles::new(u128::from_le_bytes(heap[3..].try_into().unwrap()))
+    };
+
+    // The fee is scaled to the size of the signing subnet.
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    assert_eq!(
+        cost_on_subnet(subnet_test_id(3)),
+        cycles_account_manager.ecdsa_signature_fee(signing_subnet_size)
+    );
+    assert_ne!(
+        cycles_account_manager.ecdsa_signature_fee(signing_subnet_size),
+        cycles_account_manager.ecdsa_signature_fee(SMALL_APP_SUBNET_MAX_SIZE)
+    );
+    // Requests from the NNS are not charged.
+    assert_eq!(cost_on_subnet(subnet_test_id(2)), Cycles::zero());
+}
+
 /// msg_cycles_accept() can accept all cycles in call context
 #[test]
 fn msg_cycles_accept_all_cycles_in_call_context() {
@@ -1385,7 +1610,7 @@ fn msg_cycles_accept_all_cycles_in_call_context() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::from(amount),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1408,7 +1633,7 @@ fn msg_cycles_accept_all_cycles_in_call_context_when_more_asked() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::new(40),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1439,7 +1664,7 @@ fn call_perform_not_enough_cycles_resets_state() {
         .call_context_manager_mut()
         .unwrap()
         .new_call_context(
-            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5)),
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
             Cycles::new(40),
             Time::from_nanos_since_unix_epoch(0),
         );
@@ -1468,6 +1693,95 @@ fn call_perform_not_enough_cycles_resets_state() {
     assert_eq!(call_context_manager.callbacks().len(), 0);
 }
 
+#[test]
+fn call_with_best_effort_response_sets_capped_deadline() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let mut system_state = SystemStateBuilder::new().build();
+    system_state
+        .call_context_manager_mut()
+        .unwrap()
+        .new_call_context(
+            CallOrigin::CanisterUpdate(canister_test_id(33), CallbackId::from(5), NO_DEADLINE),
+            Cycles::new(40),
+            Time::from_nanos_since_unix_epoch(0),
+        );
+    let mut api = get_system_api(
+        ApiTypeBuilder::build_update_api(),
+        &system_state,
+        cycles_account_manager,
+    );
+
+    // Not allowed outside of a call under construction.
+    assert_matches!(
+        api.ic0_call_with_best_effort_response(10),
+        Err(HypervisorError::ContractViolation(_))
+    );
+
+    api.ic0_call_new(0, 10, 0, 10, 0, 0, 0, 0, &[0; 1024])
+        .unwrap();
+    api.ic0_call_with_best_effort_response(u32::MAX).unwrap();
+    // Can only be called once per call.
+    assert_matches!(
+        api.ic0_call_with_best_effort_response(10),
+        Err(HypervisorError::ContractViolation(_))
+    );
+    assert_eq!(api.ic0_call_perform().unwrap(), 0);
+
+    let expected_deadline =
+        CoarseTime::floor(mock_time()).saturating_add_secs(MAX_CALL_TIMEOUT_SECONDS);
+    let system_state_changes = api.into_system_state_changes();
+    system_state_changes
+        .apply_changes(
+            mock_time(),
+            &mut system_state,
+            &default_network_topology(),
+            subnet_test_id(1),
+            &no_op_logger(),
+        )
+        .unwrap();
+    let call_context_manager = system_state.call_context_manager().unwrap();
+    assert_eq!(call_context_manager.callbacks().len(), 1);
+    let callback_id = *call_context_manager.callbacks().keys().next().unwrap();
+    assert_eq!(
+        expected_deadline,
+        call_context_manager
+            .callback(&callback_id)
+            .unwrap()
+            .deadline
+    );
+    assert!(call_context_manager.is_unexpired_callback(callback_id));
+}
+
+#[test]
+fn msg_deadline_returns_call_deadline() {
+    let cycles_account_manager = CyclesAccountManagerBuilder::new().build();
+    let deadline = CoarseTime::from_secs_since_unix_epoch(42);
+    let api = get_system_api(
+        ApiType::update(
+            mock_time(),
+            vec![],
+            Cycles::zero(),
+            user_test_id(1).get(),
+            CallContextId::from(1),
+            deadline,
+        ),
+        &get_system_state(),
+        cycles_account_manager.clone(),
+    );
+    assert_eq!(
+        api.ic0_msg_deadline(),
+        Ok(deadline.as_time().as_nanos_since_unix_epoch())
+    );
+
+    // Guaranteed response calls have no deadline.
+    let api = get_system_api(
+        ApiTypeBuilder::build_update_api(),
+        &get_system_state(),
+        cycles_account_manager,
+    );
+    assert_eq!(api.ic0_msg_deadline(), Ok(0));
+}
+
 #[test]
 fn update_available_memory_updates_subnet_available_memory() {
     let wasm_page_size = 64 << 10;
@@ -1524,6 +1838,7 @@ fn take_execution_result_properly_frees_memory() {
             WasmClosure::new(0, 0),
             WasmClosure::new(0, 0),
             None,
+            NO_DEADLINE,
         ))
         .unwrap();
     let mut api = SystemApiImpl::new(
@@ -1588,6 +1903,7 @@ fn push_output_request_respects_memory_limits() {
                 WasmClosure::new(0, 0),
                 WasmClosure::new(0, 0),
                 None,
+                NO_DEADLINE,
             ))
             .unwrap();
         let mut api = SystemApiImpl::new(
@@ -1691,6 +2007,7 @@ fn push_output_request_oversized_request_memory_limits() {
             WasmClosure::new(0, 0),
             WasmClosure::new(0, 0),
             None,
+            NO_DEADLINE,
         ))
         .unwrap();
     let mut api = SystemApiImpl::new(
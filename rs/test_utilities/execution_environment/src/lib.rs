@@ -18,7 +18,7 @@ use ic_embedders::{wasm_utils::compile, WasmtimeEmbedder};
 use ic_error_types::{ErrorCode, RejectCode, UserError};
 pub use ic_execution_environment::ExecutionResponse;
 use ic_execution_environment::{
-    execute_canister, util::process_stopping_canisters, CompilationCostHandling,
+    execute_canister, init_query_stats, util::process_stopping_canisters, CompilationCostHandling,
     ExecuteMessageResult, ExecutionEnvironment, Hypervisor, IngressHistoryWriterImpl,
     InternalHttpQueryHandler, RoundInstructions, RoundLimits,
 };
@@ -576,6 +576,54 @@ impl ExecutionTest {
         self.subnet_message(Method::UpdateSettings, payload)
     }
 
+    /// Updates the Wasm memory limit of the given canister.
+    pub fn update_wasm_memory_limit(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_memory_limit: NumBytes,
+    ) -> Result<WasmResult, UserError> {
+        let payload = UpdateSettingsArgs {
+            canister_id: canister_id.into(),
+            settings: CanisterSettingsArgs::new(None, None, None, None)
+                .with_wasm_memory_limit(wasm_memory_limit.get()),
+            sender_canister_version: None,
+        }
+        .encode();
+        self.subnet_message(Method::UpdateSettings, payload)
+    }
+
+    /// Updates the Wasm memory threshold of the given canister.
+    pub fn update_wasm_memory_threshold(
+        &mut self,
+        canister_id: CanisterId,
+        wasm_memory_threshold: NumBytes,
+    ) -> Result<WasmResult, UserError> {
+        let payload = UpdateSettingsArgs {
+            canister_id: canister_id.into(),
+            settings: CanisterSettingsArgs::new(None, None, None, None)
+                .with_wasm_memory_threshold(wasm_memory_threshold.get()),
+            sender_canister_version: None,
+        }
+        .encode();
+        self.subnet_message(Method::UpdateSettings, payload)
+    }
+
+    /// Updates the reserved cycles limit of the given canister.
+    pub fn update_reserved_cycles_limit(
+        &mut self,
+        canister_id: CanisterId,
+        reserved_cycles_limit: Cycles,
+    ) -> Result<WasmResult, UserError> {
+        let payload = UpdateSettingsArgs {
+            canister_id: canister_id.into(),
+            settings: CanisterSettingsArgs::new(None, None, None, None)
+                .with_reserved_cycles_limit(reserved_cycles_limit.get()),
+            sender_canister_version: None,
+        }
+        .encode();
+        self.subnet_message(Method::UpdateSettings, payload)
+    }
+
     /// Sets the controller of the canister to the given principal.
     pub fn set_controller(
         &mut self,
@@ -841,6 +889,12 @@ impl ExecutionTest {
                     .task_queue
                     .push_front(ExecutionTask::GlobalTimer);
             }
+            CanisterTask::OnLowWasmMemory => {
+                canister
+                    .system_state
+                    .task_queue
+                    .push_front(ExecutionTask::OnLowWasmMemory);
+            }
         }
         let result = execute_canister(
             &self.exec_env,
@@ -1352,6 +1406,7 @@ pub struct ExecutionTestBuilder {
     instruction_limit_without_dts: NumInstructions,
     initial_canister_cycles: Cycles,
     subnet_total_memory: i64,
+    subnet_memory_threshold: i64,
     subnet_message_memory: i64,
     registry_settings: RegistryExecutionSettings,
     manual_execution: bool,
@@ -1376,6 +1431,9 @@ impl Default for ExecutionTestBuilder {
         let subnet_total_memory = ic_config::execution_environment::Config::default()
             .subnet_memory_capacity
             .get() as i64;
+        let subnet_memory_threshold = ic_config::execution_environment::Config::default()
+            .subnet_memory_threshold
+            .get() as i64;
         let subnet_message_memory = ic_config::execution_environment::Config::default()
             .subnet_message_memory_capacity
             .get() as i64;
@@ -1400,6 +1458,7 @@ impl Default for ExecutionTestBuilder {
                 .max_instructions_per_message_without_dts,
             initial_canister_cycles: INITIAL_CANISTER_CYCLES,
             subnet_total_memory,
+            subnet_memory_threshold,
             subnet_message_memory,
             registry_settings: test_registry_settings(),
             manual_execution: false,
@@ -1533,6 +1592,13 @@ impl ExecutionTestBuilder {
         }
     }
 
+    pub fn with_subnet_memory_threshold(self, subnet_memory_threshold: i64) -> Self {
+        Self {
+            subnet_memory_threshold,
+            ..self
+        }
+    }
+
     pub fn with_subnet_message_memory(self, subnet_message_memory: i64) -> Self {
         Self {
             subnet_message_memory,
@@ -1740,6 +1806,7 @@ impl ExecutionTestBuilder {
             composite_queries,
             allocatable_compute_capacity_in_percent: self.allocatable_compute_capacity_in_percent,
             subnet_memory_capacity: NumBytes::from(self.subnet_total_memory as u64),
+            subnet_memory_threshold: NumBytes::from(self.subnet_memory_threshold as u64),
             subnet_message_memory_capacity: NumBytes::from(self.subnet_message_memory as u64),
             bitcoin: BitcoinConfig {
                 privileged_access: self.bitcoin_privileged_access,
@@ -1788,6 +1855,7 @@ impl ExecutionTestBuilder {
             config.clone(),
             Arc::clone(&cycles_account_manager),
         );
+        let (query_stats_collector, _) = init_query_stats(config.query_stats_epoch_length);
         let query_handler = InternalHttpQueryHandler::new(
             self.log.clone(),
             hypervisor,
@@ -1797,6 +1865,7 @@ impl ExecutionTestBuilder {
             self.instruction_limit_without_dts,
             Arc::clone(&cycles_account_manager),
             composite_queries,
+            Arc::new(query_stats_collector),
         );
         ExecutionTest {
             state: Some(state),
@@ -122,6 +122,7 @@ impl WasmtimeInstanceBuilder {
                     self.num_instructions,
                 ),
                 canister_memory_limit: ic_types::NumBytes::from(4 << 30),
+                wasm_memory_limit: None,
                 compute_allocation: ComputeAllocation::default(),
                 subnet_type: self.subnet_type,
                 execution_mode: ExecutionMode::Replicated,
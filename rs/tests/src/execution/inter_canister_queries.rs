@@ -62,8 +62,8 @@ pub fn simple_query(env: TestEnv) {
     });
 }
 
-/// User queries canister A; A queries self which fails.
-pub fn self_loop_fails(env: TestEnv) {
+/// User queries canister A; A queries self; A replies to A; A replies to user.
+pub fn self_loop_succeeds(env: TestEnv) {
     let logger = env.logger();
     let node = env.get_first_healthy_node_snapshot();
     let agent = node.build_default_agent();
@@ -72,16 +72,24 @@ pub fn self_loop_fails(env: TestEnv) {
             let canister =
                 UniversalCanister::new_with_retries(&agent, node.effective_canister_id(), &logger)
                     .await;
-            let res = canister
-                .query(wasm().inter_query(canister.canister_id(), call_args()))
-                .await;
-            assert_reject(res, RejectCode::CanisterError);
+            let arbitrary_bytes = b"l49sdk";
+            assert_eq!(
+                canister
+                    .query(wasm().inter_query(
+                        canister.canister_id(),
+                        call_args().other_side(wasm().reply_data(arbitrary_bytes)),
+                    ))
+                    .await
+                    .unwrap(),
+                arbitrary_bytes
+            );
         }
     });
 }
 
-/// User queries canister A; A queries B; B queries A which fails.
-pub fn canisters_loop_fails(env: TestEnv) {
+/// User queries canister A; A queries B; B queries A; A replies to B; B replies
+/// to A; A replies to user.
+pub fn canisters_loop_succeeds(env: TestEnv) {
     let logger = env.logger();
     let node = env.get_first_healthy_node_snapshot();
     let agent = node.build_default_agent();
@@ -93,16 +101,20 @@ pub fn canisters_loop_fails(env: TestEnv) {
             let canister_b =
                 UniversalCanister::new_with_retries(&agent, node.effective_canister_id(), &logger)
                     .await;
-            let res = canister_a
-                .query(
-                    wasm().inter_query(
+            let arbitrary_bytes = b";ioapusdvzn,x";
+            assert_eq!(
+                canister_a
+                    .query(wasm().inter_query(
                         canister_b.canister_id(),
-                        call_args()
-                            .other_side(wasm().inter_query(canister_a.canister_id(), call_args())),
-                    ),
-                )
-                .await;
-            assert_reject(res, RejectCode::CanisterError);
+                        call_args().other_side(wasm().inter_query(
+                            canister_a.canister_id(),
+                            call_args().other_side(wasm().reply_data(arbitrary_bytes))
+                        ))
+                    ))
+                    .await
+                    .unwrap(),
+                arbitrary_bytes
+            );
         }
     });
 }
@@ -250,12 +250,12 @@ pub fn inter_canister_queries() -> Pot {
                 execution::inter_canister_queries::simple_query
             ),
             sys_t(
-                "self_loop_fails",
-                execution::inter_canister_queries::self_loop_fails
+                "self_loop_succeeds",
+                execution::inter_canister_queries::self_loop_succeeds
             ),
             sys_t(
-                "canisters_loop_fails",
-                execution::inter_canister_queries::canisters_loop_fails
+                "canisters_loop_succeeds",
+                execution::inter_canister_queries::canisters_loop_succeeds
             ),
             sys_t(
                 "query_two_canisters",
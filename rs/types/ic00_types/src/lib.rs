@@ -7,7 +7,11 @@ use ic_base_types::{CanisterId, NodeId, NumBytes, PrincipalId, RegistryVersion,
 use ic_error_types::{ErrorCode, UserError};
 use ic_protobuf::registry::crypto::v1::PublicKey;
 use ic_protobuf::registry::subnet::v1::{InitialIDkgDealings, InitialNiDkgTranscriptRecord};
-use ic_protobuf::{proxy::ProxyDecodeError, registry::crypto::v1 as pb_registry_crypto};
+use ic_protobuf::state::canister_state_bits::v1 as pb_canister_state_bits;
+use ic_protobuf::{
+    proxy::{try_from_option_field, ProxyDecodeError},
+    registry::crypto::v1 as pb_registry_crypto,
+};
 use num_traits::cast::ToPrimitive;
 use serde::Serialize;
 use std::{collections::BTreeSet, convert::TryFrom, fmt, slice::Iter, str::FromStr};
@@ -26,6 +30,7 @@ pub use provisional::{ProvisionalCreateCanisterWithCyclesArgs, ProvisionalTopUpC
 #[derive(Debug, EnumString, EnumIter, Display, Copy, Clone)]
 #[strum(serialize_all = "snake_case")]
 pub enum Method {
+    CanisterInfo,
     CanisterStatus,
     CreateCanister,
     DeleteCanister,
@@ -44,6 +49,21 @@ pub enum Method {
     UpdateSettings,
     ComputeInitialEcdsaDealings,
 
+    // Canister snapshots.
+    TakeCanisterSnapshot,
+    LoadCanisterSnapshot,
+    ListCanisterSnapshots,
+    DeleteCanisterSnapshot,
+
+    // Canister logging.
+    FetchCanisterLogs,
+
+    // Chunked Wasm upload.
+    UploadChunk,
+    ClearChunkStore,
+    StoredChunks,
+    InstallChunkedCode,
+
     // Bitcoin Interface.
     BitcoinGetBalance,
     BitcoinGetUtxos,
@@ -127,6 +147,10 @@ impl Payload<'_> for UninstallCodeArgs {}
 ///     controller : principal;
 ///     compute_allocation: nat;
 ///     memory_allocation: opt nat;
+///     freezing_threshold: nat;
+///     wasm_memory_limit: nat;
+///     wasm_memory_threshold: nat;
+///     reserved_cycles_limit: nat;
 /// })`
 #[derive(CandidType, Deserialize, Debug, Eq, PartialEq)]
 pub struct DefiniteCanisterSettingsArgs {
@@ -135,6 +159,9 @@ pub struct DefiniteCanisterSettingsArgs {
     compute_allocation: candid::Nat,
     memory_allocation: candid::Nat,
     freezing_threshold: candid::Nat,
+    wasm_memory_limit: candid::Nat,
+    wasm_memory_threshold: candid::Nat,
+    reserved_cycles_limit: candid::Nat,
 }
 
 impl DefiniteCanisterSettingsArgs {
@@ -155,12 +182,32 @@ impl DefiniteCanisterSettingsArgs {
             compute_allocation: candid::Nat::from(compute_allocation),
             memory_allocation,
             freezing_threshold: candid::Nat::from(freezing_threshold),
+            wasm_memory_limit: candid::Nat::from(0),
+            wasm_memory_threshold: candid::Nat::from(0),
+            reserved_cycles_limit: candid::Nat::from(0),
         }
     }
 
     pub fn controllers(&self) -> Vec<PrincipalId> {
         self.controllers.clone()
     }
+
+    /// Returns the Wasm memory limit of the canister, where 0 means that
+    /// no limit is set.
+    pub fn wasm_memory_limit(&self) -> u64 {
+        self.wasm_memory_limit.0.to_u64().unwrap()
+    }
+
+    /// Returns the free Wasm heap below which `canister_on_low_wasm_memory`
+    /// is run, where 0 means that the hook is disabled.
+    pub fn wasm_memory_threshold(&self) -> u64 {
+        self.wasm_memory_threshold.0.to_u64().unwrap()
+    }
+
+    /// Returns the upper bound on the reserved cycles balance of the canister.
+    pub fn reserved_cycles_limit(&self) -> u128 {
+        self.reserved_cycles_limit.0.to_u128().unwrap()
+    }
 }
 
 impl Payload<'_> for DefiniteCanisterSettingsArgs {}
@@ -230,6 +277,8 @@ impl Payload<'_> for CanisterStatusResult {}
 ///     memory_size: nat;
 ///     cycles: nat;
 ///     idle_cycles_burned_per_day: nat;
+///     reserved_cycles: nat;
+///     query_stats: query_stats;
 /// })`
 #[derive(CandidType, Debug, Deserialize, Eq, PartialEq)]
 pub struct CanisterStatusResultV2 {
@@ -243,6 +292,23 @@ pub struct CanisterStatusResultV2 {
     balance: Vec<(Vec<u8>, candid::Nat)>,
     freezing_threshold: candid::Nat,
     idle_cycles_burned_per_day: candid::Nat,
+    reserved_cycles: candid::Nat,
+    query_stats: QueryStats,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     num_calls_total: nat;
+///     num_instructions_total: nat;
+///     request_payload_bytes_total: nat;
+///     response_payload_bytes_total: nat;
+/// })`
+#[derive(CandidType, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub struct QueryStats {
+    pub num_calls_total: candid::Nat,
+    pub num_instructions_total: candid::Nat,
+    pub request_payload_bytes_total: candid::Nat,
+    pub response_payload_bytes_total: candid::Nat,
 }
 
 impl CanisterStatusResultV2 {
@@ -277,6 +343,13 @@ impl CanisterStatusResultV2 {
             ),
             freezing_threshold: candid::Nat::from(freezing_threshold),
             idle_cycles_burned_per_day: candid::Nat::from(idle_cycles_burned_per_day),
+            reserved_cycles: candid::Nat::from(0),
+            query_stats: QueryStats {
+                num_calls_total: candid::Nat::from(0),
+                num_instructions_total: candid::Nat::from(0),
+                request_payload_bytes_total: candid::Nat::from(0),
+                response_payload_bytes_total: candid::Nat::from(0),
+            },
         }
     }
 
@@ -311,6 +384,64 @@ impl CanisterStatusResultV2 {
     pub fn idle_cycles_burned_per_day(&self) -> u128 {
         self.idle_cycles_burned_per_day.0.to_u128().unwrap()
     }
+
+    /// Returns the reserved cycles balance of the canister.
+    pub fn reserved_cycles(&self) -> u128 {
+        self.reserved_cycles.0.to_u128().unwrap()
+    }
+
+    pub fn settings(&self) -> &DefiniteCanisterSettingsArgs {
+        &self.settings
+    }
+
+    /// Returns the query statistics of the canister aggregated across the
+    /// replicas of the subnet.
+    pub fn query_stats(&self) -> &QueryStats {
+        &self.query_stats
+    }
+
+    /// Sets the Wasm memory limit reported in the settings, where `None` is
+    /// reported as 0.
+    pub fn with_wasm_memory_limit(mut self, wasm_memory_limit: Option<NumBytes>) -> Self {
+        self.settings.wasm_memory_limit =
+            candid::Nat::from(wasm_memory_limit.map_or(0, |limit| limit.get()));
+        self
+    }
+
+    /// Sets the Wasm memory threshold reported in the settings.
+    pub fn with_wasm_memory_threshold(mut self, wasm_memory_threshold: NumBytes) -> Self {
+        self.settings.wasm_memory_threshold = candid::Nat::from(wasm_memory_threshold.get());
+        self
+    }
+
+    /// Sets the reserved cycles balance and the reserved cycles limit, where
+    /// a limit of `None` is reported as 0.
+    pub fn with_reserved_cycles(
+        mut self,
+        reserved_cycles: u128,
+        reserved_cycles_limit: Option<u128>,
+    ) -> Self {
+        self.reserved_cycles = candid::Nat::from(reserved_cycles);
+        self.settings.reserved_cycles_limit = candid::Nat::from(reserved_cycles_limit.unwrap_or(0));
+        self
+    }
+
+    /// Sets the query statistics totals.
+    pub fn with_query_stats(
+        mut self,
+        num_calls_total: u128,
+        num_instructions_total: u128,
+        request_payload_bytes_total: u128,
+        response_payload_bytes_total: u128,
+    ) -> Self {
+        self.query_stats = QueryStats {
+            num_calls_total: candid::Nat::from(num_calls_total),
+            num_instructions_total: candid::Nat::from(num_instructions_total),
+            request_payload_bytes_total: candid::Nat::from(request_payload_bytes_total),
+            response_payload_bytes_total: candid::Nat::from(response_payload_bytes_total),
+        };
+        self
+    }
 }
 
 /// Indicates whether the canister is running, stopping, or stopped.
@@ -405,6 +536,393 @@ impl From<CanisterInstallMode> for String {
 
 impl Payload<'_> for CanisterStatusResultV2 {}
 
+impl From<CanisterInstallMode> for pb_canister_state_bits::CanisterInstallMode {
+    fn from(item: CanisterInstallMode) -> Self {
+        match item {
+            CanisterInstallMode::Install => pb_canister_state_bits::CanisterInstallMode::Install,
+            CanisterInstallMode::Reinstall => {
+                pb_canister_state_bits::CanisterInstallMode::Reinstall
+            }
+            CanisterInstallMode::Upgrade => pb_canister_state_bits::CanisterInstallMode::Upgrade,
+        }
+    }
+}
+
+impl TryFrom<pb_canister_state_bits::CanisterInstallMode> for CanisterInstallMode {
+    type Error = CanisterInstallModeError;
+
+    fn try_from(item: pb_canister_state_bits::CanisterInstallMode) -> Result<Self, Self::Error> {
+        match item {
+            pb_canister_state_bits::CanisterInstallMode::Install => {
+                Ok(CanisterInstallMode::Install)
+            }
+            pb_canister_state_bits::CanisterInstallMode::Reinstall => {
+                Ok(CanisterInstallMode::Reinstall)
+            }
+            pb_canister_state_bits::CanisterInstallMode::Upgrade => {
+                Ok(CanisterInstallMode::Upgrade)
+            }
+            pb_canister_state_bits::CanisterInstallMode::Unspecified => {
+                Err(CanisterInstallModeError(format!("{:?}", item)))
+            }
+        }
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     user_id : principal;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterChangeFromUserRecord {
+    pub user_id: PrincipalId,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     canister_version : opt nat64;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterChangeFromCanisterRecord {
+    pub canister_id: PrincipalId,
+    pub canister_version: Option<u64>,
+}
+
+/// The entity that triggered a canister change.
+/// ```text
+/// (variant {
+///     from_user : record { user_id : principal; };
+///     from_canister : record {
+///         canister_id : principal;
+///         canister_version : opt nat64;
+///     };
+/// })
+/// ```
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CanisterChangeOrigin {
+    #[serde(rename = "from_user")]
+    FromUser(CanisterChangeFromUserRecord),
+    #[serde(rename = "from_canister")]
+    FromCanister(CanisterChangeFromCanisterRecord),
+}
+
+impl CanisterChangeOrigin {
+    pub fn from_user(user_id: PrincipalId) -> Self {
+        Self::FromUser(CanisterChangeFromUserRecord { user_id })
+    }
+
+    pub fn from_canister(canister_id: PrincipalId, canister_version: Option<u64>) -> Self {
+        Self::FromCanister(CanisterChangeFromCanisterRecord {
+            canister_id,
+            canister_version,
+        })
+    }
+
+    /// Returns the principal that triggered the change.
+    pub fn origin(&self) -> PrincipalId {
+        match self {
+            Self::FromUser(record) => record.user_id,
+            Self::FromCanister(record) => record.canister_id,
+        }
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     controllers : vec principal;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterCreationRecord {
+    pub controllers: Vec<PrincipalId>,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     mode : variant { install; reinstall; upgrade };
+///     module_hash : blob;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterCodeDeploymentRecord {
+    pub mode: CanisterInstallMode,
+    #[serde(with = "serde_bytes")]
+    pub module_hash: Vec<u8>,
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     controllers : vec principal;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterControllersChangeRecord {
+    pub controllers: Vec<PrincipalId>,
+}
+
+/// The details of a canister change.
+/// ```text
+/// (variant {
+///     creation : record { controllers : vec principal; };
+///     code_uninstall;
+///     code_deployment : record {
+///         mode : variant { install; reinstall; upgrade };
+///         module_hash : blob;
+///     };
+///     controllers_change : record { controllers : vec principal; };
+/// })
+/// ```
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum CanisterChangeDetails {
+    #[serde(rename = "creation")]
+    Creation(CanisterCreationRecord),
+    #[serde(rename = "code_uninstall")]
+    CodeUninstall,
+    #[serde(rename = "code_deployment")]
+    CodeDeployment(CanisterCodeDeploymentRecord),
+    #[serde(rename = "controllers_change")]
+    ControllersChange(CanisterControllersChangeRecord),
+}
+
+impl CanisterChangeDetails {
+    pub fn canister_creation(controllers: Vec<PrincipalId>) -> Self {
+        Self::Creation(CanisterCreationRecord { controllers })
+    }
+
+    pub fn code_deployment(mode: CanisterInstallMode, module_hash: [u8; 32]) -> Self {
+        Self::CodeDeployment(CanisterCodeDeploymentRecord {
+            mode,
+            module_hash: module_hash.to_vec(),
+        })
+    }
+
+    pub fn controllers_change(controllers: Vec<PrincipalId>) -> Self {
+        Self::ControllersChange(CanisterControllersChangeRecord { controllers })
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     timestamp_nanos : nat64;
+///     canister_version : nat64;
+///     origin : change_origin;
+///     details : change_details;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterChange {
+    pub timestamp_nanos: u64,
+    pub canister_version: u64,
+    pub origin: CanisterChangeOrigin,
+    pub details: CanisterChangeDetails,
+}
+
+impl CanisterChange {
+    pub fn new(
+        timestamp_nanos: u64,
+        canister_version: u64,
+        origin: CanisterChangeOrigin,
+        details: CanisterChangeDetails,
+    ) -> Self {
+        Self {
+            timestamp_nanos,
+            canister_version,
+            origin,
+            details,
+        }
+    }
+}
+
+impl From<&CanisterChange> for pb_canister_state_bits::CanisterChange {
+    fn from(item: &CanisterChange) -> Self {
+        use pb_canister_state_bits::canister_change::{ChangeDetails, ChangeOrigin};
+
+        let change_origin = match &item.origin {
+            CanisterChangeOrigin::FromUser(record) => ChangeOrigin::CanisterChangeFromUser(
+                pb_canister_state_bits::CanisterChangeFromUser {
+                    user_id: Some(record.user_id.into()),
+                },
+            ),
+            CanisterChangeOrigin::FromCanister(record) => ChangeOrigin::CanisterChangeFromCanister(
+                pb_canister_state_bits::CanisterChangeFromCanister {
+                    canister_id: Some(record.canister_id.into()),
+                    canister_version: record.canister_version,
+                },
+            ),
+        };
+        let change_details = match &item.details {
+            CanisterChangeDetails::Creation(record) => {
+                ChangeDetails::CanisterCreation(pb_canister_state_bits::CanisterCreation {
+                    controllers: record.controllers.iter().map(|c| (*c).into()).collect(),
+                })
+            }
+            CanisterChangeDetails::CodeUninstall => ChangeDetails::CanisterCodeUninstall(
+                pb_canister_state_bits::CanisterCodeUninstall {},
+            ),
+            CanisterChangeDetails::CodeDeployment(record) => ChangeDetails::CanisterCodeDeployment(
+                pb_canister_state_bits::CanisterCodeDeployment {
+                    mode: pb_canister_state_bits::CanisterInstallMode::from(record.mode) as i32,
+                    module_hash: record.module_hash.clone(),
+                },
+            ),
+            CanisterChangeDetails::ControllersChange(record) => {
+                ChangeDetails::CanisterControllersChange(
+                    pb_canister_state_bits::CanisterControllersChange {
+                        controllers: record.controllers.iter().map(|c| (*c).into()).collect(),
+                    },
+                )
+            }
+        };
+        Self {
+            timestamp_nanos: item.timestamp_nanos,
+            canister_version: item.canister_version,
+            change_origin: Some(change_origin),
+            change_details: Some(change_details),
+        }
+    }
+}
+
+impl TryFrom<pb_canister_state_bits::CanisterChange> for CanisterChange {
+    type Error = ProxyDecodeError;
+
+    fn try_from(item: pb_canister_state_bits::CanisterChange) -> Result<Self, Self::Error> {
+        use pb_canister_state_bits::canister_change::{ChangeDetails, ChangeOrigin};
+
+        let origin =
+            match try_from_option_field(item.change_origin, "CanisterChange::change_origin")? {
+                ChangeOrigin::CanisterChangeFromUser(record) => CanisterChangeOrigin::from_user(
+                    try_from_option_field(record.user_id, "CanisterChangeFromUser::user_id")?,
+                ),
+                ChangeOrigin::CanisterChangeFromCanister(record) => {
+                    CanisterChangeOrigin::from_canister(
+                        try_from_option_field(
+                            record.canister_id,
+                            "CanisterChangeFromCanister::canister_id",
+                        )?,
+                        record.canister_version,
+                    )
+                }
+            };
+        let details =
+            match try_from_option_field(item.change_details, "CanisterChange::change_details")? {
+                ChangeDetails::CanisterCreation(record) => {
+                    CanisterChangeDetails::Creation(CanisterCreationRecord {
+                        controllers: record
+                            .controllers
+                            .into_iter()
+                            .map(PrincipalId::try_from)
+                            .collect::<Result<_, _>>()?,
+                    })
+                }
+                ChangeDetails::CanisterCodeUninstall(_) => CanisterChangeDetails::CodeUninstall,
+                ChangeDetails::CanisterCodeDeployment(record) => {
+                    let mode = pb_canister_state_bits::CanisterInstallMode::from_i32(record.mode)
+                        .and_then(|mode| CanisterInstallMode::try_from(mode).ok())
+                        .ok_or(ProxyDecodeError::ValueOutOfRange {
+                            typ: "CanisterInstallMode",
+                            err: format!("Unexpected value of install mode: {}", record.mode),
+                        })?;
+                    CanisterChangeDetails::CodeDeployment(CanisterCodeDeploymentRecord {
+                        mode,
+                        module_hash: record.module_hash,
+                    })
+                }
+                ChangeDetails::CanisterControllersChange(record) => {
+                    CanisterChangeDetails::ControllersChange(CanisterControllersChangeRecord {
+                        controllers: record
+                            .controllers
+                            .into_iter()
+                            .map(PrincipalId::try_from)
+                            .collect::<Result<_, _>>()?,
+                    })
+                }
+            };
+        Ok(Self {
+            timestamp_nanos: item.timestamp_nanos,
+            canister_version: item.canister_version,
+            origin,
+            details,
+        })
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     num_requested_changes : opt nat64;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct CanisterInfoRequest {
+    canister_id: PrincipalId,
+    num_requested_changes: Option<u64>,
+}
+
+impl CanisterInfoRequest {
+    pub fn new(canister_id: CanisterId, num_requested_changes: Option<u64>) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            num_requested_changes,
+        }
+    }
+
+    pub fn canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn num_requested_changes(&self) -> Option<u64> {
+        self.num_requested_changes
+    }
+}
+
+impl Payload<'_> for CanisterInfoRequest {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     total_num_changes : nat64;
+///     recent_changes : vec change;
+///     module_hash : opt blob;
+///     controllers : vec principal;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterInfoResponse {
+    total_num_changes: u64,
+    recent_changes: Vec<CanisterChange>,
+    module_hash: Option<Vec<u8>>,
+    controllers: Vec<PrincipalId>,
+}
+
+impl CanisterInfoResponse {
+    pub fn new(
+        total_num_changes: u64,
+        recent_changes: Vec<CanisterChange>,
+        module_hash: Option<Vec<u8>>,
+        controllers: Vec<PrincipalId>,
+    ) -> Self {
+        Self {
+            total_num_changes,
+            recent_changes,
+            module_hash,
+            controllers,
+        }
+    }
+
+    pub fn total_num_changes(&self) -> u64 {
+        self.total_num_changes
+    }
+
+    pub fn changes(&self) -> Vec<CanisterChange> {
+        self.recent_changes.clone()
+    }
+
+    pub fn module_hash(&self) -> Option<Vec<u8>> {
+        self.module_hash.clone()
+    }
+
+    pub fn controllers(&self) -> Vec<PrincipalId> {
+        self.controllers.clone()
+    }
+}
+
+impl Payload<'_> for CanisterInfoResponse {}
+
 /// Struct used for encoding/decoding
 /// `(record {
 ///     mode : variant { install; reinstall; upgrade };
@@ -497,6 +1015,123 @@ impl InstallCodeArgs {
     }
 }
 
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id: principal;
+///     chunk: blob;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct UploadChunkArgs {
+    pub canister_id: PrincipalId,
+    #[serde(with = "serde_bytes")]
+    pub chunk: Vec<u8>,
+}
+
+impl UploadChunkArgs {
+    pub fn new(canister_id: CanisterId, chunk: Vec<u8>) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            chunk,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+}
+
+impl Payload<'_> for UploadChunkArgs {}
+
+/// Struct used for encoding/decoding `(record { hash: blob })`, the hash of
+/// a chunk in the Wasm chunk store.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkHash {
+    #[serde(with = "serde_bytes")]
+    pub hash: Vec<u8>,
+}
+
+impl Payload<'_> for ChunkHash {}
+
+/// The result of `upload_chunk`: the hash of the uploaded chunk.
+pub type UploadChunkReply = ChunkHash;
+
+/// Struct used for encoding/decoding `(vec chunk_hash)`, the result of
+/// `stored_chunks`.
+#[derive(CandidType, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct StoredChunksReply(pub Vec<ChunkHash>);
+
+impl Payload<'_> for StoredChunksReply {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     mode : variant { install; reinstall; upgrade };
+///     target_canister: principal;
+///     store_canister: opt principal;
+///     chunk_hashes_list: vec chunk_hash;
+///     wasm_module_hash: blob;
+///     arg: blob;
+///     sender_canister_version : opt nat64;
+/// })`
+///
+/// The Wasm module is assembled by concatenating the chunks listed in
+/// `chunk_hashes_list`, which are looked up in the chunk store of
+/// `store_canister` (or of `target_canister` if no store canister is given).
+#[derive(Clone, CandidType, Deserialize, Debug)]
+pub struct InstallChunkedCodeArgs {
+    pub mode: CanisterInstallMode,
+    pub target_canister: PrincipalId,
+    pub store_canister: Option<PrincipalId>,
+    pub chunk_hashes_list: Vec<ChunkHash>,
+    #[serde(with = "serde_bytes")]
+    pub wasm_module_hash: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub arg: Vec<u8>,
+    pub sender_canister_version: Option<u64>,
+}
+
+impl Payload<'_> for InstallChunkedCodeArgs {}
+
+impl InstallChunkedCodeArgs {
+    pub fn new(
+        mode: CanisterInstallMode,
+        target_canister: CanisterId,
+        store_canister: Option<CanisterId>,
+        chunk_hashes_list: Vec<Vec<u8>>,
+        wasm_module_hash: Vec<u8>,
+        arg: Vec<u8>,
+    ) -> Self {
+        Self {
+            mode,
+            target_canister: target_canister.into(),
+            store_canister: store_canister.map(|id| id.into()),
+            chunk_hashes_list: chunk_hashes_list
+                .into_iter()
+                .map(|hash| ChunkHash { hash })
+                .collect(),
+            wasm_module_hash,
+            arg,
+            sender_canister_version: None,
+        }
+    }
+
+    pub fn target_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.target_canister).unwrap()
+    }
+
+    /// Returns the canister holding the chunks, which defaults to the
+    /// target canister.
+    pub fn store_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.store_canister.unwrap_or(self.target_canister)).unwrap()
+    }
+
+    pub fn get_sender_canister_version(&self) -> Option<u64> {
+        self.sender_canister_version
+    }
+}
+
 /// Represents the empty blob.
 #[derive(CandidType, Deserialize)]
 pub struct EmptyBlob;
@@ -550,6 +1185,11 @@ impl Payload<'_> for UpdateSettingsArgs {}
 ///     controllers: opt vec principal;
 ///     compute_allocation: opt nat;
 ///     memory_allocation: opt nat;
+///     freezing_threshold: opt nat;
+///     log_visibility: opt log_visibility;
+///     wasm_memory_limit: opt nat;
+///     wasm_memory_threshold: opt nat;
+///     reserved_cycles_limit: opt nat;
 /// })`
 #[derive(Default, Clone, CandidType, Deserialize, Debug)]
 pub struct CanisterSettingsArgs {
@@ -559,6 +1199,10 @@ pub struct CanisterSettingsArgs {
     pub compute_allocation: Option<candid::Nat>,
     pub memory_allocation: Option<candid::Nat>,
     pub freezing_threshold: Option<candid::Nat>,
+    pub log_visibility: Option<LogVisibility>,
+    pub wasm_memory_limit: Option<candid::Nat>,
+    pub wasm_memory_threshold: Option<candid::Nat>,
+    pub reserved_cycles_limit: Option<candid::Nat>,
 }
 
 impl Payload<'_> for CanisterSettingsArgs {}
@@ -576,6 +1220,38 @@ impl CanisterSettingsArgs {
             compute_allocation: compute_allocation.map(candid::Nat::from),
             memory_allocation: memory_allocation.map(candid::Nat::from),
             freezing_threshold: freezing_threshold.map(candid::Nat::from),
+            log_visibility: None,
+            wasm_memory_limit: None,
+            wasm_memory_threshold: None,
+            reserved_cycles_limit: None,
+        }
+    }
+
+    pub fn with_wasm_memory_limit(self, wasm_memory_limit: u64) -> Self {
+        Self {
+            wasm_memory_limit: Some(candid::Nat::from(wasm_memory_limit)),
+            ..self
+        }
+    }
+
+    pub fn with_wasm_memory_threshold(self, wasm_memory_threshold: u64) -> Self {
+        Self {
+            wasm_memory_threshold: Some(candid::Nat::from(wasm_memory_threshold)),
+            ..self
+        }
+    }
+
+    pub fn with_reserved_cycles_limit(self, reserved_cycles_limit: u128) -> Self {
+        Self {
+            reserved_cycles_limit: Some(candid::Nat::from(reserved_cycles_limit)),
+            ..self
+        }
+    }
+
+    pub fn with_log_visibility(self, log_visibility: LogVisibility) -> Self {
+        Self {
+            log_visibility: Some(log_visibility),
+            ..self
         }
     }
 
@@ -648,6 +1324,253 @@ impl SetControllerArgs {
 
 impl Payload<'_> for SetControllerArgs {}
 
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     replace_snapshot : opt blob;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct TakeCanisterSnapshotArgs {
+    canister_id: PrincipalId,
+    replace_snapshot: Option<Vec<u8>>,
+}
+
+impl TakeCanisterSnapshotArgs {
+    pub fn new(canister_id: CanisterId, replace_snapshot: Option<Vec<u8>>) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            replace_snapshot,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn replace_snapshot(&self) -> Option<&Vec<u8>> {
+        self.replace_snapshot.as_ref()
+    }
+}
+
+impl Payload<'_> for TakeCanisterSnapshotArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     snapshot_id : blob;
+///     sender_canister_version : opt nat64;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct LoadCanisterSnapshotArgs {
+    canister_id: PrincipalId,
+    snapshot_id: Vec<u8>,
+    sender_canister_version: Option<u64>,
+}
+
+impl LoadCanisterSnapshotArgs {
+    pub fn new(
+        canister_id: CanisterId,
+        snapshot_id: Vec<u8>,
+        sender_canister_version: Option<u64>,
+    ) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            snapshot_id,
+            sender_canister_version,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn snapshot_id(&self) -> &[u8] {
+        &self.snapshot_id
+    }
+
+    pub fn get_sender_canister_version(&self) -> Option<u64> {
+        self.sender_canister_version
+    }
+}
+
+impl Payload<'_> for LoadCanisterSnapshotArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+///     snapshot_id : blob;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct DeleteCanisterSnapshotArgs {
+    canister_id: PrincipalId,
+    snapshot_id: Vec<u8>,
+}
+
+impl DeleteCanisterSnapshotArgs {
+    pub fn new(canister_id: CanisterId, snapshot_id: Vec<u8>) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+            snapshot_id,
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+
+    pub fn snapshot_id(&self) -> &[u8] {
+        &self.snapshot_id
+    }
+}
+
+impl Payload<'_> for DeleteCanisterSnapshotArgs {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     id : blob;
+///     taken_at_timestamp : nat64;
+///     total_size : nat64;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterSnapshotResponse {
+    pub id: Vec<u8>,
+    pub taken_at_timestamp: u64,
+    pub total_size: u64,
+}
+
+impl CanisterSnapshotResponse {
+    pub fn new(id: Vec<u8>, taken_at_timestamp: u64, total_size: u64) -> Self {
+        Self {
+            id,
+            taken_at_timestamp,
+            total_size,
+        }
+    }
+}
+
+impl Payload<'_> for CanisterSnapshotResponse {}
+
+/// Struct used for encoding/decoding `(vec canister_snapshot_response)`, the
+/// result of `list_canister_snapshots`.
+#[derive(CandidType, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ListCanisterSnapshotsResponse(pub Vec<CanisterSnapshotResponse>);
+
+impl Payload<'_> for ListCanisterSnapshotsResponse {}
+
+/// Determines who is allowed to fetch the logs of a canister.
+/// ```text
+/// (variant { controllers; public; })
+/// ```
+#[derive(CandidType, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LogVisibility {
+    /// Only the controllers of the canister can fetch the logs.
+    #[serde(rename = "controllers")]
+    Controllers,
+    /// Everyone can fetch the logs.
+    #[serde(rename = "public")]
+    Public,
+}
+
+impl Default for LogVisibility {
+    fn default() -> Self {
+        LogVisibility::Controllers
+    }
+}
+
+impl From<LogVisibility> for pb_canister_state_bits::LogVisibility {
+    fn from(item: LogVisibility) -> Self {
+        match item {
+            LogVisibility::Controllers => pb_canister_state_bits::LogVisibility::Controllers,
+            LogVisibility::Public => pb_canister_state_bits::LogVisibility::Public,
+        }
+    }
+}
+
+impl From<pb_canister_state_bits::LogVisibility> for LogVisibility {
+    fn from(item: pb_canister_state_bits::LogVisibility) -> Self {
+        match item {
+            // Checkpoints written before the log visibility was introduced
+            // default to the most restrictive setting.
+            pb_canister_state_bits::LogVisibility::Unspecified
+            | pb_canister_state_bits::LogVisibility::Controllers => LogVisibility::Controllers,
+            pb_canister_state_bits::LogVisibility::Public => LogVisibility::Public,
+        }
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_id : principal;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Debug)]
+pub struct FetchCanisterLogsRequest {
+    canister_id: PrincipalId,
+}
+
+impl FetchCanisterLogsRequest {
+    pub fn new(canister_id: CanisterId) -> Self {
+        Self {
+            canister_id: canister_id.into(),
+        }
+    }
+
+    pub fn get_canister_id(&self) -> CanisterId {
+        // Safe as this was converted from CanisterId when Self was constructed.
+        CanisterId::new(self.canister_id).unwrap()
+    }
+}
+
+impl Payload<'_> for FetchCanisterLogsRequest {}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     idx : nat64;
+///     timestamp_nanos : nat64;
+///     content : blob;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CanisterLogRecord {
+    pub idx: u64,
+    pub timestamp_nanos: u64,
+    pub content: Vec<u8>,
+}
+
+impl Payload<'_> for CanisterLogRecord {}
+
+impl From<&CanisterLogRecord> for pb_canister_state_bits::CanisterLogRecord {
+    fn from(item: &CanisterLogRecord) -> Self {
+        Self {
+            idx: item.idx,
+            timestamp_nanos: item.timestamp_nanos,
+            content: item.content.clone(),
+        }
+    }
+}
+
+impl From<pb_canister_state_bits::CanisterLogRecord> for CanisterLogRecord {
+    fn from(item: pb_canister_state_bits::CanisterLogRecord) -> Self {
+        Self {
+            idx: item.idx,
+            timestamp_nanos: item.timestamp_nanos,
+            content: item.content,
+        }
+    }
+}
+
+/// Struct used for encoding/decoding
+/// `(record {
+///     canister_log_records : vec canister_log_record;
+/// })`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct FetchCanisterLogsResponse {
+    pub canister_log_records: Vec<CanisterLogRecord>,
+}
+
+impl Payload<'_> for FetchCanisterLogsResponse {}
+
 /// Struct used for encoding/decoding
 /// `(record {
 ///     node_ids : vec principal;
@@ -751,7 +1674,7 @@ impl SetupInitialDKGResponse {
 
 /// Types of curves that can be used for ECDSA signing.
 /// ```text
-/// (variant { secp256k1; })
+/// (variant { secp256k1; secp256r1; })
 /// ```
 #[derive(
     CandidType, Copy, Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize, Hash,
@@ -759,6 +1682,8 @@ impl SetupInitialDKGResponse {
 pub enum EcdsaCurve {
     #[serde(rename = "secp256k1")]
     Secp256k1,
+    #[serde(rename = "secp256r1")]
+    Secp256r1,
 }
 
 impl TryFrom<pb_registry_crypto::EcdsaCurve> for EcdsaCurve {
@@ -767,6 +1692,7 @@ impl TryFrom<pb_registry_crypto::EcdsaCurve> for EcdsaCurve {
     fn try_from(item: pb_registry_crypto::EcdsaCurve) -> Result<Self, Self::Error> {
         match item {
             pb_registry_crypto::EcdsaCurve::Secp256k1 => Ok(EcdsaCurve::Secp256k1),
+            pb_registry_crypto::EcdsaCurve::Secp256r1 => Ok(EcdsaCurve::Secp256r1),
             pb_registry_crypto::EcdsaCurve::Unspecified => Err(ProxyDecodeError::ValueOutOfRange {
                 typ: "EcdsaCurve",
                 err: format!("Unable to convert {:?} to an EcdsaCurve", item),
@@ -779,6 +1705,7 @@ impl From<EcdsaCurve> for pb_registry_crypto::EcdsaCurve {
     fn from(item: EcdsaCurve) -> Self {
         match item {
             EcdsaCurve::Secp256k1 => pb_registry_crypto::EcdsaCurve::Secp256k1,
+            EcdsaCurve::Secp256r1 => pb_registry_crypto::EcdsaCurve::Secp256r1,
         }
     }
 }
@@ -795,6 +1722,7 @@ impl FromStr for EcdsaCurve {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "Secp256k1" => Ok(Self::Secp256k1),
+            "Secp256r1" => Ok(Self::Secp256r1),
             _ => Err(format!("{} is not a recognized ECDSA curve", s)),
         }
     }
@@ -802,12 +1730,13 @@ impl FromStr for EcdsaCurve {
 
 #[test]
 fn ecdsa_curve_round_trip() {
-    assert_eq!(
-        format!("{}", EcdsaCurve::Secp256k1)
-            .parse::<EcdsaCurve>()
-            .unwrap(),
-        EcdsaCurve::Secp256k1
-    );
+    for curve in [EcdsaCurve::Secp256k1, EcdsaCurve::Secp256r1] {
+        assert_eq!(format!("{}", curve).parse::<EcdsaCurve>().unwrap(), curve);
+        assert_eq!(
+            EcdsaCurve::try_from(pb_registry_crypto::EcdsaCurve::from(curve)).unwrap(),
+            curve
+        );
+    }
 }
 
 /// Unique identifier for a key that can be used for ECDSA signatures. The name
@@ -499,7 +499,7 @@ impl ChunkableArtifact for StateSyncMessage {
         #[cfg(target_family = "unix")]
         {
             use crate::chunkable::ArtifactChunkData;
-            use std::os::unix::fs::FileExt;
+            use ic_utils::layered_file::LayeredFile;
 
             let get_single_chunk = |chunk_index: usize| -> Option<Vec<u8>> {
                 let chunk = self.manifest.chunk_table.get(chunk_index).cloned()?;
@@ -507,7 +507,8 @@ impl ChunkableArtifact for StateSyncMessage {
                     .checkpoint_root
                     .join(&self.manifest.file_table[chunk.file_index as usize].relative_path);
                 let mut buf = vec![0; chunk.size_bytes as usize];
-                let f = std::fs::File::open(path).ok()?;
+                // Files with overlays are served from their logical contents.
+                let f = LayeredFile::open(&path).ok()?;
                 f.read_exact_at(&mut buf[..], chunk.offset).ok()?;
                 Some(buf)
             };
@@ -526,6 +527,15 @@ impl ChunkableArtifact for StateSyncMessage {
                 }
             }
 
+            if _chunk_id != crate::state_sync::MANIFEST_CHUNK
+                && self.manifest.version >= crate::state_sync::MIN_COMPRESSED_CHUNKS_VERSION
+            {
+                // A payload that fails to compress is still served, just uncompressed:
+                // peers decode both encodings the same way.
+                payload = crate::state_sync::encode_chunk_payload(&payload)
+                    .unwrap_or_else(|_err| crate::state_sync::raw_chunk_payload(&payload));
+            }
+
             Some(ArtifactChunk {
                 chunk_id: _chunk_id,
                 witness: Vec::new(),
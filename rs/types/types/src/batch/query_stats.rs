@@ -0,0 +1,166 @@
+use crate::{
+    node_id_into_protobuf, node_id_try_from_protobuf, CanisterId, CountBytes, Height, NodeId,
+};
+use ic_protobuf::{
+    proxy::{try_from_option_field, ProxyDecodeError},
+    types::v1 as pb,
+};
+use phantom_newtype::AmountOf;
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, mem::size_of};
+
+pub struct QueryStatsEpochTag;
+/// The epoch during which query statistics were collected. An epoch spans a
+/// fixed number of consecutive heights.
+pub type QueryStatsEpoch = AmountOf<QueryStatsEpochTag, u64>;
+
+/// Returns the epoch that the given height belongs to.
+pub fn epoch_from_height(height: Height, epoch_length: u64) -> QueryStatsEpoch {
+    QueryStatsEpoch::from(height.get() / epoch_length.max(1))
+}
+
+/// Statistics about the query calls that a single replica executed on a
+/// canister.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QueryStats {
+    pub num_calls: u32,
+    pub num_instructions: u64,
+    pub ingress_payload_size: u64,
+    pub egress_payload_size: u64,
+}
+
+impl QueryStats {
+    /// Adds the statistics of `other` to `self`, saturating on overflow.
+    pub fn saturating_accumulate(&mut self, other: &QueryStats) {
+        self.num_calls = self.num_calls.saturating_add(other.num_calls);
+        self.num_instructions = self.num_instructions.saturating_add(other.num_instructions);
+        self.ingress_payload_size = self
+            .ingress_payload_size
+            .saturating_add(other.ingress_payload_size);
+        self.egress_payload_size = self
+            .egress_payload_size
+            .saturating_add(other.egress_payload_size);
+    }
+}
+
+/// The query statistics of a single canister.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CanisterQueryStats {
+    pub canister_id: CanisterId,
+    pub stats: QueryStats,
+}
+
+impl CountBytes for CanisterQueryStats {
+    fn count_bytes(&self) -> usize {
+        size_of::<CanisterQueryStats>()
+    }
+}
+
+/// The query statistics that a replica collected locally during an epoch.
+///
+/// They are handed from the query handler to the payload builder once the
+/// epoch has ended.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LocalQueryStats {
+    pub epoch: QueryStatsEpoch,
+    pub stats: Vec<CanisterQueryStats>,
+}
+
+/// Payload that contains (a part of) the query statistics that the block
+/// proposer collected locally during `epoch`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct QueryStatsPayload {
+    pub epoch: QueryStatsEpoch,
+    pub proposer: NodeId,
+    pub stats: Vec<CanisterQueryStats>,
+}
+
+impl CountBytes for QueryStatsPayload {
+    fn count_bytes(&self) -> usize {
+        size_of::<QueryStatsEpoch>()
+            + size_of::<NodeId>()
+            + self.stats.iter().map(|s| s.count_bytes()).sum::<usize>()
+    }
+}
+
+impl From<&QueryStatsPayload> for pb::QueryStatsPayload {
+    fn from(payload: &QueryStatsPayload) -> Self {
+        Self {
+            epoch: payload.epoch.get(),
+            proposer: Some(node_id_into_protobuf(payload.proposer)),
+            canister_stats: payload
+                .stats
+                .iter()
+                .map(|canister_stats| pb::CanisterQueryStats {
+                    canister_id: Some(pb::CanisterId::from(canister_stats.canister_id)),
+                    num_calls: canister_stats.stats.num_calls,
+                    num_instructions: canister_stats.stats.num_instructions,
+                    ingress_payload_size: canister_stats.stats.ingress_payload_size,
+                    egress_payload_size: canister_stats.stats.egress_payload_size,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<pb::QueryStatsPayload> for QueryStatsPayload {
+    type Error = ProxyDecodeError;
+
+    fn try_from(payload: pb::QueryStatsPayload) -> Result<Self, Self::Error> {
+        let proposer: pb::NodeId =
+            try_from_option_field(payload.proposer, "QueryStatsPayload::proposer")?;
+        let mut stats = Vec::with_capacity(payload.canister_stats.len());
+        for canister_stats in payload.canister_stats {
+            let canister_id: pb::CanisterId = try_from_option_field(
+                canister_stats.canister_id,
+                "CanisterQueryStats::canister_id",
+            )?;
+            stats.push(CanisterQueryStats {
+                canister_id: CanisterId::try_from(canister_id)?,
+                stats: QueryStats {
+                    num_calls: canister_stats.num_calls,
+                    num_instructions: canister_stats.num_instructions,
+                    ingress_payload_size: canister_stats.ingress_payload_size,
+                    egress_payload_size: canister_stats.egress_payload_size,
+                },
+            });
+        }
+        Ok(Self {
+            epoch: QueryStatsEpoch::from(payload.epoch),
+            proposer: node_id_try_from_protobuf(proposer)?,
+            stats,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_base_types::PrincipalId;
+
+    #[test]
+    fn query_stats_payload_proto_round_trip() {
+        let payload = QueryStatsPayload {
+            epoch: QueryStatsEpoch::from(3),
+            proposer: NodeId::from(PrincipalId::new_node_test_id(7)),
+            stats: vec![CanisterQueryStats {
+                canister_id: CanisterId::from_u64(42),
+                stats: QueryStats {
+                    num_calls: 5,
+                    num_instructions: 1_000,
+                    ingress_payload_size: 20,
+                    egress_payload_size: 300,
+                },
+            }],
+        };
+        let proto = pb::QueryStatsPayload::from(&payload);
+        assert_eq!(QueryStatsPayload::try_from(proto).unwrap(), payload);
+    }
+
+    #[test]
+    fn epoch_from_height_rounds_down() {
+        assert_eq!(epoch_from_height(Height::from(0), 10).get(), 0);
+        assert_eq!(epoch_from_height(Height::from(9), 10).get(), 0);
+        assert_eq!(epoch_from_height(Height::from(10), 10).get(), 1);
+    }
+}
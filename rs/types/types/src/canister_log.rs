@@ -0,0 +1,180 @@
+//! A bounded buffer of the log records produced by a canister via
+//! `ic0.debug_print` and `ic0.trap`.
+
+pub use ic_ic00_types::CanisterLogRecord;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The maximum total size in bytes of the records kept in a canister log.
+/// Once the limit is reached, the oldest records are evicted to make room
+/// for new ones.
+pub const MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE: usize = 4 * 1024;
+
+/// The number of bytes accounted for the fixed-size fields of a record, i.e.
+/// the index and the timestamp.
+const RECORD_HEADER_SIZE: usize = 2 * std::mem::size_of::<u64>();
+
+/// Returns the number of bytes taken by the given record.
+fn record_size(record: &CanisterLogRecord) -> usize {
+    RECORD_HEADER_SIZE + record.content.len()
+}
+
+/// A ring buffer of canister log records.
+///
+/// Every record gets a unique, monotonically increasing index. The total size
+/// of the records is bounded by `MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CanisterLog {
+    /// The index of the next record.
+    next_idx: u64,
+    /// The records ordered by index.
+    records: VecDeque<CanisterLogRecord>,
+    /// The total size of `records` in bytes.
+    used_space: usize,
+}
+
+impl CanisterLog {
+    /// Creates a canister log from the given records, e.g. when loading it
+    /// from a checkpoint. Records exceeding the size limit are evicted.
+    pub fn new(next_idx: u64, records: Vec<CanisterLogRecord>) -> Self {
+        let mut log = Self::new_with_next_index(next_idx);
+        for record in records {
+            log.push_record(record);
+        }
+        log
+    }
+
+    /// Creates an empty canister log whose first record gets the given index.
+    pub fn new_with_next_index(next_idx: u64) -> Self {
+        Self {
+            next_idx,
+            records: VecDeque::new(),
+            used_space: 0,
+        }
+    }
+
+    /// Returns the index of the next record.
+    pub fn next_idx(&self) -> u64 {
+        self.next_idx
+    }
+
+    /// Returns the records ordered by index.
+    pub fn records(&self) -> &VecDeque<CanisterLogRecord> {
+        &self.records
+    }
+
+    /// Returns the total size of the records in bytes.
+    pub fn used_space(&self) -> usize {
+        self.used_space
+    }
+
+    /// Adds a new record with the given timestamp and content. The content is
+    /// truncated if it does not fit in the buffer on its own.
+    pub fn add_record(&mut self, timestamp_nanos: u64, mut content: Vec<u8>) {
+        content.truncate(MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE - RECORD_HEADER_SIZE);
+        let record = CanisterLogRecord {
+            idx: self.next_idx,
+            timestamp_nanos,
+            content,
+        };
+        self.next_idx += 1;
+        self.push_record(record);
+    }
+
+    /// Moves all records of `other` to the end of this log, assigning new
+    /// indices to them. `other` is left empty.
+    pub fn append(&mut self, other: &mut CanisterLog) {
+        for record in other.records.drain(..) {
+            self.add_record(record.timestamp_nanos, record.content);
+        }
+        other.used_space = 0;
+    }
+
+    /// Removes all records. The index of the next record is preserved.
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.used_space = 0;
+    }
+
+    fn push_record(&mut self, record: CanisterLogRecord) {
+        let size = record_size(&record);
+        while self.used_space + size > MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE {
+            match self.records.pop_front() {
+                Some(evicted) => self.used_space -= record_size(&evicted),
+                None => return,
+            }
+        }
+        self.next_idx = self.next_idx.max(record.idx + 1);
+        self.used_space += size;
+        self.records.push_back(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_get_consecutive_indices() {
+        let mut log = CanisterLog::new_with_next_index(10);
+        log.add_record(1, b"a".to_vec());
+        log.add_record(2, b"b".to_vec());
+
+        let indices: Vec<_> = log.records().iter().map(|r| r.idx).collect();
+        assert_eq!(indices, vec![10, 11]);
+        assert_eq!(log.next_idx(), 12);
+        assert_eq!(log.used_space(), 2 * (RECORD_HEADER_SIZE + 1));
+    }
+
+    #[test]
+    fn oldest_records_are_evicted_when_full() {
+        let mut log = CanisterLog::default();
+        let content_size = MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE / 4 - RECORD_HEADER_SIZE;
+        for i in 0..6 {
+            log.add_record(i, vec![0; content_size]);
+        }
+
+        let indices: Vec<_> = log.records().iter().map(|r| r.idx).collect();
+        assert_eq!(indices, vec![2, 3, 4, 5]);
+        assert_eq!(log.used_space(), MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn oversized_record_is_truncated() {
+        let mut log = CanisterLog::default();
+        log.add_record(0, b"x".to_vec());
+        log.add_record(1, vec![0; 2 * MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE]);
+
+        assert_eq!(log.records().len(), 1);
+        assert_eq!(log.records()[0].idx, 1);
+        assert_eq!(log.used_space(), MAX_ALLOWED_CANISTER_LOG_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn append_moves_records_and_reindexes_them() {
+        let mut log = CanisterLog::new_with_next_index(5);
+        log.add_record(0, b"first".to_vec());
+        let mut delta = CanisterLog::new_with_next_index(log.next_idx());
+        delta.add_record(1, b"second".to_vec());
+        delta.add_record(2, b"third".to_vec());
+
+        log.append(&mut delta);
+
+        assert!(delta.records().is_empty());
+        assert_eq!(delta.used_space(), 0);
+        let records: Vec<_> = log
+            .records()
+            .iter()
+            .map(|r| (r.idx, r.content.clone()))
+            .collect();
+        assert_eq!(
+            records,
+            vec![
+                (5, b"first".to_vec()),
+                (6, b"second".to_vec()),
+                (7, b"third".to_vec())
+            ]
+        );
+        assert_eq!(log.next_idx(), 8);
+    }
+}
@@ -1193,6 +1193,7 @@ impl From<&Block> for pb::Block {
             ingress_payload,
             self_validating_payload,
             canister_http_payload,
+            query_stats_payload,
             ecdsa_payload,
         ) = if payload.is_summary() {
             (
@@ -1201,6 +1202,7 @@ impl From<&Block> for pb::Block {
                 None,
                 None,
                 None,
+                None,
                 payload
                     .as_summary()
                     .ecdsa
@@ -1215,6 +1217,7 @@ impl From<&Block> for pb::Block {
                 Some(pb::IngressPayload::from(&batch.ingress)),
                 Some(pb::SelfValidatingPayload::from(&batch.self_validating)),
                 Some(pb::CanisterHttpPayload::from(&batch.canister_http)),
+                batch.query_stats.as_ref().map(pb::QueryStatsPayload::from),
                 payload.as_data().ecdsa.as_ref().map(|ecdsa| ecdsa.into()),
             )
         };
@@ -1231,6 +1234,7 @@ impl From<&Block> for pb::Block {
             ingress_payload,
             self_validating_payload,
             canister_http_payload,
+            query_stats_payload,
             ecdsa_payload,
             payload_hash: block.payload.get_hash().clone().get().0,
         }
@@ -1266,6 +1270,11 @@ impl TryFrom<pb::Block> for Block {
                 .map(crate::batch::CanisterHttpPayload::try_from)
                 .transpose()?
                 .unwrap_or_default(),
+            block
+                .query_stats_payload
+                .map(crate::batch::QueryStatsPayload::try_from)
+                .transpose()
+                .map_err(|err| err.to_string())?,
         );
         let payload = match dkg_payload {
             dkg::Payload::Summary(summary) => {
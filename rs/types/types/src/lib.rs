@@ -67,6 +67,7 @@ pub mod artifact;
 pub mod artifact_kind;
 pub mod batch;
 pub mod canister_http;
+pub mod canister_log;
 pub mod chunkable;
 pub mod consensus;
 pub mod crypto;
@@ -551,7 +552,15 @@ pub const MAX_STABLE_MEMORY_IN_BYTES: u64 = 48 * GB;
 /// it is public and `u64` (`NumBytes` cannot be used in const expressions).
 pub const MAX_WASM_MEMORY_IN_BYTES: u64 = 4 * GB;
 
+/// The upper limit on the size of a 64-bit (memory64) Wasm memory.
+/// This constant is used by other crates to define other constants, that's why
+/// it is public and `u64` (`NumBytes` cannot be used in const expressions).
+pub const MAX_WASM64_MEMORY_IN_BYTES: u64 = 6 * GB;
+
 const MIN_MEMORY_ALLOCATION: NumBytes = NumBytes::new(0);
+/// The upper limit on the memory allocation of a canister. It is derived from
+/// the size of 32-bit heaps as long as 64-bit heaps are behind the `wasm64`
+/// feature flag, which is disabled by default.
 pub const MAX_MEMORY_ALLOCATION: NumBytes =
     NumBytes::new(MAX_STABLE_MEMORY_IN_BYTES + MAX_WASM_MEMORY_IN_BYTES);
 
@@ -1,8 +1,12 @@
-use crate::{ingress::WasmResult, CanisterId, CountBytes, Cycles, Funds, NumBytes};
+use crate::{
+    ingress::WasmResult, time::CoarseTime, CanisterId, CountBytes, Cycles, Funds, NumBytes,
+};
 use ic_error_types::{RejectCode, TryFromError, UserError};
 use ic_ic00_types::{
-    CanisterIdRecord, InstallCodeArgs, Method, Payload as _, ProvisionalTopUpCanisterArgs,
-    SetControllerArgs, UpdateSettingsArgs,
+    CanisterIdRecord, CanisterInfoRequest, DeleteCanisterSnapshotArgs, FetchCanisterLogsRequest,
+    InstallChunkedCodeArgs, InstallCodeArgs, LoadCanisterSnapshotArgs, Method, Payload as _,
+    ProvisionalTopUpCanisterArgs, SetControllerArgs, TakeCanisterSnapshotArgs, UpdateSettingsArgs,
+    UploadChunkArgs,
 };
 use ic_protobuf::{
     proxy::{try_from_option_field, ProxyDecodeError},
@@ -34,6 +38,10 @@ pub enum CallContextIdTag {}
 /// Identifies an incoming call.
 pub type CallContextId = Id<CallContextIdTag, u64>;
 
+/// The deadline of guaranteed response messages, i.e. messages without a
+/// deadline.
+pub const NO_DEADLINE: CoarseTime = CoarseTime::from_secs_since_unix_epoch(0);
+
 /// Canister-to-canister request message.
 #[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Request {
@@ -44,6 +52,9 @@ pub struct Request {
     pub method_name: String,
     #[serde(with = "serde_bytes")]
     pub method_payload: Vec<u8>,
+    /// The deadline of a best-effort call, after which the caller gets a
+    /// `SYS_UNKNOWN` reject. `NO_DEADLINE` for guaranteed response calls.
+    pub deadline: CoarseTime,
 }
 
 impl Request {
@@ -52,6 +63,12 @@ impl Request {
         self.sender
     }
 
+    /// Returns `true` if this is a best-effort request, i.e. one with a
+    /// deadline.
+    pub fn is_best_effort(&self) -> bool {
+        self.deadline != NO_DEADLINE
+    }
+
     /// Takes the payment out of this `Request`.
     pub fn take_cycles(&mut self) -> Cycles {
         self.payment.take()
@@ -78,6 +95,9 @@ impl Request {
             | Ok(Method::DeleteCanister)
             | Ok(Method::UninstallCode)
             | Ok(Method::DepositCycles)
+            | Ok(Method::ListCanisterSnapshots)
+            | Ok(Method::ClearChunkStore)
+            | Ok(Method::StoredChunks)
             | Ok(Method::StopCanister) => match CanisterIdRecord::decode(&self.method_payload) {
                 Ok(record) => Some(record.get_canister_id()),
                 Err(_) => None,
@@ -100,6 +120,44 @@ impl Request {
                     Err(_) => None,
                 }
             }
+            Ok(Method::TakeCanisterSnapshot) => {
+                match TakeCanisterSnapshotArgs::decode(&self.method_payload) {
+                    Ok(record) => Some(record.get_canister_id()),
+                    Err(_) => None,
+                }
+            }
+            Ok(Method::LoadCanisterSnapshot) => {
+                match LoadCanisterSnapshotArgs::decode(&self.method_payload) {
+                    Ok(record) => Some(record.get_canister_id()),
+                    Err(_) => None,
+                }
+            }
+            Ok(Method::DeleteCanisterSnapshot) => {
+                match DeleteCanisterSnapshotArgs::decode(&self.method_payload) {
+                    Ok(record) => Some(record.get_canister_id()),
+                    Err(_) => None,
+                }
+            }
+            Ok(Method::FetchCanisterLogs) => {
+                match FetchCanisterLogsRequest::decode(&self.method_payload) {
+                    Ok(record) => Some(record.get_canister_id()),
+                    Err(_) => None,
+                }
+            }
+            Ok(Method::CanisterInfo) => match CanisterInfoRequest::decode(&self.method_payload) {
+                Ok(record) => Some(record.canister_id()),
+                Err(_) => None,
+            },
+            Ok(Method::UploadChunk) => match UploadChunkArgs::decode(&self.method_payload) {
+                Ok(record) => Some(record.get_canister_id()),
+                Err(_) => None,
+            },
+            Ok(Method::InstallChunkedCode) => {
+                match InstallChunkedCodeArgs::decode(&self.method_payload) {
+                    Ok(record) => Some(record.target_canister_id()),
+                    Err(_) => None,
+                }
+            }
             Ok(Method::CreateCanister)
             | Ok(Method::SetupInitialDKG)
             | Ok(Method::HttpRequest)
@@ -142,9 +200,10 @@ impl std::fmt::Debug for Request {
         }
         write!(
             f,
-            "method_payload: [{}] }}",
+            "method_payload: [{}], ",
             truncate_and_format(&self.method_payload, 1024)
         )?;
+        write!(f, "deadline: {:?} }}", self.deadline)?;
         Ok(())
     }
 }
@@ -270,6 +329,8 @@ pub struct Response {
     pub originator_reply_callback: CallbackId,
     pub refund: Cycles,
     pub response_payload: Payload,
+    /// The deadline of the `Request` that this `Response` is for.
+    pub deadline: CoarseTime,
 }
 
 impl Response {
@@ -277,6 +338,11 @@ impl Response {
     pub fn payload_size_bytes(&self) -> NumBytes {
         self.response_payload.size_bytes()
     }
+
+    /// Returns `true` if this is a response to a best-effort request.
+    pub fn is_best_effort(&self) -> bool {
+        self.deadline != NO_DEADLINE
+    }
 }
 
 /// Canister-to-canister message.
@@ -323,6 +389,14 @@ impl RequestOrResponse {
             RequestOrResponse::Response(resp) => resp.refund,
         }
     }
+
+    /// Returns `true` if this is a best-effort request or a response to one.
+    pub fn is_best_effort(&self) -> bool {
+        match self {
+            RequestOrResponse::Request(req) => req.is_best_effort(),
+            RequestOrResponse::Response(resp) => resp.is_best_effort(),
+        }
+    }
 }
 
 /// Convenience `CountBytes` implementation that returns the same value as
@@ -381,6 +455,7 @@ impl From<&Request> for pb_queues::Request {
             method_name: req.method_name.clone(),
             method_payload: req.method_payload.clone(),
             cycles_payment: Some((req.payment).into()),
+            deadline_seconds: req.deadline.as_secs_since_unix_epoch(),
         }
     }
 }
@@ -404,6 +479,7 @@ impl TryFrom<pb_queues::Request> for Request {
             payment,
             method_name: req.method_name,
             method_payload: req.method_payload,
+            deadline: CoarseTime::from_secs_since_unix_epoch(req.deadline_seconds),
         })
     }
 }
@@ -446,6 +522,7 @@ impl From<&Response> for pb_queues::Response {
             refund: Some((&Funds::new(rep.refund)).into()),
             response_payload: Some(p),
             cycles_refund: Some((rep.refund).into()),
+            deadline_seconds: rep.deadline.as_secs_since_unix_epoch(),
         }
     }
 }
@@ -476,6 +553,7 @@ impl TryFrom<pb_queues::Response> for Response {
             originator_reply_callback: rep.originator_reply_callback.into(),
             refund,
             response_payload,
+            deadline: CoarseTime::from_secs_since_unix_epoch(rep.deadline_seconds),
         })
     }
 }
@@ -91,6 +91,18 @@ pub const MANIFEST_CHUNK: ChunkId = ChunkId::new(0);
 // The real number of canisters and size of state are not even close to the assumption so the value of `FILE_GROUP_CHUNK_ID_OFFSET` is chosen safely.
 pub const FILE_GROUP_CHUNK_ID_OFFSET: u32 = 1 << 30;
 
+/// Starting from this manifest version, the payloads of all chunks except the
+/// manifest chunk are encoded with [`encode_chunk_payload`] during state sync.
+/// Older manifests are still served as raw bytes, so peers that only support
+/// older versions can keep syncing them.
+pub const MIN_COMPRESSED_CHUNKS_VERSION: u32 = 3;
+
+/// Tag of an encoded chunk payload that holds the raw chunk bytes.
+const CHUNK_PAYLOAD_RAW: u8 = 0;
+
+/// Tag of an encoded chunk payload that holds a zstd frame.
+const CHUNK_PAYLOAD_ZSTD: u8 = 1;
+
 /// An entry of the file table.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct FileInfo {
@@ -305,6 +317,57 @@ pub fn decode_manifest(bytes: &[u8]) -> Result<Manifest, String> {
         .map_err(|err| format!("failed to convert Manifest proto into an object: {}", err))
 }
 
+/// Encodes a chunk payload for state sync of a manifest with version
+/// `MIN_COMPRESSED_CHUNKS_VERSION` or higher.
+///
+/// The encoding is a one byte tag followed by either a zstd frame or, if
+/// compression does not make the payload smaller, the raw payload (see
+/// [`raw_chunk_payload`]).
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn encode_chunk_payload(payload: &[u8]) -> Result<Vec<u8>, String> {
+    let compressed = zstd::bulk::compress(payload, zstd::DEFAULT_COMPRESSION_LEVEL)
+        .map_err(|err| format!("failed to compress chunk payload: {}", err))?;
+    if compressed.len() >= payload.len() {
+        return Ok(raw_chunk_payload(payload));
+    }
+    let mut buf = Vec::with_capacity(compressed.len() + 1);
+    buf.push(CHUNK_PAYLOAD_ZSTD);
+    buf.extend_from_slice(&compressed);
+    Ok(buf)
+}
+
+/// Encodes a chunk payload as is, without compressing it. Decodes to the same
+/// bytes as [`encode_chunk_payload`].
+pub fn raw_chunk_payload(payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(payload.len() + 1);
+    buf.push(CHUNK_PAYLOAD_RAW);
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// Decodes a chunk payload produced by [`encode_chunk_payload`] or
+/// [`raw_chunk_payload`]. Fails if the decoded payload would be larger than
+/// `max_size` bytes.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub fn decode_chunk_payload(bytes: &[u8], max_size: usize) -> Result<Vec<u8>, String> {
+    match bytes.split_first() {
+        Some((&CHUNK_PAYLOAD_RAW, payload)) => {
+            if payload.len() > max_size {
+                return Err(format!(
+                    "chunk payload of {} bytes exceeds the limit of {} bytes",
+                    payload.len(),
+                    max_size
+                ));
+            }
+            Ok(payload.to_vec())
+        }
+        Some((&CHUNK_PAYLOAD_ZSTD, payload)) => zstd::bulk::decompress(payload, max_size)
+            .map_err(|err| format!("failed to decompress chunk payload: {}", err)),
+        Some((tag, _)) => Err(format!("unknown chunk payload tag {}", tag)),
+        None => Err("empty chunk payload".to_string()),
+    }
+}
+
 type P2PChunkId = u32;
 type ManifestChunkTableIndex = u32;
 
@@ -336,3 +399,55 @@ impl FileGroupChunks {
         self.0.is_empty()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_payload_roundtrip() {
+        let zeros = vec![0_u8; 1 << 20];
+        // A xorshift sequence does not compress.
+        let mut x: u32 = 42;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 17;
+                x ^= x << 5;
+                x as u8
+            })
+            .collect();
+
+        for payload in [vec![], vec![1, 2, 3], zeros.clone(), noise.clone()] {
+            let encoded = encode_chunk_payload(&payload).unwrap();
+            assert_eq!(
+                decode_chunk_payload(&encoded, payload.len()).unwrap(),
+                payload
+            );
+
+            let raw = raw_chunk_payload(&payload);
+            assert_eq!(decode_chunk_payload(&raw, payload.len()).unwrap(), payload);
+        }
+
+        // Compressible payloads are compressed, others are sent as is.
+        let encoded = encode_chunk_payload(&zeros).unwrap();
+        assert_eq!(encoded[0], CHUNK_PAYLOAD_ZSTD);
+        assert!(encoded.len() < zeros.len());
+        assert_eq!(
+            encode_chunk_payload(&noise).unwrap(),
+            raw_chunk_payload(&noise)
+        );
+    }
+
+    #[test]
+    fn decode_chunk_payload_rejects_invalid_payloads() {
+        let zeros = vec![0_u8; 1024];
+        let encoded = encode_chunk_payload(&zeros).unwrap();
+        assert!(decode_chunk_payload(&encoded, zeros.len() - 1).is_err());
+        assert!(decode_chunk_payload(&raw_chunk_payload(&zeros), zeros.len() - 1).is_err());
+
+        assert!(decode_chunk_payload(&[], 1024).is_err());
+        assert!(decode_chunk_payload(&[2, 0, 0], 1024).is_err());
+        assert!(decode_chunk_payload(&[CHUNK_PAYLOAD_ZSTD, 1, 2, 3], 1024).is_err());
+    }
+}
@@ -29,6 +29,7 @@ pub const UNIX_EPOCH: Time = Time(0);
 pub const GENESIS: Time = Time::from_nanos_since_unix_epoch(1_620_328_630_000_000_000);
 
 const NANOS_PER_MILLI: u64 = 1_000_000;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
 
 impl std::ops::Add<Duration> for Time {
     type Output = Time;
@@ -139,6 +140,39 @@ impl Time {
     }
 }
 
+/// Time since UNIX_EPOCH, in seconds. Used e.g. for message deadlines, where
+/// a coarser granularity allows for a more compact encoding.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct CoarseTime(u32);
+
+impl CoarseTime {
+    pub const fn from_secs_since_unix_epoch(secs: u32) -> Self {
+        Self(secs)
+    }
+
+    pub fn as_secs_since_unix_epoch(self) -> u32 {
+        self.0
+    }
+
+    /// Returns the largest `CoarseTime` that is less than or equal to `time`,
+    /// saturating at `u32::MAX` seconds.
+    pub fn floor(time: Time) -> Self {
+        let secs = time.as_nanos_since_unix_epoch() / NANOS_PER_SEC;
+        Self(secs.min(u32::MAX as u64) as u32)
+    }
+
+    /// Returns the equivalent `Time`.
+    pub fn as_time(self) -> Time {
+        Time::from_nanos_since_unix_epoch(self.0 as u64 * NANOS_PER_SEC)
+    }
+
+    /// Adds the given number of seconds, saturating at `u32::MAX`.
+    pub fn saturating_add_secs(self, secs: u32) -> Self {
+        Self(self.0.saturating_add(secs))
+    }
+}
+
 #[derive(Error, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TimeInstantiationError {
     #[error("Time cannot be instantiated as it would overflow: {0}")]
@@ -1,4 +1,4 @@
-use crate::time::{TimeInstantiationError, NANOS_PER_MILLI};
+use crate::time::{CoarseTime, TimeInstantiationError, NANOS_PER_MILLI};
 use crate::Time;
 use assert_matches::assert_matches;
 use std::time::SystemTime;
@@ -65,3 +65,27 @@ fn should_convert_from_system_time_and_back() {
     let back: SystemTime = time.into();
     assert_eq!(system_time, back);
 }
+
+#[test]
+fn coarse_time_floor_and_back() {
+    let time = Time::from_nanos_since_unix_epoch(1_700_000_000_999_999_999);
+    let coarse = CoarseTime::floor(time);
+    assert_eq!(coarse.as_secs_since_unix_epoch(), 1_700_000_000);
+    assert_eq!(
+        coarse.as_time(),
+        Time::from_nanos_since_unix_epoch(1_700_000_000_000_000_000)
+    );
+    assert_eq!(
+        coarse
+            .saturating_add_secs(u32::MAX)
+            .as_secs_since_unix_epoch(),
+        u32::MAX
+    );
+
+    // Times beyond the `u32` range saturate.
+    let far_future = Time::from_nanos_since_unix_epoch(u64::MAX);
+    assert_eq!(
+        CoarseTime::floor(far_future).as_secs_since_unix_epoch(),
+        u32::MAX
+    );
+}
@@ -0,0 +1,606 @@
+//! Storage format for files that are persisted incrementally.
+//!
+//! A layered file consists of a base file and a stack of immutable overlay
+//! files stored next to it. Every overlay contains only the pages that were
+//! modified since the overlay below it was written, so persisting a small
+//! change of a large file does not require rewriting or copying the file.
+//!
+//! The logical contents of a layered file are obtained by applying the
+//! overlays on top of the base file from the oldest to the newest. The logical
+//! length is the maximum of the length of the base file and the end of the
+//! highest page stored in any overlay; all bytes not covered by any layer read
+//! as zeros.
+//!
+//! The overlays of `dir/vmemory_0.bin` are stored as
+//! `dir/vmemory_0_<height>.overlay`, where `<height>` is the height at which
+//! the overlay was written encoded as 16 hex digits, such that the
+//! lexicographic order of the names matches the order of the stack.
+//!
+//! An overlay file has the following layout (all integers are little-endian):
+//!
+//! ```text
+//! ┌───────────────────┬─────────────────────────┬───────────┬─────────┬───────┐
+//! │ page data         │ page indices            │ num_pages │ version │ magic │
+//! │ num_pages × 4 KiB │ num_pages × u64, sorted │ u64       │ u32     │ 4 B   │
+//! └───────────────────┴─────────────────────────┴───────────┴─────────┴───────┘
+//! ```
+//!
+//! The page data comes first so that every page is aligned to the page size
+//! and can be served directly from a memory mapping of the overlay file.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+/// Size of the pages stored in overlay files.
+pub const PAGE_SIZE: usize = 4096;
+
+const OVERLAY_EXTENSION: &str = "overlay";
+const OVERLAY_MAGIC: [u8; 4] = *b"OVLY";
+const OVERLAY_VERSION: u32 = 1;
+const INDEX_ENTRY_SIZE: usize = std::mem::size_of::<u64>();
+/// Size of the `num_pages`, `version` and `magic` fields.
+const TRAILER_SIZE: usize = 16;
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn with_path(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path.display(), err))
+}
+
+fn file_stem(base: &Path) -> String {
+    base.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the height encoded in `file_name` if it is the name of an overlay
+/// of a base file with the given stem.
+fn overlay_height(base_stem: &str, file_name: &str) -> Option<u64> {
+    let height = file_name
+        .strip_prefix(base_stem)?
+        .strip_prefix('_')?
+        .strip_suffix(OVERLAY_EXTENSION)?
+        .strip_suffix('.')?;
+    if height.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(height, 16).ok()
+}
+
+/// Returns the path of the overlay of `base` written at the given height.
+pub fn overlay_path(base: &Path, height: u64) -> PathBuf {
+    base.with_file_name(format!(
+        "{}_{:016x}.{}",
+        file_stem(base),
+        height,
+        OVERLAY_EXTENSION
+    ))
+}
+
+/// Returns true if `path` is the path of an overlay file.
+pub fn is_overlay(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == OVERLAY_EXTENSION)
+}
+
+/// Returns the paths of all overlays of `base` ordered from the oldest to the
+/// newest. Returns an empty list if the parent directory does not exist.
+pub fn overlay_paths(base: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let entries = match dir.read_dir() {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(with_path(dir, err)),
+    };
+    let stem = file_stem(base);
+    let mut overlays = vec![];
+    for entry in entries {
+        let entry = entry.map_err(|err| with_path(dir, err))?;
+        if let Some(height) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| overlay_height(&stem, name))
+        {
+            overlays.push((height, entry.path()));
+        }
+    }
+    overlays.sort();
+    Ok(overlays.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Removes all overlays of `base`.
+pub fn remove_overlays(base: &Path) -> io::Result<()> {
+    for path in overlay_paths(base)? {
+        fs::remove_file(&path).map_err(|err| with_path(&path, err))?;
+    }
+    Ok(())
+}
+
+/// The sorted list of pages stored in an overlay file. The page with the
+/// `i`-th smallest index is stored at offset `i * PAGE_SIZE` of the file.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OverlayIndex {
+    page_indices: Vec<u64>,
+}
+
+impl OverlayIndex {
+    /// Parses the index from the full contents of an overlay file.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < TRAILER_SIZE {
+            return Err(invalid_data(format!(
+                "overlay of {} bytes is shorter than its trailer",
+                bytes.len()
+            )));
+        }
+        let num_pages =
+            Self::parse_trailer(bytes.len() as u64, &bytes[bytes.len() - TRAILER_SIZE..])?;
+        let start = num_pages * PAGE_SIZE;
+        Self::parse_page_indices(&bytes[start..start + num_pages * INDEX_ENTRY_SIZE])
+    }
+
+    /// Reads the index of the given overlay file.
+    pub fn read(file: &File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+        if len < TRAILER_SIZE as u64 {
+            return Err(invalid_data(format!(
+                "overlay of {} bytes is shorter than its trailer",
+                len
+            )));
+        }
+        let mut trailer = [0; TRAILER_SIZE];
+        file.read_exact_at(&mut trailer, len - TRAILER_SIZE as u64)?;
+        let num_pages = Self::parse_trailer(len, &trailer)?;
+        let mut index = vec![0; num_pages * INDEX_ENTRY_SIZE];
+        file.read_exact_at(&mut index, (num_pages * PAGE_SIZE) as u64)?;
+        Self::parse_page_indices(&index)
+    }
+
+    /// Validates the trailer and returns the number of pages in the overlay.
+    fn parse_trailer(file_len: u64, trailer: &[u8]) -> io::Result<usize> {
+        if trailer[12..16] != OVERLAY_MAGIC {
+            return Err(invalid_data("missing overlay magic".to_string()));
+        }
+        let version = u32::from_le_bytes(trailer[8..12].try_into().unwrap());
+        if version != OVERLAY_VERSION {
+            return Err(invalid_data(format!(
+                "unsupported overlay version {}",
+                version
+            )));
+        }
+        let num_pages = u64::from_le_bytes(trailer[0..8].try_into().unwrap());
+        let expected_len = num_pages
+            .checked_mul((PAGE_SIZE + INDEX_ENTRY_SIZE) as u64)
+            .and_then(|len| len.checked_add(TRAILER_SIZE as u64));
+        if expected_len != Some(file_len) {
+            return Err(invalid_data(format!(
+                "overlay of {} pages has unexpected length {}",
+                num_pages, file_len
+            )));
+        }
+        Ok(num_pages as usize)
+    }
+
+    fn parse_page_indices(bytes: &[u8]) -> io::Result<Self> {
+        let page_indices: Vec<u64> = bytes
+            .chunks_exact(INDEX_ENTRY_SIZE)
+            .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()))
+            .collect();
+        if page_indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(invalid_data(
+                "overlay page indices are not strictly increasing".to_string(),
+            ));
+        }
+        Ok(Self { page_indices })
+    }
+
+    /// Returns the indices of the pages in the overlay in increasing order.
+    pub fn page_indices(&self) -> &[u64] {
+        &self.page_indices
+    }
+
+    /// Returns the position of the given page in the overlay file.
+    pub fn slot(&self, page_index: u64) -> Option<usize> {
+        self.page_indices.binary_search(&page_index).ok()
+    }
+
+    /// Returns the smallest page index in the overlay that is not smaller than
+    /// `page_index`.
+    pub fn next_page(&self, page_index: u64) -> Option<u64> {
+        let position = self.page_indices.partition_point(|i| *i < page_index);
+        self.page_indices.get(position).copied()
+    }
+
+    /// Returns the largest page index in the overlay that is smaller than
+    /// `page_index`.
+    pub fn prev_page(&self, page_index: u64) -> Option<u64> {
+        let position = self.page_indices.partition_point(|i| *i < page_index);
+        position
+            .checked_sub(1)
+            .map(|position| self.page_indices[position])
+    }
+
+    /// Returns the largest page index in the overlay.
+    pub fn max_page_index(&self) -> Option<u64> {
+        self.page_indices.last().copied()
+    }
+
+    /// Returns the number of pages in the overlay.
+    pub fn len(&self) -> usize {
+        self.page_indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.page_indices.is_empty()
+    }
+}
+
+/// Writes an overlay file page by page.
+///
+/// The contents are written to a temporary file that is renamed to the final
+/// path by `finish()`, so a partially written overlay never becomes part of
+/// the stack.
+pub struct OverlayWriter {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    writer: BufWriter<File>,
+    page_indices: Vec<u64>,
+}
+
+impl OverlayWriter {
+    /// Starts writing the overlay file at `path`.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let tmp_path = path.with_extension(format!("{}.tmp", OVERLAY_EXTENSION));
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&tmp_path)
+            .map_err(|err| with_path(&tmp_path, err))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            tmp_path,
+            writer: BufWriter::new(file),
+            page_indices: vec![],
+        })
+    }
+
+    /// Appends a page to the overlay. Pages must be written in strictly
+    /// increasing order of their indices.
+    pub fn write_page(&mut self, page_index: u64, contents: &[u8]) -> io::Result<()> {
+        if contents.len() != PAGE_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("page {} has size {}", page_index, contents.len()),
+            ));
+        }
+        if let Some(last) = self.page_indices.last() {
+            if page_index <= *last {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("page {} written after page {}", page_index, last),
+                ));
+            }
+        }
+        self.writer.write_all(contents)?;
+        self.page_indices.push(page_index);
+        Ok(())
+    }
+
+    /// Writes the index and moves the overlay to its final path.
+    pub fn finish(mut self) -> io::Result<()> {
+        for page_index in self.page_indices.iter() {
+            self.writer.write_all(&page_index.to_le_bytes())?;
+        }
+        self.writer
+            .write_all(&(self.page_indices.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&OVERLAY_VERSION.to_le_bytes())?;
+        self.writer.write_all(&OVERLAY_MAGIC)?;
+        self.writer.flush()?;
+        fs::rename(&self.tmp_path, &self.path).map_err(|err| with_path(&self.path, err))
+    }
+}
+
+/// Writes an overlay file containing the given pages, which must be sorted by
+/// strictly increasing index.
+pub fn write_overlay<'a, I>(path: &Path, pages: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = (u64, &'a [u8])>,
+{
+    let mut writer = OverlayWriter::create(path)?;
+    for (page_index, contents) in pages {
+        writer.write_page(page_index, contents)?;
+    }
+    writer.finish()
+}
+
+/// A read-only view of the logical contents of a layered file.
+pub struct LayeredFile {
+    base: Option<File>,
+    base_len: u64,
+    /// The overlays ordered from the newest to the oldest.
+    overlays: Vec<(File, OverlayIndex)>,
+    len: u64,
+}
+
+impl LayeredFile {
+    /// Opens the base file at `base` together with all its overlays. A missing
+    /// base file is treated as an empty one.
+    pub fn open(base: &Path) -> io::Result<Self> {
+        let (base_file, base_len) = match File::open(base) {
+            Ok(file) => {
+                let len = file.metadata().map_err(|err| with_path(base, err))?.len();
+                (Some(file), len)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (None, 0),
+            Err(err) => return Err(with_path(base, err)),
+        };
+        let mut len = base_len;
+        let mut overlays = vec![];
+        for path in overlay_paths(base)?.into_iter().rev() {
+            let file = File::open(&path).map_err(|err| with_path(&path, err))?;
+            let index = OverlayIndex::read(&file).map_err(|err| with_path(&path, err))?;
+            if let Some(max_page_index) = index.max_page_index() {
+                len = len.max((max_page_index + 1) * PAGE_SIZE as u64);
+            }
+            overlays.push((file, index));
+        }
+        Ok(Self {
+            base: base_file,
+            base_len,
+            overlays,
+            len,
+        })
+    }
+
+    /// Returns the logical length of the file in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of overlays stacked on top of the base file.
+    pub fn num_overlays(&self) -> usize {
+        self.overlays.len()
+    }
+
+    /// Returns the indices of all pages stored in any of the overlays.
+    pub fn overlay_page_indices(&self) -> BTreeSet<u64> {
+        self.overlays
+            .iter()
+            .flat_map(|(_, index)| index.page_indices().iter().copied())
+            .collect()
+    }
+
+    /// Fills `buf` with the logical contents of the file starting at `offset`.
+    ///
+    /// Fails with `UnexpectedEof` if the range extends past the logical end of
+    /// the file.
+    pub fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        if offset
+            .checked_add(buf.len() as u64)
+            .map_or(true, |end| end > self.len)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "cannot read {} bytes at offset {} of a file of length {}",
+                    buf.len(),
+                    offset,
+                    self.len
+                ),
+            ));
+        }
+        while !buf.is_empty() {
+            let page_index = offset / PAGE_SIZE as u64;
+            let offset_in_page = (offset % PAGE_SIZE as u64) as usize;
+            let overlay_page = self
+                .overlays
+                .iter()
+                .find_map(|(file, index)| index.slot(page_index).map(|slot| (file, slot)));
+            let n = match overlay_page {
+                Some((file, slot)) => {
+                    let n = buf.len().min(PAGE_SIZE - offset_in_page);
+                    file.read_exact_at(&mut buf[..n], (slot * PAGE_SIZE + offset_in_page) as u64)?;
+                    n
+                }
+                None => {
+                    // Read from the base up to the next page stored in an overlay.
+                    let n = match self.next_overlay_page(page_index) {
+                        Some(next) => (next * PAGE_SIZE as u64 - offset).min(buf.len() as u64),
+                        None => buf.len() as u64,
+                    } as usize;
+                    self.read_base(&mut buf[..n], offset)?;
+                    n
+                }
+            };
+            buf = &mut std::mem::take(&mut buf)[n..];
+            offset += n as u64;
+        }
+        Ok(())
+    }
+
+    fn next_overlay_page(&self, page_index: u64) -> Option<u64> {
+        self.overlays
+            .iter()
+            .filter_map(|(_, index)| index.next_page(page_index))
+            .min()
+    }
+
+    /// Reads from the base file. Bytes past the end of the base read as zeros.
+    fn read_base(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let available = self.base_len.saturating_sub(offset).min(buf.len() as u64) as usize;
+        if available > 0 {
+            if let Some(base) = &self.base {
+                base.read_exact_at(&mut buf[..available], offset)?;
+            }
+        }
+        buf[available..].fill(0);
+        Ok(())
+    }
+}
+
+/// Applies all overlays of `base` to the base file in place and removes them.
+///
+/// Only the pages stored in the overlays are written. The logical contents of
+/// the file are the same at every step, so the merge can be safely repeated if
+/// it is interrupted.
+pub fn merge_into_base(base: &Path) -> io::Result<()> {
+    let overlays = overlay_paths(base)?;
+    if overlays.is_empty() {
+        return Ok(());
+    }
+    let layered = LayeredFile::open(base)?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(base)
+        .map_err(|err| with_path(base, err))?;
+    let mut page = vec![0; PAGE_SIZE];
+    for page_index in layered.overlay_page_indices() {
+        let offset = page_index * PAGE_SIZE as u64;
+        layered.read_exact_at(&mut page, offset)?;
+        file.write_all_at(&page, offset)
+            .map_err(|err| with_path(base, err))?;
+    }
+    for path in overlays {
+        fs::remove_file(&path).map_err(|err| with_path(&path, err))?;
+    }
+    Ok(())
+}
+
+/// Replaces all overlays of `base` by a single overlay with the same logical
+/// contents, stored under the name of the newest overlay.
+///
+/// The merged overlay is complete before any of the older overlays are
+/// removed, so the logical contents of the file are the same at every step.
+pub fn merge_overlays(base: &Path) -> io::Result<()> {
+    let overlays = overlay_paths(base)?;
+    if overlays.len() < 2 {
+        return Ok(());
+    }
+    let layered = LayeredFile::open(base)?;
+    let newest = overlays.last().unwrap();
+    let mut writer = OverlayWriter::create(newest)?;
+    let mut page = vec![0; PAGE_SIZE];
+    for page_index in layered.overlay_page_indices() {
+        layered.read_exact_at(&mut page, page_index * PAGE_SIZE as u64)?;
+        writer.write_page(page_index, &page)?;
+    }
+    writer.finish()?;
+    for path in &overlays[..overlays.len() - 1] {
+        fs::remove_file(path).map_err(|err| with_path(path, err))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(byte: u8) -> Vec<u8> {
+        vec![byte; PAGE_SIZE]
+    }
+
+    fn read_all(base: &Path) -> Vec<u8> {
+        let layered = LayeredFile::open(base).unwrap();
+        let mut buf = vec![0; layered.len() as usize];
+        layered.read_exact_at(&mut buf, 0).unwrap();
+        buf
+    }
+
+    fn expected(pages: &[u8]) -> Vec<u8> {
+        pages.iter().flat_map(|byte| page(*byte)).collect()
+    }
+
+    #[test]
+    fn overlay_paths_are_ordered_by_height() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let base = tmp.path().join("vmemory_0.bin");
+        write_overlay(&overlay_path(&base, 300), [(0, &page(1)[..])]).unwrap();
+        write_overlay(&overlay_path(&base, 20), [(0, &page(2)[..])]).unwrap();
+        write_overlay(&overlay_path(&tmp.path().join("stable_memory.bin"), 10), []).unwrap();
+        fs::write(tmp.path().join("vmemory_0_garbage.overlay"), b"").unwrap();
+
+        assert_eq!(
+            overlay_paths(&base).unwrap(),
+            vec![overlay_path(&base, 20), overlay_path(&base, 300)]
+        );
+        assert!(is_overlay(&overlay_path(&base, 20)));
+        assert!(!is_overlay(&base));
+    }
+
+    #[test]
+    fn newer_overlays_take_precedence() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let base = tmp.path().join("vmemory_0.bin");
+        fs::write(&base, expected(&[1, 1, 1])).unwrap();
+        write_overlay(
+            &overlay_path(&base, 1),
+            [(1, &page(2)[..]), (4, &page(2)[..])],
+        )
+        .unwrap();
+        write_overlay(&overlay_path(&base, 2), [(4, &page(3)[..])]).unwrap();
+
+        assert_eq!(read_all(&base), expected(&[1, 2, 1, 0, 3]));
+
+        // Unaligned reads spanning several layers.
+        let layered = LayeredFile::open(&base).unwrap();
+        let mut buf = vec![0; PAGE_SIZE + 2];
+        layered
+            .read_exact_at(&mut buf, 2 * PAGE_SIZE as u64 - 1)
+            .unwrap();
+        assert_eq!(buf[0], 2);
+        assert!(buf[1..PAGE_SIZE + 1].iter().all(|b| *b == 1));
+        assert_eq!(buf[PAGE_SIZE + 1], 0);
+        assert!(layered
+            .read_exact_at(&mut buf, 4 * PAGE_SIZE as u64)
+            .is_err());
+    }
+
+    #[test]
+    fn merging_preserves_logical_contents() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let base = tmp.path().join("vmemory_0.bin");
+        fs::write(&base, expected(&[1, 1])).unwrap();
+        write_overlay(
+            &overlay_path(&base, 1),
+            [(0, &page(2)[..]), (3, &page(2)[..])],
+        )
+        .unwrap();
+        write_overlay(&overlay_path(&base, 2), [(3, &page(3)[..])]).unwrap();
+        let contents = expected(&[2, 1, 0, 3]);
+
+        merge_overlays(&base).unwrap();
+        assert_eq!(overlay_paths(&base).unwrap(), vec![overlay_path(&base, 2)]);
+        assert_eq!(read_all(&base), contents);
+
+        merge_into_base(&base).unwrap();
+        assert!(overlay_paths(&base).unwrap().is_empty());
+        assert_eq!(fs::read(&base).unwrap(), contents);
+    }
+
+    #[test]
+    fn corrupted_overlay_is_rejected() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let base = tmp.path().join("vmemory_0.bin");
+        let overlay = overlay_path(&base, 1);
+        write_overlay(&overlay, [(0, &page(1)[..])]).unwrap();
+        let mut bytes = fs::read(&overlay).unwrap();
+        bytes.pop();
+        fs::write(&overlay, &bytes).unwrap();
+
+        assert!(OverlayIndex::parse(&bytes).is_err());
+        assert!(LayeredFile::open(&base).is_err());
+    }
+}
@@ -12,6 +12,8 @@ pub mod command;
 pub mod deterministic_operations;
 pub mod fs;
 pub mod ic_features;
+#[cfg(unix)]
+pub mod layered_file;
 pub mod rle;
 pub mod serde_arc;
 pub mod str;